@@ -0,0 +1,88 @@
+// Copyright (C) 2025 rrrrrzy
+// SPDX-License-Identifier: GPL-3.0-or-later
+//
+// --------------------------------------------------
+// 致敬所有在深夜调试代码的灵魂。
+// 即便 Bug 如山，我亦往矣。
+// --------------------------------------------------
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+use std::{
+    io::{self, Write},
+    thread,
+    time::Duration,
+};
+
+use anyhow::Result;
+use clap::Parser;
+use net_stack::{cli::Args, config, stack, transport::tcp::TcpStream};
+
+fn main() -> Result<()> {
+    let args = Args::parse();
+    let stack_config = config::load_config(&args)?;
+    let stack = stack::initialize_from_args(&args, stack_config)?;
+
+    // 启动网络栈主循环线程
+    let stack_clone = stack.clone();
+    thread::spawn(move || {
+        if let Err(e) = net_stack::event_loop::run(stack_clone) {
+            eprintln!("Event loop error: {:?}", e);
+        }
+    });
+
+    // 目标地址 (假设 Server 在同一网段的另一台机器)
+    let target = "192.168.31.223:8080"; // 请根据实际情况修改
+
+    // 阻塞直到三次握手完成，本地端口自动分配
+    let stream = TcpStream::connect(stack.clone(), target)?;
+    println!("Connected to {} from {}", target, stream.local_addr());
+    println!("Enter message to send (type 'quit' to exit):");
+
+    loop {
+        print!("> ");
+        io::stdout().flush()?;
+
+        let mut input = String::new();
+        io::stdin().read_line(&mut input)?;
+        let msg = input.trim();
+
+        if msg == "quit" {
+            break;
+        }
+
+        // 发送消息
+        stream.send(msg.as_bytes())?;
+
+        // 尝试接收回复 (带超时重试)
+        let mut retries = 0;
+        loop {
+            match stream.recv() {
+                Ok(data) if data.is_empty() => {
+                    println!("Connection closed by server");
+                    return Ok(());
+                }
+                Ok(data) => {
+                    println!("Received reply: {}", String::from_utf8_lossy(&data));
+                    break;
+                }
+                Err(_) => {
+                    thread::sleep(Duration::from_millis(100));
+                    retries += 1;
+                    if retries > 10 {
+                        println!("No reply received (timeout)");
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    // 发送 FIN，等待对端确认后再退出
+    stream.close();
+    thread::sleep(Duration::from_millis(500));
+    Ok(())
+}
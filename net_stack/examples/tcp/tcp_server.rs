@@ -0,0 +1,94 @@
+// Copyright (C) 2025 rrrrrzy
+// SPDX-License-Identifier: GPL-3.0-or-later
+//
+// --------------------------------------------------
+// 致敬所有在深夜调试代码的灵魂。
+// 即便 Bug 如山，我亦往矣。
+// --------------------------------------------------
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+use std::{thread, time::Duration};
+
+use anyhow::Result;
+use clap::Parser;
+use net_stack::{
+    cli::Args,
+    config, stack,
+    transport::tcp::{TcpError, TcpListener, TcpStream},
+};
+
+fn main() -> Result<()> {
+    let args = Args::parse();
+    let stack_config = config::load_config(&args)?;
+    let stack = stack::initialize_from_args(&args, stack_config)?;
+
+    // 启动网络栈主循环线程
+    let stack_clone = stack.clone();
+    thread::spawn(move || {
+        if let Err(e) = net_stack::event_loop::run(stack_clone) {
+            eprintln!("Event loop error: {:?}", e);
+        }
+    });
+
+    // 监听 TCP 端口 8080
+    let listener = TcpListener::bind(stack.clone(), "0.0.0.0:8080")?;
+    println!("TCP Server listening on 0.0.0.0:8080");
+
+    loop {
+        // 非阻塞 accept
+        match listener.accept() {
+            Ok((stream, peer_addr)) => {
+                println!("Accepted connection from {}", peer_addr);
+                // 每个连接一个线程
+                thread::spawn(move || serve(stream, peer_addr));
+            }
+            Err(_) => {
+                // 没有新连接，稍微休眠避免 CPU 空转
+                thread::sleep(Duration::from_millis(10));
+            }
+        }
+    }
+}
+
+fn serve(stream: TcpStream, peer_addr: String) {
+    loop {
+        match stream.recv() {
+            // 对端关闭了写方向
+            Ok(data) if data.is_empty() => break,
+            Ok(data) => {
+                println!(
+                    "Received from {}: {}",
+                    peer_addr,
+                    String::from_utf8_lossy(&data)
+                );
+
+                // 回显 (Echo)，发送缓冲区满时稍后重试
+                let mut sent = 0;
+                while sent < data.len() {
+                    match stream.send(&data[sent..]) {
+                        Ok(0) => thread::sleep(Duration::from_millis(10)),
+                        Ok(n) => sent += n,
+                        Err(e) => {
+                            eprintln!("Connection to {} failed: {}", peer_addr, e);
+                            return;
+                        }
+                    }
+                }
+            }
+            // 连接被重置或超时
+            Err(e) if e.downcast_ref::<TcpError>().is_some() => {
+                eprintln!("Connection to {} failed: {}", peer_addr, e);
+                return;
+            }
+            // 暂时没有数据
+            Err(_) => thread::sleep(Duration::from_millis(10)),
+        }
+    }
+
+    stream.close();
+    println!("Connection from {} closed", peer_addr);
+}
@@ -19,17 +19,12 @@ use std::{
 
 use anyhow::Result;
 use clap::Parser;
-use net_stack::{
-    cli::Args,
-    config,
-    stack,
-    transport::udp::UdpSocket,
-};
+use net_stack::{cli::Args, config, stack, transport::udp::UdpSocket};
 
 fn main() -> Result<()> {
     let args = Args::parse();
     let stack_config = config::load_config(&args)?;
-    let stack = stack::initialize(&args.iface, stack_config)?;
+    let stack = stack::initialize_from_args(&args, stack_config)?;
 
     // 启动网络栈主循环线程
     let stack_clone = stack.clone();
@@ -11,15 +11,19 @@
 // the Free Software Foundation, either version 3 of the License, or
 // (at your option) any later version.
 
-use clap::Parser;
+use clap::{Parser, ValueEnum};
 use std::path::PathBuf;
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 pub struct Args {
-    /// Network interface to use
+    /// Network interface to use (pcap device name, or TAP interface name with --device tap)
     #[arg(short, long)]
-    pub iface: String,
+    pub iface: Option<String>,
+
+    /// Link-layer backend used to send and receive frames
+    #[arg(long, value_enum, default_value_t = DeviceKind::Pcap)]
+    pub device: DeviceKind,
 
     /// IP address of this stack (optional if using config file)
     #[arg(long)]
@@ -29,15 +33,194 @@ pub struct Args {
     #[arg(long)]
     pub mac: Option<String>,
 
+    /// Only send and receive frames tagged with this VLAN ID (1-4094); give twice for QinQ, outer tag first
+    #[arg(long = "vlan")]
+    pub vlans: Vec<u16>,
+
+    /// Obtain the IP address, netmask, gateway and DNS servers via DHCP instead of --ip
+    #[arg(long)]
+    pub dhcp: bool,
+
+    /// Input .pcap file whose frames are fed to the stack (with --device replay)
+    #[arg(long)]
+    pub replay_input: Option<PathBuf>,
+
+    /// Output .pcap file receiving every frame the stack transmits (with --device replay)
+    #[arg(long)]
+    pub replay_output: Option<PathBuf>,
+
+    /// Netmask of the local subnet, dotted or prefix length (e.g. 255.255.255.0 or 24)
+    #[arg(long)]
+    pub netmask: Option<String>,
+
+    /// Default gateway IP address
+    #[arg(long)]
+    pub gateway: Option<String>,
+
+    /// Static route "<prefix>/<len> [via <gateway>] [dev <iface>] [metric <n>]", repeatable
+    #[arg(long = "route")]
+    pub routes: Vec<String>,
+
+    /// IPv6 address with prefix length (e.g. fe80::2/64 or 2001:db8::2/64), repeatable
+    #[arg(long = "ipv6")]
+    pub ipv6: Vec<String>,
+
+    /// Default IPv6 gateway, usually the router's link-local address
+    #[arg(long)]
+    pub ipv6_gateway: Option<String>,
+
+    /// Autoconfigure a link-local address and global addresses from router advertisements (SLAAC)
+    #[arg(long)]
+    pub slaac: bool,
+
+    /// 128-bit secret in IPv6 address format; SLAAC then uses stable privacy addresses instead of EUI-64
+    #[arg(long)]
+    pub ipv6_stable_secret: Option<String>,
+
+    /// Override the link MTU used to fragment outgoing IPv4 datagrams
+    #[arg(long)]
+    pub mtu: Option<usize>,
+
+    /// Attach another interface "<iface> mac <mac> ip <ip>/<len>" of the same --device kind, repeatable;
+    /// routes between all interfaces are added automatically (use with --forward to route between them)
+    #[arg(long = "attach")]
+    pub attach: Vec<String>,
+
+    /// Forward IPv4 packets not addressed to this stack (router mode)
+    #[arg(long)]
+    pub forward: bool,
+
+    /// Do not answer with ICMP Destination Unreachable (port/protocol unreachable, fragmentation needed)
+    #[arg(long)]
+    pub no_icmp_unreachable: bool,
+
+    /// Maximum ICMP error messages sent per second, 0 disables rate limiting
+    #[arg(long)]
+    pub icmp_rate_limit: Option<u32>,
+
+    /// Highest IGMP version used for multicast group membership (1-3), lowered automatically for older queriers
+    #[arg(long)]
+    pub igmp_version: Option<u8>,
+
+    /// DNS server used to resolve host names, repeatable (overrides servers learned via DHCP)
+    #[arg(long = "dns")]
+    pub dns_servers: Vec<String>,
+
+    /// /etc/hosts-style file consulted before DNS
+    #[arg(long)]
+    pub hosts_file: Option<PathBuf>,
+
+    /// Answer multicast DNS queries for <name>.local with this stack's address
+    #[arg(long)]
+    pub mdns_hostname: Option<String>,
+
+    /// Resolve a host name (or reverse-resolve an IPv4 address) and print the records, .local names via mDNS
+    #[arg(long)]
+    pub resolve: Option<String>,
+
+    /// Record type queried by --resolve (A, AAAA, CNAME, PTR, TXT, SRV, SOA)
+    #[arg(long)]
+    pub resolve_type: Option<String>,
+
     /// Configuration file path (format: ip=x.x.x.x\nmac=xx:xx:xx:xx:xx:xx)
     #[arg(short, long)]
     pub config: Option<PathBuf>,
 
-    /// Ping target IP address
+    /// Ping target IP address or host name
     #[arg(long)]
     pub ping: Option<String>,
 
-    /// Target MAC address for ping (temporary until ARP is fully implemented)
+    /// Number of echo requests to send (ping), unlimited until Ctrl-C if omitted
+    #[arg(long)]
+    pub count: Option<u32>,
+
+    /// Seconds between echo requests (ping)
+    #[arg(long, default_value_t = 1.0)]
+    pub interval: f64,
+
+    /// Probe data size in bytes, excluding the 8-byte ICMP/UDP header (ping, traceroute)
+    #[arg(long, default_value_t = 56)]
+    pub size: usize,
+
+    /// IP time to live of outgoing probes (ping)
+    #[arg(long, default_value_t = 64)]
+    pub ttl: u8,
+
+    /// Set the Don't Fragment bit on outgoing probes (ping)
+    #[arg(long)]
+    pub dont_fragment: bool,
+
+    /// Seconds to wait for replies after the last request (ping), or for each probe (traceroute)
+    #[arg(long, default_value_t = 1.0)]
+    pub timeout: f64,
+
+    /// Traceroute target IP address or host name
+    #[arg(long)]
+    pub traceroute: Option<String>,
+
+    /// Probe packets sent by traceroute
+    #[arg(long, value_enum, default_value_t = TracerouteMethod::Icmp)]
+    pub method: TracerouteMethod,
+
+    /// TTL of the first traceroute probe
+    #[arg(long, default_value_t = 1)]
+    pub first_ttl: u8,
+
+    /// Maximum TTL probed by traceroute
+    #[arg(long, default_value_t = 30)]
+    pub max_hops: u8,
+
+    /// Number of probes per hop (traceroute)
+    #[arg(long, default_value_t = 3)]
+    pub probes: u32,
+
+    /// Base destination port of UDP probes, incremented per probe (traceroute)
+    #[arg(long, default_value_t = 33434)]
+    pub port: u16,
+
+    /// Run a DHCP server handing out addresses from --dhcp-pool
+    #[arg(long)]
+    pub dhcp_server: bool,
+
+    /// DHCP server address pool "<first>-<last>"
+    #[arg(long)]
+    pub dhcp_pool: Option<String>,
+
+    /// DHCP server lease time in seconds
+    #[arg(long)]
+    pub dhcp_lease_time: Option<u64>,
+
+    /// Default gateway handed out by the DHCP server
     #[arg(long)]
-    pub target_mac: Option<String>,
+    pub dhcp_router: Option<String>,
+
+    /// DNS server handed out by the DHCP server, repeatable
+    #[arg(long = "dhcp-dns")]
+    pub dhcp_dns: Vec<String>,
+
+    /// Static DHCP reservation "<mac>,<ip>", repeatable
+    #[arg(long = "dhcp-reserve")]
+    pub dhcp_reservations: Vec<String>,
+
+    /// File where the DHCP server keeps its leases across restarts
+    #[arg(long)]
+    pub dhcp_lease_file: Option<PathBuf>,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DeviceKind {
+    /// Capture on a physical NIC through libpcap
+    Pcap,
+    /// Linux TAP interface (/dev/net/tun), the host kernel sits on the other end
+    Tap,
+    /// Read frames from a .pcap file and record transmitted frames to another one
+    Replay,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TracerouteMethod {
+    /// ICMP Echo Request, answered with Echo Reply by the target
+    Icmp,
+    /// UDP datagrams to unlikely ports, answered with Port Unreachable by the target
+    Udp,
 }
@@ -12,39 +12,556 @@
 // (at your option) any later version.
 
 use crate::cli::Args;
+use crate::dhcp::server::DhcpServerConfig;
+use crate::dns::resolver::ResolverConfig;
+use crate::ping::PingConfig;
+use crate::route::{self, Route};
 use crate::stack::StackConfig;
+use crate::traceroute::TracerouteConfig;
 use anyhow::{Context, Result};
-use protocol::{ipv4::Ipv4Addr, mac::MacAddr};
+use protocol::{
+    ethernet::VlanTag,
+    ipv4::Ipv4Addr,
+    ipv6::{Ipv6Addr, Ipv6Cidr},
+    mac::MacAddr,
+};
+use std::collections::HashMap;
 use std::fs;
 use std::str::FromStr;
+use std::time::Duration;
+
+/// 配置文件中读到的原始字符串
+#[derive(Default)]
+struct FileConfig {
+    ip: Option<String>,
+    mac: Option<String>,
+    vlans: Vec<String>,
+    netmask: Option<String>,
+    gateway: Option<String>,
+    routes: Vec<String>,
+    attach: Vec<String>,
+    ipv6: Vec<String>,
+    ipv6_gateway: Option<String>,
+    slaac: Option<String>,
+    ipv6_stable_secret: Option<String>,
+    forward: Option<String>,
+    mtu: Option<String>,
+    icmp_unreachable: Option<String>,
+    icmp_rate_limit: Option<String>,
+    igmp_version: Option<String>,
+    dhcp: Option<String>,
+    dns: Vec<String>,
+    hosts_file: Option<String>,
+    mdns_hostname: Option<String>,
+    dhcp_server: Option<String>,
+    dhcp_pool: Option<String>,
+    dhcp_lease_time: Option<String>,
+    dhcp_router: Option<String>,
+    dhcp_dns: Vec<String>,
+    dhcp_reservations: Vec<String>,
+    dhcp_lease_file: Option<String>,
+}
 
 pub fn load_config(args: &Args) -> Result<StackConfig> {
-    let (ip_str, mac_str) = if let Some(config_path) = &args.config {
-        load_from_file(config_path)?
-    } else {
-        let ip = args
-            .ip
+    let file = match &args.config {
+        Some(config_path) => Some(load_from_file(config_path)?),
+        None => None,
+    };
+
+    let dhcp = args.dhcp
+        || match file.as_ref().and_then(|f| f.dhcp.as_deref()) {
+            None => false,
+            Some(v) => parse_bool(v).ok_or_else(|| anyhow::anyhow!("Invalid dhcp '{}'", v))?,
+        };
+
+    let slaac = args.slaac
+        || match file.as_ref().and_then(|f| f.slaac.as_deref()) {
+            None => false,
+            Some(v) => parse_bool(v).ok_or_else(|| anyhow::anyhow!("Invalid slaac '{}'", v))?,
+        };
+    let ipv6_only =
+        slaac || !args.ipv6.is_empty() || file.as_ref().is_some_and(|f| !f.ipv6.is_empty());
+
+    let (ip_str, mac_str) = if let Some(file) = &file {
+        let mac = file
+            .mac
             .clone()
-            .ok_or_else(|| anyhow::anyhow!("--ip required"))?;
+            .ok_or_else(|| anyhow::anyhow!("Missing 'mac' in config file"))?;
+        (file.ip.clone(), mac)
+    } else {
         let mac = args
             .mac
             .clone()
             .ok_or_else(|| anyhow::anyhow!("--mac required"))?;
-        (ip, mac)
+        (args.ip.clone(), mac)
     };
 
-    let ip = Ipv4Addr::from_str(&ip_str)?;
+    // 使用 DHCP 时先以 0.0.0.0 启动，地址由 DHCP 客户端在运行时配置
+    let ip = match (ip_str, dhcp) {
+        (Some(ip), false) => Ipv4Addr::from_str(&ip)?,
+        (None, true) => Ipv4Addr::unspecified(),
+        (Some(_), true) => anyhow::bail!("'ip' cannot be combined with 'dhcp'"),
+        // 只配置了 IPv6 时 IPv4 地址留空
+        (None, false) if ipv6_only => Ipv4Addr::unspecified(),
+        (None, false) if file.is_some() => anyhow::bail!("Missing 'ip' in config file"),
+        (None, false) => anyhow::bail!("--ip required"),
+    };
     let mac = MacAddr::from_str(&mac_str)?;
+    let mut config = StackConfig::new(mac, ip);
+    config.dhcp = dhcp;
+    config.slaac = slaac;
+
+    // 路由相关的配置：命令行优先于配置文件，静态路由两者合并
+    let file = file.unwrap_or_default();
+
+    // 多层标签的顺序有意义，命令行给出时整体替换配置文件
+    let vids = if args.vlans.is_empty() {
+        file.vlans
+            .iter()
+            .map(|v| {
+                v.parse::<u16>()
+                    .map_err(|_| anyhow::anyhow!("Invalid vlan '{}'", v))
+            })
+            .collect::<Result<Vec<_>>>()?
+    } else {
+        args.vlans.clone()
+    };
+    // VID 0 只用于携带优先级，不能作为绑定的 VLAN
+    config.vlans = VlanTag::stacked(&vids)
+        .filter(|_| !vids.contains(&0))
+        .ok_or_else(|| {
+            anyhow::anyhow!("Invalid vlan {:?}: expected 1-{}", vids, VlanTag::MAX_VID)
+        })?;
+
+    if let Some(netmask) = args.netmask.as_ref().or(file.netmask.as_ref()) {
+        config.netmask = parse_netmask(netmask)?;
+    }
+
+    if let Some(gateway) = args.gateway.as_ref().or(file.gateway.as_ref()) {
+        if config.netmask == Ipv4Addr::unspecified() {
+            anyhow::bail!("'gateway' requires 'netmask' to be configured");
+        }
+        config.gateway = Some(
+            Ipv4Addr::from_str(gateway)
+                .map_err(|e| anyhow::anyhow!("Invalid gateway '{}': {}", gateway, e))?,
+        );
+    }
+
+    for spec in file.routes.iter().chain(args.routes.iter()) {
+        let route = Route::from_str(spec).with_context(|| format!("Invalid route: {}", spec))?;
+        config.routes.push(route);
+    }
+
+    // IPv6 地址两者合并，网关命令行优先
+    for spec in file.ipv6.iter().chain(args.ipv6.iter()) {
+        let cidr = Ipv6Cidr::from_str(spec)
+            .map_err(|e| anyhow::anyhow!("Invalid ipv6 '{}': {}", spec, e))?;
+        if cidr.addr.is_unspecified() || cidr.addr.is_multicast() {
+            anyhow::bail!("Invalid ipv6 '{}': not a unicast address", spec);
+        }
+        config.ipv6_addrs.push(cidr);
+    }
+
+    if let Some(gateway) = args.ipv6_gateway.as_ref().or(file.ipv6_gateway.as_ref()) {
+        config.ipv6_gateway = Some(
+            Ipv6Addr::from_str(gateway)
+                .map_err(|e| anyhow::anyhow!("Invalid ipv6_gateway '{}': {}", gateway, e))?,
+        );
+    }
+
+    // 与 Linux 的 stable_secret 一样写成 IPv6 地址的形式
+    if let Some(secret) = args
+        .ipv6_stable_secret
+        .as_ref()
+        .or(file.ipv6_stable_secret.as_ref())
+    {
+        let secret = Ipv6Addr::from_str(secret)
+            .map_err(|e| anyhow::anyhow!("Invalid ipv6_stable_secret '{}': {}", secret, e))?;
+        config.ipv6_stable_secret = Some(secret.octets());
+    }
+
+    config.forwarding = args.forward
+        || match file.forward.as_deref() {
+            None => false,
+            Some(v) => parse_bool(v).ok_or_else(|| anyhow::anyhow!("Invalid forward '{}'", v))?,
+        };
+
+    config.mtu = match (args.mtu, file.mtu.as_deref()) {
+        (Some(mtu), _) => Some(mtu),
+        (None, Some(v)) => Some(
+            v.parse::<usize>()
+                .map_err(|_| anyhow::anyhow!("Invalid mtu '{}'", v))?,
+        ),
+        (None, None) => None,
+    };
+    if let Some(mtu) = config.mtu
+        && mtu < 68
+    {
+        // RFC 791: 每个 IPv4 模块都必须能不分片地转发 68 字节的数据报
+        anyhow::bail!("MTU {} is below the IPv4 minimum of 68", mtu);
+    }
+    if let Some(mtu) = config.mtu
+        && mtu < 1280
+        && (!config.ipv6_addrs.is_empty() || config.slaac)
+    {
+        // RFC 8200 5: IPv6 要求链路 MTU 至少 1280
+        anyhow::bail!("MTU {} is below the IPv6 minimum of 1280", mtu);
+    }
+
+    if args.no_icmp_unreachable {
+        config.icmp_unreachable = false;
+    } else if let Some(v) = file.icmp_unreachable.as_deref() {
+        config.icmp_unreachable =
+            parse_bool(v).ok_or_else(|| anyhow::anyhow!("Invalid icmp_unreachable '{}'", v))?;
+    }
+
+    if let Some(rate) = args.icmp_rate_limit {
+        config.icmp_rate_limit = rate;
+    } else if let Some(v) = file.icmp_rate_limit.as_deref() {
+        config.icmp_rate_limit = v
+            .parse::<u32>()
+            .map_err(|_| anyhow::anyhow!("Invalid icmp_rate_limit '{}'", v))?;
+    }
+
+    if let Some(version) = args.igmp_version {
+        config.igmp_version = version;
+    } else if let Some(v) = file.igmp_version.as_deref() {
+        config.igmp_version = v
+            .parse::<u8>()
+            .map_err(|_| anyhow::anyhow!("Invalid igmp_version '{}'", v))?;
+    }
+    if !(1..=3).contains(&config.igmp_version) {
+        anyhow::bail!("Invalid IGMP version {}: expected 1-3", config.igmp_version);
+    }
+
+    Ok(config)
+}
+
+/// 通过 `attach` 关联的另一个接口
+pub struct InterfaceConfig {
+    /// 接口名，即 pcap / TAP 设备名，路由的 `dev` 字段用它指定出接口
+    pub name: String,
+    pub config: StackConfig,
+}
+
+/// 额外接口的配置，格式 `<iface> mac <mac> ip <ip>/<len>`，命令行和配置文件合并
+///
+/// 所有接口共用一张逻辑路由表：主接口加上到各附加网段的路由，
+/// 附加接口继承主接口的路由 (主接口直连的网段和网关都经由主接口)。
+/// 转发和 ICMP 相关的设置也与主接口一致。
+pub fn load_interfaces(args: &Args, primary: &mut StackConfig) -> Result<Vec<InterfaceConfig>> {
+    let file = match &args.config {
+        Some(config_path) => load_from_file(config_path)?,
+        None => FileConfig::default(),
+    };
+    let specs: Vec<&String> = file.attach.iter().chain(args.attach.iter()).collect();
+    if specs.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let primary_name = args
+        .iface
+        .clone()
+        .ok_or_else(|| anyhow::anyhow!("'attach' requires --iface"))?;
+    if primary.dhcp {
+        anyhow::bail!("'attach' cannot be combined with 'dhcp'");
+    }
+    if primary.netmask == Ipv4Addr::unspecified() {
+        anyhow::bail!("'attach' requires 'netmask' to be configured");
+    }
+
+    let mut interfaces = Vec::new();
+    for spec in specs {
+        let (name, mac, ip, netmask) =
+            parse_attach(spec).with_context(|| format!("Invalid attach: {}", spec))?;
+        if name == primary_name || interfaces.iter().any(|i: &InterfaceConfig| i.name == name) {
+            anyhow::bail!("Interface '{}' is attached twice", name);
+        }
+        let mut config = StackConfig::new(mac, ip);
+        config.netmask = netmask;
+        config.forwarding = primary.forwarding;
+        config.icmp_unreachable = primary.icmp_unreachable;
+        config.icmp_rate_limit = primary.icmp_rate_limit;
+        config.igmp_version = primary.igmp_version;
+        interfaces.push(InterfaceConfig { name, config });
+    }
+
+    // 从某个附加接口看，主接口的路由：没有指定出接口的经由主接口，指定了它自己的改为直连
+    let connected = |name: &str, config: &StackConfig| {
+        let mut route = Route::connected(config.ip, config.netmask);
+        route.iface = Some(name.to_string());
+        route
+    };
+    let mut primary_routes = vec![connected(&primary_name, primary)];
+    if let Some(gateway) = primary.gateway {
+        let mut route = Route::default_via(gateway);
+        route.iface = Some(primary_name.clone());
+        primary_routes.push(route);
+    }
+    primary_routes.extend(primary.routes.iter().cloned());
+
+    let peers: Vec<Route> = interfaces
+        .iter()
+        .map(|i| connected(&i.name, &i.config))
+        .collect();
+    for (idx, interface) in interfaces.iter_mut().enumerate() {
+        for route in &primary_routes {
+            let mut route = route.clone();
+            route.iface = match route.iface {
+                Some(iface) if iface == interface.name => None,
+                Some(iface) => Some(iface),
+                None => Some(primary_name.clone()),
+            };
+            interface.config.routes.push(route);
+        }
+        for (peer_idx, peer) in peers.iter().enumerate() {
+            if peer_idx != idx {
+                interface.config.routes.push(peer.clone());
+            }
+        }
+    }
+    primary.routes.extend(peers);
+
+    Ok(interfaces)
+}
+
+fn parse_attach(spec: &str) -> Result<(String, MacAddr, Ipv4Addr, Ipv4Addr)> {
+    let mut tokens = spec.split_whitespace();
+    let name = tokens
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("Missing interface name"))?
+        .to_string();
+
+    let mut mac = None;
+    let mut cidr = None;
+    while let Some(key) = tokens.next() {
+        let value = tokens
+            .next()
+            .ok_or_else(|| anyhow::anyhow!("Missing value for '{}'", key))?;
+        match key {
+            "mac" => mac = Some(MacAddr::from_str(value)?),
+            "ip" => {
+                let (ip, mask) = value
+                    .split_once('/')
+                    .ok_or_else(|| anyhow::anyhow!("Expected <ip>/<len>, got '{}'", value))?;
+                let ip = Ipv4Addr::from_str(ip)
+                    .map_err(|e| anyhow::anyhow!("Invalid ip '{}': {}", ip, e))?;
+                cidr = Some((ip, parse_netmask(mask)?));
+            }
+            _ => anyhow::bail!("Unknown attribute '{}'", key),
+        }
+    }
+
+    let mac = mac.ok_or_else(|| anyhow::anyhow!("Missing 'mac'"))?;
+    let (ip, netmask) = cidr.ok_or_else(|| anyhow::anyhow!("Missing 'ip'"))?;
+    Ok((name, mac, ip, netmask))
+}
+
+/// 名字解析的配置
+///
+/// 没有静态配置 DNS 服务器时，解析器使用 DHCP 获得的服务器
+pub fn load_resolver_config(args: &Args) -> Result<ResolverConfig> {
+    let file = match &args.config {
+        Some(config_path) => load_from_file(config_path)?,
+        None => FileConfig::default(),
+    };
+
+    // 命令行给出 DNS 服务器时忽略配置文件中的
+    let dns = if args.dns_servers.is_empty() {
+        &file.dns
+    } else {
+        &args.dns_servers
+    };
+    let mut servers = Vec::new();
+    for server in dns {
+        servers.push(
+            Ipv4Addr::from_str(server)
+                .map_err(|e| anyhow::anyhow!("Invalid dns '{}': {}", server, e))?,
+        );
+    }
+
+    Ok(ResolverConfig {
+        servers,
+        hosts_file: args.hosts_file.clone().or(file.hosts_file.map(Into::into)),
+        ..Default::default()
+    })
+}
+
+/// mDNS 应答的本机名字，未配置时返回 None
+pub fn load_mdns_hostname(args: &Args) -> Result<Option<String>> {
+    if args.mdns_hostname.is_some() {
+        return Ok(args.mdns_hostname.clone());
+    }
+    match &args.config {
+        Some(config_path) => Ok(load_from_file(config_path)?.mdns_hostname),
+        None => Ok(None),
+    }
+}
+
+/// DHCP 服务器的配置，未开启服务器模式时返回 None
+///
+/// 命令行优先于配置文件，DNS 服务器和静态保留两者合并
+pub fn load_dhcp_server_config(args: &Args) -> Result<Option<DhcpServerConfig>> {
+    let file = match &args.config {
+        Some(config_path) => load_from_file(config_path)?,
+        None => FileConfig::default(),
+    };
+
+    let enabled = args.dhcp_server
+        || match file.dhcp_server.as_deref() {
+            None => false,
+            Some(v) => {
+                parse_bool(v).ok_or_else(|| anyhow::anyhow!("Invalid dhcp_server '{}'", v))?
+            }
+        };
+    if !enabled {
+        return Ok(None);
+    }
+
+    let parse_ip = |name: &str, s: &str| {
+        Ipv4Addr::from_str(s.trim()).map_err(|e| anyhow::anyhow!("Invalid {} '{}': {}", name, s, e))
+    };
+
+    let pool = args
+        .dhcp_pool
+        .as_ref()
+        .or(file.dhcp_pool.as_ref())
+        .ok_or_else(|| anyhow::anyhow!("DHCP server requires 'dhcp_pool'"))?;
+    let (start, end) = pool
+        .split_once('-')
+        .ok_or_else(|| anyhow::anyhow!("Invalid dhcp_pool '{}', expected <first>-<last>", pool))?;
+    let mut config =
+        DhcpServerConfig::new(parse_ip("dhcp_pool", start)?, parse_ip("dhcp_pool", end)?);
+
+    match (args.dhcp_lease_time, file.dhcp_lease_time.as_deref()) {
+        (Some(secs), _) => config.lease_time = Duration::from_secs(secs),
+        (None, Some(v)) => {
+            let secs = v
+                .parse::<u64>()
+                .map_err(|_| anyhow::anyhow!("Invalid dhcp_lease_time '{}'", v))?;
+            config.lease_time = Duration::from_secs(secs);
+        }
+        (None, None) => {}
+    }
+    if config.lease_time.is_zero() {
+        anyhow::bail!("DHCP lease time must be at least 1 second");
+    }
+
+    if let Some(router) = args.dhcp_router.as_ref().or(file.dhcp_router.as_ref()) {
+        config.router = Some(parse_ip("dhcp_router", router)?);
+    }
+
+    for server in file.dhcp_dns.iter().chain(args.dhcp_dns.iter()) {
+        config.dns_servers.push(parse_ip("dhcp_dns", server)?);
+    }
+
+    let mut reservations = HashMap::new();
+    for spec in file
+        .dhcp_reservations
+        .iter()
+        .chain(args.dhcp_reservations.iter())
+    {
+        let (mac, ip) = spec.split_once(',').ok_or_else(|| {
+            anyhow::anyhow!("Invalid dhcp_reserve '{}', expected <mac>,<ip>", spec)
+        })?;
+        let mac = MacAddr::from_str(mac.trim())?;
+        reservations.insert(mac, parse_ip("dhcp_reserve", ip)?);
+    }
+    config.reservations = reservations;
+
+    config.lease_file = args
+        .dhcp_lease_file
+        .clone()
+        .or(file.dhcp_lease_file.map(Into::into));
+
+    Ok(Some(config))
+}
+
+/// 由命令行参数得到 ping 的配置
+pub fn load_ping_config(args: &Args) -> Result<PingConfig> {
+    let seconds = |name: &str, v: f64| {
+        if v.is_finite() && v > 0.0 {
+            Ok(Duration::from_secs_f64(v))
+        } else {
+            Err(anyhow::anyhow!("Invalid {} '{}'", name, v))
+        }
+    };
+
+    if args.ttl == 0 {
+        anyhow::bail!("TTL must be at least 1");
+    }
+    // IPv4 total_len 上限 65535，减去 20 字节 IP 首部和 8 字节 ICMP 首部
+    if args.size > 65507 {
+        anyhow::bail!("Echo data size {} is larger than 65507", args.size);
+    }
+
+    Ok(PingConfig {
+        count: args.count,
+        interval: seconds("interval", args.interval)?,
+        payload_size: args.size,
+        ttl: args.ttl,
+        dont_fragment: args.dont_fragment,
+        timeout: seconds("timeout", args.timeout)?,
+        quiet: false,
+    })
+}
+
+/// 由命令行参数得到 traceroute 的配置
+pub fn load_traceroute_config(args: &Args) -> Result<TracerouteConfig> {
+    if args.first_ttl == 0 || args.first_ttl > args.max_hops {
+        anyhow::bail!(
+            "First TTL {} must be between 1 and max hops {}",
+            args.first_ttl,
+            args.max_hops
+        );
+    }
+    if args.probes == 0 {
+        anyhow::bail!("Probes per hop must be at least 1");
+    }
+    if !(args.timeout.is_finite() && args.timeout > 0.0) {
+        anyhow::bail!("Invalid timeout '{}'", args.timeout);
+    }
+    if args.size > 65507 {
+        anyhow::bail!("Probe data size {} is larger than 65507", args.size);
+    }
 
-    Ok(StackConfig { mac, ip })
+    Ok(TracerouteConfig {
+        method: args.method,
+        first_ttl: args.first_ttl,
+        max_hops: args.max_hops,
+        probes: args.probes,
+        timeout: Duration::from_secs_f64(args.timeout),
+        port: args.port,
+        payload_size: args.size,
+        quiet: false,
+    })
 }
 
-fn load_from_file(path: &std::path::Path) -> Result<(String, String)> {
+fn parse_bool(s: &str) -> Option<bool> {
+    match s {
+        "1" | "true" | "yes" | "on" => Some(true),
+        "0" | "false" | "no" | "off" => Some(false),
+        _ => None,
+    }
+}
+
+/// 掩码既可以写成点分十进制，也可以写成前缀长度
+fn parse_netmask(s: &str) -> Result<Ipv4Addr> {
+    if s.contains('.') {
+        route::parse_netmask(s)
+    } else {
+        let len = s
+            .parse::<u32>()
+            .map_err(|_| anyhow::anyhow!("Invalid netmask '{}'", s))?;
+        route::netmask_from_prefix_len(len)
+    }
+}
+
+fn load_from_file(path: &std::path::Path) -> Result<FileConfig> {
     let content = fs::read_to_string(path)
         .with_context(|| format!("Failed to read config file: {}", path.display()))?;
 
-    let mut ip = None;
-    let mut mac = None;
+    let mut config = FileConfig::default();
 
     for line in content.lines() {
         let line = line.trim();
@@ -57,15 +574,253 @@ fn load_from_file(path: &std::path::Path) -> Result<(String, String)> {
             let value = value.trim();
 
             match key {
-                "ip" => ip = Some(value.to_string()),
-                "mac" => mac = Some(value.to_string()),
+                "ip" => config.ip = Some(value.to_string()),
+                "mac" => config.mac = Some(value.to_string()),
+                "vlan" => config.vlans.push(value.to_string()),
+                "netmask" => config.netmask = Some(value.to_string()),
+                "gateway" => config.gateway = Some(value.to_string()),
+                "route" => config.routes.push(value.to_string()),
+                "attach" => config.attach.push(value.to_string()),
+                "ipv6" => config.ipv6.push(value.to_string()),
+                "ipv6_gateway" => config.ipv6_gateway = Some(value.to_string()),
+                "slaac" => config.slaac = Some(value.to_string()),
+                "ipv6_stable_secret" => config.ipv6_stable_secret = Some(value.to_string()),
+                "forward" => config.forward = Some(value.to_string()),
+                "mtu" => config.mtu = Some(value.to_string()),
+                "icmp_unreachable" => config.icmp_unreachable = Some(value.to_string()),
+                "icmp_rate_limit" => config.icmp_rate_limit = Some(value.to_string()),
+                "igmp_version" => config.igmp_version = Some(value.to_string()),
+                "dhcp" => config.dhcp = Some(value.to_string()),
+                "dns" => config.dns.push(value.to_string()),
+                "hosts_file" => config.hosts_file = Some(value.to_string()),
+                "mdns_hostname" => config.mdns_hostname = Some(value.to_string()),
+                "dhcp_server" => config.dhcp_server = Some(value.to_string()),
+                "dhcp_pool" => config.dhcp_pool = Some(value.to_string()),
+                "dhcp_lease_time" => config.dhcp_lease_time = Some(value.to_string()),
+                "dhcp_router" => config.dhcp_router = Some(value.to_string()),
+                "dhcp_dns" => config.dhcp_dns.push(value.to_string()),
+                "dhcp_reserve" => config.dhcp_reservations.push(value.to_string()),
+                "dhcp_lease_file" => config.dhcp_lease_file = Some(value.to_string()),
                 _ => eprintln!("Warning: Unknown config key: {}", key),
             }
         }
     }
 
-    let ip = ip.ok_or_else(|| anyhow::anyhow!("Missing 'ip' in config file"))?;
-    let mac = mac.ok_or_else(|| anyhow::anyhow!("Missing 'mac' in config file"))?;
+    Ok(config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sim::{LinkConfig, SegmentKind, Simulator};
+    use crate::transport::udp::UdpSocket;
+    use clap::Parser;
+
+    fn router_args(extra: &[&str]) -> Args {
+        let mut argv = vec![
+            "net_stack",
+            "--iface",
+            "eth0",
+            "--mac",
+            "02:00:00:00:00:01",
+            "--ip",
+            "10.0.0.1",
+            "--netmask",
+            "24",
+            "--forward",
+            "--attach",
+            "eth1 mac 02:00:00:00:01:01 ip 10.1.0.1/24",
+        ];
+        argv.extend_from_slice(extra);
+        Args::parse_from(argv)
+    }
+
+    fn route(spec: &str) -> Route {
+        Route::from_str(spec).unwrap()
+    }
+
+    #[test]
+    fn attach_links_routing_tables() {
+        let args = router_args(&[
+            "--gateway",
+            "10.0.0.254",
+            "--route",
+            "10.9.0.0/16 via 10.1.0.9 dev eth1",
+        ]);
+        let mut primary = load_config(&args).unwrap();
+        let interfaces = load_interfaces(&args, &mut primary).unwrap();
 
-    Ok((ip, mac))
+        assert_eq!(interfaces.len(), 1);
+        let eth1 = &interfaces[0];
+        assert_eq!(eth1.name, "eth1");
+        assert_eq!(eth1.config.ip, Ipv4Addr::new(10, 1, 0, 1));
+        assert!(eth1.config.forwarding);
+
+        assert!(primary.routes.contains(&route("10.1.0.0/24 dev eth1")));
+        assert_eq!(
+            eth1.config.routes,
+            vec![
+                route("10.0.0.0/24 dev eth0"),
+                route("default via 10.0.0.254 dev eth0"),
+                route("10.9.0.0/16 via 10.1.0.9"),
+            ]
+        );
+    }
+
+    #[test]
+    fn attach_rejects_invalid_specs() {
+        for spec in [
+            "eth1 ip 10.1.0.1/24",
+            "eth1 mac 02:00:00:00:01:01",
+            "eth1 mac 02:00:00:00:01:01 ip 10.1.0.1",
+            "eth1 mac 02:00:00:00:01:01 ip 10.1.0.1/24 vlan 5",
+            "eth0 mac 02:00:00:00:01:01 ip 10.1.0.1/24",
+        ] {
+            let mut args = router_args(&[]);
+            args.attach = vec![spec.to_string()];
+            let mut primary = load_config(&args).unwrap();
+            assert!(load_interfaces(&args, &mut primary).is_err(), "{}", spec);
+        }
+    }
+
+    fn host(mac: &str, ip: &str, gateway: &str) -> StackConfig {
+        let mut config = StackConfig::new(
+            MacAddr::from_str(mac).unwrap(),
+            Ipv4Addr::from_str(ip).unwrap(),
+        );
+        config.netmask = Ipv4Addr::new(255, 255, 255, 0);
+        config.gateway = Some(Ipv4Addr::from_str(gateway).unwrap());
+        config
+    }
+
+    // 两个网段各一台主机，中间是由 --attach 配置出的双接口路由器
+    #[test]
+    fn attached_interfaces_route_between_segments() {
+        let args = router_args(&[]);
+        let mut primary = load_config(&args).unwrap();
+        let mut interfaces = load_interfaces(&args, &mut primary).unwrap();
+        let eth1 = interfaces.pop().unwrap();
+
+        let mut lan0 = Simulator::new(SegmentKind::Switch, LinkConfig::default());
+        let mut lan1 = Simulator::new(SegmentKind::Switch, LinkConfig::default());
+        let r0 = lan0.add_host(primary);
+        let r1 = lan1.add_host(eth1.config);
+        r0.attach_interface("eth1", &r1);
+        r1.attach_interface("eth0", &r0);
+        let a = lan0.add_host(host("02:00:00:00:00:02", "10.0.0.2", "10.0.0.1"));
+        let b = lan1.add_host(host("02:00:00:00:01:02", "10.1.0.2", "10.1.0.1"));
+
+        let mut run = || {
+            for _ in 0..4 {
+                lan0.run_until_idle();
+                lan1.run_until_idle();
+            }
+        };
+
+        let client = UdpSocket::bind(a, "0.0.0.0:40000").unwrap();
+        let server = UdpSocket::bind(b, "0.0.0.0:9000").unwrap();
+        client.send_to(b"across", "10.1.0.2:9000").unwrap();
+        run();
+        let (data, src) = server.recv_from().unwrap();
+        assert_eq!(data, b"across");
+        assert_eq!(src, "10.0.0.2:40000");
+
+        server.send_to(b"back", &src).unwrap();
+        run();
+        assert_eq!(client.recv_from().unwrap().0, b"back");
+
+        // 路由器另一个接口的地址由该接口自己接收
+        let far = UdpSocket::bind(r1, "0.0.0.0:7").unwrap();
+        client.send_to(b"router", "10.1.0.1:7").unwrap();
+        run();
+        assert_eq!(far.recv_from().unwrap().0, b"router");
+    }
+
+    #[test]
+    fn ipv6_options_are_loaded() {
+        let args = Args::parse_from([
+            "net_stack",
+            "--iface",
+            "eth0",
+            "--mac",
+            "02:00:00:00:00:01",
+            "--slaac",
+            "--ipv6",
+            "2001:db8::2/64",
+            "--ipv6-gateway",
+            "fe80::1",
+            "--ipv6-stable-secret",
+            "0102:0304:0506:0708:090a:0b0c:0d0e:0f10",
+        ]);
+        let config = load_config(&args).unwrap();
+        // 只配置 IPv6 时不要求 IPv4 地址
+        assert_eq!(config.ip, Ipv4Addr::unspecified());
+        assert!(config.slaac);
+        assert_eq!(
+            config.ipv6_addrs,
+            vec![Ipv6Cidr::from_str("2001:db8::2/64").unwrap()]
+        );
+        assert_eq!(
+            config.ipv6_gateway,
+            Some(Ipv6Addr::from_str("fe80::1").unwrap())
+        );
+        assert_eq!(
+            config.ipv6_stable_secret,
+            Some(std::array::from_fn(|i| i as u8 + 1))
+        );
+
+        for extra in [
+            ["--ipv6", "ff02::1/64"],
+            ["--ipv6-stable-secret", "secret"],
+            ["--mtu", "1200"],
+        ] {
+            let mut argv = vec![
+                "net_stack",
+                "--iface",
+                "eth0",
+                "--mac",
+                "02:00:00:00:00:01",
+                "--slaac",
+            ];
+            argv.extend_from_slice(&extra);
+            assert!(load_config(&Args::parse_from(argv)).is_err(), "{:?}", extra);
+        }
+    }
+
+    #[test]
+    fn vlans_are_loaded() {
+        let host_args = |extra: &[&str]| {
+            let mut argv = vec!["net_stack", "--iface", "eth0"];
+            argv.extend_from_slice(extra);
+            Args::parse_from(argv)
+        };
+        let base = ["--mac", "02:00:00:00:00:01", "--ip", "10.0.0.1"];
+
+        let config = load_config(&host_args(&base)).unwrap();
+        assert!(config.vlans.is_empty());
+        let config = load_config(&host_args(
+            &[&base[..], &["--vlan", "100", "--vlan", "10"]].concat(),
+        ))
+        .unwrap();
+        assert_eq!(config.vlans, VlanTag::stacked(&[100, 10]).unwrap());
+        for vid in ["0", "4095"] {
+            assert!(load_config(&host_args(&[&base[..], &["--vlan", vid]].concat())).is_err());
+        }
+
+        // 配置文件中的标签按出现顺序，命令行给出时整体替换
+        let path = std::env::temp_dir().join(format!("net_stack_vlan_{}.conf", std::process::id()));
+        fs::write(
+            &path,
+            "mac=02:00:00:00:00:01\nip=10.0.0.1\nvlan=200\nvlan=20\n",
+        )
+        .unwrap();
+        let path_arg = path.to_str().unwrap();
+        let config = load_config(&host_args(&["--config", path_arg])).unwrap();
+        assert_eq!(config.vlans, VlanTag::stacked(&[200, 20]).unwrap());
+        let config = load_config(&host_args(&["--config", path_arg, "--vlan", "30"])).unwrap();
+        assert_eq!(config.vlans, VlanTag::stacked(&[30]).unwrap());
+        fs::write(&path, "mac=02:00:00:00:00:01\nip=10.0.0.1\nvlan=ten\n").unwrap();
+        assert!(load_config(&host_args(&["--config", path_arg])).is_err());
+        fs::remove_file(&path).unwrap();
+    }
 }
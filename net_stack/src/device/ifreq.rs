@@ -0,0 +1,89 @@
+// Copyright (C) 2025 rrrrrzy
+// SPDX-License-Identifier: GPL-3.0-or-later
+//
+// --------------------------------------------------
+// 致敬所有在深夜调试代码的灵魂。
+// 即便 Bug 如山，我亦往矣。
+// --------------------------------------------------
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+//! 通过 `ifreq` ioctl 查询网卡属性，TAP 和 pcap 设备共用
+
+use protocol::mac::MacAddr;
+
+pub(super) fn new_ifreq(name: &str) -> libc::ifreq {
+    // SAFETY: ifreq 是纯 C 结构体，全零是合法值
+    let mut req: libc::ifreq = unsafe { std::mem::zeroed() };
+    for (dst, src) in req.ifr_name.iter_mut().zip(name.bytes()) {
+        *dst = src as libc::c_char;
+    }
+    req
+}
+
+pub(super) fn ifreq_name(req: &libc::ifreq) -> String {
+    let bytes: Vec<u8> = req
+        .ifr_name
+        .iter()
+        .take_while(|&&c| c != 0)
+        .map(|&c| c as u8)
+        .collect();
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+/// 在一个临时的普通 socket 上对指定网卡执行 ioctl，失败时返回 None
+fn socket_ioctl(name: &str, request: libc::c_ulong) -> Option<libc::ifreq> {
+    if name.len() >= libc::IFNAMSIZ {
+        return None;
+    }
+    // SAFETY: 参数均为常量
+    let sock = unsafe { libc::socket(libc::AF_INET, libc::SOCK_DGRAM, 0) };
+    if sock < 0 {
+        return None;
+    }
+
+    let mut req = new_ifreq(name);
+    // SAFETY: sock 是刚创建的合法 socket，req 在调用期间有效
+    let ret = unsafe { libc::ioctl(sock, request, &mut req) };
+    // SAFETY: sock 只在这里使用，关闭后不再访问
+    unsafe { libc::close(sock) };
+
+    (ret >= 0).then_some(req)
+}
+
+/// 网卡自身的 MAC 地址 (SIOCGIFHWADDR)
+pub(super) fn query_hwaddr(name: &str) -> Option<MacAddr> {
+    let req = socket_ioctl(name, libc::SIOCGIFHWADDR)?;
+    // SAFETY: SIOCGIFHWADDR 成功后联合体中有效的成员是 ifru_hwaddr
+    let sa_data = unsafe { req.ifr_ifru.ifru_hwaddr.sa_data };
+    let mut mac = [0u8; 6];
+    for (dst, src) in mac.iter_mut().zip(sa_data.iter()) {
+        *dst = *src as u8;
+    }
+    Some(MacAddr::from_raw(mac))
+}
+
+/// 网卡的 MTU (SIOCGIFMTU)
+pub(super) fn query_mtu(name: &str) -> Option<usize> {
+    let req = socket_ioctl(name, libc::SIOCGIFMTU)?;
+    // SAFETY: SIOCGIFMTU 成功后联合体中有效的成员是 ifru_mtu
+    let mtu = unsafe { req.ifr_ifru.ifru_mtu };
+    usize::try_from(mtu).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn loopback_is_queried() {
+        assert_eq!(ifreq_name(&new_ifreq("tap0")), "tap0");
+        assert_eq!(query_hwaddr("lo"), Some(MacAddr::zero()));
+        assert!(query_mtu("lo").is_some_and(|mtu| mtu >= 1280));
+        assert_eq!(query_mtu("no-such-if0"), None);
+        assert_eq!(query_hwaddr("an-interface-name-too-long"), None);
+    }
+}
@@ -0,0 +1,89 @@
+// Copyright (C) 2025 rrrrrzy
+// SPDX-License-Identifier: GPL-3.0-or-later
+//
+// --------------------------------------------------
+// 致敬所有在深夜调试代码的灵魂。
+// 即便 Bug 如山，我亦往矣。
+// --------------------------------------------------
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+use protocol::mac::MacAddr;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+use super::{DEFAULT_MTU, Device};
+
+/// 内存设备：收发帧都放在内存队列里，不需要 root 权限和真实网卡
+///
+/// 所有克隆共享同一对队列，因此可以把同一个设备同时交给
+/// `NetworkStack` 作为收发两端，测试代码再持有一份用来注入/取出帧。
+#[derive(Debug, Clone)]
+pub struct MemoryDevice {
+    rx_queue: Arc<Mutex<VecDeque<Vec<u8>>>>,
+    tx_queue: Arc<Mutex<VecDeque<Vec<u8>>>>,
+    mtu: usize,
+    link_addr: Option<MacAddr>,
+}
+
+impl MemoryDevice {
+    pub fn new() -> Self {
+        Self {
+            rx_queue: Arc::new(Mutex::new(VecDeque::new())),
+            tx_queue: Arc::new(Mutex::new(VecDeque::new())),
+            mtu: DEFAULT_MTU,
+            link_addr: None,
+        }
+    }
+
+    pub fn set_mtu(&mut self, mtu: usize) {
+        self.mtu = mtu;
+    }
+
+    pub fn set_link_addr(&mut self, mac: MacAddr) {
+        self.link_addr = Some(mac);
+    }
+
+    /// 注入一帧，等待协议栈接收
+    pub fn inject(&self, frame: &[u8]) {
+        self.rx_queue.lock().unwrap().push_back(frame.to_vec());
+    }
+
+    /// 取出协议栈发送的第一帧
+    pub fn pop_transmitted(&self) -> Option<Vec<u8>> {
+        self.tx_queue.lock().unwrap().pop_front()
+    }
+
+    /// 取出协议栈发送的全部帧
+    pub fn take_transmitted(&self) -> Vec<Vec<u8>> {
+        self.tx_queue.lock().unwrap().drain(..).collect()
+    }
+}
+
+impl Default for MemoryDevice {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Device for MemoryDevice {
+    fn receive(&mut self) -> anyhow::Result<Option<Vec<u8>>> {
+        Ok(self.rx_queue.lock().unwrap().pop_front())
+    }
+
+    fn transmit(&mut self, frame: &[u8]) -> anyhow::Result<()> {
+        self.tx_queue.lock().unwrap().push_back(frame.to_vec());
+        Ok(())
+    }
+
+    fn mtu(&self) -> usize {
+        self.mtu
+    }
+
+    fn link_addr(&self) -> Option<MacAddr> {
+        self.link_addr
+    }
+}
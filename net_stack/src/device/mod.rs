@@ -0,0 +1,66 @@
+// Copyright (C) 2025 rrrrrzy
+// SPDX-License-Identifier: GPL-3.0-or-later
+//
+// --------------------------------------------------
+// 致敬所有在深夜调试代码的灵魂。
+// 即便 Bug 如山，我亦往矣。
+// --------------------------------------------------
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+use protocol::mac::MacAddr;
+
+#[cfg(target_os = "linux")]
+mod ifreq;
+pub mod memory;
+pub mod pcap;
+pub mod replay;
+#[cfg(target_os = "linux")]
+pub mod tap;
+
+pub use memory::MemoryDevice;
+pub use pcap::PcapDevice;
+pub use replay::ReplayDevice;
+#[cfg(target_os = "linux")]
+pub use tap::TapDevice;
+
+/// 以太网默认 MTU (不含以太网头)
+pub const DEFAULT_MTU: usize = 1500;
+
+// 实时设备单次 receive 最多等待的时间，超时后返回 Ok(None) 让事件循环有机会处理定时器和发送
+const POLL_TIMEOUT_MS: i32 = 10;
+
+/// 链路层设备抽象
+///
+/// `NetworkStack` 只通过该 trait 收发以太网帧，不关心底层是 pcap 抓包、
+/// TAP 设备还是内存队列。
+pub trait Device: Send {
+    /// 接收一帧完整的以太网帧
+    /// 返回 `Ok(None)` 表示本次轮询没有数据 (超时)，调用方应继续轮询
+    fn receive(&mut self) -> anyhow::Result<Option<Vec<u8>>>;
+
+    /// 发送一帧完整的以太网帧
+    fn transmit(&mut self, frame: &[u8]) -> anyhow::Result<()>;
+
+    /// 链路 MTU，即以太网帧载荷的最大长度
+    fn mtu(&self) -> usize {
+        DEFAULT_MTU
+    }
+
+    /// 宿主机一侧网卡自身的 MAC 地址，未知时返回 None
+    ///
+    /// pcap 设备返回被抓包网卡的地址，TAP 设备返回内核一侧 tap 接口的地址，
+    /// 都不是协议栈自己使用的地址 (`StackConfig::mac`)。
+    fn link_addr(&self) -> Option<MacAddr> {
+        None
+    }
+
+    /// 设备是否已经没有更多输入 (例如离线回放读到了文件末尾)
+    /// 实时设备永远返回 false
+    fn is_exhausted(&self) -> bool {
+        false
+    }
+}
@@ -0,0 +1,110 @@
+// Copyright (C) 2025 rrrrrzy
+// SPDX-License-Identifier: GPL-3.0-or-later
+//
+// --------------------------------------------------
+// 致敬所有在深夜调试代码的灵魂。
+// 即便 Bug 如山，我亦往矣。
+// --------------------------------------------------
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+use pcap::{Active, Capture};
+use protocol::mac::MacAddr;
+
+use super::{DEFAULT_MTU, Device, POLL_TIMEOUT_MS};
+
+/// 基于 libpcap 的物理网卡设备
+pub struct PcapDevice {
+    capture: Capture<Active>,
+    mtu: usize,
+    link_addr: Option<MacAddr>,
+}
+
+impl PcapDevice {
+    /// 在指定网卡上打开一个活动抓包句柄，MTU 和 MAC 地址取自网卡
+    pub fn open(iface: &str) -> anyhow::Result<Self> {
+        let device = pcap::Device::list()?
+            .into_iter()
+            .find(|d| d.name == iface)
+            .ok_or_else(|| anyhow::anyhow!("Device not found"))?;
+
+        // 设置读超时并关闭缓冲，链路空闲时 receive 也会按时返回，事件循环的定时器不会停摆
+        let capture = Capture::from_device(device)?
+            .timeout(POLL_TIMEOUT_MS)
+            .immediate_mode(true)
+            .open()?;
+        #[cfg(target_os = "linux")]
+        let (mtu, link_addr) = (
+            super::ifreq::query_mtu(iface).unwrap_or(DEFAULT_MTU),
+            super::ifreq::query_hwaddr(iface),
+        );
+        #[cfg(not(target_os = "linux"))]
+        let (mtu, link_addr) = (DEFAULT_MTU, None);
+        Ok(Self {
+            capture,
+            mtu,
+            link_addr,
+        })
+    }
+
+    pub fn from_capture(capture: Capture<Active>) -> Self {
+        Self {
+            capture,
+            mtu: DEFAULT_MTU,
+            link_addr: None,
+        }
+    }
+
+    pub fn set_mtu(&mut self, mtu: usize) {
+        self.mtu = mtu;
+    }
+}
+
+/// 读超时 (`TimeoutExpired`) 表示本次轮询没有数据
+fn frame_or_timeout(packet: Result<&[u8], pcap::Error>) -> anyhow::Result<Option<Vec<u8>>> {
+    match packet {
+        Ok(data) => Ok(Some(data.to_vec())),
+        Err(pcap::Error::TimeoutExpired) => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+impl Device for PcapDevice {
+    fn receive(&mut self) -> anyhow::Result<Option<Vec<u8>>> {
+        frame_or_timeout(self.capture.next_packet().map(|packet| packet.data))
+    }
+
+    fn transmit(&mut self, frame: &[u8]) -> anyhow::Result<()> {
+        self.capture.sendpacket(frame)?;
+        Ok(())
+    }
+
+    fn mtu(&self) -> usize {
+        self.mtu
+    }
+
+    fn link_addr(&self) -> Option<MacAddr> {
+        self.link_addr
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_timeout_is_an_empty_poll() {
+        assert_eq!(
+            frame_or_timeout(Err(pcap::Error::TimeoutExpired)).unwrap(),
+            None
+        );
+        assert_eq!(
+            frame_or_timeout(Ok(&[0xff; 14])).unwrap(),
+            Some(vec![0xff; 14])
+        );
+        assert!(frame_or_timeout(Err(pcap::Error::NoMorePackets)).is_err());
+    }
+}
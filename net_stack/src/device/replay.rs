@@ -0,0 +1,130 @@
+// Copyright (C) 2025 rrrrrzy
+// SPDX-License-Identifier: GPL-3.0-or-later
+//
+// --------------------------------------------------
+// 致敬所有在深夜调试代码的灵魂。
+// 即便 Bug 如山，我亦往矣。
+// --------------------------------------------------
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+use pcap::{Capture, Linktype, Offline, Packet, PacketHeader, Savefile};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::{DEFAULT_MTU, Device};
+
+/// 离线回放设备
+///
+/// 从 `.pcap` 文件中按顺序读取输入帧，并把协议栈发出的每一帧写入输出 `.pcap`。
+/// 回放不按原始时间戳节奏进行，而是尽快把所有帧交给协议栈。
+pub struct ReplayDevice {
+    input: Option<Capture<Offline>>,
+    output: Option<Savefile>,
+    mtu: usize,
+}
+
+impl ReplayDevice {
+    /// 打开输入文件；`output` 为 None 时丢弃协议栈发出的帧
+    pub fn open(input: &Path, output: Option<&Path>) -> anyhow::Result<Self> {
+        let capture = Capture::from_file(input)
+            .map_err(|e| anyhow::anyhow!("Failed to open {}: {}", input.display(), e))?;
+
+        let output = match output {
+            Some(path) => Some(create_savefile(path)?),
+            None => None,
+        };
+
+        Ok(Self {
+            input: Some(capture),
+            output,
+            mtu: DEFAULT_MTU,
+        })
+    }
+
+    /// 只写不读的设备，用于把发送端单独记录到文件
+    pub fn output_only(output: &Path) -> anyhow::Result<Self> {
+        Ok(Self {
+            input: None,
+            output: Some(create_savefile(output)?),
+            mtu: DEFAULT_MTU,
+        })
+    }
+
+    pub fn set_mtu(&mut self, mtu: usize) {
+        self.mtu = mtu;
+    }
+}
+
+impl Device for ReplayDevice {
+    fn receive(&mut self) -> anyhow::Result<Option<Vec<u8>>> {
+        let Some(capture) = self.input.as_mut() else {
+            return Ok(None);
+        };
+
+        match capture.next_packet() {
+            Ok(packet) => Ok(Some(packet.data.to_vec())),
+            Err(pcap::Error::NoMorePackets) => {
+                self.input = None;
+                Ok(None)
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn transmit(&mut self, frame: &[u8]) -> anyhow::Result<()> {
+        let Some(savefile) = self.output.as_mut() else {
+            return Ok(());
+        };
+
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let header = PacketHeader {
+            ts: libc::timeval {
+                tv_sec: now.as_secs() as _,
+                tv_usec: now.subsec_micros() as _,
+            },
+            caplen: frame.len() as u32,
+            len: frame.len() as u32,
+        };
+
+        savefile.write(&Packet::new(&header, frame));
+        // 每帧都刷盘，协议栈可能直到进程退出都不会释放设备
+        savefile.flush()?;
+        Ok(())
+    }
+
+    fn mtu(&self) -> usize {
+        self.mtu
+    }
+
+    fn is_exhausted(&self) -> bool {
+        self.input.is_none()
+    }
+}
+
+/// 读取一个 `.pcap` 文件中的全部帧，方便与协议栈输出做对比
+pub fn read_frames(path: &Path) -> anyhow::Result<Vec<Vec<u8>>> {
+    let mut capture = Capture::from_file(path)
+        .map_err(|e| anyhow::anyhow!("Failed to open {}: {}", path.display(), e))?;
+
+    let mut frames = Vec::new();
+    loop {
+        match capture.next_packet() {
+            Ok(packet) => frames.push(packet.data.to_vec()),
+            Err(pcap::Error::NoMorePackets) => break,
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(frames)
+}
+
+fn create_savefile(path: &Path) -> anyhow::Result<Savefile> {
+    let dead = Capture::dead(Linktype::ETHERNET)?;
+    dead.savefile(path)
+        .map_err(|e| anyhow::anyhow!("Failed to create {}: {}", path.display(), e))
+}
@@ -0,0 +1,160 @@
+// Copyright (C) 2025 rrrrrzy
+// SPDX-License-Identifier: GPL-3.0-or-later
+//
+// --------------------------------------------------
+// 致敬所有在深夜调试代码的灵魂。
+// 即便 Bug 如山，我亦往矣。
+// --------------------------------------------------
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+use protocol::mac::MacAddr;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::os::fd::AsRawFd;
+
+use super::ifreq::{ifreq_name, new_ifreq, query_hwaddr, query_mtu};
+use super::{DEFAULT_MTU, Device, POLL_TIMEOUT_MS};
+
+const TUN_CLONE_DEVICE: &str = "/dev/net/tun";
+
+/// Linux TAP 设备
+///
+/// 通过 `/dev/net/tun` 以 `IFF_TAP | IFF_NO_PI` 模式打开，读写的都是完整的以太网帧。
+/// 另一端是宿主机内核，所以可以直接用系统自带的 ping / nc 等工具和协议栈通信。
+pub struct TapDevice {
+    file: File,
+    name: String,
+    mtu: usize,
+    link_addr: Option<MacAddr>,
+}
+
+impl TapDevice {
+    /// 打开 (不存在时创建) 指定名字的 TAP 接口
+    pub fn open(name: &str) -> anyhow::Result<Self> {
+        if name.len() >= libc::IFNAMSIZ {
+            anyhow::bail!("TAP interface name too long: {}", name);
+        }
+
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(TUN_CLONE_DEVICE)
+            .map_err(|e| anyhow::anyhow!("Failed to open {}: {}", TUN_CLONE_DEVICE, e))?;
+
+        let mut req = new_ifreq(name);
+        req.ifr_ifru.ifru_flags = (libc::IFF_TAP | libc::IFF_NO_PI) as libc::c_short;
+
+        // SAFETY: file 是合法的 tun 文件描述符，req 在调用期间有效
+        if unsafe { libc::ioctl(file.as_raw_fd(), libc::TUNSETIFF, &mut req) } < 0 {
+            anyhow::bail!(
+                "TUNSETIFF on {} failed: {}",
+                name,
+                io::Error::last_os_error()
+            );
+        }
+
+        // 内核可能会改写接口名 (例如传入 "tap%d")
+        let name = ifreq_name(&req);
+
+        Ok(Self {
+            link_addr: query_hwaddr(&name),
+            mtu: query_mtu(&name).unwrap_or(DEFAULT_MTU),
+            file,
+            name,
+        })
+    }
+
+    /// 复制一个指向同一 TAP 队列的句柄，用于收发分离
+    pub fn try_clone(&self) -> anyhow::Result<Self> {
+        Ok(Self {
+            file: self.file.try_clone()?,
+            name: self.name.clone(),
+            mtu: self.mtu,
+            link_addr: self.link_addr,
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn set_mtu(&mut self, mtu: usize) {
+        self.mtu = mtu;
+    }
+}
+
+impl Device for TapDevice {
+    fn receive(&mut self) -> anyhow::Result<Option<Vec<u8>>> {
+        let mut pfd = libc::pollfd {
+            fd: self.file.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+
+        // SAFETY: pfd 指向一个有效的 pollfd，数量为 1
+        let ready = unsafe { libc::poll(&mut pfd, 1, POLL_TIMEOUT_MS) };
+        if ready < 0 {
+            let err = io::Error::last_os_error();
+            if err.kind() == io::ErrorKind::Interrupted {
+                return Ok(None);
+            }
+            return Err(err.into());
+        }
+        if ready == 0 {
+            return Ok(None);
+        }
+
+        // 以太网头 + MTU，额外留一点余量给 VLAN 标签
+        let mut buf = vec![0u8; self.mtu + 64];
+        let n = self.file.read(&mut buf)?;
+        buf.truncate(n);
+        Ok(Some(buf))
+    }
+
+    fn transmit(&mut self, frame: &[u8]) -> anyhow::Result<()> {
+        self.file.write_all(frame)?;
+        Ok(())
+    }
+
+    fn mtu(&self) -> usize {
+        self.mtu
+    }
+
+    fn link_addr(&self) -> Option<MacAddr> {
+        self.link_addr
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn long_names_are_rejected() {
+        let name = "t".repeat(libc::IFNAMSIZ);
+        let err = TapDevice::open(&name).err().unwrap();
+        assert!(err.to_string().contains("too long"));
+    }
+
+    /// 需要 /dev/net/tun 和 CAP_NET_ADMIN，没有权限时跳过
+    #[test]
+    fn tap_reports_the_kernel_side_interface() {
+        let Ok(mut dev) = TapDevice::open("nstest%d") else {
+            return;
+        };
+        assert!(dev.name().starts_with("nstest"));
+        assert_eq!(dev.mtu(), DEFAULT_MTU);
+        let host_mac = dev.link_addr().unwrap();
+        assert!(host_mac != MacAddr::zero() && !host_mac.is_multicast());
+
+        // 接口没有启用，轮询超时后返回 None
+        assert_eq!(dev.receive().unwrap(), None);
+        let clone = dev.try_clone().unwrap();
+        assert_eq!(clone.name(), dev.name());
+        assert_eq!(clone.link_addr(), Some(host_mac));
+    }
+}
@@ -0,0 +1,562 @@
+// Copyright (C) 2025 rrrrrzy
+// SPDX-License-Identifier: GPL-3.0-or-later
+//
+// --------------------------------------------------
+// 致敬所有在深夜调试代码的灵魂。
+// 即便 Bug 如山，我亦往矣。
+// --------------------------------------------------
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+//! DHCP 客户端：获取地址，按 T1 / T2 续租和重新绑定，退出时释放
+
+use std::fmt;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use protocol::dhcp::{
+    BOOTREPLY, BOOTREQUEST, DHCP_CLIENT_PORT, DHCP_FLAG_BROADCAST, DHCP_OPT_DNS_SERVER,
+    DHCP_OPT_DOMAIN_NAME, DHCP_OPT_LEASE_TIME, DHCP_OPT_REBINDING_TIME, DHCP_OPT_RENEWAL_TIME,
+    DHCP_OPT_ROUTER, DHCP_OPT_SUBNET_MASK, DHCP_SERVER_PORT, DhcpMessage, DhcpMessageType,
+    DhcpOption,
+};
+use protocol::ipv4::Ipv4Addr;
+use protocol::mac::MacAddr;
+
+use crate::stack::NetworkStack;
+use crate::transport::udp::UdpSocket;
+
+// DISCOVER / REQUEST 的重传间隔从 4 秒开始翻倍，最多 64 秒 (RFC 2131 4.1)
+const INITIAL_RETRANSMIT: Duration = Duration::from_secs(4);
+const MAX_RETRANSMIT: Duration = Duration::from_secs(64);
+const MAX_ATTEMPTS: u32 = 5;
+// 续租和重新绑定时的重传间隔下限 (RFC 2131 4.4.5)
+const MIN_RENEW_RETRANSMIT: Duration = Duration::from_secs(60);
+// 收到 NAK 或请求无人应答时，从 INIT 重新开始的次数
+const MAX_RESTARTS: u32 = 3;
+
+const PARAMETER_REQUEST_LIST: [u8; 7] = [
+    DHCP_OPT_SUBNET_MASK,
+    DHCP_OPT_ROUTER,
+    DHCP_OPT_DNS_SERVER,
+    DHCP_OPT_DOMAIN_NAME,
+    DHCP_OPT_LEASE_TIME,
+    DHCP_OPT_RENEWAL_TIME,
+    DHCP_OPT_REBINDING_TIME,
+];
+
+/// 客户端状态 (RFC 2131 图 5)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DhcpState {
+    Init,
+    Selecting,
+    Requesting,
+    Bound,
+    Renewing,
+    Rebinding,
+}
+
+/// 从 DHCPACK 中得到的租约
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DhcpLease {
+    pub address: Ipv4Addr,
+    /// 服务器没有下发掩码时为 0.0.0.0，即所有目的地址都视为直连
+    pub netmask: Ipv4Addr,
+    pub gateway: Option<Ipv4Addr>,
+    pub dns_servers: Vec<Ipv4Addr>,
+    pub server: Ipv4Addr,
+    pub lease_time: Duration,
+    /// T1，到期后向原服务器单播续租
+    pub renewal_time: Duration,
+    /// T2，到期后广播请求任意服务器重新绑定
+    pub rebinding_time: Duration,
+    pub acquired: Instant,
+}
+
+impl DhcpLease {
+    fn from_ack(ack: &DhcpMessage, server: Ipv4Addr, acquired: Instant) -> anyhow::Result<Self> {
+        let lease_secs = ack
+            .lease_time()
+            .ok_or_else(|| anyhow::anyhow!("DHCPACK from {} has no lease time", server))?;
+        let lease_time = Duration::from_secs(lease_secs as u64);
+        // T1、T2 缺省为租期的 0.5 和 0.875 倍 (RFC 2131 4.4.5)
+        let renewal_time = ack
+            .renewal_time()
+            .map(|secs| Duration::from_secs(secs as u64))
+            .unwrap_or(lease_time / 2);
+        let rebinding_time = ack
+            .rebinding_time()
+            .map(|secs| Duration::from_secs(secs as u64))
+            .unwrap_or(lease_time * 7 / 8);
+
+        Ok(Self {
+            address: ack.yiaddr,
+            netmask: ack.subnet_mask().unwrap_or(Ipv4Addr::unspecified()),
+            gateway: ack.routers().first().copied(),
+            dns_servers: ack.dns_servers().to_vec(),
+            server,
+            lease_time,
+            renewal_time: renewal_time.min(lease_time),
+            rebinding_time: rebinding_time.clamp(renewal_time.min(lease_time), lease_time),
+            acquired,
+        })
+    }
+
+    pub fn renew_at(&self) -> Instant {
+        self.acquired + self.renewal_time
+    }
+
+    pub fn rebind_at(&self) -> Instant {
+        self.acquired + self.rebinding_time
+    }
+
+    pub fn expires_at(&self) -> Instant {
+        self.acquired + self.lease_time
+    }
+}
+
+impl fmt::Display for DhcpLease {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} netmask {}", self.address, self.netmask)?;
+        if let Some(gateway) = self.gateway {
+            write!(f, " gateway {}", gateway)?;
+        }
+        if !self.dns_servers.is_empty() {
+            let servers: Vec<String> = self.dns_servers.iter().map(|s| s.to_string()).collect();
+            write!(f, " dns {}", servers.join(","))?;
+        }
+        write!(
+            f,
+            " from {}, lease {}s",
+            self.server,
+            self.lease_time.as_secs()
+        )
+    }
+}
+
+/// DHCP 客户端
+///
+/// 报文由事件循环中的 UDP handler 投递到 68 端口，调用前需要有线程在运行 `event_loop::run`
+pub struct DhcpClient {
+    stack: Arc<NetworkStack>,
+    socket: UdpSocket,
+    mac: MacAddr,
+    state: DhcpState,
+    xid: u32,
+    lease: Option<DhcpLease>,
+}
+
+impl DhcpClient {
+    pub fn new(stack: Arc<NetworkStack>) -> anyhow::Result<Self> {
+        let socket = UdpSocket::bind(stack.clone(), &format!("0.0.0.0:{}", DHCP_CLIENT_PORT))?;
+        let mac = stack.config().mac;
+        Ok(Self {
+            stack,
+            socket,
+            mac,
+            state: DhcpState::Init,
+            xid: 0,
+            lease: None,
+        })
+    }
+
+    pub fn state(&self) -> DhcpState {
+        self.state
+    }
+
+    pub fn lease(&self) -> Option<&DhcpLease> {
+        self.lease.as_ref()
+    }
+
+    /// 从 INIT 开始获取一个租约并配置到协议栈，阻塞直到收到 DHCPACK、超时或 cancel 被置位
+    pub fn acquire(&mut self, cancel: &AtomicBool) -> anyhow::Result<DhcpLease> {
+        for _ in 0..MAX_RESTARTS {
+            self.state = DhcpState::Init;
+            self.new_transaction();
+            let start = self.stack.now();
+
+            // 1. DISCOVER，取第一个 OFFER
+            self.state = DhcpState::Selecting;
+            println!("DHCPDISCOVER on {} (xid 0x{:08x})", self.mac, self.xid);
+            let discover = self.request(DhcpMessageType::Discover, Vec::new());
+            let offer = self.transact(
+                discover,
+                None,
+                start,
+                backoff(),
+                |m| m.message_type() == Some(DhcpMessageType::Offer) && m.server_id().is_some(),
+                cancel,
+            );
+            let Some(offer) = offer else {
+                if cancel.load(Ordering::Relaxed) {
+                    anyhow::bail!("DHCP interrupted");
+                }
+                anyhow::bail!("No DHCPOFFER received");
+            };
+            let server = offer.server_id().unwrap_or(Ipv4Addr::unspecified());
+            println!("DHCPOFFER of {} from {}", offer.yiaddr, server);
+
+            // 2. 广播 REQUEST 选定该服务器，其他服务器据此收回各自的 OFFER
+            self.state = DhcpState::Requesting;
+            let request = self.request(
+                DhcpMessageType::Request,
+                vec![
+                    DhcpOption::RequestedIp(offer.yiaddr),
+                    DhcpOption::ServerId(server),
+                ],
+            );
+            println!("DHCPREQUEST for {} to {}", offer.yiaddr, server);
+            let reply = self.transact(
+                request,
+                None,
+                start,
+                backoff(),
+                |m| is_ack_or_nak(m) && m.server_id().is_none_or(|id| id == server),
+                cancel,
+            );
+
+            match reply {
+                Some(ack) if ack.message_type() == Some(DhcpMessageType::Ack) => {
+                    let lease = DhcpLease::from_ack(&ack, server, start)?;
+                    self.bind(lease.clone());
+                    return Ok(lease);
+                }
+                Some(nak) => println!("DHCPNAK from {}: {}", server, nak_reason(&nak)),
+                None if cancel.load(Ordering::Relaxed) => anyhow::bail!("DHCP interrupted"),
+                None => println!("No DHCPACK from {}, restarting", server),
+            }
+        }
+        anyhow::bail!("Failed to obtain a DHCP lease")
+    }
+
+    /// 维护租约直到 cancel 被置位，然后释放
+    ///
+    /// 到达 T1 时向原服务器单播续租，到达 T2 时广播重新绑定，租约过期或收到 NAK 时重新获取
+    pub fn run(&mut self, cancel: &AtomicBool) -> anyhow::Result<()> {
+        if self.lease.is_none() {
+            self.acquire(cancel)?;
+        }
+
+        while !cancel.load(Ordering::Relaxed) {
+            let Some(lease) = self.lease.clone() else {
+                self.acquire(cancel)?;
+                continue;
+            };
+            let now = self.stack.now();
+
+            if now >= lease.expires_at() {
+                println!("DHCP lease of {} expired", lease.address);
+                self.unbind();
+                continue;
+            }
+            if now < lease.renew_at() {
+                sleep_until(&self.stack, lease.renew_at(), cancel);
+                continue;
+            }
+
+            // T1 之后单播给原服务器，T2 之后广播给任意服务器
+            let rebinding = now >= lease.rebind_at();
+            let (state, deadline, server) = if rebinding {
+                (DhcpState::Rebinding, lease.expires_at(), None)
+            } else {
+                (DhcpState::Renewing, lease.rebind_at(), Some(lease.server))
+            };
+            self.state = state;
+            // 重传间隔取到下一阶段剩余时间的一半，但不少于 60 秒
+            let remaining = deadline - now;
+            let wait = (remaining / 2).max(MIN_RENEW_RETRANSMIT).min(remaining);
+
+            self.new_transaction();
+            let mut request = self.request(DhcpMessageType::Request, Vec::new());
+            request.ciaddr = lease.address;
+            request.flags = 0;
+            println!(
+                "DHCPREQUEST for {} to {}",
+                lease.address,
+                server.unwrap_or(Ipv4Addr::broadcast())
+            );
+            let reply = self.transact(
+                request,
+                server,
+                now,
+                std::iter::once(wait),
+                is_ack_or_nak,
+                cancel,
+            );
+
+            match reply {
+                Some(ack) if ack.message_type() == Some(DhcpMessageType::Ack) => {
+                    let server = ack.server_id().unwrap_or(lease.server);
+                    match DhcpLease::from_ack(&ack, server, now) {
+                        Ok(lease) => self.bind(lease),
+                        Err(e) => eprintln!("{}", e),
+                    }
+                }
+                Some(nak) => {
+                    println!("DHCPNAK: {}", nak_reason(&nak));
+                    self.unbind();
+                }
+                None => {}
+            }
+        }
+
+        self.release();
+        Ok(())
+    }
+
+    /// 向服务器发送 DHCPRELEASE 并清除本机地址
+    pub fn release(&mut self) {
+        let Some(lease) = self.lease.clone() else {
+            return;
+        };
+
+        self.new_transaction();
+        let mut release = self.request(
+            DhcpMessageType::Release,
+            vec![DhcpOption::ServerId(lease.server)],
+        );
+        release.ciaddr = lease.address;
+        release.flags = 0;
+        println!("DHCPRELEASE of {} to {}", lease.address, lease.server);
+        self.send(&release, Some(lease.server));
+
+        // 服务器的 MAC 可能还没解析，等 ARP 完成、报文真正发出后再清除地址
+        let deadline = self.stack.now() + Duration::from_secs(1);
+        while self.stack.now() < deadline && self.is_pending(lease.server) {
+            thread::sleep(Duration::from_millis(10));
+        }
+        self.unbind();
+    }
+
+    fn new_transaction(&mut self) {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos() as u32;
+        let mac = self.mac.as_bytes();
+        self.xid = nanos ^ u32::from_be_bytes([mac[2], mac[3], mac[4], mac[5]]);
+    }
+
+    /// 带上公共选项的客户端报文，默认请求服务器广播回复
+    fn request(&self, message_type: DhcpMessageType, extra: Vec<DhcpOption>) -> DhcpMessage {
+        let mut client_id = vec![1]; // 硬件类型：以太网
+        client_id.extend_from_slice(self.mac.as_bytes());
+
+        let mut options = vec![
+            DhcpOption::MessageType(message_type),
+            DhcpOption::ClientId(client_id),
+        ];
+        options.extend(extra);
+        if message_type != DhcpMessageType::Release {
+            options.push(DhcpOption::ParameterRequestList(
+                PARAMETER_REQUEST_LIST.to_vec(),
+            ));
+        }
+
+        let mut message = DhcpMessage::new(BOOTREQUEST, self.xid, self.mac, options);
+        message.flags = DHCP_FLAG_BROADCAST;
+        message
+    }
+
+    /// 发送 request，按 timeouts 给出的间隔重传，返回第一个满足 accept 的回复
+    ///
+    /// server 为 None 时广播
+    fn transact(
+        &self,
+        mut request: DhcpMessage,
+        server: Option<Ipv4Addr>,
+        start: Instant,
+        timeouts: impl Iterator<Item = Duration>,
+        accept: impl Fn(&DhcpMessage) -> bool,
+        cancel: &AtomicBool,
+    ) -> Option<DhcpMessage> {
+        // 丢掉之前事务遗留的报文
+        while self.socket.recv_from().is_ok() {}
+
+        for timeout in timeouts {
+            request.secs = self
+                .stack
+                .now()
+                .duration_since(start)
+                .as_secs()
+                .min(u16::MAX as u64) as u16;
+            self.send(&request, server);
+
+            let deadline = self.stack.now() + timeout;
+            while self.stack.now() < deadline {
+                if cancel.load(Ordering::Relaxed) {
+                    return None;
+                }
+                match self.socket.recv_from() {
+                    Ok((data, _)) => match DhcpMessage::parse(&data) {
+                        Ok(reply) if self.is_reply(&reply) && accept(&reply) => {
+                            return Some(reply);
+                        }
+                        Ok(_) => {}
+                        Err(e) => eprintln!("Invalid DHCP message: {}", e),
+                    },
+                    Err(_) => thread::sleep(Duration::from_millis(10)),
+                }
+            }
+        }
+        None
+    }
+
+    fn is_reply(&self, reply: &DhcpMessage) -> bool {
+        reply.op == BOOTREPLY && reply.xid == self.xid && reply.chaddr == self.mac
+    }
+
+    fn send(&self, message: &DhcpMessage, server: Option<Ipv4Addr>) {
+        // 还没有地址时无法查路由和 ARP，直接发往广播 MAC
+        let (dst_ip, dst_mac) = match server {
+            Some(server) => (server, None),
+            None => (Ipv4Addr::broadcast(), Some(MacAddr::broadcast())),
+        };
+        super::send_message(
+            &self.stack,
+            message,
+            DHCP_CLIENT_PORT,
+            (dst_ip, DHCP_SERVER_PORT),
+            dst_mac,
+        );
+    }
+
+    fn is_pending(&self, ip: Ipv4Addr) -> bool {
+        let pending = self.stack.pending_packets().lock().unwrap();
+        pending.values().flatten().any(|packet| packet.dst_ip == ip)
+    }
+
+    fn bind(&mut self, lease: DhcpLease) {
+        println!(
+            "Bound to {} -- renewal in {} seconds",
+            lease,
+            lease.renewal_time.as_secs()
+        );
+        self.stack
+            .set_address(lease.address, lease.netmask, lease.gateway);
+        self.stack.set_dns_servers(lease.dns_servers.clone());
+        self.state = DhcpState::Bound;
+        self.lease = Some(lease);
+    }
+
+    fn unbind(&mut self) {
+        self.stack
+            .set_address(Ipv4Addr::unspecified(), Ipv4Addr::unspecified(), None);
+        self.stack.set_dns_servers(Vec::new());
+        self.state = DhcpState::Init;
+        self.lease = None;
+    }
+}
+
+/// 4、8、16、32、64 秒
+fn backoff() -> impl Iterator<Item = Duration> {
+    (0..MAX_ATTEMPTS).map(|i| (INITIAL_RETRANSMIT * 2u32.pow(i)).min(MAX_RETRANSMIT))
+}
+
+fn is_ack_or_nak(message: &DhcpMessage) -> bool {
+    matches!(
+        message.message_type(),
+        Some(DhcpMessageType::Ack | DhcpMessageType::Nak)
+    )
+}
+
+fn nak_reason(nak: &DhcpMessage) -> String {
+    nak.options
+        .iter()
+        .find_map(|o| match o {
+            DhcpOption::Message(message) => Some(message.clone()),
+            _ => None,
+        })
+        .unwrap_or_else(|| "no reason given".to_string())
+}
+
+/// 分段睡眠到 deadline，以便及时响应 cancel
+fn sleep_until(stack: &NetworkStack, deadline: Instant, cancel: &AtomicBool) {
+    while !cancel.load(Ordering::Relaxed) {
+        let now = stack.now();
+        if now >= deadline {
+            return;
+        }
+        thread::sleep((deadline - now).min(Duration::from_millis(100)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SERVER: Ipv4Addr = Ipv4Addr::new(10, 0, 0, 1);
+
+    fn ack(options: Vec<DhcpOption>) -> DhcpMessage {
+        let mut options = options;
+        options.insert(0, DhcpOption::MessageType(DhcpMessageType::Ack));
+        let mut ack = DhcpMessage::new(BOOTREPLY, 1, MacAddr::zero(), options);
+        ack.yiaddr = Ipv4Addr::new(10, 0, 0, 100);
+        ack
+    }
+
+    #[test]
+    fn lease_timers_default_to_rfc_2131() {
+        let now = Instant::now();
+        let lease = DhcpLease::from_ack(
+            &ack(vec![
+                DhcpOption::LeaseTime(3600),
+                DhcpOption::SubnetMask(Ipv4Addr::new(255, 255, 255, 0)),
+                DhcpOption::Router(vec![SERVER]),
+                DhcpOption::DnsServers(vec![SERVER]),
+            ]),
+            SERVER,
+            now,
+        )
+        .unwrap();
+
+        assert_eq!(lease.address, Ipv4Addr::new(10, 0, 0, 100));
+        assert_eq!(lease.gateway, Some(SERVER));
+        assert_eq!(lease.renew_at(), now + Duration::from_secs(1800));
+        assert_eq!(lease.rebind_at(), now + Duration::from_secs(3150));
+        assert_eq!(lease.expires_at(), now + Duration::from_secs(3600));
+        assert_eq!(
+            lease.to_string(),
+            "10.0.0.100 netmask 255.255.255.0 gateway 10.0.0.1 dns 10.0.0.1 from 10.0.0.1, lease 3600s"
+        );
+    }
+
+    #[test]
+    fn server_timers_are_clamped_to_the_lease() {
+        let now = Instant::now();
+        let lease = |options| DhcpLease::from_ack(&ack(options), SERVER, now).unwrap();
+
+        let explicit = lease(vec![
+            DhcpOption::LeaseTime(600),
+            DhcpOption::RenewalTime(100),
+            DhcpOption::RebindingTime(200),
+        ]);
+        assert_eq!(explicit.renewal_time, Duration::from_secs(100));
+        assert_eq!(explicit.rebinding_time, Duration::from_secs(200));
+        // 没有掩码时所有目的地址都视为直连
+        assert_eq!(explicit.netmask, Ipv4Addr::unspecified());
+
+        // T1、T2 超过租期时截到租期，T2 不早于 T1
+        let clamped = lease(vec![
+            DhcpOption::LeaseTime(600),
+            DhcpOption::RenewalTime(900),
+            DhcpOption::RebindingTime(300),
+        ]);
+        assert_eq!(clamped.renewal_time, Duration::from_secs(600));
+        assert_eq!(clamped.rebinding_time, Duration::from_secs(600));
+
+        let early_t2 = lease(vec![
+            DhcpOption::LeaseTime(600),
+            DhcpOption::RenewalTime(400),
+            DhcpOption::RebindingTime(300),
+        ]);
+        assert_eq!(early_t2.rebinding_time, Duration::from_secs(400));
+
+        assert!(DhcpLease::from_ack(&ack(Vec::new()), SERVER, now).is_err());
+    }
+}
@@ -0,0 +1,56 @@
+// Copyright (C) 2025 rrrrrzy
+// SPDX-License-Identifier: GPL-3.0-or-later
+//
+// --------------------------------------------------
+// 致敬所有在深夜调试代码的灵魂。
+// 即便 Bug 如山，我亦往矣。
+// --------------------------------------------------
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+//! DHCPv4 (RFC 2131)
+
+use protocol::dhcp::DhcpMessage;
+use protocol::ipv4::{Ipv4Addr, Ipv4Protocol};
+use protocol::mac::MacAddr;
+use protocol::udp::{UdpHeader, UdpPacket};
+
+use crate::handlers::ipv4::{self, SendOptions};
+use crate::stack::NetworkStack;
+
+pub mod client;
+pub mod server;
+
+/// 以本机地址为源地址发送 DHCP 报文
+///
+/// 给出 dst_mac 时不查路由和 ARP：广播，或者发给还没有地址、无法应答 ARP 的客户端
+fn send_message(
+    stack: &NetworkStack,
+    message: &DhcpMessage,
+    src_port: u16,
+    (dst_ip, dst_port): (Ipv4Addr, u16),
+    dst_mac: Option<MacAddr>,
+) {
+    let packet = UdpPacket::new(
+        UdpHeader::new(src_port, dst_port, 0),
+        message.to_bytes(),
+        stack.config().ip,
+        dst_ip,
+    );
+    let bytes = packet.to_bytes();
+
+    match dst_mac {
+        Some(dst_mac) => ipv4::send_packet_with_mac(
+            stack,
+            dst_mac,
+            dst_ip,
+            Ipv4Protocol::UDP,
+            &bytes,
+            &SendOptions::default(),
+        ),
+        None => ipv4::send_packet(stack, dst_ip, Ipv4Protocol::UDP, &bytes),
+    }
+}
@@ -0,0 +1,797 @@
+// Copyright (C) 2025 rrrrrzy
+// SPDX-License-Identifier: GPL-3.0-or-later
+//
+// --------------------------------------------------
+// 致敬所有在深夜调试代码的灵魂。
+// 即便 Bug 如山，我亦往矣。
+// --------------------------------------------------
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+//! DHCP 服务器：从地址池分配地址，支持按 MAC 的静态保留，租约写入文件以便重启后恢复
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::Context;
+use protocol::dhcp::{
+    BOOTREPLY, BOOTREQUEST, DHCP_CLIENT_PORT, DHCP_FLAG_BROADCAST, DHCP_SERVER_PORT, DhcpMessage,
+    DhcpMessageType, DhcpOption,
+};
+use protocol::ipv4::Ipv4Addr;
+use protocol::mac::MacAddr;
+
+use crate::stack::NetworkStack;
+use crate::transport::udp::UdpSocket;
+
+/// 默认租期
+pub const DEFAULT_LEASE_TIME: Duration = Duration::from_secs(3600);
+
+// 发出 OFFER 后为客户端保留地址的时间
+const OFFER_TIMEOUT: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone)]
+pub struct DhcpServerConfig {
+    /// 地址池，首尾都包含
+    pub pool_start: Ipv4Addr,
+    pub pool_end: Ipv4Addr,
+    pub lease_time: Duration,
+    /// 下发的默认网关
+    pub router: Option<Ipv4Addr>,
+    pub dns_servers: Vec<Ipv4Addr>,
+    /// 按 MAC 的静态保留，地址可以不在池内，但必须在本网段
+    pub reservations: HashMap<MacAddr, Ipv4Addr>,
+    /// 租约文件，每行 "<到期 Unix 时间> <MAC> <IP>"，与 dnsmasq 租约文件的前三列相同
+    pub lease_file: Option<PathBuf>,
+}
+
+impl DhcpServerConfig {
+    pub fn new(pool_start: Ipv4Addr, pool_end: Ipv4Addr) -> Self {
+        Self {
+            pool_start,
+            pool_end,
+            lease_time: DEFAULT_LEASE_TIME,
+            router: None,
+            dns_servers: Vec::new(),
+            reservations: HashMap::new(),
+            lease_file: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LeaseState {
+    /// 已发出 OFFER，等待客户端 REQUEST
+    Offered,
+    Bound,
+    /// 客户端发现地址已被占用 (DHCPDECLINE)，一个租期内不再分配
+    Declined,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Lease {
+    pub address: Ipv4Addr,
+    /// Declined 状态下为全 0
+    pub mac: MacAddr,
+    pub expires: SystemTime,
+    pub state: LeaseState,
+}
+
+impl Lease {
+    fn is_active(&self, now: SystemTime) -> bool {
+        self.expires > now
+    }
+}
+
+/// DHCP 服务器
+///
+/// 报文由事件循环中的 UDP handler 投递到 67 端口，调用前需要有线程在运行 `event_loop::run`
+pub struct DhcpServer {
+    stack: Arc<NetworkStack>,
+    socket: UdpSocket,
+    config: DhcpServerConfig,
+    server_id: Ipv4Addr,
+    netmask: Ipv4Addr,
+    // 按地址索引，过期的 Bound 租约保留下来，客户端再来时优先分配原地址
+    leases: BTreeMap<Ipv4Addr, Lease>,
+}
+
+impl DhcpServer {
+    pub fn new(stack: Arc<NetworkStack>, config: DhcpServerConfig) -> anyhow::Result<Self> {
+        let (server_id, netmask) = {
+            let stack_config = stack.config();
+            (stack_config.ip, stack_config.netmask)
+        };
+        if server_id == Ipv4Addr::unspecified() {
+            anyhow::bail!("DHCP server requires a static IP address");
+        }
+        if netmask == Ipv4Addr::unspecified() {
+            anyhow::bail!("DHCP server requires 'netmask' to be configured");
+        }
+
+        let in_subnet = |addr: Ipv4Addr| {
+            addr.to_bits() & netmask.to_bits() == server_id.to_bits() & netmask.to_bits()
+        };
+        if config.pool_start.to_bits() > config.pool_end.to_bits() {
+            anyhow::bail!(
+                "Invalid DHCP pool {}-{}",
+                config.pool_start,
+                config.pool_end
+            );
+        }
+        if !in_subnet(config.pool_start) || !in_subnet(config.pool_end) {
+            anyhow::bail!(
+                "DHCP pool {}-{} is outside {}/{}",
+                config.pool_start,
+                config.pool_end,
+                server_id,
+                netmask
+            );
+        }
+        if let Some((mac, addr)) = config.reservations.iter().find(|(_, a)| !in_subnet(**a)) {
+            anyhow::bail!(
+                "DHCP reservation {} for {} is outside the subnet",
+                addr,
+                mac
+            );
+        }
+
+        let leases = match &config.lease_file {
+            Some(path) => load_leases(path)?,
+            None => BTreeMap::new(),
+        };
+        let socket = UdpSocket::bind(stack.clone(), &format!("0.0.0.0:{}", DHCP_SERVER_PORT))?;
+
+        Ok(Self {
+            stack,
+            socket,
+            config,
+            server_id,
+            netmask,
+            leases,
+        })
+    }
+
+    /// 当前有效的租约
+    pub fn leases(&self) -> impl Iterator<Item = &Lease> {
+        let now = SystemTime::now();
+        self.leases
+            .values()
+            .filter(move |l| l.state == LeaseState::Bound && l.is_active(now))
+    }
+
+    /// 处理客户端报文，直到 cancel 被置位
+    pub fn run(&mut self, cancel: &AtomicBool) -> anyhow::Result<()> {
+        println!(
+            "DHCP server on {} offering {}-{}, lease {}s",
+            self.server_id,
+            self.config.pool_start,
+            self.config.pool_end,
+            self.config.lease_time.as_secs()
+        );
+
+        while !cancel.load(Ordering::Relaxed) {
+            match self.socket.recv_from() {
+                Ok((data, src)) => match DhcpMessage::parse(&data) {
+                    Ok(message) => self.handle(&message),
+                    Err(e) => eprintln!("Invalid DHCP message from {}: {}", src, e),
+                },
+                Err(_) => thread::sleep(Duration::from_millis(10)),
+            }
+        }
+        Ok(())
+    }
+
+    /// 处理一个客户端报文，需要时发送回复
+    pub fn handle(&mut self, request: &DhcpMessage) {
+        if request.op != BOOTREQUEST {
+            return;
+        }
+        let now = SystemTime::now();
+        // 过期的 OFFER 和 DECLINE 记录不再占用地址
+        self.leases
+            .retain(|_, l| l.state == LeaseState::Bound || l.is_active(now));
+
+        if let Some(reply) = self.reply(request, now) {
+            self.send(request, &reply);
+        }
+    }
+
+    fn reply(&mut self, request: &DhcpMessage, now: SystemTime) -> Option<DhcpMessage> {
+        let mac = request.chaddr;
+        match request.message_type()? {
+            DhcpMessageType::Discover => {
+                let Some(address) = self.select_address(mac, request.requested_ip(), now) else {
+                    eprintln!("DHCPDISCOVER from {}: no free address in pool", mac);
+                    return None;
+                };
+                // 客户端已经持有的租约不降级为 OFFER
+                let bound = self.leases.get(&address).is_some_and(|l| {
+                    l.mac == mac && l.state == LeaseState::Bound && l.is_active(now)
+                });
+                if !bound {
+                    self.leases.insert(
+                        address,
+                        Lease {
+                            address,
+                            mac,
+                            expires: now + OFFER_TIMEOUT,
+                            state: LeaseState::Offered,
+                        },
+                    );
+                }
+                println!("DHCPOFFER of {} to {}", address, mac);
+                Some(self.reply_message(request, DhcpMessageType::Offer, address))
+            }
+            DhcpMessageType::Request => {
+                // SELECTING 状态下带有 Server ID，选了别的服务器时收回 OFFER
+                if let Some(server_id) = request.server_id()
+                    && server_id != self.server_id
+                {
+                    self.leases
+                        .retain(|_, l| !(l.mac == mac && l.state == LeaseState::Offered));
+                    return None;
+                }
+
+                // SELECTING / INIT-REBOOT 用 Requested IP 选项，RENEWING / REBINDING 用 ciaddr
+                let address = request.requested_ip().unwrap_or(request.ciaddr);
+                if address == Ipv4Addr::unspecified() {
+                    return None;
+                }
+                if !self.is_available(mac, address, now) {
+                    println!("DHCPNAK of {} to {}", address, mac);
+                    return Some(self.nak(request, "requested address not available"));
+                }
+
+                self.leases.insert(
+                    address,
+                    Lease {
+                        address,
+                        mac,
+                        expires: now + self.config.lease_time,
+                        state: LeaseState::Bound,
+                    },
+                );
+                self.save();
+                println!("DHCPACK of {} to {}", address, mac);
+                Some(self.reply_message(request, DhcpMessageType::Ack, address))
+            }
+            DhcpMessageType::Decline => {
+                let address = request.requested_ip()?;
+                if self.leases.get(&address).is_some_and(|l| l.mac == mac) {
+                    eprintln!("{} declined {}, address is in use", mac, address);
+                    self.leases.insert(
+                        address,
+                        Lease {
+                            address,
+                            mac: MacAddr::zero(),
+                            expires: now + self.config.lease_time,
+                            state: LeaseState::Declined,
+                        },
+                    );
+                    self.save();
+                }
+                None
+            }
+            DhcpMessageType::Release => {
+                let address = request.ciaddr;
+                if self
+                    .leases
+                    .get(&address)
+                    .is_some_and(|l| l.mac == mac && l.state == LeaseState::Bound)
+                {
+                    println!("DHCPRELEASE of {} from {}", address, mac);
+                    // 保留记录但立即过期，客户端下次仍优先拿到同一地址
+                    if let Some(lease) = self.leases.get_mut(&address) {
+                        lease.expires = now;
+                    }
+                    self.save();
+                }
+                None
+            }
+            // 客户端已经有地址，只要配置参数
+            DhcpMessageType::Inform => {
+                Some(self.reply_message(request, DhcpMessageType::Ack, Ipv4Addr::unspecified()))
+            }
+            _ => None,
+        }
+    }
+
+    /// 给 mac 挑一个地址：静态保留、原有租约、客户端请求的地址、池中空闲地址，依次尝试
+    fn select_address(
+        &self,
+        mac: MacAddr,
+        requested: Option<Ipv4Addr>,
+        now: SystemTime,
+    ) -> Option<Ipv4Addr> {
+        if let Some(address) = self.config.reservations.get(&mac) {
+            return Some(*address);
+        }
+
+        let previous = self
+            .leases
+            .values()
+            .find(|l| l.mac == mac && self.is_available(mac, l.address, now))
+            .map(|l| l.address);
+        let requested = requested.filter(|addr| self.is_available(mac, *addr, now));
+        if let Some(address) = previous.or(requested) {
+            return Some(address);
+        }
+
+        // 优先从未分配过的地址，其次复用已过期的
+        let pool = || {
+            (self.config.pool_start.to_bits()..=self.config.pool_end.to_bits())
+                .map(Ipv4Addr::from_bits)
+                .filter(|addr| self.is_available(mac, *addr, now))
+        };
+        pool()
+            .find(|addr| !self.leases.contains_key(addr))
+            .or_else(|| pool().next())
+    }
+
+    /// address 能否分配给 mac
+    fn is_available(&self, mac: MacAddr, address: Ipv4Addr, now: SystemTime) -> bool {
+        // 有静态保留的客户端只能用保留地址，保留地址也只给对应的客户端
+        if let Some(reserved) = self.config.reservations.get(&mac) {
+            return *reserved == address;
+        }
+        if self.config.reservations.values().any(|a| *a == address) {
+            return false;
+        }
+
+        let bits = address.to_bits();
+        let in_pool =
+            self.config.pool_start.to_bits() <= bits && bits <= self.config.pool_end.to_bits();
+        if !in_pool || address == self.server_id {
+            return false;
+        }
+
+        match self.leases.get(&address) {
+            Some(lease) => lease.mac == mac || !lease.is_active(now),
+            None => true,
+        }
+    }
+
+    /// OFFER / ACK，带上租期和网络参数
+    fn reply_message(
+        &self,
+        request: &DhcpMessage,
+        message_type: DhcpMessageType,
+        address: Ipv4Addr,
+    ) -> DhcpMessage {
+        let mut options = vec![
+            DhcpOption::MessageType(message_type),
+            DhcpOption::ServerId(self.server_id),
+        ];
+        // DHCPINFORM 的回复不带租期 (RFC 2131 表 3)
+        if address != Ipv4Addr::unspecified() {
+            let lease_secs = self.config.lease_time.as_secs().min(u32::MAX as u64) as u32;
+            options.push(DhcpOption::LeaseTime(lease_secs));
+            options.push(DhcpOption::RenewalTime(lease_secs / 2));
+            options.push(DhcpOption::RebindingTime(
+                (lease_secs as u64 * 7 / 8) as u32,
+            ));
+        }
+        options.push(DhcpOption::SubnetMask(self.netmask));
+        if let Some(router) = self.config.router {
+            options.push(DhcpOption::Router(vec![router]));
+        }
+        if !self.config.dns_servers.is_empty() {
+            options.push(DhcpOption::DnsServers(self.config.dns_servers.clone()));
+        }
+
+        let mut reply = DhcpMessage::new(BOOTREPLY, request.xid, request.chaddr, options);
+        reply.flags = request.flags;
+        reply.giaddr = request.giaddr;
+        reply.yiaddr = address;
+        if message_type == DhcpMessageType::Ack {
+            reply.ciaddr = request.ciaddr;
+        }
+        reply
+    }
+
+    fn nak(&self, request: &DhcpMessage, reason: &str) -> DhcpMessage {
+        let options = vec![
+            DhcpOption::MessageType(DhcpMessageType::Nak),
+            DhcpOption::ServerId(self.server_id),
+            DhcpOption::Message(reason.to_string()),
+        ];
+        let mut reply = DhcpMessage::new(BOOTREPLY, request.xid, request.chaddr, options);
+        reply.flags = request.flags;
+        reply.giaddr = request.giaddr;
+        reply
+    }
+
+    /// 按 RFC 2131 4.1 选择回复的目的地址
+    fn send(&self, request: &DhcpMessage, reply: &DhcpMessage) {
+        let broadcast = (
+            (Ipv4Addr::broadcast(), DHCP_CLIENT_PORT),
+            Some(MacAddr::broadcast()),
+        );
+        let (dst, dst_mac) = if request.giaddr != Ipv4Addr::unspecified() {
+            // 经过中继时交给中继代理
+            ((request.giaddr, DHCP_SERVER_PORT), None)
+        } else if reply.message_type() == Some(DhcpMessageType::Nak) {
+            broadcast
+        } else if request.ciaddr != Ipv4Addr::unspecified() {
+            ((request.ciaddr, DHCP_CLIENT_PORT), None)
+        } else if request.flags & DHCP_FLAG_BROADCAST != 0 {
+            broadcast
+        } else {
+            // 客户端还不能应答 ARP，直接发往它的硬件地址
+            ((reply.yiaddr, DHCP_CLIENT_PORT), Some(request.chaddr))
+        };
+        super::send_message(&self.stack, reply, DHCP_SERVER_PORT, dst, dst_mac);
+    }
+
+    /// 把有效的租约和未到期的 DECLINE 记录写入租约文件，先写临时文件再改名，避免写到一半时崩溃
+    fn save(&self) {
+        let Some(path) = &self.config.lease_file else {
+            return;
+        };
+        let now = SystemTime::now();
+        let mut content = String::new();
+        for lease in self.leases.values().filter(|l| {
+            matches!(l.state, LeaseState::Bound | LeaseState::Declined) && l.is_active(now)
+        }) {
+            let expires = lease
+                .expires
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs();
+            content.push_str(&format!("{} {} {}\n", expires, lease.mac, lease.address));
+        }
+
+        let tmp = path.with_extension("tmp");
+        if let Err(e) = fs::write(&tmp, content).and_then(|_| fs::rename(&tmp, path)) {
+            eprintln!("Failed to write lease file {}: {}", path.display(), e);
+        }
+    }
+}
+
+/// 读取租约文件，文件不存在时返回空表
+fn load_leases(path: &Path) -> anyhow::Result<BTreeMap<Ipv4Addr, Lease>> {
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(BTreeMap::new()),
+        Err(e) => {
+            return Err(e)
+                .with_context(|| format!("Failed to read lease file: {}", path.display()));
+        }
+    };
+
+    let mut leases = BTreeMap::new();
+    for (n, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let lease = parse_lease(line)
+            .with_context(|| format!("Invalid lease at {}:{}", path.display(), n + 1))?;
+        leases.insert(lease.address, lease);
+    }
+    Ok(leases)
+}
+
+fn parse_lease(line: &str) -> anyhow::Result<Lease> {
+    let fields: Vec<&str> = line.split_whitespace().collect();
+    let [expires, mac, address, ..] = fields[..] else {
+        anyhow::bail!("expected '<expiry> <mac> <ip>'");
+    };
+    let mac = MacAddr::from_str(mac)?;
+    // DECLINE 记录不属于任何客户端，MAC 写成全 0
+    let state = if mac == MacAddr::zero() {
+        LeaseState::Declined
+    } else {
+        LeaseState::Bound
+    };
+    Ok(Lease {
+        address: Ipv4Addr::from_str(address)
+            .map_err(|e| anyhow::anyhow!("Invalid IP '{}': {}", address, e))?,
+        mac,
+        expires: UNIX_EPOCH + Duration::from_secs(expires.parse()?),
+        state,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sim::{LinkConfig, SegmentKind, Simulator};
+    use crate::stack::StackConfig;
+
+    const SERVER_IP: Ipv4Addr = Ipv4Addr::new(10, 0, 0, 1);
+    const FIRST: Ipv4Addr = Ipv4Addr::new(10, 0, 0, 100);
+    const SECOND: Ipv4Addr = Ipv4Addr::new(10, 0, 0, 101);
+
+    fn mac(id: u8) -> MacAddr {
+        MacAddr::from_raw([0x02, 0, 0, 0, 0, id])
+    }
+
+    fn stack(sim: &mut Simulator) -> Arc<NetworkStack> {
+        let mut config = StackConfig::new(mac(1), SERVER_IP);
+        config.netmask = Ipv4Addr::new(255, 255, 255, 0);
+        sim.add_host(config)
+    }
+
+    /// 地址池只有 FIRST 和 SECOND 两个地址
+    fn new_server(sim: &mut Simulator, f: impl FnOnce(&mut DhcpServerConfig)) -> DhcpServer {
+        let mut config = DhcpServerConfig::new(FIRST, SECOND);
+        config.router = Some(SERVER_IP);
+        f(&mut config);
+        DhcpServer::new(stack(sim), config).unwrap()
+    }
+
+    fn message(id: u8, message_type: DhcpMessageType, options: Vec<DhcpOption>) -> DhcpMessage {
+        let mut options = options;
+        options.insert(0, DhcpOption::MessageType(message_type));
+        DhcpMessage::new(BOOTREQUEST, id as u32, mac(id), options)
+    }
+
+    fn discover(server: &mut DhcpServer, id: u8, now: SystemTime) -> Option<Ipv4Addr> {
+        let reply = server.reply(&message(id, DhcpMessageType::Discover, Vec::new()), now)?;
+        assert_eq!(reply.message_type(), Some(DhcpMessageType::Offer));
+        Some(reply.yiaddr)
+    }
+
+    fn request(server: &mut DhcpServer, id: u8, address: Ipv4Addr, now: SystemTime) -> DhcpMessage {
+        let options = vec![
+            DhcpOption::RequestedIp(address),
+            DhcpOption::ServerId(SERVER_IP),
+        ];
+        server
+            .reply(&message(id, DhcpMessageType::Request, options), now)
+            .unwrap()
+    }
+
+    fn bind(server: &mut DhcpServer, id: u8, now: SystemTime) -> Ipv4Addr {
+        let address = discover(server, id, now).unwrap();
+        let ack = request(server, id, address, now);
+        assert_eq!(ack.message_type(), Some(DhcpMessageType::Ack));
+        address
+    }
+
+    #[test]
+    fn config_is_validated() {
+        let mut sim = Simulator::new(SegmentKind::Switch, LinkConfig::default());
+        let invalid = |sim: &mut Simulator, config: DhcpServerConfig| {
+            DhcpServer::new(stack(sim), config).is_err()
+        };
+
+        assert!(invalid(&mut sim, DhcpServerConfig::new(SECOND, FIRST)));
+        assert!(invalid(
+            &mut sim,
+            DhcpServerConfig::new(FIRST, Ipv4Addr::new(10, 0, 1, 1))
+        ));
+        let mut config = DhcpServerConfig::new(FIRST, SECOND);
+        config
+            .reservations
+            .insert(mac(9), Ipv4Addr::new(10, 0, 1, 9));
+        assert!(invalid(&mut sim, config));
+
+        // 服务器自己需要静态地址
+        let no_ip = sim.add_host(StackConfig::new(mac(2), Ipv4Addr::unspecified()));
+        assert!(DhcpServer::new(no_ip, DhcpServerConfig::new(FIRST, SECOND)).is_err());
+    }
+
+    #[test]
+    fn discover_and_request_bind_an_address() {
+        let mut sim = Simulator::new(SegmentKind::Switch, LinkConfig::default());
+        let mut server = new_server(&mut sim, |_| {});
+        let now = SystemTime::now();
+
+        let offer = server
+            .reply(&message(10, DhcpMessageType::Discover, Vec::new()), now)
+            .unwrap();
+        assert_eq!(offer.op, BOOTREPLY);
+        assert_eq!(offer.xid, 10);
+        assert_eq!(offer.yiaddr, FIRST);
+        assert_eq!(offer.server_id(), Some(SERVER_IP));
+        assert_eq!(offer.lease_time(), Some(3600));
+        assert_eq!(offer.renewal_time(), Some(1800));
+        assert_eq!(offer.rebinding_time(), Some(3150));
+        assert_eq!(offer.subnet_mask(), Some(Ipv4Addr::new(255, 255, 255, 0)));
+        assert_eq!(offer.routers(), &[SERVER_IP]);
+        // OFFER 不算有效租约，但地址已为该客户端保留
+        assert_eq!(server.leases().count(), 0);
+        assert_eq!(discover(&mut server, 11, now), Some(SECOND));
+
+        let ack = request(&mut server, 10, FIRST, now);
+        assert_eq!(ack.message_type(), Some(DhcpMessageType::Ack));
+        assert_eq!(ack.yiaddr, FIRST);
+        let leases: Vec<_> = server.leases().map(|l| (l.address, l.mac)).collect();
+        assert_eq!(leases, vec![(FIRST, mac(10))]);
+
+        // 别的客户端请求已分配的地址得到 NAK
+        let nak = request(&mut server, 11, FIRST, now);
+        assert_eq!(nak.message_type(), Some(DhcpMessageType::Nak));
+        assert_eq!(nak.yiaddr, Ipv4Addr::unspecified());
+
+        // 客户端选了别的服务器时不回复，并收回 OFFER
+        let options = vec![
+            DhcpOption::RequestedIp(SECOND),
+            DhcpOption::ServerId(Ipv4Addr::new(10, 0, 0, 2)),
+        ];
+        let other = message(11, DhcpMessageType::Request, options);
+        assert_eq!(server.reply(&other, now), None);
+        assert_eq!(discover(&mut server, 12, now), Some(SECOND));
+
+        // 地址池耗尽
+        bind(&mut server, 12, now);
+        assert_eq!(discover(&mut server, 13, now), None);
+
+        // DHCPINFORM 只回复配置参数
+        let mut inform = message(14, DhcpMessageType::Inform, Vec::new());
+        inform.ciaddr = Ipv4Addr::new(10, 0, 0, 50);
+        let ack = server.reply(&inform, now).unwrap();
+        assert_eq!(ack.message_type(), Some(DhcpMessageType::Ack));
+        assert_eq!(ack.yiaddr, Ipv4Addr::unspecified());
+        assert_eq!(ack.ciaddr, inform.ciaddr);
+        assert_eq!(ack.lease_time(), None);
+    }
+
+    #[test]
+    fn clients_get_their_previous_address_back() {
+        let mut sim = Simulator::new(SegmentKind::Switch, LinkConfig::default());
+        let mut server = new_server(&mut sim, |_| {});
+        let now = SystemTime::now();
+        assert_eq!(bind(&mut server, 10, now), FIRST);
+
+        // 释放后租约立即过期，但记录保留
+        let mut release = message(10, DhcpMessageType::Release, Vec::new());
+        release.ciaddr = FIRST;
+        assert_eq!(server.reply(&release, now), None);
+        assert_eq!(server.leases().count(), 0);
+
+        // 新客户端优先拿从未分配过的地址，原客户端拿回原地址
+        let later = now + Duration::from_secs(1);
+        assert_eq!(bind(&mut server, 11, later), SECOND);
+        assert_eq!(bind(&mut server, 10, later), FIRST);
+
+        // 没有空闲地址时复用过期的
+        assert_eq!(server.reply(&release, later), None);
+        assert_eq!(bind(&mut server, 12, later), FIRST);
+        assert_eq!(discover(&mut server, 10, later), None);
+
+        // 租约到期后其他客户端可以拿走
+        let expired = later + DEFAULT_LEASE_TIME;
+        assert!(discover(&mut server, 10, expired).is_some());
+    }
+
+    #[test]
+    fn reservations_are_exclusive() {
+        let mut sim = Simulator::new(SegmentKind::Switch, LinkConfig::default());
+        let reserved = Ipv4Addr::new(10, 0, 0, 50);
+        let mut server = new_server(&mut sim, |config| {
+            config.reservations.insert(mac(10), reserved);
+            config.reservations.insert(mac(11), FIRST);
+        });
+        let now = SystemTime::now();
+
+        // 保留地址可以在池外，客户端请求别的地址也只给保留地址
+        assert_eq!(discover(&mut server, 10, now), Some(reserved));
+        let nak = request(&mut server, 10, SECOND, now);
+        assert_eq!(nak.message_type(), Some(DhcpMessageType::Nak));
+        assert_eq!(bind(&mut server, 10, now), reserved);
+
+        // 池内的保留地址不分给其他客户端
+        assert_eq!(discover(&mut server, 12, now), Some(SECOND));
+        assert_eq!(discover(&mut server, 13, now), None);
+        assert_eq!(bind(&mut server, 11, now), FIRST);
+    }
+
+    #[test]
+    fn declined_addresses_are_not_offered() {
+        let mut sim = Simulator::new(SegmentKind::Switch, LinkConfig::default());
+        let mut server = new_server(&mut sim, |_| {});
+        let now = SystemTime::now();
+        assert_eq!(bind(&mut server, 10, now), FIRST);
+
+        let decline = message(
+            10,
+            DhcpMessageType::Decline,
+            vec![DhcpOption::RequestedIp(FIRST)],
+        );
+        assert_eq!(server.reply(&decline, now), None);
+        assert_eq!(server.leases().count(), 0);
+        assert_eq!(discover(&mut server, 10, now), Some(SECOND));
+        assert_eq!(discover(&mut server, 11, now), None);
+
+        // 只有持有该地址的客户端可以 DECLINE
+        let decline = message(
+            11,
+            DhcpMessageType::Decline,
+            vec![DhcpOption::RequestedIp(SECOND)],
+        );
+        assert_eq!(server.reply(&decline, now), None);
+        assert_eq!(
+            server.leases.get(&SECOND).map(|l| l.state),
+            Some(LeaseState::Offered)
+        );
+    }
+
+    #[test]
+    fn leases_survive_a_restart() {
+        let path = std::env::temp_dir().join(format!("dhcp-leases-{}", std::process::id()));
+        let mut sim = Simulator::new(SegmentKind::Switch, LinkConfig::default());
+        let with_file = |config: &mut DhcpServerConfig| config.lease_file = Some(path.clone());
+        let now = SystemTime::now();
+
+        let mut server = new_server(&mut sim, with_file);
+        assert_eq!(bind(&mut server, 10, now), FIRST);
+        let content = fs::read_to_string(&path).unwrap();
+        assert!(
+            content.ends_with(" 02:00:00:00:00:0a 10.0.0.100\n"),
+            "{}",
+            content
+        );
+        drop(server);
+
+        let mut server = new_server(&mut sim, with_file);
+        let leases: Vec<_> = server.leases().map(|l| (l.address, l.mac)).collect();
+        assert_eq!(leases, vec![(FIRST, mac(10))]);
+        assert_eq!(discover(&mut server, 11, now), Some(SECOND));
+        assert_eq!(discover(&mut server, 10, now), Some(FIRST));
+
+        fs::write(&path, "# comment\n\n1 zz:00:00:00:00:0a 10.0.0.100\n").unwrap();
+        let error = load_leases(&path).unwrap_err();
+        assert!(format!("{:#}", error).contains(":3"), "{:#}", error);
+        fs::remove_file(&path).unwrap();
+        assert!(load_leases(&path).unwrap().is_empty());
+
+        assert!(parse_lease("1 02:00:00:00:00:0a").is_err());
+        assert!(parse_lease("soon 02:00:00:00:00:0a 10.0.0.100").is_err());
+        assert!(parse_lease("1 02:00:00:00:00:0a 10.0.0").is_err());
+        let lease = parse_lease("1 02:00:00:00:00:0a 10.0.0.100 host *").unwrap();
+        assert_eq!(lease.expires, UNIX_EPOCH + Duration::from_secs(1));
+    }
+
+    #[test]
+    fn declined_addresses_survive_a_restart() {
+        let path = std::env::temp_dir().join(format!("dhcp-declined-{}", std::process::id()));
+        let mut sim = Simulator::new(SegmentKind::Switch, LinkConfig::default());
+        let with_file = |config: &mut DhcpServerConfig| config.lease_file = Some(path.clone());
+        let now = SystemTime::now();
+
+        let mut server = new_server(&mut sim, with_file);
+        assert_eq!(bind(&mut server, 10, now), FIRST);
+        let decline = message(
+            10,
+            DhcpMessageType::Decline,
+            vec![DhcpOption::RequestedIp(FIRST)],
+        );
+        assert_eq!(server.reply(&decline, now), None);
+        let content = fs::read_to_string(&path).unwrap();
+        assert!(
+            content.ends_with(" 00:00:00:00:00:00 10.0.0.100\n"),
+            "{}",
+            content
+        );
+        drop(server);
+
+        // 重启后冲突的地址仍然不会分配出去
+        let mut server = new_server(&mut sim, with_file);
+        assert_eq!(server.leases().count(), 0);
+        assert_eq!(
+            server.leases.get(&FIRST).map(|l| l.state),
+            Some(LeaseState::Declined)
+        );
+        assert_eq!(discover(&mut server, 10, now), Some(SECOND));
+        assert_eq!(discover(&mut server, 11, now), None);
+
+        // 一个租期后地址重新可用
+        let expired = now + DEFAULT_LEASE_TIME + Duration::from_secs(1);
+        assert_eq!(discover(&mut server, 11, expired), Some(FIRST));
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,283 @@
+// Copyright (C) 2025 rrrrrzy
+// SPDX-License-Identifier: GPL-3.0-or-later
+//
+// --------------------------------------------------
+// 致敬所有在深夜调试代码的灵魂。
+// 即便 Bug 如山，我亦往矣。
+// --------------------------------------------------
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+//! 按 TTL 过期的查询结果缓存，包括否定应答 (RFC 2308)
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use protocol::dns::{DnsRecord, DnsRecordType};
+
+/// 正向应答的 TTL 上限
+pub const MAX_TTL: u32 = 86400;
+
+/// 否定应答的 TTL 上限，RFC 2308 建议一到三小时
+pub const MAX_NEGATIVE_TTL: u32 = 3 * 3600;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CachedAnswer {
+    Records(Vec<DnsRecord>),
+    /// 名字不存在 (NXDOMAIN)
+    NameError,
+    /// 名字存在但没有该类型的记录
+    NoData,
+}
+
+#[derive(Debug)]
+struct CacheEntry {
+    answer: CachedAnswer,
+    stored: Instant,
+    expires: Instant,
+}
+
+#[derive(Debug)]
+pub struct DnsCache {
+    entries: HashMap<(String, DnsRecordType), CacheEntry>,
+    capacity: usize,
+}
+
+impl DnsCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            entries: HashMap::new(),
+            capacity,
+        }
+    }
+
+    /// 查找未过期的结果，记录的 TTL 减去已缓存的时间
+    pub fn get(&mut self, name: &str, rtype: DnsRecordType, now: Instant) -> Option<CachedAnswer> {
+        let key = (name.to_ascii_lowercase(), rtype);
+        let entry = self.entries.get(&key)?;
+        if now >= entry.expires {
+            self.entries.remove(&key);
+            return None;
+        }
+
+        let elapsed = now.duration_since(entry.stored).as_secs() as u32;
+        let answer = match &entry.answer {
+            CachedAnswer::Records(records) => CachedAnswer::Records(
+                records
+                    .iter()
+                    .cloned()
+                    .map(|mut record| {
+                        record.ttl = record.ttl.min(MAX_TTL).saturating_sub(elapsed);
+                        record
+                    })
+                    .collect(),
+            ),
+            answer => answer.clone(),
+        };
+        Some(answer)
+    }
+
+    /// 缓存 ttl 秒，ttl 为 0 时不缓存；已满时淘汰最早过期的条目
+    pub fn insert(
+        &mut self,
+        name: &str,
+        rtype: DnsRecordType,
+        answer: CachedAnswer,
+        ttl: u32,
+        now: Instant,
+    ) {
+        let limit = match answer {
+            CachedAnswer::Records(_) => MAX_TTL,
+            _ => MAX_NEGATIVE_TTL,
+        };
+        let ttl = ttl.min(limit);
+        if ttl == 0 || self.capacity == 0 {
+            return;
+        }
+
+        let key = (name.to_ascii_lowercase(), rtype);
+        if self.entries.len() >= self.capacity && !self.entries.contains_key(&key) {
+            self.entries.retain(|_, entry| entry.expires > now);
+            if self.entries.len() >= self.capacity
+                && let Some(oldest) = self
+                    .entries
+                    .iter()
+                    .min_by_key(|(_, entry)| entry.expires)
+                    .map(|(key, _)| key.clone())
+            {
+                self.entries.remove(&oldest);
+            }
+        }
+
+        self.entries.insert(
+            key,
+            CacheEntry {
+                answer,
+                stored: now,
+                expires: now + Duration::from_secs(ttl as u64),
+            },
+        );
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use protocol::dns::DnsRecordData;
+    use protocol::ipv4::Ipv4Addr;
+
+    fn a_record(ttl: u32) -> CachedAnswer {
+        CachedAnswer::Records(vec![DnsRecord::new(
+            "example.com",
+            ttl,
+            DnsRecordData::A(Ipv4Addr::new(93, 184, 216, 34)),
+        )])
+    }
+
+    fn ttl_of(answer: Option<CachedAnswer>) -> Option<u32> {
+        match answer? {
+            CachedAnswer::Records(records) => Some(records[0].ttl),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn entries_age_and_expire() {
+        let mut cache = DnsCache::new(8);
+        let now = Instant::now();
+        cache.insert("Example.COM", DnsRecordType::A, a_record(300), 300, now);
+        assert_eq!(cache.len(), 1);
+
+        // 名字不区分大小写，类型区分
+        let later = now + Duration::from_secs(100);
+        assert_eq!(
+            ttl_of(cache.get("example.com", DnsRecordType::A, later)),
+            Some(200)
+        );
+        assert_eq!(cache.get("example.com", DnsRecordType::Aaaa, later), None);
+
+        let expired = now + Duration::from_secs(300);
+        assert_eq!(cache.get("example.com", DnsRecordType::A, expired), None);
+        assert!(cache.is_empty());
+
+        // TTL 为 0 不缓存
+        cache.insert("example.com", DnsRecordType::A, a_record(0), 0, now);
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn ttls_are_capped() {
+        let mut cache = DnsCache::new(8);
+        let now = Instant::now();
+        cache.insert(
+            "example.com",
+            DnsRecordType::A,
+            a_record(u32::MAX),
+            u32::MAX,
+            now,
+        );
+        cache.insert(
+            "missing.example.com",
+            DnsRecordType::A,
+            CachedAnswer::NameError,
+            u32::MAX,
+            now,
+        );
+
+        assert_eq!(
+            ttl_of(cache.get("example.com", DnsRecordType::A, now)),
+            Some(MAX_TTL)
+        );
+        let negative_expiry = now + Duration::from_secs(MAX_NEGATIVE_TTL as u64);
+        assert_eq!(
+            cache.get(
+                "missing.example.com",
+                DnsRecordType::A,
+                negative_expiry - Duration::from_secs(1)
+            ),
+            Some(CachedAnswer::NameError)
+        );
+        assert_eq!(
+            cache.get("missing.example.com", DnsRecordType::A, negative_expiry),
+            None
+        );
+        assert!(
+            cache
+                .get("example.com", DnsRecordType::A, negative_expiry)
+                .is_some()
+        );
+    }
+
+    #[test]
+    fn full_cache_evicts_the_earliest_expiry() {
+        let mut cache = DnsCache::new(2);
+        let now = Instant::now();
+        cache.insert(
+            "a.example",
+            DnsRecordType::A,
+            CachedAnswer::NoData,
+            100,
+            now,
+        );
+        cache.insert("b.example", DnsRecordType::A, CachedAnswer::NoData, 50, now);
+        cache.insert(
+            "c.example",
+            DnsRecordType::A,
+            CachedAnswer::NoData,
+            200,
+            now,
+        );
+        assert_eq!(cache.len(), 2);
+        assert!(cache.get("b.example", DnsRecordType::A, now).is_none());
+        assert!(cache.get("a.example", DnsRecordType::A, now).is_some());
+
+        // 已过期的条目先被清掉，不淘汰有效条目
+        let later = now + Duration::from_secs(150);
+        cache.insert(
+            "d.example",
+            DnsRecordType::A,
+            CachedAnswer::NoData,
+            100,
+            later,
+        );
+        assert!(cache.get("c.example", DnsRecordType::A, later).is_some());
+        assert!(cache.get("d.example", DnsRecordType::A, later).is_some());
+
+        // 更新已有条目不触发淘汰
+        cache.insert(
+            "d.example",
+            DnsRecordType::A,
+            CachedAnswer::NameError,
+            100,
+            later,
+        );
+        assert_eq!(cache.len(), 2);
+
+        cache.clear();
+        assert!(cache.is_empty());
+        let mut disabled = DnsCache::new(0);
+        disabled.insert(
+            "a.example",
+            DnsRecordType::A,
+            CachedAnswer::NoData,
+            100,
+            now,
+        );
+        assert!(disabled.is_empty());
+    }
+}
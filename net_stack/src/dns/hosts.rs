@@ -0,0 +1,150 @@
+// Copyright (C) 2025 rrrrrzy
+// SPDX-License-Identifier: GPL-3.0-or-later
+//
+// --------------------------------------------------
+// 致敬所有在深夜调试代码的灵魂。
+// 即便 Bug 如山，我亦往矣。
+// --------------------------------------------------
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+//! `/etc/hosts` 格式的静态名字表，优先于 DNS 查询
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+use anyhow::Context;
+use protocol::ipv4::Ipv4Addr;
+use protocol::ipv6::Ipv6Addr;
+
+#[derive(Debug, Clone, Default)]
+pub struct HostsFile {
+    // 名字统一转为小写
+    ipv4: HashMap<String, Vec<Ipv4Addr>>,
+    ipv6: HashMap<String, Vec<Ipv6Addr>>,
+    // 反向查询取该地址所在第一行的第一个名字
+    names: HashMap<Ipv4Addr, String>,
+}
+
+impl HostsFile {
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read hosts file: {}", path.display()))?;
+        Ok(Self::parse(&content))
+    }
+
+    /// 每行 `<地址> <名字> [别名...]`，`#` 之后为注释，无法解析的行被跳过
+    pub fn parse(content: &str) -> Self {
+        let mut hosts = Self::default();
+
+        for line in content.lines() {
+            let line = line.split('#').next().unwrap_or_default();
+            let mut fields = line.split_whitespace();
+            let Some(addr) = fields.next() else {
+                continue;
+            };
+            let names: Vec<String> = fields.map(|name| name.to_ascii_lowercase()).collect();
+            if names.is_empty() {
+                eprintln!("Warning: hosts entry without a name: {}", line.trim());
+                continue;
+            }
+
+            if let Ok(addr) = Ipv4Addr::from_str(addr) {
+                hosts.names.entry(addr).or_insert_with(|| names[0].clone());
+                for name in names {
+                    hosts.ipv4.entry(name).or_default().push(addr);
+                }
+            } else if let Ok(addr) = Ipv6Addr::from_str(addr) {
+                for name in names {
+                    hosts.ipv6.entry(name).or_default().push(addr);
+                }
+            } else {
+                eprintln!("Warning: invalid address in hosts file: {}", addr);
+            }
+        }
+        hosts
+    }
+
+    pub fn lookup_ipv4(&self, name: &str) -> Option<&[Ipv4Addr]> {
+        self.ipv4.get(&normalize(name)).map(Vec::as_slice)
+    }
+
+    pub fn lookup_ipv6(&self, name: &str) -> Option<&[Ipv6Addr]> {
+        self.ipv6.get(&normalize(name)).map(Vec::as_slice)
+    }
+
+    pub fn lookup_name(&self, addr: Ipv4Addr) -> Option<&str> {
+        self.names.get(&addr).map(String::as_str)
+    }
+
+    /// 名字是否出现在 hosts 文件中，不论地址族
+    pub fn contains(&self, name: &str) -> bool {
+        let name = normalize(name);
+        self.ipv4.contains_key(&name) || self.ipv6.contains_key(&name)
+    }
+}
+
+fn normalize(name: &str) -> String {
+    name.trim_end_matches('.').to_ascii_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HOSTS: &str = "\
+# 注释行
+127.0.0.1   localhost
+10.0.0.1    Router.LAN router   # 行尾注释
+10.0.0.1    gateway
+10.0.0.2    nas
+10.0.0.3    nas
+::1         localhost ip6-localhost
+not-an-ip   broken
+10.0.0.9
+";
+
+    #[test]
+    fn entries_are_parsed() {
+        let hosts = HostsFile::parse(HOSTS);
+        assert_eq!(
+            hosts.lookup_ipv4("router.lan."),
+            Some(&[Ipv4Addr::new(10, 0, 0, 1)][..])
+        );
+        assert_eq!(
+            hosts.lookup_ipv4("ROUTER"),
+            Some(&[Ipv4Addr::new(10, 0, 0, 1)][..])
+        );
+        // 同一名字的多行合并
+        assert_eq!(
+            hosts.lookup_ipv4("nas"),
+            Some(&[Ipv4Addr::new(10, 0, 0, 2), Ipv4Addr::new(10, 0, 0, 3)][..])
+        );
+        assert_eq!(
+            hosts.lookup_ipv6("localhost"),
+            Some(&[Ipv6Addr::localhost()][..])
+        );
+        assert_eq!(hosts.lookup_ipv6("router"), None);
+        assert!(hosts.contains("ip6-localhost"));
+        assert!(!hosts.contains("broken"));
+        assert!(!hosts.contains("comment"));
+
+        // 反向查询取该地址第一行的第一个名字
+        assert_eq!(
+            hosts.lookup_name(Ipv4Addr::new(10, 0, 0, 1)),
+            Some("router.lan")
+        );
+        assert_eq!(hosts.lookup_name(Ipv4Addr::new(10, 0, 0, 9)), None);
+    }
+
+    #[test]
+    fn missing_file_is_an_error() {
+        let path = std::env::temp_dir().join(format!("hosts-missing-{}", std::process::id()));
+        assert!(HostsFile::load(&path).is_err());
+    }
+}
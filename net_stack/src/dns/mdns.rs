@@ -0,0 +1,446 @@
+// Copyright (C) 2025 rrrrrzy
+// SPDX-License-Identifier: GPL-3.0-or-later
+//
+// --------------------------------------------------
+// 致敬所有在深夜调试代码的灵魂。
+// 即便 Bug 如山，我亦往矣。
+// --------------------------------------------------
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+//! 组播 DNS (RFC 6762)：应答本机 .local 名字的查询
+//!
+//! 解析其他主机的 .local 名字由 `Resolver` 以一次性查询 (RFC 6762 5.1) 完成
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
+
+use protocol::dns::{
+    DNS_CLASS_IN, DNS_MAX_LABEL_LEN, DnsMessage, DnsQuestion, DnsRecord, DnsRecordData,
+    DnsRecordType, MDNS_IPV4_GROUP, MDNS_PORT, reverse_name,
+};
+use protocol::ip::IpAddr;
+use protocol::ipv4::{Ipv4Addr, Ipv4Protocol};
+use protocol::udp::{UdpHeader, UdpPacket};
+
+use crate::handlers::ipv4::{self, SendOptions};
+use crate::stack::NetworkStack;
+use crate::transport::udp::{UdpSocket, parse_addr};
+
+/// 主机地址记录的 TTL (RFC 6762 10)
+pub const MDNS_HOST_TTL: u32 = 120;
+
+/// 传统单播查询的应答 TTL 不超过 10 秒 (RFC 6762 6.7)
+const LEGACY_UNICAST_TTL: u32 = 10;
+
+const PROBE_COUNT: u32 = 3;
+const PROBE_INTERVAL: Duration = Duration::from_millis(250);
+const ANNOUNCE_COUNT: u32 = 2;
+const ANNOUNCE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// 名字是否由 mDNS 解析：.local 域和链路本地地址的反向解析 (RFC 6762 3, 4)
+pub fn is_mdns_name(name: &str) -> bool {
+    let name = name.trim_end_matches('.').to_ascii_lowercase();
+    name.ends_with(".local") || name.ends_with(".254.169.in-addr.arpa")
+}
+
+/// 按 mDNS 的要求发送：IP TTL 为 255，多播目的地址直接映射为以太网地址，不经 ARP
+pub(super) fn send_message(
+    stack: &NetworkStack,
+    message: &DnsMessage,
+    src_port: u16,
+    (dst_ip, dst_port): (Ipv4Addr, u16),
+) {
+    let packet = UdpPacket::new(
+        UdpHeader::new(src_port, dst_port, 0),
+        message.to_bytes(),
+        stack.config().ip,
+        dst_ip,
+    );
+    let bytes = packet.to_bytes();
+    let options = SendOptions {
+        ttl: 255,
+        dont_fragment: false,
+        router_alert: false,
+    };
+
+    // 发往组播地址时直接使用映射出的 MAC，不经过 ARP
+    ipv4::send_packet_with_options(stack, dst_ip, Ipv4Protocol::UDP, &bytes, &options);
+}
+
+/// 为本机的 `<hostname>.local` 应答 A 查询，为本机地址应答 PTR 查询
+pub struct MdnsResponder {
+    stack: Arc<NetworkStack>,
+    socket: UdpSocket,
+    hostname: String,
+    // 探测并宣告过的地址，地址变化 (DHCP) 或发现冲突时重新探测
+    announced: Option<Ipv4Addr>,
+}
+
+impl MdnsResponder {
+    /// hostname 是单个标签，可以带 .local 后缀
+    pub fn new(stack: Arc<NetworkStack>, hostname: &str) -> anyhow::Result<Self> {
+        let name = hostname.trim_end_matches('.');
+        let label = match name.len().checked_sub(".local".len()) {
+            Some(i) if name[i..].eq_ignore_ascii_case(".local") => &name[..i],
+            _ => name,
+        };
+        if label.is_empty() || label.contains('.') || label.len() > DNS_MAX_LABEL_LEN {
+            anyhow::bail!("Invalid mDNS host name '{}'", hostname);
+        }
+
+        let socket = UdpSocket::bind(stack.clone(), &format!("0.0.0.0:{}", MDNS_PORT))?;
+        // socket 关闭时自动离开该组
+        socket.join_multicast(MDNS_IPV4_GROUP)?;
+
+        Ok(Self {
+            stack,
+            socket,
+            hostname: format!("{}.local", label),
+            announced: None,
+        })
+    }
+
+    /// 当前使用的名字，探测到冲突后会改名
+    pub fn hostname(&self) -> &str {
+        &self.hostname
+    }
+
+    /// 探测名字并宣告，然后应答查询直到 cancel 被置位，退出前发送 goodbye
+    ///
+    /// 本机还没有地址时等待 (例如 DHCP 尚未完成)，需要有线程在运行 `event_loop::run`
+    pub fn run(&mut self, cancel: &AtomicBool) -> anyhow::Result<()> {
+        while !cancel.load(Ordering::Relaxed) {
+            let ip = self.stack.config().ip;
+            if ip != Ipv4Addr::unspecified() && self.announced != Some(ip) {
+                self.probe(ip, cancel);
+                if cancel.load(Ordering::Relaxed) {
+                    break;
+                }
+                self.announce(ip, cancel);
+                self.announced = Some(ip);
+            }
+
+            match self.socket.recv_from() {
+                Ok((data, src)) => self.handle(&data, &src),
+                Err(_) => thread::sleep(Duration::from_millis(10)),
+            }
+        }
+
+        if let Some(ip) = self.announced.take() {
+            // TTL 为 0 的记录通知其他主机立即删除缓存 (RFC 6762 10.1)
+            let goodbye = self.unsolicited_response(ip, 0);
+            send_message(
+                &self.stack,
+                &goodbye,
+                MDNS_PORT,
+                (MDNS_IPV4_GROUP, MDNS_PORT),
+            );
+            println!("mDNS: {} withdrawn", self.hostname);
+        }
+        Ok(())
+    }
+
+    /// 探测名字是否已被其他主机使用，冲突时在名字后加序号重新探测 (RFC 6762 8.1, 9)
+    fn probe(&mut self, ip: Ipv4Addr, cancel: &AtomicBool) {
+        'names: loop {
+            for i in 0..PROBE_COUNT {
+                if cancel.load(Ordering::Relaxed) {
+                    return;
+                }
+                let mut probe = DnsMessage::new(0);
+                probe.questions.push(DnsQuestion {
+                    name: self.hostname.clone(),
+                    qtype: DnsRecordType::Any,
+                    qclass: DNS_CLASS_IN,
+                    // 第一个探测包请求单播应答
+                    unicast_response: i == 0,
+                });
+                // 权威部分带上打算使用的记录，供同时探测的主机比较
+                probe
+                    .authorities
+                    .push(self.address_record(ip, MDNS_HOST_TTL));
+                send_message(&self.stack, &probe, MDNS_PORT, (MDNS_IPV4_GROUP, MDNS_PORT));
+
+                if self.wait_conflict(ip, PROBE_INTERVAL) {
+                    let renamed = next_name(&self.hostname);
+                    eprintln!(
+                        "mDNS: {} is already in use, trying {}",
+                        self.hostname, renamed
+                    );
+                    self.hostname = renamed;
+                    continue 'names;
+                }
+            }
+            return;
+        }
+    }
+
+    /// 在 timeout 内等待其他主机对本机名字的应答，探测期间不应答查询
+    fn wait_conflict(&self, ip: Ipv4Addr, timeout: Duration) -> bool {
+        let deadline = self.stack.now() + timeout;
+        while self.stack.now() < deadline {
+            let Ok((data, _)) = self.socket.recv_from() else {
+                thread::sleep(Duration::from_millis(10));
+                continue;
+            };
+            if let Ok(message) = DnsMessage::parse(&data)
+                && message.response
+                && self.conflicts(&message, ip)
+            {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// 应答中是否有同名但数据不同的记录
+    fn conflicts(&self, message: &DnsMessage, ip: Ipv4Addr) -> bool {
+        message
+            .answers
+            .iter()
+            .chain(&message.additionals)
+            .any(|record| {
+                record.name.eq_ignore_ascii_case(&self.hostname)
+                    && record.data != DnsRecordData::A(ip)
+            })
+    }
+
+    /// 连续发送两次主动应答 (RFC 6762 8.3)
+    fn announce(&self, ip: Ipv4Addr, cancel: &AtomicBool) {
+        println!("mDNS: announcing {} at {}", self.hostname, ip);
+        let announcement = self.unsolicited_response(ip, MDNS_HOST_TTL);
+        for i in 0..ANNOUNCE_COUNT {
+            if i > 0 {
+                let deadline = self.stack.now() + ANNOUNCE_INTERVAL;
+                while self.stack.now() < deadline && !cancel.load(Ordering::Relaxed) {
+                    thread::sleep(Duration::from_millis(10));
+                }
+            }
+            send_message(
+                &self.stack,
+                &announcement,
+                MDNS_PORT,
+                (MDNS_IPV4_GROUP, MDNS_PORT),
+            );
+        }
+    }
+
+    fn handle(&mut self, data: &[u8], src: &str) {
+        let Ok((IpAddr::V4(src_ip), src_port)) = parse_addr(src) else {
+            return;
+        };
+        let message = match DnsMessage::parse(data) {
+            Ok(message) => message,
+            Err(e) => {
+                eprintln!("Invalid mDNS message from {}: {}", src, e);
+                return;
+            }
+        };
+        let Some(ip) = self.announced else {
+            return;
+        };
+
+        if message.response {
+            if self.conflicts(&message, ip) {
+                eprintln!("mDNS: {} claims {}, probing again", src_ip, self.hostname);
+                self.announced = None;
+            }
+            return;
+        }
+        if message.opcode != 0 {
+            return;
+        }
+
+        // 源端口不是 5353 的是传统单播查询，按普通 DNS 回复给源端口 (RFC 6762 6.7)
+        let legacy = src_port != MDNS_PORT;
+        let mut unicast = legacy;
+        let mut answers = Vec::new();
+        for question in &message.questions {
+            let records: Vec<DnsRecord> = self
+                .records_for(question, ip)
+                .into_iter()
+                // 查询方已经知道且剩余 TTL 超过一半的记录不再回答 (RFC 6762 7.1)
+                .filter(|record| {
+                    !message.answers.iter().any(|known| {
+                        known.name.eq_ignore_ascii_case(&record.name)
+                            && known.data == record.data
+                            && known.ttl >= record.ttl / 2
+                    })
+                })
+                .collect();
+            if !records.is_empty() && question.unicast_response {
+                unicast = true;
+            }
+            answers.extend(records);
+        }
+        if answers.is_empty() {
+            return;
+        }
+
+        let mut reply = DnsMessage::new(0);
+        reply.response = true;
+        reply.authoritative = true;
+        if legacy {
+            reply.id = message.id;
+            reply.questions = message.questions.clone();
+            for record in &mut answers {
+                record.ttl = record.ttl.min(LEGACY_UNICAST_TTL);
+                record.cache_flush = false;
+            }
+        }
+        reply.answers = answers;
+
+        let dst = if unicast {
+            (src_ip, src_port)
+        } else {
+            (MDNS_IPV4_GROUP, MDNS_PORT)
+        };
+        send_message(&self.stack, &reply, MDNS_PORT, dst);
+    }
+
+    /// 本机对一个问题的回答
+    fn records_for(&self, question: &DnsQuestion, ip: Ipv4Addr) -> Vec<DnsRecord> {
+        let wants = |rtype| question.qtype == rtype || question.qtype == DnsRecordType::Any;
+        let mut records = Vec::new();
+        if question.name.eq_ignore_ascii_case(&self.hostname) && wants(DnsRecordType::A) {
+            records.push(self.address_record(ip, MDNS_HOST_TTL));
+        }
+        if question.name.eq_ignore_ascii_case(&reverse_name(ip)) && wants(DnsRecordType::Ptr) {
+            records.push(self.pointer_record(ip, MDNS_HOST_TTL));
+        }
+        records
+    }
+
+    fn unsolicited_response(&self, ip: Ipv4Addr, ttl: u32) -> DnsMessage {
+        let mut message = DnsMessage::new(0);
+        message.response = true;
+        message.authoritative = true;
+        message.answers = vec![self.address_record(ip, ttl), self.pointer_record(ip, ttl)];
+        message
+    }
+
+    /// 本机名字是唯一记录，带 cache-flush 位
+    fn address_record(&self, ip: Ipv4Addr, ttl: u32) -> DnsRecord {
+        let mut record = DnsRecord::new(&self.hostname, ttl, DnsRecordData::A(ip));
+        record.cache_flush = true;
+        record
+    }
+
+    fn pointer_record(&self, ip: Ipv4Addr, ttl: u32) -> DnsRecord {
+        let mut record = DnsRecord::new(
+            &reverse_name(ip),
+            ttl,
+            DnsRecordData::Ptr(self.hostname.clone()),
+        );
+        record.cache_flush = true;
+        record
+    }
+}
+
+/// foo.local -> foo-2.local，foo-2.local -> foo-3.local
+fn next_name(hostname: &str) -> String {
+    let label = hostname.strip_suffix(".local").unwrap_or(hostname);
+    let (base, n) = match label.rsplit_once('-') {
+        Some((base, n)) if !base.is_empty() => match n.parse::<u32>() {
+            Ok(n) => (base, n + 1),
+            Err(_) => (label, 2),
+        },
+        _ => (label, 2),
+    };
+    format!("{}-{}.local", base, n)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sim::{LinkConfig, SegmentKind, Simulator};
+    use crate::stack::StackConfig;
+    use protocol::mac::MacAddr;
+
+    const IP: Ipv4Addr = Ipv4Addr::new(169, 254, 1, 2);
+
+    fn responder(hostname: &str) -> anyhow::Result<MdnsResponder> {
+        let mut sim = Simulator::new(SegmentKind::Switch, LinkConfig::default());
+        let stack = sim.add_host(StackConfig::new(
+            MacAddr::from_raw([0x02, 0, 0, 0, 0, 1]),
+            IP,
+        ));
+        MdnsResponder::new(stack, hostname)
+    }
+
+    fn question(name: &str, qtype: DnsRecordType) -> DnsQuestion {
+        DnsQuestion {
+            name: name.to_string(),
+            qtype,
+            qclass: DNS_CLASS_IN,
+            unicast_response: false,
+        }
+    }
+
+    #[test]
+    fn local_names_are_recognized() {
+        assert!(is_mdns_name("printer.local"));
+        assert!(is_mdns_name("Printer.LOCAL."));
+        assert!(is_mdns_name("2.1.254.169.in-addr.arpa"));
+        assert!(!is_mdns_name("local"));
+        assert!(!is_mdns_name("printer.localdomain"));
+        assert!(!is_mdns_name("2.1.168.192.in-addr.arpa"));
+    }
+
+    #[test]
+    fn conflicting_names_get_a_number() {
+        assert_eq!(next_name("host.local"), "host-2.local");
+        assert_eq!(next_name("host-2.local"), "host-3.local");
+        assert_eq!(next_name("my-host.local"), "my-host-2.local");
+        assert_eq!(next_name("-1.local"), "-1-2.local");
+    }
+
+    #[test]
+    fn host_names_are_validated() {
+        assert_eq!(responder("alpha").unwrap().hostname(), "alpha.local");
+        assert_eq!(responder("Alpha.LOCAL.").unwrap().hostname(), "Alpha.local");
+        for invalid in ["", ".local", "alpha.lan", &"a".repeat(64)] {
+            assert!(responder(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn questions_are_answered_for_the_host() {
+        let responder = responder("alpha").unwrap();
+        let records = |name: &str, qtype| responder.records_for(&question(name, qtype), IP);
+
+        let a = records("ALPHA.local", DnsRecordType::A);
+        assert_eq!(a.len(), 1);
+        assert_eq!(a[0].data, DnsRecordData::A(IP));
+        assert!(a[0].cache_flush);
+        assert_eq!(a[0].ttl, MDNS_HOST_TTL);
+
+        let ptr = records("2.1.254.169.in-addr.arpa", DnsRecordType::Any);
+        assert_eq!(ptr[0].data, DnsRecordData::Ptr("alpha.local".to_string()));
+        assert_eq!(records("alpha.local", DnsRecordType::Any).len(), 1);
+        assert!(records("alpha.local", DnsRecordType::Aaaa).is_empty());
+        assert!(records("beta.local", DnsRecordType::A).is_empty());
+
+        // 同名同数据的记录不算冲突
+        let mut response = DnsMessage::new(0);
+        response.response = true;
+        response.answers = a.clone();
+        assert!(!responder.conflicts(&response, IP));
+        response.additionals = vec![DnsRecord::new(
+            "Alpha.local",
+            MDNS_HOST_TTL,
+            DnsRecordData::A(Ipv4Addr::new(169, 254, 9, 9)),
+        )];
+        assert!(responder.conflicts(&response, IP));
+
+        let goodbye = responder.unsolicited_response(IP, 0);
+        assert!(goodbye.authoritative);
+        assert!(goodbye.answers.iter().all(|record| record.ttl == 0));
+    }
+}
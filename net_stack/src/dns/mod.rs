@@ -0,0 +1,19 @@
+// Copyright (C) 2025 rrrrrzy
+// SPDX-License-Identifier: GPL-3.0-or-later
+//
+// --------------------------------------------------
+// 致敬所有在深夜调试代码的灵魂。
+// 即便 Bug 如山，我亦往矣。
+// --------------------------------------------------
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+//! DNS 名字解析 (RFC 1035)：带缓存的存根解析器、hosts 文件和 mDNS (RFC 6762)
+
+pub mod cache;
+pub mod hosts;
+pub mod mdns;
+pub mod resolver;
@@ -0,0 +1,702 @@
+// Copyright (C) 2025 rrrrrzy
+// SPDX-License-Identifier: GPL-3.0-or-later
+//
+// --------------------------------------------------
+// 致敬所有在深夜调试代码的灵魂。
+// 即便 Bug 如山，我亦往矣。
+// --------------------------------------------------
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+//! 存根解析器：向配置的递归服务器发送查询，结果按 TTL 缓存
+//!
+//! 查询经 UDP 发出，应答被截断时改用 TCP 重新查询 (RFC 7766)；
+//! .local 名字改为向 mDNS 组播地址发送一次性查询 (RFC 6762 5.1)
+
+use std::error;
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU16, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use protocol::dns::{
+    DNS_PORT, DnsMessage, DnsRecord, DnsRecordData, DnsRecordType, DnsResponseCode,
+    MDNS_IPV4_GROUP, MDNS_PORT, is_valid_name, reverse_name,
+};
+use protocol::ipv4::Ipv4Addr;
+use protocol::ipv6::Ipv6Addr;
+
+use super::cache::{CachedAnswer, DnsCache};
+use super::hosts::HostsFile;
+use super::mdns;
+use crate::stack::NetworkStack;
+use crate::transport::tcp::{TcpError, TcpStream};
+use crate::transport::udp::{UdpSocket, parse_addr};
+
+#[derive(Debug, Clone)]
+pub struct ResolverConfig {
+    /// 为空时使用协议栈配置的 DNS 服务器（静态配置或由 DHCP 获得）
+    pub servers: Vec<Ipv4Addr>,
+    /// 每个服务器每次查询等待应答的时间
+    pub timeout: Duration,
+    /// 依次尝试全部服务器的轮数
+    pub attempts: u32,
+    /// 优先于 DNS 的 hosts 文件
+    pub hosts_file: Option<PathBuf>,
+    /// 缓存的最大条目数，0 表示不缓存
+    pub cache_size: usize,
+}
+
+impl Default for ResolverConfig {
+    fn default() -> Self {
+        Self {
+            servers: Vec::new(),
+            timeout: Duration::from_secs(2),
+            attempts: 2,
+            hosts_file: None,
+            cache_size: 1024,
+        }
+    }
+}
+
+/// 解析失败的原因
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResolveError {
+    InvalidName,
+    NoServers,
+    /// 名字不存在 (NXDOMAIN)
+    NotFound,
+    /// 名字存在但没有请求类型的记录
+    NoData,
+    /// 所有服务器都拒绝或无法完成查询
+    ServerFailure(DnsResponseCode),
+    TimedOut,
+}
+
+impl fmt::Display for ResolveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ResolveError::InvalidName => write!(f, "Invalid domain name"),
+            ResolveError::NoServers => write!(f, "No DNS servers configured"),
+            ResolveError::NotFound => write!(f, "Name not found"),
+            ResolveError::NoData => write!(f, "No records of the requested type"),
+            ResolveError::ServerFailure(rcode) => write!(f, "DNS server failure ({})", rcode),
+            ResolveError::TimedOut => write!(f, "DNS query timed out"),
+        }
+    }
+}
+
+impl error::Error for ResolveError {}
+
+pub struct Resolver {
+    stack: Arc<NetworkStack>,
+    config: ResolverConfig,
+    hosts: HostsFile,
+    cache: Mutex<DnsCache>,
+    // 所有查询共用一个 socket，锁同时保证一次只有一个查询在等应答
+    socket: Mutex<UdpSocket>,
+    next_id: AtomicU16,
+}
+
+impl Resolver {
+    pub fn new(stack: Arc<NetworkStack>, config: ResolverConfig) -> anyhow::Result<Self> {
+        let hosts = match &config.hosts_file {
+            Some(path) => HostsFile::load(path)?,
+            None => HostsFile::default(),
+        };
+        let socket = UdpSocket::bind(stack.clone(), "0.0.0.0:0")?;
+        let seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .subsec_nanos();
+
+        Ok(Self {
+            stack,
+            cache: Mutex::new(DnsCache::new(config.cache_size)),
+            config,
+            hosts,
+            socket: Mutex::new(socket),
+            next_id: AtomicU16::new(seed as u16),
+        })
+    }
+
+    /// 查询 name 的 rtype 记录，阻塞直到得到应答或超时
+    ///
+    /// 结果包含从 name 出发的 CNAME 链，需要有线程在运行 `event_loop::run`
+    pub fn lookup(&self, name: &str, rtype: DnsRecordType) -> anyhow::Result<Vec<DnsRecord>> {
+        let name = name.trim_end_matches('.');
+        if name.is_empty() || !is_valid_name(name) {
+            return Err(ResolveError::InvalidName.into());
+        }
+
+        if let Some(records) = self.lookup_hosts(name, rtype) {
+            return Ok(records);
+        }
+
+        let cached = self
+            .cache
+            .lock()
+            .unwrap()
+            .get(name, rtype, self.stack.now());
+        let answer = match cached {
+            Some(answer) => answer,
+            None => self.query(name, rtype)?,
+        };
+        match answer {
+            CachedAnswer::Records(records) => Ok(records),
+            CachedAnswer::NameError => Err(ResolveError::NotFound.into()),
+            CachedAnswer::NoData => Err(ResolveError::NoData.into()),
+        }
+    }
+
+    /// 主机名到 IPv4 地址，字面地址原样返回
+    pub fn lookup_ipv4(&self, host: &str) -> anyhow::Result<Vec<Ipv4Addr>> {
+        if let Ok(addr) = Ipv4Addr::from_str(host) {
+            return Ok(vec![addr]);
+        }
+        let addrs = self
+            .lookup(host, DnsRecordType::A)?
+            .into_iter()
+            .filter_map(|record| match record.data {
+                DnsRecordData::A(addr) => Some(addr),
+                _ => None,
+            })
+            .collect();
+        Ok(addrs)
+    }
+
+    /// 主机名到 IPv6 地址
+    pub fn lookup_ipv6(&self, host: &str) -> anyhow::Result<Vec<Ipv6Addr>> {
+        if let Ok(addr) = Ipv6Addr::from_str(host) {
+            return Ok(vec![addr]);
+        }
+        let addrs = self
+            .lookup(host, DnsRecordType::Aaaa)?
+            .into_iter()
+            .filter_map(|record| match record.data {
+                DnsRecordData::Aaaa(addr) => Some(addr),
+                _ => None,
+            })
+            .collect();
+        Ok(addrs)
+    }
+
+    /// 反向解析，查询 in-addr.arpa 下的 PTR 记录
+    pub fn lookup_addr(&self, addr: Ipv4Addr) -> anyhow::Result<Vec<String>> {
+        let names = self
+            .lookup(&reverse_name(addr), DnsRecordType::Ptr)?
+            .into_iter()
+            .filter_map(|record| match record.data {
+                DnsRecordData::Ptr(name) => Some(name),
+                _ => None,
+            })
+            .collect();
+        Ok(names)
+    }
+
+    /// 解析 `<主机名或地址>:<端口>`，取第一个地址
+    pub fn resolve_addr(&self, addr: &str) -> anyhow::Result<(Ipv4Addr, u16)> {
+        let (host, port) = addr
+            .rsplit_once(':')
+            .ok_or_else(|| anyhow::anyhow!("Invalid address format, expected HOST:PORT"))?;
+        let port = port
+            .parse::<u16>()
+            .map_err(|_| anyhow::anyhow!("Invalid port number"))?;
+        let ip = self
+            .lookup_ipv4(host)?
+            .first()
+            .copied()
+            .ok_or(ResolveError::NoData)?;
+        Ok((ip, port))
+    }
+
+    pub fn clear_cache(&self) {
+        self.cache.lock().unwrap().clear();
+    }
+
+    /// hosts 文件中的名字直接给出结果，TTL 为 0
+    fn lookup_hosts(&self, name: &str, rtype: DnsRecordType) -> Option<Vec<DnsRecord>> {
+        let records: Vec<DnsRecord> = match rtype {
+            DnsRecordType::A => self
+                .hosts
+                .lookup_ipv4(name)?
+                .iter()
+                .map(|addr| DnsRecord::new(name, 0, DnsRecordData::A(*addr)))
+                .collect(),
+            DnsRecordType::Aaaa => self
+                .hosts
+                .lookup_ipv6(name)?
+                .iter()
+                .map(|addr| DnsRecord::new(name, 0, DnsRecordData::Aaaa(*addr)))
+                .collect(),
+            DnsRecordType::Ptr => {
+                let addr = parse_reverse_name(name)?;
+                let host = self.hosts.lookup_name(addr)?;
+                vec![DnsRecord::new(
+                    name,
+                    0,
+                    DnsRecordData::Ptr(host.to_string()),
+                )]
+            }
+            _ => return None,
+        };
+        Some(records)
+    }
+
+    /// 依次向每个服务器查询，重复 attempts 轮，结果写入缓存
+    fn query(&self, name: &str, rtype: DnsRecordType) -> anyhow::Result<CachedAnswer> {
+        if mdns::is_mdns_name(name) {
+            return self.query_mdns(name, rtype);
+        }
+
+        let servers = if self.config.servers.is_empty() {
+            self.stack.config().dns_servers.clone()
+        } else {
+            self.config.servers.clone()
+        };
+        if servers.is_empty() {
+            return Err(ResolveError::NoServers.into());
+        }
+
+        let mut error = ResolveError::TimedOut;
+        for _ in 0..self.config.attempts.max(1) {
+            for &server in &servers {
+                let id = self.next_id.fetch_add(1, Ordering::Relaxed) ^ random_bits();
+                let query = DnsMessage::query(id, name, rtype);
+                let Some(response) = self.exchange(server, &query)? else {
+                    continue;
+                };
+
+                match response.rcode {
+                    DnsResponseCode::NoError | DnsResponseCode::NameError => {
+                        let (answer, ttl) = interpret(name, rtype, &response);
+                        self.cache.lock().unwrap().insert(
+                            name,
+                            rtype,
+                            answer.clone(),
+                            ttl,
+                            self.stack.now(),
+                        );
+                        return Ok(answer);
+                    }
+                    // 换下一个服务器
+                    rcode => {
+                        eprintln!("DNS server {} answered {} for {}", server, rcode, name);
+                        error = ResolveError::ServerFailure(rcode);
+                    }
+                }
+            }
+        }
+        Err(error.into())
+    }
+
+    /// 向 mDNS 组播地址查询，接受第一个给出记录的应答，没有主机应答时视为名字不存在
+    fn query_mdns(&self, name: &str, rtype: DnsRecordType) -> anyhow::Result<CachedAnswer> {
+        for _ in 0..self.config.attempts.max(1) {
+            let id = self.next_id.fetch_add(1, Ordering::Relaxed) ^ random_bits();
+            let mut query = DnsMessage::query(id, name, rtype);
+            query.recursion_desired = false;
+
+            let socket = self.socket.lock().unwrap();
+            while socket.recv_from().is_ok() {}
+            // 源端口不是 5353，应答方按传统单播查询直接回复到该端口
+            mdns::send_message(
+                &self.stack,
+                &query,
+                socket.local_port(),
+                (MDNS_IPV4_GROUP, MDNS_PORT),
+            );
+
+            let deadline = self.stack.now() + self.config.timeout;
+            while self.stack.now() < deadline {
+                let Ok((data, _)) = socket.recv_from() else {
+                    thread::sleep(Duration::from_millis(5));
+                    continue;
+                };
+                let Ok(response) = DnsMessage::parse(&data) else {
+                    continue;
+                };
+                if !response.response || (response.id != id && response.id != 0) {
+                    continue;
+                }
+                if let (CachedAnswer::Records(records), ttl) = interpret(name, rtype, &response) {
+                    let answer = CachedAnswer::Records(records);
+                    self.cache.lock().unwrap().insert(
+                        name,
+                        rtype,
+                        answer.clone(),
+                        ttl,
+                        self.stack.now(),
+                    );
+                    return Ok(answer);
+                }
+            }
+        }
+        Ok(CachedAnswer::NameError)
+    }
+
+    /// 发送一次查询并等待匹配的应答，超时返回 None
+    fn exchange(&self, server: Ipv4Addr, query: &DnsMessage) -> anyhow::Result<Option<DnsMessage>> {
+        let socket = self.socket.lock().unwrap();
+        // 丢掉之前超时的查询迟到的应答
+        while socket.recv_from().is_ok() {}
+        socket.send_to(&query.to_bytes(), &format!("{}:{}", server, DNS_PORT))?;
+
+        let deadline = self.stack.now() + self.config.timeout;
+        while self.stack.now() < deadline {
+            let (data, src) = match socket.recv_from() {
+                Ok(received) => received,
+                Err(_) => {
+                    thread::sleep(Duration::from_millis(5));
+                    continue;
+                }
+            };
+            if parse_addr(&src).ok() != Some((server.into(), DNS_PORT)) {
+                continue;
+            }
+            let response = match DnsMessage::parse(&data) {
+                Ok(response) => response,
+                Err(e) => {
+                    eprintln!("Invalid DNS message from {}: {}", server, e);
+                    continue;
+                }
+            };
+            if !is_response_to(&response, query) {
+                continue;
+            }
+            if response.truncated {
+                drop(socket);
+                return self.exchange_tcp(server, query);
+            }
+            return Ok(Some(response));
+        }
+        Ok(None)
+    }
+
+    /// 通过 TCP 查询，报文前加两字节长度 (RFC 1035 4.2.2)
+    fn exchange_tcp(
+        &self,
+        server: Ipv4Addr,
+        query: &DnsMessage,
+    ) -> anyhow::Result<Option<DnsMessage>> {
+        let stream =
+            match TcpStream::connect(self.stack.clone(), &format!("{}:{}", server, DNS_PORT)) {
+                Ok(stream) => stream,
+                Err(e) => {
+                    eprintln!("DNS over TCP to {} failed: {}", server, e);
+                    return Ok(None);
+                }
+            };
+
+        let bytes = query.to_bytes();
+        let mut request = (bytes.len() as u16).to_be_bytes().to_vec();
+        request.extend_from_slice(&bytes);
+        let deadline = self.stack.now() + self.config.timeout;
+
+        let mut sent = 0;
+        while sent < request.len() {
+            if self.stack.now() >= deadline {
+                return Ok(None);
+            }
+            match stream.send(&request[sent..])? {
+                0 => thread::sleep(Duration::from_millis(1)),
+                n => sent += n,
+            }
+        }
+
+        let mut response = Vec::new();
+        while self.stack.now() < deadline {
+            match stream.recv() {
+                // 对端关闭
+                Ok(data) if data.is_empty() => break,
+                Ok(data) => response.extend_from_slice(&data),
+                Err(e) if e.downcast_ref::<TcpError>().is_some() => return Err(e),
+                Err(_) => thread::sleep(Duration::from_millis(1)),
+            }
+            if response.len() >= 2 {
+                let len = u16::from_be_bytes([response[0], response[1]]) as usize;
+                if response.len() >= 2 + len {
+                    stream.close();
+                    let response = DnsMessage::parse(&response[2..2 + len])?;
+                    return Ok(is_response_to(&response, query).then_some(response));
+                }
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// 应答的 ID、问题与查询一致
+fn is_response_to(response: &DnsMessage, query: &DnsMessage) -> bool {
+    response.response
+        && response.id == query.id
+        && response.questions.len() == 1
+        && response.questions[0].qtype == query.questions[0].qtype
+        && response.questions[0]
+            .name
+            .eq_ignore_ascii_case(&query.questions[0].name)
+}
+
+/// 从应答中取出 name 的 CNAME 链和 rtype 记录，返回结果和缓存时间
+fn interpret(name: &str, rtype: DnsRecordType, response: &DnsMessage) -> (CachedAnswer, u32) {
+    let mut records = Vec::new();
+    let mut owner = name.to_string();
+    // 链长度不超过回答记录数，避免 CNAME 环
+    for _ in 0..=response.answers.len() {
+        let cname = response
+            .answers
+            .iter()
+            .find_map(|record| match &record.data {
+                DnsRecordData::Cname(target)
+                    if rtype != DnsRecordType::Cname
+                        && record.name.eq_ignore_ascii_case(&owner) =>
+                {
+                    Some((record, target.clone()))
+                }
+                _ => None,
+            });
+        match cname {
+            Some((record, target)) => {
+                records.push(record.clone());
+                owner = target;
+            }
+            None => break,
+        }
+    }
+    records.extend(
+        response
+            .answers
+            .iter()
+            .filter(|record| record.rtype() == rtype && record.name.eq_ignore_ascii_case(&owner))
+            .cloned(),
+    );
+
+    if records.iter().any(|record| record.rtype() == rtype) {
+        let ttl = records.iter().map(|record| record.ttl).min().unwrap_or(0);
+        return (CachedAnswer::Records(records), ttl);
+    }
+
+    // 否定应答的缓存时间取权威部分 SOA 的 TTL 和 MINIMUM 中较小者 (RFC 2308 5)
+    let ttl = response
+        .authorities
+        .iter()
+        .find_map(|record| match record.data {
+            DnsRecordData::Soa { minimum, .. } => Some(record.ttl.min(minimum)),
+            _ => None,
+        })
+        .unwrap_or(0);
+    let answer = if response.rcode == DnsResponseCode::NameError {
+        CachedAnswer::NameError
+    } else {
+        CachedAnswer::NoData
+    };
+    (answer, ttl)
+}
+
+/// d.c.b.a.in-addr.arpa 还原为地址
+fn parse_reverse_name(name: &str) -> Option<Ipv4Addr> {
+    let lower = name.to_ascii_lowercase();
+    let octets = lower.strip_suffix(".in-addr.arpa")?;
+    let mut parts: Vec<&str> = octets.split('.').collect();
+    parts.reverse();
+    Ipv4Addr::from_str(&parts.join(".")).ok()
+}
+
+fn random_bits() -> u16 {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .subsec_nanos();
+    (nanos ^ (nanos >> 16)) as u16
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sim::{LinkConfig, SegmentKind, Simulator};
+    use crate::stack::StackConfig;
+    use protocol::mac::MacAddr;
+
+    const ADDR: Ipv4Addr = Ipv4Addr::new(93, 184, 216, 34);
+
+    fn response(answers: Vec<DnsRecord>) -> DnsMessage {
+        let mut response = DnsMessage::query(1, "www.example.com", DnsRecordType::A);
+        response.response = true;
+        response.answers = answers;
+        response
+    }
+
+    fn cname(name: &str, ttl: u32, target: &str) -> DnsRecord {
+        DnsRecord::new(name, ttl, DnsRecordData::Cname(target.to_string()))
+    }
+
+    #[test]
+    fn cname_chains_are_followed() {
+        let answers = vec![
+            DnsRecord::new("cdn.example.net", 30, DnsRecordData::A(ADDR)),
+            cname("WWW.example.com", 300, "edge.example.com"),
+            cname("edge.example.com", 60, "cdn.example.net"),
+            // 与链无关的记录被忽略
+            DnsRecord::new("other.example.com", 5, DnsRecordData::A(ADDR)),
+        ];
+        let (answer, ttl) = interpret("www.example.com", DnsRecordType::A, &response(answers));
+        let CachedAnswer::Records(records) = answer else {
+            panic!("expected records");
+        };
+        let types: Vec<_> = records.iter().map(DnsRecord::rtype).collect();
+        assert_eq!(
+            types,
+            [DnsRecordType::Cname, DnsRecordType::Cname, DnsRecordType::A]
+        );
+        assert_eq!(records[2].name, "cdn.example.net");
+        // 缓存时间取链上最小的 TTL
+        assert_eq!(ttl, 30);
+
+        // 查询 CNAME 本身时不跟随
+        let answers = vec![cname("www.example.com", 300, "edge.example.com")];
+        let (answer, ttl) = interpret("www.example.com", DnsRecordType::Cname, &response(answers));
+        assert!(matches!(answer, CachedAnswer::Records(records) if records.len() == 1));
+        assert_eq!(ttl, 300);
+
+        // CNAME 环没有终点，视为没有数据
+        let answers = vec![
+            cname("www.example.com", 300, "loop.example.com"),
+            cname("loop.example.com", 300, "www.example.com"),
+        ];
+        let (answer, _) = interpret("www.example.com", DnsRecordType::A, &response(answers));
+        assert_eq!(answer, CachedAnswer::NoData);
+    }
+
+    #[test]
+    fn negative_answers_use_the_soa_ttl() {
+        let soa = |ttl, minimum| {
+            DnsRecord::new(
+                "example.com",
+                ttl,
+                DnsRecordData::Soa {
+                    mname: "ns.example.com".to_string(),
+                    rname: "hostmaster.example.com".to_string(),
+                    serial: 1,
+                    refresh: 7200,
+                    retry: 3600,
+                    expire: 1209600,
+                    minimum,
+                },
+            )
+        };
+
+        let mut nxdomain = response(Vec::new());
+        nxdomain.rcode = DnsResponseCode::NameError;
+        nxdomain.authorities.push(soa(3600, 300));
+        assert_eq!(
+            interpret("www.example.com", DnsRecordType::A, &nxdomain),
+            (CachedAnswer::NameError, 300)
+        );
+
+        let mut nodata = response(vec![cname("www.example.com", 300, "example.com")]);
+        nodata.authorities.push(soa(60, 300));
+        assert_eq!(
+            interpret("www.example.com", DnsRecordType::A, &nodata),
+            (CachedAnswer::NoData, 60)
+        );
+
+        // 没有 SOA 时不缓存
+        assert_eq!(
+            interpret("www.example.com", DnsRecordType::A, &response(Vec::new())),
+            (CachedAnswer::NoData, 0)
+        );
+    }
+
+    #[test]
+    fn responses_must_match_the_query() {
+        let query = DnsMessage::query(7, "www.example.com", DnsRecordType::A);
+        let mut response = query.clone();
+        response.response = true;
+        assert!(is_response_to(&response, &query));
+        response.questions[0].name = "WWW.EXAMPLE.COM".to_string();
+        assert!(is_response_to(&response, &query));
+
+        let mismatched = [
+            DnsMessage {
+                response: false,
+                ..response.clone()
+            },
+            DnsMessage {
+                id: 8,
+                ..response.clone()
+            },
+            DnsMessage::query(7, "www.example.com", DnsRecordType::Aaaa),
+            DnsMessage {
+                questions: Vec::new(),
+                ..response.clone()
+            },
+        ];
+        for other in mismatched {
+            assert!(!is_response_to(&other, &query), "{}", other);
+        }
+    }
+
+    #[test]
+    fn hosts_file_answers_without_queries() {
+        let mut sim = Simulator::new(SegmentKind::Switch, LinkConfig::default());
+        let stack = sim.add_host(StackConfig::new(
+            MacAddr::from_raw([0x02, 0, 0, 0, 0, 1]),
+            Ipv4Addr::new(10, 0, 0, 1),
+        ));
+        let mut resolver = Resolver::new(stack, ResolverConfig::default()).unwrap();
+        resolver.hosts = HostsFile::parse("10.0.0.2 nas nas.lan\n::1 localhost\n");
+
+        assert_eq!(
+            resolver.lookup_ipv4("NAS.lan.").unwrap(),
+            vec![Ipv4Addr::new(10, 0, 0, 2)]
+        );
+        assert_eq!(
+            resolver.lookup_ipv6("localhost").unwrap(),
+            vec![Ipv6Addr::localhost()]
+        );
+        assert_eq!(
+            resolver.lookup_addr(Ipv4Addr::new(10, 0, 0, 2)).unwrap(),
+            vec!["nas".to_string()]
+        );
+        assert_eq!(
+            resolver.resolve_addr("nas:8080").unwrap(),
+            (Ipv4Addr::new(10, 0, 0, 2), 8080)
+        );
+        // 字面地址原样返回
+        assert_eq!(
+            resolver.lookup_ipv4("192.0.2.1").unwrap(),
+            vec![Ipv4Addr::new(192, 0, 2, 1)]
+        );
+
+        let error = |result: anyhow::Result<Vec<Ipv4Addr>>| {
+            *result.unwrap_err().downcast_ref::<ResolveError>().unwrap()
+        };
+        assert_eq!(
+            error(resolver.lookup_ipv4("bad..name")),
+            ResolveError::InvalidName
+        );
+        // 不在 hosts 文件中，也没有配置服务器
+        assert_eq!(
+            error(resolver.lookup_ipv4("example.com")),
+            ResolveError::NoServers
+        );
+        assert!(resolver.resolve_addr("nas").is_err());
+    }
+
+    #[test]
+    fn reverse_names_are_parsed() {
+        assert_eq!(
+            parse_reverse_name("1.2.0.192.IN-ADDR.ARPA"),
+            Some(Ipv4Addr::new(192, 0, 2, 1))
+        );
+        assert_eq!(parse_reverse_name(&reverse_name(ADDR)), Some(ADDR));
+        assert_eq!(parse_reverse_name("2.0.192.in-addr.arpa"), None);
+        assert_eq!(parse_reverse_name("example.com"), None);
+    }
+}
@@ -14,53 +14,141 @@
 use crate::handlers;
 use crate::stack::NetworkStack;
 use anyhow::Result;
-use protocol::ipv4::Ipv4Addr;
-use std::str::FromStr;
 use std::sync::Arc;
 use std::thread;
 use std::time::{Duration, Instant};
 
+// 输入读完后，输出静默这么久才退出
+const DRAIN_QUIET: Duration = Duration::from_millis(200);
+// 排空最多等待的时间，比等待地址解析的超时 (3 秒) 长，让未解析的包按超时丢弃
+const DRAIN_TIMEOUT: Duration = Duration::from_secs(5);
+
 pub fn run(stack: Arc<NetworkStack>) -> Result<()> {
     println!("Entering event loop...");
 
     // 这里唯一占有该锁
-    let mut rx_cap = stack.get_rx_capture().lock().unwrap();
-    let mut last_cleanup = Instant::now();
+    let mut rx_dev = stack.get_rx_device().lock().unwrap();
+    let mut last_cleanup = stack.now();
 
     loop {
         // 1. 接收
-        match rx_cap.next_packet() {
-            Ok(packet) => stack.receive(packet.data),
-            Err(pcap::Error::TimeoutExpired) => {}
+        match rx_dev.receive() {
+            Ok(Some(frame)) => stack.receive(&frame),
+            Ok(None) => {}
             Err(e) => eprintln!("RX Error: {:?}", e),
         }
 
+        // 离线回放读完输入后，把 socket 中剩余的数据发出去再退出
+        if rx_dev.is_exhausted() {
+            drain(&stack);
+            println!("Input exhausted, leaving event loop.");
+            return Ok(());
+        }
+
         // 2. 发送
         stack.poll_and_send();
+        handlers::igmp::poll(&stack);
 
         // 3. 清理
+        let now = stack.now();
+        if now.duration_since(last_cleanup) > Duration::from_secs(1) {
+            stack.cleanup_pending_packets();
+            stack.cleanup_fragments();
+            stack.cleanup_ip_ids();
+            last_cleanup = now;
+        }
+    }
+}
+
+// 应用线程可能还没处理完最后几个包，持续发送直到输出静默一段时间
+// 等待的是真实线程，这里用系统时钟而不是协议栈时钟
+fn drain(stack: &NetworkStack) {
+    let deadline = Instant::now() + DRAIN_TIMEOUT;
+    let mut last_activity = Instant::now();
+    let mut last_cleanup = Instant::now();
+
+    while Instant::now() < deadline {
+        if stack.has_pending_output() {
+            last_activity = Instant::now();
+        } else if last_activity.elapsed() >= DRAIN_QUIET {
+            return;
+        }
+
+        stack.poll_and_send();
+        handlers::igmp::poll(stack);
+
         if last_cleanup.elapsed() > Duration::from_secs(1) {
             stack.cleanup_pending_packets();
+            stack.cleanup_fragments();
             last_cleanup = Instant::now();
         }
+        thread::sleep(Duration::from_millis(1));
     }
+    eprintln!("Drain timed out, some output may be lost");
 }
 
-pub fn ping(target_ip_str: &str, stack: &Arc<NetworkStack>) -> Result<()> {
-    let target_ip = Ipv4Addr::from_str(target_ip_str)
-        .map_err(|e| anyhow::anyhow!("Invalid target IP: {}", e))?;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device::replay::read_frames;
+    use crate::stack::{self, StackConfig};
+    use crate::transport::udp::UdpSocket;
+    use protocol::ipv4::Ipv4Addr;
+    use protocol::mac::MacAddr;
+    use std::path::PathBuf;
 
-    println!("Starting Ping to {}", target_ip);
+    fn testdata(name: &str) -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("testdata/replay")
+            .join(name)
+    }
 
-    let stack_clone = stack.clone();
-    thread::spawn(move || {
-        let mut seq = 1;
-        loop {
-            println!("Sending ICMP Request seq={} to {}", seq, target_ip);
-            handlers::icmp::send_icmp_request(&stack_clone, target_ip, seq);
-            seq += 1;
-            thread::sleep(Duration::from_secs(1));
-        }
-    });
-    Ok(())
+    // Identification 的初值取自时钟，比较前把它和 IPv4 首部校验和一起清零
+    fn normalize(frames: Vec<Vec<u8>>) -> Vec<Vec<u8>> {
+        frames
+            .into_iter()
+            .map(|mut frame| {
+                if frame.len() >= 34 && frame[12..14] == [0x08, 0x00] {
+                    frame[18..20].fill(0);
+                    frame[24..26].fill(0);
+                }
+                frame
+            })
+            .collect()
+    }
+
+    // echo.in.pcap: 对端 10.0.0.1 发来 ARP 请求、ICMP Echo 请求，最后是发往 UDP 7 端口的数据报
+    // UDP 回显由应用线程在输入读完之后才发出，事件循环必须先排空再退出
+    #[test]
+    fn replay_matches_golden_capture() {
+        let output = std::env::temp_dir().join(format!("replay-{}.pcap", std::process::id()));
+        let mut config = StackConfig::new(
+            MacAddr::from_raw([0x02, 0, 0, 0, 0, 0x02]),
+            Ipv4Addr::new(10, 0, 0, 2),
+        );
+        config.netmask = Ipv4Addr::new(255, 255, 255, 0);
+        let stack = stack::initialize_replay(&testdata("echo.in.pcap"), &output, config).unwrap();
+
+        let socket = UdpSocket::bind(stack.clone(), "0.0.0.0:7").unwrap();
+        let echo = thread::spawn(move || {
+            let deadline = Instant::now() + Duration::from_secs(5);
+            while Instant::now() < deadline {
+                if let Ok((data, src)) = socket.recv_from() {
+                    socket.send_to(&data, &src).unwrap();
+                    // socket 关闭时未发送的数据随之丢弃，交给调用方在事件循环退出后关闭
+                    return socket;
+                }
+                thread::sleep(Duration::from_millis(1));
+            }
+            panic!("echo server received nothing");
+        });
+
+        run(stack).unwrap();
+        drop(echo.join().unwrap());
+
+        let frames = read_frames(&output).unwrap();
+        std::fs::remove_file(&output).unwrap();
+        let golden = read_frames(&testdata("echo.out.pcap")).unwrap();
+        assert_eq!(normalize(frames), normalize(golden));
+    }
 }
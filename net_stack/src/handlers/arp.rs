@@ -15,7 +15,7 @@ use crate::handlers;
 use crate::stack::NetworkStack;
 use protocol::{
     arp::{ArpOperation, ArpPacket},
-    ethernet::{EtherType, EthernetHeader},
+    ethernet::EtherType,
     ipv4::Ipv4Addr,
     mac::MacAddr,
 };
@@ -34,7 +34,7 @@ pub fn handle(stack: &NetworkStack, payload: &[u8]) {
     {
         // 这里使用 unwrap，是因为如果锁被 poison，说明程序已经处于不一致状态，应该 panic 而不是继续执行
         let mut arp_table = stack.arp_table().lock().unwrap();
-        arp_table.insert(packet.sender_ip, packet.sender_mac);
+        arp_table.insert(packet.sender_ip, packet.sender_mac, stack.now());
         println!(
             "学习到 ARP 映射: {} -> {}",
             packet.sender_ip, packet.sender_mac
@@ -51,27 +51,23 @@ pub fn handle(stack: &NetworkStack, payload: &[u8]) {
                 packet.sender_ip
             );
             for pkt in packets {
-                handlers::ipv4::send_packet_with_mac(
+                handlers::ipv4::send_datagram_with_mac(
                     stack,
                     packet.sender_mac, // 现在我们知道 MAC 了
-                    pkt.dst_ip,
-                    pkt.protocol,
-                    &pkt.payload,
+                    &pkt.datagram,
                 );
             }
         }
     }
 
     match ArpOperation::parse(packet.opcode) {
-        ArpOperation::Request => {
-            if packet.target_ip == stack.config().ip {
-                println!(
-                    "收到 ARP 请求: 谁是 {}? (来自 {})",
-                    packet.target_ip, packet.sender_ip
-                );
+        ArpOperation::Request if packet.target_ip == stack.config().ip => {
+            println!(
+                "收到 ARP 请求: 谁是 {}? (来自 {})",
+                packet.target_ip, packet.sender_ip
+            );
 
-                send_reply(stack, &packet);
-            }
+            send_reply(stack, &packet);
         }
         ArpOperation::Reply => {
             println!(
@@ -93,9 +89,9 @@ fn send_reply(stack: &NetworkStack, request: &ArpPacket) {
         request.sender_ip,  // Target IP (对方)
     );
 
-    let eth_header = EthernetHeader::new(stack.config().mac, request.sender_mac, EtherType::Arp);
+    let eth_header = stack.ethernet_header(request.sender_mac, EtherType::Arp);
 
-    let mut frame = eth_header.to_bytes().to_vec();
+    let mut frame = eth_header.to_bytes();
     frame.extend_from_slice(&reply_packet.to_bytes());
 
     // Padding to minimum Ethernet frame size (60 bytes + 4 CRC = 64 bytes)
@@ -123,11 +119,7 @@ pub fn send_request(stack: &NetworkStack, target_ip: Ipv4Addr) {
     let payload = request_packet.to_bytes();
 
     // 构造以太网帧（广播）
-    let eth_header = protocol::ethernet::EthernetHeader {
-        dst: MacAddr::broadcast(), // FF:FF:FF:FF:FF:FF
-        src: stack.config().mac,
-        ethertype: protocol::ethernet::EtherType::Arp,
-    };
+    let eth_header = stack.ethernet_header(MacAddr::broadcast(), EtherType::Arp); // FF:FF:FF:FF:FF:FF
 
     let mut frame = Vec::new();
     frame.extend_from_slice(&eth_header.to_bytes());
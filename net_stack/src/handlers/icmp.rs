@@ -11,68 +11,357 @@
 // the Free Software Foundation, either version 3 of the License, or
 // (at your option) any later version.
 
-use protocol::icmp::{ICMP, IcmpType};
-use protocol::ipv4::{Ipv4Addr, Ipv4Protocol};
+use std::sync::atomic::{AtomicU16, Ordering};
+use std::sync::mpsc::{self, Receiver};
+use std::time::{Duration, Instant};
+
+use protocol::icmp::{self, DestUnreachableCode, IcmpMessage, TimeExceededCode, Timestamp};
+use protocol::ipv4::{Ipv4Addr, Ipv4Header, Ipv4Protocol};
 
 use crate::handlers::ipv4;
 use crate::stack::NetworkStack;
 
-pub fn handle(stack: &NetworkStack, src_ip: Ipv4Addr, payload: &[u8]) {
-    let packet = match ICMP::parse(payload) {
-        Ok(p) => p,
+/// 交给 ICMP 监听者的一个报文
+#[derive(Debug, Clone)]
+pub struct IcmpEvent {
+    pub src: Ipv4Addr,
+    /// 收到的 IP 数据报的 TTL
+    pub ttl: u8,
+    pub message: IcmpMessage,
+    pub received: Instant,
+}
+
+// ephemeral 分配标识的起点，每次分配后递增，同一进程内可以并发多个会话
+static NEXT_ID: AtomicU16 = AtomicU16::new(0);
+
+/// 监听者关心的报文：Echo 标识，或本机发出的 UDP 数据报的源端口。
+/// 后者只会收到引用了该 UDP 数据报的差错报文
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum IcmpListenerKey {
+    Echo(u16),
+    Udp(u16),
+}
+
+impl IcmpListenerKey {
+    fn id(self) -> u16 {
+        match self {
+            IcmpListenerKey::Echo(id) | IcmpListenerKey::Udp(id) => id,
+        }
+    }
+}
+
+/// 按 Echo 标识或 UDP 源端口接收 ICMP 报文，drop 时自动注销
+pub struct IcmpListener<'a> {
+    stack: &'a NetworkStack,
+    key: IcmpListenerKey,
+    rx: Receiver<IcmpEvent>,
+}
+
+impl<'a> IcmpListener<'a> {
+    /// 注册 Echo 标识为 id 的监听者，该标识已被占用时返回 None
+    pub fn new(stack: &'a NetworkStack, id: u16) -> Option<Self> {
+        Self::register(stack, IcmpListenerKey::Echo(id))
+    }
+
+    /// 注册监听者，key 已被占用时返回 None
+    pub fn register(stack: &'a NetworkStack, key: IcmpListenerKey) -> Option<Self> {
+        let mut listeners = stack.icmp_listeners().lock().unwrap();
+        if listeners.contains_key(&key) {
+            return None;
+        }
+        let (tx, rx) = mpsc::channel();
+        listeners.insert(key, tx);
+        Some(Self { stack, key, rx })
+    }
+
+    /// 以进程号为起点找一个空闲的标识注册，make 决定标识的种类
+    pub fn ephemeral(
+        stack: &'a NetworkStack,
+        make: impl Fn(u16) -> IcmpListenerKey,
+    ) -> Option<Self> {
+        let base = std::process::id() as u16;
+        (0..u16::MAX)
+            .map(|_| base.wrapping_add(NEXT_ID.fetch_add(1, Ordering::Relaxed)))
+            .find_map(|id| Self::register(stack, make(id)))
+    }
+
+    /// Echo 标识或 UDP 源端口
+    pub fn id(&self) -> u16 {
+        self.key.id()
+    }
+
+    /// 最多等待 timeout，超时返回 None
+    pub fn recv_timeout(&self, timeout: Duration) -> Option<IcmpEvent> {
+        self.rx.recv_timeout(timeout).ok()
+    }
+}
+
+impl Drop for IcmpListener<'_> {
+    fn drop(&mut self) {
+        self.stack
+            .icmp_listeners()
+            .lock()
+            .unwrap()
+            .remove(&self.key);
+    }
+}
+
+/// 把报文交给对应的监听者，没有监听者时返回 false
+fn notify_listener(stack: &NetworkStack, key: IcmpListenerKey, event: IcmpEvent) -> bool {
+    let listeners = stack.icmp_listeners().lock().unwrap();
+    match listeners.get(&key) {
+        Some(tx) => tx.send(event).is_ok(),
+        None => false,
+    }
+}
+
+/// 差错报文引用的原始数据报首部，以及其载荷的前 8 字节 (传输层首部)
+pub fn quoted_datagram(message: &IcmpMessage) -> Option<(Ipv4Header, &[u8])> {
+    let original = message.original()?;
+    let header = Ipv4Header::parse_quoted(original).ok()?;
+    if header.frag_offset != 0 {
+        return None;
+    }
+    let quoted = original.get(header.header_len()..header.header_len() + 8)?;
+    Some((header, quoted))
+}
+
+/// 根据差错报文引用的原始数据报找出它属于哪个监听者，只认本机发出的 Echo Request 和 UDP
+fn error_listener_key(stack: &NetworkStack, message: &IcmpMessage) -> Option<IcmpListenerKey> {
+    let (header, quoted) = quoted_datagram(message)?;
+    if header.src != stack.config().ip {
+        return None;
+    }
+    match header.get_protocol() {
+        Ipv4Protocol::ICMP if quoted[0] == icmp::ICMP_ECHO_REQUEST => {
+            Some(IcmpListenerKey::Echo(u16::from_be_bytes([
+                quoted[4], quoted[5],
+            ])))
+        }
+        Ipv4Protocol::UDP => Some(IcmpListenerKey::Udp(u16::from_be_bytes([
+            quoted[0], quoted[1],
+        ]))),
+        _ => None,
+    }
+}
+
+pub fn handle(stack: &NetworkStack, header: &Ipv4Header, payload: &[u8]) {
+    let src_ip = header.src;
+    let message = match IcmpMessage::parse(payload) {
+        Ok(m) => m,
         Err(e) => {
             eprintln!("Invalid ICMP: {:?}", e);
             return;
         }
     };
 
-    match IcmpType::parse(packet.header.type_) {
-        IcmpType::Request => {
+    // 发给本机某个 ping / traceroute 会话的回复，或者引用了其探测包的差错报文
+    let key = match &message {
+        IcmpMessage::EchoReply(echo) => Some(IcmpListenerKey::Echo(echo.id)),
+        m if m.is_error() => error_listener_key(stack, m),
+        _ => None,
+    };
+    if let Some(key) = key {
+        let event = IcmpEvent {
+            src: src_ip,
+            ttl: header.ttl,
+            message: message.clone(),
+            received: stack.now(),
+        };
+        if notify_listener(stack, key, event) {
+            return;
+        }
+    }
+
+    match &message {
+        // 与 Linux 默认的 icmp_echo_ignore_broadcasts 一致，不回复广播请求
+        IcmpMessage::EchoRequest(_) | IcmpMessage::Timestamp(_)
+            if stack.is_broadcast_addr(header.dst) => {}
+        IcmpMessage::EchoRequest(echo) => {
             println!("Received ICMP Request from {}", src_ip);
-            println!("{}", packet);
-            send_reply(stack, src_ip, &packet);
+            println!("{}", message);
+            // 载荷原样带回，兼容各种 ping 实现自定义的数据格式
+            let reply = IcmpMessage::EchoReply(echo.clone());
+            ipv4::send_packet(stack, src_ip, Ipv4Protocol::ICMP, &reply.to_bytes());
         }
-        IcmpType::Reply => {
+        IcmpMessage::EchoReply(_) => {
             println!("Received ICMP Reply from {}", src_ip);
-            println!("{}", packet);
+            println!("{}", message);
+        }
+        IcmpMessage::Timestamp(request) => {
+            println!("Received ICMP Timestamp Request from {}", src_ip);
+            let now = icmp::ms_since_midnight_ut();
+            let reply = IcmpMessage::TimestampReply(Timestamp {
+                receive: now,
+                transmit: now,
+                ..*request
+            });
+            ipv4::send_packet(stack, src_ip, Ipv4Protocol::ICMP, &reply.to_bytes());
+        }
+        IcmpMessage::TimestampReply(_) => {
+            println!("Received {} from {}", message, src_ip);
         }
-        IcmpType::Unknown => {
-            eprintln!("error get unknown icmp type: {}.", packet.header.type_);
+        IcmpMessage::Unknown { type_, .. } => {
+            eprintln!("error get unknown icmp type: {}.", type_);
+        }
+        _ => {
+            // 差错报文：打印被引用的原始数据报
+            match message.original().map(Ipv4Header::parse_quoted) {
+                Some(Ok(original)) => eprintln!(
+                    "Received {} from {} (original {} -> {})",
+                    message, src_ip, original.src, original.dst
+                ),
+                _ => eprintln!("Received {} from {}", message, src_ip),
+            }
         }
     }
 }
 
-fn send_reply(stack: &NetworkStack, dst_ip: Ipv4Addr, request: &ICMP) {
-    let reply_packet = ICMP::new(
-        IcmpType::Reply,
-        request.header.code,
-        request.header.id,
-        request.header.seq,
-        request.data.time,
-        &request.data.payload,
-    );
+/// ICMP 差错报文的令牌桶限速器，rate 为每秒允许的报文数，0 表示不限速
+pub struct IcmpRateLimiter {
+    rate: u32,
+    tokens: f64,
+    last: Instant,
+}
 
-    let payload = reply_packet.to_bytes();
-    ipv4::send_packet(stack, dst_ip, Ipv4Protocol::ICMP, &payload);
+impl IcmpRateLimiter {
+    pub fn new(rate: u32, now: Instant) -> Self {
+        Self {
+            rate,
+            tokens: rate as f64,
+            last: now,
+        }
+    }
+
+    /// 取一个令牌，桶空时返回 false
+    pub fn allow(&mut self, now: Instant) -> bool {
+        if self.rate == 0 {
+            return true;
+        }
+
+        // 按流逝时间补充令牌，桶容量为一秒的配额
+        let elapsed = now.saturating_duration_since(self.last).as_secs_f64();
+        self.last = now;
+        self.tokens = (self.tokens + elapsed * self.rate as f64).min(self.rate as f64);
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
 }
 
-pub fn send_icmp_request(stack: &NetworkStack, dst_ip: Ipv4Addr, seq: u16) {
-    let time = std::time::SystemTime::now()
-        .duration_since(std::time::UNIX_EPOCH)
-        .unwrap()
-        .as_millis() as u32;
+/// 回复 Destination Unreachable，mtu 只在 FragmentationNeeded 时使用
+pub fn send_dest_unreachable(
+    stack: &NetworkStack,
+    code: DestUnreachableCode,
+    mtu: u16,
+    header: &Ipv4Header,
+    datagram: &[u8],
+) {
+    if !stack.config().icmp_unreachable {
+        return;
+    }
+    send_error(stack, header, datagram, |quote| {
+        icmp::build_dest_unreachable(code, mtu, quote)
+    });
+}
+
+/// 回复 Time Exceeded
+pub fn send_time_exceeded(
+    stack: &NetworkStack,
+    code: TimeExceededCode,
+    header: &Ipv4Header,
+    datagram: &[u8],
+) {
+    send_error(stack, header, datagram, |quote| {
+        icmp::build_time_exceeded(code, quote)
+    });
+}
+
+/// 差错报文的公共逻辑：按 RFC 1122 3.2.2 过滤不该回复的数据报，限速后引用原始首部 + 8 字节发回源地址
+fn send_error(
+    stack: &NetworkStack,
+    header: &Ipv4Header,
+    datagram: &[u8],
+    build: impl FnOnce(&[u8]) -> Vec<u8>,
+) {
+    let header_len = header.header_len();
+
+    // 不为 ICMP 差错报文再生成差错报文，避免差错风暴
+    if header.get_protocol() == Ipv4Protocol::ICMP
+        && let Some(&icmp_type) = datagram.get(header_len)
+        && icmp::is_error_type(icmp_type)
+    {
+        return;
+    }
+
+    // 只针对首个分片；广播、组播目的地址，以及无法作为单播回复的源地址都不回复
+    if header.frag_offset != 0
+        || stack.is_broadcast_addr(header.dst)
+        || header.dst.is_multicast()
+        || stack.is_broadcast_addr(header.src)
+        || header.src.is_multicast()
+        || header.src == Ipv4Addr::unspecified()
+    {
+        return;
+    }
+
+    if !stack.icmp_limiter().lock().unwrap().allow(stack.now()) {
+        return;
+    }
+
+    let quote_len = datagram.len().min(header_len + 8);
+    let message = build(&datagram[..quote_len]);
+    ipv4::send_packet(stack, header.src, Ipv4Protocol::ICMP, &message);
+}
 
-    let payload_data = vec![0u8; 32]; // 32 bytes payload
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    let request_packet = ICMP::new(
-        IcmpType::Request,
-        0,    // code
-        1234, // id
-        seq,
-        time,
-        &payload_data,
-    );
+    #[test]
+    fn quoted_datagram_accepts_rewritten_header() {
+        // 路由器引用的首部 TTL 已减到 0，校验和也未更新
+        let mut original = Ipv4Header::new(
+            Ipv4Addr::new(10, 0, 0, 1),
+            Ipv4Addr::new(8, 8, 8, 8),
+            17,
+            8,
+            1,
+        )
+        .to_bytes();
+        original[8] = 0;
+        original.extend_from_slice(&[0x82, 0x9b, 0x82, 0x9b, 0x00, 0x08, 0x00, 0x00]);
+        let message = IcmpMessage::TimeExceeded {
+            code: TimeExceededCode::TtlExceeded,
+            original,
+        };
+
+        let (header, quoted) = quoted_datagram(&message).unwrap();
+        assert_eq!(header.src, Ipv4Addr::new(10, 0, 0, 1));
+        assert_eq!(header.get_protocol(), Ipv4Protocol::UDP);
+        assert_eq!(&quoted[..2], &[0x82, 0x9b]);
+    }
 
-    let payload = request_packet.to_bytes();
-    ipv4::send_packet(stack, dst_ip, Ipv4Protocol::ICMP, &payload);
+    #[test]
+    fn rate_limiter_allows_a_burst_then_refills() {
+        let start = Instant::now();
+        let mut limiter = IcmpRateLimiter::new(10, start);
+        for _ in 0..10 {
+            assert!(limiter.allow(start));
+        }
+        assert!(!limiter.allow(start));
+
+        // 每 100ms 补充一个令牌，桶容量不超过一秒的配额
+        assert!(limiter.allow(start + Duration::from_millis(100)));
+        assert!(!limiter.allow(start + Duration::from_millis(150)));
+        let later = start + Duration::from_secs(60);
+        assert_eq!((0..20).filter(|_| limiter.allow(later)).count(), 10);
+
+        let mut unlimited = IcmpRateLimiter::new(0, start);
+        assert!((0..1000).all(|_| unlimited.allow(start)));
+    }
 }
@@ -0,0 +1,450 @@
+// Copyright (C) 2025 rrrrrzy
+// SPDX-License-Identifier: GPL-3.0-or-later
+//
+// --------------------------------------------------
+// 致敬所有在深夜调试代码的灵魂。
+// 即便 Bug 如山，我亦往矣。
+// --------------------------------------------------
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+use protocol::icmpv6::{
+    self, Icmpv6DestUnreachableCode, Icmpv6Message, Icmpv6ParameterProblemCode, NDP_HOP_LIMIT,
+    NdpOption,
+};
+use protocol::ipv6::{
+    IPV6_HEADER_LEN, IPV6_MIN_MTU, IPV6_NEXT_ICMPV6, Ipv6Addr, Ipv6Header, Ipv6OptionAction,
+};
+use protocol::mac::MacAddr;
+
+use crate::handlers::ipv6::{self, SendOptions};
+use crate::stack::NetworkStack;
+
+/// 交给 SLAAC 客户端的邻居发现报文：路由器通告，以及与检测中地址冲突的请求和通告
+#[derive(Debug, Clone)]
+pub struct NdpEvent {
+    pub src: Ipv6Addr,
+    pub dst: Ipv6Addr,
+    pub message: Icmpv6Message,
+}
+
+// packet 是完整的 IPv6 报文，差错报文需要引用它
+pub fn handle(stack: &NetworkStack, header: &Ipv6Header, packet: &[u8]) {
+    let src_ip = header.src;
+    let payload = &packet[header.header_len()..];
+    let message = match Icmpv6Message::parse(payload, src_ip, header.dst) {
+        Ok(m) => m,
+        Err(e) => {
+            eprintln!("Invalid ICMPv6 from {}: {}", src_ip, e);
+            return;
+        }
+    };
+
+    match &message {
+        Icmpv6Message::EchoRequest(echo) => {
+            println!("Received ICMPv6 Request from {}", src_ip);
+            println!("{}", message);
+            // 与 Linux 默认的 echo_ignore_multicast = 0 一致，组播请求也回复，源地址另选单播地址
+            let reply = Icmpv6Message::EchoReply(echo.clone());
+            let src = (!header.dst.is_multicast()).then_some(header.dst);
+            send_message(
+                stack,
+                src_ip,
+                &reply,
+                &SendOptions {
+                    src,
+                    ..Default::default()
+                },
+            );
+        }
+        Icmpv6Message::EchoReply(_) => {
+            println!("Received {} from {}", message, src_ip);
+        }
+        // 只接受本链路上发出的 NDP 报文，经过路由器转发的 Hop Limit 一定小于 255
+        Icmpv6Message::RouterSolicitation { .. }
+        | Icmpv6Message::RouterAdvertisement { .. }
+        | Icmpv6Message::NeighborSolicitation { .. }
+        | Icmpv6Message::NeighborAdvertisement { .. }
+            if header.hop_limit != NDP_HOP_LIMIT =>
+        {
+            eprintln!(
+                "Dropping {} from {} with hop limit {}",
+                message, src_ip, header.hop_limit
+            );
+        }
+        // 本机不是路由器
+        Icmpv6Message::RouterSolicitation { .. } => {}
+        Icmpv6Message::RouterAdvertisement { .. } => {
+            handle_router_advertisement(stack, header, message.clone());
+        }
+        // 检测中的地址还不属于本机：别人也在检测它时交给 SLAAC 客户端判断冲突，其余请求不回复
+        Icmpv6Message::NeighborSolicitation { target, .. } if stack.is_tentative_ipv6(*target) => {
+            if header.src.is_unspecified() {
+                notify_listener(stack, header, message.clone());
+            }
+        }
+        Icmpv6Message::NeighborAdvertisement { target, .. } if stack.is_tentative_ipv6(*target) => {
+            notify_listener(stack, header, message.clone());
+        }
+        Icmpv6Message::NeighborSolicitation { target, .. } => {
+            handle_solicitation(stack, header, &message, *target);
+        }
+        Icmpv6Message::NeighborAdvertisement { .. } => {
+            handle_advertisement(stack, header, &message);
+        }
+        Icmpv6Message::Unknown { type_, .. } => {
+            eprintln!("error get unknown icmpv6 type: {}.", type_);
+        }
+        _ => {
+            // 差错报文：打印被引用的原始报文
+            match message.original().map(Ipv6Header::parse) {
+                Some(Ok(original)) => eprintln!(
+                    "Received {} from {} (original {} -> {})",
+                    message, src_ip, original.src, original.dst
+                ),
+                _ => eprintln!("Received {} from {}", message, src_ip),
+            }
+        }
+    }
+}
+
+/// 路由器通告 (RFC 4861 6.1.2, 6.3.4)：记下路由器的 MAC，前缀和默认路由交给 SLAAC 客户端处理
+fn handle_router_advertisement(stack: &NetworkStack, header: &Ipv6Header, message: Icmpv6Message) {
+    // 路由器必须用链路本地地址发送通告
+    if !header.src.is_unicast_link_local() {
+        eprintln!("Dropping {} from non link-local {}", message, header.src);
+        return;
+    }
+    println!("Received {} from {}", message, header.src);
+
+    if let Icmpv6Message::RouterAdvertisement {
+        router_lifetime, ..
+    } = message
+    {
+        let mut neighbor_cache = stack.neighbor_cache().lock().unwrap();
+        if let Some(mac) = message.source_link_layer_addr()
+            && neighbor_cache.lookup(header.src, stack.now()) != Some(mac)
+        {
+            neighbor_cache.insert_stale(header.src, mac, stack.now());
+        }
+        neighbor_cache.set_router(header.src, router_lifetime > 0);
+    }
+    if let Some(mac) = message.source_link_layer_addr() {
+        ipv6::flush_pending(stack, header.src, mac);
+    }
+
+    notify_listener(stack, header, message);
+}
+
+/// 没有注册 SLAAC 客户端时直接丢弃
+fn notify_listener(stack: &NetworkStack, header: &Ipv6Header, message: Icmpv6Message) {
+    let mut listener = stack.ndp_listener().lock().unwrap();
+    let event = NdpEvent {
+        src: header.src,
+        dst: header.dst,
+        message,
+    };
+    // 客户端已经退出时注销
+    if let Some(tx) = listener.as_ref()
+        && tx.send(event).is_err()
+    {
+        *listener = None;
+    }
+}
+
+/// 邻居请求 (RFC 4861 7.2.3)：目标是本机地址时回复邻居通告，同时记下请求者的 MAC
+fn handle_solicitation(
+    stack: &NetworkStack,
+    header: &Ipv6Header,
+    message: &Icmpv6Message,
+    target: Ipv6Addr,
+) {
+    // 不做代理，只回答本机地址
+    if !stack.is_local_ipv6(target) {
+        return;
+    }
+
+    let source_mac = message.source_link_layer_addr();
+    if header.src.is_unspecified() {
+        // 对方在做重复地址检测，不能带源链路层地址，回复发给所有节点 (RFC 4861 7.2.4)
+        if source_mac.is_none() && header.dst.is_multicast() {
+            eprintln!("{} is already used by this stack", target);
+            send_advertisement(stack, Ipv6Addr::all_nodes(), target, false);
+        }
+        return;
+    }
+
+    println!("收到邻居请求: 谁是 {}? (来自 {})", target, header.src);
+    if let Some(mac) = source_mac {
+        stack
+            .neighbor_cache()
+            .lock()
+            .unwrap()
+            .insert_stale(header.src, mac, stack.now());
+        ipv6::flush_pending(stack, header.src, mac);
+    } else if header.dst.is_multicast() {
+        // 发给组播地址的请求必须带源链路层地址 (RFC 4861 7.1.1)
+        return;
+    }
+
+    send_advertisement(stack, header.src, target, true);
+}
+
+/// 邻居通告 (RFC 4861 7.2.5 的简化)：
+/// 被请求的通告确认邻居可达，其余的只在允许覆盖或还没有缓存时记为 Stale
+fn handle_advertisement(stack: &NetworkStack, header: &Ipv6Header, message: &Icmpv6Message) {
+    let Icmpv6Message::NeighborAdvertisement {
+        router,
+        solicited,
+        override_,
+        target,
+        ..
+    } = *message
+    else {
+        return;
+    };
+    if target.is_multicast() || (solicited && header.dst.is_multicast()) {
+        return;
+    }
+    if stack.is_local_ipv6(target) {
+        eprintln!(
+            "{} from {} claims an address of this stack",
+            message, header.src
+        );
+        return;
+    }
+
+    let mac = {
+        let mut neighbor_cache = stack.neighbor_cache().lock().unwrap();
+        let known = neighbor_cache.lookup(target, stack.now());
+        // 不带目标链路层地址的通告只能确认已缓存的地址
+        let Some(mac) = message.target_link_layer_addr().or(known) else {
+            return;
+        };
+        if solicited && (override_ || known.is_none() || known == Some(mac)) {
+            neighbor_cache.confirm(target, mac, stack.now());
+        } else if override_ || known.is_none() {
+            neighbor_cache.insert_stale(target, mac, stack.now());
+        } else {
+            return;
+        }
+        neighbor_cache.set_router(target, router);
+        mac
+    };
+    println!("学习到邻居映射: {} -> {}", target, mac);
+
+    ipv6::flush_pending(stack, target, mac);
+}
+
+/// 回复邻居通告，总是带上目标链路层地址并设置 O 位
+fn send_advertisement(stack: &NetworkStack, dst_ip: Ipv6Addr, target: Ipv6Addr, solicited: bool) {
+    let advertisement = Icmpv6Message::NeighborAdvertisement {
+        router: false,
+        solicited,
+        override_: true,
+        target,
+        options: vec![NdpOption::TargetLinkLayerAddr(stack.config().mac)],
+    };
+    send_message(
+        stack,
+        dst_ip,
+        &advertisement,
+        &SendOptions {
+            hop_limit: NDP_HOP_LIMIT,
+            src: Some(target),
+        },
+    );
+}
+
+/// 发送邻居请求：地址解析发往目标的请求节点组播地址，
+/// 给出 neighbor_mac 时是可达性探测，直接单播给该邻居 (RFC 4861 7.2.2, 7.3.3)
+pub fn send_neighbor_solicitation(
+    stack: &NetworkStack,
+    target: Ipv6Addr,
+    neighbor_mac: Option<MacAddr>,
+) {
+    let Some(src_ip) = ipv6::source_address(stack, target) else {
+        return;
+    };
+    let (dst_ip, dst_mac) = match neighbor_mac {
+        Some(mac) => (target, mac),
+        None => (
+            target.solicited_node(),
+            MacAddr::from_ipv6_multicast(target.solicited_node()),
+        ),
+    };
+
+    let solicitation = Icmpv6Message::NeighborSolicitation {
+        target,
+        options: vec![NdpOption::SourceLinkLayerAddr(stack.config().mac)],
+    };
+    let payload = solicitation.to_bytes(src_ip, dst_ip);
+    if let Some(packet) =
+        ipv6::build_packet(src_ip, dst_ip, IPV6_NEXT_ICMPV6, &payload, NDP_HOP_LIMIT)
+    {
+        ipv6::send_datagram_with_mac(stack, dst_mac, &packet);
+        println!("已发送邻居请求: 谁是 {}?", target);
+    }
+}
+
+/// 重复地址检测 (RFC 4862 5.4.2)：从未指定地址发往检测地址的请求节点组，
+/// 带上随机数以便识别环回的请求 (RFC 7527)
+pub fn send_dad_solicitation(stack: &NetworkStack, target: Ipv6Addr, nonce: &[u8]) {
+    let src_ip = Ipv6Addr::unspecified();
+    let dst_ip = target.solicited_node();
+    let solicitation = Icmpv6Message::NeighborSolicitation {
+        target,
+        options: vec![NdpOption::Nonce(nonce.to_vec())],
+    };
+    let payload = solicitation.to_bytes(src_ip, dst_ip);
+    if let Some(packet) =
+        ipv6::build_packet(src_ip, dst_ip, IPV6_NEXT_ICMPV6, &payload, NDP_HOP_LIMIT)
+    {
+        ipv6::send_datagram_with_mac(stack, MacAddr::from_ipv6_multicast(dst_ip), &packet);
+        println!("已发送重复地址检测请求: {}", target);
+    }
+}
+
+/// 发送路由器请求：有链路本地地址时用它作源地址并带上源链路层地址，否则从未指定地址发送 (RFC 4861 6.3.7)
+pub fn send_router_solicitation(stack: &NetworkStack) {
+    let dst_ip = Ipv6Addr::all_routers();
+    let src_ip = stack
+        .config()
+        .ipv6_addrs
+        .iter()
+        .map(|cidr| cidr.addr)
+        .find(|addr| addr.is_unicast_link_local())
+        .unwrap_or(Ipv6Addr::unspecified());
+    let options = if src_ip.is_unspecified() {
+        Vec::new()
+    } else {
+        vec![NdpOption::SourceLinkLayerAddr(stack.config().mac)]
+    };
+    let payload = Icmpv6Message::RouterSolicitation { options }.to_bytes(src_ip, dst_ip);
+    if let Some(packet) =
+        ipv6::build_packet(src_ip, dst_ip, IPV6_NEXT_ICMPV6, &payload, NDP_HOP_LIMIT)
+    {
+        ipv6::send_datagram_with_mac(stack, MacAddr::from_ipv6_multicast(dst_ip), &packet);
+        println!("已发送路由器请求");
+    }
+}
+
+/// 以 options 中的源地址 (或按目的地址选出的源地址) 计算校验和并发送
+pub fn send_message(
+    stack: &NetworkStack,
+    dst_ip: Ipv6Addr,
+    message: &Icmpv6Message,
+    options: &SendOptions,
+) {
+    let Some(src_ip) = options.src.or_else(|| ipv6::source_address(stack, dst_ip)) else {
+        eprintln!("No IPv6 source address for {}, dropping packet", dst_ip);
+        return;
+    };
+    ipv6::send_packet_with_options(
+        stack,
+        dst_ip,
+        IPV6_NEXT_ICMPV6,
+        &message.to_bytes(src_ip, dst_ip),
+        &SendOptions {
+            src: Some(src_ip),
+            ..*options
+        },
+    );
+}
+
+/// 回复 Destination Unreachable
+pub fn send_dest_unreachable(
+    stack: &NetworkStack,
+    code: Icmpv6DestUnreachableCode,
+    header: &Ipv6Header,
+    packet: &[u8],
+) {
+    if !stack.config().icmp_unreachable {
+        return;
+    }
+    send_error(stack, header, packet, false, |original| {
+        Icmpv6Message::DestUnreachable { code, original }
+    });
+}
+
+/// 回复 Parameter Problem，pointer 为出错字段在原始报文中的偏移
+pub fn send_parameter_problem(
+    stack: &NetworkStack,
+    code: Icmpv6ParameterProblemCode,
+    pointer: u32,
+    header: &Ipv6Header,
+    packet: &[u8],
+) {
+    send_error(stack, header, packet, false, |original| {
+        Icmpv6Message::ParameterProblem {
+            code,
+            pointer,
+            original,
+        }
+    });
+}
+
+/// 不认识的逐跳选项或目的选项 (RFC 8200 4.2)，pointer 指向该选项的类型字节
+pub fn send_unrecognized_option(
+    stack: &NetworkStack,
+    action: Ipv6OptionAction,
+    pointer: u32,
+    header: &Ipv6Header,
+    packet: &[u8],
+) {
+    // 类型最高两位为 10 时，目的地址是组播也要回复 (RFC 4443 2.4 (e.3))
+    let report_multicast = action == Ipv6OptionAction::DiscardAndReport;
+    send_error(stack, header, packet, report_multicast, |original| {
+        Icmpv6Message::ParameterProblem {
+            code: Icmpv6ParameterProblemCode::UnrecognizedOption,
+            pointer,
+            original,
+        }
+    });
+}
+
+/// 差错报文的公共逻辑：按 RFC 4443 2.4 过滤不该回复的报文，限速后引用原始报文发回源地址
+fn send_error(
+    stack: &NetworkStack,
+    header: &Ipv6Header,
+    packet: &[u8],
+    report_multicast: bool,
+    build: impl FnOnce(Vec<u8>) -> Icmpv6Message,
+) {
+    // 不为 ICMPv6 差错报文再生成差错报文
+    if header.protocol == IPV6_NEXT_ICMPV6
+        && let Some(&icmp_type) = packet.get(header.header_len())
+        && icmpv6::is_error_type(icmp_type)
+    {
+        return;
+    }
+
+    // 组播目的地址 (除个别例外)，以及未指定、组播源地址都不回复
+    if (header.dst.is_multicast() && !report_multicast)
+        || header.src.is_unspecified()
+        || header.src.is_multicast()
+    {
+        return;
+    }
+
+    if !stack.icmp_limiter().lock().unwrap().allow(stack.now()) {
+        return;
+    }
+
+    // 整个差错报文不超过 IPv6 最小 MTU，原始报文能放多少放多少
+    let quote_len = packet.len().min(IPV6_MIN_MTU - IPV6_HEADER_LEN - 8);
+    let message = build(packet[..quote_len].to_vec());
+    send_message(
+        stack,
+        header.src,
+        &message,
+        &SendOptions {
+            // 发给组播地址的报文另选单播源地址
+            src: (!header.dst.is_multicast()).then_some(header.dst),
+            ..Default::default()
+        },
+    );
+}
@@ -0,0 +1,435 @@
+// Copyright (C) 2025 rrrrrzy
+// SPDX-License-Identifier: GPL-3.0-or-later
+//
+// --------------------------------------------------
+// 致敬所有在深夜调试代码的灵魂。
+// 即便 Bug 如山，我亦往矣。
+// --------------------------------------------------
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+//! 主机侧的 IGMP (RFC 2236 / RFC 3376)：加入和离开组时主动报告，按查询延迟应答，听到旧版本查询者时降级
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use protocol::igmp::{
+    GroupRecord, GroupRecordType, IGMP_ALL_ROUTERS, IGMP_ALL_SYSTEMS, IGMPV3_ROUTERS, IgmpMessage,
+};
+use protocol::ipv4::{Ipv4Addr, Ipv4Header, Ipv4Protocol};
+use protocol::mac::MacAddr;
+
+use crate::handlers::ipv4::{self, SendOptions};
+use crate::stack::NetworkStack;
+
+/// 默认的健壮性变量，主动报告发送这么多次
+const ROBUSTNESS: u8 = 2;
+/// 旧版本查询者消失的超时：健壮性变量 * 查询间隔 125 秒 + 查询响应间隔 10 秒 (RFC 3376 8.12)
+const OLDER_VERSION_QUERIER_TIMEOUT: Duration = Duration::from_secs(260);
+/// v1 / v2 主动报告的重传间隔 (RFC 2236 8.10)
+const V2_UNSOLICITED_REPORT_INTERVAL: Duration = Duration::from_secs(10);
+/// v3 状态变化报告的重传间隔 (RFC 3376 8.11)
+const V3_UNSOLICITED_REPORT_INTERVAL: Duration = Duration::from_secs(1);
+/// 一个 v3 报告中组记录的总长度上限，加上 IP 首部 (含 Router Alert) 和报告首部不超过 1500 字节
+const V3_MAX_RECORDS_LEN: usize = 1440;
+
+/// 加入或离开后还要重传的状态变化报告
+struct StateChange {
+    joined: bool,
+    remaining: u8,
+    at: Instant,
+}
+
+/// 已加入的组：回应查询的定时器，以及 v2 下最后一次报告是否由本机发出
+struct GroupTimer {
+    report_at: Option<Instant>,
+    /// 待应答的 v3 特定组和源查询累积的源，为空时按特定组查询应答 (RFC 3376 5.2)
+    query_sources: Vec<Ipv4Addr>,
+    last_reporter: bool,
+}
+
+pub struct IgmpState {
+    /// 配置的最高版本，1-3
+    version: u8,
+    v1_querier_until: Option<Instant>,
+    v2_querier_until: Option<Instant>,
+    groups: HashMap<Ipv4Addr, GroupTimer>,
+    changes: HashMap<Ipv4Addr, StateChange>,
+    /// v3 通用查询的应答时间
+    general_report_at: Option<Instant>,
+    seed: u64,
+}
+
+impl IgmpState {
+    pub fn new(version: u8, mac: MacAddr) -> Self {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos() as u64;
+        let mut mac_bytes = [0u8; 8];
+        mac_bytes[2..].copy_from_slice(mac.as_bytes());
+        Self {
+            version,
+            v1_querier_until: None,
+            v2_querier_until: None,
+            groups: HashMap::new(),
+            changes: HashMap::new(),
+            general_report_at: None,
+            // xorshift 的种子不能为 0
+            seed: (nanos ^ u64::from_be_bytes(mac_bytes)) | 1,
+        }
+    }
+
+    /// 当前的兼容模式 (RFC 3376 7.2.1)：最近听到过旧版本查询者时按该版本收发
+    pub fn compat_version(&self, now: Instant) -> u8 {
+        if self.v1_querier_until.is_some_and(|until| until > now) {
+            1
+        } else if self.v2_querier_until.is_some_and(|until| until > now) {
+            self.version.min(2)
+        } else {
+            self.version
+        }
+    }
+
+    /// [0, max) 内的随机延迟
+    fn random_delay(&mut self, max: Duration) -> Duration {
+        self.seed ^= self.seed << 13;
+        self.seed ^= self.seed >> 7;
+        self.seed ^= self.seed << 17;
+        max.mul_f64((self.seed >> 11) as f64 / (1u64 << 53) as f64)
+    }
+
+    /// 安排应答，已有更早的定时器时保留原定时器 (RFC 2236 3)
+    fn schedule(at: &mut Option<Instant>, deadline: Instant) {
+        if at.is_none_or(|at| at > deadline) {
+            *at = Some(deadline);
+        }
+    }
+}
+
+fn unsolicited_report_interval(version: u8) -> Duration {
+    if version == 3 {
+        V3_UNSOLICITED_REPORT_INTERVAL
+    } else {
+        V2_UNSOLICITED_REPORT_INTERVAL
+    }
+}
+
+/// 第一次加入组时发送主动报告，之后由 poll 重传
+pub fn join_group(stack: &NetworkStack, group: Ipv4Addr) {
+    if group == IGMP_ALL_SYSTEMS {
+        return;
+    }
+    let version = {
+        let mut state = stack.igmp().lock().unwrap();
+        let now = stack.now();
+        let version = state.compat_version(now);
+        state.groups.insert(
+            group,
+            GroupTimer {
+                report_at: None,
+                query_sources: Vec::new(),
+                last_reporter: true,
+            },
+        );
+        let delay = state.random_delay(unsolicited_report_interval(version));
+        state.changes.insert(
+            group,
+            StateChange {
+                joined: true,
+                remaining: ROBUSTNESS - 1,
+                at: now + delay,
+            },
+        );
+        version
+    };
+    send_state_change(stack, version, group, true);
+}
+
+/// 最后一次离开组时通知路由器：v2 发送 Leave，v3 发送 TO_IN({})，v1 没有离开报文
+pub fn leave_group(stack: &NetworkStack, group: Ipv4Addr) {
+    if group == IGMP_ALL_SYSTEMS {
+        return;
+    }
+    let (version, last_reporter) = {
+        let mut state = stack.igmp().lock().unwrap();
+        let now = stack.now();
+        let version = state.compat_version(now);
+        let last_reporter = state
+            .groups
+            .remove(&group)
+            .is_some_and(|timer| timer.last_reporter);
+        state.changes.remove(&group);
+        if version == 3 {
+            let delay = state.random_delay(V3_UNSOLICITED_REPORT_INTERVAL);
+            state.changes.insert(
+                group,
+                StateChange {
+                    joined: false,
+                    remaining: ROBUSTNESS - 1,
+                    at: now + delay,
+                },
+            );
+        }
+        (version, last_reporter)
+    };
+    // v2 下其他主机的报告抑制了本机的报告时，由那台主机负责离开
+    if version != 2 || last_reporter {
+        send_state_change(stack, version, group, false);
+    }
+}
+
+pub fn handle(stack: &NetworkStack, header: &Ipv4Header, payload: &[u8]) {
+    let message = match IgmpMessage::parse(payload) {
+        Ok(message) => message,
+        Err(e) => {
+            eprintln!("Invalid IGMP message: {}", e);
+            return;
+        }
+    };
+
+    match &message {
+        IgmpMessage::Query { group, .. } => handle_query(stack, header, &message, *group),
+        IgmpMessage::V1Report { group } | IgmpMessage::V2Report { group } => {
+            // 抓包设备可能把本机发出的帧再收回来
+            if header.src == stack.config().ip {
+                return;
+            }
+            // 旧版本下其他成员已经报告过，本机取消应答 (报告抑制)
+            let mut state = stack.igmp().lock().unwrap();
+            if state.compat_version(stack.now()) < 3
+                && let Some(timer) = state.groups.get_mut(group)
+            {
+                timer.report_at = None;
+                timer.last_reporter = false;
+            }
+        }
+        // 离开报文和 v3 报告只有路由器关心
+        _ => {}
+    }
+}
+
+fn handle_query(stack: &NetworkStack, header: &Ipv4Header, message: &IgmpMessage, group: Ipv4Addr) {
+    let (Some(query_version), Some(max_resp)) = (message.query_version(), message.max_resp_time())
+    else {
+        return;
+    };
+    // v1 查询的组字段没有意义，一律视为通用查询
+    let group = if query_version == 1 {
+        Ipv4Addr::unspecified()
+    } else {
+        group
+    };
+    let general = group == Ipv4Addr::unspecified();
+    // 通用查询发往所有主机组，特定组查询发往该组
+    if header.dst != IGMP_ALL_SYSTEMS && (general || header.dst != group) {
+        return;
+    }
+    println!("Received {} from {}", message, header.src);
+
+    let mut state = stack.igmp().lock().unwrap();
+    let now = stack.now();
+    match query_version {
+        1 => state.v1_querier_until = Some(now + OLDER_VERSION_QUERIER_TIMEOUT),
+        2 => state.v2_querier_until = Some(now + OLDER_VERSION_QUERIER_TIMEOUT),
+        _ => {}
+    }
+
+    let version = state.compat_version(now);
+    if version == 3 && general {
+        // v3 用一个报告应答通用查询
+        let deadline = now + state.random_delay(max_resp);
+        IgmpState::schedule(&mut state.general_report_at, deadline);
+        return;
+    }
+
+    // 旧版本兼容模式下不理会 v3 查询中的源
+    let sources = match message {
+        IgmpMessage::Query { v3: Some(v3), .. } if version == 3 => v3.sources.clone(),
+        _ => Vec::new(),
+    };
+    let targets: Vec<Ipv4Addr> = if general {
+        state.groups.keys().copied().collect()
+    } else if state.groups.contains_key(&group) {
+        vec![group]
+    } else {
+        Vec::new()
+    };
+    for target in targets {
+        let deadline = now + state.random_delay(max_resp);
+        if let Some(timer) = state.groups.get_mut(&target) {
+            // 特定组查询优先于特定组和源查询，两个源列表的查询合并源 (RFC 3376 5.2)
+            if timer.report_at.is_none() {
+                timer.query_sources = sources.clone();
+            } else if sources.is_empty() || timer.query_sources.is_empty() {
+                timer.query_sources.clear();
+            } else {
+                for source in &sources {
+                    if !timer.query_sources.contains(source) {
+                        timer.query_sources.push(*source);
+                    }
+                }
+            }
+            IgmpState::schedule(&mut timer.report_at, deadline);
+        }
+    }
+}
+
+/// 发送到期的查询应答和状态变化报告的重传，由事件循环周期调用
+pub fn poll(stack: &NetworkStack) {
+    let now = stack.now();
+    let (version, changes, current, general) = {
+        let mut state = stack.igmp().lock().unwrap();
+        let version = state.compat_version(now);
+        let interval = unsolicited_report_interval(version);
+
+        let due: Vec<Ipv4Addr> = state
+            .changes
+            .iter()
+            .filter(|(_, change)| change.at <= now)
+            .map(|(group, _)| *group)
+            .collect();
+        let mut changes = Vec::new();
+        for group in due {
+            let delay = state.random_delay(interval);
+            let Some(change) = state.changes.get_mut(&group) else {
+                continue;
+            };
+            changes.push((group, change.joined));
+            if change.remaining <= 1 {
+                state.changes.remove(&group);
+            } else {
+                change.remaining -= 1;
+                change.at = now + delay;
+            }
+        }
+
+        let mut current = Vec::new();
+        for (group, timer) in state.groups.iter_mut() {
+            if timer.report_at.is_some_and(|at| at <= now) {
+                timer.report_at = None;
+                timer.last_reporter = true;
+                current.push((*group, std::mem::take(&mut timer.query_sources)));
+            }
+        }
+
+        let general = if state.general_report_at.is_some_and(|at| at <= now) {
+            state.general_report_at = None;
+            state.groups.keys().copied().collect()
+        } else {
+            Vec::new()
+        };
+        (version, changes, current, general)
+    };
+
+    for (group, joined) in changes {
+        send_state_change(stack, version, group, joined);
+    }
+    if version == 3 {
+        // 本机对每个组都是 EXCLUDE({})：特定组查询答 IS_EX({})，特定组和源查询答 IS_IN(查询的源)
+        let records = current
+            .into_iter()
+            .map(|(group, sources)| {
+                if sources.is_empty() {
+                    GroupRecord::any_source(GroupRecordType::ModeIsExclude, group)
+                } else {
+                    GroupRecord {
+                        record_type: GroupRecordType::ModeIsInclude,
+                        group,
+                        sources,
+                        aux_data: Vec::new(),
+                    }
+                }
+            })
+            .chain(
+                general
+                    .into_iter()
+                    .map(|group| GroupRecord::any_source(GroupRecordType::ModeIsExclude, group)),
+            )
+            .collect();
+        send_v3_records(stack, records);
+    } else {
+        for (group, _) in current {
+            send_report(stack, version, group);
+        }
+    }
+}
+
+/// 回应查询的当前状态报告，v3 下由调用方合并成一个报告
+fn send_report(stack: &NetworkStack, version: u8, group: Ipv4Addr) {
+    let message = if version == 1 {
+        IgmpMessage::V1Report { group }
+    } else {
+        IgmpMessage::V2Report { group }
+    };
+    send_message(stack, group, &message);
+}
+
+/// 加入或离开组时的主动报告
+fn send_state_change(stack: &NetworkStack, version: u8, group: Ipv4Addr, joined: bool) {
+    match (version, joined) {
+        (3, true) => send_v3_report(stack, GroupRecordType::ChangeToExclude, [group]),
+        (3, false) => send_v3_report(stack, GroupRecordType::ChangeToInclude, [group]),
+        (_, true) => send_report(stack, version, group),
+        (2, false) => send_message(stack, IGMP_ALL_ROUTERS, &IgmpMessage::Leave { group }),
+        _ => {}
+    }
+}
+
+/// 只接收任意源的组，记录都不带源地址
+fn send_v3_report(
+    stack: &NetworkStack,
+    record_type: GroupRecordType,
+    groups: impl IntoIterator<Item = Ipv4Addr>,
+) {
+    let records = groups
+        .into_iter()
+        .map(|group| GroupRecord::any_source(record_type, group))
+        .collect();
+    send_v3_records(stack, records);
+}
+
+/// 组记录太多时拆成几个报告发送
+fn send_v3_records(stack: &NetworkStack, records: Vec<GroupRecord>) {
+    let mut chunk = Vec::new();
+    let mut chunk_len = 0;
+    for record in records {
+        let len = 8 + 4 * record.sources.len() + record.aux_data.len();
+        if !chunk.is_empty() && chunk_len + len > V3_MAX_RECORDS_LEN {
+            let message = IgmpMessage::V3Report {
+                records: std::mem::take(&mut chunk),
+            };
+            send_message(stack, IGMPV3_ROUTERS, &message);
+            chunk_len = 0;
+        }
+        chunk.push(record);
+        chunk_len += len;
+    }
+    if !chunk.is_empty() {
+        send_message(
+            stack,
+            IGMPV3_ROUTERS,
+            &IgmpMessage::V3Report { records: chunk },
+        );
+    }
+}
+
+/// IGMP 报文只在本链路有效：TTL 为 1 并带 Router Alert 选项 (RFC 2236 2 / RFC 3376 4)
+fn send_message(stack: &NetworkStack, dst: Ipv4Addr, message: &IgmpMessage) {
+    println!("Sending {}", message);
+    ipv4::send_packet_with_mac(
+        stack,
+        MacAddr::from_ipv4_multicast(dst),
+        dst,
+        Ipv4Protocol::IGMP,
+        &message.to_bytes(),
+        &SendOptions {
+            ttl: 1,
+            dont_fragment: false,
+            router_alert: true,
+        },
+    );
+}
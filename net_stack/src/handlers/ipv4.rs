@@ -11,31 +11,27 @@
 // the Free Software Foundation, either version 3 of the License, or
 // (at your option) any later version.
 
-use std::time::Instant;
-
-use protocol::ethernet::{EtherType, EthernetHeader};
-use protocol::ipv4::{Ipv4Addr, Ipv4Header, Ipv4Protocol};
+use protocol::checksum::simple_checksum;
+use protocol::error::Ipv4FragmentError;
+use protocol::ethernet::EtherType;
+use protocol::icmp::{DestUnreachableCode, TimeExceededCode};
+use protocol::ipv4::{
+    IPV4_FLAG_DF, IPV4_FLAG_MF, Ipv4Addr, Ipv4Header, Ipv4Option, Ipv4Protocol, fragment_datagram,
+};
 use protocol::mac::MacAddr;
+use std::sync::Arc;
 
-use crate::handlers::udp;
+use crate::handlers::{igmp, tcp, udp};
 use crate::stack::PendingPacket;
 use crate::{handlers::icmp, stack::NetworkStack};
 
 pub fn handle(stack: &NetworkStack, payload: &[u8]) {
-    if payload.len() <= 20 {
-        eprintln!("error: IPv4 frame length less equal than 20");
+    if payload.len() < 20 {
+        eprintln!("error: IPv4 frame length less than 20");
         return;
     }
 
-    let header_bytes: &[u8; 20] = match payload[0..20].try_into() {
-        Ok(bytes) => bytes,
-        Err(_) => {
-            eprintln!("error: IPv4 frame length less equal than 20");
-            return;
-        }
-    };
-
-    let header = match Ipv4Header::parse(header_bytes) {
+    let header = match Ipv4Header::parse(payload) {
         Ok(p) => p,
         Err(e) => {
             eprintln!("Invalid IPv4 header: {}", e);
@@ -43,101 +39,402 @@ pub fn handle(stack: &NetworkStack, payload: &[u8]) {
         }
     };
 
-    if header.dst != stack.config().ip {
-        // drop or resend(router)
+    if payload.len() < header.total_len as usize {
+        // error length
+        return;
+    }
+
+    // 去掉以太网最小帧长带来的填充
+    let datagram = &payload[..header.total_len as usize];
+
+    // 广播也交给本机，DHCP 在获得地址之前只能靠受限广播收包；多播只收已加入的组
+    if header.dst != stack.config().ip
+        && !stack.is_broadcast_addr(header.dst)
+        && !stack.is_multicast_member(header.dst)
+    {
+        // 开启转发时充当路由器，否则丢弃
+        if stack.config().forwarding {
+            forward(stack, &header, datagram);
+        }
         return;
     }
 
-    if payload.len() < header.total_len as usize {
-        // error length
+    // 分片先交给重组器，凑齐后再按完整数据报分发
+    if header.flags & IPV4_FLAG_MF != 0 || header.frag_offset != 0 {
+        let reassembled = {
+            let mut reassembler = stack.reassembler().lock().unwrap();
+            reassembler.push(&header, datagram, stack.now())
+        };
+        // 重组后的首部来自首片，选项可能与当前分片不同，需要重新解析
+        if let Some(reassembled) = reassembled {
+            match Ipv4Header::parse(&reassembled) {
+                Ok(header) => deliver(stack, &header, &reassembled),
+                Err(e) => eprintln!("Invalid reassembled IPv4 datagram: {}", e),
+            }
+        }
         return;
     }
 
+    deliver(stack, &header, datagram);
+}
+
+/// 把发给本机的完整数据报交给上层协议
+fn deliver(stack: &NetworkStack, header: &Ipv4Header, datagram: &[u8]) {
+    let payload = &datagram[header.header_len()..];
     match header.get_protocol() {
         Ipv4Protocol::ICMP => {
-            icmp::handle(stack, header.src, &payload[20..]);
+            icmp::handle(stack, header, payload);
+        }
+        Ipv4Protocol::IGMP => {
+            igmp::handle(stack, header, payload);
         }
         Ipv4Protocol::TCP => {
-            // drop
+            tcp::handle(stack, header, datagram);
         }
         Ipv4Protocol::UDP => {
-            udp::handle(stack, header.src, header.dst, &payload[20..]);
+            udp::handle(stack, header, datagram);
         }
         Ipv4Protocol::Unknown => {
-            eprintln!("Unknown IPv4 Protocol: {}", header.protocol)
+            eprintln!("Unknown IPv4 Protocol: {}", header.protocol);
+            icmp::send_dest_unreachable(
+                stack,
+                DestUnreachableCode::ProtocolUnreachable,
+                0,
+                header,
+                datagram,
+            );
+        }
+    }
+}
+
+/// 转发一个目的地址不是本机的数据报
+fn forward(stack: &NetworkStack, header: &Ipv4Header, datagram: &[u8]) {
+    // 广播、组播、未指定地址以及自己发出的包都不转发
+    if header.dst.is_broadcast()
+        || header.dst.is_multicast()
+        || header.dst == Ipv4Addr::unspecified()
+        || header.src == stack.config().ip
+    {
+        return;
+    }
+
+    let route = {
+        let routing_table = stack.routing_table().lock().unwrap();
+        routing_table.lookup(header.dst).cloned()
+    };
+    // 没有路由时通知源主机网络不可达 (RFC 1812 5.2.7.1)，与其他差错报文共用限速
+    let Some(route) = route else {
+        println!("No route to {} for {}, dropping", header.dst, header.src);
+        icmp::send_dest_unreachable(
+            stack,
+            DestUnreachableCode::NetUnreachable,
+            0,
+            header,
+            datagram,
+        );
+        return;
+    };
+    let peer = route.iface.and_then(|iface| stack.interface(&iface));
+    if let Some(peer) = peer {
+        // 也不转发发往其他已关联网段的定向广播 (RFC 2644)
+        if peer.is_broadcast_addr(header.dst) {
+            return;
+        }
+        // 发给另一个接口自身地址的包由那个接口接收，与多地址主机一样
+        if header.dst == peer.config().ip {
+            handle(&peer, datagram);
+            return;
+        }
+    }
+
+    if header.ttl <= 1 {
+        println!("TTL exceeded for {} -> {}", header.src, header.dst);
+        icmp::send_time_exceeded(stack, TimeExceededCode::TtlExceeded, header, datagram);
+        return;
+    }
+
+    // 直接在原始字节上改 TTL 并重算校验和，保留首部选项
+    let header_len = header.header_len();
+    let mut packet = datagram.to_vec();
+    packet[8] = header.ttl - 1;
+    packet[10..12].copy_from_slice(&[0, 0]);
+    let checksum = simple_checksum(&packet[..header_len]);
+    packet[10..12].copy_from_slice(&checksum.to_be_bytes());
+
+    println!(
+        "Forwarding {} -> {} (ttl {})",
+        header.src,
+        header.dst,
+        header.ttl - 1
+    );
+    send_datagram(stack, header.dst, packet);
+}
+
+/// 本机发出的数据报的首部参数
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SendOptions {
+    pub ttl: u8,
+    /// 设置 DF 位，超过 MTU 时丢弃而不是分片
+    pub dont_fragment: bool,
+    /// 加上 Router Alert 选项，让路由器检查报文内容 (IGMP 需要)
+    pub router_alert: bool,
+}
+
+impl Default for SendOptions {
+    fn default() -> Self {
+        Self {
+            ttl: 64,
+            dont_fragment: false,
+            router_alert: false,
         }
     }
 }
 
 pub fn send_packet(stack: &NetworkStack, dst_ip: Ipv4Addr, protocol: Ipv4Protocol, payload: &[u8]) {
+    send_packet_with_options(stack, dst_ip, protocol, payload, &SendOptions::default());
+}
+
+pub fn send_packet_with_options(
+    stack: &NetworkStack,
+    dst_ip: Ipv4Addr,
+    protocol: Ipv4Protocol,
+    payload: &[u8],
+    options: &SendOptions,
+) {
+    if let Some(datagram) = build_datagram(stack, dst_ip, protocol, payload, options) {
+        send_datagram(stack, dst_ip, datagram);
+    }
+}
+
+/// 发送一个已经封装好的 IPv4 数据报：查路由、解析下一跳 MAC，然后发送
+pub fn send_datagram(stack: &NetworkStack, dst_ip: Ipv4Addr, datagram: Vec<u8>) {
+    // 受限广播和多播只在本链路发送，不查路由也不需要 ARP
+    if dst_ip.is_broadcast() {
+        send_datagram_to_mac(stack, MacAddr::broadcast(), dst_ip, &datagram);
+        return;
+    }
+    if dst_ip.is_multicast() {
+        send_datagram_to_mac(
+            stack,
+            MacAddr::from_ipv4_multicast(dst_ip),
+            dst_ip,
+            &datagram,
+        );
+        return;
+    }
+
+    // 0. 查路由表，确定下一跳 (直连时就是目的地址本身)
+    let Some((next_hop, peer)) = lookup_egress(stack, dst_ip) else {
+        eprintln!("No route to host {}, dropping packet", dst_ip);
+        return;
+    };
+    // 路由指向另一个已关联的接口时，由该接口的协议栈完成分片、ARP 和发送
+    let egress = peer.as_deref().unwrap_or(stack);
+
+    // 超过出接口 MTU 时分片
+    let datagrams = match fragment_datagram(&datagram, egress.mtu()) {
+        Ok(fragments) => fragments,
+        Err(Ipv4FragmentError::DontFragment) => {
+            eprintln!(
+                "Packet to {} exceeds MTU {} with DF set, dropping",
+                dst_ip,
+                egress.mtu()
+            );
+            // 转发的数据报通知源主机下一跳 MTU，供其做路径 MTU 发现 (RFC 1191)
+            if let Ok(header) = Ipv4Header::parse(&datagram)
+                && header.src != stack.config().ip
+            {
+                icmp::send_dest_unreachable(
+                    stack,
+                    DestUnreachableCode::FragmentationNeeded,
+                    egress.mtu().min(u16::MAX as usize) as u16,
+                    &header,
+                    &datagram,
+                );
+            }
+            return;
+        }
+        Err(e) => {
+            eprintln!("Failed to fragment packet to {}: {}", dst_ip, e);
+            return;
+        }
+    };
+
+    resolve_and_send(egress, next_hop, dst_ip, datagrams);
+}
+
+/// 查路由得到下一跳，以及路由指向的另一个已关联接口 (出接口就是本接口时为 None)
+fn lookup_egress(
+    stack: &NetworkStack,
+    dst_ip: Ipv4Addr,
+) -> Option<(Ipv4Addr, Option<Arc<NetworkStack>>)> {
+    let route = {
+        let routing_table = stack.routing_table().lock().unwrap();
+        routing_table.lookup(dst_ip).cloned()
+    }?;
+    let peer = route
+        .iface
+        .as_deref()
+        .and_then(|iface| stack.interface(iface));
+    Some((route.next_hop(dst_ip), peer))
+}
+
+/// 目的地址是否是广播：受限广播，本接口网段的定向广播，或路由出接口直连网段的定向广播
+pub fn is_broadcast_destination(stack: &NetworkStack, dst_ip: Ipv4Addr) -> bool {
+    if stack.is_broadcast_addr(dst_ip) {
+        return true;
+    }
+    match lookup_egress(stack, dst_ip) {
+        Some((next_hop, Some(peer))) => next_hop == dst_ip && peer.is_broadcast_addr(dst_ip),
+        _ => false,
+    }
+}
+
+/// 在出接口 egress 上解析下一跳并发送，定向广播和 ARP 都按出接口的网段判断
+fn resolve_and_send(
+    egress: &NetworkStack,
+    next_hop: Ipv4Addr,
+    dst_ip: Ipv4Addr,
+    datagrams: Vec<Vec<u8>>,
+) {
+    // 直连网段的定向广播发往广播 MAC
+    if next_hop == dst_ip && egress.is_broadcast_addr(dst_ip) {
+        for datagram in &datagrams {
+            send_datagram_with_mac(egress, MacAddr::broadcast(), datagram);
+        }
+        return;
+    }
+
     // 1. 查询 ARP 表
     let dst_mac_opt = {
         // 这里使用 unwrap，是因为如果锁被 poison，说明程序已经处于不一致状态，应该 panic 而不是继续执行
-        let arp_table = stack.arp_table().lock().unwrap();
-        arp_table.lookup(dst_ip)
+        let arp_table = egress.arp_table().lock().unwrap();
+        arp_table.lookup(next_hop, egress.now())
     };
 
     match dst_mac_opt {
         Some(dst_mac) => {
             // 情况A：ARP 表中有，直接发送
-            send_packet_with_mac(stack, dst_mac, dst_ip, protocol, payload);
+            for datagram in &datagrams {
+                send_datagram_with_mac(egress, dst_mac, datagram);
+            }
         }
         None => {
             // 情况B：ARP 表中没有，缓存包并触发 ARP 请求
-            println!("ARP 表中没有 {}，正在发送 ARP 请求...", dst_ip);
+            println!("ARP 表中没有 {}，正在发送 ARP 请求...", next_hop);
 
-            // 1. 将当前包加入待发送队列
+            // 1. 将当前包加入待发送队列 (按下一跳地址等待 ARP 解析)
             {
-                let mut pending = stack.pending_packets().lock().unwrap();
-                pending.entry(dst_ip).or_default().push_back(PendingPacket {
-                    dst_ip,
-                    protocol,
-                    payload: payload.to_vec(),
-                    timestamp: Instant::now(),
-                });
+                let mut pending = egress.pending_packets().lock().unwrap();
+                let queue = pending.entry(next_hop).or_default();
+                for datagram in datagrams {
+                    queue.push_back(PendingPacket {
+                        dst_ip,
+                        datagram,
+                        timestamp: egress.now(),
+                    });
+                }
             }
 
             // 2. 触发 ARP 请求
-            crate::handlers::arp::send_request(stack, dst_ip);
+            crate::handlers::arp::send_request(egress, next_hop);
         }
     }
 }
 
+/// 不查路由和 ARP，直接发给 dst_mac，TTL 和 DF 由 options 指定
 pub fn send_packet_with_mac(
     stack: &NetworkStack,
     dst_mac: MacAddr,
     dst_ip: Ipv4Addr,
     protocol: Ipv4Protocol,
     payload: &[u8],
+    options: &SendOptions,
 ) {
+    if let Some(datagram) = build_datagram(stack, dst_ip, protocol, payload, options) {
+        send_datagram_to_mac(stack, dst_mac, dst_ip, &datagram);
+    }
+}
+
+/// 按本接口的 MTU 分片后直接发给 dst_mac
+fn send_datagram_to_mac(stack: &NetworkStack, dst_mac: MacAddr, dst_ip: Ipv4Addr, datagram: &[u8]) {
+    match fragment_datagram(datagram, stack.mtu()) {
+        Ok(fragments) => {
+            for fragment in &fragments {
+                send_datagram_with_mac(stack, dst_mac, fragment);
+            }
+        }
+        Err(e) => eprintln!("Failed to fragment packet to {}: {}", dst_ip, e),
+    }
+}
+
+/// 以本机地址为源地址封装 IPv4 数据报，载荷过长无法装进一个数据报时返回 None
+fn build_datagram(
+    stack: &NetworkStack,
+    dst_ip: Ipv4Addr,
+    protocol: Ipv4Protocol,
+    payload: &[u8],
+    options: &SendOptions,
+) -> Option<Vec<u8>> {
+    // 值为 0 表示路由器应检查该数据报 (RFC 2113)
+    let ip_options = if options.router_alert {
+        vec![Ipv4Option::RouterAlert(0)]
+    } else {
+        Vec::new()
+    };
+    // 选项也占总长度，载荷上限要扣掉
+    let options_len = ip_options
+        .iter()
+        .map(Ipv4Option::len)
+        .sum::<usize>()
+        .next_multiple_of(4);
+    if payload.len() > u16::MAX as usize - 20 - options_len {
+        eprintln!(
+            "Payload of {} bytes does not fit in an IPv4 datagram, dropping",
+            payload.len()
+        );
+        return None;
+    }
+
     let src_ip = stack.config().ip;
-    // ID generation? Just use 0 or random for now.
-    let id = 0;
+    // 每个目的地址独立递增的标识，供对端重组分片
+    let id = stack.next_ip_id(dst_ip);
     let protocol_u8 = match protocol {
         Ipv4Protocol::ICMP => 1,
+        Ipv4Protocol::IGMP => 2,
         Ipv4Protocol::TCP => 6,
         Ipv4Protocol::UDP => 17,
         _ => 0,
     };
 
-    let header = Ipv4Header::new(src_ip, dst_ip, protocol_u8, payload.len() as u16, id);
+    let mut header = Ipv4Header::new(src_ip, dst_ip, protocol_u8, payload.len() as u16, id);
+    header.ttl = options.ttl;
+    if options.dont_fragment {
+        header.flags |= IPV4_FLAG_DF;
+    }
+    if !ip_options.is_empty() {
+        header
+            .set_options(ip_options)
+            .expect("router alert fits in the IPv4 options");
+    }
+    header.checksum = header.checksum();
     let header_bytes = header.to_bytes();
 
     // Combine
-    let mut frame_payload = Vec::new();
-    frame_payload.extend_from_slice(&header_bytes);
-    frame_payload.extend_from_slice(payload);
+    let mut datagram = Vec::with_capacity(header_bytes.len() + payload.len());
+    datagram.extend_from_slice(&header_bytes);
+    datagram.extend_from_slice(payload);
+    Some(datagram)
+}
 
+/// 把 IPv4 数据报封装进以太网帧发送
+pub fn send_datagram_with_mac(stack: &NetworkStack, dst_mac: MacAddr, datagram: &[u8]) {
     // Ethernet Header
-    let eth_header = EthernetHeader {
-        dst: dst_mac,
-        src: stack.config().mac,
-        ethertype: EtherType::Ipv4,
-    };
+    let eth_header = stack.ethernet_header(dst_mac, EtherType::Ipv4);
 
     let mut frame = Vec::new();
     frame.extend_from_slice(&eth_header.to_bytes());
-    frame.extend_from_slice(&frame_payload);
+    frame.extend_from_slice(datagram);
 
     // Padding to minimum Ethernet frame size (60 bytes)
     if frame.len() < 60 {
@@ -146,3 +443,47 @@ pub fn send_packet_with_mac(
 
     stack.send_frame(&frame);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sim::{LinkConfig, SegmentKind, Simulator};
+    use crate::stack::StackConfig;
+
+    #[test]
+    fn router_alert_counts_towards_the_payload_limit() {
+        let mut sim = Simulator::new(SegmentKind::Switch, LinkConfig::default());
+        let stack = sim.add_host(StackConfig::new(
+            MacAddr::from_raw([0x02, 0, 0, 0, 0, 1]),
+            Ipv4Addr::new(10, 0, 0, 1),
+        ));
+        let group = Ipv4Addr::new(239, 1, 2, 3);
+        let options = SendOptions {
+            router_alert: true,
+            ..SendOptions::default()
+        };
+
+        // 20 字节首部加 4 字节 Router Alert，载荷最多 65511 字节
+        let limit = u16::MAX as usize - 24;
+        let datagram =
+            build_datagram(&stack, group, Ipv4Protocol::UDP, &vec![0; limit], &options).unwrap();
+        let header = Ipv4Header::parse(&datagram).unwrap();
+        assert_eq!(header.header_len(), 24);
+        assert_eq!(header.total_len, u16::MAX);
+        assert_eq!(datagram.len(), u16::MAX as usize);
+
+        let oversized = vec![0; limit + 1];
+        assert!(build_datagram(&stack, group, Ipv4Protocol::UDP, &oversized, &options).is_none());
+        // 不带选项时同样长度的载荷放得下
+        assert!(
+            build_datagram(
+                &stack,
+                group,
+                Ipv4Protocol::UDP,
+                &oversized,
+                &SendOptions::default()
+            )
+            .is_some()
+        );
+    }
+}
@@ -0,0 +1,339 @@
+// Copyright (C) 2025 rrrrrzy
+// SPDX-License-Identifier: GPL-3.0-or-later
+//
+// --------------------------------------------------
+// 致敬所有在深夜调试代码的灵魂。
+// 即便 Bug 如山，我亦往矣。
+// --------------------------------------------------
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+use std::time::Duration;
+
+use protocol::ethernet::EtherType;
+use protocol::icmpv6::Icmpv6ParameterProblemCode;
+use protocol::ipv6::{
+    IPV6_HEADER_LEN, IPV6_OPT_PAD1, Ipv6Addr, Ipv6Cidr, Ipv6Extension, Ipv6Header,
+    Ipv6OptionAction, Ipv6Protocol, Ipv6Scope,
+};
+use protocol::mac::MacAddr;
+
+use crate::handlers::{icmpv6, udp};
+use crate::stack::{NetworkStack, PendingPacket};
+
+/// 向 Stale 邻居发包后等待多久没有被确认就发送单播邻居请求 (RFC 4861 DELAY_FIRST_PROBE_TIME)
+const NEIGHBOR_PROBE_DELAY: Duration = Duration::from_secs(5);
+
+pub fn handle(stack: &NetworkStack, payload: &[u8]) {
+    let header = match Ipv6Header::parse(payload) {
+        Ok(h) => h,
+        Err(e) => {
+            eprintln!("Invalid IPv6 header: {}", e);
+            return;
+        }
+    };
+
+    // 去掉以太网最小帧长带来的填充
+    let packet = &payload[..IPV6_HEADER_LEN + header.payload_len as usize];
+
+    // 不做 IPv6 转发，只收发给本机地址、所有节点组和本机请求节点组的报文
+    if !stack.accepts_ipv6_dst(header.dst) {
+        return;
+    }
+
+    if header.fragment().is_some() {
+        eprintln!(
+            "IPv6 fragments are not supported, dropping packet from {}",
+            header.src
+        );
+        return;
+    }
+
+    // 扩展首部在报文中的偏移，按线路上的长度累加
+    let mut offset = IPV6_HEADER_LEN;
+    for extension in &header.extensions {
+        match extension {
+            // 不认识的选项按类型的最高两位处理 (RFC 8200 4.2)
+            Ipv6Extension::HopByHop(options) | Ipv6Extension::DestinationOptions(options) => {
+                if let Some(option) = options
+                    .options()
+                    .iter()
+                    .find(|option| option.action() != Ipv6OptionAction::Skip)
+                {
+                    eprintln!(
+                        "Unrecognized IPv6 option {} from {}, dropping packet",
+                        option.kind(),
+                        header.src
+                    );
+                    let raw = &packet[offset..offset + extension.len()];
+                    if option.action() != Ipv6OptionAction::Discard
+                        && let Some(position) = option_position(raw, option.kind())
+                    {
+                        icmpv6::send_unrecognized_option(
+                            stack,
+                            option.action(),
+                            (offset + position) as u32,
+                            &header,
+                            packet,
+                        );
+                    }
+                    return;
+                }
+            }
+            // 本机不是路由器，还有剩余段的路由首部无法处理
+            Ipv6Extension::Routing { segments_left, .. } if *segments_left != 0 => {
+                eprintln!(
+                    "IPv6 routing header with {} segments left from {}, dropping packet",
+                    segments_left, header.src
+                );
+                return;
+            }
+            _ => {}
+        }
+        offset += extension.len();
+    }
+
+    deliver(stack, &header, packet);
+}
+
+/// 在选项首部的原始字节中找出第一个类型为 `kind` 的选项，返回其类型字节的偏移
+fn option_position(extension: &[u8], kind: u8) -> Option<usize> {
+    let mut i = 2;
+    while i < extension.len() {
+        match extension[i] {
+            IPV6_OPT_PAD1 => i += 1,
+            k if k == kind => return Some(i),
+            _ => i += 2 + *extension.get(i + 1)? as usize,
+        }
+    }
+    None
+}
+
+/// 把发给本机的报文交给上层协议
+fn deliver(stack: &NetworkStack, header: &Ipv6Header, packet: &[u8]) {
+    match header.get_protocol() {
+        Ipv6Protocol::ICMPv6 => {
+            icmpv6::handle(stack, header, packet);
+        }
+        Ipv6Protocol::UDP => {
+            udp::handle_v6(stack, header, packet);
+        }
+        Ipv6Protocol::NoNextHeader => {}
+        Ipv6Protocol::TCP | Ipv6Protocol::Unknown => {
+            eprintln!(
+                "Unsupported IPv6 Next Header {} from {}",
+                header.protocol, header.src
+            );
+            // 指向链上最后一个 Next Header 字段：固定首部的第 6 字节，或最后一个扩展首部的第 0 字节
+            let pointer = match header.extensions.last() {
+                Some(last) => header.header_len() - last.len(),
+                None => 6,
+            };
+            icmpv6::send_parameter_problem(
+                stack,
+                Icmpv6ParameterProblemCode::UnrecognizedNextHeader,
+                pointer as u32,
+                header,
+                packet,
+            );
+        }
+    }
+}
+
+/// 本机发出的报文的首部参数
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SendOptions {
+    pub hop_limit: u8,
+    /// 源地址，None 时按目的地址选择
+    pub src: Option<Ipv6Addr>,
+}
+
+impl Default for SendOptions {
+    fn default() -> Self {
+        Self {
+            hop_limit: 64,
+            src: None,
+        }
+    }
+}
+
+pub fn send_packet(stack: &NetworkStack, dst_ip: Ipv6Addr, next_header: u8, payload: &[u8]) {
+    send_packet_with_options(stack, dst_ip, next_header, payload, &SendOptions::default());
+}
+
+pub fn send_packet_with_options(
+    stack: &NetworkStack,
+    dst_ip: Ipv6Addr,
+    next_header: u8,
+    payload: &[u8],
+    options: &SendOptions,
+) {
+    let Some(src_ip) = options.src.or_else(|| source_address(stack, dst_ip)) else {
+        eprintln!("No IPv6 source address for {}, dropping packet", dst_ip);
+        return;
+    };
+    if let Some(packet) = build_packet(src_ip, dst_ip, next_header, payload, options.hop_limit) {
+        send_datagram(stack, dst_ip, packet);
+    }
+}
+
+/// 封装 IPv6 报文，载荷超过 65535 字节时返回 None
+pub fn build_packet(
+    src_ip: Ipv6Addr,
+    dst_ip: Ipv6Addr,
+    next_header: u8,
+    payload: &[u8],
+    hop_limit: u8,
+) -> Option<Vec<u8>> {
+    if payload.len() > u16::MAX as usize {
+        eprintln!(
+            "Payload of {} bytes does not fit in an IPv6 packet, dropping",
+            payload.len()
+        );
+        return None;
+    }
+
+    let mut header = Ipv6Header::new(src_ip, dst_ip, next_header, payload.len() as u16);
+    header.hop_limit = hop_limit;
+    let mut packet = header.to_bytes();
+    packet.extend_from_slice(payload);
+    Some(packet)
+}
+
+/// 按目的地址选择源地址，是 RFC 6724 的简化：
+/// 优先选前缀包含目的地址的地址，其次是与目的地址作用域相同 (链路本地或更大) 的地址；
+/// 已弃用的地址只在没有其他地址时使用 (规则 3)
+pub fn source_address(stack: &NetworkStack, dst_ip: Ipv6Addr) -> Option<Ipv6Addr> {
+    let (deprecated, preferred): (Vec<Ipv6Cidr>, Vec<Ipv6Cidr>) = stack
+        .config()
+        .ipv6_addrs
+        .iter()
+        .partition(|cidr| stack.is_deprecated_ipv6(cidr.addr));
+    let link_scope = matches!(
+        dst_ip.scope(),
+        Some(Ipv6Scope::InterfaceLocal | Ipv6Scope::LinkLocal)
+    );
+    let select = |addrs: &[Ipv6Cidr]| {
+        addrs
+            .iter()
+            .filter(|cidr| cidr.contains(dst_ip))
+            .max_by_key(|cidr| cidr.prefix_len)
+            .or_else(|| {
+                addrs
+                    .iter()
+                    .find(|cidr| cidr.addr.is_unicast_link_local() == link_scope)
+            })
+            .or_else(|| addrs.first())
+            .map(|cidr| cidr.addr)
+    };
+    select(&preferred).or_else(|| select(&deprecated))
+}
+
+/// 下一跳：链路本地地址和已配置前缀内的地址直接发送，其余交给默认网关
+pub fn next_hop(stack: &NetworkStack, dst_ip: Ipv6Addr) -> Option<Ipv6Addr> {
+    let config = stack.config();
+    if dst_ip.is_unicast_link_local() || config.ipv6_addrs.iter().any(|cidr| cidr.contains(dst_ip))
+    {
+        Some(dst_ip)
+    } else {
+        config.ipv6_gateway
+    }
+}
+
+/// 发送一个已经封装好的 IPv6 报文：组播直接映射 MAC，单播查下一跳并解析邻居
+///
+/// 本机不对 IPv6 报文分片，超过链路 MTU 的报文直接丢弃
+pub fn send_datagram(stack: &NetworkStack, dst_ip: Ipv6Addr, packet: Vec<u8>) {
+    let mtu = stack.ipv6_mtu();
+    if packet.len() > mtu {
+        eprintln!("IPv6 packet to {} exceeds MTU {}, dropping", dst_ip, mtu);
+        return;
+    }
+
+    if dst_ip.is_multicast() {
+        send_datagram_with_mac(stack, MacAddr::from_ipv6_multicast(dst_ip), &packet);
+        return;
+    }
+
+    let Some(next_hop) = next_hop(stack, dst_ip) else {
+        eprintln!("No route to host {}, dropping packet", dst_ip);
+        return;
+    };
+    resolve_and_send(stack, next_hop, dst_ip, packet);
+}
+
+fn resolve_and_send(stack: &NetworkStack, next_hop: Ipv6Addr, dst_ip: Ipv6Addr, packet: Vec<u8>) {
+    // 1. 查询邻居缓存，Stale 的邻居照常发送，过一段时间还没被确认就探测一次
+    let (dst_mac_opt, probe) = {
+        let mut neighbor_cache = stack.neighbor_cache().lock().unwrap();
+        (
+            neighbor_cache.lookup(next_hop, stack.now()),
+            neighbor_cache.should_probe(next_hop, NEIGHBOR_PROBE_DELAY, stack.now()),
+        )
+    };
+
+    match dst_mac_opt {
+        Some(dst_mac) => {
+            send_datagram_with_mac(stack, dst_mac, &packet);
+            if probe {
+                icmpv6::send_neighbor_solicitation(stack, next_hop, Some(dst_mac));
+            }
+        }
+        None => {
+            println!("邻居缓存中没有 {}，正在发送邻居请求...", next_hop);
+
+            // 按下一跳地址等待邻居通告
+            {
+                let mut pending = stack.pending_ipv6_packets().lock().unwrap();
+                pending
+                    .entry(next_hop)
+                    .or_default()
+                    .push_back(PendingPacket {
+                        dst_ip,
+                        datagram: packet,
+                        timestamp: stack.now(),
+                    });
+            }
+
+            icmpv6::send_neighbor_solicitation(stack, next_hop, None);
+        }
+    }
+}
+
+/// 学到邻居的 MAC 后，发出所有等待它的报文
+pub fn flush_pending(stack: &NetworkStack, neighbor: Ipv6Addr, mac: MacAddr) {
+    let packets = stack
+        .pending_ipv6_packets()
+        .lock()
+        .unwrap()
+        .remove(&neighbor);
+    if let Some(packets) = packets {
+        println!(
+            "发现 {} 个等待 {} 的数据包，正在发送...",
+            packets.len(),
+            neighbor
+        );
+        for pkt in packets {
+            send_datagram_with_mac(stack, mac, &pkt.datagram);
+        }
+    }
+}
+
+/// 把 IPv6 报文封装进以太网帧发送
+pub fn send_datagram_with_mac(stack: &NetworkStack, dst_mac: MacAddr, packet: &[u8]) {
+    let eth_header = stack.ethernet_header(dst_mac, EtherType::Ipv6);
+
+    let mut frame = Vec::new();
+    frame.extend_from_slice(&eth_header.to_bytes());
+    frame.extend_from_slice(packet);
+
+    // Padding to minimum Ethernet frame size (60 bytes)
+    if frame.len() < 60 {
+        frame.resize(60, 0);
+    }
+
+    stack.send_frame(&frame);
+}
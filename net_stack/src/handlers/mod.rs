@@ -13,5 +13,9 @@
 
 pub mod arp;
 pub mod icmp;
+pub mod icmpv6;
+pub mod igmp;
 pub mod ipv4;
+pub mod ipv6;
+pub mod tcp;
 pub mod udp;
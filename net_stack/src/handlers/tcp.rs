@@ -0,0 +1,175 @@
+// Copyright (C) 2025 rrrrrzy
+// SPDX-License-Identifier: GPL-3.0-or-later
+//
+// --------------------------------------------------
+// 致敬所有在深夜调试代码的灵魂。
+// 即便 Bug 如山，我亦往矣。
+// --------------------------------------------------
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+use protocol::ipv4::{Ipv4Header, Ipv4Protocol};
+use protocol::tcp::{TCP_FLAG_ACK, TCP_FLAG_RST, TCP_FLAG_SYN, TcpHeader, TcpSegment};
+
+use crate::handlers::ipv4;
+use crate::stack::NetworkStack;
+use crate::transport::tcp::{TcpAction, TcpSocketState, TcpState, local_mss};
+use crate::transport::{Socket, SocketHandle, SocketSet, SocketType};
+
+// receive
+// datagram 是完整的 IPv4 数据报
+pub fn handle(stack: &NetworkStack, header: &Ipv4Header, datagram: &[u8]) {
+    let (src_ip, dst_ip) = (header.src, header.dst);
+    // TCP 只有单播
+    if dst_ip != stack.config().ip {
+        return;
+    }
+
+    let payload = &datagram[header.header_len()..];
+    let segment = match TcpSegment::parse(payload) {
+        Ok(s) => s,
+        Err(e) => {
+            eprintln!("Invalid TCP segment: {}", e);
+            return;
+        }
+    };
+    if let Err(e) = segment.validate(src_ip, dst_ip) {
+        eprintln!("Invalid TCP segment from {}: {}", src_ip, e);
+        return;
+    }
+
+    let action = {
+        let mut sockets = stack.sockets.lock().unwrap();
+        let handle = sockets.lookup_handle(
+            &SocketType::Tcp,
+            src_ip.into(),
+            segment.header.src_port,
+            dst_ip.into(),
+            segment.header.dst_port,
+        );
+        match handle {
+            Some(handle) => dispatch(stack, &mut sockets, handle, header, &segment),
+            // 没有对应的连接或监听 socket
+            None => TcpAction::Reset,
+        }
+    }; // socket_set 锁在这里释放
+
+    if action == TcpAction::Reset {
+        send_reset(stack, header, &segment);
+    }
+}
+
+fn dispatch(
+    stack: &NetworkStack,
+    sockets: &mut SocketSet,
+    handle: SocketHandle,
+    header: &Ipv4Header,
+    segment: &TcpSegment,
+) -> TcpAction {
+    let Some(Socket::Tcp(socket)) = sockets.get_mut(handle) else {
+        return TcpAction::Reset;
+    };
+
+    if socket.state() == TcpState::Listen {
+        return listen(stack, sockets, handle, header, segment);
+    }
+
+    let action = socket.process(segment, stack.now());
+    if action != TcpAction::Established {
+        return action;
+    }
+
+    // 同时打开 (RFC 9293 3.5) 也会经过 SYN-RECEIVED，但没有监听 socket
+    let Some(parent) = socket.parent() else {
+        return TcpAction::None;
+    };
+    // 被动打开的连接完成握手，放进监听 socket 的 accept 队列
+    match sockets.get_mut(parent) {
+        Some(Socket::Tcp(listener)) => listener.accept_queue().push_back(handle),
+        // 监听 socket 已关闭
+        _ => {
+            if let Some(Socket::Tcp(socket)) = sockets.get_mut(handle) {
+                socket.abort();
+            }
+        }
+    }
+    TcpAction::None
+}
+
+/// 监听 socket 收到报文段 (RFC 9293 3.10.7.2)
+fn listen(
+    stack: &NetworkStack,
+    sockets: &mut SocketSet,
+    listener: SocketHandle,
+    header: &Ipv4Header,
+    segment: &TcpSegment,
+) -> TcpAction {
+    let h = &segment.header;
+    if h.has_flag(TCP_FLAG_RST) {
+        return TcpAction::None;
+    }
+    if h.has_flag(TCP_FLAG_ACK) {
+        return TcpAction::Reset;
+    }
+    if !h.has_flag(TCP_FLAG_SYN) {
+        return TcpAction::None;
+    }
+
+    // 半连接和等待 accept 的连接总数超过 backlog 时丢弃 SYN，让对端重传
+    let Some(Socket::Tcp(state)) = sockets.get(listener) else {
+        return TcpAction::None;
+    };
+    let backlog = state.backlog();
+    let queued = sockets
+        .iter()
+        .filter(|(_, socket)| {
+            matches!(socket, Socket::Tcp(s) if s.parent() == Some(listener) && s.state() != TcpState::Closed)
+        })
+        .count();
+    if queued >= backlog {
+        eprintln!(
+            "TCP backlog of {}:{} is full, dropping SYN from {}",
+            listener.local_addr, listener.local_port, header.src
+        );
+        return TcpAction::None;
+    }
+
+    let handle = SocketHandle::new(
+        &SocketType::Tcp,
+        header.dst.into(),
+        h.dst_port,
+        header.src.into(),
+        h.src_port,
+    );
+    let socket =
+        TcpSocketState::accept_syn(listener, header.dst, header.src, segment, local_mss(stack));
+    sockets.add(handle, Socket::Tcp(Box::new(socket)));
+    TcpAction::None
+}
+
+/// 回复 RST，序号按 RFC 9293 3.10.7.1 选取
+pub fn send_reset(stack: &NetworkStack, header: &Ipv4Header, segment: &TcpSegment) {
+    let h = &segment.header;
+    if h.has_flag(TCP_FLAG_RST) {
+        return;
+    }
+
+    let reset = if h.has_flag(TCP_FLAG_ACK) {
+        TcpHeader::new(h.dst_port, h.src_port, h.ack, 0, TCP_FLAG_RST, 0)
+    } else {
+        let ack = h.seq.wrapping_add(segment.seq_len());
+        TcpHeader::new(
+            h.dst_port,
+            h.src_port,
+            0,
+            ack,
+            TCP_FLAG_RST | TCP_FLAG_ACK,
+            0,
+        )
+    };
+    let reset = TcpSegment::new(reset, Vec::new(), header.dst, header.src);
+    ipv4::send_packet(stack, header.src, Ipv4Protocol::TCP, &reset.to_bytes());
+}
@@ -11,15 +11,23 @@
 // the Free Software Foundation, either version 3 of the License, or
 // (at your option) any later version.
 
-use protocol::{ipv4::Ipv4Addr, udp::UdpPacket};
+use protocol::icmp::DestUnreachableCode;
+use protocol::icmpv6::Icmpv6DestUnreachableCode;
+use protocol::ip::IpAddr;
+use protocol::ipv6::Ipv6Header;
+use protocol::{ipv4::Ipv4Header, udp::UdpPacket};
 
 use crate::{
+    handlers::{icmp, icmpv6},
     stack::NetworkStack,
     transport::{Socket, SocketType},
 };
 
 // receive
-pub fn handle(stack: &NetworkStack, src_ip: Ipv4Addr, dst_ip: Ipv4Addr, payload: &[u8]) {
+// datagram 是完整的 IPv4 数据报，端口不可达时需要引用其首部
+pub fn handle(stack: &NetworkStack, header: &Ipv4Header, datagram: &[u8]) {
+    let (src_ip, dst_ip) = (header.src, header.dst);
+    let payload = &datagram[header.header_len()..];
     let packet = match UdpPacket::parse(payload) {
         Ok(p) => p,
         Err(e) => {
@@ -29,7 +37,7 @@ pub fn handle(stack: &NetworkStack, src_ip: Ipv4Addr, dst_ip: Ipv4Addr, payload:
     };
 
     // 防御性检查：确保目的 IP 是我们关心的
-    if !(dst_ip == stack.config().ip || dst_ip.is_broadcast() || dst_ip.is_multicast()) {
+    if !(dst_ip == stack.config().ip || stack.is_broadcast_addr(dst_ip) || dst_ip.is_multicast()) {
         return;
     }
 
@@ -42,34 +50,76 @@ pub fn handle(stack: &NetworkStack, src_ip: Ipv4Addr, dst_ip: Ipv4Addr, payload:
         }
     }
 
-    // 先查找 socket，获取必要的引用，然后立即释放锁
-    let should_enqueue = {
-        if let Ok(mut socket_set) = stack.sockets.lock() {
-            if let Some(socket) = socket_set.lookup(
-                &SocketType::Udp,
-                src_ip,
-                packet.header.src_port,
-                dst_ip,
-                packet.header.dst_port,
-            ) {
-                match socket {
-                    Socket::Udp(udp_socket) => {
-                        // 直接在这里入队（因为已经拿到了锁）
-                        udp_socket.rx_enqueue(src_ip, packet.header.src_port, &packet.payload);
-                        true
-                    }
-                    _ => false,
-                }
-            } else {
-                false
+    // 广播和多播交给所有匹配的 socket，没有 socket 时也不回复端口不可达
+    let group = stack.is_broadcast_addr(dst_ip) || dst_ip.is_multicast();
+    if !deliver(stack, src_ip.into(), dst_ip.into(), &packet, group) && !group {
+        icmp::send_dest_unreachable(
+            stack,
+            DestUnreachableCode::PortUnreachable,
+            0,
+            header,
+            datagram,
+        );
+    }
+}
+
+// packet 是完整的 IPv6 报文，目的地址已由 IPv6 层过滤
+pub fn handle_v6(stack: &NetworkStack, header: &Ipv6Header, packet: &[u8]) {
+    let (src_ip, dst_ip) = (header.src, header.dst);
+    let datagram = match UdpPacket::parse(&packet[header.header_len()..]) {
+        Ok(p) => p,
+        Err(e) => {
+            eprintln!("Invalid UDP packet: {:?}", e);
+            return;
+        }
+    };
+
+    if let Err(e) = datagram.validate_ipv6(src_ip, dst_ip) {
+        eprintln!("Invalid UDP Packet: {:?}", e);
+        return;
+    }
+
+    let group = dst_ip.is_multicast();
+    if !deliver(stack, src_ip.into(), dst_ip.into(), &datagram, group) && !group {
+        icmpv6::send_dest_unreachable(
+            stack,
+            Icmpv6DestUnreachableCode::PortUnreachable,
+            header,
+            packet,
+        );
+    }
+}
+
+/// 把数据报交给匹配的 socket，没有任何 socket 收下时返回 false
+fn deliver(
+    stack: &NetworkStack,
+    src_ip: IpAddr,
+    dst_ip: IpAddr,
+    packet: &UdpPacket,
+    group: bool,
+) -> bool {
+    let (src_port, dst_port) = (packet.header.src_port, packet.header.dst_port);
+    let mut socket_set = stack.sockets.lock().unwrap();
+
+    if group {
+        let mut delivered = false;
+        for socket in
+            socket_set.lookup_multicast(&SocketType::Udp, src_ip, src_port, dst_ip, dst_port)
+        {
+            if let Socket::Udp(udp_socket) = socket {
+                udp_socket.rx_enqueue(src_ip, src_port, &packet.payload);
+                delivered = true;
             }
-        } else {
-            false
         }
-    }; // socket_set 锁在这里释放
+        return delivered;
+    }
 
-    if !should_enqueue {
-        // drop now
-        // future: send ICMP Port Unreachable
+    if let Some(Socket::Udp(udp_socket)) =
+        socket_set.lookup(&SocketType::Udp, src_ip, src_port, dst_ip, dst_port)
+    {
+        udp_socket.rx_enqueue(src_ip, src_port, &packet.payload);
+        true
+    } else {
+        false
     }
 }
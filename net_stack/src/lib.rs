@@ -12,8 +12,18 @@
 // (at your option) any later version.
 
 pub mod cli;
+pub mod clock;
 pub mod config;
+pub mod device;
+pub mod dhcp;
+pub mod dns;
 pub mod event_loop;
 pub mod handlers;
+pub mod ping;
+pub mod reassembly;
+pub mod route;
+pub mod sim;
+pub mod slaac;
 pub mod stack;
+pub mod traceroute;
 pub mod transport;
@@ -15,22 +15,182 @@ use anyhow::Result;
 use clap::Parser;
 use net_stack::cli::Args;
 use net_stack::config;
+use net_stack::dhcp::client::DhcpClient;
+use net_stack::dhcp::server::DhcpServer;
+use net_stack::dns::mdns::MdnsResponder;
+use net_stack::dns::resolver::Resolver;
 use net_stack::event_loop;
-use net_stack::stack;
+use net_stack::ping;
+use net_stack::slaac::SlaacClient;
+use net_stack::stack::{self, NetworkStack};
+use net_stack::traceroute;
+use protocol::dns::{DnsRecordType, reverse_name};
+use protocol::ipv4::Ipv4Addr;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+
+// Ctrl-C 时置位，ping 据此提前结束并打印统计，DHCP 客户端据此释放租约
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+#[cfg(unix)]
+fn install_sigint_handler() {
+    extern "C" fn on_sigint(_: libc::c_int) {
+        INTERRUPTED.store(true, Ordering::Relaxed);
+    }
+    // SAFETY: 信号处理函数只写一个原子变量，是 async-signal-safe 的
+    unsafe {
+        libc::signal(
+            libc::SIGINT,
+            on_sigint as extern "C" fn(libc::c_int) as libc::sighandler_t,
+        );
+    }
+}
+
+#[cfg(not(unix))]
+fn install_sigint_handler() {}
 
 fn main() -> Result<()> {
     let args = Args::parse();
 
     // 从配置文件或命令行参数获取 IP 和 MAC
-    let stack_config = config::load_config(&args)?;
+    let mut stack_config = config::load_config(&args)?;
+    let interfaces = config::load_interfaces(&args, &mut stack_config)?;
+    let dhcp_server_config = config::load_dhcp_server_config(&args)?;
+    if stack_config.dhcp && dhcp_server_config.is_some() {
+        anyhow::bail!("DHCP client and server cannot run on the same stack");
+    }
+
+    let stack = stack::initialize_from_args(&args, stack_config)?;
+
+    // 附加接口各自在后台收包，主接口按下面的模式运行
+    for interface in stack::initialize_interfaces(&args, &stack, interfaces)? {
+        thread::spawn(move || event_loop::run(interface));
+    }
+
+    // 收包交给后台线程，主线程做 DHCP / ping / traceroute
+    let spawn_event_loop = || {
+        let runner = stack.clone();
+        thread::spawn(move || event_loop::run(runner));
+    };
+
+    // 服务器模式：主线程处理 DHCP 请求直到 Ctrl-C
+    if let Some(server_config) = dhcp_server_config {
+        spawn_event_loop();
+        install_sigint_handler();
+        let mut server = DhcpServer::new(stack.clone(), server_config)?;
+        server.run(&INTERRUPTED)?;
+        return Ok(());
+    }
+
+    // mDNS 应答和 SLAAC 在后台线程运行，没有地址时 mDNS 等 DHCP 配置好再宣告
+    let mut services = Vec::new();
+    if let Some(hostname) = config::load_mdns_hostname(&args)? {
+        let mut responder = MdnsResponder::new(stack.clone(), &hostname)?;
+        services.push(thread::spawn(move || responder.run(&INTERRUPTED)));
+    }
+    if stack.config().slaac {
+        let mut client = SlaacClient::new(stack.clone())?;
+        services.push(thread::spawn(move || client.run(&INTERRUPTED)));
+    }
+
+    // 先拿到租约再做其他事
+    let dhcp = stack.config().dhcp;
+    let mut dhcp_client = None;
+    if dhcp {
+        spawn_event_loop();
+        install_sigint_handler();
+        let mut client = DhcpClient::new(stack.clone())?;
+        client.acquire(&INTERRUPTED)?;
+        dhcp_client = Some(client);
+    }
+
+    if let Some(name) = &args.resolve {
+        if !dhcp {
+            spawn_event_loop();
+        }
+        let resolver = Resolver::new(stack.clone(), config::load_resolver_config(&args)?)?;
+        // 给出 IPv4 地址时默认做反向解析
+        let (name, default_type) = match Ipv4Addr::from_str(name) {
+            Ok(addr) => (reverse_name(addr), DnsRecordType::Ptr),
+            Err(_) => (name.clone(), DnsRecordType::A),
+        };
+        let rtype = match &args.resolve_type {
+            Some(t) => DnsRecordType::from_name(t)
+                .ok_or_else(|| anyhow::anyhow!("Unknown record type '{}'", t))?,
+            None => default_type,
+        };
+        let result = resolver.lookup(&name, rtype);
+        if let Some(mut client) = dhcp_client {
+            client.release();
+        }
+        for record in result.map_err(|e| anyhow::anyhow!("{} {}: {}", name, rtype, e))? {
+            println!("{}", record);
+        }
+        return Ok(());
+    }
+
+    if let Some(target) = &args.ping {
+        let ping_config = config::load_ping_config(&args)?;
 
-    let stack = stack::initialize(&args.iface, stack_config)?;
+        if !dhcp {
+            spawn_event_loop();
+            install_sigint_handler();
+        }
+        let target_ip = resolve_target(&stack, &args, target)?;
+        let stats = ping::ping(&stack, target_ip, &ping_config, &INTERRUPTED)?;
+        println!();
+        println!("{}", stats);
+        if let Some(mut client) = dhcp_client {
+            client.release();
+        }
+        return Ok(());
+    }
+
+    if let Some(target) = &args.traceroute {
+        let traceroute_config = config::load_traceroute_config(&args)?;
 
-    if let Some(target_ip_str) = args.ping {
-        event_loop::ping(&target_ip_str, &stack)?;
+        if !dhcp {
+            spawn_event_loop();
+            install_sigint_handler();
+        }
+        let target_ip = resolve_target(&stack, &args, target)?;
+        traceroute::traceroute(&stack, target_ip, &traceroute_config, &INTERRUPTED)?;
+        if let Some(mut client) = dhcp_client {
+            client.release();
+        }
+        return Ok(());
     }
 
-    event_loop::run(stack)?;
+    match dhcp_client {
+        // 事件循环已在后台运行，主线程维护租约，Ctrl-C 时释放
+        Some(mut client) => client.run(&INTERRUPTED)?,
+        None if !services.is_empty() => {
+            spawn_event_loop();
+            install_sigint_handler();
+        }
+        None => event_loop::run(stack)?,
+    }
+    // Ctrl-C 时等 mDNS 发出 goodbye 再退出
+    for service in services {
+        service.join().unwrap()?;
+    }
 
     Ok(())
 }
+
+/// ping / traceroute 的目标可以是地址或主机名，主机名取解析到的第一个地址
+fn resolve_target(stack: &Arc<NetworkStack>, args: &Args, target: &str) -> Result<Ipv4Addr> {
+    if let Ok(addr) = Ipv4Addr::from_str(target) {
+        return Ok(addr);
+    }
+    let resolver = Resolver::new(stack.clone(), config::load_resolver_config(args)?)?;
+    let addrs = resolver
+        .lookup_ipv4(target)
+        .map_err(|e| anyhow::anyhow!("Cannot resolve {}: {}", target, e))?;
+    addrs
+        .first()
+        .copied()
+        .ok_or_else(|| anyhow::anyhow!("Cannot resolve {}: no address", target))
+}
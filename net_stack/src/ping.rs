@@ -0,0 +1,372 @@
+// Copyright (C) 2025 rrrrrzy
+// SPDX-License-Identifier: GPL-3.0-or-later
+//
+// --------------------------------------------------
+// 致敬所有在深夜调试代码的灵魂。
+// 即便 Bug 如山，我亦往矣。
+// --------------------------------------------------
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+//! 类似 iputils ping 的 ICMP Echo 探测
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use protocol::icmp::{Echo, IcmpMessage};
+use protocol::ipv4::{Ipv4Addr, Ipv4Protocol};
+
+use crate::handlers::icmp::{self, IcmpListener, IcmpListenerKey};
+use crate::handlers::ipv4::{self, SendOptions};
+use crate::stack::NetworkStack;
+
+#[derive(Debug, Clone)]
+pub struct PingConfig {
+    /// 发送的请求个数，None 表示一直发送直到被取消
+    pub count: Option<u32>,
+    pub interval: Duration,
+    /// ICMP 数据部分的字节数，不含 8 字节 ICMP 首部
+    pub payload_size: usize,
+    pub ttl: u8,
+    pub dont_fragment: bool,
+    /// 最后一个请求发出后等待回复的时间
+    pub timeout: Duration,
+    /// 不打印每个回复，只返回统计
+    pub quiet: bool,
+}
+
+impl Default for PingConfig {
+    fn default() -> Self {
+        Self {
+            count: None,
+            interval: Duration::from_secs(1),
+            payload_size: 56,
+            ttl: 64,
+            dont_fragment: false,
+            timeout: Duration::from_secs(1),
+            quiet: false,
+        }
+    }
+}
+
+/// 一次 ping 的统计结果
+#[derive(Debug, Clone, Default)]
+pub struct PingStats {
+    pub target: Option<Ipv4Addr>,
+    pub transmitted: u32,
+    pub received: u32,
+    pub duplicates: u32,
+    /// 每个 (非重复) 回复的往返时间
+    pub rtts: Vec<Duration>,
+    pub elapsed: Duration,
+}
+
+impl PingStats {
+    /// 丢包率，0.0 - 100.0
+    pub fn loss_percent(&self) -> f64 {
+        if self.transmitted == 0 {
+            return 0.0;
+        }
+        (self.transmitted - self.received.min(self.transmitted)) as f64 * 100.0
+            / self.transmitted as f64
+    }
+
+    pub fn min(&self) -> Option<Duration> {
+        self.rtts.iter().min().copied()
+    }
+
+    pub fn max(&self) -> Option<Duration> {
+        self.rtts.iter().max().copied()
+    }
+
+    pub fn avg(&self) -> Option<Duration> {
+        if self.rtts.is_empty() {
+            return None;
+        }
+        Some(self.rtts.iter().sum::<Duration>() / self.rtts.len() as u32)
+    }
+
+    /// 标准差，与 iputils 的 mdev 计算方式相同
+    pub fn mdev(&self) -> Option<Duration> {
+        let avg = self.avg()?.as_secs_f64();
+        let n = self.rtts.len() as f64;
+        let sq = self
+            .rtts
+            .iter()
+            .map(|r| r.as_secs_f64().powi(2))
+            .sum::<f64>()
+            / n;
+        Some(Duration::from_secs_f64((sq - avg * avg).max(0.0).sqrt()))
+    }
+}
+
+fn ms(d: Duration) -> f64 {
+    d.as_secs_f64() * 1000.0
+}
+
+impl fmt::Display for PingStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(target) = self.target {
+            writeln!(f, "--- {} ping statistics ---", target)?;
+        }
+        write!(
+            f,
+            "{} packets transmitted, {} received, ",
+            self.transmitted, self.received
+        )?;
+        if self.duplicates > 0 {
+            write!(f, "+{} duplicates, ", self.duplicates)?;
+        }
+        write!(
+            f,
+            "{}% packet loss, time {}ms",
+            self.loss_percent().round(),
+            self.elapsed.as_millis()
+        )?;
+
+        if let (Some(min), Some(avg), Some(max), Some(mdev)) =
+            (self.min(), self.avg(), self.max(), self.mdev())
+        {
+            write!(
+                f,
+                "\nrtt min/avg/max/mdev = {:.3}/{:.3}/{:.3}/{:.3} ms",
+                ms(min),
+                ms(avg),
+                ms(max),
+                ms(mdev)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// 向 target 发送 ICMP Echo Request 并统计回复，阻塞直到发完且等待结束，或 cancel 被置位
+///
+/// 回复由事件循环中的 ICMP handler 投递，调用前需要有线程在运行 `event_loop::run`
+/// 按序号记录请求的发送时间和是否已收到回复；序号回绕后重新发送时覆盖旧记录
+#[derive(Debug, Default)]
+struct Outstanding {
+    requests: HashMap<u16, (Instant, bool)>,
+}
+
+impl Outstanding {
+    fn sent(&mut self, seq: u16, at: Instant) {
+        self.requests.insert(seq, (at, false));
+    }
+
+    /// 返回请求的发送时间以及这是否是重复的回复，没有发过的序号返回 None
+    fn reply(&mut self, seq: u16) -> Option<(Instant, bool)> {
+        let (sent, replied) = self.requests.get_mut(&seq)?;
+        Some((*sent, std::mem::replace(replied, true)))
+    }
+}
+
+pub fn ping(
+    stack: &NetworkStack,
+    target: Ipv4Addr,
+    config: &PingConfig,
+    cancel: &AtomicBool,
+) -> anyhow::Result<PingStats> {
+    let listener = IcmpListener::ephemeral(stack, IcmpListenerKey::Echo)
+        .ok_or_else(|| anyhow::anyhow!("No free ICMP echo identifier"))?;
+
+    // 与 iputils 一样用递增字节填充数据部分
+    let data: Vec<u8> = (0..config.payload_size).map(|i| i as u8).collect();
+    let options = SendOptions {
+        ttl: config.ttl,
+        dont_fragment: config.dont_fragment,
+        router_alert: false,
+    };
+
+    if !config.quiet {
+        println!(
+            "PING {} {}({}) bytes of data.",
+            target,
+            config.payload_size,
+            config.payload_size + 8 + 20
+        );
+    }
+
+    let mut stats = PingStats {
+        target: Some(target),
+        ..Default::default()
+    };
+    let mut outstanding = Outstanding::default();
+
+    let start = stack.now();
+    let mut next_send = start;
+    let mut seq: u16 = 1;
+    let mut deadline = None;
+
+    while !cancel.load(Ordering::Relaxed) {
+        let now = stack.now();
+
+        // 发送
+        let more = config.count.is_none_or(|count| stats.transmitted < count);
+        if more && now >= next_send {
+            let request = IcmpMessage::EchoRequest(Echo {
+                id: listener.id(),
+                seq,
+                data: data.clone(),
+            });
+            outstanding.sent(seq, stack.now());
+            ipv4::send_packet_with_options(
+                stack,
+                target,
+                Ipv4Protocol::ICMP,
+                &request.to_bytes(),
+                &options,
+            );
+            stats.transmitted += 1;
+            seq = seq.wrapping_add(1);
+            next_send += config.interval;
+
+            if config.count.is_some_and(|count| stats.transmitted >= count) {
+                deadline = Some(stack.now() + config.timeout);
+            }
+            continue;
+        }
+
+        // 全部发完后，收齐回复或等待超时就结束
+        if let Some(deadline) = deadline
+            && (now >= deadline || stats.received >= stats.transmitted)
+        {
+            break;
+        }
+
+        let wake = match deadline {
+            Some(deadline) => deadline,
+            None => next_send,
+        };
+        // 分段等待，以便及时响应 cancel
+        let wait = wake
+            .saturating_duration_since(now)
+            .min(Duration::from_millis(100));
+        let Some(event) = listener.recv_timeout(wait) else {
+            continue;
+        };
+        let echo = match &event.message {
+            IcmpMessage::EchoReply(echo) => echo,
+            message if message.is_error() => {
+                // 途中路由器对某个请求返回的差错报文
+                if !config.quiet
+                    && let Some((_, quoted)) = icmp::quoted_datagram(message)
+                {
+                    let seq = u16::from_be_bytes([quoted[6], quoted[7]]);
+                    println!("From {} icmp_seq={} {}", event.src, seq, message);
+                }
+                continue;
+            }
+            _ => continue,
+        };
+        let Some((sent, duplicate)) = outstanding.reply(echo.seq) else {
+            continue;
+        };
+
+        let rtt = event.received.saturating_duration_since(sent);
+        if duplicate {
+            stats.duplicates += 1;
+        } else {
+            stats.received += 1;
+            stats.rtts.push(rtt);
+        }
+
+        if !config.quiet {
+            println!(
+                "{} bytes from {}: icmp_seq={} ttl={} time={:.3} ms{}{}",
+                echo.data.len() + 8,
+                event.src,
+                echo.seq,
+                event.ttl,
+                ms(rtt),
+                if duplicate { " (DUP!)" } else { "" },
+                if echo.data != data {
+                    " (wrong data)"
+                } else {
+                    ""
+                }
+            );
+        }
+    }
+
+    stats.elapsed = stack.now().duration_since(start);
+    Ok(stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stats(transmitted: u32, rtts_ms: &[u64]) -> PingStats {
+        PingStats {
+            target: Some(Ipv4Addr::new(10, 0, 0, 2)),
+            transmitted,
+            received: rtts_ms.len() as u32,
+            duplicates: 0,
+            rtts: rtts_ms
+                .iter()
+                .map(|&ms| Duration::from_millis(ms))
+                .collect(),
+            elapsed: Duration::from_millis(3004),
+        }
+    }
+
+    #[test]
+    fn statistics_match_iputils() {
+        let stats = stats(4, &[10, 20, 30]);
+        assert_eq!(stats.loss_percent(), 25.0);
+        assert_eq!(stats.min(), Some(Duration::from_millis(10)));
+        assert_eq!(stats.max(), Some(Duration::from_millis(30)));
+        assert_eq!(stats.avg(), Some(Duration::from_millis(20)));
+        // sqrt((100 + 400 + 900) / 3 - 400) ≈ 8.165ms
+        let mdev = stats.mdev().unwrap().as_secs_f64() * 1000.0;
+        assert!((mdev - 8.165).abs() < 0.001, "{}", mdev);
+        assert_eq!(
+            stats.to_string(),
+            "--- 10.0.0.2 ping statistics ---\n\
+             4 packets transmitted, 3 received, 25% packet loss, time 3004ms\n\
+             rtt min/avg/max/mdev = 10.000/20.000/30.000/8.165 ms"
+        );
+    }
+
+    #[test]
+    fn statistics_without_replies() {
+        let mut stats = stats(3, &[]);
+        assert_eq!(stats.loss_percent(), 100.0);
+        assert!(stats.mdev().is_none());
+        assert_eq!(
+            stats.to_string(),
+            "--- 10.0.0.2 ping statistics ---\n\
+             3 packets transmitted, 0 received, 100% packet loss, time 3004ms"
+        );
+
+        // 重复回复不计入 received，单独显示
+        stats.duplicates = 2;
+        assert!(
+            stats
+                .to_string()
+                .contains("0 received, +2 duplicates, 100%")
+        );
+        assert_eq!(PingStats::default().loss_percent(), 0.0);
+    }
+
+    #[test]
+    fn reused_sequence_numbers_are_not_duplicates() {
+        let start = Instant::now();
+        let mut outstanding = Outstanding::default();
+        outstanding.sent(1, start);
+        assert_eq!(outstanding.reply(1), Some((start, false)));
+        assert_eq!(outstanding.reply(1), Some((start, true)));
+        assert_eq!(outstanding.reply(2), None);
+
+        // 发满 65536 个请求后序号回绕，新请求的回复不算重复
+        let later = start + Duration::from_secs(65536);
+        outstanding.sent(1, later);
+        assert_eq!(outstanding.reply(1), Some((later, false)));
+    }
+}
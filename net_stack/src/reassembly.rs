@@ -0,0 +1,520 @@
+// Copyright (C) 2025 rrrrrzy
+// SPDX-License-Identifier: GPL-3.0-or-later
+//
+// --------------------------------------------------
+// 致敬所有在深夜调试代码的灵魂。
+// 即便 Bug 如山，我亦往矣。
+// --------------------------------------------------
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+//! IPv4 分片重组 (RFC 791 / RFC 815)
+//!
+//! 按 (源地址, 目的地址, 协议, Identification) 归并分片。重叠部分以先到的数据为准，
+//! 完全重复的分片直接忽略；每个数据报有独立的超时，所有未完成数据报占用的内存有总上限，
+//! 超出时从最老的开始丢弃。
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use protocol::checksum::simple_checksum;
+use protocol::ipv4::{IPV4_FLAG_MF, Ipv4Addr, Ipv4Header};
+
+/// 默认重组超时，与 Linux 的 ipfrag_time 一致
+pub const DEFAULT_REASSEMBLY_TIMEOUT: Duration = Duration::from_secs(30);
+/// 默认为所有未完成数据报缓存的字节数上限
+pub const DEFAULT_REASSEMBLY_MEMORY: usize = 256 * 1024;
+
+// 数据报的最大载荷长度：total_len 最大 65535，减去最短首部
+const MAX_PAYLOAD_LEN: usize = u16::MAX as usize - 20;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct FragmentKey {
+    src: Ipv4Addr,
+    dst: Ipv4Addr,
+    protocol: u8,
+    id: u16,
+}
+
+struct PartialDatagram {
+    // 偏移为 0 的分片的首部，重组后的数据报沿用它
+    header: Option<Vec<u8>>,
+    data: Vec<u8>,
+    // 已收到的区间 [start, end)，按 start 排序且互不相交
+    ranges: Vec<(usize, usize)>,
+    // 收到最后一片 (MF = 0) 后才知道载荷总长
+    total_len: Option<usize>,
+    created: Instant,
+}
+
+impl PartialDatagram {
+    fn new(now: Instant) -> Self {
+        Self {
+            header: None,
+            data: Vec::new(),
+            ranges: Vec::new(),
+            total_len: None,
+            created: now,
+        }
+    }
+
+    fn memory(&self) -> usize {
+        self.data.len() + self.header.as_ref().map_or(0, Vec::len)
+    }
+
+    /// 写入一段数据，只填补还没收到的空洞
+    fn insert(&mut self, start: usize, chunk: &[u8]) {
+        let end = start + chunk.len();
+        if self.data.len() < end {
+            self.data.resize(end, 0);
+        }
+
+        let mut pos = start;
+        for &(s, e) in &self.ranges {
+            if e <= pos {
+                continue;
+            }
+            if s >= end {
+                break;
+            }
+            if s > pos {
+                self.data[pos..s].copy_from_slice(&chunk[pos - start..s - start]);
+            }
+            pos = pos.max(e);
+            if pos >= end {
+                break;
+            }
+        }
+        if pos < end {
+            self.data[pos..end].copy_from_slice(&chunk[pos - start..]);
+        }
+
+        self.ranges.push((start, end));
+        self.ranges.sort_unstable();
+        let mut merged: Vec<(usize, usize)> = Vec::with_capacity(self.ranges.len());
+        for &(s, e) in &self.ranges {
+            match merged.last_mut() {
+                Some(last) if s <= last.1 => last.1 = last.1.max(e),
+                _ => merged.push((s, e)),
+            }
+        }
+        self.ranges = merged;
+    }
+
+    fn is_complete(&self) -> bool {
+        match (self.total_len, &self.header, self.ranges.as_slice()) {
+            (Some(total), Some(_), [(0, end)]) => *end == total,
+            _ => false,
+        }
+    }
+}
+
+/// 重组超时被丢弃的数据报，若收到过首片则附带其首部和前 8 字节载荷，
+/// 用于回复 ICMP Time Exceeded (code 1)
+pub struct ExpiredDatagram {
+    pub src: Ipv4Addr,
+    pub quote: Option<Vec<u8>>,
+}
+
+/// IPv4 分片重组器
+pub struct Reassembler {
+    datagrams: HashMap<FragmentKey, PartialDatagram>,
+    timeout: Duration,
+    max_memory: usize,
+    memory: usize,
+}
+
+impl Default for Reassembler {
+    fn default() -> Self {
+        Self::new(DEFAULT_REASSEMBLY_TIMEOUT, DEFAULT_REASSEMBLY_MEMORY)
+    }
+}
+
+impl Reassembler {
+    pub fn new(timeout: Duration, max_memory: usize) -> Self {
+        Self {
+            datagrams: HashMap::new(),
+            timeout,
+            max_memory,
+            memory: 0,
+        }
+    }
+
+    /// 正在重组的数据报个数
+    pub fn len(&self) -> usize {
+        self.datagrams.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.datagrams.is_empty()
+    }
+
+    /// 当前缓存的字节数
+    pub fn memory(&self) -> usize {
+        self.memory
+    }
+
+    /// 处理一个分片，`datagram` 是去掉链路层填充后的完整分片。
+    /// 凑齐所有分片时返回重组好的数据报 (首部已清除分片字段并重算校验和)
+    pub fn push(&mut self, header: &Ipv4Header, datagram: &[u8], now: Instant) -> Option<Vec<u8>> {
+        let header_len = header.header_len();
+        if datagram.len() < header_len {
+            return None;
+        }
+
+        let key = FragmentKey {
+            src: header.src,
+            dst: header.dst,
+            protocol: header.protocol,
+            id: header.id,
+        };
+        let start = header.frag_offset as usize * 8;
+        let chunk = &datagram[header_len..];
+        let end = start + chunk.len();
+        let more = header.flags & IPV4_FLAG_MF != 0;
+
+        // 除最后一片外长度必须是 8 的倍数，且重组后不能超过 IPv4 最大长度
+        if (more && !chunk.len().is_multiple_of(8)) || end > MAX_PAYLOAD_LEN {
+            eprintln!(
+                "Malformed fragment from {} (id {:#06x}), dropping datagram",
+                header.src, header.id
+            );
+            self.remove(&key);
+            return None;
+        }
+
+        let partial = self
+            .datagrams
+            .entry(key)
+            .or_insert_with(|| PartialDatagram::new(now));
+        let before = partial.memory();
+
+        // 与已知总长冲突的分片说明数据报已被篡改或 ID 撞号，整个丢掉
+        let conflicting = match partial.total_len {
+            Some(total) => end > total || (!more && end != total),
+            None => !more && partial.ranges.last().is_some_and(|&(_, e)| e > end),
+        };
+        // 首片的首部最长 60 字节，加上它后重组结果仍不能超过 IPv4 最大长度
+        let first_header_len = if start == 0 {
+            Some(header_len)
+        } else {
+            partial.header.as_ref().map(Vec::len)
+        };
+        let furthest = partial.ranges.last().map_or(end, |&(_, e)| e.max(end));
+        let oversized = first_header_len.is_some_and(|len| len + furthest > u16::MAX as usize);
+        if conflicting || oversized {
+            eprintln!(
+                "Inconsistent fragment from {} (id {:#06x}), dropping datagram",
+                header.src, header.id
+            );
+            self.remove(&key);
+            return None;
+        }
+
+        if !more {
+            partial.total_len = Some(end);
+        }
+        if start == 0 && partial.header.is_none() {
+            partial.header = Some(datagram[..header_len].to_vec());
+        }
+        partial.insert(start, chunk);
+
+        let after = partial.memory();
+        self.memory = self.memory + after - before;
+
+        if partial.is_complete() {
+            let partial = self.remove(&key)?;
+            return assemble(partial);
+        }
+
+        self.enforce_memory_limit(key);
+        None
+    }
+
+    /// 丢弃超时的数据报并返回它们
+    pub fn expire(&mut self, now: Instant) -> Vec<ExpiredDatagram> {
+        let expired: Vec<FragmentKey> = self
+            .datagrams
+            .iter()
+            .filter(|(_, d)| now.saturating_duration_since(d.created) >= self.timeout)
+            .map(|(k, _)| *k)
+            .collect();
+
+        expired
+            .into_iter()
+            .filter_map(|key| {
+                let partial = self.remove(&key)?;
+                let quote = partial.header.map(|mut quote| {
+                    let n = partial.data.len().min(8);
+                    quote.extend_from_slice(&partial.data[..n]);
+                    quote
+                });
+                Some(ExpiredDatagram {
+                    src: key.src,
+                    quote,
+                })
+            })
+            .collect()
+    }
+
+    fn remove(&mut self, key: &FragmentKey) -> Option<PartialDatagram> {
+        let partial = self.datagrams.remove(key)?;
+        self.memory -= partial.memory();
+        Some(partial)
+    }
+
+    /// 超出内存上限时从最老的数据报开始丢弃，`current` 最后才考虑
+    fn enforce_memory_limit(&mut self, current: FragmentKey) {
+        while self.memory > self.max_memory {
+            let oldest = self
+                .datagrams
+                .iter()
+                .filter(|(k, _)| **k != current)
+                .min_by_key(|(_, d)| d.created)
+                .map(|(k, _)| *k)
+                .unwrap_or(current);
+            eprintln!(
+                "Reassembly buffer full, dropping datagram from {} (id {:#06x})",
+                oldest.src, oldest.id
+            );
+            self.remove(&oldest);
+        }
+    }
+}
+
+fn assemble(partial: PartialDatagram) -> Option<Vec<u8>> {
+    let mut datagram = partial.header.unwrap_or_default();
+    let header_len = datagram.len();
+    datagram.extend_from_slice(&partial.data);
+
+    let total_len = u16::try_from(datagram.len()).ok()?;
+    datagram[2..4].copy_from_slice(&total_len.to_be_bytes());
+    // 清除 MF 和片偏移，保留 DF
+    datagram[6] &= 0b0100_0000;
+    datagram[7] = 0;
+    datagram[10..12].fill(0);
+    let checksum = simple_checksum(&datagram[..header_len]);
+    datagram[10..12].copy_from_slice(&checksum.to_be_bytes());
+    Some(datagram)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use protocol::ipv4::{Ipv4Option, fragment_datagram};
+
+    fn datagram(id: u16, payload_len: usize) -> Vec<u8> {
+        let mut datagram = Ipv4Header::new(
+            Ipv4Addr::new(10, 0, 0, 1),
+            Ipv4Addr::new(10, 0, 0, 2),
+            17,
+            payload_len as u16,
+            id,
+        )
+        .to_bytes();
+        datagram.extend((0..payload_len).map(|i| (i * 7) as u8));
+        datagram
+    }
+
+    fn push(reassembler: &mut Reassembler, fragment: &[u8], now: Instant) -> Option<Vec<u8>> {
+        let header = Ipv4Header::parse(fragment).unwrap();
+        reassembler.push(&header, fragment, now)
+    }
+
+    #[test]
+    fn reassembles_out_of_order_and_duplicate_fragments() {
+        let original = datagram(1, 3000);
+        let fragments = fragment_datagram(&original, 1000).unwrap();
+        assert_eq!(fragments.len(), 4);
+
+        let mut reassembler = Reassembler::default();
+        let now = Instant::now();
+        assert!(push(&mut reassembler, &fragments[3], now).is_none());
+        assert!(push(&mut reassembler, &fragments[1], now).is_none());
+        assert!(push(&mut reassembler, &fragments[1], now).is_none());
+        assert!(push(&mut reassembler, &fragments[0], now).is_none());
+        assert_eq!(reassembler.len(), 1);
+        let reassembled = push(&mut reassembler, &fragments[2], now).unwrap();
+
+        // 重组结果的首部已清除分片字段，校验和有效
+        assert_eq!(reassembled, original);
+        assert!(reassembler.is_empty());
+        assert_eq!(reassembler.memory(), 0);
+    }
+
+    #[test]
+    fn overlapping_fragments_keep_the_first_data() {
+        let original = datagram(2, 32);
+        let mut first = fragment_datagram(&original, 20 + 16).unwrap();
+        assert_eq!(first.len(), 2);
+        // 覆盖 [8, 24) 的伪造分片，内容全改为 0xff
+        let mut overlap = original[..20].to_vec();
+        overlap.extend_from_slice(&[0xff; 16]);
+        let mut header = Ipv4Header::parse(&overlap[..20]).unwrap();
+        header.total_len = 36;
+        header.frag_offset = 1;
+        header.flags = IPV4_FLAG_MF;
+        header.checksum = 0;
+        header.checksum = header.checksum();
+        overlap[..20].copy_from_slice(&header.to_bytes());
+
+        let mut reassembler = Reassembler::default();
+        let now = Instant::now();
+        assert!(push(&mut reassembler, &first[0], now).is_none());
+        assert!(push(&mut reassembler, &overlap, now).is_none());
+        let last = first.pop().unwrap();
+        let reassembled = push(&mut reassembler, &last, now).unwrap();
+        assert_eq!(reassembled[20..36], original[20..36]);
+        assert_eq!(reassembled[36..44], [0xff; 8]);
+        assert_eq!(reassembled[44..], original[44..]);
+    }
+
+    #[test]
+    fn inconsistent_fragments_drop_the_datagram() {
+        let fragments = fragment_datagram(&datagram(3, 3000), 1000).unwrap();
+        let mut reassembler = Reassembler::default();
+        let now = Instant::now();
+        assert!(push(&mut reassembler, &fragments[3], now).is_none());
+        // 更短的最后一片与已知总长冲突
+        let mut short = fragments[3].clone();
+        short.truncate(short.len() - 8);
+        let mut header = Ipv4Header::parse(&fragments[3]).unwrap();
+        header.total_len -= 8;
+        header.checksum = 0;
+        header.checksum = header.checksum();
+        short[..20].copy_from_slice(&header.to_bytes());
+        assert!(push(&mut reassembler, &short, now).is_none());
+        assert!(reassembler.is_empty());
+
+        // 中间分片长度不是 8 的倍数
+        let mut odd = fragments[1].clone();
+        odd.pop();
+        let mut header = Ipv4Header::parse(&fragments[1]).unwrap();
+        header.total_len -= 1;
+        header.checksum = 0;
+        header.checksum = header.checksum();
+        odd[..20].copy_from_slice(&header.to_bytes());
+        assert!(push(&mut reassembler, &odd, now).is_none());
+        assert!(reassembler.is_empty());
+    }
+
+    #[test]
+    fn expired_datagrams_quote_the_first_fragment() {
+        let fragments = fragment_datagram(&datagram(4, 3000), 1000).unwrap();
+        let mut reassembler = Reassembler::new(Duration::from_secs(30), DEFAULT_REASSEMBLY_MEMORY);
+        let start = Instant::now();
+        push(&mut reassembler, &fragments[0], start);
+        let other = fragment_datagram(&datagram(5, 3000), 1000).unwrap();
+        push(&mut reassembler, &other[1], start + Duration::from_secs(10));
+
+        assert!(
+            reassembler
+                .expire(start + Duration::from_secs(29))
+                .is_empty()
+        );
+        let expired = reassembler.expire(start + Duration::from_secs(30));
+        assert_eq!(expired.len(), 1);
+        assert_eq!(expired[0].src, Ipv4Addr::new(10, 0, 0, 1));
+        // 首部加前 8 字节载荷
+        assert_eq!(expired[0].quote.as_deref(), Some(&fragments[0][..28]));
+
+        // 没收到首片的数据报超时时没有可引用的内容
+        let expired = reassembler.expire(start + Duration::from_secs(40));
+        assert_eq!(expired.len(), 1);
+        assert!(expired[0].quote.is_none());
+        assert_eq!(reassembler.memory(), 0);
+    }
+
+    #[test]
+    fn memory_limit_drops_the_oldest_datagram() {
+        // 上限只够缓存一个完整的数据报 (每片 976 字节数据，首片另有 20 字节首部)
+        let mut reassembler = Reassembler::new(DEFAULT_REASSEMBLY_TIMEOUT, 3100);
+        let start = Instant::now();
+        let old = fragment_datagram(&datagram(6, 3000), 1000).unwrap();
+        let new = fragment_datagram(&datagram(7, 3000), 1000).unwrap();
+        push(&mut reassembler, &old[0], start);
+        push(&mut reassembler, &new[0], start + Duration::from_secs(1));
+        push(&mut reassembler, &new[1], start + Duration::from_secs(1));
+        assert_eq!(reassembler.len(), 2);
+
+        // 超出上限后最老的数据报被丢弃，新数据报仍能完成
+        push(&mut reassembler, &new[2], start + Duration::from_secs(2));
+        assert_eq!(reassembler.len(), 1);
+        assert!(reassembler.memory() <= 3100);
+        assert!(push(&mut reassembler, &new[3], start + Duration::from_secs(2)).is_some());
+        assert!(reassembler.is_empty());
+        assert!(push(&mut reassembler, &old[1], start + Duration::from_secs(2)).is_none());
+    }
+
+    /// 载荷 payload_len 字节的数据报切成每片 1480 字节，首片带 40 字节选项 (首部 60 字节)
+    fn fragments_with_long_header(id: u16, payload_len: usize) -> Vec<Vec<u8>> {
+        (0..payload_len)
+            .step_by(1480)
+            .map(|start| {
+                let len = 1480.min(payload_len - start);
+                let mut header = Ipv4Header::new(
+                    Ipv4Addr::new(10, 0, 0, 1),
+                    Ipv4Addr::new(10, 0, 0, 2),
+                    17,
+                    len as u16,
+                    id,
+                );
+                header.flags = if start + len < payload_len {
+                    IPV4_FLAG_MF
+                } else {
+                    0
+                };
+                header.frag_offset = (start / 8) as u16;
+                header.checksum = 0;
+                header.checksum = header.checksum();
+                if start == 0 {
+                    header
+                        .set_options(vec![Ipv4Option::NoOperation; 40])
+                        .unwrap();
+                }
+                let mut fragment = header.to_bytes();
+                fragment.extend((start..start + len).map(|i| i as u8));
+                fragment
+            })
+            .collect()
+    }
+
+    #[test]
+    fn reassembled_length_includes_the_first_header() {
+        // 60 字节首部加载荷正好 65535 字节
+        let fragments = fragments_with_long_header(8, 65535 - 60);
+        assert_eq!(fragments[0].len(), 60 + 1480);
+        let mut reassembler = Reassembler::default();
+        let now = Instant::now();
+        let (last, rest) = fragments.split_last().unwrap();
+        assert!(push(&mut reassembler, last, now).is_none());
+        let mut reassembled = None;
+        for fragment in rest {
+            reassembled = push(&mut reassembler, fragment, now);
+        }
+        let reassembled = reassembled.unwrap();
+        assert_eq!(reassembled.len(), 65535);
+        let header = Ipv4Header::parse(&reassembled).unwrap();
+        assert_eq!(header.total_len, 65535);
+        assert_eq!(header.header_len(), 60);
+
+        // 多 1 字节：最后一片先到时还不知道首部长度，首片到达后整个丢掉
+        let fragments = fragments_with_long_header(9, 65535 - 60 + 1);
+        let (last, rest) = fragments.split_last().unwrap();
+        assert!(push(&mut reassembler, last, now).is_none());
+        assert!(push(&mut reassembler, &rest[0], now).is_none());
+        assert!(reassembler.is_empty());
+        for fragment in &rest[1..] {
+            assert!(push(&mut reassembler, fragment, now).is_none());
+        }
+
+        // 首片先到时，超出的最后一片同样导致丢弃
+        let mut reassembler = Reassembler::default();
+        assert!(push(&mut reassembler, &rest[0], now).is_none());
+        assert!(push(&mut reassembler, last, now).is_none());
+        assert!(reassembler.is_empty());
+    }
+}
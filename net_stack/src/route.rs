@@ -0,0 +1,338 @@
+// Copyright (C) 2025 rrrrrzy
+// SPDX-License-Identifier: GPL-3.0-or-later
+//
+// --------------------------------------------------
+// 致敬所有在深夜调试代码的灵魂。
+// 即便 Bug 如山，我亦往矣。
+// --------------------------------------------------
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+use protocol::ipv4::Ipv4Addr;
+use std::fmt;
+use std::str::FromStr;
+
+/// 直连路由的默认 metric
+pub const CONNECTED_METRIC: u32 = 0;
+/// 默认路由 (网关) 的默认 metric
+pub const DEFAULT_GATEWAY_METRIC: u32 = 100;
+
+/// 一条路由表项
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Route {
+    pub prefix: Ipv4Addr,
+    pub netmask: Ipv4Addr,
+    /// 下一跳网关，None 表示目的网段直连
+    pub gateway: Option<Ipv4Addr>,
+    /// 出接口名，None 表示协议栈自身的接口
+    pub iface: Option<String>,
+    pub metric: u32,
+}
+
+impl Route {
+    pub fn new(
+        prefix: Ipv4Addr,
+        netmask: Ipv4Addr,
+        gateway: Option<Ipv4Addr>,
+        metric: u32,
+    ) -> Self {
+        Self {
+            // 统一把主机位清零，避免 10.0.0.1/8 这种写法导致匹配失败
+            prefix: Ipv4Addr::from_bits(prefix.to_bits() & netmask.to_bits()),
+            netmask,
+            gateway,
+            iface: None,
+            metric,
+        }
+    }
+
+    /// 由本机地址和掩码得到的直连路由
+    pub fn connected(ip: Ipv4Addr, netmask: Ipv4Addr) -> Self {
+        Self::new(ip, netmask, None, CONNECTED_METRIC)
+    }
+
+    /// 经由网关的默认路由 0.0.0.0/0
+    pub fn default_via(gateway: Ipv4Addr) -> Self {
+        Self::new(
+            Ipv4Addr::unspecified(),
+            Ipv4Addr::unspecified(),
+            Some(gateway),
+            DEFAULT_GATEWAY_METRIC,
+        )
+    }
+
+    /// 掩码中连续 1 的个数
+    pub fn prefix_len(&self) -> u32 {
+        self.netmask.to_bits().leading_ones()
+    }
+
+    pub fn matches(&self, dst: Ipv4Addr) -> bool {
+        dst.to_bits() & self.netmask.to_bits() == self.prefix.to_bits()
+    }
+
+    /// 发往 dst 时实际需要 ARP 解析的地址
+    pub fn next_hop(&self, dst: Ipv4Addr) -> Ipv4Addr {
+        self.gateway.unwrap_or(dst)
+    }
+}
+
+impl fmt::Display for Route {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.prefix, self.prefix_len())?;
+        if let Some(gw) = self.gateway {
+            write!(f, " via {}", gw)?;
+        }
+        if let Some(iface) = &self.iface {
+            write!(f, " dev {}", iface)?;
+        }
+        write!(f, " metric {}", self.metric)
+    }
+}
+
+/// 解析 `<prefix>/<len|mask> [via <gateway>] [dev <iface>] [metric <n>]`
+/// 例如 `10.1.0.0/16 via 192.168.1.254 metric 10`，`default via 192.168.1.1`
+impl FromStr for Route {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut tokens = s.split_whitespace();
+
+        let dst = tokens
+            .next()
+            .ok_or_else(|| anyhow::anyhow!("Empty route"))?;
+        let (prefix, netmask) = if dst == "default" {
+            (Ipv4Addr::unspecified(), Ipv4Addr::unspecified())
+        } else {
+            parse_cidr(dst)?
+        };
+
+        let mut gateway = None;
+        let mut iface = None;
+        let mut metric = None;
+        while let Some(key) = tokens.next() {
+            let value = tokens
+                .next()
+                .ok_or_else(|| anyhow::anyhow!("Missing value for '{}' in route '{}'", key, s))?;
+            match key {
+                "via" => {
+                    gateway = Some(
+                        Ipv4Addr::from_str(value)
+                            .map_err(|e| anyhow::anyhow!("Invalid gateway '{}': {}", value, e))?,
+                    )
+                }
+                "dev" => iface = Some(value.to_string()),
+                "metric" => {
+                    metric = Some(
+                        value
+                            .parse::<u32>()
+                            .map_err(|_| anyhow::anyhow!("Invalid metric '{}'", value))?,
+                    )
+                }
+                _ => anyhow::bail!("Unknown route attribute '{}' in '{}'", key, s),
+            }
+        }
+
+        let default_metric = if gateway.is_some() {
+            DEFAULT_GATEWAY_METRIC
+        } else {
+            CONNECTED_METRIC
+        };
+        let mut route = Route::new(prefix, netmask, gateway, metric.unwrap_or(default_metric));
+        route.iface = iface;
+        Ok(route)
+    }
+}
+
+/// 解析 `a.b.c.d/len` 或 `a.b.c.d/m.m.m.m`，不带掩码时视为主机路由 /32
+pub fn parse_cidr(s: &str) -> anyhow::Result<(Ipv4Addr, Ipv4Addr)> {
+    let (addr, mask) = match s.split_once('/') {
+        Some((addr, mask)) => (addr, Some(mask)),
+        None => (s, None),
+    };
+
+    let addr = Ipv4Addr::from_str(addr)
+        .map_err(|e| anyhow::anyhow!("Invalid prefix '{}': {}", addr, e))?;
+    let netmask = match mask {
+        None => Ipv4Addr::broadcast(),
+        Some(mask) if mask.contains('.') => parse_netmask(mask)?,
+        Some(len) => {
+            let len = len
+                .parse::<u32>()
+                .map_err(|_| anyhow::anyhow!("Invalid prefix length '{}'", len))?;
+            netmask_from_prefix_len(len)?
+        }
+    };
+
+    Ok((addr, netmask))
+}
+
+/// 解析点分十进制掩码，并检查 1 是否连续
+pub fn parse_netmask(s: &str) -> anyhow::Result<Ipv4Addr> {
+    let mask =
+        Ipv4Addr::from_str(s).map_err(|e| anyhow::anyhow!("Invalid netmask '{}': {}", s, e))?;
+    let bits = mask.to_bits();
+    if bits.leading_ones() + bits.trailing_zeros() != 32 {
+        anyhow::bail!("Netmask '{}' is not contiguous", s);
+    }
+    Ok(mask)
+}
+
+pub fn netmask_from_prefix_len(len: u32) -> anyhow::Result<Ipv4Addr> {
+    match len {
+        0 => Ok(Ipv4Addr::unspecified()),
+        1..=32 => Ok(Ipv4Addr::from_bits(u32::MAX << (32 - len))),
+        _ => anyhow::bail!("Prefix length {} out of range 0-32", len),
+    }
+}
+
+/// 路由表：最长前缀匹配，前缀相同时选 metric 最小的
+#[derive(Debug, Clone, Default)]
+pub struct RoutingTable {
+    routes: Vec<Route>,
+}
+
+impl RoutingTable {
+    pub fn new() -> Self {
+        Self { routes: Vec::new() }
+    }
+
+    /// 添加路由，目的网段、网关和 metric 都相同的旧表项会被替换
+    pub fn add(&mut self, route: Route) {
+        self.routes.retain(|r| {
+            !(r.prefix == route.prefix
+                && r.netmask == route.netmask
+                && r.gateway == route.gateway
+                && r.metric == route.metric)
+        });
+        self.routes.push(route);
+    }
+
+    /// 删除目的网段匹配的所有路由，返回删除的条数
+    pub fn remove(&mut self, prefix: Ipv4Addr, netmask: Ipv4Addr) -> usize {
+        let before = self.routes.len();
+        self.routes
+            .retain(|r| !(r.prefix == prefix && r.netmask == netmask));
+        before - self.routes.len()
+    }
+
+    pub fn lookup(&self, dst: Ipv4Addr) -> Option<&Route> {
+        self.routes
+            .iter()
+            .filter(|r| r.matches(dst))
+            .max_by(|a, b| {
+                a.prefix_len()
+                    .cmp(&b.prefix_len())
+                    .then(b.metric.cmp(&a.metric))
+            })
+    }
+
+    pub fn routes(&self) -> &[Route] {
+        &self.routes
+    }
+}
+
+impl fmt::Display for RoutingTable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for route in &self.routes {
+            writeln!(f, "{}", route)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn route(spec: &str) -> Route {
+        Route::from_str(spec).unwrap()
+    }
+
+    #[test]
+    fn parses_route_specs() {
+        let r = route("10.1.2.3/16 via 192.168.1.254 dev eth1 metric 10");
+        assert_eq!(r.prefix, Ipv4Addr::new(10, 1, 0, 0));
+        assert_eq!(r.netmask, Ipv4Addr::new(255, 255, 0, 0));
+        assert_eq!(r.gateway, Some(Ipv4Addr::new(192, 168, 1, 254)));
+        assert_eq!(r.iface.as_deref(), Some("eth1"));
+        assert_eq!(r.metric, 10);
+        assert_eq!(
+            r.to_string(),
+            "10.1.0.0/16 via 192.168.1.254 dev eth1 metric 10"
+        );
+
+        // 默认 metric：直连 0，经网关 100
+        assert_eq!(
+            route("default via 192.168.1.1").metric,
+            DEFAULT_GATEWAY_METRIC
+        );
+        assert_eq!(route("192.168.1.0/255.255.255.0").metric, CONNECTED_METRIC);
+        // 不带掩码视为主机路由
+        assert_eq!(route("10.0.0.9").prefix_len(), 32);
+    }
+
+    #[test]
+    fn rejects_invalid_route_specs() {
+        for spec in [
+            "",
+            "10.0.0.0/33",
+            "10.0.0.0/255.0.255.0",
+            "10.0.0.0/8 via",
+            "10.0.0.0/8 via 10.0.0",
+            "10.0.0.0/8 metric x",
+            "10.0.0.0/8 gw 10.0.0.1",
+        ] {
+            assert!(Route::from_str(spec).is_err(), "{:?}", spec);
+        }
+    }
+
+    #[test]
+    fn lookup_prefers_longest_prefix_then_lowest_metric() {
+        let mut table = RoutingTable::new();
+        table.add(route("default via 192.168.1.1"));
+        table.add(route("10.0.0.0/8 via 192.168.1.2 metric 5"));
+        table.add(route("10.1.0.0/16 via 192.168.1.3 metric 50"));
+        table.add(route("10.1.0.0/16 via 192.168.1.4 metric 20"));
+
+        let gateway = |dst| table.lookup(dst).and_then(|r| r.gateway);
+        assert_eq!(
+            gateway(Ipv4Addr::new(10, 1, 2, 3)),
+            Some(Ipv4Addr::new(192, 168, 1, 4))
+        );
+        assert_eq!(
+            gateway(Ipv4Addr::new(10, 2, 0, 1)),
+            Some(Ipv4Addr::new(192, 168, 1, 2))
+        );
+        assert_eq!(
+            gateway(Ipv4Addr::new(8, 8, 8, 8)),
+            Some(Ipv4Addr::new(192, 168, 1, 1))
+        );
+
+        // 直连网段的下一跳就是目的地址本身
+        table.add(Route::connected(
+            Ipv4Addr::new(192, 168, 1, 7),
+            Ipv4Addr::new(255, 255, 255, 0),
+        ));
+        let dst = Ipv4Addr::new(192, 168, 1, 9);
+        assert_eq!(table.lookup(dst).unwrap().next_hop(dst), dst);
+    }
+
+    #[test]
+    fn add_replaces_and_remove_deletes_by_prefix() {
+        let mut table = RoutingTable::new();
+        table.add(route("10.0.0.0/8 via 192.168.1.2"));
+        table.add(route("10.0.0.0/8 via 192.168.1.2 dev eth1"));
+        assert_eq!(table.routes().len(), 1);
+        assert_eq!(table.routes()[0].iface.as_deref(), Some("eth1"));
+
+        table.add(route("10.0.0.0/8 via 192.168.1.3"));
+        assert_eq!(
+            table.remove(Ipv4Addr::new(10, 0, 0, 0), Ipv4Addr::new(255, 0, 0, 0)),
+            2
+        );
+        assert!(table.lookup(Ipv4Addr::new(10, 0, 0, 1)).is_none());
+    }
+}
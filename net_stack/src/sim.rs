@@ -0,0 +1,1849 @@
+// Copyright (C) 2025 rrrrrzy
+// SPDX-License-Identifier: GPL-3.0-or-later
+//
+// --------------------------------------------------
+// 致敬所有在深夜调试代码的灵魂。
+// 即便 Bug 如山，我亦往矣。
+// --------------------------------------------------
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+//! 进程内虚拟网络
+//!
+//! 多个 `NetworkStack` 通过一个内存中的以太网段 (集线器或自学习交换机) 互联，
+//! 帧的投递按虚拟时钟推进，延迟、丢包、重复、乱序都由固定种子的伪随机数决定，
+//! 因此同样的配置每次运行得到的结果完全一致。协议栈的定时器 (重传、老化、重组超时等)
+//! 也读取同一个虚拟时钟，只随 `step` 前进。
+
+use protocol::mac::MacAddr;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::clock::VirtualClock;
+use crate::device::Device;
+use crate::handlers;
+use crate::stack::{self, NetworkStack, StackConfig};
+
+// 单次 step 内最多处理的轮数，防止两台主机互相无限回包
+const MAX_ROUNDS_PER_STEP: usize = 1024;
+
+/// 以太网段的转发方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SegmentKind {
+    /// 集线器：每一帧都复制到除入口外的所有端口
+    Hub,
+    /// 自学习交换机：根据源 MAC 学习端口，未知单播和广播才泛洪
+    Switch,
+}
+
+/// 链路损伤参数，概率取值范围为 0.0 - 1.0
+#[derive(Debug, Clone, Copy)]
+pub struct LinkConfig {
+    /// 每一帧的固定传输延迟
+    pub latency: Duration,
+    /// 丢包概率
+    pub loss: f64,
+    /// 重复投递概率
+    pub duplicate: f64,
+    /// 乱序概率，命中时额外延迟 `reorder_delay`
+    pub reorder: f64,
+    pub reorder_delay: Duration,
+    /// 伪随机数种子
+    pub seed: u64,
+}
+
+impl Default for LinkConfig {
+    fn default() -> Self {
+        Self {
+            latency: Duration::ZERO,
+            loss: 0.0,
+            duplicate: 0.0,
+            reorder: 0.0,
+            reorder_delay: Duration::from_millis(10),
+            seed: 0x5eed,
+        }
+    }
+}
+
+/// 以太网段的统计信息
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SegmentStats {
+    pub transmitted: u64,
+    pub delivered: u64,
+    pub dropped: u64,
+    pub duplicated: u64,
+    pub reordered: u64,
+}
+
+struct InFlightFrame {
+    deliver_at: Duration,
+    seq: u64,
+    frame: Vec<u8>,
+}
+
+struct Segment {
+    kind: SegmentKind,
+    link: LinkConfig,
+    now: Duration,
+    rng: XorShift64,
+    next_seq: u64,
+    ports: Vec<Vec<InFlightFrame>>,
+    mac_table: HashMap<MacAddr, usize>,
+    stats: SegmentStats,
+}
+
+impl Segment {
+    fn transmit(&mut self, from: usize, frame: &[u8]) {
+        self.stats.transmitted += 1;
+
+        if frame.len() < 12 {
+            self.stats.dropped += 1;
+            return;
+        }
+        let dst = MacAddr::from_slice(&frame[0..6]);
+        let src = MacAddr::from_slice(&frame[6..12]);
+
+        let targets: Vec<usize> = match self.kind {
+            SegmentKind::Hub => (0..self.ports.len()).filter(|&p| p != from).collect(),
+            SegmentKind::Switch => {
+                self.mac_table.insert(src, from);
+                match self.mac_table.get(&dst) {
+                    Some(&port) if port == from => Vec::new(),
+                    Some(&port) => vec![port],
+                    None => (0..self.ports.len()).filter(|&p| p != from).collect(),
+                }
+            }
+        };
+
+        for port in targets {
+            if self.rng.chance(self.link.loss) {
+                self.stats.dropped += 1;
+                continue;
+            }
+
+            let copies = if self.rng.chance(self.link.duplicate) {
+                self.stats.duplicated += 1;
+                2
+            } else {
+                1
+            };
+
+            for _ in 0..copies {
+                let mut deliver_at = self.now + self.link.latency;
+                if self.rng.chance(self.link.reorder) {
+                    self.stats.reordered += 1;
+                    deliver_at += self.link.reorder_delay;
+                }
+
+                let seq = self.next_seq;
+                self.next_seq += 1;
+                self.ports[port].push(InFlightFrame {
+                    deliver_at,
+                    seq,
+                    frame: frame.to_vec(),
+                });
+            }
+        }
+    }
+
+    fn receive(&mut self, port: usize) -> Option<Vec<u8>> {
+        let now = self.now;
+        let queue = &mut self.ports[port];
+
+        // 取投递时间最早的帧，同一时刻按发送顺序
+        let (idx, _) = queue
+            .iter()
+            .enumerate()
+            .filter(|(_, f)| f.deliver_at <= now)
+            .min_by_key(|(_, f)| (f.deliver_at, f.seq))?;
+
+        self.stats.delivered += 1;
+        Some(queue.remove(idx).frame)
+    }
+
+    fn has_due_frames(&self) -> bool {
+        self.ports
+            .iter()
+            .flatten()
+            .any(|f| f.deliver_at <= self.now)
+    }
+
+    fn next_deadline(&self) -> Option<Duration> {
+        self.ports.iter().flatten().map(|f| f.deliver_at).min()
+    }
+}
+
+/// 接在虚拟以太网段某个端口上的设备
+#[derive(Clone)]
+pub struct SimDevice {
+    segment: Arc<Mutex<Segment>>,
+    port: usize,
+}
+
+impl Device for SimDevice {
+    fn receive(&mut self) -> anyhow::Result<Option<Vec<u8>>> {
+        Ok(self.segment.lock().unwrap().receive(self.port))
+    }
+
+    fn transmit(&mut self, frame: &[u8]) -> anyhow::Result<()> {
+        self.segment.lock().unwrap().transmit(self.port, frame);
+        Ok(())
+    }
+}
+
+/// 虚拟网络：一个以太网段加上挂在上面的若干协议栈
+pub struct Simulator {
+    segment: Arc<Mutex<Segment>>,
+    clock: Arc<VirtualClock>,
+    hosts: Vec<Arc<NetworkStack>>,
+}
+
+impl Simulator {
+    pub fn new(kind: SegmentKind, link: LinkConfig) -> Self {
+        let segment = Segment {
+            kind,
+            link,
+            now: Duration::ZERO,
+            rng: XorShift64::new(link.seed),
+            next_seq: 0,
+            ports: Vec::new(),
+            mac_table: HashMap::new(),
+            stats: SegmentStats::default(),
+        };
+
+        Self {
+            segment: Arc::new(Mutex::new(segment)),
+            clock: Arc::new(VirtualClock::new()),
+            hosts: Vec::new(),
+        }
+    }
+
+    /// 在以太网段上新开一个端口，并挂上一台主机
+    pub fn add_host(&mut self, config: StackConfig) -> Arc<NetworkStack> {
+        let dev = self.add_device();
+        let host = stack::initialize_with_clock(config, dev.clone(), dev, self.clock.clone());
+        self.hosts.push(host.clone());
+        host
+    }
+
+    /// 只开一个端口，不挂协议栈，可用来旁路抓包或手动注入帧
+    pub fn add_device(&mut self) -> SimDevice {
+        let mut segment = self.segment.lock().unwrap();
+        segment.ports.push(Vec::new());
+        SimDevice {
+            segment: self.segment.clone(),
+            port: segment.ports.len() - 1,
+        }
+    }
+
+    pub fn hosts(&self) -> &[Arc<NetworkStack>] {
+        &self.hosts
+    }
+
+    /// 当前虚拟时间
+    pub fn now(&self) -> Duration {
+        self.segment.lock().unwrap().now
+    }
+
+    pub fn stats(&self) -> SegmentStats {
+        self.segment.lock().unwrap().stats
+    }
+
+    /// 推进虚拟时钟 `dt`，然后处理所有已经到期的帧和 socket 发送队列
+    pub fn step(&mut self, dt: Duration) {
+        self.segment.lock().unwrap().now += dt;
+        self.clock.advance(dt);
+
+        for _ in 0..MAX_ROUNDS_PER_STEP {
+            for host in &self.hosts {
+                host.poll_and_send();
+                loop {
+                    let frame = host.get_rx_device().lock().unwrap().receive();
+                    match frame {
+                        Ok(Some(frame)) => host.receive(&frame),
+                        _ => break,
+                    }
+                }
+                host.poll_and_send();
+            }
+
+            if !self.segment.lock().unwrap().has_due_frames() {
+                break;
+            }
+        }
+
+        for host in &self.hosts {
+            handlers::igmp::poll(host);
+            host.cleanup_pending_packets();
+            host.cleanup_fragments();
+            host.cleanup_ip_ids();
+        }
+    }
+
+    /// 以 `tick` 为步长运行 `duration` 的虚拟时间
+    pub fn run_for(&mut self, duration: Duration, tick: Duration) {
+        let end = self.now() + duration;
+        while self.now() < end {
+            self.step(tick);
+        }
+    }
+
+    /// 一直运行到段上没有在途帧为止，直接跳到下一帧的投递时间
+    pub fn run_until_idle(&mut self) {
+        self.step(Duration::ZERO);
+        loop {
+            let deadline = self.segment.lock().unwrap().next_deadline();
+            match deadline {
+                Some(at) => {
+                    let now = self.now();
+                    self.step(at.saturating_sub(now));
+                }
+                None => break,
+            }
+        }
+    }
+}
+
+/// 固定种子的 xorshift64* 伪随机数生成器
+struct XorShift64(u64);
+
+impl XorShift64 {
+    fn new(seed: u64) -> Self {
+        // 种子为 0 时 xorshift 会一直输出 0
+        Self(if seed == 0 {
+            0x9E37_79B9_7F4A_7C15
+        } else {
+            seed
+        })
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        let mut x = self.0;
+        x ^= x >> 12;
+        x ^= x << 25;
+        x ^= x >> 27;
+        self.0 = x;
+        x.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    fn chance(&mut self, p: f64) -> bool {
+        if p <= 0.0 {
+            return false;
+        }
+        // 取高 53 位映射到 [0, 1)
+        let v = (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64;
+        v < p
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::TracerouteMethod;
+    use crate::dhcp::client::{DhcpClient, DhcpState};
+    use crate::dhcp::server::{DhcpServer, DhcpServerConfig};
+    use crate::dns::mdns::MdnsResponder;
+    use crate::dns::resolver::{ResolveError, Resolver, ResolverConfig};
+    use crate::ping::{PingConfig, ping};
+    use crate::route::Route;
+    use crate::slaac::SlaacClient;
+    use crate::traceroute::{Hop, TracerouteConfig, traceroute};
+    use crate::transport::tcp::{TcpError, TcpListener, TcpSocketState, TcpState, TcpStream};
+    use crate::transport::udp::UdpSocket;
+    use crate::transport::{Socket, SocketHandle, SocketType};
+    use protocol::dns::{DnsMessage, DnsRecord, DnsRecordData, DnsResponseCode};
+    use protocol::ethernet::{EtherType, EthernetHeader, VlanTag};
+    use protocol::icmp::{DestUnreachableCode, Echo, IcmpMessage};
+    use protocol::icmpv6::{
+        Icmpv6Message, Icmpv6ParameterProblemCode, NDP_HOP_LIMIT, NdpOption, NeighborState,
+        PrefixInformation,
+    };
+    use protocol::igmp::{GroupRecord, GroupRecordType, IgmpMessage, Igmpv3Query};
+    use protocol::ipv4::{Ipv4Addr, Ipv4Header, Ipv4Option, Ipv4Protocol};
+    use protocol::ipv6::{
+        IPV6_NEXT_DEST_OPTIONS, IPV6_NEXT_ICMPV6, IPV6_NEXT_UDP, IPV6_OPT_PADN, Ipv6Addr, Ipv6Cidr,
+        Ipv6Header, Ipv6Protocol, stable_privacy_interface_id,
+    };
+    use protocol::udp::{UdpHeader, UdpPacket};
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::thread;
+
+    const A_IP: Ipv4Addr = Ipv4Addr::new(10, 0, 0, 1);
+    const B_IP: Ipv4Addr = Ipv4Addr::new(10, 0, 0, 2);
+
+    fn host(id: u8) -> StackConfig {
+        let mut config = StackConfig::new(
+            MacAddr::from_raw([0x02, 0, 0, 0, 0, id]),
+            Ipv4Addr::new(10, 0, 0, id),
+        );
+        config.netmask = Ipv4Addr::new(255, 255, 255, 0);
+        config
+    }
+
+    fn pair(link: LinkConfig) -> (Simulator, Arc<NetworkStack>, Arc<NetworkStack>) {
+        let mut sim = Simulator::new(SegmentKind::Switch, link);
+        let a = sim.add_host(host(1));
+        let b = sim.add_host(host(2));
+        (sim, a, b)
+    }
+
+    #[test]
+    fn udp_datagram_crosses_switch() {
+        let (mut sim, a, b) = pair(LinkConfig::default());
+        let server = UdpSocket::bind(b.clone(), "0.0.0.0:9000").unwrap();
+        let client = UdpSocket::bind(a.clone(), "0.0.0.0:40000").unwrap();
+
+        client.send_to(b"ping", "10.0.0.2:9000").unwrap();
+        sim.run_until_idle();
+        let (data, src) = server.recv_from().unwrap();
+        assert_eq!(data, b"ping");
+        assert_eq!(src, "10.0.0.1:40000");
+
+        server.send_to(b"pong", &src).unwrap();
+        sim.run_until_idle();
+        assert_eq!(client.recv_from().unwrap().0, b"pong");
+        // ARP 请求、ARP 响应、两个 UDP 数据报
+        assert_eq!(sim.stats().delivered, 4);
+    }
+
+    #[test]
+    fn large_udp_datagram_is_fragmented_and_reassembled() {
+        let (mut sim, a, b) = pair(LinkConfig::default());
+        let server = UdpSocket::bind(b.clone(), "0.0.0.0:9000").unwrap();
+        let client = UdpSocket::bind(a.clone(), "0.0.0.0:40000").unwrap();
+
+        let payload: Vec<u8> = (0..4000).map(|i| i as u8).collect();
+        client.send_to(&payload, "10.0.0.2:9000").unwrap();
+        sim.run_until_idle();
+        let (data, _) = server.recv_from().unwrap();
+        assert_eq!(data, payload);
+        assert!(b.reassembler().lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn arp_entries_age_with_virtual_clock() {
+        let (mut sim, a, b) = pair(LinkConfig::default());
+        let _server = UdpSocket::bind(b, "0.0.0.0:9000").unwrap();
+        let client = UdpSocket::bind(a.clone(), "0.0.0.0:0").unwrap();
+        client.send_to(b"x", "10.0.0.2:9000").unwrap();
+        sim.run_until_idle();
+
+        let lookup = || a.arp_table().lock().unwrap().lookup(B_IP, a.now());
+        assert!(lookup().is_some());
+        sim.run_for(Duration::from_secs(299), Duration::from_secs(1));
+        assert!(lookup().is_some());
+        sim.run_for(Duration::from_secs(2), Duration::from_secs(1));
+        assert!(lookup().is_none());
+    }
+
+    #[test]
+    fn unresolved_packets_expire_after_three_virtual_seconds() {
+        let (mut sim, a, _b) = pair(LinkConfig::default());
+        let client = UdpSocket::bind(a.clone(), "0.0.0.0:0").unwrap();
+        client.send_to(b"x", "10.0.0.99:9").unwrap();
+
+        sim.run_for(Duration::from_secs(2), Duration::from_millis(100));
+        assert!(
+            a.pending_packets()
+                .lock()
+                .unwrap()
+                .contains_key(&Ipv4Addr::new(10, 0, 0, 99))
+        );
+        sim.run_for(Duration::from_secs(2), Duration::from_millis(100));
+        assert!(a.pending_packets().lock().unwrap().is_empty());
+        assert_eq!(sim.now(), Duration::from_secs(4));
+    }
+
+    #[test]
+    fn impairments_are_deterministic() {
+        let link = LinkConfig {
+            latency: Duration::from_millis(1),
+            loss: 0.3,
+            duplicate: 0.1,
+            reorder: 0.2,
+            ..Default::default()
+        };
+        let run = || {
+            let (mut sim, a, b) = pair(link);
+            let _server = UdpSocket::bind(b, "0.0.0.0:9000").unwrap();
+            let client = UdpSocket::bind(a, "0.0.0.0:40000").unwrap();
+            for i in 0..50u8 {
+                client.send_to(&[i], "10.0.0.2:9000").unwrap();
+                sim.step(Duration::from_millis(1));
+            }
+            sim.run_until_idle();
+            sim.stats()
+        };
+
+        let stats = run();
+        assert!(stats.dropped > 0 && stats.duplicated > 0 && stats.reordered > 0);
+        assert_eq!(stats, run());
+    }
+
+    // 握手和重传都由虚拟时钟驱动：丢包后的 RTO 超时只随 step 前进
+    #[test]
+    fn tcp_recovers_from_loss_in_virtual_time() {
+        let link = LinkConfig {
+            latency: Duration::from_millis(5),
+            loss: 0.1,
+            ..Default::default()
+        };
+        let (mut sim, a, b) = pair(link);
+        // 提前写好 ARP，让丢包只影响 TCP
+        static_arp(&a, &b);
+
+        let listener = TcpListener::bind(b.clone(), "10.0.0.2:8080").unwrap();
+        let client = connect(&mut sim, a, "10.0.0.2:8080").unwrap();
+        sim.run_until_idle();
+        let (server, _) = listener.accept().unwrap();
+
+        let data: Vec<u8> = (0..20_000u32).map(|i| i as u8).collect();
+        let mut sent = 0;
+        let mut received = Vec::new();
+        while received.len() < data.len() {
+            assert!(sim.now() < Duration::from_secs(120), "transfer timed out");
+            if sent < data.len() {
+                sent += client.send(&data[sent..]).unwrap();
+            }
+            sim.step(Duration::from_millis(10));
+            if let Ok(chunk) = server.recv() {
+                received.extend_from_slice(&chunk);
+            }
+        }
+        assert_eq!(received, data);
+        assert!(sim.stats().dropped > 0);
+    }
+
+    /// 两端预先写好 ARP，握手不受地址解析影响
+    fn static_arp(a: &NetworkStack, b: &NetworkStack) {
+        a.arp_table().lock().unwrap().insert_static(
+            B_IP,
+            MacAddr::from_raw([0x02, 0, 0, 0, 0, 2]),
+            a.now(),
+        );
+        b.arp_table().lock().unwrap().insert_static(
+            A_IP,
+            MacAddr::from_raw([0x02, 0, 0, 0, 0, 1]),
+            b.now(),
+        );
+    }
+
+    fn tcp_state(stack: &NetworkStack, handle: SocketHandle) -> Option<TcpState> {
+        match stack.sockets.lock().unwrap().get(handle) {
+            Some(Socket::Tcp(socket)) => Some(socket.state()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn tcp_simultaneous_open_establishes() {
+        let (mut sim, a, b) = pair(LinkConfig::default());
+        static_arp(&a, &b);
+
+        // 两端同时向对方的固定端口发 SYN
+        let handle_a = SocketHandle::new(&SocketType::Tcp, A_IP.into(), 5000, B_IP.into(), 6000);
+        let handle_b = SocketHandle::new(&SocketType::Tcp, B_IP.into(), 6000, A_IP.into(), 5000);
+        let socket_a = TcpSocketState::connect(A_IP, 5000, B_IP, 6000, 1460);
+        let socket_b = TcpSocketState::connect(B_IP, 6000, A_IP, 5000, 1460);
+        a.sockets
+            .lock()
+            .unwrap()
+            .add(handle_a, Socket::Tcp(Box::new(socket_a)));
+        b.sockets
+            .lock()
+            .unwrap()
+            .add(handle_b, Socket::Tcp(Box::new(socket_b)));
+
+        sim.run_until_idle();
+        assert_eq!(tcp_state(&a, handle_a), Some(TcpState::Established));
+        assert_eq!(tcp_state(&b, handle_b), Some(TcpState::Established));
+    }
+
+    #[test]
+    fn accepted_connections_leave_the_backlog() {
+        let (mut sim, a, b) = pair(LinkConfig::default());
+        static_arp(&a, &b);
+
+        let listener = TcpListener::bind(b.clone(), "10.0.0.2:8080").unwrap();
+        let _client = connect(&mut sim, a, "10.0.0.2:8080").unwrap();
+        sim.run_until_idle();
+        let (_server, _) = listener.accept().unwrap();
+
+        // 已 accept 的连接不再挂在监听 socket 下，不计入 backlog
+        let sockets = b.sockets.lock().unwrap();
+        assert!(sockets.iter().all(|(_, socket)| match socket {
+            Socket::Tcp(socket) => socket.parent().is_none(),
+            _ => true,
+        }));
+    }
+
+    /// 在另一个线程里阻塞 connect，同时推进仿真
+    fn connect(
+        sim: &mut Simulator,
+        stack: Arc<NetworkStack>,
+        addr: &str,
+    ) -> anyhow::Result<TcpStream> {
+        let addr = addr.to_string();
+        let connecting = thread::spawn(move || TcpStream::connect(stack, &addr));
+        while !connecting.is_finished() {
+            assert!(sim.now() < Duration::from_secs(30), "connect timed out");
+            sim.step(Duration::from_millis(1));
+            thread::sleep(Duration::from_micros(100));
+        }
+        connecting.join().unwrap()
+    }
+
+    #[test]
+    fn tcp_connect_to_closed_port_is_refused() {
+        let (mut sim, a, _b) = pair(LinkConfig::default());
+        let Err(error) = connect(&mut sim, a, "10.0.0.2:81") else {
+            panic!("connected to a closed port");
+        };
+        assert_eq!(error.downcast_ref::<TcpError>(), Some(&TcpError::Refused));
+    }
+
+    #[test]
+    fn tcp_close_walks_both_sides_through_the_fin_handshake() {
+        let (mut sim, a, b) = pair(LinkConfig::default());
+        let listener = TcpListener::bind(b.clone(), "10.0.0.2:8080").unwrap();
+        let client = connect(&mut sim, a.clone(), "10.0.0.2:8080").unwrap();
+        sim.run_until_idle();
+        let (server, _) = listener.accept().unwrap();
+
+        client.send(b"bye").unwrap();
+        client.close();
+        sim.run_until_idle();
+        // 先读到数据，再读到 EOF
+        assert_eq!(server.recv().unwrap(), b"bye");
+        assert_eq!(server.recv().unwrap(), b"");
+        assert_eq!(server.state(), TcpState::CloseWait);
+        assert_eq!(client.state(), TcpState::FinWait2);
+        assert!(client.send(b"more").is_err());
+
+        // 半关闭后对端仍可发送
+        server.send(b"ok").unwrap();
+        server.close();
+        sim.run_until_idle();
+        assert_eq!(client.recv().unwrap(), b"ok");
+        assert_eq!(client.recv().unwrap(), b"");
+        assert_eq!(client.state(), TcpState::TimeWait);
+        assert_eq!(server.state(), TcpState::Closed);
+    }
+
+    /// 从旁路设备发出一个带目的选项首部的 UDP 报文，选项区中间是类型为 `kind` 的未知选项
+    fn send_unknown_option(
+        dev: &mut SimDevice,
+        src: Ipv6Addr,
+        dst: Ipv6Addr,
+        dst_mac: MacAddr,
+        kind: u8,
+    ) {
+        // Hdr Ext Len = 1：PadN(4) + 未知选项(2) + PadN(2)，未知选项位于扩展首部第 8 字节
+        let extension = [
+            IPV6_NEXT_UDP,
+            1,
+            IPV6_OPT_PADN,
+            4,
+            0,
+            0,
+            0,
+            0,
+            kind,
+            2,
+            0xaa,
+            0xbb,
+            IPV6_OPT_PADN,
+            2,
+            0,
+            0,
+        ];
+        let udp = [0x12, 0x34, 0x00, 0x35, 0x00, 0x08, 0x00, 0x00];
+        let mut header = Ipv6Header::new(
+            src,
+            dst,
+            IPV6_NEXT_UDP,
+            (extension.len() + udp.len()) as u16,
+        );
+        header.protocol = IPV6_NEXT_DEST_OPTIONS;
+
+        let mut frame = EthernetHeader::new(
+            MacAddr::from_raw([0x02, 0, 0, 0, 0, 0x99]),
+            dst_mac,
+            EtherType::Ipv6,
+        )
+        .to_bytes();
+        frame.extend_from_slice(&header.to_bytes());
+        frame.extend_from_slice(&extension);
+        frame.extend_from_slice(&udp);
+        dev.transmit(&frame).unwrap();
+    }
+
+    /// 旁路设备收到的 ICMPv6 报文
+    fn received_icmpv6(dev: &mut SimDevice) -> Vec<(Ipv6Header, Icmpv6Message)> {
+        let mut messages = Vec::new();
+        while let Some(frame) = dev.receive().unwrap() {
+            let Ok(eth) = EthernetHeader::parse(&frame) else {
+                continue;
+            };
+            let packet = &frame[eth.header_len()..];
+            if eth.ethertype != EtherType::Ipv6 {
+                continue;
+            }
+            if let Ok(header) = Ipv6Header::parse(packet)
+                && header.get_protocol() == Ipv6Protocol::ICMPv6
+                && let Ok(message) =
+                    Icmpv6Message::parse(&packet[header.header_len()..], header.src, header.dst)
+            {
+                messages.push((header, message));
+            }
+        }
+        messages
+    }
+
+    #[test]
+    fn unknown_ipv6_option_is_reported() {
+        // 旁路设备不会取走发给自己的帧，不能用 run_until_idle
+        let mut sim = Simulator::new(SegmentKind::Switch, LinkConfig::default());
+        let b = sim.add_host(host(2));
+        let mut dev = sim.add_device();
+        let b_mac = MacAddr::from_raw([0x02, 0, 0, 0, 0, 2]);
+        let b_ip = Ipv6Addr::link_local_eui64(b_mac);
+        let peer = Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 0x99);
+        b.add_ipv6_address(Ipv6Cidr::new(b_ip, 64));
+        b.neighbor_cache().lock().unwrap().insert_static(
+            peer,
+            MacAddr::from_raw([0x02, 0, 0, 0, 0, 0x99]),
+            b.now(),
+        );
+
+        // 最高两位 10：回复 Parameter Problem code 2，指针指向选项类型字节
+        send_unknown_option(&mut dev, peer, b_ip, b_mac, 0x80);
+        sim.run_for(Duration::from_millis(10), Duration::from_millis(1));
+        let messages = received_icmpv6(&mut dev);
+        assert_eq!(messages.len(), 1);
+        match &messages[0].1 {
+            Icmpv6Message::ParameterProblem { code, pointer, .. } => {
+                assert_eq!(*code, Icmpv6ParameterProblemCode::UnrecognizedOption);
+                assert_eq!(*pointer, 40 + 8);
+            }
+            other => panic!("unexpected {}", other),
+        }
+
+        // 最高两位 10 时发给组播地址也回复，源地址用本机单播地址
+        send_unknown_option(
+            &mut dev,
+            peer,
+            Ipv6Addr::all_nodes(),
+            MacAddr::broadcast(),
+            0x80,
+        );
+        sim.run_for(Duration::from_millis(10), Duration::from_millis(1));
+        let messages = received_icmpv6(&mut dev);
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].0.src, b_ip);
+
+        // 最高两位 11 时发给组播地址不回复，01 时一律静默丢弃
+        send_unknown_option(
+            &mut dev,
+            peer,
+            Ipv6Addr::all_nodes(),
+            MacAddr::broadcast(),
+            0xc0,
+        );
+        send_unknown_option(&mut dev, peer, b_ip, b_mac, 0x40);
+        sim.run_for(Duration::from_millis(10), Duration::from_millis(1));
+        assert!(received_icmpv6(&mut dev).is_empty());
+    }
+
+    #[test]
+    fn ipv6_udp_resolves_neighbors() {
+        let (mut sim, a, b) = pair(LinkConfig::default());
+        let a_ip = Ipv6Addr::link_local_eui64(MacAddr::from_raw([0x02, 0, 0, 0, 0, 1]));
+        let b_ip = Ipv6Addr::link_local_eui64(MacAddr::from_raw([0x02, 0, 0, 0, 0, 2]));
+        a.add_ipv6_address(Ipv6Cidr::new(a_ip, 64));
+        b.add_ipv6_address(Ipv6Cidr::new(b_ip, 64));
+        let server = UdpSocket::bind(b.clone(), "[::]:9000").unwrap();
+        let client = UdpSocket::bind(a.clone(), "[::]:40000").unwrap();
+
+        client
+            .send_to(b"ping", &format!("[{}]:9000", b_ip))
+            .unwrap();
+        sim.run_until_idle();
+        let (data, src) = server.recv_from().unwrap();
+        assert_eq!(data, b"ping");
+        assert_eq!(src, format!("[{}]:40000", a_ip));
+
+        // 请求方从被请求的通告确认了对方，应答方只从请求的源链路层地址学到 Stale 项
+        assert_eq!(
+            a.neighbor_cache().lock().unwrap().state(b_ip, a.now()),
+            Some(NeighborState::Reachable)
+        );
+        assert_eq!(
+            b.neighbor_cache().lock().unwrap().state(a_ip, b.now()),
+            Some(NeighborState::Stale)
+        );
+
+        server.send_to(b"pong", &src).unwrap();
+        sim.run_until_idle();
+        assert_eq!(client.recv_from().unwrap().0, b"pong");
+        // 邻居请求、邻居通告、两个 UDP 数据报
+        assert_eq!(sim.stats().delivered, 4);
+    }
+
+    /// 从旁路设备 (fe80::99) 注入一个 ICMPv6 报文
+    fn send_icmpv6(
+        dev: &mut SimDevice,
+        dst: Ipv6Addr,
+        dst_mac: MacAddr,
+        hop_limit: u8,
+        message: &Icmpv6Message,
+    ) {
+        let src = Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 0x99);
+        let payload = message.to_bytes(src, dst);
+        let mut header = Ipv6Header::new(src, dst, IPV6_NEXT_ICMPV6, payload.len() as u16);
+        header.hop_limit = hop_limit;
+
+        let mut frame = EthernetHeader::new(
+            MacAddr::from_raw([0x02, 0, 0, 0, 0, 0x99]),
+            dst_mac,
+            EtherType::Ipv6,
+        )
+        .to_bytes();
+        frame.extend_from_slice(&header.to_bytes());
+        frame.extend_from_slice(&payload);
+        dev.transmit(&frame).unwrap();
+    }
+
+    #[test]
+    fn neighbor_solicitations_and_echoes_are_answered() {
+        let mut sim = Simulator::new(SegmentKind::Switch, LinkConfig::default());
+        let b = sim.add_host(host(2));
+        let mut dev = sim.add_device();
+        let b_mac = MacAddr::from_raw([0x02, 0, 0, 0, 0, 2]);
+        let b_ip = Ipv6Addr::link_local_eui64(b_mac);
+        let peer = Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 0x99);
+        let peer_mac = MacAddr::from_raw([0x02, 0, 0, 0, 0, 0x99]);
+        b.add_ipv6_address(Ipv6Cidr::new(b_ip, 64));
+        let solicitation = Icmpv6Message::NeighborSolicitation {
+            target: b_ip,
+            options: vec![NdpOption::SourceLinkLayerAddr(peer_mac)],
+        };
+        let group = b_ip.solicited_node();
+
+        // Hop Limit 不是 255 的邻居请求可能来自别的链路，丢弃且不学习
+        send_icmpv6(
+            &mut dev,
+            group,
+            MacAddr::from_ipv6_multicast(group),
+            64,
+            &solicitation,
+        );
+        sim.run_for(Duration::from_millis(10), Duration::from_millis(1));
+        assert!(received_icmpv6(&mut dev).is_empty());
+        assert!(!b.neighbor_cache().lock().unwrap().contains(peer, b.now()));
+
+        send_icmpv6(
+            &mut dev,
+            group,
+            MacAddr::from_ipv6_multicast(group),
+            NDP_HOP_LIMIT,
+            &solicitation,
+        );
+        sim.run_for(Duration::from_millis(10), Duration::from_millis(1));
+        let messages = received_icmpv6(&mut dev);
+        assert_eq!(messages.len(), 1);
+        let (header, advertisement) = &messages[0];
+        assert_eq!((header.src, header.dst), (b_ip, peer));
+        assert_eq!(header.hop_limit, NDP_HOP_LIMIT);
+        assert_eq!(
+            *advertisement,
+            Icmpv6Message::NeighborAdvertisement {
+                router: false,
+                solicited: true,
+                override_: true,
+                target: b_ip,
+                options: vec![NdpOption::TargetLinkLayerAddr(b_mac)],
+            }
+        );
+        assert_eq!(
+            b.neighbor_cache().lock().unwrap().lookup(peer, b.now()),
+            Some(peer_mac)
+        );
+
+        // Echo 的数据原样返回，发给组播地址的请求用本机单播地址回复
+        let echo = Echo {
+            id: 0x4242,
+            seq: 3,
+            data: (0..100).collect(),
+        };
+        send_icmpv6(
+            &mut dev,
+            b_ip,
+            b_mac,
+            64,
+            &Icmpv6Message::EchoRequest(echo.clone()),
+        );
+        send_icmpv6(
+            &mut dev,
+            Ipv6Addr::all_nodes(),
+            MacAddr::from_ipv6_multicast(Ipv6Addr::all_nodes()),
+            64,
+            &Icmpv6Message::EchoRequest(echo.clone()),
+        );
+        sim.run_for(Duration::from_millis(10), Duration::from_millis(1));
+        let messages = received_icmpv6(&mut dev);
+        assert_eq!(messages.len(), 2);
+        for (header, message) in messages {
+            assert_eq!((header.src, header.dst), (b_ip, peer));
+            assert_eq!(message, Icmpv6Message::EchoReply(echo.clone()));
+        }
+    }
+
+    /// 在后台线程运行 SLAAC 客户端，旁路设备收到的 ICMPv6 报文交给 respond，done 返回 true 后停止客户端
+    fn run_slaac(
+        sim: &mut Simulator,
+        stack: &Arc<NetworkStack>,
+        dev: &mut SimDevice,
+        mut respond: impl FnMut(&mut SimDevice, &Ipv6Header, &Icmpv6Message),
+        done: impl Fn() -> bool,
+    ) -> anyhow::Result<()> {
+        let mut client = SlaacClient::new(stack.clone()).unwrap();
+        let cancel = Arc::new(AtomicBool::new(false));
+        let running = {
+            let cancel = cancel.clone();
+            thread::spawn(move || client.run(&cancel))
+        };
+        while !running.is_finished() {
+            assert!(sim.now() < Duration::from_secs(60), "SLAAC timed out");
+            if done() {
+                cancel.store(true, Ordering::Relaxed);
+            }
+            sim.step(Duration::from_millis(1));
+            thread::sleep(Duration::from_micros(100));
+            for (header, message) in received_icmpv6(dev) {
+                respond(dev, &header, &message);
+            }
+        }
+        running.join().unwrap()
+    }
+
+    #[test]
+    fn slaac_configures_addresses_from_router_advertisements() {
+        let mut sim = Simulator::new(SegmentKind::Switch, LinkConfig::default());
+        let a = sim.add_host(host(1));
+        let mut dev = sim.add_device();
+        let link_local = Ipv6Addr::link_local_eui64(MacAddr::from_raw([0x02, 0, 0, 0, 0, 1]));
+        let prefix = Ipv6Addr::new(0x2001, 0xdb8, 1, 0, 0, 0, 0, 0);
+        let global = Ipv6Addr::from_prefix_and_interface_id(prefix, link_local.interface_id());
+        let router = Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 0x99);
+        let advertisement = Icmpv6Message::RouterAdvertisement {
+            hop_limit: 64,
+            managed: false,
+            other: false,
+            router_lifetime: 1800,
+            reachable_time: 0,
+            retrans_timer: 0,
+            options: vec![
+                NdpOption::SourceLinkLayerAddr(MacAddr::from_raw([0x02, 0, 0, 0, 0, 0x99])),
+                NdpOption::Mtu(1400),
+                NdpOption::PrefixInformation(PrefixInformation {
+                    prefix_len: 64,
+                    on_link: true,
+                    autonomous: true,
+                    valid_lifetime: 86400,
+                    preferred_lifetime: 14400,
+                    prefix,
+                }),
+            ],
+        };
+
+        // 每个地址先做重复地址检测，链路本地地址就绪后才请求路由器
+        let mut probed = Vec::new();
+        let result = run_slaac(
+            &mut sim,
+            &a,
+            &mut dev,
+            |dev, header, message| match message {
+                Icmpv6Message::NeighborSolicitation { target, .. }
+                    if header.src.is_unspecified() =>
+                {
+                    assert_eq!(header.dst, target.solicited_node());
+                    assert!(message.nonce().is_some());
+                    probed.push(*target);
+                }
+                Icmpv6Message::RouterSolicitation { .. } => {
+                    assert_eq!(header.src, link_local);
+                    assert_eq!(header.dst, Ipv6Addr::all_routers());
+                    send_icmpv6(
+                        dev,
+                        Ipv6Addr::all_nodes(),
+                        MacAddr::from_ipv6_multicast(Ipv6Addr::all_nodes()),
+                        NDP_HOP_LIMIT,
+                        &advertisement,
+                    );
+                }
+                _ => {}
+            },
+            || a.is_local_ipv6(global),
+        );
+        result.unwrap();
+
+        assert_eq!(probed, vec![link_local, global]);
+        assert!(a.is_local_ipv6(link_local));
+        assert_eq!(a.config().ipv6_gateway, Some(router));
+        assert!(a.neighbor_cache().lock().unwrap().is_router(router));
+        assert_eq!(a.ipv6_mtu(), 1400);
+    }
+
+    #[test]
+    fn duplicate_addresses_are_detected() {
+        let mac = MacAddr::from_raw([0x02, 0, 0, 0, 0, 1]);
+        let link_local_prefix = Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 0);
+        // 对第一个重复地址检测请求回复邻居通告，冒充已在使用这个地址的主机
+        let claim_first = |probed: &mut Vec<Ipv6Addr>,
+                           dev: &mut SimDevice,
+                           header: &Ipv6Header,
+                           message: &Icmpv6Message| {
+            if let Icmpv6Message::NeighborSolicitation { target, .. } = message
+                && header.src.is_unspecified()
+            {
+                if probed.is_empty() {
+                    let advertisement = Icmpv6Message::NeighborAdvertisement {
+                        router: false,
+                        solicited: false,
+                        override_: true,
+                        target: *target,
+                        options: vec![NdpOption::TargetLinkLayerAddr(MacAddr::from_raw([
+                            0x02, 0, 0, 0, 0, 0x99,
+                        ]))],
+                    };
+                    send_icmpv6(
+                        dev,
+                        Ipv6Addr::all_nodes(),
+                        MacAddr::from_ipv6_multicast(Ipv6Addr::all_nodes()),
+                        NDP_HOP_LIMIT,
+                        &advertisement,
+                    );
+                }
+                probed.push(*target);
+            }
+        };
+
+        // EUI-64 地址冲突后无法换地址，IPv6 被禁用
+        let mut sim = Simulator::new(SegmentKind::Switch, LinkConfig::default());
+        let a = sim.add_host(host(1));
+        let mut dev = sim.add_device();
+        let mut probed = Vec::new();
+        let result = run_slaac(
+            &mut sim,
+            &a,
+            &mut dev,
+            |dev, header, message| claim_first(&mut probed, dev, header, message),
+            || false,
+        );
+        assert!(result.is_err());
+        assert_eq!(probed, vec![Ipv6Addr::link_local_eui64(mac)]);
+        assert!(a.config().ipv6_addrs.is_empty());
+
+        // 稳定隐私地址冲突后增加冲突计数，换一个接口标识重试
+        let secret = [7; 16];
+        let mut config = host(1);
+        config.ipv6_stable_secret = Some(secret);
+        let mut sim = Simulator::new(SegmentKind::Switch, LinkConfig::default());
+        let a = sim.add_host(config);
+        let mut dev = sim.add_device();
+        let [first, second] = [0, 1].map(|dad_counter| {
+            Ipv6Addr::from_prefix_and_interface_id(
+                link_local_prefix,
+                stable_privacy_interface_id(link_local_prefix, mac, dad_counter, &secret),
+            )
+        });
+        let mut probed = Vec::new();
+        let result = run_slaac(
+            &mut sim,
+            &a,
+            &mut dev,
+            |dev, header, message| claim_first(&mut probed, dev, header, message),
+            || a.is_local_ipv6(second),
+        );
+        result.unwrap();
+        assert_eq!(probed, vec![first, second]);
+        assert!(!a.is_local_ipv6(first));
+    }
+
+    /// 从旁路设备注入一个发往 group 的 IGMP 查询
+    fn send_igmp_query(dev: &mut SimDevice, group: Ipv4Addr, sources: Vec<Ipv4Addr>) {
+        let query = IgmpMessage::Query {
+            max_resp_code: 10,
+            group,
+            v3: Some(Igmpv3Query {
+                suppress_router_processing: false,
+                robustness: 2,
+                qqic: 125,
+                sources,
+            }),
+        }
+        .to_bytes();
+        let mut header =
+            Ipv4Header::new(Ipv4Addr::new(10, 0, 0, 99), group, 2, query.len() as u16, 1);
+        header.ttl = 1;
+        header
+            .set_options(vec![Ipv4Option::RouterAlert(0)])
+            .unwrap();
+        header.checksum = header.checksum();
+
+        let mut frame = EthernetHeader::new(
+            MacAddr::from_raw([0x02, 0, 0, 0, 0, 0x99]),
+            MacAddr::from_ipv4_multicast(group),
+            EtherType::Ipv4,
+        )
+        .to_bytes();
+        frame.extend_from_slice(&header.to_bytes());
+        frame.extend_from_slice(&query);
+        dev.transmit(&frame).unwrap();
+    }
+
+    /// 旁路设备收到的 v3 报告中的组记录
+    fn received_group_records(dev: &mut SimDevice) -> Vec<GroupRecord> {
+        let mut records = Vec::new();
+        while let Some(frame) = dev.receive().unwrap() {
+            let Ok(eth) = EthernetHeader::parse(&frame) else {
+                continue;
+            };
+            let packet = &frame[eth.header_len()..];
+            if eth.ethertype != EtherType::Ipv4 {
+                continue;
+            }
+            if let Ok(header) = Ipv4Header::parse(packet)
+                && header.get_protocol() == Ipv4Protocol::IGMP
+                && let Ok(IgmpMessage::V3Report { records: report }) =
+                    IgmpMessage::parse(&packet[header.header_len()..])
+            {
+                records.extend(report);
+            }
+        }
+        records
+    }
+
+    #[test]
+    fn igmp_source_specific_query_is_answered_with_is_in() {
+        let mut sim = Simulator::new(SegmentKind::Switch, LinkConfig::default());
+        let b = sim.add_host(host(2));
+        let mut dev = sim.add_device();
+        let group = Ipv4Addr::new(239, 1, 2, 3);
+        let s1 = Ipv4Addr::new(10, 0, 0, 50);
+        let s2 = Ipv4Addr::new(10, 0, 0, 51);
+        b.join_multicast(group).unwrap();
+        // 等主动报告发完
+        sim.run_for(Duration::from_secs(3), Duration::from_millis(10));
+        received_group_records(&mut dev);
+
+        // EXCLUDE({}) 的成员对特定组和源查询回答 IS_IN(查询的源)
+        send_igmp_query(&mut dev, group, vec![s1]);
+        sim.run_for(Duration::from_secs(2), Duration::from_millis(10));
+        let expected = GroupRecord {
+            record_type: GroupRecordType::ModeIsInclude,
+            group,
+            sources: vec![s1],
+            aux_data: Vec::new(),
+        };
+        assert_eq!(received_group_records(&mut dev), vec![expected]);
+
+        // 应答前再收到一个源查询，源合并后一起回答
+        send_igmp_query(&mut dev, group, vec![s1]);
+        send_igmp_query(&mut dev, group, vec![s2, s1]);
+        sim.run_for(Duration::from_secs(2), Duration::from_millis(10));
+        let records = received_group_records(&mut dev);
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].record_type, GroupRecordType::ModeIsInclude);
+        assert_eq!(records[0].sources, vec![s1, s2]);
+
+        // 特定组查询优先：回答 IS_EX({})
+        send_igmp_query(&mut dev, group, vec![s1]);
+        send_igmp_query(&mut dev, group, Vec::new());
+        sim.run_for(Duration::from_secs(2), Duration::from_millis(10));
+        assert_eq!(
+            received_group_records(&mut dev),
+            vec![GroupRecord::any_source(
+                GroupRecordType::ModeIsExclude,
+                group
+            )]
+        );
+    }
+
+    /// 旁路设备收到的发往广播 MAC 的 UDP 数据报的目的地址
+    fn received_broadcasts(dev: &mut SimDevice) -> Vec<Ipv4Addr> {
+        let mut dsts = Vec::new();
+        while let Some(frame) = dev.receive().unwrap() {
+            let Ok(eth) = EthernetHeader::parse(&frame) else {
+                continue;
+            };
+            if eth.ethertype != EtherType::Ipv4 || eth.dst != MacAddr::broadcast() {
+                continue;
+            }
+            if let Ok(header) = Ipv4Header::parse(&frame[eth.header_len()..])
+                && header.get_protocol() == Ipv4Protocol::UDP
+            {
+                dsts.push(header.dst);
+            }
+        }
+        dsts
+    }
+
+    #[test]
+    fn udp_broadcast_requires_permission() {
+        let mut sim = Simulator::new(SegmentKind::Switch, LinkConfig::default());
+        let a = sim.add_host(host(1));
+        let mut dev = sim.add_device();
+        let client = UdpSocket::bind(a.clone(), "0.0.0.0:40000").unwrap();
+
+        // 受限广播和本网段的定向广播都需要先开启广播权限
+        assert!(client.send_to(b"hi", "255.255.255.255:9000").is_err());
+        assert!(client.send_to(b"hi", "10.0.0.255:9000").is_err());
+        sim.run_for(Duration::from_millis(10), Duration::from_millis(1));
+        assert!(received_broadcasts(&mut dev).is_empty());
+
+        client.set_broadcast(true).unwrap();
+        assert!(client.broadcast().unwrap());
+        client.send_to(b"hi", "255.255.255.255:9000").unwrap();
+        client.send_to(b"hi", "10.0.0.255:9000").unwrap();
+        sim.run_for(Duration::from_millis(10), Duration::from_millis(1));
+        assert_eq!(
+            received_broadcasts(&mut dev),
+            vec![
+                Ipv4Addr::new(255, 255, 255, 255),
+                Ipv4Addr::new(10, 0, 0, 255)
+            ]
+        );
+    }
+
+    #[test]
+    fn udp_broadcast_checks_egress_interface() {
+        let mut sim = Simulator::new(SegmentKind::Switch, LinkConfig::default());
+        let a = sim.add_host(host(1));
+        let mut other = Simulator::new(SegmentKind::Switch, LinkConfig::default());
+        let mut config = StackConfig::new(
+            MacAddr::from_raw([0x02, 0, 0, 0, 1, 1]),
+            Ipv4Addr::new(10, 0, 1, 1),
+        );
+        config.netmask = Ipv4Addr::new(255, 255, 255, 0);
+        let eth1 = other.add_host(config);
+        let mut dev = other.add_device();
+        a.attach_interface("eth1", &eth1);
+        let mut route =
+            Route::connected(Ipv4Addr::new(10, 0, 1, 1), Ipv4Addr::new(255, 255, 255, 0));
+        route.iface = Some("eth1".to_string());
+        a.routing_table().lock().unwrap().add(route);
+        let client = UdpSocket::bind(a.clone(), "0.0.0.0:40000").unwrap();
+
+        // 10.0.1.255 不是 a 自己网段的广播地址，但在出接口 eth1 上是
+        assert!(client.send_to(b"hi", "10.0.1.255:9000").is_err());
+        client.set_broadcast(true).unwrap();
+        client.send_to(b"hi", "10.0.1.255:9000").unwrap();
+        sim.run_for(Duration::from_millis(10), Duration::from_millis(1));
+        other.run_for(Duration::from_millis(10), Duration::from_millis(1));
+        assert_eq!(
+            received_broadcasts(&mut dev),
+            vec![Ipv4Addr::new(10, 0, 1, 255)]
+        );
+    }
+
+    /// 从旁路设备 (10.0.0.99) 向 dst 的 dst_port 发送一个 UDP 数据报
+    fn send_udp(dev: &mut SimDevice, dst: Ipv4Addr, dst_mac: MacAddr, dst_port: u16) {
+        send_tagged_udp(dev, Vec::new(), dst, dst_mac, dst_port);
+    }
+
+    /// 同 send_udp，帧带着由外到内的 VLAN 标签
+    fn send_tagged_udp(
+        dev: &mut SimDevice,
+        vlans: Vec<VlanTag>,
+        dst: Ipv4Addr,
+        dst_mac: MacAddr,
+        dst_port: u16,
+    ) {
+        let src = Ipv4Addr::new(10, 0, 0, 99);
+        let udp = UdpPacket::new(
+            UdpHeader::new(40000, dst_port, 0),
+            b"probe".to_vec(),
+            src,
+            dst,
+        )
+        .to_bytes();
+        let header = Ipv4Header::new(src, dst, 17, udp.len() as u16, 1);
+        let mut frame = EthernetHeader {
+            vlans,
+            ..EthernetHeader::new(
+                MacAddr::from_raw([0x02, 0, 0, 0, 0, 0x99]),
+                dst_mac,
+                EtherType::Ipv4,
+            )
+        }
+        .to_bytes();
+        frame.extend_from_slice(&header.to_bytes());
+        frame.extend_from_slice(&udp);
+        dev.transmit(&frame).unwrap();
+    }
+
+    #[test]
+    fn vlan_bound_stacks_only_see_their_vlan() {
+        let mut sim = Simulator::new(SegmentKind::Switch, LinkConfig::default());
+        let tagged = |id| StackConfig {
+            vlans: VlanTag::stacked(&[100, 10]).unwrap(),
+            ..host(id)
+        };
+        let a = sim.add_host(tagged(1));
+        let b = sim.add_host(tagged(2));
+        let c = sim.add_host(host(3));
+        let mut dev = sim.add_device();
+        let b_mac = MacAddr::from_raw([0x02, 0, 0, 0, 0, 2]);
+        let c_ip = Ipv4Addr::new(10, 0, 0, 3);
+        let c_mac = MacAddr::from_raw([0x02, 0, 0, 0, 0, 3]);
+        let server = UdpSocket::bind(b.clone(), "0.0.0.0:9000").unwrap();
+        let client = UdpSocket::bind(a.clone(), "0.0.0.0:40000").unwrap();
+        let untagged = UdpSocket::bind(c.clone(), "0.0.0.0:9000").unwrap();
+
+        // 同一 VLAN 上的主机正常通信，广播出来的 ARP 请求带着 QinQ 标签
+        client.send_to(b"ping", "10.0.0.2:9000").unwrap();
+        sim.run_for(Duration::from_millis(10), Duration::from_millis(1));
+        assert_eq!(server.recv_from().unwrap().0, b"ping");
+        let mut flooded = 0;
+        while let Some(frame) = dev.receive().unwrap() {
+            let eth = EthernetHeader::parse(&frame).unwrap();
+            assert_eq!(eth.vlans, VlanTag::stacked(&[100, 10]).unwrap());
+            flooded += 1;
+        }
+        assert!(flooded > 0);
+        // 不带标签的主机看不到这个 VLAN 上的 ARP 请求
+        assert!(
+            c.arp_table()
+                .lock()
+                .unwrap()
+                .lookup(A_IP, c.now())
+                .is_none()
+        );
+
+        // 不带标签、标签不完整或 VID 不同的帧都被丢弃，优先级不影响接收
+        send_tagged_udp(&mut dev, Vec::new(), B_IP, b_mac, 9000);
+        send_tagged_udp(
+            &mut dev,
+            VlanTag::stacked(&[10]).unwrap(),
+            B_IP,
+            b_mac,
+            9000,
+        );
+        send_tagged_udp(
+            &mut dev,
+            VlanTag::stacked(&[100, 20]).unwrap(),
+            B_IP,
+            b_mac,
+            9000,
+        );
+        sim.run_for(Duration::from_millis(10), Duration::from_millis(1));
+        assert!(server.recv_from().is_err());
+        let mut priority = VlanTag::stacked(&[100, 10]).unwrap();
+        priority[1].pcp = 6;
+        send_tagged_udp(&mut dev, priority, B_IP, b_mac, 9000);
+        sim.run_for(Duration::from_millis(10), Duration::from_millis(1));
+        assert_eq!(server.recv_from().unwrap().0, b"probe");
+
+        // 没有绑定 VLAN 的主机接受只带优先级的标签 (VID 0)
+        send_tagged_udp(
+            &mut dev,
+            VlanTag::stacked(&[10]).unwrap(),
+            c_ip,
+            c_mac,
+            9000,
+        );
+        send_tagged_udp(&mut dev, vec![VlanTag::new(0).unwrap()], c_ip, c_mac, 9000);
+        sim.run_for(Duration::from_millis(10), Duration::from_millis(1));
+        assert_eq!(untagged.recv_from().unwrap().0, b"probe");
+        assert!(untagged.recv_from().is_err());
+    }
+
+    /// 旁路设备收到的 ICMP 报文
+    fn received_icmp(dev: &mut SimDevice) -> Vec<IcmpMessage> {
+        let mut messages = Vec::new();
+        while let Some(frame) = dev.receive().unwrap() {
+            let Ok(eth) = EthernetHeader::parse(&frame) else {
+                continue;
+            };
+            if eth.ethertype != EtherType::Ipv4 {
+                continue;
+            }
+            let packet = &frame[eth.header_len()..];
+            if let Ok(header) = Ipv4Header::parse(packet)
+                && header.get_protocol() == Ipv4Protocol::ICMP
+                && let Ok(message) = IcmpMessage::parse(&packet[header.header_len()..])
+            {
+                messages.push(message);
+            }
+        }
+        messages
+    }
+
+    #[test]
+    fn ping_counts_replies_and_losses() {
+        let (mut sim, a, _b) = pair(LinkConfig::default());
+        let config = PingConfig {
+            count: Some(3),
+            interval: Duration::from_millis(100),
+            timeout: Duration::from_millis(500),
+            quiet: true,
+            ..Default::default()
+        };
+
+        let run = |sim: &mut Simulator, target| {
+            let (a, config) = (a.clone(), config.clone());
+            let pinging = thread::spawn(move || ping(&a, target, &config, &AtomicBool::new(false)));
+            while !pinging.is_finished() {
+                assert!(sim.now() < Duration::from_secs(60), "ping timed out");
+                sim.step(Duration::from_millis(1));
+                thread::sleep(Duration::from_micros(100));
+            }
+            pinging.join().unwrap().unwrap()
+        };
+
+        let stats = run(&mut sim, B_IP);
+        assert_eq!((stats.transmitted, stats.received), (3, 3));
+        assert_eq!(stats.duplicates, 0);
+        assert_eq!(stats.rtts.len(), 3);
+        assert_eq!(stats.loss_percent(), 0.0);
+
+        // 不存在的主机：ARP 无法解析，全部丢失
+        let stats = run(&mut sim, Ipv4Addr::new(10, 0, 0, 3));
+        assert_eq!((stats.transmitted, stats.received), (3, 0));
+        assert_eq!(stats.loss_percent(), 100.0);
+    }
+
+    #[test]
+    fn echo_reply_returns_the_payload_verbatim() {
+        let mut sim = Simulator::new(SegmentKind::Switch, LinkConfig::default());
+        let b = sim.add_host(host(2));
+        let mut dev = sim.add_device();
+        let src = Ipv4Addr::new(10, 0, 0, 99);
+        b.arp_table().lock().unwrap().insert_static(
+            src,
+            MacAddr::from_raw([0x02, 0, 0, 0, 0, 0x99]),
+            b.now(),
+        );
+
+        // 任意长度、不带时间戳的载荷
+        let request = Echo {
+            id: 0xbeef,
+            seq: 3,
+            data: (0..37).map(|i| (i * 13) as u8).collect(),
+        };
+        let icmp = IcmpMessage::EchoRequest(request.clone()).to_bytes();
+        let header = Ipv4Header::new(src, B_IP, 1, icmp.len() as u16, 1);
+        let mut frame = EthernetHeader::new(
+            MacAddr::from_raw([0x02, 0, 0, 0, 0, 0x99]),
+            MacAddr::from_raw([0x02, 0, 0, 0, 0, 2]),
+            EtherType::Ipv4,
+        )
+        .to_bytes();
+        frame.extend_from_slice(&header.to_bytes());
+        frame.extend_from_slice(&icmp);
+        dev.transmit(&frame).unwrap();
+
+        sim.run_for(Duration::from_millis(10), Duration::from_millis(1));
+        assert_eq!(
+            received_icmp(&mut dev),
+            vec![IcmpMessage::EchoReply(request)]
+        );
+    }
+
+    #[test]
+    fn closed_udp_port_is_reported_with_rate_limit() {
+        let mut sim = Simulator::new(SegmentKind::Switch, LinkConfig::default());
+        let mut config = host(2);
+        config.icmp_rate_limit = 2;
+        let b = sim.add_host(config);
+        let mut dev = sim.add_device();
+        let b_mac = MacAddr::from_raw([0x02, 0, 0, 0, 0, 2]);
+        b.arp_table().lock().unwrap().insert_static(
+            Ipv4Addr::new(10, 0, 0, 99),
+            MacAddr::from_raw([0x02, 0, 0, 0, 0, 0x99]),
+            b.now(),
+        );
+
+        // 令牌桶只允许两个差错报文，引用原始首部和 8 字节 UDP 首部
+        for _ in 0..5 {
+            send_udp(&mut dev, B_IP, b_mac, 9999);
+        }
+        sim.run_for(Duration::from_millis(10), Duration::from_millis(1));
+        let messages = received_icmp(&mut dev);
+        assert_eq!(messages.len(), 2);
+        match &messages[0] {
+            IcmpMessage::DestUnreachable { code, original, .. } => {
+                assert_eq!(*code, DestUnreachableCode::PortUnreachable);
+                assert_eq!(original.len(), 20 + 8);
+                assert_eq!(&original[22..24], &9999u16.to_be_bytes());
+            }
+            other => panic!("unexpected {}", other),
+        }
+
+        // 一秒后令牌补满；发往广播地址的数据报不回复
+        sim.run_for(Duration::from_secs(1), Duration::from_millis(10));
+        send_udp(
+            &mut dev,
+            Ipv4Addr::new(10, 0, 0, 255),
+            MacAddr::broadcast(),
+            9999,
+        );
+        sim.run_for(Duration::from_millis(10), Duration::from_millis(1));
+        assert!(received_icmp(&mut dev).is_empty());
+        send_udp(&mut dev, B_IP, b_mac, 9999);
+        sim.run_for(Duration::from_millis(10), Duration::from_millis(1));
+        assert_eq!(received_icmp(&mut dev).len(), 1);
+    }
+
+    #[test]
+    fn unroutable_datagrams_are_reported_as_net_unreachable() {
+        let mut sim = Simulator::new(SegmentKind::Switch, LinkConfig::default());
+        let mut config = host(1);
+        config.forwarding = true;
+        config.icmp_rate_limit = 2;
+        let router = sim.add_host(config);
+        let mut dev = sim.add_device();
+        let router_mac = MacAddr::from_raw([0x02, 0, 0, 0, 0, 1]);
+        router.arp_table().lock().unwrap().insert_static(
+            Ipv4Addr::new(10, 0, 0, 99),
+            MacAddr::from_raw([0x02, 0, 0, 0, 0, 0x99]),
+            router.now(),
+        );
+
+        // 没有默认路由，发往其他网段的数据报回复 Net Unreachable，并受令牌桶限速
+        let unroutable = Ipv4Addr::new(192, 168, 5, 5);
+        for _ in 0..5 {
+            send_udp(&mut dev, unroutable, router_mac, 9999);
+        }
+        sim.run_for(Duration::from_millis(10), Duration::from_millis(1));
+        let messages = received_icmp(&mut dev);
+        assert_eq!(messages.len(), 2);
+        match &messages[0] {
+            IcmpMessage::DestUnreachable { code, original, .. } => {
+                assert_eq!(*code, DestUnreachableCode::NetUnreachable);
+                assert_eq!(&original[16..20], &unroutable.octets());
+            }
+            other => panic!("unexpected {}", other),
+        }
+    }
+
+    #[test]
+    fn traceroute_reports_router_then_target() {
+        // a (10.0.0.2) -- r0 (10.0.0.1) | r1 (10.1.0.1) -- b (10.1.0.2)，r0 和 r1 是同一路由器的两个接口
+        let mut lan0 = Simulator::new(SegmentKind::Switch, LinkConfig::default());
+        let mut lan1 = Simulator::new(SegmentKind::Switch, LinkConfig::default());
+        let config = |mac: u8, ip: [u8; 4], gateway: Option<[u8; 4]>| {
+            let mut config = StackConfig::new(
+                MacAddr::from_raw([0x02, 0, 0, 0, ip[2], mac]),
+                Ipv4Addr::from_octets(ip),
+            );
+            config.netmask = Ipv4Addr::new(255, 255, 255, 0);
+            config.gateway = gateway.map(Ipv4Addr::from_octets);
+            config
+        };
+        let mut r0 = config(1, [10, 0, 0, 1], None);
+        let mut r1 = config(1, [10, 1, 0, 1], None);
+        r0.forwarding = true;
+        r1.forwarding = true;
+        let mut to_lan1 = Route::connected(r1.ip, r1.netmask);
+        to_lan1.iface = Some("eth1".to_string());
+        r0.routes.push(to_lan1);
+        let mut to_lan0 = Route::connected(r0.ip, r0.netmask);
+        to_lan0.iface = Some("eth0".to_string());
+        r1.routes.push(to_lan0);
+        let r0 = lan0.add_host(r0);
+        let r1 = lan1.add_host(r1);
+        r0.attach_interface("eth1", &r1);
+        r1.attach_interface("eth0", &r0);
+        let a = lan0.add_host(config(2, [10, 0, 0, 2], Some([10, 0, 0, 1])));
+        lan1.add_host(config(2, [10, 1, 0, 2], Some([10, 1, 0, 1])));
+        let target = Ipv4Addr::new(10, 1, 0, 2);
+
+        for method in [TracerouteMethod::Icmp, TracerouteMethod::Udp] {
+            let trace_config = TracerouteConfig {
+                method,
+                max_hops: 5,
+                probes: 2,
+                timeout: Duration::from_millis(500),
+                quiet: true,
+                ..Default::default()
+            };
+            let a = a.clone();
+            let tracing = thread::spawn(move || {
+                traceroute(&a, target, &trace_config, &AtomicBool::new(false))
+            });
+            while !tracing.is_finished() {
+                assert!(lan0.now() < Duration::from_secs(60), "traceroute timed out");
+                lan0.step(Duration::from_millis(1));
+                lan1.step(Duration::from_millis(1));
+                thread::sleep(Duration::from_micros(100));
+            }
+            let trace = tracing.join().unwrap().unwrap();
+
+            assert!(trace.reached, "{:?}", method);
+            assert_eq!(trace.hops.len(), 2);
+            let addrs = |hop: &Hop| -> Vec<Option<Ipv4Addr>> {
+                hop.probes
+                    .iter()
+                    .map(|p| p.as_ref().map(|r| r.addr))
+                    .collect()
+            };
+            assert_eq!(addrs(&trace.hops[0]), vec![Some(r0.config().ip); 2]);
+            assert_eq!(addrs(&trace.hops[1]), vec![Some(target); 2]);
+        }
+    }
+
+    #[test]
+    fn dhcp_client_acquires_a_lease_from_the_server() {
+        let mut sim = Simulator::new(SegmentKind::Switch, LinkConfig::default());
+        let server_stack = sim.add_host(host(1));
+        let mut client_config = host(2);
+        client_config.ip = Ipv4Addr::unspecified();
+        client_config.netmask = Ipv4Addr::unspecified();
+        client_config.dhcp = true;
+        let client_stack = sim.add_host(client_config);
+
+        let mut config =
+            DhcpServerConfig::new(Ipv4Addr::new(10, 0, 0, 100), Ipv4Addr::new(10, 0, 0, 110));
+        config.router = Some(A_IP);
+        config.dns_servers = vec![A_IP];
+        let mut server = DhcpServer::new(server_stack, config).unwrap();
+        let stop = Arc::new(AtomicBool::new(false));
+        let serving = {
+            let stop = stop.clone();
+            thread::spawn(move || {
+                server.run(&stop).unwrap();
+                server
+            })
+        };
+
+        let mut client = DhcpClient::new(client_stack.clone()).unwrap();
+        let acquiring = thread::spawn(move || {
+            let lease = client.acquire(&AtomicBool::new(false));
+            (client, lease)
+        });
+        while !acquiring.is_finished() {
+            assert!(sim.now() < Duration::from_secs(60), "DHCP timed out");
+            sim.step(Duration::from_millis(1));
+            thread::sleep(Duration::from_micros(100));
+        }
+        let (client, lease) = acquiring.join().unwrap();
+        stop.store(true, Ordering::Relaxed);
+        let server = serving.join().unwrap();
+
+        let lease = lease.unwrap();
+        assert_eq!(client.state(), DhcpState::Bound);
+        assert_eq!(lease.address, Ipv4Addr::new(10, 0, 0, 100));
+        assert_eq!(lease.server, A_IP);
+        assert_eq!(lease.lease_time, Duration::from_secs(3600));
+
+        // 地址和网络参数已配置到协议栈
+        let config = client_stack.config();
+        assert_eq!(config.ip, lease.address);
+        assert_eq!(config.netmask, Ipv4Addr::new(255, 255, 255, 0));
+        assert_eq!(config.gateway, Some(A_IP));
+        assert_eq!(config.dns_servers, vec![A_IP]);
+
+        let leases: Vec<_> = server.leases().map(|l| (l.address, l.mac)).collect();
+        assert_eq!(leases, vec![(lease.address, host(2).mac)]);
+    }
+
+    /// 简单的权威服务器：www 有一条 A 记录，big 的应答放不进 UDP，其他名字不存在
+    fn dns_answer(query: &DnsMessage, tcp: bool) -> DnsMessage {
+        let mut response = query.clone();
+        response.response = true;
+        let name = query.questions[0].name.as_str();
+        match name {
+            "www.example.com" => response.answers.push(DnsRecord::new(
+                name,
+                300,
+                DnsRecordData::A(Ipv4Addr::new(10, 0, 0, 80)),
+            )),
+            "big.example.com" if !tcp => response.truncated = true,
+            "big.example.com" => {
+                for i in 1..=40 {
+                    let addr = Ipv4Addr::new(10, 0, 1, i);
+                    response
+                        .answers
+                        .push(DnsRecord::new(name, 300, DnsRecordData::A(addr)));
+                }
+            }
+            _ => {
+                response.rcode = DnsResponseCode::NameError;
+                response.authorities.push(DnsRecord::new(
+                    "example.com",
+                    60,
+                    DnsRecordData::Soa {
+                        mname: "ns.example.com".to_string(),
+                        rname: "hostmaster.example.com".to_string(),
+                        serial: 1,
+                        refresh: 7200,
+                        retry: 3600,
+                        expire: 1209600,
+                        minimum: 60,
+                    },
+                ));
+            }
+        }
+        response
+    }
+
+    #[test]
+    fn dns_queries_are_cached_and_retried_over_tcp() {
+        let (mut sim, a, b) = pair(LinkConfig::default());
+        let stop = Arc::new(AtomicBool::new(false));
+        let udp_queries = Arc::new(AtomicUsize::new(0));
+        let tcp_queries = Arc::new(AtomicUsize::new(0));
+        let serving = {
+            let (stop, udp_queries, tcp_queries) =
+                (stop.clone(), udp_queries.clone(), tcp_queries.clone());
+            let udp = UdpSocket::bind(b.clone(), "0.0.0.0:53").unwrap();
+            let tcp = TcpListener::bind(b, "10.0.0.2:53").unwrap();
+            thread::spawn(move || {
+                while !stop.load(Ordering::Relaxed) {
+                    if let Ok((data, src)) = udp.recv_from() {
+                        udp_queries.fetch_add(1, Ordering::Relaxed);
+                        let query = DnsMessage::parse(&data).unwrap();
+                        udp.send_to(&dns_answer(&query, false).to_bytes(), &src)
+                            .unwrap();
+                    }
+                    if let Ok((stream, _)) = tcp.accept() {
+                        tcp_queries.fetch_add(1, Ordering::Relaxed);
+                        let mut request = Vec::new();
+                        while request.len() < 2
+                            || request.len()
+                                < 2 + u16::from_be_bytes([request[0], request[1]]) as usize
+                        {
+                            match stream.recv() {
+                                Ok(data) => request.extend_from_slice(&data),
+                                Err(_) => thread::sleep(Duration::from_millis(1)),
+                            }
+                        }
+                        let query = DnsMessage::parse(&request[2..]).unwrap();
+                        let response = dns_answer(&query, true).to_bytes();
+                        assert!(response.len() > 512);
+                        let mut reply = (response.len() as u16).to_be_bytes().to_vec();
+                        reply.extend_from_slice(&response);
+                        let mut sent = 0;
+                        while sent < reply.len() {
+                            sent += stream.send(&reply[sent..]).unwrap();
+                        }
+                        stream.close();
+                    }
+                    thread::sleep(Duration::from_millis(1));
+                }
+            })
+        };
+
+        let resolving = {
+            let (udp_queries, tcp_queries) = (udp_queries.clone(), tcp_queries.clone());
+            thread::spawn(move || {
+                let config = ResolverConfig {
+                    servers: vec![B_IP],
+                    ..Default::default()
+                };
+                let resolver = Resolver::new(a.clone(), config).unwrap();
+                let www = Ipv4Addr::new(10, 0, 0, 80);
+                assert_eq!(resolver.lookup_ipv4("www.example.com").unwrap(), vec![www]);
+                // 第二次命中缓存
+                assert_eq!(resolver.lookup_ipv4("WWW.example.com.").unwrap(), vec![www]);
+                assert_eq!(udp_queries.load(Ordering::Relaxed), 1);
+
+                // 否定应答也被缓存
+                for _ in 0..2 {
+                    let error = resolver.lookup_ipv4("missing.example.com").unwrap_err();
+                    assert_eq!(
+                        error.downcast_ref::<ResolveError>(),
+                        Some(&ResolveError::NotFound)
+                    );
+                }
+                assert_eq!(udp_queries.load(Ordering::Relaxed), 2);
+
+                // UDP 应答被截断，改用 TCP
+                let addrs = resolver.lookup_ipv4("big.example.com").unwrap();
+                assert_eq!(addrs.len(), 40);
+                assert_eq!(tcp_queries.load(Ordering::Relaxed), 1);
+
+                // 第一个服务器不应答时换下一个
+                let config = ResolverConfig {
+                    servers: vec![Ipv4Addr::new(10, 0, 0, 3), B_IP],
+                    timeout: Duration::from_millis(500),
+                    ..Default::default()
+                };
+                let resolver = Resolver::new(a, config).unwrap();
+                assert_eq!(resolver.lookup_ipv4("www.example.com").unwrap(), vec![www]);
+                assert_eq!(udp_queries.load(Ordering::Relaxed), 4);
+            })
+        };
+        while !resolving.is_finished() {
+            assert!(sim.now() < Duration::from_secs(60), "DNS timed out");
+            sim.step(Duration::from_millis(1));
+            thread::sleep(Duration::from_micros(100));
+        }
+        stop.store(true, Ordering::Relaxed);
+        serving.join().unwrap();
+        resolving.join().unwrap();
+    }
+
+    #[test]
+    fn mdns_names_are_resolved_and_conflicts_renamed() {
+        let (mut sim, a, b) = pair(LinkConfig::default());
+        let stop_a = Arc::new(AtomicBool::new(false));
+        let stop_b = Arc::new(AtomicBool::new(false));
+        let respond = |stack: &Arc<NetworkStack>, stop: &Arc<AtomicBool>| {
+            let mut responder = MdnsResponder::new(stack.clone(), "alpha").unwrap();
+            let stop = stop.clone();
+            thread::spawn(move || {
+                responder.run(&stop).unwrap();
+                responder
+            })
+        };
+        let run_until = |sim: &mut Simulator, until: Duration| {
+            while sim.now() < until {
+                sim.step(Duration::from_millis(1));
+                thread::sleep(Duration::from_micros(100));
+            }
+        };
+
+        // a 探测并宣告 alpha.local，之后 b 用同一个名字探测时发现冲突并改名
+        let responding_a = respond(&a, &stop_a);
+        run_until(&mut sim, Duration::from_secs(3));
+        let responding_b = respond(&b, &stop_b);
+        run_until(&mut sim, Duration::from_secs(6));
+
+        let lookup = |stack: &Arc<NetworkStack>, name: &'static str| {
+            let resolver = Resolver::new(stack.clone(), ResolverConfig::default()).unwrap();
+            thread::spawn(move || resolver.lookup_ipv4(name))
+        };
+        let wait = |sim: &mut Simulator, resolving: thread::JoinHandle<_>| {
+            while !resolving.is_finished() {
+                assert!(sim.now() < Duration::from_secs(60), "mDNS timed out");
+                sim.step(Duration::from_millis(1));
+                thread::sleep(Duration::from_micros(100));
+            }
+            resolving.join().unwrap()
+        };
+        let resolving = lookup(&b, "alpha.local");
+        assert_eq!(wait(&mut sim, resolving).unwrap(), vec![A_IP]);
+        let resolving = lookup(&a, "ALPHA-2.local.");
+        assert_eq!(wait(&mut sim, resolving).unwrap(), vec![B_IP]);
+        // 没有主机应答的名字视为不存在
+        let resolving = lookup(&a, "beta.local");
+        let error = wait(&mut sim, resolving).unwrap_err();
+        assert_eq!(
+            error.downcast_ref::<ResolveError>(),
+            Some(&ResolveError::NotFound)
+        );
+
+        stop_a.store(true, Ordering::Relaxed);
+        stop_b.store(true, Ordering::Relaxed);
+        assert_eq!(responding_a.join().unwrap().hostname(), "alpha.local");
+        assert_eq!(responding_b.join().unwrap().hostname(), "alpha-2.local");
+    }
+}
@@ -0,0 +1,656 @@
+// Copyright (C) 2025 rrrrrzy
+// SPDX-License-Identifier: GPL-3.0-or-later
+//
+// --------------------------------------------------
+// 致敬所有在深夜调试代码的灵魂。
+// 即便 Bug 如山，我亦往矣。
+// --------------------------------------------------
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+//! 无状态地址自动配置 (RFC 4862)：生成链路本地地址并做重复地址检测，
+//! 再按路由器通告中的前缀生成全局地址、学习默认路由器
+
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use protocol::checksum::sha256;
+use protocol::icmpv6::{Icmpv6Message, NdpOption, PrefixInformation};
+use protocol::ipv6::{
+    IPV6_MIN_MTU, Ipv6Addr, Ipv6Cidr, eui64_interface_id, is_reserved_interface_id,
+    stable_privacy_interface_id,
+};
+
+use crate::handlers::icmpv6::{self, NdpEvent};
+use crate::stack::NetworkStack;
+
+/// 每个地址发送的重复地址检测请求数和等待时间 (RFC 4861 10 的默认值)
+const DUP_ADDR_DETECT_TRANSMITS: u32 = 1;
+const RETRANS_TIMER: Duration = Duration::from_secs(1);
+
+const MAX_RTR_SOLICITATION_DELAY: Duration = Duration::from_secs(1);
+const RTR_SOLICITATION_INTERVAL: Duration = Duration::from_secs(4);
+const MAX_RTR_SOLICITATIONS: u32 = 3;
+
+/// 稳定隐私地址冲突后最多换几次接口标识 (RFC 7217 6)
+const IDGEN_RETRIES: u8 = 3;
+
+/// 防止伪造的路由器通告缩短已有地址的有效期 (RFC 4862 5.5.3 e)
+const TWO_HOURS: Duration = Duration::from_secs(2 * 60 * 60);
+
+const NONCE_LEN: usize = 6;
+
+/// 生存期 u32::MAX 表示无限，返回 None
+fn lifetime(secs: u32) -> Option<Duration> {
+    (secs != u32::MAX).then(|| Duration::from_secs(secs as u64))
+}
+
+/// 收到前缀信息后地址的剩余有效期 (RFC 4862 5.5.3 e)，None 表示无限
+fn updated_valid_lifetime(
+    received: Option<Duration>,
+    remaining: Option<Duration>,
+) -> Option<Duration> {
+    match (received, remaining) {
+        (None, _) => None,
+        (Some(received), _) if received > TWO_HOURS => Some(received),
+        (Some(received), Some(remaining)) if received > remaining => Some(received),
+        // 剩余不超过两小时的不再理会，否则最多缩短到两小时
+        (Some(_), Some(remaining)) if remaining <= TWO_HOURS => Some(remaining),
+        _ => Some(TWO_HOURS),
+    }
+}
+
+enum DadResult {
+    Unique,
+    Duplicate,
+    Cancelled,
+}
+
+/// 由路由器通告生成的地址
+struct AutoconfAddress {
+    prefix: Ipv6Addr,
+    cidr: Ipv6Cidr,
+    // None 表示永不过期
+    valid_until: Option<Instant>,
+    // 过了这个时刻地址被弃用 (RFC 4862 5.5.4)，None 表示一直是首选地址
+    preferred_until: Option<Instant>,
+    deprecated: bool,
+}
+
+/// SLAAC 客户端，地址和默认网关直接写入协议栈配置
+pub struct SlaacClient {
+    stack: Arc<NetworkStack>,
+    rx: Receiver<NdpEvent>,
+    // 重复地址检测期间收到的其他报文，检测结束后再处理
+    deferred: VecDeque<NdpEvent>,
+    addresses: Vec<AutoconfAddress>,
+    // 最近一次通告自己是默认路由器的路由器及其过期时刻
+    router: Option<(Ipv6Addr, Instant)>,
+    // 静态配置了默认网关时不用通告中的路由器替换它
+    static_gateway: bool,
+    random_counter: u64,
+}
+
+impl SlaacClient {
+    /// 注册为协议栈的邻居发现监听者，同一协议栈只能有一个 SLAAC 客户端
+    pub fn new(stack: Arc<NetworkStack>) -> anyhow::Result<Self> {
+        let rx = {
+            let mut listener = stack.ndp_listener().lock().unwrap();
+            if listener.is_some() {
+                anyhow::bail!("A SLAAC client is already running on this stack");
+            }
+            let (tx, rx) = mpsc::channel();
+            *listener = Some(tx);
+            rx
+        };
+        let static_gateway = stack.config().ipv6_gateway.is_some();
+
+        Ok(Self {
+            stack,
+            rx,
+            deferred: VecDeque::new(),
+            addresses: Vec::new(),
+            router: None,
+            static_gateway,
+            random_counter: 0,
+        })
+    }
+
+    /// 配置链路本地地址并请求路由器，然后处理路由器通告直到 cancel 被置位
+    ///
+    /// 按 MAC 生成的链路本地地址冲突时返回错误 (RFC 4862 5.4.5)，需要有线程在运行 `event_loop::run`
+    pub fn run(&mut self, cancel: &AtomicBool) -> anyhow::Result<()> {
+        self.configure_link_local(cancel)?;
+        self.solicit_routers(cancel);
+
+        while !cancel.load(Ordering::Relaxed) {
+            if let Some(event) = self.next_event(Duration::from_millis(100)) {
+                self.handle(event, cancel);
+            }
+            self.expire(self.stack.now());
+        }
+        Ok(())
+    }
+
+    /// 已经静态配置了链路本地地址时不再生成
+    fn configure_link_local(&mut self, cancel: &AtomicBool) -> anyhow::Result<()> {
+        if let Some(cidr) = self
+            .stack
+            .config()
+            .ipv6_addrs
+            .iter()
+            .find(|cidr| cidr.addr.is_unicast_link_local())
+        {
+            println!("使用已配置的链路本地地址 {}", cidr.addr);
+            return Ok(());
+        }
+
+        let prefix = Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 0);
+        if self.assign(prefix, 64, cancel).is_none() && !cancel.load(Ordering::Relaxed) {
+            anyhow::bail!("No unique IPv6 link-local address, IPv6 is disabled");
+        }
+        Ok(())
+    }
+
+    /// 启动后随机等待一段时间再发送路由器请求，收到任何路由器通告即停止 (RFC 4861 6.3.7)
+    fn solicit_routers(&mut self, cancel: &AtomicBool) {
+        let delay = self.random_delay(MAX_RTR_SOLICITATION_DELAY);
+        if self.wait_for_advertisement(delay, cancel) {
+            return;
+        }
+        for _ in 0..MAX_RTR_SOLICITATIONS {
+            icmpv6::send_router_solicitation(&self.stack);
+            if self.wait_for_advertisement(RTR_SOLICITATION_INTERVAL, cancel) {
+                return;
+            }
+        }
+        println!("没有收到路由器通告，只使用链路本地地址");
+    }
+
+    /// 收到路由器通告或 cancel 被置位时返回 true
+    fn wait_for_advertisement(&mut self, timeout: Duration, cancel: &AtomicBool) -> bool {
+        let deadline = self.stack.now() + timeout;
+        while !cancel.load(Ordering::Relaxed) {
+            let now = self.stack.now();
+            if now >= deadline {
+                return false;
+            }
+            let Some(event) = self.next_event((deadline - now).min(Duration::from_millis(100)))
+            else {
+                continue;
+            };
+            let is_advertisement =
+                matches!(event.message, Icmpv6Message::RouterAdvertisement { .. });
+            self.handle(event, cancel);
+            if is_advertisement {
+                return true;
+            }
+        }
+        true
+    }
+
+    fn next_event(&mut self, timeout: Duration) -> Option<NdpEvent> {
+        self.deferred
+            .pop_front()
+            .or_else(|| self.rx.recv_timeout(timeout).ok())
+    }
+
+    /// 检测结束后才到达的冲突报文已经没有意义，只处理路由器通告
+    fn handle(&mut self, event: NdpEvent, cancel: &AtomicBool) {
+        if let Icmpv6Message::RouterAdvertisement { .. } = event.message {
+            self.handle_router_advertisement(event.src, &event.message, cancel);
+        }
+    }
+
+    /// 路由器通告 (RFC 4861 6.3.4)：只记一个默认路由器，以最近通告的为准
+    fn handle_router_advertisement(
+        &mut self,
+        src: Ipv6Addr,
+        message: &Icmpv6Message,
+        cancel: &AtomicBool,
+    ) {
+        let Icmpv6Message::RouterAdvertisement {
+            router_lifetime, ..
+        } = *message
+        else {
+            return;
+        };
+
+        let now = self.stack.now();
+        if router_lifetime > 0 {
+            if self.router.is_none_or(|(router, _)| router != src) {
+                println!("学习到默认路由器 {}", src);
+            }
+            self.router = Some((src, now + Duration::from_secs(router_lifetime as u64)));
+            if !self.static_gateway {
+                self.stack.set_ipv6_gateway(Some(src));
+            }
+        } else if self.router.is_some_and(|(router, _)| router == src) {
+            // 生存期为 0 表示它不再作为默认路由器
+            self.remove_router();
+        }
+
+        // 只接受比当前链路 MTU 小的值
+        let mtu = message
+            .ndp_options()
+            .iter()
+            .find_map(|option| match option {
+                NdpOption::Mtu(mtu) => Some(*mtu as usize),
+                _ => None,
+            });
+        if let Some(mtu) = mtu
+            && mtu >= IPV6_MIN_MTU
+            && mtu <= self.stack.mtu()
+        {
+            self.stack.set_ipv6_mtu(Some(mtu));
+        }
+
+        let prefixes: Vec<PrefixInformation> = message.prefix_information().copied().collect();
+        for info in prefixes {
+            self.handle_prefix(&info, now, cancel);
+        }
+    }
+
+    /// 前缀信息 (RFC 4862 5.5.3)：新前缀生成地址，已有前缀刷新有效期
+    fn handle_prefix(&mut self, info: &PrefixInformation, now: Instant, cancel: &AtomicBool) {
+        if !info.autonomous
+            || info.prefix.is_unicast_link_local()
+            || info.preferred_lifetime > info.valid_lifetime
+        {
+            return;
+        }
+        // 接口标识是 64 位，其他长度的前缀无法生成地址
+        if info.prefix_len != 64 {
+            eprintln!(
+                "Ignoring prefix {}/{}: SLAAC needs a /64",
+                info.prefix, info.prefix_len
+            );
+            return;
+        }
+        let prefix = Ipv6Addr::from_prefix_and_interface_id(info.prefix, [0; 8]);
+        // 不在链路上的前缀只配置地址本身，发往前缀内其他地址的报文交给路由器
+        let prefix_len = if info.on_link { 64 } else { 128 };
+        let received = lifetime(info.valid_lifetime);
+        // 首选生存期直接取通告中的值，不受两小时规则限制
+        let preferred_until = lifetime(info.preferred_lifetime).map(|d| now + d);
+
+        if let Some(address) = self.addresses.iter_mut().find(|a| a.prefix == prefix) {
+            let remaining = address
+                .valid_until
+                .map(|until| until.saturating_duration_since(now));
+            address.valid_until = updated_valid_lifetime(received, remaining).map(|d| now + d);
+            address.preferred_until = preferred_until;
+            if address.cidr.prefix_len != prefix_len {
+                address.cidr = Ipv6Cidr::new(address.cidr.addr, prefix_len);
+                self.stack.add_ipv6_address(address.cidr);
+            }
+            return;
+        }
+
+        if info.valid_lifetime == 0 {
+            return;
+        }
+        if let Some(addr) = self.assign(prefix, prefix_len, cancel) {
+            self.addresses.push(AutoconfAddress {
+                prefix,
+                cidr: Ipv6Cidr::new(addr, prefix_len),
+                valid_until: received.map(|d| now + d),
+                preferred_until,
+                deprecated: false,
+            });
+        }
+    }
+
+    /// 按前缀生成地址，重复地址检测通过后加入协议栈
+    ///
+    /// 地址冲突时稳定隐私地址换一个接口标识重试，EUI-64 无法换地址，返回 None；
+    /// 生成的地址已静态配置时不由 SLAAC 管理，也返回 None
+    fn assign(
+        &mut self,
+        prefix: Ipv6Addr,
+        prefix_len: u8,
+        cancel: &AtomicBool,
+    ) -> Option<Ipv6Addr> {
+        let retries = match self.stack.config().ipv6_stable_secret {
+            Some(_) => IDGEN_RETRIES,
+            None => 0,
+        };
+        for dad_counter in 0..=retries {
+            let Some(addr) = self.interface_address(prefix, dad_counter) else {
+                continue;
+            };
+            if self.stack.is_local_ipv6(addr) {
+                return None;
+            }
+            match self.detect_duplicate(addr, cancel) {
+                DadResult::Unique => {
+                    let cidr = Ipv6Cidr::new(addr, prefix_len);
+                    self.stack.add_ipv6_address(cidr);
+                    println!("自动配置 IPv6 地址 {}/{}", addr, prefix_len);
+                    return Some(addr);
+                }
+                DadResult::Cancelled => return None,
+                DadResult::Duplicate => eprintln!("Duplicate IPv6 address detected: {}", addr),
+            }
+        }
+        None
+    }
+
+    /// 前缀高 64 位加上接口标识，接口标识是 RFC 5453 保留值时返回 None
+    fn interface_address(&self, prefix: Ipv6Addr, dad_counter: u8) -> Option<Ipv6Addr> {
+        let config = self.stack.config();
+        let interface_id = match &config.ipv6_stable_secret {
+            Some(secret) => stable_privacy_interface_id(prefix, config.mac, dad_counter, secret),
+            None => eui64_interface_id(config.mac),
+        };
+        (!is_reserved_interface_id(interface_id))
+            .then(|| Ipv6Addr::from_prefix_and_interface_id(prefix, interface_id))
+    }
+
+    /// 重复地址检测 (RFC 4862 5.4)：检测期间地址是暂定的，只接收发给它的请求节点组的报文
+    fn detect_duplicate(&mut self, addr: Ipv6Addr, cancel: &AtomicBool) -> DadResult {
+        self.stack.add_tentative_ipv6(addr);
+        let result = self.probe(addr, cancel);
+        self.stack.remove_tentative_ipv6(addr);
+        result
+    }
+
+    fn probe(&mut self, addr: Ipv6Addr, cancel: &AtomicBool) -> DadResult {
+        // 多个主机同时启动时错开第一个请求 (RFC 4862 5.4.2)
+        let delay = self.random_delay(MAX_RTR_SOLICITATION_DELAY);
+        if let Some(result) = self.wait_for_conflict(addr, None, delay, cancel) {
+            return result;
+        }
+        for _ in 0..DUP_ADDR_DETECT_TRANSMITS {
+            let random = self.random_bytes();
+            let nonce = &random[..NONCE_LEN];
+            icmpv6::send_dad_solicitation(&self.stack, addr, nonce);
+            if let Some(result) = self.wait_for_conflict(addr, Some(nonce), RETRANS_TIMER, cancel) {
+                return result;
+            }
+        }
+        DadResult::Unique
+    }
+
+    /// 等待 timeout，期间有其他主机在用或也在检测这个地址时返回 Duplicate (RFC 4862 5.4.3, 5.4.4)
+    ///
+    /// 带着自己 nonce 的请求是被环回的，不算冲突 (RFC 7527)
+    fn wait_for_conflict(
+        &mut self,
+        addr: Ipv6Addr,
+        nonce: Option<&[u8]>,
+        timeout: Duration,
+        cancel: &AtomicBool,
+    ) -> Option<DadResult> {
+        let deadline = self.stack.now() + timeout;
+        loop {
+            if cancel.load(Ordering::Relaxed) {
+                return Some(DadResult::Cancelled);
+            }
+            let now = self.stack.now();
+            if now >= deadline {
+                return None;
+            }
+            let Ok(event) = self
+                .rx
+                .recv_timeout((deadline - now).min(Duration::from_millis(100)))
+            else {
+                continue;
+            };
+            match &event.message {
+                Icmpv6Message::NeighborSolicitation { target, .. }
+                    if *target == addr && event.src.is_unspecified() =>
+                {
+                    if nonce.is_some() && event.message.nonce() == nonce {
+                        continue;
+                    }
+                    return Some(DadResult::Duplicate);
+                }
+                Icmpv6Message::NeighborAdvertisement { target, .. } if *target == addr => {
+                    return Some(DadResult::Duplicate);
+                }
+                _ => self.deferred.push_back(event),
+            }
+        }
+    }
+
+    /// 删除有效期已过的地址和默认路由器，弃用首选生存期已过的地址
+    fn expire(&mut self, now: Instant) {
+        let stack = &self.stack;
+        self.addresses.retain_mut(|address| {
+            let expired = address.valid_until.is_some_and(|until| until <= now);
+            if expired {
+                println!("IPv6 地址 {} 已过期", address.cidr.addr);
+                stack.remove_ipv6_address(address.cidr.addr);
+                return false;
+            }
+            // 新的通告可能刷新首选生存期，让已弃用的地址重新成为首选地址
+            let deprecated = address.preferred_until.is_some_and(|until| until <= now);
+            if deprecated != address.deprecated {
+                if deprecated {
+                    println!("IPv6 地址 {} 已弃用", address.cidr.addr);
+                }
+                address.deprecated = deprecated;
+                stack.set_ipv6_deprecated(address.cidr.addr, deprecated);
+            }
+            true
+        });
+        if self.router.is_some_and(|(_, until)| until <= now) {
+            self.remove_router();
+        }
+    }
+
+    fn remove_router(&mut self) {
+        if let Some((router, _)) = self.router.take() {
+            println!("默认路由器 {} 已失效", router);
+            if !self.static_gateway {
+                self.stack.set_ipv6_gateway(None);
+            }
+        }
+    }
+
+    /// 以时钟、MAC 和计数器做 SHA-256，够用作随机延迟和 nonce
+    fn random_bytes(&mut self) -> [u8; 32] {
+        self.random_counter += 1;
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos();
+        let mut input = nanos.to_be_bytes().to_vec();
+        input.extend_from_slice(self.stack.config().mac.as_bytes());
+        input.extend_from_slice(&self.random_counter.to_be_bytes());
+        sha256(&input)
+    }
+
+    /// [0, max) 内均匀分布的随机延迟
+    fn random_delay(&mut self, max: Duration) -> Duration {
+        let random = self.random_bytes();
+        let fraction = u32::from_be_bytes([random[0], random[1], random[2], random[3]]) as f64
+            / (u32::MAX as f64 + 1.0);
+        max.mul_f64(fraction)
+    }
+}
+
+impl Drop for SlaacClient {
+    fn drop(&mut self) {
+        *self.stack.ndp_listener().lock().unwrap() = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handlers::ipv6::source_address;
+    use crate::sim::{LinkConfig, SegmentKind, Simulator};
+    use crate::stack::StackConfig;
+    use protocol::ipv4::Ipv4Addr;
+    use protocol::mac::MacAddr;
+
+    fn prefix_info(prefix: Ipv6Addr, valid: u32, preferred: u32) -> PrefixInformation {
+        PrefixInformation {
+            prefix_len: 64,
+            on_link: true,
+            autonomous: true,
+            valid_lifetime: valid,
+            preferred_lifetime: preferred,
+            prefix,
+        }
+    }
+
+    #[test]
+    fn preferred_lifetime_deprecates_address() {
+        let mut sim = Simulator::new(SegmentKind::Switch, LinkConfig::default());
+        let stack = sim.add_host(StackConfig::new(
+            MacAddr::from_raw([0x02, 0, 0, 0, 0, 1]),
+            Ipv4Addr::new(10, 0, 0, 1),
+        ));
+        let mut client = SlaacClient::new(stack.clone()).unwrap();
+        let cancel = AtomicBool::new(false);
+
+        // 两个前缀的地址都已配置好，刷新时不再做重复地址检测
+        let old = Ipv6Addr::new(0x2001, 0xdb8, 1, 0, 0, 0, 0, 0);
+        let new = Ipv6Addr::new(0x2001, 0xdb8, 2, 0, 0, 0, 0, 0);
+        for prefix in [old, new] {
+            let cidr = Ipv6Cidr::new(
+                Ipv6Addr::from_prefix_and_interface_id(prefix, [0, 0, 0, 0, 0, 0, 0, 1]),
+                64,
+            );
+            stack.add_ipv6_address(cidr);
+            client.addresses.push(AutoconfAddress {
+                prefix,
+                cidr,
+                valid_until: None,
+                preferred_until: None,
+                deprecated: false,
+            });
+        }
+        let old_addr = client.addresses[0].cidr.addr;
+        let new_addr = client.addresses[1].cidr.addr;
+        let remote = Ipv6Addr::new(0x2001, 0xdb8, 0xff, 0, 0, 0, 0, 1);
+
+        // 旧前缀的首选生存期 10 秒，新前缀一直首选
+        let now = stack.now();
+        client.handle_prefix(&prefix_info(old, 3600, 10), now, &cancel);
+        client.handle_prefix(&prefix_info(new, 3600, u32::MAX), now, &cancel);
+        client.expire(now);
+        assert!(!stack.is_deprecated_ipv6(old_addr));
+        assert_eq!(source_address(&stack, remote), Some(old_addr));
+
+        // 首选生存期过后地址仍然有效，但不再优先作源地址
+        let later = now + Duration::from_secs(11);
+        client.expire(later);
+        assert!(stack.is_deprecated_ipv6(old_addr));
+        assert!(stack.is_local_ipv6(old_addr));
+        assert_eq!(source_address(&stack, remote), Some(new_addr));
+        // 目的地址在已弃用地址的前缀内也一样
+        let neighbor = Ipv6Addr::from_prefix_and_interface_id(old, [0, 0, 0, 0, 0, 0, 0, 2]);
+        assert_eq!(source_address(&stack, neighbor), Some(new_addr));
+
+        // 新的通告刷新首选生存期后恢复为首选地址
+        client.handle_prefix(&prefix_info(old, 3600, 600), later, &cancel);
+        client.expire(later);
+        assert!(!stack.is_deprecated_ipv6(old_addr));
+        assert_eq!(source_address(&stack, remote), Some(old_addr));
+    }
+
+    fn client(secret: Option<[u8; 16]>) -> (Simulator, Arc<NetworkStack>, SlaacClient) {
+        let mut sim = Simulator::new(SegmentKind::Switch, LinkConfig::default());
+        let mut config = StackConfig::new(
+            MacAddr::from_raw([0x02, 0, 0, 0, 0, 1]),
+            Ipv4Addr::new(10, 0, 0, 1),
+        );
+        config.ipv6_stable_secret = secret;
+        let stack = sim.add_host(config);
+        let client = SlaacClient::new(stack.clone()).unwrap();
+        (sim, stack, client)
+    }
+
+    #[test]
+    fn valid_lifetime_follows_the_two_hour_rule() {
+        let minutes = |m: u64| Some(Duration::from_secs(m * 60));
+        assert_eq!(lifetime(u32::MAX), None);
+        assert_eq!(lifetime(600), minutes(10));
+
+        // 无限、超过两小时或比剩余时间长的直接采用
+        assert_eq!(updated_valid_lifetime(None, minutes(10)), None);
+        assert_eq!(
+            updated_valid_lifetime(minutes(180), minutes(10)),
+            minutes(180)
+        );
+        assert_eq!(
+            updated_valid_lifetime(minutes(60), minutes(30)),
+            minutes(60)
+        );
+        // 剩余不超过两小时的不缩短，否则最多缩短到两小时
+        assert_eq!(
+            updated_valid_lifetime(minutes(10), minutes(60)),
+            minutes(60)
+        );
+        assert_eq!(
+            updated_valid_lifetime(minutes(10), minutes(300)),
+            minutes(120)
+        );
+        assert_eq!(updated_valid_lifetime(minutes(10), None), minutes(120));
+    }
+
+    #[test]
+    fn interface_addresses_use_eui64_or_stable_privacy() {
+        let mac = MacAddr::from_raw([0x02, 0, 0, 0, 0, 1]);
+        let prefix = Ipv6Addr::new(0x2001, 0xdb8, 1, 0, 0, 0, 0, 0);
+
+        let (_sim, _stack, eui64_client) = client(None);
+        let eui64 = Ipv6Addr::from_prefix_and_interface_id(prefix, eui64_interface_id(mac));
+        assert_eq!(eui64_client.interface_address(prefix, 0), Some(eui64));
+        assert_eq!(eui64_client.interface_address(prefix, 1), Some(eui64));
+
+        // 冲突计数变化时生成新的稳定隐私地址
+        let secret = [7; 16];
+        let (_sim, _stack, stable_client) = client(Some(secret));
+        for dad_counter in 0..=IDGEN_RETRIES {
+            let id = stable_privacy_interface_id(prefix, mac, dad_counter, &secret);
+            assert_eq!(
+                stable_client.interface_address(prefix, dad_counter),
+                Some(Ipv6Addr::from_prefix_and_interface_id(prefix, id))
+            );
+        }
+        assert_ne!(stable_client.interface_address(prefix, 0), Some(eui64));
+    }
+
+    #[test]
+    fn unusable_prefixes_are_ignored() {
+        let (_sim, stack, mut client) = client(None);
+        let cancel = AtomicBool::new(false);
+        let prefix = Ipv6Addr::new(0x2001, 0xdb8, 1, 0, 0, 0, 0, 0);
+        let now = stack.now();
+
+        let not_autonomous = PrefixInformation {
+            autonomous: false,
+            ..prefix_info(prefix, 3600, 600)
+        };
+        let link_local = prefix_info(Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 0), 3600, 600);
+        let preferred_too_long = prefix_info(prefix, 600, 3600);
+        let not_64 = PrefixInformation {
+            prefix_len: 48,
+            ..prefix_info(prefix, 3600, 600)
+        };
+        let expired = prefix_info(prefix, 0, 0);
+        // 这些前缀都在重复地址检测之前被丢弃，不会阻塞
+        for info in [
+            not_autonomous,
+            link_local,
+            preferred_too_long,
+            not_64,
+            expired,
+        ] {
+            client.handle_prefix(&info, now, &cancel);
+        }
+        assert!(client.addresses.is_empty());
+        assert!(stack.config().ipv6_addrs.is_empty());
+    }
+}
@@ -11,71 +11,337 @@
 // the Free Software Foundation, either version 3 of the License, or
 // (at your option) any later version.
 
-use pcap::{Active, Capture, Device};
-use protocol::ethernet::{EtherType, EthernetHeader};
-use protocol::ipv4::{Ipv4Addr, Ipv4Protocol};
+use protocol::ethernet::{EtherType, EthernetHeader, VlanTag};
+use protocol::icmp::TimeExceededCode;
+use protocol::icmpv6::NeighborCache;
+use protocol::igmp::IGMP_ALL_SYSTEMS;
+use protocol::ip::IpAddr;
+use protocol::ipv4::{Ipv4Addr, Ipv4Header, Ipv4Protocol};
+use protocol::ipv6::{IPV6_NEXT_UDP, Ipv6Addr, Ipv6Cidr};
 use protocol::mac::MacAddr;
 use std::collections::{HashMap, VecDeque};
-use std::sync::{Arc, Mutex};
-use std::time::{Duration, Instant};
+use std::fmt;
+use std::path::Path;
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex, RwLock, RwLockReadGuard, Weak};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 // 引入 handlers
+use crate::cli::{Args, DeviceKind};
+use crate::clock::{Clock, SystemClock};
+use crate::config::InterfaceConfig;
+use crate::device::{Device, PcapDevice, ReplayDevice};
 use crate::handlers;
+use crate::handlers::icmp::{IcmpEvent, IcmpListenerKey, IcmpRateLimiter};
+use crate::handlers::icmpv6::NdpEvent;
+use crate::handlers::igmp::IgmpState;
+use crate::reassembly::{DEFAULT_REASSEMBLY_MEMORY, DEFAULT_REASSEMBLY_TIMEOUT, Reassembler};
+use crate::route::{Route, RoutingTable};
 use crate::transport::{Socket, SocketSet};
 use protocol::arp::ArpTable;
 
-pub struct PendingPacket {
-    pub dst_ip: Ipv4Addr,
-    pub protocol: Ipv4Protocol,
-    pub payload: Vec<u8>,
+/// ICMP 差错报文默认每秒最多发送的个数
+pub const DEFAULT_ICMP_RATE_LIMIT: u32 = 10;
+
+/// 默认使用 IGMPv3，听到旧版本查询者时自动降级
+pub const DEFAULT_IGMP_VERSION: u8 = 3;
+
+// IPv4 Identification 计数器的闲置时限，取 TCP 的报文最大生存时间 (RFC 793 MSL = 2 分钟)
+const IP_ID_IDLE_TIMEOUT: Duration = Duration::from_secs(120);
+
+pub struct PendingPacket<A = Ipv4Addr> {
+    pub dst_ip: A,
+    // 已封装好 IP 首部的完整数据报，ARP 或邻居发现完成后直接加上以太网头发送
+    pub datagram: Vec<u8>,
     pub timestamp: Instant,
 }
 
+/// 按下一跳地址排队、等待地址解析的包
+pub type PendingQueue<A = Ipv4Addr> = HashMap<A, VecDeque<PendingPacket<A>>>;
+
 pub struct StackConfig {
     pub mac: MacAddr,
+    // 绑定的 VLAN，由外到内；为空时只收发不带标签的帧
+    pub vlans: Vec<VlanTag>,
     pub ip: Ipv4Addr,
-    // pub gateway: Ipv4Addr, // 以后再加
+    // 未配置时为 0.0.0.0，即所有目的地址都视为直连 (与早期行为一致)
+    pub netmask: Ipv4Addr,
+    pub gateway: Option<Ipv4Addr>,
+    // 额外的静态路由
+    pub routes: Vec<Route>,
+    // 是否转发目的地址不是本机的 IPv4 数据报 (路由器模式)
+    pub forwarding: bool,
+    // 覆盖设备上报的 MTU，None 时使用设备的值
+    pub mtu: Option<usize>,
+    // 分片重组的超时和缓存上限
+    pub reassembly_timeout: Duration,
+    pub reassembly_memory: usize,
+    // 是否回复 ICMP Destination Unreachable
+    pub icmp_unreachable: bool,
+    // ICMP 差错报文每秒最多发送的个数，0 表示不限速
+    pub icmp_rate_limit: u32,
+    // 加入多播组时使用的最高 IGMP 版本 (1-3)
+    pub igmp_version: u8,
+    // DNS 服务器，静态配置或由 DHCP 获得
+    pub dns_servers: Vec<Ipv4Addr>,
+    // 启动时通过 DHCP 获取地址，此前 ip 为 0.0.0.0
+    pub dhcp: bool,
+    // 静态配置的 IPv6 地址及前缀长度，前缀内的地址视为直连
+    pub ipv6_addrs: Vec<Ipv6Cidr>,
+    pub ipv6_gateway: Option<Ipv6Addr>,
+    // 路由器通告给出的链路 MTU，只用于 IPv6，不超过 mtu
+    pub ipv6_mtu: Option<usize>,
+    // 启动时通过无状态地址自动配置 (SLAAC) 生成链路本地地址，并按路由器通告生成全局地址
+    pub slaac: bool,
+    // 设置时按 RFC 7217 生成稳定的隐私接口标识，否则按 MAC 生成 EUI-64 接口标识
+    pub ipv6_stable_secret: Option<[u8; 16]>,
+}
+
+impl StackConfig {
+    pub fn new(mac: MacAddr, ip: Ipv4Addr) -> Self {
+        Self {
+            mac,
+            vlans: Vec::new(),
+            ip,
+            netmask: Ipv4Addr::unspecified(),
+            gateway: None,
+            routes: Vec::new(),
+            forwarding: false,
+            mtu: None,
+            reassembly_timeout: DEFAULT_REASSEMBLY_TIMEOUT,
+            reassembly_memory: DEFAULT_REASSEMBLY_MEMORY,
+            icmp_unreachable: true,
+            icmp_rate_limit: DEFAULT_ICMP_RATE_LIMIT,
+            igmp_version: DEFAULT_IGMP_VERSION,
+            dns_servers: Vec::new(),
+            dhcp: false,
+            ipv6_addrs: Vec::new(),
+            ipv6_gateway: None,
+            ipv6_mtu: None,
+            slaac: false,
+            ipv6_stable_secret: None,
+        }
+    }
+
+    /// 由直连网段、默认网关和静态路由组成初始路由表
+    pub fn routing_table(&self) -> RoutingTable {
+        let mut table = RoutingTable::new();
+        table.add(Route::connected(self.ip, self.netmask));
+        if let Some(gateway) = self.gateway {
+            table.add(Route::default_via(gateway));
+        }
+        for route in &self.routes {
+            table.add(route.clone());
+        }
+        table
+    }
 }
 
 pub fn initialize(iface: &str, config: StackConfig) -> anyhow::Result<Arc<NetworkStack>> {
-    let device = Device::list()?
-        .into_iter()
-        .find(|d| d.name == iface)
-        .ok_or_else(|| anyhow::anyhow!("Device not found"))?;
+    // 收发各开一个抓包句柄，避免 RX 线程阻塞在 next_packet 时占住发送端
+    let rx_dev = PcapDevice::open(iface)?;
+    let tx_dev = PcapDevice::open(iface)?;
+
+    println!("Starting Network Stack on interface: {}", iface);
+
+    Ok(initialize_with_device(config, tx_dev, rx_dev))
+}
 
-    println!("Starting Network Stack on interface: {}", device.name);
+/// 在 Linux TAP 接口上初始化协议栈，接口不存在时会被创建
+#[cfg(target_os = "linux")]
+pub fn initialize_tap(iface: &str, config: StackConfig) -> anyhow::Result<Arc<NetworkStack>> {
+    let rx_dev = crate::device::TapDevice::open(iface)?;
+    let tx_dev = rx_dev.try_clone()?;
 
-    let rx_cap = Capture::from_device(device.clone())?.open()?;
-    let tx_cap = Capture::from_device(device)?.open()?;
+    println!("Starting Network Stack on TAP interface: {}", rx_dev.name());
 
-    let stack = NetworkStack::new(config, tx_cap, rx_cap, SocketSet::new());
-    Ok(Arc::new(stack))
+    Ok(initialize_with_device(config, tx_dev, rx_dev))
 }
 
-pub struct NetworkStack {
+/// 从 `.pcap` 文件回放输入帧，并把协议栈发出的帧写入另一个 `.pcap` 文件
+pub fn initialize_replay(
+    input: &Path,
+    output: &Path,
+    config: StackConfig,
+) -> anyhow::Result<Arc<NetworkStack>> {
+    let rx_dev = ReplayDevice::open(input, None)?;
+    let tx_dev = ReplayDevice::output_only(output)?;
+
+    println!(
+        "Starting Network Stack in replay mode: {} -> {}",
+        input.display(),
+        output.display()
+    );
+
+    Ok(initialize_with_device(config, tx_dev, rx_dev))
+}
+
+/// 根据命令行参数选择链路层设备并初始化协议栈
+pub fn initialize_from_args(args: &Args, config: StackConfig) -> anyhow::Result<Arc<NetworkStack>> {
+    let iface = || {
+        args.iface
+            .as_deref()
+            .ok_or_else(|| anyhow::anyhow!("--iface required"))
+    };
+
+    match args.device {
+        DeviceKind::Pcap => initialize(iface()?, config),
+        #[cfg(target_os = "linux")]
+        DeviceKind::Tap => initialize_tap(iface()?, config),
+        #[cfg(not(target_os = "linux"))]
+        DeviceKind::Tap => anyhow::bail!("TAP device is only supported on Linux"),
+        DeviceKind::Replay => {
+            let input = args
+                .replay_input
+                .as_deref()
+                .ok_or_else(|| anyhow::anyhow!("--replay-input required"))?;
+            let output = args
+                .replay_output
+                .as_deref()
+                .ok_or_else(|| anyhow::anyhow!("--replay-output required"))?;
+            initialize_replay(input, output, config)
+        }
+    }
+}
+
+/// 按命令行选择的设备类型打开 `attach` 给出的接口，并让所有接口两两关联
+///
+/// 返回新打开的接口，每个都需要各自运行 `event_loop::run`
+pub fn initialize_interfaces(
+    args: &Args,
+    primary: &Arc<NetworkStack>,
+    interfaces: Vec<InterfaceConfig>,
+) -> anyhow::Result<Vec<Arc<NetworkStack>>> {
+    let Some(primary_name) = args.iface.as_deref() else {
+        return Ok(Vec::new());
+    };
+
+    let mut linked = vec![(primary_name.to_string(), primary.clone())];
+    for InterfaceConfig { name, config } in interfaces {
+        let stack = match args.device {
+            DeviceKind::Pcap => initialize(&name, config)?,
+            #[cfg(target_os = "linux")]
+            DeviceKind::Tap => initialize_tap(&name, config)?,
+            #[cfg(not(target_os = "linux"))]
+            DeviceKind::Tap => anyhow::bail!("TAP device is only supported on Linux"),
+            DeviceKind::Replay => anyhow::bail!("'attach' is not supported with replay devices"),
+        };
+        for (peer_name, peer) in &linked {
+            peer.attach_interface(&name, &stack);
+            stack.attach_interface(peer_name, peer);
+        }
+        linked.push((name, stack));
+    }
+
+    Ok(linked.into_iter().skip(1).map(|(_, stack)| stack).collect())
+}
+
+/// 使用任意链路层设备初始化协议栈
+pub fn initialize_with_device(
+    config: StackConfig,
+    tx_dev: impl Device + 'static,
+    rx_dev: impl Device + 'static,
+) -> Arc<NetworkStack> {
+    initialize_with_clock(config, tx_dev, rx_dev, Arc::new(SystemClock))
+}
+
+/// 同上，定时器改用给定的时钟 (例如模拟器的虚拟时钟)
+pub fn initialize_with_clock(
     config: StackConfig,
+    tx_dev: impl Device + 'static,
+    rx_dev: impl Device + 'static,
+    clock: Arc<dyn Clock>,
+) -> Arc<NetworkStack> {
+    // 与宿主机网卡同一个 MAC 时，宿主机内核也会处理发给协议栈的帧 (例如对 TCP 连接回 RST)
+    if rx_dev.link_addr() == Some(config.mac) {
+        eprintln!(
+            "Warning: MAC {} is also used by the host interface; the host kernel will answer frames sent to it",
+            config.mac
+        );
+    }
+    let stack = NetworkStack::new(
+        config,
+        Box::new(tx_dev),
+        Box::new(rx_dev),
+        SocketSet::new(),
+        clock,
+    );
+    Arc::new(stack)
+}
+
+pub struct NetworkStack {
+    // 地址可能在运行时被 DHCP 修改
+    config: RwLock<StackConfig>,
     // 需要互斥锁，因为可能有多个线程（RX线程回包，用户线程发包）同时发送
-    sender: Arc<Mutex<Capture<Active>>>,
-    receiver: Arc<Mutex<Capture<Active>>>,
+    sender: Arc<Mutex<Box<dyn Device>>>,
+    receiver: Arc<Mutex<Box<dyn Device>>>,
+    // 所有定时器读取的时钟
+    clock: Arc<dyn Clock>,
     arp_table: Arc<Mutex<ArpTable>>,
+    neighbor_cache: Arc<Mutex<NeighborCache>>,
+    routing_table: Arc<Mutex<RoutingTable>>,
     pub sockets: Arc<Mutex<SocketSet>>,
-    pending_packets: Arc<Mutex<HashMap<Ipv4Addr, VecDeque<PendingPacket>>>>,
+    pending_packets: Arc<Mutex<PendingQueue>>,
+    // 等待邻居发现的 IPv6 报文，按下一跳地址排队
+    pending_ipv6_packets: Arc<Mutex<PendingQueue<Ipv6Addr>>>,
+    // 同一进程内关联的其他接口，路由的 dev 字段指向它们时经由对应协议栈发送
+    interfaces: Mutex<HashMap<String, Weak<NetworkStack>>>,
+    // 每个目的地址下一个要用的 IPv4 Identification 和最后使用的时刻
+    ip_ids: Mutex<HashMap<Ipv4Addr, (u16, Instant)>>,
+    // 发给本机的 IPv4 分片
+    reassembler: Arc<Mutex<Reassembler>>,
+    // ICMP 差错报文限速
+    icmp_limiter: Mutex<IcmpRateLimiter>,
+    // 按 Echo 标识注册的 ICMP 监听者 (ping 等)
+    icmp_listeners: Mutex<HashMap<IcmpListenerKey, Sender<IcmpEvent>>>,
+    // 已加入的 IPv4 多播组及加入的次数
+    multicast_groups: Mutex<HashMap<Ipv4Addr, usize>>,
+    // IGMP 的报告定时器和查询者版本
+    igmp: Mutex<IgmpState>,
+    // 正在做重复地址检测的 IPv6 地址，检测通过前不能使用
+    ipv6_tentative: Mutex<Vec<Ipv6Addr>>,
+    // 首选生存期已过的 IPv6 地址，仍然接收发给它们的报文，但不再优先用作源地址
+    ipv6_deprecated: Mutex<Vec<Ipv6Addr>>,
+    // 接收路由器通告和重复地址检测结果的 SLAAC 客户端
+    ndp_listener: Mutex<Option<Sender<NdpEvent>>>,
 }
 
 impl NetworkStack {
     pub fn new(
         config: StackConfig,
-        sender: Capture<Active>,
-        receiver: Capture<Active>,
+        sender: Box<dyn Device>,
+        receiver: Box<dyn Device>,
         socket: SocketSet,
+        clock: Arc<dyn Clock>,
     ) -> Self {
+        let routing_table = config.routing_table();
+        let reassembler = Reassembler::new(config.reassembly_timeout, config.reassembly_memory);
+        let icmp_limiter = IcmpRateLimiter::new(config.icmp_rate_limit, clock.now());
+        let igmp = IgmpState::new(config.igmp_version, config.mac);
         Self {
-            config,
+            config: RwLock::new(config),
             sender: Arc::new(Mutex::new(sender)),
             receiver: Arc::new(Mutex::new(receiver)),
+            clock,
             arp_table: Arc::new(Mutex::new(ArpTable::new(Duration::from_secs(300)))),
+            neighbor_cache: Arc::new(Mutex::new(NeighborCache::new(
+                Duration::from_secs(30),
+                Duration::from_secs(300),
+            ))),
+            routing_table: Arc::new(Mutex::new(routing_table)),
             sockets: Arc::new(Mutex::new(socket)),
             pending_packets: Arc::new(Mutex::new(HashMap::new())),
+            pending_ipv6_packets: Arc::new(Mutex::new(HashMap::new())),
+            interfaces: Mutex::new(HashMap::new()),
+            ip_ids: Mutex::new(HashMap::new()),
+            reassembler: Arc::new(Mutex::new(reassembler)),
+            icmp_limiter: Mutex::new(icmp_limiter),
+            icmp_listeners: Mutex::new(HashMap::new()),
+            multicast_groups: Mutex::new(HashMap::new()),
+            igmp: Mutex::new(igmp),
+            ipv6_tentative: Mutex::new(Vec::new()),
+            ipv6_deprecated: Mutex::new(Vec::new()),
+            ndp_listener: Mutex::new(None),
         }
     }
 
@@ -90,13 +356,23 @@ impl NetworkStack {
             }
         };
 
-        // 2. 过滤：只处理发给我的，或者广播包
-        if eth_header.dst != self.config.mac && eth_header.dst != MacAddr::broadcast() {
-            return;
+        // 2. 过滤：只处理绑定的 VLAN 上发给我的、广播包，或者已加入的多播组
+        // 配置的读锁在分发前释放，处理函数可能要修改配置
+        {
+            let config = self.config();
+            if !Self::accepts_vlans(&config, &eth_header.vlans) {
+                return;
+            }
+            if eth_header.dst != config.mac
+                && eth_header.dst != MacAddr::broadcast()
+                && !self.accepts_multicast_mac(&config, eth_header.dst)
+            {
+                return;
+            }
         }
 
-        // 3. 剥离以太网头，获取 Payload
-        let payload = &packet[EthernetHeader::LEN..];
+        // 3. 剥离以太网头和 VLAN 标签，获取 Payload
+        let payload = &packet[eth_header.header_len()..];
 
         // 4. 分发
         match eth_header.ethertype {
@@ -109,7 +385,8 @@ impl NetworkStack {
                 handlers::ipv4::handle(self, payload);
             }
             EtherType::Ipv6 => {
-                // println!("IPv6 is not supported");
+                // 调用 IPv6 Handler
+                handlers::ipv6::handle(self, payload);
             }
             _ => {
                 println!("Unknown EtherType: {}", eth_header.ethertype);
@@ -117,16 +394,216 @@ impl NetworkStack {
         }
     }
 
+    /// 逐层比较 VID；VID 为 0 的标签只携带优先级，视同不带标签 (802.1Q 6.9)
+    fn accepts_vlans(config: &StackConfig, vlans: &[VlanTag]) -> bool {
+        let vids = vlans.iter().map(|tag| tag.vid).filter(|&vid| vid != 0);
+        vids.eq(config.vlans.iter().map(|tag| tag.vid))
+    }
+
+    /// 本机发出的帧的以太网首部，带上绑定的 VLAN 标签
+    pub fn ethernet_header(&self, dst: MacAddr, ethertype: EtherType) -> EthernetHeader {
+        let config = self.config();
+        EthernetHeader {
+            dst,
+            src: config.mac,
+            vlans: config.vlans.clone(),
+            ethertype,
+        }
+    }
+
     // 发送接口：发送以太网帧
     pub fn send_frame(&self, frame: &[u8]) {
-        if let Ok(mut sender) = self.sender.lock() {
-            let _ = sender.sendpacket(frame);
+        if let Ok(mut sender) = self.sender.lock()
+            && let Err(e) = sender.transmit(frame)
+        {
+            eprintln!("TX Error: {:?}", e);
         }
     }
 
+    // 协议栈时钟的当前时刻，定时器都应以它为准而不是 Instant::now()
+    pub fn now(&self) -> Instant {
+        self.clock.now()
+    }
+
     // 辅助接口：获取本机配置
-    pub fn config(&self) -> &StackConfig {
-        &self.config
+    // 返回读锁，不要跨越可能修改配置的调用持有
+    pub fn config(&self) -> RwLockReadGuard<'_, StackConfig> {
+        self.config.read().unwrap()
+    }
+
+    /// 运行时修改本机地址 (DHCP 等)
+    ///
+    /// 路由表按新的地址、掩码和网关重新生成，运行时手动添加的路由会丢失
+    pub fn set_address(&self, ip: Ipv4Addr, netmask: Ipv4Addr, gateway: Option<Ipv4Addr>) {
+        let routing_table = {
+            let mut config = self.config.write().unwrap();
+            config.ip = ip;
+            config.netmask = netmask;
+            config.gateway = gateway;
+            config.routing_table()
+        };
+        *self.routing_table.lock().unwrap() = routing_table;
+    }
+
+    pub fn set_dns_servers(&self, servers: Vec<Ipv4Addr>) {
+        self.config.write().unwrap().dns_servers = servers;
+    }
+
+    /// 添加 IPv6 地址，已存在时更新前缀长度
+    pub fn add_ipv6_address(&self, cidr: Ipv6Cidr) {
+        let mut config = self.config.write().unwrap();
+        match config.ipv6_addrs.iter_mut().find(|c| c.addr == cidr.addr) {
+            Some(existing) => *existing = cidr,
+            None => config.ipv6_addrs.push(cidr),
+        }
+    }
+
+    pub fn remove_ipv6_address(&self, addr: Ipv6Addr) {
+        self.config
+            .write()
+            .unwrap()
+            .ipv6_addrs
+            .retain(|cidr| cidr.addr != addr);
+        self.set_ipv6_deprecated(addr, false);
+    }
+
+    pub fn set_ipv6_gateway(&self, gateway: Option<Ipv6Addr>) {
+        self.config.write().unwrap().ipv6_gateway = gateway;
+    }
+
+    pub fn set_ipv6_mtu(&self, mtu: Option<usize>) {
+        self.config.write().unwrap().ipv6_mtu = mtu;
+    }
+
+    /// 开始对地址做重复地址检测：加入它的请求节点组，但还不把它当作本机地址
+    pub fn add_tentative_ipv6(&self, addr: Ipv6Addr) {
+        let mut tentative = self.ipv6_tentative.lock().unwrap();
+        if !tentative.contains(&addr) {
+            tentative.push(addr);
+        }
+    }
+
+    pub fn remove_tentative_ipv6(&self, addr: Ipv6Addr) {
+        self.ipv6_tentative.lock().unwrap().retain(|a| *a != addr);
+    }
+
+    pub fn is_tentative_ipv6(&self, addr: Ipv6Addr) -> bool {
+        self.ipv6_tentative.lock().unwrap().contains(&addr)
+    }
+
+    /// 标记地址已弃用 (RFC 4862 5.5.4)，或在首选生存期刷新后取消标记
+    pub fn set_ipv6_deprecated(&self, addr: Ipv6Addr, deprecated: bool) {
+        let mut addrs = self.ipv6_deprecated.lock().unwrap();
+        addrs.retain(|a| *a != addr);
+        if deprecated {
+            addrs.push(addr);
+        }
+    }
+
+    pub fn is_deprecated_ipv6(&self, addr: Ipv6Addr) -> bool {
+        self.ipv6_deprecated.lock().unwrap().contains(&addr)
+    }
+
+    /// 加入 IPv4 多播组，之后发往该组的数据报交给本机
+    ///
+    /// 按次数计数，加入几次就需要离开几次；第一次加入时发送 IGMP 成员报告
+    pub fn join_multicast(&self, group: Ipv4Addr) -> anyhow::Result<()> {
+        if !group.is_multicast() {
+            anyhow::bail!("{} is not a multicast address", group);
+        }
+        let first = {
+            let mut groups = self.multicast_groups.lock().unwrap();
+            let count = groups.entry(group).or_insert(0);
+            *count += 1;
+            *count == 1
+        };
+        if first {
+            handlers::igmp::join_group(self, group);
+        }
+        Ok(())
+    }
+
+    /// 最后一次离开时通知路由器
+    pub fn leave_multicast(&self, group: Ipv4Addr) {
+        let last = {
+            let mut groups = self.multicast_groups.lock().unwrap();
+            match groups.get_mut(&group) {
+                Some(count) if *count > 1 => {
+                    *count -= 1;
+                    false
+                }
+                Some(_) => {
+                    groups.remove(&group);
+                    true
+                }
+                None => false,
+            }
+        };
+        if last {
+            handlers::igmp::leave_group(self, group);
+        }
+    }
+
+    /// 受限广播或本网段的定向广播
+    pub fn is_broadcast_addr(&self, addr: Ipv4Addr) -> bool {
+        let config = self.config();
+        let netmask = config.netmask.to_bits();
+        addr.is_broadcast()
+            || (netmask != 0
+                && addr.to_bits() & netmask == config.ip.to_bits() & netmask
+                && addr.to_bits() | netmask == u32::MAX)
+    }
+
+    /// 所有主机组 224.0.0.1 总是接收，IGMP 查询发往该组
+    pub fn is_multicast_member(&self, group: Ipv4Addr) -> bool {
+        group == IGMP_ALL_SYSTEMS || self.multicast_groups.lock().unwrap().contains_key(&group)
+    }
+
+    /// 多播 MAC 只保留组地址的低 23 位 (IPv6 为低 32 位)，可能有多个组映射到同一个 MAC，由 IP 层再过滤
+    fn accepts_multicast_mac(&self, config: &StackConfig, mac: MacAddr) -> bool {
+        mac.is_multicast()
+            && (mac == MacAddr::from_ipv4_multicast(IGMP_ALL_SYSTEMS)
+                || self
+                    .multicast_groups
+                    .lock()
+                    .unwrap()
+                    .keys()
+                    .any(|group| MacAddr::from_ipv4_multicast(*group) == mac)
+                || self
+                    .ipv6_groups(config)
+                    .into_iter()
+                    .any(|group| MacAddr::from_ipv6_multicast(group) == mac))
+    }
+
+    /// 本机的 IPv6 地址
+    pub fn is_local_ipv6(&self, addr: Ipv6Addr) -> bool {
+        self.config()
+            .ipv6_addrs
+            .iter()
+            .any(|cidr| cidr.addr == addr)
+    }
+
+    /// 配置了 IPv6 地址时自动加入的组：所有节点组，以及每个地址 (包括检测中的地址) 的请求节点组
+    fn ipv6_groups(&self, config: &StackConfig) -> Vec<Ipv6Addr> {
+        let tentative = self.ipv6_tentative.lock().unwrap();
+        if config.ipv6_addrs.is_empty() && tentative.is_empty() {
+            return Vec::new();
+        }
+        let mut groups = vec![Ipv6Addr::all_nodes()];
+        groups.extend(
+            config
+                .ipv6_addrs
+                .iter()
+                .map(|cidr| cidr.addr)
+                .chain(tentative.iter().copied())
+                .map(|addr| addr.solicited_node()),
+        );
+        groups
+    }
+
+    /// IPv6 报文的目的地址是否是本机
+    pub fn accepts_ipv6_dst(&self, addr: Ipv6Addr) -> bool {
+        self.is_local_ipv6(addr) || self.ipv6_groups(&self.config()).contains(&addr)
     }
 
     // 获取 ARP 表
@@ -134,53 +611,302 @@ impl NetworkStack {
         &self.arp_table
     }
 
+    // 获取 IPv6 邻居缓存
+    pub fn neighbor_cache(&self) -> &Arc<Mutex<NeighborCache>> {
+        &self.neighbor_cache
+    }
+
+    // 获取路由表
+    pub fn routing_table(&self) -> &Arc<Mutex<RoutingTable>> {
+        &self.routing_table
+    }
+
+    // 查路由，返回需要 ARP 解析的下一跳地址
+    pub fn next_hop(&self, dst_ip: Ipv4Addr) -> Option<Ipv4Addr> {
+        let routing_table = self.routing_table.lock().unwrap();
+        routing_table
+            .lookup(dst_ip)
+            .map(|route| route.next_hop(dst_ip))
+    }
+
+    // 关联另一个接口 (另一个协议栈实例)，用于多接口转发
+    pub fn attach_interface(&self, name: &str, peer: &Arc<NetworkStack>) {
+        let mut interfaces = self.interfaces.lock().unwrap();
+        interfaces.insert(name.to_string(), Arc::downgrade(peer));
+    }
+
+    pub fn interface(&self, name: &str) -> Option<Arc<NetworkStack>> {
+        let interfaces = self.interfaces.lock().unwrap();
+        interfaces.get(name).and_then(Weak::upgrade)
+    }
+
     // 获取待发送的 IP 包列表
-    pub fn pending_packets(&self) -> &Arc<Mutex<HashMap<Ipv4Addr, VecDeque<PendingPacket>>>> {
+    pub fn pending_packets(&self) -> &Arc<Mutex<PendingQueue>> {
         &self.pending_packets
     }
 
-    pub fn get_rx_capture(&self) -> &Arc<Mutex<Capture<Active>>> {
+    // 获取等待邻居发现的 IPv6 报文
+    pub fn pending_ipv6_packets(&self) -> &Arc<Mutex<PendingQueue<Ipv6Addr>>> {
+        &self.pending_ipv6_packets
+    }
+
+    // 获取分片重组器
+    pub fn reassembler(&self) -> &Arc<Mutex<Reassembler>> {
+        &self.reassembler
+    }
+
+    pub fn icmp_limiter(&self) -> &Mutex<IcmpRateLimiter> {
+        &self.icmp_limiter
+    }
+
+    pub fn icmp_listeners(&self) -> &Mutex<HashMap<IcmpListenerKey, Sender<IcmpEvent>>> {
+        &self.icmp_listeners
+    }
+
+    pub fn igmp(&self) -> &Mutex<IgmpState> {
+        &self.igmp
+    }
+
+    pub fn ndp_listener(&self) -> &Mutex<Option<Sender<NdpEvent>>> {
+        &self.ndp_listener
+    }
+
+    pub fn get_rx_device(&self) -> &Arc<Mutex<Box<dyn Device>>> {
         &self.receiver
     }
 
-    pub fn get_tx_capture(&self) -> &Arc<Mutex<Capture<Active>>> {
+    pub fn get_tx_device(&self) -> &Arc<Mutex<Box<dyn Device>>> {
         &self.sender
     }
 
+    // 链路 MTU，配置优先，否则以发送端设备为准
+    pub fn mtu(&self) -> usize {
+        let mtu = self.config().mtu;
+        match mtu {
+            Some(mtu) => mtu,
+            None => self.sender.lock().unwrap().mtu(),
+        }
+    }
+
+    // IPv6 使用的链路 MTU，路由器通告只能把它调小
+    pub fn ipv6_mtu(&self) -> usize {
+        let mtu = self.mtu();
+        match self.config().ipv6_mtu {
+            Some(ipv6_mtu) => ipv6_mtu.min(mtu),
+            None => mtu,
+        }
+    }
+
+    // 为发往 dst_ip 的数据报分配 Identification
+    // 每个目的地址单独计数，初值取自时钟，避免重启后立即与旧分片撞号
+    pub fn next_ip_id(&self, dst_ip: Ipv4Addr) -> u16 {
+        let now = self.now();
+        let mut ids = self.ip_ids.lock().unwrap();
+        let (id, last_used) = ids.entry(dst_ip).or_insert_with(|| {
+            let nanos = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .subsec_nanos();
+            ((nanos ^ dst_ip.to_bits()) as u16, now)
+        });
+        let current = *id;
+        *id = id.wrapping_add(1);
+        *last_used = now;
+        current
+    }
+
+    // 删除长时间没有使用的 Identification 计数器，否则每个通信过的目的地址都会留下一项
+    // 闲置超过报文最大生存时间后，旧分片已不可能还在网络中，重新取初值是安全的
+    pub fn cleanup_ip_ids(&self) {
+        let now = self.now();
+        self.ip_ids
+            .lock()
+            .unwrap()
+            .retain(|_, (_, last_used)| now.duration_since(*last_used) < IP_ID_IDLE_TIMEOUT);
+    }
+
     pub fn poll_and_send(&self) {
         let mut socket_set = self.sockets.lock().unwrap();
+        let now = self.now();
 
         for (handle, socket) in socket_set.iter_mut() {
-            if let Socket::Udp(udp_socket) = socket {
-                while let Some((dst_ip, dst_port, payload)) = udp_socket.poll_transmit() {
-                    // 从 SocketHandle 中提取源端口
-                    let src_port = handle.local_port;
-
-                    // 构造 UDP 包
-                    let udp_header = protocol::udp::UdpHeader::new(src_port, dst_port, 0);
-                    let udp_packet =
-                        protocol::udp::UdpPacket::new(udp_header, payload, self.config.ip, dst_ip);
-                    let udp_bytes = udp_packet.to_bytes();
-
-                    // 发送
-                    handlers::ipv4::send_packet(self, dst_ip, Ipv4Protocol::UDP, &udp_bytes);
+            match socket {
+                Socket::Udp(udp_socket) => {
+                    while let Some((dst_ip, dst_port, payload)) = udp_socket.poll_transmit() {
+                        // 从 SocketHandle 中提取源端口
+                        let src_port = handle.local_port;
+
+                        // 构造 UDP 包
+                        let udp_header = protocol::udp::UdpHeader::new(src_port, dst_port, 0);
+                        match dst_ip {
+                            IpAddr::V4(dst_ip) => {
+                                let udp_packet = protocol::udp::UdpPacket::new(
+                                    udp_header,
+                                    payload,
+                                    self.config().ip,
+                                    dst_ip,
+                                );
+                                let udp_bytes = udp_packet.to_bytes();
+
+                                // 发送
+                                handlers::ipv4::send_packet(
+                                    self,
+                                    dst_ip,
+                                    Ipv4Protocol::UDP,
+                                    &udp_bytes,
+                                );
+                            }
+                            IpAddr::V6(dst_ip) => {
+                                // 绑定了具体地址时以它为源地址，否则按目的地址选择
+                                let src_ip = match handle.local_addr {
+                                    IpAddr::V6(ip) if !ip.is_unspecified() => Some(ip),
+                                    _ => handlers::ipv6::source_address(self, dst_ip),
+                                };
+                                let Some(src_ip) = src_ip else {
+                                    eprintln!(
+                                        "No IPv6 source address for {}, dropping packet",
+                                        dst_ip
+                                    );
+                                    continue;
+                                };
+                                let udp_packet = protocol::udp::UdpPacket::new_ipv6(
+                                    udp_header, payload, src_ip, dst_ip,
+                                );
+
+                                handlers::ipv6::send_packet_with_options(
+                                    self,
+                                    dst_ip,
+                                    IPV6_NEXT_UDP,
+                                    &udp_packet.to_bytes(),
+                                    &handlers::ipv6::SendOptions {
+                                        src: Some(src_ip),
+                                        ..Default::default()
+                                    },
+                                );
+                            }
+                        }
+                    }
+                }
+                Socket::Tcp(tcp_socket) => {
+                    // 数据、确认、重传和挥手报文都由连接状态机产生
+                    // TCP 只支持 IPv4，连接的地址一定是 IPv4
+                    let IpAddr::V4(remote_addr) = handle.remote_addr else {
+                        continue;
+                    };
+                    for segment in tcp_socket.poll_transmit(now) {
+                        handlers::ipv4::send_packet(
+                            self,
+                            remote_addr,
+                            Ipv4Protocol::TCP,
+                            &segment.to_bytes(),
+                        );
+                    }
                 }
             }
         }
+
+        // 回收已关闭且没有用户持有的 TCP 连接
+        socket_set.retain(|_, socket| !matches!(socket, Socket::Tcp(tcp) if tcp.is_released()));
+    }
+
+    // 是否还有待发送的输出：UDP socket 中未取走的数据，或等待地址解析的包
+    // TCP 的未确认数据要等对端回复，不算在内
+    pub fn has_pending_output(&self) -> bool {
+        let udp_pending = self
+            .sockets
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|(_, socket)| matches!(socket, Socket::Udp(udp) if udp.can_transmit()));
+        udp_pending
+            || !self.pending_packets.lock().unwrap().is_empty()
+            || !self.pending_ipv6_packets.lock().unwrap().is_empty()
     }
 
     pub fn cleanup_pending_packets(&self) {
-        let mut pending = self.pending_packets().lock().unwrap();
-        for (ip, packets) in pending.iter_mut() {
-            packets.retain(|pkt| {
-                if pkt.timestamp.elapsed() < Duration::from_secs(3) {
-                    true
-                } else {
-                    eprintln!("drop timeout pending packet: dst_ip {}", ip);
-                    false
-                }
-            });
+        let now = self.now();
+        expire_pending(&mut self.pending_packets().lock().unwrap(), now);
+        expire_pending(&mut self.pending_ipv6_packets.lock().unwrap(), now);
+    }
+
+    // 丢弃重组超时的分片，收到过首片的回复 ICMP Time Exceeded (code 1)
+    pub fn cleanup_fragments(&self) {
+        let expired = self.reassembler.lock().unwrap().expire(self.now());
+        for datagram in expired {
+            eprintln!("drop timeout fragments: src_ip {}", datagram.src);
+            if let Some(quote) = datagram.quote
+                && let Ok(header) = Ipv4Header::parse(&quote)
+            {
+                handlers::icmp::send_time_exceeded(
+                    self,
+                    TimeExceededCode::FragmentReassembly,
+                    &header,
+                    &quote,
+                );
+            }
         }
-        pending.retain(|_, packets| !packets.is_empty());
+    }
+}
+
+/// 丢弃等待地址解析超过 3 秒的包
+fn expire_pending<A: fmt::Display>(pending: &mut PendingQueue<A>, now: Instant) {
+    for (ip, packets) in pending.iter_mut() {
+        packets.retain(|pkt| {
+            if now.duration_since(pkt.timestamp) < Duration::from_secs(3) {
+                true
+            } else {
+                eprintln!("drop timeout pending packet: dst_ip {}", ip);
+                false
+            }
+        });
+    }
+    pending.retain(|_, packets| !packets.is_empty());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::VirtualClock;
+    use crate::device::MemoryDevice;
+
+    fn stack_with_clock() -> (Arc<NetworkStack>, Arc<VirtualClock>) {
+        let clock = Arc::new(VirtualClock::new());
+        let dev = MemoryDevice::new();
+        let config = StackConfig::new(
+            MacAddr::from_raw([0x02, 0, 0, 0, 0, 1]),
+            Ipv4Addr::new(10, 0, 0, 1),
+        );
+        let stack = initialize_with_clock(config, dev.clone(), dev, clock.clone());
+        (stack, clock)
+    }
+
+    #[test]
+    fn ip_ids_count_per_destination() {
+        let (stack, _) = stack_with_clock();
+        let a = Ipv4Addr::new(10, 0, 0, 2);
+        let b = Ipv4Addr::new(10, 0, 0, 3);
+
+        let first_a = stack.next_ip_id(a);
+        let first_b = stack.next_ip_id(b);
+        assert_eq!(stack.next_ip_id(a), first_a.wrapping_add(1));
+        assert_eq!(stack.next_ip_id(b), first_b.wrapping_add(1));
+    }
+
+    #[test]
+    fn idle_ip_ids_are_evicted() {
+        let (stack, clock) = stack_with_clock();
+        let idle = Ipv4Addr::new(10, 0, 0, 2);
+        let busy = Ipv4Addr::new(10, 0, 0, 3);
+        stack.next_ip_id(idle);
+        stack.next_ip_id(busy);
+
+        clock.advance(IP_ID_IDLE_TIMEOUT / 2);
+        stack.next_ip_id(busy);
+        clock.advance(IP_ID_IDLE_TIMEOUT / 2);
+        stack.cleanup_ip_ids();
+
+        let ids = stack.ip_ids.lock().unwrap();
+        assert!(!ids.contains_key(&idle));
+        assert!(ids.contains_key(&busy));
     }
 }
@@ -0,0 +1,403 @@
+// Copyright (C) 2025 rrrrrzy
+// SPDX-License-Identifier: GPL-3.0-or-later
+//
+// --------------------------------------------------
+// 致敬所有在深夜调试代码的灵魂。
+// 即便 Bug 如山，我亦往矣。
+// --------------------------------------------------
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+//! 类似 traceroute(8) 的路径探测：逐跳增大 TTL 发送探测包，
+//! 收集途中路由器的 Time Exceeded 和目的主机的回复
+
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use protocol::icmp::{DestUnreachableCode, Echo, IcmpMessage};
+use protocol::ipv4::{Ipv4Addr, Ipv4Protocol};
+use protocol::udp::{UdpHeader, UdpPacket};
+
+use crate::cli::TracerouteMethod;
+use crate::handlers::icmp::{self, IcmpListener, IcmpListenerKey};
+use crate::handlers::ipv4::{self, SendOptions};
+use crate::stack::NetworkStack;
+
+#[derive(Debug, Clone)]
+pub struct TracerouteConfig {
+    pub method: TracerouteMethod,
+    pub first_ttl: u8,
+    pub max_hops: u8,
+    /// 每一跳发送的探测包个数
+    pub probes: u32,
+    /// 每个探测包等待回复的时间
+    pub timeout: Duration,
+    /// UDP 探测的起始目的端口，每个探测包加一
+    pub port: u16,
+    /// 探测包的数据部分长度，不含 ICMP / UDP 首部
+    pub payload_size: usize,
+    /// 不打印每一跳，只返回结果
+    pub quiet: bool,
+}
+
+impl Default for TracerouteConfig {
+    fn default() -> Self {
+        Self {
+            method: TracerouteMethod::Icmp,
+            first_ttl: 1,
+            max_hops: 30,
+            probes: 3,
+            timeout: Duration::from_secs(1),
+            port: 33434,
+            payload_size: 32,
+            quiet: false,
+        }
+    }
+}
+
+/// 一个探测包收到的回复
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProbeReply {
+    pub addr: Ipv4Addr,
+    pub rtt: Duration,
+    /// traceroute(8) 风格的不可达标记，如 !H、!N、!F-1500
+    pub annotation: Option<String>,
+}
+
+/// 一跳的探测结果，没有回复的探测包为 None
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hop {
+    pub ttl: u8,
+    pub probes: Vec<Option<ProbeReply>>,
+}
+
+impl fmt::Display for Hop {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:2} ", self.ttl)?;
+        // 同一跳的回复可能来自不同路由器 (负载均衡)，地址变化时重新打印
+        let mut last = None;
+        for probe in &self.probes {
+            match probe {
+                Some(reply) => {
+                    if last != Some(reply.addr) {
+                        write!(f, " {}", reply.addr)?;
+                        last = Some(reply.addr);
+                    }
+                    write!(f, "  {:.3} ms", reply.rtt.as_secs_f64() * 1000.0)?;
+                    if let Some(annotation) = &reply.annotation {
+                        write!(f, " {}", annotation)?;
+                    }
+                }
+                None => write!(f, " *")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// 一次 traceroute 的结果
+#[derive(Debug, Clone, Default)]
+pub struct Trace {
+    pub target: Option<Ipv4Addr>,
+    pub hops: Vec<Hop>,
+    /// 是否收到了目的主机本身的回复
+    pub reached: bool,
+}
+
+// 探测包的回复种类
+enum Outcome {
+    // 途中路由器的 Time Exceeded
+    Hop,
+    // 到达目的主机：ICMP Echo Reply，或 UDP 探测的端口不可达
+    Reached,
+    // 其他不可达，继续增大 TTL 没有意义
+    Unreachable(String),
+}
+
+/// 向 target 逐跳探测，阻塞直到到达目的主机、超过最大跳数，或 cancel 被置位
+///
+/// 回复由事件循环中的 ICMP handler 投递，调用前需要有线程在运行 `event_loop::run`
+pub fn traceroute(
+    stack: &NetworkStack,
+    target: Ipv4Addr,
+    config: &TracerouteConfig,
+    cancel: &AtomicBool,
+) -> anyhow::Result<Trace> {
+    if config.first_ttl == 0 || config.first_ttl > config.max_hops {
+        anyhow::bail!(
+            "Invalid TTL range {}..={}",
+            config.first_ttl,
+            config.max_hops
+        );
+    }
+
+    // ICMP 探测按 Echo 标识、UDP 探测按源端口认领回复
+    let listener = match config.method {
+        TracerouteMethod::Icmp => IcmpListener::ephemeral(stack, IcmpListenerKey::Echo),
+        TracerouteMethod::Udp => IcmpListener::ephemeral(stack, IcmpListenerKey::Udp),
+    }
+    .ok_or_else(|| anyhow::anyhow!("No free probe identifier"))?;
+
+    let data = vec![0u8; config.payload_size];
+    if !config.quiet {
+        // ICMP 和 UDP 首部都是 8 字节
+        println!(
+            "traceroute to {}, {} hops max, {} byte packets",
+            target,
+            config.max_hops,
+            config.payload_size + 8 + 20
+        );
+    }
+
+    let mut trace = Trace {
+        target: Some(target),
+        ..Default::default()
+    };
+    let mut seq: u16 = 0;
+
+    for ttl in config.first_ttl..=config.max_hops {
+        let mut hop = Hop {
+            ttl,
+            probes: Vec::new(),
+        };
+        let mut done = false;
+
+        for _ in 0..config.probes {
+            if cancel.load(Ordering::Relaxed) {
+                return Ok(trace);
+            }
+            seq = seq.wrapping_add(1);
+
+            let (protocol, probe) = match config.method {
+                TracerouteMethod::Icmp => {
+                    let request = IcmpMessage::EchoRequest(Echo {
+                        id: listener.id(),
+                        seq,
+                        data: data.clone(),
+                    });
+                    (Ipv4Protocol::ICMP, request.to_bytes())
+                }
+                TracerouteMethod::Udp => {
+                    let dst_port = config.port.wrapping_add(seq);
+                    let packet = UdpPacket::new(
+                        UdpHeader::new(listener.id(), dst_port, 0),
+                        data.clone(),
+                        stack.config().ip,
+                        target,
+                    );
+                    (Ipv4Protocol::UDP, packet.to_bytes())
+                }
+            };
+            let options = SendOptions {
+                ttl,
+                dont_fragment: false,
+                router_alert: false,
+            };
+
+            let sent = stack.now();
+            ipv4::send_packet_with_options(stack, target, protocol, &probe, &options);
+
+            let reply = wait_reply(stack, &listener, config, seq, sent, cancel);
+            match reply {
+                Some((reply, outcome)) => {
+                    done |= !matches!(outcome, Outcome::Hop);
+                    trace.reached |= matches!(outcome, Outcome::Reached);
+                    hop.probes.push(Some(reply));
+                }
+                None => hop.probes.push(None),
+            }
+        }
+
+        if !config.quiet {
+            println!("{}", hop);
+        }
+        trace.hops.push(hop);
+        if done {
+            break;
+        }
+    }
+
+    Ok(trace)
+}
+
+/// 等待第 seq 个探测包的回复，超时返回 None
+fn wait_reply(
+    stack: &NetworkStack,
+    listener: &IcmpListener,
+    config: &TracerouteConfig,
+    seq: u16,
+    sent: Instant,
+    cancel: &AtomicBool,
+) -> Option<(ProbeReply, Outcome)> {
+    let deadline = sent + config.timeout;
+
+    while !cancel.load(Ordering::Relaxed) {
+        let now = stack.now();
+        if now >= deadline {
+            return None;
+        }
+        // 分段等待，以便及时响应 cancel
+        let wait = (deadline - now).min(Duration::from_millis(100));
+        let Some(event) = listener.recv_timeout(wait) else {
+            continue;
+        };
+
+        // 前面超时的探测包迟到的回复直接丢掉
+        let outcome = match (&event.message, config.method) {
+            (IcmpMessage::EchoReply(echo), TracerouteMethod::Icmp) if echo.seq == seq => {
+                Outcome::Reached
+            }
+            (message, method) if message.is_error() => {
+                let Some((_, quoted)) = icmp::quoted_datagram(message) else {
+                    continue;
+                };
+                // Echo 的序号和 UDP 的目的端口都标识了是哪个探测包
+                let probe_seq = match method {
+                    TracerouteMethod::Icmp => u16::from_be_bytes([quoted[6], quoted[7]]),
+                    TracerouteMethod::Udp => {
+                        u16::from_be_bytes([quoted[2], quoted[3]]).wrapping_sub(config.port)
+                    }
+                };
+                if probe_seq != seq {
+                    continue;
+                }
+                match classify_error(message) {
+                    Some(outcome) => outcome,
+                    None => continue,
+                }
+            }
+            _ => continue,
+        };
+
+        let annotation = match &outcome {
+            Outcome::Unreachable(annotation) => Some(annotation.clone()),
+            _ => None,
+        };
+        let reply = ProbeReply {
+            addr: event.src,
+            rtt: event.received.saturating_duration_since(sent),
+            annotation,
+        };
+        return Some((reply, outcome));
+    }
+    None
+}
+
+/// 按 traceroute(8) 的约定解释差错报文
+fn classify_error(message: &IcmpMessage) -> Option<Outcome> {
+    match message {
+        IcmpMessage::TimeExceeded { .. } => Some(Outcome::Hop),
+        IcmpMessage::DestUnreachable {
+            code, next_hop_mtu, ..
+        } => {
+            let annotation = match code {
+                DestUnreachableCode::PortUnreachable => return Some(Outcome::Reached),
+                DestUnreachableCode::NetUnreachable
+                | DestUnreachableCode::NetUnknown
+                | DestUnreachableCode::NetUnreachableForTos => "!N".to_string(),
+                DestUnreachableCode::HostUnreachable
+                | DestUnreachableCode::HostUnknown
+                | DestUnreachableCode::HostUnreachableForTos => "!H".to_string(),
+                DestUnreachableCode::ProtocolUnreachable => "!P".to_string(),
+                DestUnreachableCode::FragmentationNeeded => format!("!F-{}", next_hop_mtu),
+                DestUnreachableCode::SourceRouteFailed => "!S".to_string(),
+                DestUnreachableCode::NetProhibited
+                | DestUnreachableCode::HostProhibited
+                | DestUnreachableCode::AdminProhibited => "!X".to_string(),
+                DestUnreachableCode::HostPrecedenceViolation => "!V".to_string(),
+                DestUnreachableCode::PrecedenceCutoff => "!C".to_string(),
+                code => format!("!<{}>", code.code()),
+            };
+            Some(Outcome::Unreachable(annotation))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reply(addr: [u8; 4], ms: u64, annotation: Option<&str>) -> Option<ProbeReply> {
+        Some(ProbeReply {
+            addr: Ipv4Addr::from_octets(addr),
+            rtt: Duration::from_millis(ms),
+            annotation: annotation.map(str::to_string),
+        })
+    }
+
+    #[test]
+    fn hop_display_matches_traceroute() {
+        let hop = Hop {
+            ttl: 3,
+            probes: vec![
+                reply([10, 0, 0, 1], 1, None),
+                None,
+                reply([10, 0, 0, 1], 2, None),
+                reply([10, 0, 0, 7], 3, Some("!H")),
+            ],
+        };
+        // 地址不变时不重复打印
+        assert_eq!(
+            hop.to_string(),
+            " 3  10.0.0.1  1.000 ms *  2.000 ms 10.0.0.7  3.000 ms !H"
+        );
+    }
+
+    #[test]
+    fn errors_are_classified_like_traceroute() {
+        let unreachable = |code, next_hop_mtu| IcmpMessage::DestUnreachable {
+            code,
+            next_hop_mtu,
+            original: Vec::new(),
+        };
+        let annotation = |message: &IcmpMessage| match classify_error(message) {
+            Some(Outcome::Unreachable(annotation)) => annotation,
+            _ => panic!("{} is not an unreachable outcome", message),
+        };
+
+        assert!(matches!(
+            classify_error(&IcmpMessage::TimeExceeded {
+                code: protocol::icmp::TimeExceededCode::TtlExceeded,
+                original: Vec::new(),
+            }),
+            Some(Outcome::Hop)
+        ));
+        assert!(matches!(
+            classify_error(&unreachable(DestUnreachableCode::PortUnreachable, 0)),
+            Some(Outcome::Reached)
+        ));
+        assert_eq!(
+            annotation(&unreachable(DestUnreachableCode::HostUnreachable, 0)),
+            "!H"
+        );
+        assert_eq!(
+            annotation(&unreachable(DestUnreachableCode::NetUnreachable, 0)),
+            "!N"
+        );
+        assert_eq!(
+            annotation(&unreachable(DestUnreachableCode::FragmentationNeeded, 1400)),
+            "!F-1400"
+        );
+        assert_eq!(
+            annotation(&unreachable(DestUnreachableCode::AdminProhibited, 0)),
+            "!X"
+        );
+        assert_eq!(
+            annotation(&unreachable(DestUnreachableCode::parse(99), 0)),
+            "!<99>"
+        );
+        assert!(
+            classify_error(&IcmpMessage::EchoReply(Echo {
+                id: 1,
+                seq: 1,
+                data: Vec::new(),
+            }))
+            .is_none()
+        );
+    }
+}
@@ -11,28 +11,30 @@
 // the Free Software Foundation, either version 3 of the License, or
 // (at your option) any later version.
 
-use protocol::ipv4::Ipv4Addr;
+use protocol::ip::IpAddr;
 use std::collections::HashMap;
 
+use crate::transport::tcp::TcpSocketState;
 use crate::transport::udp::UdpSocketState;
 
+pub mod tcp;
 pub mod udp;
 
 #[derive(Debug, Hash, Clone, Copy, PartialEq, Eq)]
 pub struct SocketHandle {
     pub protocol: u8, // 6 -> TCP, 17 -> UDP
-    pub local_addr: Ipv4Addr,
+    pub local_addr: IpAddr,
     pub local_port: u16,
-    pub remote_addr: Ipv4Addr,
+    pub remote_addr: IpAddr,
     pub remote_port: u16,
 }
 
 impl SocketHandle {
     pub fn new(
         protocol: &SocketType,
-        local_addr: Ipv4Addr,
+        local_addr: IpAddr,
         local_port: u16,
-        remote_addr: Ipv4Addr,
+        remote_addr: IpAddr,
         remote_port: u16,
     ) -> Self {
         Self {
@@ -73,7 +75,8 @@ impl SocketType {
 #[derive(Debug)]
 pub enum Socket {
     Udp(UdpSocketState),
-    // Future: Tcp(TcpSocket)
+    // 控制块比 UDP 大得多，放在堆上
+    Tcp(Box<TcpSocketState>),
 }
 
 pub struct SocketSet {
@@ -107,16 +110,38 @@ impl SocketSet {
         self.sockets.iter_mut()
     }
 
+    pub fn iter(&self) -> impl Iterator<Item = (&SocketHandle, &Socket)> {
+        self.sockets.iter()
+    }
+
+    /// 只保留 f 返回 true 的 Socket
+    pub fn retain(&mut self, f: impl FnMut(&SocketHandle, &mut Socket) -> bool) {
+        self.sockets.retain(f);
+    }
+
     /// 查找单播 Socket (Unicast Lookup)
     /// 返回最佳匹配的一个 Socket
     pub fn lookup(
         &mut self,
         protocol: &SocketType,
-        src_ip: Ipv4Addr,
+        src_ip: IpAddr,
         src_port: u16,
-        dst_ip: Ipv4Addr,
+        dst_ip: IpAddr,
         dst_port: u16,
     ) -> Option<&mut Socket> {
+        let handle = self.lookup_handle(protocol, src_ip, src_port, dst_ip, dst_port)?;
+        self.sockets.get_mut(&handle)
+    }
+
+    /// 与 lookup 相同的匹配规则，返回匹配到的 SocketHandle
+    pub fn lookup_handle(
+        &self,
+        protocol: &SocketType,
+        src_ip: IpAddr,
+        src_port: u16,
+        dst_ip: IpAddr,
+        dst_port: u16,
+    ) -> Option<SocketHandle> {
         // 1. 精确匹配 (5元组完整匹配)
         let socket_handle_exact = SocketHandle::new(
             protocol, dst_ip,   // 本地 IP
@@ -125,31 +150,30 @@ impl SocketSet {
             src_port, // 远程端口
         );
         if self.sockets.contains_key(&socket_handle_exact) {
-            return self.sockets.get_mut(&socket_handle_exact);
+            return Some(socket_handle_exact);
         }
 
+        // 通配地址与报文同一版本，IPv4 的 socket 不会收到 IPv6 报文
+        let any_ip = dst_ip.unspecified_of_same_family();
+
         // 2. 监听特定 IP (Local IP 匹配, Remote 为 0)
         let socket_handle_specified = SocketHandle::new(
-            protocol,
-            dst_ip,
-            dst_port,
-            Ipv4Addr::unspecified(), // 0.0.0.0
-            0,                       // 0
+            protocol, dst_ip, dst_port, any_ip, // 0.0.0.0 或 ::
+            0,
         );
         if self.sockets.contains_key(&socket_handle_specified) {
-            return self.sockets.get_mut(&socket_handle_specified);
+            return Some(socket_handle_specified);
         }
 
         // 3. 监听所有 IP (Local IP 为 0, Remote 为 0)
         let socket_handle_wildcard = SocketHandle::new(
-            protocol,
-            Ipv4Addr::unspecified(), // 0.0.0.0
-            dst_port,                // specified ports
-            Ipv4Addr::unspecified(), // 0.0.0.0
-            0,                       // 0
+            protocol, any_ip,   // 0.0.0.0 或 ::
+            dst_port, // specified ports
+            any_ip,   // 0.0.0.0 或 ::
+            0,
         );
         if self.sockets.contains_key(&socket_handle_wildcard) {
-            return self.sockets.get_mut(&socket_handle_wildcard);
+            return Some(socket_handle_wildcard);
         }
         None
     }
@@ -159,12 +183,12 @@ impl SocketSet {
     pub fn lookup_multicast(
         &mut self,
         protocol: &SocketType,
-        src_ip: Ipv4Addr,
+        src_ip: IpAddr,
         src_port: u16,
-        dst_ip: Ipv4Addr,
+        dst_ip: IpAddr,
         dst_port: u16,
     ) -> Vec<&mut Socket> {
-        let any_ip = Ipv4Addr::unspecified();
+        let any_ip = dst_ip.unspecified_of_same_family();
 
         self.sockets
             .iter_mut()
@@ -180,14 +204,14 @@ impl SocketSet {
                 }
 
                 // 3. 本地 IP 匹配 (目的 IP)
-                // Socket 必须绑定到该多播组 IP，或者绑定到 0.0.0.0 (INADDR_ANY)
+                // Socket 必须绑定到该多播组 IP，或者绑定到同一版本的通配地址 (INADDR_ANY / in6addr_any)
                 if handle.local_addr != dst_ip && handle.local_addr != any_ip {
                     return None;
                 }
 
                 // 4. 远程 IP 匹配 (源 IP)
                 // 如果 Socket 指定了远程 IP (已连接)，则必须匹配源 IP
-                // 否则 (远程 IP 为 0.0.0.0)，接受任何源 IP
+                // 否则 (远程 IP 为通配地址)，接受任何源 IP
                 if handle.remote_addr != any_ip && handle.remote_addr != src_ip {
                     return None;
                 }
@@ -11,3 +11,1240 @@
 // the Free Software Foundation, either version 3 of the License, or
 // (at your option) any later version.
 
+//! TCP 连接 (RFC 9293)
+//!
+//! `TcpSocketState` 是一个连接的控制块：收到的报文段由 `handlers::tcp` 交给 `process`，
+//! 要发出的报文段 (含重传、确认、窗口探测) 由 `NetworkStack::poll_and_send` 调用
+//! `poll_transmit` 取出。重传超时按 RFC 6298 估计，发送窗口受对端通告窗口限制，
+//! 乱序到达的数据先缓存，补齐后再按序交给用户。
+
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use std::{error, fmt, thread};
+
+use protocol::ipv4::Ipv4Addr;
+use protocol::tcp::{
+    TCP_FLAG_ACK, TCP_FLAG_FIN, TCP_FLAG_PSH, TCP_FLAG_RST, TCP_FLAG_SYN, TcpHeader, TcpOption,
+    TcpSegment,
+};
+
+use crate::stack::NetworkStack;
+use crate::transport::udp::parse_addr;
+use crate::transport::{Socket, SocketHandle, SocketType};
+use protocol::ip::IpAddr;
+
+/// 收发缓冲区的默认大小，不使用窗口扩大，所以不超过 65535
+const DEFAULT_BUFFER_SIZE: usize = 65535;
+/// 监听 socket 默认的全连接 + 半连接队列长度
+const DEFAULT_BACKLOG: usize = 128;
+/// 对端没有通告 MSS 时使用的默认值 (RFC 9293 3.7.1)
+const DEFAULT_MSS: u16 = 536;
+
+const INITIAL_RTO: Duration = Duration::from_secs(1);
+const MIN_RTO: Duration = Duration::from_millis(200);
+const MAX_RTO: Duration = Duration::from_secs(60);
+/// 同一报文段连续超时这么多次后放弃连接
+const MAX_RETRIES: u32 = 8;
+
+/// TIME_WAIT 持续时间，即 2 * MSL
+pub const TIME_WAIT_TIMEOUT: Duration = Duration::from_secs(60);
+
+// 序号比较，按 2^32 回绕 (RFC 9293 3.4)
+fn seq_lt(a: u32, b: u32) -> bool {
+    (a.wrapping_sub(b) as i32) < 0
+}
+
+fn seq_le(a: u32, b: u32) -> bool {
+    (a.wrapping_sub(b) as i32) <= 0
+}
+
+/// 初始序号：4 微秒一跳的时钟加上四元组的散列 (RFC 9293 3.4.1)
+fn initial_sequence(local_port: u16, remote_ip: Ipv4Addr, remote_port: u16) -> u32 {
+    let micros = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_micros() as u32;
+    let tuple = remote_ip.to_bits() ^ ((local_port as u32) << 16 | remote_port as u32);
+    (micros / 4).wrapping_add(tuple.wrapping_mul(0x9E37_79B9))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TcpState {
+    Closed,
+    Listen,
+    SynSent,
+    SynReceived,
+    Established,
+    FinWait1,
+    FinWait2,
+    CloseWait,
+    Closing,
+    LastAck,
+    TimeWait,
+}
+
+/// 连接异常结束的原因
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TcpError {
+    Refused,
+    Reset,
+    TimedOut,
+}
+
+impl fmt::Display for TcpError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TcpError::Refused => write!(f, "Connection refused"),
+            TcpError::Reset => write!(f, "Connection reset by peer"),
+            TcpError::TimedOut => write!(f, "Connection timed out"),
+        }
+    }
+}
+
+impl error::Error for TcpError {}
+
+/// `process` 要求调用者完成的动作
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TcpAction {
+    None,
+    /// 被动打开的连接完成了三次握手，需要放进监听 socket 的 accept 队列
+    Established,
+    /// 回复 RST (RFC 9293 3.10.7 中的 reset generation)
+    Reset,
+}
+
+#[derive(Debug)]
+pub struct TcpSocketState {
+    state: TcpState,
+    local_ip: Ipv4Addr,
+    local_port: u16,
+    remote_ip: Ipv4Addr,
+    remote_port: u16,
+
+    // 监听 socket：已完成握手、等待 accept 的连接
+    backlog: usize,
+    accept_queue: VecDeque<SocketHandle>,
+    // 被动打开的连接所属的监听 socket
+    parent: Option<SocketHandle>,
+    // 没有用户持有 (已 drop 或尚未 accept)，关闭后即可回收
+    detached: bool,
+
+    // 发送序号空间
+    iss: u32,
+    snd_una: u32,
+    snd_nxt: u32,
+    // 发出过的最大序号，超时回退 snd_nxt 后仍用它判断 ACK 是否合法
+    snd_max: u32,
+    snd_wnd: u32,
+    snd_wl1: u32,
+    snd_wl2: u32,
+    snd_wscale: u8,
+    snd_mss: u16,
+    // 从 snd_una 开始的数据：已发未确认 + 未发送
+    send_buf: VecDeque<u8>,
+    send_capacity: usize,
+    fin_queued: bool,
+    fin_acked: bool,
+
+    // 接收序号空间
+    rcv_nxt: u32,
+    recv_buf: VecDeque<u8>,
+    recv_capacity: usize,
+    // 窗口内乱序到达的数据，按起始序号索引，各段互不重叠也不相邻
+    out_of_order: BTreeMap<u32, Vec<u8>>,
+    out_of_order_len: usize,
+    // 对端 FIN 的序号，前面的数据收齐后才处理
+    fin_seq: Option<u32>,
+    fin_received: bool,
+    last_adv_wnd: usize,
+    our_mss: u16,
+    peer_window_scale: bool,
+
+    // 定时器
+    rto: Duration,
+    srtt: Option<Duration>,
+    rttvar: Duration,
+    // 正在计时的报文段 (结束序号, 发送时刻)，重传后作废 (Karn 算法)
+    rtt_sample: Option<(u32, Instant)>,
+    retransmit_at: Option<Instant>,
+    retries: u32,
+    time_wait_until: Option<Instant>,
+    // 对端窗口为 0 时，定时器到期后允许发 1 字节探测
+    probe: bool,
+
+    ack_pending: bool,
+    rst_pending: bool,
+    error: Option<TcpError>,
+}
+
+impl TcpSocketState {
+    fn new(local_ip: Ipv4Addr, local_port: u16, remote_ip: Ipv4Addr, remote_port: u16) -> Self {
+        Self {
+            state: TcpState::Closed,
+            local_ip,
+            local_port,
+            remote_ip,
+            remote_port,
+            backlog: DEFAULT_BACKLOG,
+            accept_queue: VecDeque::new(),
+            parent: None,
+            detached: false,
+            iss: 0,
+            snd_una: 0,
+            snd_nxt: 0,
+            snd_max: 0,
+            snd_wnd: 0,
+            snd_wl1: 0,
+            snd_wl2: 0,
+            snd_wscale: 0,
+            snd_mss: DEFAULT_MSS,
+            send_buf: VecDeque::new(),
+            send_capacity: DEFAULT_BUFFER_SIZE,
+            fin_queued: false,
+            fin_acked: false,
+            rcv_nxt: 0,
+            recv_buf: VecDeque::new(),
+            recv_capacity: DEFAULT_BUFFER_SIZE,
+            out_of_order: BTreeMap::new(),
+            out_of_order_len: 0,
+            fin_seq: None,
+            fin_received: false,
+            last_adv_wnd: 0,
+            our_mss: DEFAULT_MSS,
+            peer_window_scale: false,
+            rto: INITIAL_RTO,
+            srtt: None,
+            rttvar: Duration::ZERO,
+            rtt_sample: None,
+            retransmit_at: None,
+            retries: 0,
+            time_wait_until: None,
+            probe: false,
+            ack_pending: false,
+            rst_pending: false,
+            error: None,
+        }
+    }
+
+    /// 监听 socket
+    pub fn listen(local_ip: Ipv4Addr, local_port: u16, backlog: usize) -> Self {
+        let mut socket = Self::new(local_ip, local_port, Ipv4Addr::unspecified(), 0);
+        socket.state = TcpState::Listen;
+        socket.backlog = backlog;
+        socket
+    }
+
+    /// 主动打开，SYN 在下一次 poll_transmit 时发出
+    pub fn connect(
+        local_ip: Ipv4Addr,
+        local_port: u16,
+        remote_ip: Ipv4Addr,
+        remote_port: u16,
+        mss: u16,
+    ) -> Self {
+        let mut socket = Self::new(local_ip, local_port, remote_ip, remote_port);
+        socket.state = TcpState::SynSent;
+        socket.our_mss = mss;
+        socket.iss = initial_sequence(local_port, remote_ip, remote_port);
+        socket.snd_una = socket.iss;
+        socket.snd_nxt = socket.iss;
+        socket.snd_max = socket.iss;
+        socket
+    }
+
+    /// 监听 socket 收到 SYN 后创建的连接，处于 SYN-RECEIVED，尚未被 accept
+    pub fn accept_syn(
+        parent: SocketHandle,
+        local_ip: Ipv4Addr,
+        remote_ip: Ipv4Addr,
+        syn: &TcpSegment,
+        mss: u16,
+    ) -> Self {
+        let h = &syn.header;
+        let mut socket = Self::new(local_ip, h.dst_port, remote_ip, h.src_port);
+        socket.state = TcpState::SynReceived;
+        socket.parent = Some(parent);
+        socket.detached = true;
+        socket.our_mss = mss;
+        socket.iss = initial_sequence(h.dst_port, remote_ip, h.src_port);
+        socket.snd_una = socket.iss;
+        socket.snd_nxt = socket.iss;
+        socket.snd_max = socket.iss;
+        socket.rcv_nxt = h.seq.wrapping_add(1);
+        socket.apply_syn(h);
+        socket
+    }
+
+    pub fn state(&self) -> TcpState {
+        self.state
+    }
+
+    pub fn error(&self) -> Option<TcpError> {
+        self.error
+    }
+
+    pub fn parent(&self) -> Option<SocketHandle> {
+        self.parent
+    }
+
+    pub fn backlog(&self) -> usize {
+        self.backlog
+    }
+
+    pub fn accept_queue(&mut self) -> &mut VecDeque<SocketHandle> {
+        &mut self.accept_queue
+    }
+
+    pub fn set_detached(&mut self, detached: bool) {
+        self.detached = detached;
+    }
+
+    /// 已关闭且没有用户持有，可以从 SocketSet 中移除
+    pub fn is_released(&self) -> bool {
+        self.detached && self.state == TcpState::Closed && !self.rst_pending
+    }
+
+    /// 有数据可读，或者对端已关闭 (读到 EOF)
+    pub fn can_recv(&self) -> bool {
+        !self.recv_buf.is_empty() || self.fin_received
+    }
+
+    /// 对端已发送 FIN 且数据都已读完
+    pub fn is_eof(&self) -> bool {
+        self.fin_received && self.recv_buf.is_empty()
+    }
+
+    /// 发送缓冲区剩余空间
+    pub fn send_space(&self) -> usize {
+        self.send_capacity.saturating_sub(self.send_buf.len())
+    }
+
+    /// 把数据放入发送缓冲区，返回实际放入的字节数
+    pub fn send(&mut self, data: &[u8]) -> usize {
+        let n = data.len().min(self.send_space());
+        self.send_buf.extend(&data[..n]);
+        n
+    }
+
+    /// 取出所有已按序到达的数据
+    pub fn recv(&mut self) -> Vec<u8> {
+        let data: Vec<u8> = self.recv_buf.drain(..).collect();
+        // 窗口从不足一半恢复到一半以上时主动通告，避免对端卡在小窗口上
+        if self.last_adv_wnd < self.recv_capacity / 2 && self.rcv_wnd() >= self.recv_capacity / 2 {
+            self.ack_pending = true;
+        }
+        data
+    }
+
+    /// 正常关闭：发完缓冲区中的数据后发送 FIN
+    pub fn close(&mut self) {
+        match self.state {
+            TcpState::Listen | TcpState::SynSent => self.enter_closed(),
+            TcpState::SynReceived | TcpState::Established => {
+                self.fin_queued = true;
+                self.state = TcpState::FinWait1;
+            }
+            TcpState::CloseWait => {
+                self.fin_queued = true;
+                self.state = TcpState::LastAck;
+            }
+            _ => {}
+        }
+    }
+
+    /// 异常关闭：发送 RST 并立即进入 CLOSED
+    pub fn abort(&mut self) {
+        if !matches!(
+            self.state,
+            TcpState::Closed | TcpState::Listen | TcpState::SynSent | TcpState::TimeWait
+        ) {
+            self.rst_pending = true;
+        }
+        self.enter_closed();
+    }
+
+    fn enter_closed(&mut self) {
+        self.state = TcpState::Closed;
+        self.retransmit_at = None;
+        self.time_wait_until = None;
+        self.send_buf.clear();
+        self.out_of_order.clear();
+        self.out_of_order_len = 0;
+    }
+
+    fn enter_time_wait(&mut self, now: Instant) {
+        self.state = TcpState::TimeWait;
+        self.retransmit_at = None;
+        self.time_wait_until = Some(now + TIME_WAIT_TIMEOUT);
+    }
+
+    /// 当前可以通告的接收窗口，已缓存的乱序数据也占用接收缓冲区
+    fn rcv_wnd(&self) -> usize {
+        self.recv_capacity
+            .saturating_sub(self.recv_buf.len() + self.out_of_order_len)
+            .min(u16::MAX as usize)
+    }
+
+    /// 从 SYN 中取出 MSS 和窗口扩大选项，SYN 中的窗口本身不扩大 (RFC 7323 2.2)
+    fn apply_syn(&mut self, h: &TcpHeader) {
+        self.snd_mss = h.mss().unwrap_or(DEFAULT_MSS).min(self.our_mss);
+        self.peer_window_scale = h.window_scale().is_some();
+        self.snd_wscale = h.window_scale().unwrap_or(0).min(14);
+        self.snd_wnd = h.window as u32;
+        self.snd_wl1 = h.seq;
+        self.snd_wl2 = h.ack;
+    }
+
+    fn update_rto(&mut self, r: Duration) {
+        // RFC 6298 2.2 / 2.3
+        match self.srtt {
+            None => {
+                self.srtt = Some(r);
+                self.rttvar = r / 2;
+            }
+            Some(srtt) => {
+                let diff = srtt.abs_diff(r);
+                self.rttvar = self.rttvar * 3 / 4 + diff / 4;
+                self.srtt = Some(srtt * 7 / 8 + r / 8);
+            }
+        }
+        let srtt = self.srtt.unwrap_or(r);
+        self.rto = (srtt + (self.rttvar * 4).max(Duration::from_millis(1))).clamp(MIN_RTO, MAX_RTO);
+    }
+
+    /// 处理一个发给本连接的报文段 (RFC 9293 3.10.7)
+    pub fn process(&mut self, segment: &TcpSegment, now: Instant) -> TcpAction {
+        let h = &segment.header;
+        match self.state {
+            TcpState::Closed => {
+                return if h.has_flag(TCP_FLAG_RST) {
+                    TcpAction::None
+                } else {
+                    TcpAction::Reset
+                };
+            }
+            // 监听 socket 由 handlers::tcp 处理
+            TcpState::Listen => return TcpAction::None,
+            TcpState::SynSent => return self.process_syn_sent(segment, now),
+            _ => {}
+        }
+
+        // SYN-RECEIVED 中再次收到对端的 SYN，序号检查会把它当成旧报文段
+        if self.state == TcpState::SynReceived
+            && h.has_flag(TCP_FLAG_SYN)
+            && !h.has_flag(TCP_FLAG_RST)
+            && h.seq.wrapping_add(1) == self.rcv_nxt
+        {
+            if !h.has_flag(TCP_FLAG_ACK) {
+                // 对端重传的 SYN，说明它没收到我们的 SYN-ACK，重发一次
+                self.snd_nxt = self.iss;
+                return TcpAction::None;
+            }
+            // 同时打开 (RFC 9293 图 8)：对端的 SYN-ACK 确认了我们的 SYN
+            if seq_lt(self.snd_una, h.ack) && seq_le(h.ack, self.snd_max) {
+                self.snd_wnd = h.window as u32;
+                self.syn_acked(h.ack, now);
+                return TcpAction::Established;
+            }
+            self.ack_pending = true;
+            return TcpAction::None;
+        }
+
+        // 1. 序号检查，不在窗口内的报文段只回一个 ACK
+        if !self.acceptable(segment) {
+            if !h.has_flag(TCP_FLAG_RST) {
+                self.ack_pending = true;
+            }
+            return TcpAction::None;
+        }
+
+        // 2. RST：序号不是恰好 rcv_nxt 时回 challenge ACK (RFC 5961 3.2)
+        if h.has_flag(TCP_FLAG_RST) {
+            if h.seq != self.rcv_nxt {
+                self.ack_pending = true;
+                return TcpAction::None;
+            }
+            if matches!(
+                self.state,
+                TcpState::Established
+                    | TcpState::FinWait1
+                    | TcpState::FinWait2
+                    | TcpState::CloseWait
+            ) || (self.state == TcpState::SynReceived && self.parent.is_none())
+            {
+                self.error = Some(TcpError::Reset);
+            }
+            self.enter_closed();
+            return TcpAction::None;
+        }
+
+        // 3. 同步后的 SYN 一律回 challenge ACK (RFC 5961 4.2)
+        if h.has_flag(TCP_FLAG_SYN) {
+            self.ack_pending = true;
+            return TcpAction::None;
+        }
+
+        // 4. ACK
+        if !h.has_flag(TCP_FLAG_ACK) {
+            return TcpAction::None;
+        }
+        let mut action = TcpAction::None;
+        if self.state == TcpState::SynReceived {
+            if !(seq_lt(self.snd_una, h.ack) && seq_le(h.ack, self.snd_max)) {
+                return TcpAction::Reset;
+            }
+            self.state = TcpState::Established;
+            action = TcpAction::Established;
+            // SYN-ACK 的确认与普通数据一样处理，窗口从这里开始按扩大因子解释
+            self.snd_wl1 = h.seq.wrapping_sub(1);
+        }
+        if !self.process_ack(h, now) {
+            return action;
+        }
+
+        // 5. 数据
+        if matches!(
+            self.state,
+            TcpState::Established | TcpState::FinWait1 | TcpState::FinWait2
+        ) && !segment.payload.is_empty()
+        {
+            self.receive_data(h.seq, &segment.payload);
+            self.ack_pending = true;
+        }
+
+        // 6. FIN，前面的数据收齐后才生效
+        if h.has_flag(TCP_FLAG_FIN) && !self.fin_received {
+            self.fin_seq = Some(h.seq.wrapping_add(segment.payload.len() as u32));
+        }
+        self.check_fin(now);
+
+        action
+    }
+
+    fn process_syn_sent(&mut self, segment: &TcpSegment, now: Instant) -> TcpAction {
+        let h = &segment.header;
+        let ack_ok = seq_lt(self.iss, h.ack) && seq_le(h.ack, self.snd_max);
+        if h.has_flag(TCP_FLAG_ACK) && !ack_ok {
+            return if h.has_flag(TCP_FLAG_RST) {
+                TcpAction::None
+            } else {
+                TcpAction::Reset
+            };
+        }
+        if h.has_flag(TCP_FLAG_RST) {
+            if h.has_flag(TCP_FLAG_ACK) {
+                self.error = Some(TcpError::Refused);
+                self.enter_closed();
+            }
+            return TcpAction::None;
+        }
+        if !h.has_flag(TCP_FLAG_SYN) {
+            return TcpAction::None;
+        }
+
+        self.rcv_nxt = h.seq.wrapping_add(1);
+        self.apply_syn(h);
+        self.ack_pending = true;
+
+        if h.has_flag(TCP_FLAG_ACK) {
+            self.syn_acked(h.ack, now);
+        } else {
+            // 同时打开：重新发出带 ACK 的 SYN
+            self.state = TcpState::SynReceived;
+            self.snd_nxt = self.iss;
+        }
+        TcpAction::None
+    }
+
+    /// 我们的 SYN 被确认，连接进入 ESTABLISHED
+    fn syn_acked(&mut self, ack: u32, now: Instant) {
+        self.snd_una = ack;
+        self.snd_nxt = ack;
+        if let Some((end, sent)) = self.rtt_sample
+            && seq_le(end, ack)
+        {
+            self.update_rto(now.saturating_duration_since(sent));
+            self.rtt_sample = None;
+        }
+        self.retransmit_at = None;
+        self.retries = 0;
+        self.state = TcpState::Established;
+    }
+
+    fn acceptable(&self, segment: &TcpSegment) -> bool {
+        let seq = segment.header.seq;
+        let len = segment.seq_len();
+        let wnd = self.rcv_wnd() as u32;
+        let in_window =
+            |s: u32| seq_le(self.rcv_nxt, s) && seq_lt(s, self.rcv_nxt.wrapping_add(wnd));
+        match (len, wnd) {
+            (0, 0) => seq == self.rcv_nxt,
+            (0, _) => in_window(seq),
+            (_, 0) => false,
+            _ => in_window(seq) || in_window(seq.wrapping_add(len - 1)),
+        }
+    }
+
+    /// 处理确认号和窗口，报文段应被丢弃时返回 false
+    fn process_ack(&mut self, h: &TcpHeader, now: Instant) -> bool {
+        // 确认了还没发出的数据
+        if seq_lt(self.snd_max, h.ack) {
+            self.ack_pending = true;
+            return false;
+        }
+
+        if seq_lt(self.snd_una, h.ack) {
+            let acked = h.ack.wrapping_sub(self.snd_una) as usize;
+            // SYN-RECEIVED 中确认的是 SYN，不占缓冲区
+            let syn = (self.snd_una == self.iss) as usize;
+            let data = (acked - syn).min(self.send_buf.len());
+            self.send_buf.drain(..data);
+            if acked - syn > data && self.fin_queued {
+                self.fin_acked = true;
+            }
+            self.snd_una = h.ack;
+            if seq_lt(self.snd_nxt, h.ack) {
+                self.snd_nxt = h.ack;
+            }
+
+            if let Some((end, sent)) = self.rtt_sample
+                && seq_le(end, h.ack)
+            {
+                self.update_rto(now.saturating_duration_since(sent));
+                self.rtt_sample = None;
+            }
+            self.retries = 0;
+            self.retransmit_at = if self.snd_una == self.snd_max {
+                None
+            } else {
+                Some(now + self.rto)
+            };
+        }
+
+        // 用更新的报文段更新发送窗口 (RFC 9293 3.10.7.4)
+        if seq_lt(self.snd_wl1, h.seq) || (self.snd_wl1 == h.seq && seq_le(self.snd_wl2, h.ack)) {
+            self.snd_wnd = (h.window as u32) << self.snd_wscale;
+            self.snd_wl1 = h.seq;
+            self.snd_wl2 = h.ack;
+        }
+
+        if self.fin_acked {
+            match self.state {
+                TcpState::FinWait1 => self.state = TcpState::FinWait2,
+                TcpState::Closing => self.enter_time_wait(now),
+                TcpState::LastAck => {
+                    self.enter_closed();
+                    return false;
+                }
+                _ => {}
+            }
+        }
+        true
+    }
+
+    fn receive_data(&mut self, seq: u32, data: &[u8]) {
+        // 去掉已经收到过的部分
+        let dup = self.rcv_nxt.wrapping_sub(seq) as i32;
+        let (seq, data) = if dup > 0 {
+            match data.get(dup as usize..) {
+                Some(rest) if !rest.is_empty() => (self.rcv_nxt, rest),
+                _ => return,
+            }
+        } else {
+            (seq, data)
+        };
+
+        // 去掉窗口外的部分
+        let edge = self.rcv_nxt.wrapping_add(self.rcv_wnd() as u32);
+        if seq_le(edge, seq) {
+            return;
+        }
+        let room = edge.wrapping_sub(seq) as usize;
+        let data = &data[..data.len().min(room)];
+        if data.is_empty() {
+            return;
+        }
+
+        if seq != self.rcv_nxt {
+            self.buffer_out_of_order(seq, data);
+            return;
+        }
+
+        self.recv_buf.extend(data);
+        let start = self.rcv_nxt;
+        self.rcv_nxt = self.rcv_nxt.wrapping_add(data.len() as u32);
+
+        // 把补齐的乱序数据接上，被新数据完全覆盖的直接丢弃
+        loop {
+            let first = self
+                .out_of_order_range(start, self.rcv_nxt.wrapping_add(1))
+                .next()
+                .map(|(&s, _)| s);
+            let Some(s) = first else {
+                break;
+            };
+            let d = self.remove_out_of_order(s);
+            let end = s.wrapping_add(d.len() as u32);
+            if seq_lt(self.rcv_nxt, end) {
+                let skip = self.rcv_nxt.wrapping_sub(s) as usize;
+                self.recv_buf.extend(&d[skip..]);
+                self.rcv_nxt = end;
+            }
+        }
+    }
+
+    /// 缓存中起始序号在 [from, to) 内的乱序数据，按序号先后排列；区间可能跨过 2^32 回绕
+    fn out_of_order_range(
+        &self,
+        from: u32,
+        to: u32,
+    ) -> impl DoubleEndedIterator<Item = (&u32, &Vec<u8>)> {
+        let wrapped = to < from;
+        let head = if wrapped {
+            self.out_of_order.range(from..)
+        } else {
+            self.out_of_order.range(from..to)
+        };
+        let tail = wrapped.then(|| self.out_of_order.range(..to));
+        head.chain(tail.into_iter().flatten())
+    }
+
+    fn remove_out_of_order(&mut self, seq: u32) -> Vec<u8> {
+        let data = self.out_of_order.remove(&seq).unwrap_or_default();
+        self.out_of_order_len -= data.len();
+        data
+    }
+
+    /// 缓存窗口内的乱序数据，与已有数据重叠或相邻的合并为一段
+    fn buffer_out_of_order(&mut self, seq: u32, data: &[u8]) {
+        let mut start = seq;
+        let mut end = seq.wrapping_add(data.len() as u32);
+        let mut merged = Vec::new();
+
+        // 起点在前面的一段覆盖或接上了新数据的开头
+        let prev = self
+            .out_of_order_range(self.rcv_nxt, seq.wrapping_add(1))
+            .next_back()
+            .map(|(&s, d)| (s, s.wrapping_add(d.len() as u32)));
+        if let Some((s, prev_end)) = prev {
+            if seq_le(end, prev_end) {
+                return;
+            }
+            if seq_le(seq, prev_end) {
+                merged = self.remove_out_of_order(s);
+                start = s;
+            }
+        }
+        let skip = start.wrapping_add(merged.len() as u32).wrapping_sub(seq) as usize;
+        merged.extend_from_slice(&data[skip..]);
+
+        // 起点落在新数据内或紧接其后的各段
+        let next: Vec<u32> = self
+            .out_of_order_range(seq, end.wrapping_add(1))
+            .map(|(&s, _)| s)
+            .collect();
+        for s in next {
+            let d = self.remove_out_of_order(s);
+            let next_end = s.wrapping_add(d.len() as u32);
+            if seq_lt(end, next_end) {
+                merged.extend_from_slice(&d[end.wrapping_sub(s) as usize..]);
+                end = next_end;
+            }
+        }
+
+        self.out_of_order_len += merged.len();
+        self.out_of_order.insert(start, merged);
+    }
+
+    fn check_fin(&mut self, now: Instant) {
+        if self.fin_received || self.fin_seq != Some(self.rcv_nxt) {
+            return;
+        }
+        self.rcv_nxt = self.rcv_nxt.wrapping_add(1);
+        self.fin_received = true;
+        self.ack_pending = true;
+
+        match self.state {
+            TcpState::SynReceived | TcpState::Established => self.state = TcpState::CloseWait,
+            TcpState::FinWait1 if self.fin_acked => self.enter_time_wait(now),
+            TcpState::FinWait1 => self.state = TcpState::Closing,
+            TcpState::FinWait2 => self.enter_time_wait(now),
+            _ => {}
+        }
+    }
+
+    fn segment(&mut self, flags: u8, seq: u32, payload: Vec<u8>) -> TcpSegment {
+        let ack = if flags & TCP_FLAG_ACK != 0 {
+            self.rcv_nxt
+        } else {
+            0
+        };
+        let window = self.rcv_wnd();
+        self.last_adv_wnd = window;
+        let mut header = TcpHeader::new(
+            self.local_port,
+            self.remote_port,
+            seq,
+            ack,
+            flags,
+            window as u16,
+        );
+        if flags & TCP_FLAG_SYN != 0 {
+            // 只接受对端的窗口扩大，自己的窗口不超过 65535，扩大因子为 0
+            let mut options = vec![TcpOption::MaxSegmentSize(self.our_mss)];
+            if self.state == TcpState::SynSent || self.peer_window_scale {
+                options.push(TcpOption::NoOperation);
+                options.push(TcpOption::WindowScale(0));
+            }
+            // 选项长度远小于上限
+            let _ = header.set_options(options);
+        }
+        if flags & TCP_FLAG_ACK != 0 {
+            self.ack_pending = false;
+        }
+        TcpSegment::new(header, payload, self.local_ip, self.remote_ip)
+    }
+
+    fn arm_retransmit(&mut self, now: Instant) {
+        if self.retransmit_at.is_none() {
+            self.retransmit_at = Some(now + self.rto);
+        }
+    }
+
+    fn on_retransmit_timeout(&mut self, now: Instant) {
+        let synchronized = !matches!(self.state, TcpState::SynSent | TcpState::SynReceived);
+        if synchronized && self.snd_wnd == 0 {
+            // 持续定时器：对端窗口为 0 时回退后发 1 字节探测，不计入重传次数
+            self.probe = true;
+        } else if self.snd_una == self.snd_max {
+            self.retransmit_at = None;
+            return;
+        } else {
+            self.retries += 1;
+            if self.retries > MAX_RETRIES {
+                self.error = Some(TcpError::TimedOut);
+                self.enter_closed();
+                return;
+            }
+        }
+
+        // 回退到 snd_una 重新发送 (go-back-N)，RTO 指数退避
+        self.rto = (self.rto * 2).min(MAX_RTO);
+        self.snd_nxt = self.snd_una;
+        self.rtt_sample = None;
+        self.retransmit_at = Some(now + self.rto);
+    }
+
+    /// 取出现在应该发出的报文段
+    pub fn poll_transmit(&mut self, now: Instant) -> Vec<TcpSegment> {
+        let mut out = Vec::new();
+
+        if self.rst_pending {
+            self.rst_pending = false;
+            out.push(self.segment(TCP_FLAG_RST | TCP_FLAG_ACK, self.snd_nxt, Vec::new()));
+            return out;
+        }
+
+        match self.state {
+            TcpState::Closed | TcpState::Listen => return out,
+            TcpState::TimeWait if self.time_wait_until.is_some_and(|t| now >= t) => {
+                self.enter_closed();
+                return out;
+            }
+            _ => {}
+        }
+
+        if self.retransmit_at.is_some_and(|t| now >= t) {
+            self.on_retransmit_timeout(now);
+            if self.state == TcpState::Closed {
+                return out;
+            }
+        }
+
+        match self.state {
+            TcpState::SynSent if self.snd_nxt == self.iss => {
+                let syn = self.segment(TCP_FLAG_SYN, self.iss, Vec::new());
+                out.push(syn);
+                self.sent_syn(now);
+            }
+            TcpState::SynSent => {}
+            TcpState::SynReceived => {
+                // 其余情况只回 ACK：同时打开时对端的 SYN-ACK 不在窗口内，
+                // 回 SYN-ACK 会让两端互相重发下去
+                if self.snd_nxt == self.iss {
+                    let syn_ack = self.segment(TCP_FLAG_SYN | TCP_FLAG_ACK, self.iss, Vec::new());
+                    out.push(syn_ack);
+                    self.sent_syn(now);
+                }
+            }
+            _ => self.transmit_data(now, &mut out),
+        }
+
+        if self.ack_pending {
+            let ack = self.segment(TCP_FLAG_ACK, self.snd_nxt, Vec::new());
+            out.push(ack);
+        }
+        out
+    }
+
+    fn sent_syn(&mut self, now: Instant) {
+        let end = self.iss.wrapping_add(1);
+        if self.snd_max == self.iss {
+            self.rtt_sample = Some((end, now));
+        }
+        self.snd_nxt = end;
+        self.snd_max = end;
+        self.arm_retransmit(now);
+    }
+
+    fn transmit_data(&mut self, now: Instant, out: &mut Vec<TcpSegment>) {
+        loop {
+            let in_flight = self.snd_nxt.wrapping_sub(self.snd_una) as usize;
+            let sent = in_flight.min(self.send_buf.len());
+            let unsent = self.send_buf.len() - sent;
+            if unsent == 0 {
+                break;
+            }
+
+            let mut usable = (self.snd_wnd as usize).saturating_sub(in_flight);
+            if usable == 0 {
+                if !self.probe {
+                    // 对端窗口为 0，用重传定时器充当持续定时器
+                    self.arm_retransmit(now);
+                    break;
+                }
+                usable = 1;
+            }
+            self.probe = false;
+
+            let len = unsent.min(usable).min(self.snd_mss as usize);
+            let data: Vec<u8> = self.send_buf.range(sent..sent + len).copied().collect();
+            let flags = if len == unsent {
+                TCP_FLAG_ACK | TCP_FLAG_PSH
+            } else {
+                TCP_FLAG_ACK
+            };
+            let seq = self.snd_nxt;
+            let segment = self.segment(flags, seq, data);
+            out.push(segment);
+
+            self.snd_nxt = seq.wrapping_add(len as u32);
+            // 只对新数据计时，重传的不计 (Karn 算法)
+            if seq == self.snd_max && self.rtt_sample.is_none() {
+                self.rtt_sample = Some((self.snd_nxt, now));
+            }
+            if seq_lt(self.snd_max, self.snd_nxt) {
+                self.snd_max = self.snd_nxt;
+            }
+            self.arm_retransmit(now);
+        }
+
+        if self.fin_queued && !self.fin_acked {
+            let data_end = self.snd_una.wrapping_add(self.send_buf.len() as u32);
+            if self.snd_nxt == data_end {
+                let fin = self.segment(TCP_FLAG_FIN | TCP_FLAG_ACK, data_end, Vec::new());
+                out.push(fin);
+                self.snd_nxt = data_end.wrapping_add(1);
+                if seq_lt(self.snd_max, self.snd_nxt) {
+                    self.snd_max = self.snd_nxt;
+                }
+                self.arm_retransmit(now);
+            }
+        }
+    }
+}
+
+/// 本机通告的 MSS：链路 MTU 减去 IPv4 和 TCP 的固定首部
+pub fn local_mss(stack: &NetworkStack) -> u16 {
+    stack.mtu().saturating_sub(40).min(u16::MAX as usize) as u16
+}
+
+/// TCP 目前只支持 IPv4
+fn parse_ipv4_addr(addr: &str) -> anyhow::Result<(Ipv4Addr, u16)> {
+    match parse_addr(addr)? {
+        (IpAddr::V4(ip), port) => Ok((ip, port)),
+        (IpAddr::V6(_), _) => anyhow::bail!("TCP over IPv6 is not supported"),
+    }
+}
+
+pub struct TcpListener {
+    handle: SocketHandle,
+    stack: Arc<NetworkStack>,
+}
+
+impl TcpListener {
+    pub fn bind(stack: Arc<NetworkStack>, addr: &str) -> anyhow::Result<Self> {
+        let (ip, port) = parse_ipv4_addr(addr)?;
+        let handle = SocketHandle::new(
+            &SocketType::Tcp,
+            ip.into(),
+            port,
+            Ipv4Addr::unspecified().into(),
+            0,
+        );
+
+        let mut sockets = stack.sockets.lock().unwrap();
+        if sockets.get(handle).is_some() {
+            anyhow::bail!("Address already in use: {}", addr);
+        }
+        sockets.add(
+            handle,
+            Socket::Tcp(Box::new(TcpSocketState::listen(ip, port, DEFAULT_BACKLOG))),
+        );
+        drop(sockets);
+
+        Ok(Self { handle, stack })
+    }
+
+    /// 取出一个已完成握手的连接，没有时立即返回错误 (非阻塞)
+    pub fn accept(&self) -> anyhow::Result<(TcpStream, String)> {
+        let mut sockets = self.stack.sockets.lock().unwrap();
+        loop {
+            let Some(Socket::Tcp(listener)) = sockets.get_mut(self.handle) else {
+                anyhow::bail!("Socket state not found");
+            };
+            let Some(handle) = listener.accept_queue.pop_front() else {
+                anyhow::bail!("No pending connection");
+            };
+            // 排队期间可能已被对端重置并回收
+            if let Some(Socket::Tcp(conn)) = sockets.get_mut(handle) {
+                conn.set_detached(false);
+                // 已 accept 的连接不再占用监听 socket 的 backlog
+                conn.parent = None;
+                let peer = format!("{}:{}", handle.remote_addr, handle.remote_port);
+                let stream = TcpStream {
+                    handle,
+                    stack: self.stack.clone(),
+                };
+                return Ok((stream, peer));
+            }
+        }
+    }
+
+    pub fn local_addr(&self) -> String {
+        format!("{}:{}", self.handle.local_addr, self.handle.local_port)
+    }
+}
+
+impl Drop for TcpListener {
+    fn drop(&mut self) {
+        let mut sockets = self.stack.sockets.lock().unwrap();
+        let Some(Socket::Tcp(listener)) = sockets.remove(self.handle) else {
+            return;
+        };
+        // 还没被 accept 的连接直接重置
+        for handle in listener.accept_queue {
+            if let Some(Socket::Tcp(conn)) = sockets.get_mut(handle) {
+                conn.abort();
+            }
+        }
+    }
+}
+
+pub struct TcpStream {
+    handle: SocketHandle,
+    stack: Arc<NetworkStack>,
+}
+
+impl TcpStream {
+    /// 主动连接 addr，阻塞直到握手完成或失败，需要有线程在运行 `event_loop::run`
+    pub fn connect(stack: Arc<NetworkStack>, addr: &str) -> anyhow::Result<Self> {
+        let (remote_ip, remote_port) = parse_ipv4_addr(addr)?;
+        let local_ip = stack.config().ip;
+        let mss = local_mss(&stack);
+
+        let handle = {
+            let mut sockets = stack.sockets.lock().unwrap();
+            // 从动态端口范围中随机位置开始找一个空闲端口
+            let start = initial_sequence(0, remote_ip, remote_port) as usize;
+            let handle = (0..16384)
+                .map(|i| 49152 + ((start + i) % 16384) as u16)
+                .map(|port| {
+                    SocketHandle::new(
+                        &SocketType::Tcp,
+                        local_ip.into(),
+                        port,
+                        remote_ip.into(),
+                        remote_port,
+                    )
+                })
+                .find(|handle| {
+                    sockets.get(*handle).is_none()
+                        && sockets
+                            .lookup_handle(
+                                &SocketType::Tcp,
+                                remote_ip.into(),
+                                remote_port,
+                                local_ip.into(),
+                                handle.local_port,
+                            )
+                            .is_none()
+                })
+                .ok_or_else(|| anyhow::anyhow!("No free local port"))?;
+            let socket =
+                TcpSocketState::connect(local_ip, handle.local_port, remote_ip, remote_port, mss);
+            sockets.add(handle, Socket::Tcp(Box::new(socket)));
+            handle
+        };
+        let stream = Self { handle, stack };
+
+        loop {
+            match stream.state() {
+                TcpState::SynSent | TcpState::SynReceived => {
+                    thread::sleep(Duration::from_millis(1));
+                }
+                TcpState::Closed => {
+                    let error = stream.with_state(|s| s.error()).flatten();
+                    return Err(error.unwrap_or(TcpError::Reset).into());
+                }
+                _ => return Ok(stream),
+            }
+        }
+    }
+
+    fn with_state<T>(&self, f: impl FnOnce(&mut TcpSocketState) -> T) -> Option<T> {
+        let mut sockets = self.stack.sockets.lock().unwrap();
+        match sockets.get_mut(self.handle) {
+            Some(Socket::Tcp(state)) => Some(f(state)),
+            _ => None,
+        }
+    }
+
+    pub fn state(&self) -> TcpState {
+        self.with_state(|s| s.state()).unwrap_or(TcpState::Closed)
+    }
+
+    /// 写入发送缓冲区，返回写入的字节数，缓冲区满时可能为 0
+    pub fn send(&self, data: &[u8]) -> anyhow::Result<usize> {
+        self.with_state(|s| {
+            if let Some(error) = s.error() {
+                return Err(error.into());
+            }
+            if s.fin_queued || s.state() == TcpState::Closed {
+                anyhow::bail!("Connection is closed for sending");
+            }
+            Ok(s.send(data))
+        })
+        .unwrap_or_else(|| anyhow::bail!("Socket state not found"))
+    }
+
+    /// 非阻塞读取，对端关闭且数据读完后返回空 Vec
+    pub fn recv(&self) -> anyhow::Result<Vec<u8>> {
+        self.with_state(|s| {
+            if let Some(error) = s.error() {
+                return Err(error.into());
+            }
+            if s.is_eof() {
+                return Ok(Vec::new());
+            }
+            let data = s.recv();
+            if data.is_empty() {
+                anyhow::bail!("No data available");
+            }
+            Ok(data)
+        })
+        .unwrap_or_else(|| anyhow::bail!("Socket state not found"))
+    }
+
+    /// 发送 FIN，之后仍可读取对端发来的数据
+    pub fn close(&self) {
+        self.with_state(|s| s.close());
+    }
+
+    pub fn local_addr(&self) -> String {
+        format!("{}:{}", self.handle.local_addr, self.handle.local_port)
+    }
+
+    pub fn peer_addr(&self) -> String {
+        format!("{}:{}", self.handle.remote_addr, self.handle.remote_port)
+    }
+}
+
+impl Drop for TcpStream {
+    fn drop(&mut self) {
+        let mut sockets = self.stack.sockets.lock().unwrap();
+        let Some(Socket::Tcp(state)) = sockets.get_mut(self.handle) else {
+            return;
+        };
+        // 交给协议栈完成剩下的挥手，关闭后由 poll_and_send 回收
+        state.close();
+        state.set_detached(true);
+        if state.is_released() {
+            sockets.remove(self.handle);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 乱序缓存的字节数与各段之和一致，各段按序号排列且互不重叠、互不相邻
+    fn assert_out_of_order_consistent(socket: &TcpSocketState) {
+        let segments: Vec<(u32, usize)> = socket
+            .out_of_order_range(socket.rcv_nxt, socket.rcv_nxt.wrapping_sub(1))
+            .map(|(&s, d)| (s, d.len()))
+            .collect();
+        assert_eq!(segments.len(), socket.out_of_order.len());
+        assert_eq!(
+            segments.iter().map(|(_, len)| len).sum::<usize>(),
+            socket.out_of_order_len
+        );
+        assert!(socket.recv_buf.len() + socket.out_of_order_len <= socket.recv_capacity);
+        for pair in segments.windows(2) {
+            let (s, len) = pair[0];
+            assert!(seq_lt(s.wrapping_add(len as u32), pair[1].0));
+        }
+    }
+
+    #[test]
+    fn out_of_order_data_is_merged_and_bounded_by_the_window() {
+        let mut socket = TcpSocketState::new(
+            Ipv4Addr::new(10, 0, 0, 1),
+            80,
+            Ipv4Addr::new(10, 0, 0, 2),
+            40000,
+        );
+        socket.state = TcpState::Established;
+        // 窗口跨过序号回绕点
+        socket.rcv_nxt = u32::MAX - 1000;
+        let base = socket.rcv_nxt;
+        let byte = |offset: u32| (offset % 251) as u8;
+        let segment = |offset: u32, len: u32| -> (u32, Vec<u8>) {
+            (
+                base.wrapping_add(offset),
+                (offset..offset + len).map(byte).collect(),
+            )
+        };
+
+        // 逆序到达、彼此错开的重叠报文段，接着是另一组步长的和完全重复的
+        for i in (0..4000).rev() {
+            let (seq, data) = segment(1 + i * 15, 1460);
+            socket.receive_data(seq, &data);
+            assert_out_of_order_consistent(&socket);
+        }
+        for i in 0..4000 {
+            let (seq, data) = segment(2 + i * 13, 100);
+            socket.receive_data(seq, &data);
+            socket.receive_data(seq, &data);
+            assert_out_of_order_consistent(&socket);
+        }
+        assert_eq!(socket.out_of_order.len(), 1);
+        assert_eq!(socket.out_of_order_len, 61445);
+        // 缓存的乱序数据从通告窗口中扣除，超出窗口的部分被丢弃
+        assert_eq!(socket.rcv_wnd(), DEFAULT_BUFFER_SIZE - 61445);
+        let (seq, data) = segment(63000, 1460);
+        socket.receive_data(seq, &data);
+        assert_eq!(socket.out_of_order_len, 61445);
+        assert_eq!(socket.rcv_nxt, base);
+
+        // 补上开头的空洞后全部按序交付
+        let (seq, data) = segment(0, 1);
+        socket.receive_data(seq, &data);
+        assert!(socket.out_of_order.is_empty());
+        assert_eq!(socket.out_of_order_len, 0);
+        assert_eq!(socket.rcv_nxt, base.wrapping_add(61446));
+        assert_eq!(socket.recv(), (0..61446).map(byte).collect::<Vec<_>>());
+    }
+}
@@ -12,29 +12,42 @@
 // (at your option) any later version.
 
 use crate::{
+    handlers::ipv4,
     stack::NetworkStack,
     transport::{Socket, SocketHandle},
 };
 use anyhow;
+use protocol::ip::IpAddr;
 use protocol::ipv4::Ipv4Addr;
-use std::{collections::VecDeque, sync::Arc};
+use protocol::ipv6::Ipv6Addr;
+use std::{
+    collections::VecDeque,
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
 
 #[derive(Debug)]
 pub struct UdpSocketState {
     /// Received packets queue: (source_ip, source_port, payload)
     /// UDP preserves message boundaries, so we store packets, not a byte stream.
-    rx_queue: VecDeque<(Ipv4Addr, u16, Vec<u8>)>,
+    rx_queue: VecDeque<(IpAddr, u16, Vec<u8>)>,
 
     /// Maximum number of packets to buffer in the receive queue
     rx_capacity: usize,
 
-    /// To send packets queue: (Ipv4Addr, u16, Vec<u8>)
+    /// To send packets queue: (IpAddr, u16, Vec<u8>)
     /// UDP send messages unordered, so we store packets,
     /// waiting for Stack scheduling to send.
-    tx_queue: VecDeque<(Ipv4Addr, u16, Vec<u8>)>,
+    tx_queue: VecDeque<(IpAddr, u16, Vec<u8>)>,
 
     /// Maximun number of packets to buffer in the send queue
     tx_capacity: usize,
+
+    /// 通过该 socket 加入的 IPv4 多播组，关闭时一并离开
+    multicast_groups: Vec<Ipv4Addr>,
+
+    /// 是否允许发往广播地址，对应 SO_BROADCAST
+    broadcast: bool,
 }
 
 impl UdpSocketState {
@@ -45,6 +58,8 @@ impl UdpSocketState {
             rx_capacity: 32, // Default buffer size
             tx_queue: VecDeque::new(),
             tx_capacity: 32, // Default buffer size
+            multicast_groups: Vec::new(),
+            broadcast: false,
         }
     }
 
@@ -60,7 +75,7 @@ impl UdpSocketState {
 
     /// Push a received packet into the socket's buffer
     /// This is called by the network stack when a packet matches this socket.
-    pub fn rx_enqueue(&mut self, src_ip: Ipv4Addr, src_port: u16, payload: &[u8]) {
+    pub fn rx_enqueue(&mut self, src_ip: IpAddr, src_port: u16, payload: &[u8]) {
         if self.rx_queue.len() < self.rx_capacity {
             self.rx_queue
                 .push_back((src_ip, src_port, payload.to_vec()));
@@ -73,7 +88,7 @@ impl UdpSocketState {
 
     /// Pop a packet from the receive queue
     /// Returns (source_ip, source_port, payload)
-    pub fn recv(&mut self) -> Option<(Ipv4Addr, u16, Vec<u8>)> {
+    pub fn recv(&mut self) -> Option<(IpAddr, u16, Vec<u8>)> {
         self.rx_queue.pop_front()
     }
 
@@ -82,17 +97,25 @@ impl UdpSocketState {
         !self.rx_queue.is_empty()
     }
 
-    pub fn send_to(&mut self, payload: &[u8], dst_ip: Ipv4Addr, dst_port: u16) {
+    pub fn send_to(&mut self, payload: &[u8], dst_ip: IpAddr, dst_port: u16) {
         if self.tx_queue.len() < self.tx_capacity {
             self.tx_queue
                 .push_back((dst_ip, dst_port, payload.to_vec()));
         }
     }
 
-    pub fn poll_transmit(&mut self) -> Option<(Ipv4Addr, u16, Vec<u8>)> {
+    pub fn poll_transmit(&mut self) -> Option<(IpAddr, u16, Vec<u8>)> {
         self.tx_queue.pop_front()
     }
 
+    pub fn can_transmit(&self) -> bool {
+        !self.tx_queue.is_empty()
+    }
+
+    pub fn multicast_groups(&self) -> &[Ipv4Addr] {
+        &self.multicast_groups
+    }
+
     // pub fn send(
     //     &self,
     //     dst_mac: MacAddr,
@@ -131,30 +154,63 @@ pub struct UdpSocket {
 }
 
 impl UdpSocket {
+    /// 绑定本地地址，端口为 0 时从动态端口范围中分配一个空闲端口
+    ///
+    /// IPv6 地址写成 `[addr]:port`，绑定到 `[::]` 的 socket 只收发 IPv6
     pub fn bind(stack: Arc<NetworkStack>, addr: &str) -> anyhow::Result<Self> {
         let (ip, port) = parse_addr(addr)?;
+        let handle_for = |port| {
+            SocketHandle::new(
+                &super::SocketType::Udp,
+                ip,
+                port,
+                ip.unspecified_of_same_family(),
+                0,
+            )
+        };
 
-        let handle = SocketHandle::new(
-            &super::SocketType::Udp,
-            ip,
-            port,
-            Ipv4Addr::unspecified(),
-            0,
-        );
+        let mut sockets = stack.sockets.lock().unwrap();
+        let handle = if port == 0 {
+            // 从随机位置开始找，避免连续的查询总是用同一个端口
+            let start = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .subsec_nanos() as usize;
+            (0..16384)
+                .map(|i| handle_for(49152 + ((start + i) % 16384) as u16))
+                .find(|handle| sockets.get(*handle).is_none())
+                .ok_or_else(|| anyhow::anyhow!("No free local port"))?
+        } else {
+            let handle = handle_for(port);
+            if sockets.get(handle).is_some() {
+                anyhow::bail!("Address {} already in use", addr);
+            }
+            handle
+        };
 
         let socket_state = UdpSocketState::new();
-
-        stack
-            .sockets
-            .lock()
-            .unwrap()
-            .add(handle, Socket::Udp(socket_state));
+        sockets.add(handle, Socket::Udp(socket_state));
+        drop(sockets);
 
         Ok(Self { handle, stack })
     }
 
+    pub fn local_addr(&self) -> String {
+        format_addr(self.handle.local_addr, self.handle.local_port)
+    }
+
+    pub fn local_port(&self) -> u16 {
+        self.handle.local_port
+    }
+
     pub fn send_to(&self, payload: &[u8], dst_addr: &str) -> anyhow::Result<()> {
         let (dst_ip, dst_port) = parse_addr(dst_addr)?;
+        if dst_ip.is_ipv4() != self.handle.local_addr.is_ipv4() {
+            anyhow::bail!("Address family of {} does not match the socket", dst_addr);
+        }
+        // 按路由的出接口判断，发往另一个已关联网段的定向广播也需要广播权限
+        let is_broadcast =
+            matches!(dst_ip, IpAddr::V4(ip) if ipv4::is_broadcast_destination(&self.stack, ip));
 
         // 我们需要通过 handle 找到自己的 SocketState
         // 注意：lookup 是用来查找"匹配数据包的 Socket"，而这里我们需要"获取自己的 Socket"
@@ -162,6 +218,10 @@ impl UdpSocket {
 
         let mut sockets = self.stack.sockets.lock().unwrap();
         if let Some(Socket::Udp(udp_socket_state)) = sockets.get_mut(self.handle) {
+            // 与 Linux 一样，没有开启广播权限时拒绝发往广播地址
+            if is_broadcast && !udp_socket_state.broadcast {
+                anyhow::bail!("Permission denied: {} is a broadcast address", dst_addr);
+            }
             udp_socket_state.send_to(payload, dst_ip, dst_port);
             Ok(())
         } else {
@@ -174,7 +234,7 @@ impl UdpSocket {
         let mut sockets = self.stack.sockets.lock().unwrap();
         if let Some(Socket::Udp(udp_socket_state)) = sockets.get_mut(self.handle) {
             if let Some((src_ip, src_port, payload)) = udp_socket_state.recv() {
-                let src_addr = format!("{}:{}", src_ip, src_port);
+                let src_addr = format_addr(src_ip, src_port);
                 Ok((payload, src_addr))
             } else {
                 // 暂时返回空数据表示没有收到，或者你可以选择阻塞/报错
@@ -186,20 +246,108 @@ impl UdpSocket {
             anyhow::bail!("Socket state not found");
         }
     }
+
+    /// 允许或禁止发往受限广播和本网段定向广播地址，默认禁止
+    pub fn set_broadcast(&self, broadcast: bool) -> anyhow::Result<()> {
+        let mut sockets = self.stack.sockets.lock().unwrap();
+        let Some(Socket::Udp(state)) = sockets.get_mut(self.handle) else {
+            anyhow::bail!("Socket state not found");
+        };
+        state.broadcast = broadcast;
+        Ok(())
+    }
+
+    pub fn broadcast(&self) -> anyhow::Result<bool> {
+        let sockets = self.stack.sockets.lock().unwrap();
+        let Some(Socket::Udp(state)) = sockets.get(self.handle) else {
+            anyhow::bail!("Socket state not found");
+        };
+        Ok(state.broadcast)
+    }
+
+    /// 加入 IPv4 多播组，之后发往该组、目的端口为本地端口的数据报交给这个 socket
+    ///
+    /// 同一个组不能重复加入，socket 关闭时自动离开
+    pub fn join_multicast(&self, group: Ipv4Addr) -> anyhow::Result<()> {
+        if !self.handle.local_addr.is_ipv4() {
+            anyhow::bail!("Cannot join IPv4 group {} on an IPv6 socket", group);
+        }
+        {
+            let mut sockets = self.stack.sockets.lock().unwrap();
+            let Some(Socket::Udp(state)) = sockets.get_mut(self.handle) else {
+                anyhow::bail!("Socket state not found");
+            };
+            if state.multicast_groups.contains(&group) {
+                anyhow::bail!("Already a member of {}", group);
+            }
+            if !group.is_multicast() {
+                anyhow::bail!("{} is not a multicast address", group);
+            }
+            state.multicast_groups.push(group);
+        }
+        self.stack.join_multicast(group)
+    }
+
+    pub fn leave_multicast(&self, group: Ipv4Addr) -> anyhow::Result<()> {
+        {
+            let mut sockets = self.stack.sockets.lock().unwrap();
+            let Some(Socket::Udp(state)) = sockets.get_mut(self.handle) else {
+                anyhow::bail!("Socket state not found");
+            };
+            let Some(pos) = state.multicast_groups.iter().position(|g| *g == group) else {
+                anyhow::bail!("Not a member of {}", group);
+            };
+            state.multicast_groups.remove(pos);
+        }
+        self.stack.leave_multicast(group);
+        Ok(())
+    }
 }
 
-fn parse_addr(addr: &str) -> anyhow::Result<(Ipv4Addr, u16)> {
-    let parts: Vec<&str> = addr.split(':').collect();
-    if parts.len() != 2 {
-        anyhow::bail!("Invalid address format, expected IP:PORT");
+impl Drop for UdpSocket {
+    fn drop(&mut self) {
+        let socket = self.stack.sockets.lock().unwrap().remove(self.handle);
+        if let Some(Socket::Udp(state)) = socket {
+            for group in state.multicast_groups {
+                self.stack.leave_multicast(group);
+            }
+        }
     }
+}
 
-    let ip = parts[0]
-        .parse::<Ipv4Addr>()
-        .map_err(|_| anyhow::anyhow!("Invalid IP address"))?;
-    let port = parts[1]
+/// 解析 `IP:PORT`，IPv6 地址需要放在方括号里：`[fe80::1]:53`
+pub(crate) fn parse_addr(addr: &str) -> anyhow::Result<(IpAddr, u16)> {
+    let (ip, port) = match addr.strip_prefix('[') {
+        Some(rest) => {
+            let (ip, port) = rest
+                .split_once("]:")
+                .ok_or_else(|| anyhow::anyhow!("Invalid address format, expected [IPv6]:PORT"))?;
+            let ip = ip
+                .parse::<Ipv6Addr>()
+                .map_err(|_| anyhow::anyhow!("Invalid IPv6 address"))?;
+            (IpAddr::V6(ip), port)
+        }
+        None => {
+            let (ip, port) = addr
+                .split_once(':')
+                .ok_or_else(|| anyhow::anyhow!("Invalid address format, expected IP:PORT"))?;
+            let ip = ip
+                .parse::<Ipv4Addr>()
+                .map_err(|_| anyhow::anyhow!("Invalid IP address"))?;
+            (IpAddr::V4(ip), port)
+        }
+    };
+    let port = port
         .parse::<u16>()
         .map_err(|_| anyhow::anyhow!("Invalid port number"))?;
 
     Ok((ip, port))
 }
+
+/// parse_addr 的逆操作
+pub(crate) fn format_addr(ip: IpAddr, port: u16) -> String {
+    match ip {
+        IpAddr::V4(ip) => format!("{}:{}", ip, port),
+        IpAddr::V6(ip) => format!("[{}]:{}", ip, port),
+    }
+}
@@ -182,11 +182,11 @@ impl ArpTable {
 
     /// 查询 IP 对应的 MAC 地址
     /// 返回 None 如果不存在或已过期
-    pub fn lookup(&self, ip: Ipv4Addr) -> Option<MacAddr> {
+    pub fn lookup(&self, ip: Ipv4Addr, now: Instant) -> Option<MacAddr> {
         let entry = self.entries.get(&ip)?;
 
         // 2. 检查是否过期
-        if entry.is_static || entry.timestamp.elapsed() < self.ttl {
+        if entry.is_static || now.duration_since(entry.timestamp) < self.ttl {
             return Some(entry.mac);
         }
 
@@ -194,10 +194,10 @@ impl ArpTable {
     }
 
     /// 插入或更新 ARP 项(动态)
-    pub fn insert(&mut self, ip: Ipv4Addr, mac: MacAddr) {
+    pub fn insert(&mut self, ip: Ipv4Addr, mac: MacAddr, now: Instant) {
         let ae = ArpEntry {
             mac,
-            timestamp: Instant::now(),
+            timestamp: now,
             is_static: false,
         };
 
@@ -205,11 +205,11 @@ impl ArpTable {
     }
 
     /// 插入静态 ARP 项(如网关)
-    pub fn insert_static(&mut self, ip: Ipv4Addr, mac: MacAddr) {
+    pub fn insert_static(&mut self, ip: Ipv4Addr, mac: MacAddr, now: Instant) {
         // 创建
         let ae = ArpEntry {
             mac,
-            timestamp: Instant::now(),
+            timestamp: now,
             is_static: true,
         };
 
@@ -217,16 +217,16 @@ impl ArpTable {
     }
 
     /// 清理过期项(后台定期调用)
-    pub fn evict_expired(&mut self) {
+    pub fn evict_expired(&mut self, now: Instant) {
         self.entries
-            .retain(|_, ae| ae.is_static || ae.timestamp.elapsed() < self.ttl);
+            .retain(|_, ae| ae.is_static || now.duration_since(ae.timestamp) < self.ttl);
     }
 
     /// 获取所有有效项(用于调试/日志)
-    pub fn entries(&self) -> Vec<(Ipv4Addr, MacAddr)> {
+    pub fn entries(&self, now: Instant) -> Vec<(Ipv4Addr, MacAddr)> {
         self.entries
             .iter()
-            .filter(|(_, ae)| ae.is_static || ae.timestamp.elapsed() < self.ttl)
+            .filter(|(_, ae)| ae.is_static || now.duration_since(ae.timestamp) < self.ttl)
             .map(|(ip, ae)| (*ip, ae.mac))
             .collect()
     }
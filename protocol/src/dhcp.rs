@@ -0,0 +1,664 @@
+// Copyright (C) 2025 rrrrrzy
+// SPDX-License-Identifier: GPL-3.0-or-later
+//
+// --------------------------------------------------
+// 致敬所有在深夜调试代码的灵魂。
+// 即便 Bug 如山，我亦往矣。
+// --------------------------------------------------
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+//! DHCPv4 报文编解码 (RFC 2131 / RFC 2132)
+
+use std::fmt;
+
+use crate::{error::DhcpParseError, ipv4::Ipv4Addr, mac::MacAddr};
+
+pub const DHCP_SERVER_PORT: u16 = 67;
+pub const DHCP_CLIENT_PORT: u16 = 68;
+
+// op 字段
+pub const BOOTREQUEST: u8 = 1;
+pub const BOOTREPLY: u8 = 2;
+
+/// flags 字段的 B 位：请求服务器以广播方式回复
+pub const DHCP_FLAG_BROADCAST: u16 = 0x8000;
+
+pub const DHCP_MAGIC_COOKIE: [u8; 4] = [99, 130, 83, 99];
+
+/// BOOTP 固定部分 236 字节加 4 字节 magic cookie
+const DHCP_FIXED_LEN: usize = 240;
+
+/// BOOTP 中继要求报文至少 300 字节 (RFC 1542)，不足时用 Pad 补齐
+pub const DHCP_MIN_LEN: usize = 300;
+
+// 选项代码
+pub const DHCP_OPT_PAD: u8 = 0;
+pub const DHCP_OPT_SUBNET_MASK: u8 = 1;
+pub const DHCP_OPT_ROUTER: u8 = 3;
+pub const DHCP_OPT_DNS_SERVER: u8 = 6;
+pub const DHCP_OPT_HOST_NAME: u8 = 12;
+pub const DHCP_OPT_DOMAIN_NAME: u8 = 15;
+pub const DHCP_OPT_BROADCAST_ADDRESS: u8 = 28;
+pub const DHCP_OPT_REQUESTED_IP: u8 = 50;
+pub const DHCP_OPT_LEASE_TIME: u8 = 51;
+pub const DHCP_OPT_MESSAGE_TYPE: u8 = 53;
+pub const DHCP_OPT_SERVER_ID: u8 = 54;
+pub const DHCP_OPT_PARAMETER_REQUEST_LIST: u8 = 55;
+pub const DHCP_OPT_MESSAGE: u8 = 56;
+pub const DHCP_OPT_MAX_MESSAGE_SIZE: u8 = 57;
+pub const DHCP_OPT_RENEWAL_TIME: u8 = 58;
+pub const DHCP_OPT_REBINDING_TIME: u8 = 59;
+pub const DHCP_OPT_CLIENT_ID: u8 = 61;
+pub const DHCP_OPT_END: u8 = 255;
+
+/// 租期选项取该值表示永久租约
+pub const DHCP_INFINITE_LEASE: u32 = u32::MAX;
+
+/// DHCP Message Type 选项 (option 53) 的取值
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DhcpMessageType {
+    Discover,
+    Offer,
+    Request,
+    Decline,
+    Ack,
+    Nak,
+    Release,
+    Inform,
+}
+
+impl DhcpMessageType {
+    pub fn from_u8(value: u8) -> Option<Self> {
+        let message_type = match value {
+            1 => Self::Discover,
+            2 => Self::Offer,
+            3 => Self::Request,
+            4 => Self::Decline,
+            5 => Self::Ack,
+            6 => Self::Nak,
+            7 => Self::Release,
+            8 => Self::Inform,
+            _ => return None,
+        };
+        Some(message_type)
+    }
+
+    pub fn to_u8(self) -> u8 {
+        match self {
+            Self::Discover => 1,
+            Self::Offer => 2,
+            Self::Request => 3,
+            Self::Decline => 4,
+            Self::Ack => 5,
+            Self::Nak => 6,
+            Self::Release => 7,
+            Self::Inform => 8,
+        }
+    }
+}
+
+impl fmt::Display for DhcpMessageType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Discover => "DHCPDISCOVER",
+            Self::Offer => "DHCPOFFER",
+            Self::Request => "DHCPREQUEST",
+            Self::Decline => "DHCPDECLINE",
+            Self::Ack => "DHCPACK",
+            Self::Nak => "DHCPNAK",
+            Self::Release => "DHCPRELEASE",
+            Self::Inform => "DHCPINFORM",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// DHCP 选项，未识别的选项按原始字节保留
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DhcpOption {
+    Pad,
+    End,
+    SubnetMask(Ipv4Addr),
+    Router(Vec<Ipv4Addr>),
+    DnsServers(Vec<Ipv4Addr>),
+    HostName(String),
+    DomainName(String),
+    BroadcastAddress(Ipv4Addr),
+    RequestedIp(Ipv4Addr),
+    /// 租期，单位秒
+    LeaseTime(u32),
+    MessageType(DhcpMessageType),
+    ServerId(Ipv4Addr),
+    ParameterRequestList(Vec<u8>),
+    Message(String),
+    MaxMessageSize(u16),
+    /// T1，单位秒
+    RenewalTime(u32),
+    /// T2，单位秒
+    RebindingTime(u32),
+    ClientId(Vec<u8>),
+    Unknown {
+        code: u8,
+        data: Vec<u8>,
+    },
+}
+
+impl DhcpOption {
+    pub fn code(&self) -> u8 {
+        match self {
+            Self::Pad => DHCP_OPT_PAD,
+            Self::End => DHCP_OPT_END,
+            Self::SubnetMask(_) => DHCP_OPT_SUBNET_MASK,
+            Self::Router(_) => DHCP_OPT_ROUTER,
+            Self::DnsServers(_) => DHCP_OPT_DNS_SERVER,
+            Self::HostName(_) => DHCP_OPT_HOST_NAME,
+            Self::DomainName(_) => DHCP_OPT_DOMAIN_NAME,
+            Self::BroadcastAddress(_) => DHCP_OPT_BROADCAST_ADDRESS,
+            Self::RequestedIp(_) => DHCP_OPT_REQUESTED_IP,
+            Self::LeaseTime(_) => DHCP_OPT_LEASE_TIME,
+            Self::MessageType(_) => DHCP_OPT_MESSAGE_TYPE,
+            Self::ServerId(_) => DHCP_OPT_SERVER_ID,
+            Self::ParameterRequestList(_) => DHCP_OPT_PARAMETER_REQUEST_LIST,
+            Self::Message(_) => DHCP_OPT_MESSAGE,
+            Self::MaxMessageSize(_) => DHCP_OPT_MAX_MESSAGE_SIZE,
+            Self::RenewalTime(_) => DHCP_OPT_RENEWAL_TIME,
+            Self::RebindingTime(_) => DHCP_OPT_REBINDING_TIME,
+            Self::ClientId(_) => DHCP_OPT_CLIENT_ID,
+            Self::Unknown { code, .. } => *code,
+        }
+    }
+
+    /// 解析一个带长度的选项，`data` 不含代码和长度字节
+    fn parse(code: u8, data: &[u8]) -> Result<Self, DhcpParseError> {
+        let addr = |b: &[u8]| Ipv4Addr::new(b[0], b[1], b[2], b[3]);
+        let addrs = |b: &[u8]| -> Result<Vec<Ipv4Addr>, DhcpParseError> {
+            if b.is_empty() || !b.len().is_multiple_of(4) {
+                return Err(DhcpParseError::InvalidOption);
+            }
+            Ok(b.chunks_exact(4).map(addr).collect())
+        };
+        let text = |b: &[u8]| String::from_utf8_lossy(b).into_owned();
+        let u32_of = |b: &[u8]| u32::from_be_bytes([b[0], b[1], b[2], b[3]]);
+
+        let option = match (code, data.len()) {
+            (DHCP_OPT_SUBNET_MASK, 4) => Self::SubnetMask(addr(data)),
+            (DHCP_OPT_ROUTER, _) => Self::Router(addrs(data)?),
+            (DHCP_OPT_DNS_SERVER, _) => Self::DnsServers(addrs(data)?),
+            (DHCP_OPT_HOST_NAME, 1..) => Self::HostName(text(data)),
+            (DHCP_OPT_DOMAIN_NAME, 1..) => Self::DomainName(text(data)),
+            (DHCP_OPT_BROADCAST_ADDRESS, 4) => Self::BroadcastAddress(addr(data)),
+            (DHCP_OPT_REQUESTED_IP, 4) => Self::RequestedIp(addr(data)),
+            (DHCP_OPT_LEASE_TIME, 4) => Self::LeaseTime(u32_of(data)),
+            (DHCP_OPT_MESSAGE_TYPE, 1) => Self::MessageType(
+                DhcpMessageType::from_u8(data[0]).ok_or(DhcpParseError::InvalidOption)?,
+            ),
+            (DHCP_OPT_SERVER_ID, 4) => Self::ServerId(addr(data)),
+            (DHCP_OPT_PARAMETER_REQUEST_LIST, 1..) => Self::ParameterRequestList(data.to_vec()),
+            (DHCP_OPT_MESSAGE, 1..) => Self::Message(text(data)),
+            (DHCP_OPT_MAX_MESSAGE_SIZE, 2) => {
+                Self::MaxMessageSize(u16::from_be_bytes([data[0], data[1]]))
+            }
+            (DHCP_OPT_RENEWAL_TIME, 4) => Self::RenewalTime(u32_of(data)),
+            (DHCP_OPT_REBINDING_TIME, 4) => Self::RebindingTime(u32_of(data)),
+            (DHCP_OPT_CLIENT_ID, 2..) => Self::ClientId(data.to_vec()),
+            // 已知代码但长度不对
+            (
+                DHCP_OPT_SUBNET_MASK
+                | DHCP_OPT_HOST_NAME
+                | DHCP_OPT_DOMAIN_NAME
+                | DHCP_OPT_BROADCAST_ADDRESS
+                | DHCP_OPT_REQUESTED_IP
+                | DHCP_OPT_LEASE_TIME
+                | DHCP_OPT_MESSAGE_TYPE
+                | DHCP_OPT_SERVER_ID
+                | DHCP_OPT_PARAMETER_REQUEST_LIST
+                | DHCP_OPT_MESSAGE
+                | DHCP_OPT_MAX_MESSAGE_SIZE
+                | DHCP_OPT_RENEWAL_TIME
+                | DHCP_OPT_REBINDING_TIME
+                | DHCP_OPT_CLIENT_ID,
+                _,
+            ) => return Err(DhcpParseError::InvalidOption),
+            _ => Self::Unknown {
+                code,
+                data: data.to_vec(),
+            },
+        };
+        Ok(option)
+    }
+
+    /// 编码，数据超过 255 字节的选项会被截断
+    pub fn write_to(&self, buf: &mut Vec<u8>) {
+        let mut data = Vec::new();
+        match self {
+            Self::Pad | Self::End => {
+                buf.push(self.code());
+                return;
+            }
+            Self::SubnetMask(addr)
+            | Self::BroadcastAddress(addr)
+            | Self::RequestedIp(addr)
+            | Self::ServerId(addr) => data.extend_from_slice(&addr.octets()),
+            Self::Router(addrs) | Self::DnsServers(addrs) => {
+                for addr in addrs {
+                    data.extend_from_slice(&addr.octets());
+                }
+            }
+            Self::HostName(s) | Self::DomainName(s) | Self::Message(s) => {
+                data.extend_from_slice(s.as_bytes())
+            }
+            Self::LeaseTime(v) | Self::RenewalTime(v) | Self::RebindingTime(v) => {
+                data.extend_from_slice(&v.to_be_bytes())
+            }
+            Self::MessageType(t) => data.push(t.to_u8()),
+            Self::MaxMessageSize(size) => data.extend_from_slice(&size.to_be_bytes()),
+            Self::ParameterRequestList(bytes)
+            | Self::ClientId(bytes)
+            | Self::Unknown { data: bytes, .. } => data.extend_from_slice(bytes),
+        }
+
+        data.truncate(u8::MAX as usize);
+        buf.push(self.code());
+        buf.push(data.len() as u8);
+        buf.extend_from_slice(&data);
+    }
+}
+
+/// 解析 magic cookie 之后的选项区，Pad 被跳过，End 之后的字节视为填充
+pub fn parse_options(bytes: &[u8]) -> Result<Vec<DhcpOption>, DhcpParseError> {
+    let mut options = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            DHCP_OPT_PAD => i += 1,
+            DHCP_OPT_END => return Ok(options),
+            code => {
+                let len = *bytes.get(i + 1).ok_or(DhcpParseError::InvalidOption)? as usize;
+                let data = bytes
+                    .get(i + 2..i + 2 + len)
+                    .ok_or(DhcpParseError::InvalidOption)?;
+                options.push(DhcpOption::parse(code, data)?);
+                i += 2 + len;
+            }
+        }
+    }
+    // RFC 2131 要求选项区以 End 结尾
+    Err(DhcpParseError::InvalidOption)
+}
+
+/// DHCP 报文，只支持以太网硬件地址
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DhcpMessage {
+    pub op: u8,
+    pub hops: u8,
+    /// 事务 ID，客户端选取，服务器原样带回
+    pub xid: u32,
+    /// 客户端开始获取地址以来经过的秒数
+    pub secs: u16,
+    pub flags: u16,
+    /// 客户端当前地址，只在 BOUND / RENEWING / REBINDING 状态下填写
+    pub ciaddr: Ipv4Addr,
+    /// 服务器分配给客户端的地址
+    pub yiaddr: Ipv4Addr,
+    /// 下一步引导使用的服务器地址
+    pub siaddr: Ipv4Addr,
+    /// 中继代理地址
+    pub giaddr: Ipv4Addr,
+    pub chaddr: MacAddr,
+    pub sname: String,
+    pub file: String,
+    pub options: Vec<DhcpOption>,
+}
+
+impl DhcpMessage {
+    /// 其余字段为 0 的报文，`options` 需要带上 MessageType
+    pub fn new(op: u8, xid: u32, chaddr: MacAddr, options: Vec<DhcpOption>) -> Self {
+        Self {
+            op,
+            hops: 0,
+            xid,
+            secs: 0,
+            flags: 0,
+            ciaddr: Ipv4Addr::unspecified(),
+            yiaddr: Ipv4Addr::unspecified(),
+            siaddr: Ipv4Addr::unspecified(),
+            giaddr: Ipv4Addr::unspecified(),
+            chaddr,
+            sname: String::new(),
+            file: String::new(),
+            options,
+        }
+    }
+
+    fn option<'a, T>(&'a self, f: impl Fn(&'a DhcpOption) -> Option<T>) -> Option<T> {
+        self.options.iter().find_map(f)
+    }
+
+    pub fn message_type(&self) -> Option<DhcpMessageType> {
+        self.option(|o| match o {
+            DhcpOption::MessageType(t) => Some(*t),
+            _ => None,
+        })
+    }
+
+    pub fn subnet_mask(&self) -> Option<Ipv4Addr> {
+        self.option(|o| match o {
+            DhcpOption::SubnetMask(mask) => Some(*mask),
+            _ => None,
+        })
+    }
+
+    pub fn routers(&self) -> &[Ipv4Addr] {
+        self.option(|o| match o {
+            DhcpOption::Router(addrs) => Some(addrs.as_slice()),
+            _ => None,
+        })
+        .unwrap_or_default()
+    }
+
+    pub fn dns_servers(&self) -> &[Ipv4Addr] {
+        self.option(|o| match o {
+            DhcpOption::DnsServers(addrs) => Some(addrs.as_slice()),
+            _ => None,
+        })
+        .unwrap_or_default()
+    }
+
+    pub fn requested_ip(&self) -> Option<Ipv4Addr> {
+        self.option(|o| match o {
+            DhcpOption::RequestedIp(addr) => Some(*addr),
+            _ => None,
+        })
+    }
+
+    pub fn server_id(&self) -> Option<Ipv4Addr> {
+        self.option(|o| match o {
+            DhcpOption::ServerId(addr) => Some(*addr),
+            _ => None,
+        })
+    }
+
+    pub fn lease_time(&self) -> Option<u32> {
+        self.option(|o| match o {
+            DhcpOption::LeaseTime(secs) => Some(*secs),
+            _ => None,
+        })
+    }
+
+    pub fn renewal_time(&self) -> Option<u32> {
+        self.option(|o| match o {
+            DhcpOption::RenewalTime(secs) => Some(*secs),
+            _ => None,
+        })
+    }
+
+    pub fn rebinding_time(&self) -> Option<u32> {
+        self.option(|o| match o {
+            DhcpOption::RebindingTime(secs) => Some(*secs),
+            _ => None,
+        })
+    }
+
+    pub fn client_id(&self) -> Option<&[u8]> {
+        self.option(|o| match o {
+            DhcpOption::ClientId(id) => Some(id.as_slice()),
+            _ => None,
+        })
+    }
+
+    pub fn parameter_request_list(&self) -> &[u8] {
+        self.option(|o| match o {
+            DhcpOption::ParameterRequestList(codes) => Some(codes.as_slice()),
+            _ => None,
+        })
+        .unwrap_or_default()
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(DHCP_MIN_LEN);
+        bytes.push(self.op);
+        bytes.push(1); // htype: 以太网
+        bytes.push(6); // hlen
+        bytes.push(self.hops);
+        bytes.extend_from_slice(&self.xid.to_be_bytes());
+        bytes.extend_from_slice(&self.secs.to_be_bytes());
+        bytes.extend_from_slice(&self.flags.to_be_bytes());
+        for addr in [self.ciaddr, self.yiaddr, self.siaddr, self.giaddr] {
+            bytes.extend_from_slice(&addr.octets());
+        }
+        // chaddr 字段 16 字节，以太网地址只用前 6 字节
+        bytes.extend_from_slice(self.chaddr.as_bytes());
+        bytes.extend_from_slice(&[0; 10]);
+        write_padded(&mut bytes, &self.sname, 64);
+        write_padded(&mut bytes, &self.file, 128);
+        bytes.extend_from_slice(&DHCP_MAGIC_COOKIE);
+
+        for option in &self.options {
+            if !matches!(option, DhcpOption::End | DhcpOption::Pad) {
+                option.write_to(&mut bytes);
+            }
+        }
+        bytes.push(DHCP_OPT_END);
+        if bytes.len() < DHCP_MIN_LEN {
+            bytes.resize(DHCP_MIN_LEN, DHCP_OPT_PAD);
+        }
+        bytes
+    }
+
+    pub fn parse(bytes: &[u8]) -> Result<Self, DhcpParseError> {
+        if bytes.len() < DHCP_FIXED_LEN {
+            return Err(DhcpParseError::InvalidLength);
+        }
+        if bytes[1] != 1 || bytes[2] != 6 {
+            return Err(DhcpParseError::UnsupportedHardware);
+        }
+        if bytes[236..240] != DHCP_MAGIC_COOKIE {
+            return Err(DhcpParseError::InvalidMagicCookie);
+        }
+
+        let addr = |i: usize| Ipv4Addr::new(bytes[i], bytes[i + 1], bytes[i + 2], bytes[i + 3]);
+        Ok(Self {
+            op: bytes[0],
+            hops: bytes[3],
+            xid: u32::from_be_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]),
+            secs: u16::from_be_bytes([bytes[8], bytes[9]]),
+            flags: u16::from_be_bytes([bytes[10], bytes[11]]),
+            ciaddr: addr(12),
+            yiaddr: addr(16),
+            siaddr: addr(20),
+            giaddr: addr(24),
+            chaddr: MacAddr::from_slice(&bytes[28..34]),
+            sname: read_padded(&bytes[44..108]),
+            file: read_padded(&bytes[108..236]),
+            options: parse_options(&bytes[DHCP_FIXED_LEN..])?,
+        })
+    }
+}
+
+impl fmt::Display for DhcpMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.message_type() {
+            Some(t) => write!(f, "{}", t)?,
+            None => write!(f, "BOOTP")?,
+        }
+        write!(f, " xid=0x{:08x} chaddr={}", self.xid, self.chaddr)?;
+        if self.ciaddr != Ipv4Addr::unspecified() {
+            write!(f, " ciaddr={}", self.ciaddr)?;
+        }
+        if self.yiaddr != Ipv4Addr::unspecified() {
+            write!(f, " yiaddr={}", self.yiaddr)?;
+        }
+        if let Some(server) = self.server_id() {
+            write!(f, " server={}", server)?;
+        }
+        Ok(())
+    }
+}
+
+/// 写入定长、以 NUL 填充的字符串字段，过长时截断
+fn write_padded(buf: &mut Vec<u8>, s: &str, len: usize) {
+    let bytes = &s.as_bytes()[..s.len().min(len - 1)];
+    buf.extend_from_slice(bytes);
+    buf.resize(buf.len() + len - bytes.len(), 0);
+}
+
+fn read_padded(bytes: &[u8]) -> String {
+    let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..end]).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CLIENT_MAC: MacAddr = MacAddr::from_raw([0x02, 0, 0, 0, 0, 0x0a]);
+    const SERVER: Ipv4Addr = Ipv4Addr::new(10, 0, 0, 1);
+
+    fn ack() -> DhcpMessage {
+        let mut ack = DhcpMessage::new(
+            BOOTREPLY,
+            0x1234_5678,
+            CLIENT_MAC,
+            vec![
+                DhcpOption::MessageType(DhcpMessageType::Ack),
+                DhcpOption::ServerId(SERVER),
+                DhcpOption::LeaseTime(3600),
+                DhcpOption::RenewalTime(1800),
+                DhcpOption::RebindingTime(3150),
+                DhcpOption::SubnetMask(Ipv4Addr::new(255, 255, 255, 0)),
+                DhcpOption::Router(vec![SERVER]),
+                DhcpOption::DnsServers(vec![SERVER, Ipv4Addr::new(10, 0, 0, 2)]),
+                DhcpOption::DomainName("lan".to_string()),
+            ],
+        );
+        ack.yiaddr = Ipv4Addr::new(10, 0, 0, 100);
+        ack.flags = DHCP_FLAG_BROADCAST;
+        ack.sname = "server".to_string();
+        ack
+    }
+
+    #[test]
+    fn messages_round_trip() {
+        let ack = ack();
+        let bytes = ack.to_bytes();
+        assert_eq!(DhcpMessage::parse(&bytes), Ok(ack.clone()));
+
+        assert_eq!(ack.message_type(), Some(DhcpMessageType::Ack));
+        assert_eq!(ack.server_id(), Some(SERVER));
+        assert_eq!(ack.lease_time(), Some(3600));
+        assert_eq!(ack.renewal_time(), Some(1800));
+        assert_eq!(ack.rebinding_time(), Some(3150));
+        assert_eq!(ack.subnet_mask(), Some(Ipv4Addr::new(255, 255, 255, 0)));
+        assert_eq!(ack.routers(), &[SERVER]);
+        assert_eq!(ack.dns_servers().len(), 2);
+        assert_eq!(ack.requested_ip(), None);
+        assert!(ack.parameter_request_list().is_empty());
+
+        let discover = DhcpMessage::new(
+            BOOTREQUEST,
+            1,
+            CLIENT_MAC,
+            vec![
+                DhcpOption::MessageType(DhcpMessageType::Discover),
+                DhcpOption::RequestedIp(Ipv4Addr::new(10, 0, 0, 100)),
+                DhcpOption::ParameterRequestList(vec![
+                    DHCP_OPT_SUBNET_MASK,
+                    DHCP_OPT_ROUTER,
+                    DHCP_OPT_DNS_SERVER,
+                ]),
+                DhcpOption::MaxMessageSize(1500),
+                DhcpOption::ClientId(vec![1, 2, 0, 0, 0, 0, 0x0a]),
+                DhcpOption::HostName("host".to_string()),
+                DhcpOption::Unknown {
+                    code: 252,
+                    data: b"http://wpad/".to_vec(),
+                },
+            ],
+        );
+        let parsed = DhcpMessage::parse(&discover.to_bytes()).unwrap();
+        assert_eq!(parsed, discover);
+        assert_eq!(parsed.client_id(), Some(&[1, 2, 0, 0, 0, 0, 0x0a][..]));
+        assert_eq!(
+            parsed.to_string(),
+            "DHCPDISCOVER xid=0x00000001 chaddr=02:00:00:00:00:0a"
+        );
+    }
+
+    #[test]
+    fn wire_layout_matches_rfc_2131() {
+        let bytes = ack().to_bytes();
+        // 选项很少时按 BOOTP 的最小长度填充
+        assert_eq!(bytes.len(), DHCP_MIN_LEN);
+        assert_eq!(&bytes[..4], &[BOOTREPLY, 1, 6, 0]);
+        assert_eq!(&bytes[4..8], &[0x12, 0x34, 0x56, 0x78]);
+        assert_eq!(&bytes[10..12], &[0x80, 0x00]);
+        assert_eq!(&bytes[16..20], &[10, 0, 0, 100]);
+        assert_eq!(&bytes[28..34], CLIENT_MAC.as_bytes());
+        assert_eq!(&bytes[34..44], &[0; 10]);
+        assert_eq!(&bytes[44..51], b"server\0");
+        assert_eq!(&bytes[236..240], &DHCP_MAGIC_COOKIE);
+        // 第一个选项是 Message Type
+        assert_eq!(&bytes[240..243], &[DHCP_OPT_MESSAGE_TYPE, 1, 5]);
+
+        // Pad 被跳过，End 之后的内容忽略
+        let options = parse_options(&[0, 0, DHCP_OPT_LEASE_TIME, 4, 0, 0, 0x0e, 0x10, 255, 1, 2]);
+        assert_eq!(options, Ok(vec![DhcpOption::LeaseTime(3600)]));
+    }
+
+    #[test]
+    fn malformed_messages_are_rejected() {
+        let bytes = ack().to_bytes();
+        assert_eq!(
+            DhcpMessage::parse(&bytes[..DHCP_FIXED_LEN - 1]),
+            Err(DhcpParseError::InvalidLength)
+        );
+
+        let mut token_ring = bytes.clone();
+        token_ring[1] = 6;
+        assert_eq!(
+            DhcpMessage::parse(&token_ring),
+            Err(DhcpParseError::UnsupportedHardware)
+        );
+
+        let mut bootp = bytes.clone();
+        bootp[236] = 0;
+        assert_eq!(
+            DhcpMessage::parse(&bootp),
+            Err(DhcpParseError::InvalidMagicCookie)
+        );
+
+        for options in [
+            // 没有 End
+            &[DHCP_OPT_LEASE_TIME, 4, 0, 0, 0x0e, 0x10][..],
+            // 长度超出选项区
+            &[DHCP_OPT_LEASE_TIME, 8, 0, 0, 0x0e, 0x10, 255][..],
+            // 已知选项长度不对
+            &[DHCP_OPT_SUBNET_MASK, 3, 255, 255, 255, 255][..],
+            &[DHCP_OPT_ROUTER, 6, 10, 0, 0, 1, 10, 0, 255][..],
+            // 未知的报文类型
+            &[DHCP_OPT_MESSAGE_TYPE, 1, 42, 255][..],
+        ] {
+            assert_eq!(
+                parse_options(options),
+                Err(DhcpParseError::InvalidOption),
+                "{:?}",
+                options
+            );
+        }
+    }
+
+    #[test]
+    fn message_types_match_rfc_2132() {
+        for value in 1..=8 {
+            let message_type = DhcpMessageType::from_u8(value).unwrap();
+            assert_eq!(message_type.to_u8(), value);
+        }
+        assert_eq!(DhcpMessageType::from_u8(0), None);
+        assert_eq!(DhcpMessageType::from_u8(9), None);
+        assert_eq!(DhcpMessageType::Request.to_string(), "DHCPREQUEST");
+    }
+}
@@ -0,0 +1,997 @@
+// Copyright (C) 2025 rrrrrzy
+// SPDX-License-Identifier: GPL-3.0-or-later
+//
+// --------------------------------------------------
+// 致敬所有在深夜调试代码的灵魂。
+// 即便 Bug 如山，我亦往矣。
+// --------------------------------------------------
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+//! DNS 报文编解码 (RFC 1035)，支持名字压缩和 A、AAAA、CNAME、PTR、TXT、SRV、SOA 记录
+//!
+//! 同一套编解码也用于组播 DNS (RFC 6762)，类别字段的最高位按 mDNS 的约定解释
+
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::{error::DnsParseError, ipv4::Ipv4Addr, ipv6::Ipv6Addr};
+
+pub const DNS_PORT: u16 = 53;
+
+/// 不使用 EDNS 时 UDP 上 DNS 报文的最大长度 (RFC 1035 4.2.1)
+pub const DNS_MAX_UDP_LEN: usize = 512;
+
+const DNS_HEADER_LEN: usize = 12;
+
+/// 名字编码后最多 255 字节，单个标签最多 63 字节
+pub const DNS_MAX_NAME_LEN: usize = 255;
+pub const DNS_MAX_LABEL_LEN: usize = 63;
+
+pub const DNS_CLASS_IN: u16 = 1;
+
+pub const MDNS_PORT: u16 = 5353;
+pub const MDNS_IPV4_GROUP: Ipv4Addr = Ipv4Addr::new(224, 0, 0, 251);
+
+// mDNS 中类别字段的最高位：问题里是单播应答请求 (QU)，记录里是 cache-flush
+const MDNS_CLASS_FLAG: u16 = 0x8000;
+
+// 首部标志位
+const DNS_FLAG_QR: u16 = 0x8000;
+const DNS_FLAG_AA: u16 = 0x0400;
+const DNS_FLAG_TC: u16 = 0x0200;
+const DNS_FLAG_RD: u16 = 0x0100;
+const DNS_FLAG_RA: u16 = 0x0080;
+
+// 压缩指针的高两位
+const DNS_POINTER: u8 = 0xC0;
+
+/// 资源记录类型，未识别的类型保留原始值
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DnsRecordType {
+    A,
+    Cname,
+    Soa,
+    Ptr,
+    Txt,
+    Aaaa,
+    Srv,
+    /// 只能出现在问题中，请求所有类型的记录
+    Any,
+    Other(u16),
+}
+
+impl DnsRecordType {
+    pub fn from_u16(value: u16) -> Self {
+        match value {
+            1 => Self::A,
+            5 => Self::Cname,
+            6 => Self::Soa,
+            12 => Self::Ptr,
+            16 => Self::Txt,
+            28 => Self::Aaaa,
+            33 => Self::Srv,
+            255 => Self::Any,
+            other => Self::Other(other),
+        }
+    }
+
+    pub fn to_u16(self) -> u16 {
+        match self {
+            Self::A => 1,
+            Self::Cname => 5,
+            Self::Soa => 6,
+            Self::Ptr => 12,
+            Self::Txt => 16,
+            Self::Aaaa => 28,
+            Self::Srv => 33,
+            Self::Any => 255,
+            Self::Other(value) => value,
+        }
+    }
+
+    /// 按助记符解析，不区分大小写，也接受 RFC 3597 的 TYPEnnn 写法
+    pub fn from_name(name: &str) -> Option<Self> {
+        let name = name.to_ascii_uppercase();
+        let rtype = match name.as_str() {
+            "A" => Self::A,
+            "CNAME" => Self::Cname,
+            "SOA" => Self::Soa,
+            "PTR" => Self::Ptr,
+            "TXT" => Self::Txt,
+            "AAAA" => Self::Aaaa,
+            "SRV" => Self::Srv,
+            "ANY" => Self::Any,
+            _ => Self::from_u16(name.strip_prefix("TYPE")?.parse().ok()?),
+        };
+        Some(rtype)
+    }
+}
+
+impl fmt::Display for DnsRecordType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::A => write!(f, "A"),
+            Self::Cname => write!(f, "CNAME"),
+            Self::Soa => write!(f, "SOA"),
+            Self::Ptr => write!(f, "PTR"),
+            Self::Txt => write!(f, "TXT"),
+            Self::Aaaa => write!(f, "AAAA"),
+            Self::Srv => write!(f, "SRV"),
+            Self::Any => write!(f, "ANY"),
+            Self::Other(value) => write!(f, "TYPE{}", value),
+        }
+    }
+}
+
+/// 首部的 RCODE 字段
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DnsResponseCode {
+    NoError,
+    FormatError,
+    ServerFailure,
+    /// 名字不存在 (NXDOMAIN)
+    NameError,
+    NotImplemented,
+    Refused,
+    Other(u8),
+}
+
+impl DnsResponseCode {
+    pub fn from_u8(value: u8) -> Self {
+        match value {
+            0 => Self::NoError,
+            1 => Self::FormatError,
+            2 => Self::ServerFailure,
+            3 => Self::NameError,
+            4 => Self::NotImplemented,
+            5 => Self::Refused,
+            other => Self::Other(other),
+        }
+    }
+
+    pub fn to_u8(self) -> u8 {
+        match self {
+            Self::NoError => 0,
+            Self::FormatError => 1,
+            Self::ServerFailure => 2,
+            Self::NameError => 3,
+            Self::NotImplemented => 4,
+            Self::Refused => 5,
+            Self::Other(value) => value,
+        }
+    }
+}
+
+impl fmt::Display for DnsResponseCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NoError => write!(f, "NOERROR"),
+            Self::FormatError => write!(f, "FORMERR"),
+            Self::ServerFailure => write!(f, "SERVFAIL"),
+            Self::NameError => write!(f, "NXDOMAIN"),
+            Self::NotImplemented => write!(f, "NOTIMP"),
+            Self::Refused => write!(f, "REFUSED"),
+            Self::Other(value) => write!(f, "RCODE{}", value),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DnsQuestion {
+    pub name: String,
+    pub qtype: DnsRecordType,
+    pub qclass: u16,
+    /// mDNS 的 QU 位：请求以单播应答
+    pub unicast_response: bool,
+}
+
+/// 资源记录的 RDATA，未识别的类型按原始字节保留
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DnsRecordData {
+    A(Ipv4Addr),
+    Aaaa(Ipv6Addr),
+    Cname(String),
+    Ptr(String),
+    /// 一个或多个 character-string
+    Txt(Vec<String>),
+    Srv {
+        priority: u16,
+        weight: u16,
+        port: u16,
+        target: String,
+    },
+    Soa {
+        mname: String,
+        rname: String,
+        serial: u32,
+        refresh: u32,
+        retry: u32,
+        expire: u32,
+        /// 否定应答的缓存时间 (RFC 2308)
+        minimum: u32,
+    },
+    Unknown {
+        rtype: u16,
+        data: Vec<u8>,
+    },
+}
+
+impl DnsRecordData {
+    pub fn rtype(&self) -> DnsRecordType {
+        match self {
+            Self::A(_) => DnsRecordType::A,
+            Self::Aaaa(_) => DnsRecordType::Aaaa,
+            Self::Cname(_) => DnsRecordType::Cname,
+            Self::Ptr(_) => DnsRecordType::Ptr,
+            Self::Txt(_) => DnsRecordType::Txt,
+            Self::Srv { .. } => DnsRecordType::Srv,
+            Self::Soa { .. } => DnsRecordType::Soa,
+            Self::Unknown { rtype, .. } => DnsRecordType::from_u16(*rtype),
+        }
+    }
+
+    /// 解析 bytes[start..end] 处的 RDATA，名字可能指向报文的其他位置
+    fn parse(
+        rtype: DnsRecordType,
+        bytes: &[u8],
+        start: usize,
+        end: usize,
+    ) -> Result<Self, DnsParseError> {
+        let rdata = &bytes[start..end];
+        let u16_at = |i: usize| u16::from_be_bytes([rdata[i], rdata[i + 1]]);
+        // RDATA 中的名字必须恰好占满剩余部分
+        let name_until_end = |pos: usize| -> Result<String, DnsParseError> {
+            let (name, next) = read_name(bytes, pos)?;
+            if next != end {
+                return Err(DnsParseError::InvalidRecord);
+            }
+            Ok(name)
+        };
+
+        let data = match (rtype, rdata.len()) {
+            (DnsRecordType::A, 4) => Self::A(Ipv4Addr::new(rdata[0], rdata[1], rdata[2], rdata[3])),
+            (DnsRecordType::Aaaa, 16) => {
+                let mut octets = [0u8; 16];
+                octets.copy_from_slice(rdata);
+                Self::Aaaa(Ipv6Addr::from_octets(octets))
+            }
+            (DnsRecordType::Cname, _) => Self::Cname(name_until_end(start)?),
+            (DnsRecordType::Ptr, _) => Self::Ptr(name_until_end(start)?),
+            (DnsRecordType::Txt, 1..) => {
+                let mut strings = Vec::new();
+                let mut i = 0;
+                while i < rdata.len() {
+                    let len = rdata[i] as usize;
+                    let text = rdata
+                        .get(i + 1..i + 1 + len)
+                        .ok_or(DnsParseError::InvalidRecord)?;
+                    strings.push(String::from_utf8_lossy(text).into_owned());
+                    i += 1 + len;
+                }
+                Self::Txt(strings)
+            }
+            (DnsRecordType::Srv, 7..) => Self::Srv {
+                priority: u16_at(0),
+                weight: u16_at(2),
+                port: u16_at(4),
+                target: name_until_end(start + 6)?,
+            },
+            (DnsRecordType::Soa, _) => {
+                let (mname, next) = read_name(bytes, start)?;
+                let (rname, next) = read_name(bytes, next)?;
+                if end.checked_sub(next) != Some(20) {
+                    return Err(DnsParseError::InvalidRecord);
+                }
+                let u32_at = |i: usize| {
+                    let b = &bytes[next + i..next + i + 4];
+                    u32::from_be_bytes([b[0], b[1], b[2], b[3]])
+                };
+                Self::Soa {
+                    mname,
+                    rname,
+                    serial: u32_at(0),
+                    refresh: u32_at(4),
+                    retry: u32_at(8),
+                    expire: u32_at(12),
+                    minimum: u32_at(16),
+                }
+            }
+            (
+                DnsRecordType::A | DnsRecordType::Aaaa | DnsRecordType::Txt | DnsRecordType::Srv,
+                _,
+            ) => {
+                return Err(DnsParseError::InvalidRecord);
+            }
+            (rtype, _) => Self::Unknown {
+                rtype: rtype.to_u16(),
+                data: rdata.to_vec(),
+            },
+        };
+        Ok(data)
+    }
+
+    /// 编码 RDATA，CNAME / PTR / SOA 中的名字参与压缩，SRV 的 target 不压缩 (RFC 2782)
+    fn write_to(&self, buf: &mut Vec<u8>, names: &mut HashMap<String, u16>) {
+        match self {
+            Self::A(addr) => buf.extend_from_slice(&addr.octets()),
+            Self::Aaaa(addr) => buf.extend_from_slice(&addr.octets()),
+            Self::Cname(name) | Self::Ptr(name) => write_name(buf, name, Some(names)),
+            Self::Txt(strings) => {
+                for s in strings {
+                    let bytes = &s.as_bytes()[..s.len().min(u8::MAX as usize)];
+                    buf.push(bytes.len() as u8);
+                    buf.extend_from_slice(bytes);
+                }
+            }
+            Self::Srv {
+                priority,
+                weight,
+                port,
+                target,
+            } => {
+                buf.extend_from_slice(&priority.to_be_bytes());
+                buf.extend_from_slice(&weight.to_be_bytes());
+                buf.extend_from_slice(&port.to_be_bytes());
+                write_name(buf, target, None);
+            }
+            Self::Soa {
+                mname,
+                rname,
+                serial,
+                refresh,
+                retry,
+                expire,
+                minimum,
+            } => {
+                write_name(buf, mname, Some(names));
+                write_name(buf, rname, Some(names));
+                for v in [serial, refresh, retry, expire, minimum] {
+                    buf.extend_from_slice(&v.to_be_bytes());
+                }
+            }
+            Self::Unknown { data, .. } => buf.extend_from_slice(data),
+        }
+    }
+}
+
+impl fmt::Display for DnsRecordData {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::A(addr) => write!(f, "{}", addr),
+            Self::Aaaa(addr) => write!(f, "{}", addr),
+            Self::Cname(name) | Self::Ptr(name) => write!(f, "{}.", name),
+            Self::Txt(strings) => {
+                let quoted: Vec<String> = strings.iter().map(|s| format!("{:?}", s)).collect();
+                write!(f, "{}", quoted.join(" "))
+            }
+            Self::Srv {
+                priority,
+                weight,
+                port,
+                target,
+            } => write!(f, "{} {} {} {}.", priority, weight, port, target),
+            Self::Soa {
+                mname,
+                rname,
+                serial,
+                refresh,
+                retry,
+                expire,
+                minimum,
+            } => write!(
+                f,
+                "{}. {}. {} {} {} {} {}",
+                mname, rname, serial, refresh, retry, expire, minimum
+            ),
+            // RFC 3597 的未知类型表示法
+            Self::Unknown { data, .. } => {
+                write!(f, "\\# {}", data.len())?;
+                if !data.is_empty() {
+                    write!(f, " ")?;
+                    for b in data {
+                        write!(f, "{:02x}", b)?;
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DnsRecord {
+    pub name: String,
+    pub class: u16,
+    /// mDNS 的 cache-flush 位：该记录集是唯一的，接收方应替换缓存中的旧记录
+    pub cache_flush: bool,
+    /// 生存时间，单位秒
+    pub ttl: u32,
+    pub data: DnsRecordData,
+}
+
+impl DnsRecord {
+    pub fn new(name: &str, ttl: u32, data: DnsRecordData) -> Self {
+        Self {
+            name: name.trim_end_matches('.').to_string(),
+            class: DNS_CLASS_IN,
+            cache_flush: false,
+            ttl,
+            data,
+        }
+    }
+
+    pub fn rtype(&self) -> DnsRecordType {
+        self.data.rtype()
+    }
+}
+
+/// dig 风格的一行：`example.com. 300 IN A 93.184.216.34`
+impl fmt::Display for DnsRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}. {} ", self.name, self.ttl)?;
+        if self.class == DNS_CLASS_IN {
+            write!(f, "IN")?;
+        } else {
+            write!(f, "CLASS{}", self.class)?;
+        }
+        write!(f, " {} {}", self.rtype(), self.data)
+    }
+}
+
+/// DNS 报文，名字不带结尾的点，根域为空字符串
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DnsMessage {
+    pub id: u16,
+    /// QR 位：false 为查询，true 为应答
+    pub response: bool,
+    pub opcode: u8,
+    pub authoritative: bool,
+    /// 应答超过传输层允许的长度被截断，需要改用 TCP
+    pub truncated: bool,
+    pub recursion_desired: bool,
+    pub recursion_available: bool,
+    pub rcode: DnsResponseCode,
+    pub questions: Vec<DnsQuestion>,
+    pub answers: Vec<DnsRecord>,
+    pub authorities: Vec<DnsRecord>,
+    pub additionals: Vec<DnsRecord>,
+}
+
+impl DnsMessage {
+    /// 标志位全部清零、各部分为空的标准查询
+    pub fn new(id: u16) -> Self {
+        Self {
+            id,
+            response: false,
+            opcode: 0,
+            authoritative: false,
+            truncated: false,
+            recursion_desired: false,
+            recursion_available: false,
+            rcode: DnsResponseCode::NoError,
+            questions: Vec::new(),
+            answers: Vec::new(),
+            authorities: Vec::new(),
+            additionals: Vec::new(),
+        }
+    }
+
+    /// 只有一个问题、请求递归的标准查询
+    pub fn query(id: u16, name: &str, qtype: DnsRecordType) -> Self {
+        let mut query = Self::new(id);
+        query.recursion_desired = true;
+        query.questions.push(DnsQuestion {
+            name: name.trim_end_matches('.').to_string(),
+            qtype,
+            qclass: DNS_CLASS_IN,
+            unicast_response: false,
+        });
+        query
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut flags = ((self.opcode as u16 & 0x0F) << 11) | self.rcode.to_u8() as u16 & 0x0F;
+        for (set, flag) in [
+            (self.response, DNS_FLAG_QR),
+            (self.authoritative, DNS_FLAG_AA),
+            (self.truncated, DNS_FLAG_TC),
+            (self.recursion_desired, DNS_FLAG_RD),
+            (self.recursion_available, DNS_FLAG_RA),
+        ] {
+            if set {
+                flags |= flag;
+            }
+        }
+
+        let mut bytes = Vec::with_capacity(DNS_MAX_UDP_LEN);
+        bytes.extend_from_slice(&self.id.to_be_bytes());
+        bytes.extend_from_slice(&flags.to_be_bytes());
+        for count in [
+            self.questions.len(),
+            self.answers.len(),
+            self.authorities.len(),
+            self.additionals.len(),
+        ] {
+            bytes.extend_from_slice(&(count as u16).to_be_bytes());
+        }
+
+        // 已写入的名字后缀到其偏移的映射，用于压缩
+        let mut names = HashMap::new();
+        for question in &self.questions {
+            write_name(&mut bytes, &question.name, Some(&mut names));
+            bytes.extend_from_slice(&question.qtype.to_u16().to_be_bytes());
+            let qclass = match question.unicast_response {
+                true => question.qclass | MDNS_CLASS_FLAG,
+                false => question.qclass,
+            };
+            bytes.extend_from_slice(&qclass.to_be_bytes());
+        }
+        for record in self
+            .answers
+            .iter()
+            .chain(&self.authorities)
+            .chain(&self.additionals)
+        {
+            write_name(&mut bytes, &record.name, Some(&mut names));
+            bytes.extend_from_slice(&record.rtype().to_u16().to_be_bytes());
+            let class = match record.cache_flush {
+                true => record.class | MDNS_CLASS_FLAG,
+                false => record.class,
+            };
+            bytes.extend_from_slice(&class.to_be_bytes());
+            bytes.extend_from_slice(&record.ttl.to_be_bytes());
+            // RDLENGTH 在写完 RDATA 后回填
+            let len_at = bytes.len();
+            bytes.extend_from_slice(&[0, 0]);
+            record.data.write_to(&mut bytes, &mut names);
+            let rdlen = (bytes.len() - len_at - 2) as u16;
+            bytes[len_at..len_at + 2].copy_from_slice(&rdlen.to_be_bytes());
+        }
+        bytes
+    }
+
+    pub fn parse(bytes: &[u8]) -> Result<Self, DnsParseError> {
+        if bytes.len() < DNS_HEADER_LEN {
+            return Err(DnsParseError::InvalidLength);
+        }
+        let u16_at = |i: usize| u16::from_be_bytes([bytes[i], bytes[i + 1]]);
+        let flags = u16_at(2);
+
+        let mut pos = DNS_HEADER_LEN;
+        let mut questions = Vec::new();
+        for _ in 0..u16_at(4) {
+            let (name, next) = read_name(bytes, pos)?;
+            if next + 4 > bytes.len() {
+                return Err(DnsParseError::InvalidLength);
+            }
+            let qclass = u16_at(next + 2);
+            questions.push(DnsQuestion {
+                name,
+                qtype: DnsRecordType::from_u16(u16_at(next)),
+                qclass: qclass & !MDNS_CLASS_FLAG,
+                unicast_response: qclass & MDNS_CLASS_FLAG != 0,
+            });
+            pos = next + 4;
+        }
+
+        let mut sections = [Vec::new(), Vec::new(), Vec::new()];
+        for (section, count) in sections.iter_mut().zip([u16_at(6), u16_at(8), u16_at(10)]) {
+            for _ in 0..count {
+                let (record, next) = read_record(bytes, pos)?;
+                section.push(record);
+                pos = next;
+            }
+        }
+        let [answers, authorities, additionals] = sections;
+
+        Ok(Self {
+            id: u16_at(0),
+            response: flags & DNS_FLAG_QR != 0,
+            opcode: ((flags >> 11) & 0x0F) as u8,
+            authoritative: flags & DNS_FLAG_AA != 0,
+            truncated: flags & DNS_FLAG_TC != 0,
+            recursion_desired: flags & DNS_FLAG_RD != 0,
+            recursion_available: flags & DNS_FLAG_RA != 0,
+            rcode: DnsResponseCode::from_u8((flags & 0x0F) as u8),
+            questions,
+            answers,
+            authorities,
+            additionals,
+        })
+    }
+}
+
+impl fmt::Display for DnsMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.response {
+            write!(f, "DNS response 0x{:04x} {}", self.id, self.rcode)?;
+        } else {
+            write!(f, "DNS query 0x{:04x}", self.id)?;
+        }
+        for question in &self.questions {
+            write!(f, " {}. {}", question.name, question.qtype)?;
+        }
+        if self.response {
+            write!(
+                f,
+                ", {} answers, {} authority, {} additional",
+                self.answers.len(),
+                self.authorities.len(),
+                self.additionals.len()
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// 名字是否能编码：每个标签 1 到 63 字节，总长不超过 255 字节，可以带结尾的点
+pub fn is_valid_name(name: &str) -> bool {
+    let name = name.strip_suffix('.').unwrap_or(name);
+    if name.is_empty() {
+        return true;
+    }
+    // 每个标签一个长度字节，再加根标签
+    name.len() + 2 <= DNS_MAX_NAME_LEN
+        && name
+            .split('.')
+            .all(|label| !label.is_empty() && label.len() <= DNS_MAX_LABEL_LEN)
+}
+
+/// IPv4 地址的反向解析名，如 1.2.0.192.in-addr.arpa
+pub fn reverse_name(addr: Ipv4Addr) -> String {
+    let [a, b, c, d] = addr.octets();
+    format!("{}.{}.{}.{}.in-addr.arpa", d, c, b, a)
+}
+
+/// 编码名字，给出 names 时尽量用指针指向之前写过的相同后缀 (RFC 1035 4.1.4)
+///
+/// 过长的标签被截断，调用者应事先用 `is_valid_name` 检查
+fn write_name(buf: &mut Vec<u8>, name: &str, mut names: Option<&mut HashMap<String, u16>>) {
+    let name = name.trim_end_matches('.');
+    let labels: Vec<&str> = if name.is_empty() {
+        Vec::new()
+    } else {
+        name.split('.').collect()
+    };
+
+    for i in 0..labels.len() {
+        if let Some(names) = names.as_deref_mut() {
+            // 名字比较不区分大小写
+            let suffix = labels[i..].join(".").to_ascii_lowercase();
+            if let Some(&offset) = names.get(&suffix) {
+                buf.extend_from_slice(&(offset | (DNS_POINTER as u16) << 8).to_be_bytes());
+                return;
+            }
+            // 指针只有 14 位
+            if buf.len() < 0x4000 {
+                names.insert(suffix, buf.len() as u16);
+            }
+        }
+        let label = &labels[i].as_bytes()[..labels[i].len().min(DNS_MAX_LABEL_LEN)];
+        buf.push(label.len() as u8);
+        buf.extend_from_slice(label);
+    }
+    buf.push(0);
+}
+
+/// 从 start 处读出一个名字，返回名字和紧跟其后的偏移
+fn read_name(bytes: &[u8], start: usize) -> Result<(String, usize), DnsParseError> {
+    let mut labels = Vec::new();
+    let mut pos = start;
+    // 第一个指针之后的位置就是名字在原处的结尾
+    let mut end = None;
+    let mut len = 1;
+
+    loop {
+        let b = *bytes.get(pos).ok_or(DnsParseError::InvalidLength)?;
+        match b & DNS_POINTER {
+            0 if b == 0 => break,
+            0 => {
+                let label = bytes
+                    .get(pos + 1..pos + 1 + b as usize)
+                    .ok_or(DnsParseError::InvalidLength)?;
+                len += 1 + label.len();
+                if len > DNS_MAX_NAME_LEN {
+                    return Err(DnsParseError::InvalidName);
+                }
+                labels.push(String::from_utf8_lossy(label).into_owned());
+                pos += 1 + label.len();
+            }
+            DNS_POINTER => {
+                let low = *bytes.get(pos + 1).ok_or(DnsParseError::InvalidLength)?;
+                let target = ((b & !DNS_POINTER) as usize) << 8 | low as usize;
+                // 只允许向前指，加上长度限制，保证不会死循环
+                if target >= pos {
+                    return Err(DnsParseError::InvalidName);
+                }
+                end.get_or_insert(pos + 2);
+                pos = target;
+            }
+            // 0x40 / 0x80 是已废弃的扩展标签类型
+            _ => return Err(DnsParseError::InvalidName),
+        }
+    }
+
+    Ok((labels.join("."), end.unwrap_or(pos + 1)))
+}
+
+/// 从 start 处读出一条资源记录，返回记录和紧跟其后的偏移
+fn read_record(bytes: &[u8], start: usize) -> Result<(DnsRecord, usize), DnsParseError> {
+    let (name, pos) = read_name(bytes, start)?;
+    if pos + 10 > bytes.len() {
+        return Err(DnsParseError::InvalidLength);
+    }
+    let u16_at = |i: usize| u16::from_be_bytes([bytes[i], bytes[i + 1]]);
+    let rtype = DnsRecordType::from_u16(u16_at(pos));
+    let class = u16_at(pos + 2);
+    let ttl = u32::from_be_bytes([
+        bytes[pos + 4],
+        bytes[pos + 5],
+        bytes[pos + 6],
+        bytes[pos + 7],
+    ]);
+    let rdlen = u16_at(pos + 8) as usize;
+
+    let rdata_start = pos + 10;
+    let rdata_end = rdata_start + rdlen;
+    if rdata_end > bytes.len() {
+        return Err(DnsParseError::InvalidLength);
+    }
+    let data = DnsRecordData::parse(rtype, bytes, rdata_start, rdata_end)?;
+
+    let record = DnsRecord {
+        name,
+        class: class & !MDNS_CLASS_FLAG,
+        cache_flush: class & MDNS_CLASS_FLAG != 0,
+        // 最高位为 1 的 TTL 按 0 处理 (RFC 2181 8)
+        ttl: if ttl > i32::MAX as u32 { 0 } else { ttl },
+        data,
+    };
+    Ok((record, rdata_end))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response() -> DnsMessage {
+        let mut response = DnsMessage::query(0xbeef, "www.example.com", DnsRecordType::A);
+        response.response = true;
+        response.recursion_available = true;
+        response.answers = vec![
+            DnsRecord::new(
+                "www.example.com",
+                300,
+                DnsRecordData::Cname("example.com".to_string()),
+            ),
+            DnsRecord::new(
+                "example.com",
+                300,
+                DnsRecordData::A(Ipv4Addr::new(93, 184, 216, 34)),
+            ),
+            DnsRecord::new(
+                "example.com",
+                300,
+                DnsRecordData::Aaaa(Ipv6Addr::new(0x2606, 0x2800, 0x220, 1, 0, 0, 0, 0x1946)),
+            ),
+            DnsRecord::new(
+                "example.com",
+                60,
+                DnsRecordData::Txt(vec!["v=spf1 -all".to_string(), String::new()]),
+            ),
+            DnsRecord::new(
+                "_sip._udp.example.com",
+                60,
+                DnsRecordData::Srv {
+                    priority: 10,
+                    weight: 5,
+                    port: 5060,
+                    target: "sip.example.com".to_string(),
+                },
+            ),
+            DnsRecord::new(
+                "34.216.184.93.in-addr.arpa",
+                60,
+                DnsRecordData::Ptr("example.com".to_string()),
+            ),
+        ];
+        response.authorities = vec![DnsRecord::new(
+            "example.com",
+            3600,
+            DnsRecordData::Soa {
+                mname: "ns.example.com".to_string(),
+                rname: "hostmaster.example.com".to_string(),
+                serial: 2025010101,
+                refresh: 7200,
+                retry: 3600,
+                expire: 1209600,
+                minimum: 300,
+            },
+        )];
+        response.additionals = vec![DnsRecord::new(
+            "example.com",
+            0,
+            DnsRecordData::Unknown {
+                rtype: 99,
+                data: vec![1, 2, 3],
+            },
+        )];
+        response
+    }
+
+    #[test]
+    fn messages_round_trip() {
+        let response = response();
+        assert_eq!(
+            DnsMessage::parse(&response.to_bytes()),
+            Ok(response.clone())
+        );
+        assert_eq!(
+            response.to_string(),
+            "DNS response 0xbeef NOERROR www.example.com. A, 6 answers, 1 authority, 1 additional"
+        );
+
+        // mDNS 的 QU 位和 cache-flush 位与类别分开保存
+        let mut mdns = DnsMessage::new(0);
+        mdns.questions.push(DnsQuestion {
+            name: "host.local".to_string(),
+            qtype: DnsRecordType::Any,
+            qclass: DNS_CLASS_IN,
+            unicast_response: true,
+        });
+        let mut record = DnsRecord::new(
+            "host.local",
+            120,
+            DnsRecordData::A(Ipv4Addr::new(10, 0, 0, 1)),
+        );
+        record.cache_flush = true;
+        mdns.answers.push(record);
+        mdns.authoritative = true;
+        mdns.response = true;
+        let bytes = mdns.to_bytes();
+        assert_eq!(&bytes[2..4], &[0x84, 0x00]);
+        assert_eq!(DnsMessage::parse(&bytes), Ok(mdns));
+    }
+
+    #[test]
+    fn wire_layout_matches_rfc_1035() {
+        let query = DnsMessage::query(0x1234, "example.com.", DnsRecordType::Aaaa);
+        let bytes = query.to_bytes();
+        assert_eq!(
+            &bytes[..12],
+            &[0x12, 0x34, 0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 0]
+        );
+        assert_eq!(&bytes[12..25], b"\x07example\x03com\x00");
+        assert_eq!(&bytes[25..], &[0, 28, 0, 1]);
+        assert_eq!(query.to_string(), "DNS query 0x1234 example.com. AAAA");
+
+        let mut nxdomain = query.clone();
+        nxdomain.response = true;
+        nxdomain.rcode = DnsResponseCode::NameError;
+        let bytes = nxdomain.to_bytes();
+        assert_eq!(&bytes[2..4], &[0x81, 0x03]);
+        assert_eq!(
+            DnsMessage::parse(&bytes).unwrap().rcode,
+            DnsResponseCode::NameError
+        );
+
+        // 重复的名字后缀压缩为指向问题区的指针，不区分大小写
+        let mut response = DnsMessage::query(1, "www.example.com", DnsRecordType::A);
+        response.answers.push(DnsRecord::new(
+            "WWW.Example.com",
+            300,
+            DnsRecordData::Cname("mail.example.com".to_string()),
+        ));
+        let bytes = response.to_bytes();
+        let answer = 12 + 17 + 4;
+        assert_eq!(&bytes[answer..answer + 2], &[0xc0, 12]);
+        // CNAME 的 RDATA 是 "mail" 加上指向 example.com 的指针
+        assert_eq!(&bytes[answer + 10..answer + 12], &[0, 7]);
+        assert_eq!(&bytes[answer + 12..], b"\x04mail\xc0\x10");
+        let parsed = DnsMessage::parse(&bytes).unwrap();
+        assert_eq!(parsed.answers[0].name, "www.example.com");
+        assert_eq!(
+            parsed.answers[0].to_string(),
+            "www.example.com. 300 IN CNAME mail.example.com."
+        );
+    }
+
+    #[test]
+    fn malformed_messages_are_rejected() {
+        let bytes = response().to_bytes();
+        assert_eq!(
+            DnsMessage::parse(&bytes[..11]),
+            Err(DnsParseError::InvalidLength)
+        );
+        // 截断在任意位置都不会 panic
+        for len in 12..bytes.len() {
+            assert!(DnsMessage::parse(&bytes[..len]).is_err(), "{}", len);
+        }
+
+        let header = |qdcount: u8| vec![0, 1, 0, 0, 0, qdcount, 0, 0, 0, 0, 0, 0];
+        let question = |name: &[u8]| {
+            let mut bytes = header(1);
+            bytes.extend_from_slice(name);
+            bytes.extend_from_slice(&[0, 1, 0, 1]);
+            bytes
+        };
+        // 指向自己的指针
+        assert_eq!(
+            DnsMessage::parse(&question(&[0xc0, 12])),
+            Err(DnsParseError::InvalidName)
+        );
+        // 已废弃的扩展标签
+        assert_eq!(
+            DnsMessage::parse(&question(&[0x41, b'a', 0])),
+            Err(DnsParseError::InvalidName)
+        );
+        // 超过 255 字节的名字
+        let long: Vec<u8> = (0..5)
+            .flat_map(|_| std::iter::once(63).chain([b'a'; 63]))
+            .chain([0])
+            .collect();
+        assert_eq!(
+            DnsMessage::parse(&question(&long)),
+            Err(DnsParseError::InvalidName)
+        );
+
+        let record = |rtype: u8, rdata: &[u8]| {
+            let mut bytes = header(0);
+            bytes[7] = 1;
+            bytes.extend_from_slice(&[0, 0, rtype, 0, 1, 0, 0, 0, 60, 0, rdata.len() as u8]);
+            bytes.extend_from_slice(rdata);
+            DnsMessage::parse(&bytes)
+        };
+        assert!(record(1, &[10, 0, 0, 1]).is_ok());
+        assert_eq!(record(1, &[10, 0, 0]), Err(DnsParseError::InvalidRecord));
+        assert_eq!(record(28, &[0; 4]), Err(DnsParseError::InvalidRecord));
+        assert_eq!(record(16, &[5, b'a']), Err(DnsParseError::InvalidRecord));
+        assert_eq!(record(33, &[0; 6]), Err(DnsParseError::InvalidRecord));
+        // 名字之后还有多余的字节
+        assert_eq!(record(5, &[0, 0]), Err(DnsParseError::InvalidRecord));
+        assert_eq!(record(6, &[0, 0, 0, 0]), Err(DnsParseError::InvalidRecord));
+    }
+
+    #[test]
+    fn ttl_with_the_high_bit_set_is_zero() {
+        let mut response = response();
+        response.answers.truncate(2);
+        response.authorities.clear();
+        response.additionals.clear();
+        let mut bytes = response.to_bytes();
+        // 第二条记录 (A) 位于报文末尾，TTL 在 RDATA 之前 10 字节处
+        let ttl = bytes.len() - 4 - 6;
+        bytes[ttl] = 0x80;
+        let parsed = DnsMessage::parse(&bytes).unwrap();
+        assert_eq!(parsed.answers[0].ttl, 300);
+        assert_eq!(parsed.answers[1].ttl, 0);
+    }
+
+    #[test]
+    fn names_and_types() {
+        assert!(is_valid_name("example.com."));
+        assert!(is_valid_name(""));
+        assert!(!is_valid_name("example..com"));
+        assert!(!is_valid_name(&"a".repeat(64)));
+        assert!(is_valid_name(&vec!["a".repeat(63); 3].join(".")));
+        assert!(!is_valid_name(&vec!["a".repeat(63); 4].join(".")));
+        assert_eq!(
+            reverse_name(Ipv4Addr::new(192, 0, 2, 1)),
+            "1.2.0.192.in-addr.arpa"
+        );
+
+        for rtype in [
+            "a", "CNAME", "SOA", "PTR", "TXT", "AAAA", "SRV", "ANY", "TYPE99",
+        ] {
+            let parsed = DnsRecordType::from_name(rtype).unwrap();
+            assert_eq!(parsed.to_string(), rtype.to_ascii_uppercase());
+            assert_eq!(DnsRecordType::from_u16(parsed.to_u16()), parsed);
+        }
+        assert_eq!(DnsRecordType::from_name("MX?"), None);
+        assert_eq!(DnsResponseCode::from_u8(9).to_string(), "RCODE9");
+    }
+}
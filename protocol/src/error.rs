@@ -17,10 +17,8 @@ use std::{error, fmt};
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum IcmpParseError {
     IcmpLengthErr,
-    IcmpHeaderLengthNotMatch,
     IcmpPayloadLengthNotMatch,
-    InvalidIcmpHeader,
-    InvalidIcmpTime,
+    InvalidChecksum,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -37,6 +35,7 @@ pub enum Ipv4HeaderParseError {
     InvalidOffset, // do not support currently
     InvalidTimeToLive,
     InvalidChecksum,
+    InvalidOption,
 }
 
 impl fmt::Display for Ipv4HeaderParseError {
@@ -48,18 +47,121 @@ impl fmt::Display for Ipv4HeaderParseError {
             Self::InvalidTimeToLive => write!(f, "IPv4 TTL cannot be 0"),
             Self::InvalidFlags => write!(f, "IPv4 flags field is invalid"),
             Self::InvalidOffset => write!(f, "IPv4 fragment offset is invalid"),
+            Self::InvalidOption => write!(f, "IPv4 header options are malformed"),
         }
     }
 }
 
 impl error::Error for Ipv4HeaderParseError {}
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Ipv4FragmentError {
+    InvalidDatagram,
+    DontFragment,
+    MtuTooSmall,
+}
+
+impl fmt::Display for Ipv4FragmentError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidDatagram => write!(f, "IPv4 datagram is malformed"),
+            Self::DontFragment => write!(f, "IPv4 datagram exceeds MTU but DF is set"),
+            Self::MtuTooSmall => write!(f, "MTU is too small to carry any fragment data"),
+        }
+    }
+}
+
+impl error::Error for Ipv4FragmentError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Ipv6ParseError {
+    InvalidFormat,
+    InvalidSegment,
+    InvalidPrefixLength,
+}
+
+impl fmt::Display for Ipv6ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidFormat => {
+                write!(f, "IPv6 address format error, should be like 2001:db8::1")
+            }
+            Self::InvalidSegment => {
+                write!(f, "IPv6 address group error, should be 1-4 hex digits")
+            }
+            Self::InvalidPrefixLength => write!(f, "IPv6 prefix length should be in 0-128"),
+        }
+    }
+}
+
+impl error::Error for Ipv6ParseError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Ipv6HeaderParseError {
+    InvalidVersion,
+    InvalidHeaderLength,
+    InvalidPayloadLength,
+    InvalidExtensionHeader,
+    InvalidOption,
+}
+
+impl fmt::Display for Ipv6HeaderParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidVersion => write!(f, "IPv6 version must be 6"),
+            Self::InvalidHeaderLength => write!(f, "IPv6 header or extension header is truncated"),
+            Self::InvalidPayloadLength => write!(f, "IPv6 payload length is invalid"),
+            Self::InvalidExtensionHeader => write!(f, "IPv6 extension header chain is malformed"),
+            Self::InvalidOption => write!(f, "IPv6 extension header options are malformed"),
+        }
+    }
+}
+
+impl error::Error for Ipv6HeaderParseError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Icmpv6ParseError {
+    InvalidLength,
+    InvalidChecksum,
+    InvalidOption,
+}
+
+impl fmt::Display for Icmpv6ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Icmpv6ParseError::InvalidLength => write!(f, "ICMPv6 message is truncated"),
+            Icmpv6ParseError::InvalidChecksum => write!(f, "ICMPv6 checksum validation failed"),
+            Icmpv6ParseError::InvalidOption => write!(f, "ICMPv6 NDP options are malformed"),
+        }
+    }
+}
+
+impl error::Error for Icmpv6ParseError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IgmpParseError {
+    InvalidLength,
+    InvalidChecksum,
+}
+
+impl fmt::Display for IgmpParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IgmpParseError::InvalidLength => write!(f, "IGMP message is truncated"),
+            IgmpParseError::InvalidChecksum => write!(f, "IGMP checksum validation failed"),
+        }
+    }
+}
+
+impl error::Error for IgmpParseError {}
+
 #[derive(Debug, Clone)]
 pub struct MacParseError(pub Cow<'static, str>);
 
 #[derive(Debug, Clone)]
 pub enum EthernetParseError {
     PacketTooShort,
+    TruncatedVlanTag,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -101,3 +203,66 @@ impl fmt::Display for UdpParseError {
 }
 
 impl error::Error for UdpParseError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TcpParseError {
+    InvalidTcpLen,
+    InvalidDataOffset,
+    InvalidOption,
+    InvalidChecksum,
+}
+
+impl fmt::Display for TcpParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TcpParseError::InvalidTcpLen => write!(f, "TCP segment length is invalid"),
+            TcpParseError::InvalidDataOffset => write!(f, "TCP data offset is less than 5"),
+            TcpParseError::InvalidOption => write!(f, "TCP options are malformed"),
+            TcpParseError::InvalidChecksum => write!(f, "TCP checksum validation failed"),
+        }
+    }
+}
+
+impl error::Error for TcpParseError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DhcpParseError {
+    InvalidLength,
+    UnsupportedHardware,
+    InvalidMagicCookie,
+    InvalidOption,
+}
+
+impl fmt::Display for DhcpParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DhcpParseError::InvalidLength => write!(f, "DHCP message is shorter than 240 bytes"),
+            DhcpParseError::UnsupportedHardware => {
+                write!(f, "DHCP hardware type is not Ethernet")
+            }
+            DhcpParseError::InvalidMagicCookie => write!(f, "DHCP magic cookie is invalid"),
+            DhcpParseError::InvalidOption => write!(f, "DHCP options are malformed"),
+        }
+    }
+}
+
+impl error::Error for DhcpParseError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DnsParseError {
+    InvalidLength,
+    InvalidName,
+    InvalidRecord,
+}
+
+impl fmt::Display for DnsParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DnsParseError::InvalidLength => write!(f, "DNS message is truncated"),
+            DnsParseError::InvalidName => write!(f, "DNS name is malformed"),
+            DnsParseError::InvalidRecord => write!(f, "DNS resource record data is malformed"),
+        }
+    }
+}
+
+impl error::Error for DnsParseError {}
@@ -21,6 +21,10 @@ pub enum EtherType {
     Ipv4 = 0x0800,
     Arp = 0x0806,
     Ipv6 = 0x86DD,
+    /// IEEE 802.1Q 标签 (C-Tag)
+    Vlan = 0x8100,
+    /// IEEE 802.1ad 外层标签 (S-Tag)，用于 QinQ
+    QinQ = 0x88A8,
     Unknown(u16),
 }
 
@@ -30,6 +34,8 @@ impl From<u16> for EtherType {
             0x0800 => EtherType::Ipv4,
             0x0806 => EtherType::Arp,
             0x86DD => EtherType::Ipv6,
+            0x8100 => EtherType::Vlan,
+            0x88A8 => EtherType::QinQ,
             _ => EtherType::Unknown(val),
         }
     }
@@ -41,6 +47,8 @@ impl From<EtherType> for u16 {
             EtherType::Ipv4 => 0x0800,
             EtherType::Arp => 0x0806,
             EtherType::Ipv6 => 0x86DD,
+            EtherType::Vlan => 0x8100,
+            EtherType::QinQ => 0x88A8,
             EtherType::Unknown(v) => v,
         }
     }
@@ -52,15 +60,107 @@ impl fmt::Display for EtherType {
             EtherType::Ipv4 => write!(f, "IPv4 (0x0800)"),
             EtherType::Arp => write!(f, "ARP (0x0806)"),
             EtherType::Ipv6 => write!(f, "IPv6 (0x86DD)"),
+            EtherType::Vlan => write!(f, "802.1Q VLAN (0x8100)"),
+            EtherType::QinQ => write!(f, "802.1ad QinQ (0x88A8)"),
             EtherType::Unknown(v) => write!(f, "Unknown (0x{:04X})", v),
         }
     }
 }
 
+impl EtherType {
+    /// 是否是 VLAN 标签的 TPID，后面跟着 TCI 和下一个 EtherType
+    pub fn is_vlan_tag(&self) -> bool {
+        matches!(self, EtherType::Vlan | EtherType::QinQ)
+    }
+}
+
+/// VLAN 标签：TPID 之后的 16 位 TCI 由 PCP (3 位)、DEI (1 位) 和 VID (12 位) 组成
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VlanTag {
+    /// EtherType::Vlan 或 EtherType::QinQ
+    pub tpid: EtherType,
+    /// 优先级 (802.1p)，0-7
+    pub pcp: u8,
+    /// 拥塞时可以优先丢弃
+    pub dei: bool,
+    /// VLAN ID，0 表示只携带优先级，4095 保留
+    pub vid: u16,
+}
+
+impl VlanTag {
+    pub const LEN: usize = 4;
+    pub const MAX_VID: u16 = 4094;
+
+    /// 802.1Q 标签，优先级为 0；VID 超过 `MAX_VID` 时返回 None
+    pub fn new(vid: u16) -> Option<Self> {
+        (vid <= Self::MAX_VID).then_some(Self {
+            tpid: EtherType::Vlan,
+            pcp: 0,
+            dei: false,
+            vid,
+        })
+    }
+
+    /// 由外到内的多层标签：最内层用 802.1Q，外层用 802.1ad (QinQ)
+    pub fn stacked(vids: &[u16]) -> Option<Vec<Self>> {
+        let last = vids.len().saturating_sub(1);
+        vids.iter()
+            .enumerate()
+            .map(|(i, &vid)| {
+                let tpid = if i == last {
+                    EtherType::Vlan
+                } else {
+                    EtherType::QinQ
+                };
+                Some(Self {
+                    tpid,
+                    ..Self::new(vid)?
+                })
+            })
+            .collect()
+    }
+
+    pub fn tci(&self) -> u16 {
+        ((self.pcp as u16 & 0x07) << 13) | ((self.dei as u16) << 12) | (self.vid & 0x0FFF)
+    }
+
+    pub fn from_tci(tpid: EtherType, tci: u16) -> Self {
+        Self {
+            tpid,
+            pcp: (tci >> 13) as u8,
+            dei: tci & 0x1000 != 0,
+            vid: tci & 0x0FFF,
+        }
+    }
+
+    pub fn to_bytes(&self) -> [u8; 4] {
+        let tpid: u16 = self.tpid.into();
+        let [a, b] = tpid.to_be_bytes();
+        let [c, d] = self.tci().to_be_bytes();
+        [a, b, c, d]
+    }
+}
+
+impl fmt::Display for VlanTag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "VLAN {} (PCP {}, DEI {}, TPID 0x{:04X})",
+            self.vid,
+            self.pcp,
+            self.dei as u8,
+            u16::from(self.tpid)
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct EthernetHeader {
     pub dst: MacAddr,
     pub src: MacAddr,
+    /// 由外到内的 VLAN 标签，不带标签的帧为空
+    pub vlans: Vec<VlanTag>,
+    /// 最内层标签之后的 EtherType
     pub ethertype: EtherType,
 }
 
@@ -70,6 +170,9 @@ impl fmt::Display for EthernetParseError {
             EthernetParseError::PacketTooShort => {
                 write!(f, "Packet is too short to contain an Ethernet header")
             }
+            EthernetParseError::TruncatedVlanTag => {
+                write!(f, "VLAN tag is truncated")
+            }
         }
     }
 }
@@ -77,16 +180,21 @@ impl fmt::Display for EthernetParseError {
 impl std::error::Error for EthernetParseError {}
 
 impl EthernetHeader {
+    /// 不带 VLAN 标签的首部长度
     pub const LEN: usize = 14;
+    /// 不含 FCS 的最小帧长
+    pub const MIN_FRAME_LEN: usize = 60;
 
     pub fn new(src: MacAddr, dst: MacAddr, ethertype: EtherType) -> Self {
         Self {
             src,
             dst,
+            vlans: Vec::new(),
             ethertype,
         }
     }
 
+    /// 依次剥离 0x8100 / 0x88A8 标签，直到遇到其他 EtherType
     pub fn parse(data: &[u8]) -> Result<Self, EthernetParseError> {
         if data.len() < Self::LEN {
             return Err(EthernetParseError::PacketTooShort);
@@ -94,21 +202,67 @@ impl EthernetHeader {
 
         let dst = MacAddr::from_slice(&data[0..6]);
         let src = MacAddr::from_slice(&data[6..12]);
-        let ethertype_val = u16::from_be_bytes([data[12], data[13]]);
+        let mut ethertype = EtherType::from(u16::from_be_bytes([data[12], data[13]]));
+        let mut vlans = Vec::new();
+        let mut offset = 12;
+        while ethertype.is_vlan_tag() {
+            let Some(tag) = data.get(offset + 2..offset + 6) else {
+                return Err(EthernetParseError::TruncatedVlanTag);
+            };
+            vlans.push(VlanTag::from_tci(
+                ethertype,
+                u16::from_be_bytes([tag[0], tag[1]]),
+            ));
+            ethertype = EtherType::from(u16::from_be_bytes([tag[2], tag[3]]));
+            offset += VlanTag::LEN;
+        }
 
         Ok(Self {
             dst,
             src,
-            ethertype: EtherType::from(ethertype_val),
+            vlans,
+            ethertype,
         })
     }
 
-    pub fn to_bytes(&self) -> [u8; 14] {
-        let mut bytes = [0u8; 14];
+    /// 含 VLAN 标签的首部长度，载荷从这里开始
+    pub fn header_len(&self) -> usize {
+        Self::LEN + VlanTag::LEN * self.vlans.len()
+    }
+
+    /// 最小载荷长度：VLAN 标签计入最小帧长，每层标签可以少补 4 字节 (802.1Q 6.9)
+    pub fn min_payload_len(&self) -> usize {
+        Self::MIN_FRAME_LEN.saturating_sub(self.header_len())
+    }
+
+    /// 最内层标签的 VLAN ID
+    pub fn vlan_id(&self) -> Option<u16> {
+        self.vlans.last().map(|tag| tag.vid)
+    }
+
+    /// 不带 VLAN 标签的首部编码为定长数组，有标签时返回 None
+    pub fn to_untagged_bytes(&self) -> Option<[u8; Self::LEN]> {
+        if !self.vlans.is_empty() {
+            return None;
+        }
+        let mut bytes = [0u8; Self::LEN];
         bytes[0..6].copy_from_slice(self.dst.as_bytes());
         bytes[6..12].copy_from_slice(self.src.as_bytes());
         let ethertype_val: u16 = self.ethertype.into();
         bytes[12..14].copy_from_slice(&ethertype_val.to_be_bytes());
+        Some(bytes)
+    }
+
+    /// 编码首部，长度为 `header_len()`
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.header_len());
+        bytes.extend_from_slice(self.dst.as_bytes());
+        bytes.extend_from_slice(self.src.as_bytes());
+        for tag in &self.vlans {
+            bytes.extend_from_slice(&tag.to_bytes());
+        }
+        let ethertype_val: u16 = self.ethertype.into();
+        bytes.extend_from_slice(&ethertype_val.to_be_bytes());
         bytes
     }
 }
@@ -119,9 +273,133 @@ impl fmt::Display for EthernetHeader {
             f,
             "Ethernet Header:
     Source: {}
-    Destination: {}
-    EtherType: {}",
-            self.src, self.dst, self.ethertype
-        )
+    Destination: {}",
+            self.src, self.dst
+        )?;
+        for tag in &self.vlans {
+            write!(f, "\n    {}", tag)?;
+        }
+        write!(f, "\n    EtherType: {}", self.ethertype)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn untagged_encoder_matches_to_bytes() {
+        let src = MacAddr::from_raw([0x02, 0, 0, 0, 0, 1]);
+        let dst = MacAddr::broadcast();
+        let mut header = EthernetHeader::new(src, dst, EtherType::Arp);
+        let bytes = header.to_untagged_bytes().unwrap();
+        assert_eq!(bytes.to_vec(), header.to_bytes());
+        assert_eq!(&bytes[12..], &[0x08, 0x06]);
+        assert_eq!(EthernetHeader::parse(&bytes).unwrap(), header);
+
+        header.vlans = vec![VlanTag::new(100).unwrap()];
+        assert_eq!(header.to_untagged_bytes(), None);
+        assert_eq!(header.to_bytes().len(), 18);
+    }
+
+    #[test]
+    fn tci_packs_pcp_dei_and_vid() {
+        let tag = VlanTag {
+            tpid: EtherType::Vlan,
+            pcp: 5,
+            dei: true,
+            vid: 0x123,
+        };
+        assert_eq!(tag.tci(), 0xb123);
+        assert_eq!(tag.to_bytes(), [0x81, 0x00, 0xb1, 0x23]);
+        assert_eq!(VlanTag::from_tci(EtherType::Vlan, 0xb123), tag);
+        assert_eq!(VlanTag::from_tci(EtherType::Vlan, 0xffff).vid, 0xfff);
+        // VID 只有 12 位，4095 保留
+        assert_eq!(VlanTag::new(0).map(|tag| tag.tci()), Some(0));
+        assert_eq!(VlanTag::new(4094).map(|tag| tag.vid), Some(4094));
+        assert_eq!(VlanTag::new(4095), None);
+        assert_eq!(VlanTag::new(0x1064), None);
+    }
+
+    #[test]
+    fn tags_count_towards_the_minimum_frame() {
+        let mut header = EthernetHeader::new(MacAddr::zero(), MacAddr::zero(), EtherType::Ipv4);
+        assert_eq!(header.min_payload_len(), 46);
+        header.vlans = VlanTag::stacked(&[100]).unwrap();
+        assert_eq!(header.min_payload_len(), 42);
+        header.vlans = VlanTag::stacked(&[200, 100]).unwrap();
+        assert_eq!(header.min_payload_len(), 38);
+    }
+
+    #[test]
+    fn stacked_tags_use_802_1ad_outside() {
+        assert_eq!(VlanTag::stacked(&[]), Some(Vec::new()));
+        assert_eq!(
+            VlanTag::stacked(&[10]),
+            Some(vec![VlanTag::new(10).unwrap()])
+        );
+        assert_eq!(VlanTag::stacked(&[100, 4095]), None);
+        let tags = VlanTag::stacked(&[100, 10]).unwrap();
+        assert_eq!(tags[0].tpid, EtherType::QinQ);
+        assert_eq!(tags[1].tpid, EtherType::Vlan);
+        assert_eq!(
+            tags.iter().map(|tag| tag.vid).collect::<Vec<_>>(),
+            [100, 10]
+        );
+        assert!(EtherType::QinQ.is_vlan_tag() && !EtherType::Ipv4.is_vlan_tag());
+    }
+
+    #[test]
+    fn qinq_frames_round_trip() {
+        let mut header = EthernetHeader::new(
+            MacAddr::from_raw([0x02, 0, 0, 0, 0, 1]),
+            MacAddr::broadcast(),
+            EtherType::Ipv4,
+        );
+        header.vlans = VlanTag::stacked(&[100, 10]).unwrap();
+        header.vlans[1].pcp = 3;
+
+        let mut bytes = header.to_bytes();
+        assert_eq!(bytes.len(), header.header_len());
+        assert_eq!(bytes.len(), 22);
+        assert_eq!(
+            &bytes[12..],
+            &[0x88, 0xa8, 0x00, 0x64, 0x81, 0x00, 0x60, 0x0a, 0x08, 0x00]
+        );
+
+        bytes.extend_from_slice(&[0x45; 20]);
+        let parsed = EthernetHeader::parse(&bytes).unwrap();
+        assert_eq!(parsed, header);
+        assert_eq!(parsed.vlan_id(), Some(10));
+        assert_eq!(&bytes[parsed.header_len()..], &[0x45; 20]);
+
+        // 0x9100 等非标准 TPID 不当作标签
+        bytes[12..14].copy_from_slice(&[0x91, 0x00]);
+        let parsed = EthernetHeader::parse(&bytes).unwrap();
+        assert!(parsed.vlans.is_empty());
+        assert_eq!(parsed.ethertype, EtherType::Unknown(0x9100));
+        assert_eq!(parsed.vlan_id(), None);
+    }
+
+    #[test]
+    fn truncated_frames_are_rejected() {
+        assert!(matches!(
+            EthernetHeader::parse(&[0; 13]),
+            Err(EthernetParseError::PacketTooShort)
+        ));
+        let mut header = EthernetHeader::new(MacAddr::zero(), MacAddr::zero(), EtherType::Arp);
+        header.vlans = VlanTag::stacked(&[100, 10]).unwrap();
+        let bytes = header.to_bytes();
+        // 缺少内层标签，或者最后一个标签后没有 EtherType
+        for len in [14, 17, 18, 21] {
+            assert!(
+                matches!(
+                    EthernetHeader::parse(&bytes[..len]),
+                    Err(EthernetParseError::TruncatedVlanTag)
+                ),
+                "{}",
+                len
+            );
+        }
     }
 }
@@ -16,266 +16,617 @@ use std::time::{SystemTime, UNIX_EPOCH};
 
 use crate::checksum::simple_checksum;
 use crate::error::IcmpParseError;
-
-#[derive(Clone, Copy, Debug, Hash)]
-/// 标准的ICMP协议首部，长度固定为8字节
-pub struct ICMPHeader {
-    pub type_: u8,
-    pub code: u8,
-    pub checksum: u16,
-    pub id: u16,
-    pub seq: u16,
+use crate::ipv4::Ipv4Addr;
+
+// ICMP 报文类型号 (RFC 792)
+pub const ICMP_ECHO_REPLY: u8 = 0;
+pub const ICMP_DEST_UNREACHABLE: u8 = 3;
+pub const ICMP_REDIRECT: u8 = 5;
+pub const ICMP_ECHO_REQUEST: u8 = 8;
+pub const ICMP_TIME_EXCEEDED: u8 = 11;
+pub const ICMP_PARAMETER_PROBLEM: u8 = 12;
+pub const ICMP_TIMESTAMP: u8 = 13;
+pub const ICMP_TIMESTAMP_REPLY: u8 = 14;
+
+/// 是否为差错报文类型，差错报文不应再触发新的差错报文 (RFC 1122 3.2.2)
+pub fn is_error_type(type_: u8) -> bool {
+    // 3 = Destination Unreachable, 4 = Source Quench, 5 = Redirect,
+    // 11 = Time Exceeded, 12 = Parameter Problem
+    matches!(type_, 3 | 4 | 5 | 11 | 12)
 }
 
+/// Destination Unreachable 的 code 字段 (RFC 792 / RFC 1122 / RFC 1812)
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub enum IcmpType {
-    Request,
-    Reply,
-    Unknown,
+pub enum DestUnreachableCode {
+    NetUnreachable,
+    HostUnreachable,
+    ProtocolUnreachable,
+    PortUnreachable,
+    /// 需要分片但设置了 DF，报文中携带下一跳 MTU (RFC 1191)
+    FragmentationNeeded,
+    SourceRouteFailed,
+    NetUnknown,
+    HostUnknown,
+    SourceHostIsolated,
+    NetProhibited,
+    HostProhibited,
+    NetUnreachableForTos,
+    HostUnreachableForTos,
+    AdminProhibited,
+    HostPrecedenceViolation,
+    PrecedenceCutoff,
+    Unknown(u8),
 }
 
-impl IcmpType {
-    pub fn type_code(self) -> u8 {
+impl DestUnreachableCode {
+    pub fn code(self) -> u8 {
         match self {
-            IcmpType::Request => 0x8,
-            IcmpType::Reply => 0x0,
-            IcmpType::Unknown => 0x1,
+            Self::NetUnreachable => 0,
+            Self::HostUnreachable => 1,
+            Self::ProtocolUnreachable => 2,
+            Self::PortUnreachable => 3,
+            Self::FragmentationNeeded => 4,
+            Self::SourceRouteFailed => 5,
+            Self::NetUnknown => 6,
+            Self::HostUnknown => 7,
+            Self::SourceHostIsolated => 8,
+            Self::NetProhibited => 9,
+            Self::HostProhibited => 10,
+            Self::NetUnreachableForTos => 11,
+            Self::HostUnreachableForTos => 12,
+            Self::AdminProhibited => 13,
+            Self::HostPrecedenceViolation => 14,
+            Self::PrecedenceCutoff => 15,
+            Self::Unknown(code) => code,
         }
     }
 
-    pub fn parse(type_: u8) -> IcmpType {
-        match type_ {
-            0x8 => IcmpType::Request,
-            0x0 => IcmpType::Reply,
-            _ => IcmpType::Unknown,
+    pub fn parse(code: u8) -> Self {
+        match code {
+            0 => Self::NetUnreachable,
+            1 => Self::HostUnreachable,
+            2 => Self::ProtocolUnreachable,
+            3 => Self::PortUnreachable,
+            4 => Self::FragmentationNeeded,
+            5 => Self::SourceRouteFailed,
+            6 => Self::NetUnknown,
+            7 => Self::HostUnknown,
+            8 => Self::SourceHostIsolated,
+            9 => Self::NetProhibited,
+            10 => Self::HostProhibited,
+            11 => Self::NetUnreachableForTos,
+            12 => Self::HostUnreachableForTos,
+            13 => Self::AdminProhibited,
+            14 => Self::HostPrecedenceViolation,
+            15 => Self::PrecedenceCutoff,
+            other => Self::Unknown(other),
         }
     }
 }
 
-impl ICMPHeader {
-    pub fn new(type_: IcmpType, code: u8, id: u16, seq: u16) -> Self {
-        Self {
-            type_: type_.type_code(),
-            code,
-            checksum: 0x0,
-            id,
-            seq,
-        }
-    }
-
-    pub fn set_checksum(&mut self, checksum: u16) {
-        self.checksum = checksum;
-    }
+/// Time Exceeded 的 code 字段
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeExceededCode {
+    /// 传输中 TTL 耗尽
+    TtlExceeded,
+    /// 分片重组超时
+    FragmentReassembly,
+    Unknown(u8),
+}
 
-    pub fn to_bytes(&self) -> [u8; 8] {
-        let [c0, c1] = self.checksum.to_be_bytes();
-        let [i0, i1] = self.id.to_be_bytes();
-        let [s0, s1] = self.seq.to_be_bytes();
-        [self.type_, self.code, c0, c1, i0, i1, s0, s1]
+impl TimeExceededCode {
+    pub fn code(self) -> u8 {
+        match self {
+            Self::TtlExceeded => 0,
+            Self::FragmentReassembly => 1,
+            Self::Unknown(code) => code,
+        }
     }
 
-    pub fn validate(&self) -> Result<(), IcmpParseError> {
-        match self.type_ {
-            0x00 | 0x08 => {}
-            _ => return Err(IcmpParseError::InvalidIcmpHeader),
-        }
-        match self.code {
-            0x00 => Ok(()),
-            _ => Err(IcmpParseError::InvalidIcmpHeader),
+    pub fn parse(code: u8) -> Self {
+        match code {
+            0 => Self::TtlExceeded,
+            1 => Self::FragmentReassembly,
+            other => Self::Unknown(other),
         }
     }
 }
 
-impl TryFrom<Vec<u8>> for ICMPHeader {
-    type Error = IcmpParseError;
-
-    fn try_from(value: Vec<u8>) -> Result<Self, Self::Error> {
-        if value.len() != 8 {
-            // 首部的长度不正确，不为8字节
-            Err(IcmpParseError::IcmpHeaderLengthNotMatch)
-        } else {
-            // 尝试解析为首部
-            let [type_, code, c0, c1, i0, i1, s0, s1]: [u8; 8] = value
-                .try_into()
-                .map_err(|_| IcmpParseError::IcmpHeaderLengthNotMatch)?;
+/// Parameter Problem 的 code 字段 (RFC 792 / RFC 1122)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParameterProblemCode {
+    /// pointer 指向出错的字节
+    Pointer,
+    MissingOption,
+    BadLength,
+    Unknown(u8),
+}
 
-            // 这里不检查字段值是否正确，只是尝试解析，字段检查交给另外的函数
+impl ParameterProblemCode {
+    pub fn code(self) -> u8 {
+        match self {
+            Self::Pointer => 0,
+            Self::MissingOption => 1,
+            Self::BadLength => 2,
+            Self::Unknown(code) => code,
+        }
+    }
 
-            Ok(Self {
-                type_,
-                code,
-                checksum: u16::from_be_bytes([c0, c1]),
-                id: u16::from_be_bytes([i0, i1]),
-                seq: u16::from_be_bytes([s0, s1]),
-            })
+    pub fn parse(code: u8) -> Self {
+        match code {
+            0 => Self::Pointer,
+            1 => Self::MissingOption,
+            2 => Self::BadLength,
+            other => Self::Unknown(other),
         }
     }
 }
 
-#[derive(Debug, Clone)]
-pub struct ICMPPayload {
-    pub time: u32,
-    pub payload: Vec<u8>,
+/// Redirect 的 code 字段
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RedirectCode {
+    Network,
+    Host,
+    TosNetwork,
+    TosHost,
+    Unknown(u8),
 }
 
-impl ICMPPayload {
-    /// 纯构造函数：将传入的时间戳和负载数据封装为 ICMPPayload
-    /// payload 参数使用 &[u8]，内部会进行拷贝以获得所有权
-    pub fn new(time: u32, payload: &[u8]) -> Self {
-        Self {
-            time,
-            payload: payload.to_vec(),
+impl RedirectCode {
+    pub fn code(self) -> u8 {
+        match self {
+            Self::Network => 0,
+            Self::Host => 1,
+            Self::TosNetwork => 2,
+            Self::TosHost => 3,
+            Self::Unknown(code) => code,
         }
     }
 
-    pub fn to_bytes(&self) -> Vec<u8> {
-        let mut bytes = Vec::with_capacity(4 + self.payload.len());
-        bytes.extend_from_slice(&self.time.to_be_bytes());
-        bytes.extend_from_slice(&self.payload);
-        bytes
-    }
-
-    pub fn validate(&self) -> Result<(), IcmpParseError> {
-        // if self.time
-        //     > SystemTime::now()
-        //         .duration_since(UNIX_EPOCH)
-        //         .expect("Time went backwards")
-        //         .as_millis() as u32
-        // {
-        //     Err(IcmpParseError::InvalidIcmpTime)
-        // } else {
-        //     Ok(())
-        // }
-        // Linux中，内核一般不会检查payload的时间是否合理，只有在上层应用中才会检查
-        Ok(())
+    pub fn parse(code: u8) -> Self {
+        match code {
+            0 => Self::Network,
+            1 => Self::Host,
+            2 => Self::TosNetwork,
+            3 => Self::TosHost,
+            other => Self::Unknown(other),
+        }
     }
 }
 
-impl TryFrom<Vec<u8>> for ICMPPayload {
-    type Error = IcmpParseError;
-
-    fn try_from(value: Vec<u8>) -> Result<Self, Self::Error> {
-        if value.len() < 4 {
-            // 载荷的长度不正确，小于4字节，不包含我们需要的时间戳
-            Err(IcmpParseError::IcmpPayloadLengthNotMatch)
-        } else {
-            // 这里不检查字段值是否正确，只是尝试解析，字段检查交给另外的函数
-            let time = u32::from_be_bytes(
-                value[0..4]
-                    .try_into()
-                    .map_err(|_| IcmpParseError::IcmpPayloadLengthNotMatch)?,
-            );
-            Ok(Self {
-                time,
-                payload: value[4..].to_vec(),
-            })
-        }
-    }
+/// Echo Request/Reply 的报文体，data 原样保存，不假定任何格式
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Echo {
+    pub id: u16,
+    pub seq: u16,
+    pub data: Vec<u8>,
 }
 
-#[derive(Clone, Debug)]
-pub struct ICMP {
-    pub header: ICMPHeader,
-    pub data: ICMPPayload,
+/// Timestamp Request/Reply 的报文体，时间为 UT 零点起的毫秒数
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Timestamp {
+    pub id: u16,
+    pub seq: u16,
+    pub originate: u32,
+    pub receive: u32,
+    pub transmit: u32,
 }
 
-impl ICMP {
-    /// 构造一个ICMP报文时，必须传入构造的首部参数和载荷内容
-    pub fn new(type_: IcmpType, code: u8, id: u16, seq: u16, time: u32, payload: &[u8]) -> Self {
-        let mut header = ICMPHeader::new(type_, code, id, seq);
-        let payload = ICMPPayload::new(time, payload);
+/// 一个完整的 ICMP 报文
+///
+/// 差错报文的 original 为引发差错的原始 IP 首部 + 至少 8 字节载荷。
+/// 类型或 code 不认识的报文保存在 Unknown 中，编码时原样输出。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IcmpMessage {
+    EchoReply(Echo),
+    DestUnreachable {
+        code: DestUnreachableCode,
+        /// 只在 FragmentationNeeded 时有意义 (RFC 1191)
+        next_hop_mtu: u16,
+        original: Vec<u8>,
+    },
+    Redirect {
+        code: RedirectCode,
+        gateway: Ipv4Addr,
+        original: Vec<u8>,
+    },
+    EchoRequest(Echo),
+    TimeExceeded {
+        code: TimeExceededCode,
+        original: Vec<u8>,
+    },
+    ParameterProblem {
+        code: ParameterProblemCode,
+        pointer: u8,
+        original: Vec<u8>,
+    },
+    Timestamp(Timestamp),
+    TimestampReply(Timestamp),
+    Unknown {
+        type_: u8,
+        code: u8,
+        /// 首部第 4-7 字节
+        rest: [u8; 4],
+        data: Vec<u8>,
+    },
+}
 
-        // get raw datas
-        let header_raw = &header.to_bytes();
-        let payload_raw = &payload.to_bytes();
+impl IcmpMessage {
+    pub fn type_(&self) -> u8 {
+        match self {
+            Self::EchoReply(_) => ICMP_ECHO_REPLY,
+            Self::DestUnreachable { .. } => ICMP_DEST_UNREACHABLE,
+            Self::Redirect { .. } => ICMP_REDIRECT,
+            Self::EchoRequest(_) => ICMP_ECHO_REQUEST,
+            Self::TimeExceeded { .. } => ICMP_TIME_EXCEEDED,
+            Self::ParameterProblem { .. } => ICMP_PARAMETER_PROBLEM,
+            Self::Timestamp(_) => ICMP_TIMESTAMP,
+            Self::TimestampReply(_) => ICMP_TIMESTAMP_REPLY,
+            Self::Unknown { type_, .. } => *type_,
+        }
+    }
 
-        // get total raw content
-        let mut raw_cont = Vec::with_capacity(header_raw.len() + payload_raw.len());
-        raw_cont.extend_from_slice(header_raw);
-        raw_cont.extend_from_slice(payload_raw);
+    pub fn code(&self) -> u8 {
+        match self {
+            Self::DestUnreachable { code, .. } => code.code(),
+            Self::Redirect { code, .. } => code.code(),
+            Self::TimeExceeded { code, .. } => code.code(),
+            Self::ParameterProblem { code, .. } => code.code(),
+            Self::Unknown { code, .. } => *code,
+            _ => 0,
+        }
+    }
 
-        // calculate simple sum and set to header
-        header.set_checksum(simple_checksum(&raw_cont));
+    pub fn is_error(&self) -> bool {
+        is_error_type(self.type_())
+    }
 
-        Self {
-            header,
-            data: payload,
+    /// 差错报文引用的原始数据报
+    pub fn original(&self) -> Option<&[u8]> {
+        match self {
+            Self::DestUnreachable { original, .. }
+            | Self::Redirect { original, .. }
+            | Self::TimeExceeded { original, .. }
+            | Self::ParameterProblem { original, .. } => Some(original),
+            _ => None,
         }
     }
 
-    pub fn parse(value: &[u8]) -> Result<Self, IcmpParseError> {
-        if value.len() < 8 + 4 {
-            Err(IcmpParseError::IcmpLengthErr)
-        } else {
-            let head = ICMPHeader::try_from(value[0..8].to_vec())?;
-            let payload = ICMPPayload::try_from(value[8..].to_vec())?;
-            let tmp = Self {
-                header: head,
-                data: payload,
-            };
-            tmp.validate()?;
-            Ok(tmp)
+    /// 解析并校验一个 ICMP 报文 (含校验和)
+    pub fn parse(bytes: &[u8]) -> Result<Self, IcmpParseError> {
+        if bytes.len() < 8 {
+            return Err(IcmpParseError::IcmpLengthErr);
         }
+        if simple_checksum(bytes) != 0 {
+            return Err(IcmpParseError::InvalidChecksum);
+        }
+
+        let type_ = bytes[0];
+        let code = bytes[1];
+        let rest: [u8; 4] = [bytes[4], bytes[5], bytes[6], bytes[7]];
+        let id = u16::from_be_bytes([rest[0], rest[1]]);
+        let seq = u16::from_be_bytes([rest[2], rest[3]]);
+        let data = &bytes[8..];
+
+        let message = match (type_, code) {
+            (ICMP_ECHO_REPLY, 0) => Self::EchoReply(Echo {
+                id,
+                seq,
+                data: data.to_vec(),
+            }),
+            (ICMP_ECHO_REQUEST, 0) => Self::EchoRequest(Echo {
+                id,
+                seq,
+                data: data.to_vec(),
+            }),
+            (ICMP_DEST_UNREACHABLE, _) => Self::DestUnreachable {
+                code: DestUnreachableCode::parse(code),
+                next_hop_mtu: seq,
+                original: data.to_vec(),
+            },
+            (ICMP_REDIRECT, _) => Self::Redirect {
+                code: RedirectCode::parse(code),
+                gateway: Ipv4Addr::from_octets(rest),
+                original: data.to_vec(),
+            },
+            (ICMP_TIME_EXCEEDED, _) => Self::TimeExceeded {
+                code: TimeExceededCode::parse(code),
+                original: data.to_vec(),
+            },
+            (ICMP_PARAMETER_PROBLEM, _) => Self::ParameterProblem {
+                code: ParameterProblemCode::parse(code),
+                pointer: rest[0],
+                original: data.to_vec(),
+            },
+            (ICMP_TIMESTAMP | ICMP_TIMESTAMP_REPLY, 0) => {
+                let [o0, o1, o2, o3, r0, r1, r2, r3, t0, t1, t2, t3]: [u8; 12] = data
+                    .try_into()
+                    .map_err(|_| IcmpParseError::IcmpPayloadLengthNotMatch)?;
+                let timestamp = Timestamp {
+                    id,
+                    seq,
+                    originate: u32::from_be_bytes([o0, o1, o2, o3]),
+                    receive: u32::from_be_bytes([r0, r1, r2, r3]),
+                    transmit: u32::from_be_bytes([t0, t1, t2, t3]),
+                };
+                if type_ == ICMP_TIMESTAMP {
+                    Self::Timestamp(timestamp)
+                } else {
+                    Self::TimestampReply(timestamp)
+                }
+            }
+            _ => Self::Unknown {
+                type_,
+                code,
+                rest,
+                data: data.to_vec(),
+            },
+        };
+        Ok(message)
     }
 
+    /// 编码为字节并填好校验和
     pub fn to_bytes(&self) -> Vec<u8> {
-        let mut bytes =
-            Vec::with_capacity(self.header.to_bytes().len() + self.data.to_bytes().len());
-        bytes.extend_from_slice(&self.header.to_bytes());
-        bytes.extend_from_slice(&self.data.to_bytes());
+        let (rest, data): ([u8; 4], &[u8]) = match self {
+            Self::EchoReply(echo) | Self::EchoRequest(echo) => {
+                let [i0, i1] = echo.id.to_be_bytes();
+                let [s0, s1] = echo.seq.to_be_bytes();
+                ([i0, i1, s0, s1], &echo.data)
+            }
+            Self::DestUnreachable {
+                code,
+                next_hop_mtu,
+                original,
+            } => {
+                let [m0, m1] = match code {
+                    DestUnreachableCode::FragmentationNeeded => next_hop_mtu.to_be_bytes(),
+                    _ => [0, 0],
+                };
+                ([0, 0, m0, m1], original)
+            }
+            Self::Redirect {
+                gateway, original, ..
+            } => (gateway.octets(), original),
+            Self::TimeExceeded { original, .. } => ([0; 4], original),
+            Self::ParameterProblem {
+                pointer, original, ..
+            } => ([*pointer, 0, 0, 0], original),
+            Self::Timestamp(ts) | Self::TimestampReply(ts) => {
+                let [i0, i1] = ts.id.to_be_bytes();
+                let [s0, s1] = ts.seq.to_be_bytes();
+                ([i0, i1, s0, s1], &[])
+            }
+            Self::Unknown { rest, data, .. } => (*rest, data),
+        };
+
+        let mut bytes = Vec::with_capacity(8 + data.len() + 12);
+        bytes.extend_from_slice(&[self.type_(), self.code(), 0, 0]);
+        bytes.extend_from_slice(&rest);
+        bytes.extend_from_slice(data);
+
+        if let Self::Timestamp(ts) | Self::TimestampReply(ts) = self {
+            bytes.extend_from_slice(&ts.originate.to_be_bytes());
+            bytes.extend_from_slice(&ts.receive.to_be_bytes());
+            bytes.extend_from_slice(&ts.transmit.to_be_bytes());
+        }
+
+        let checksum = simple_checksum(&bytes);
+        bytes[2..4].copy_from_slice(&checksum.to_be_bytes());
         bytes
     }
+}
 
-    pub fn validate(&self) -> Result<(), IcmpParseError> {
-        self.header.validate()?;
-        self.data.validate()?;
-        Ok(())
+impl fmt::Display for IcmpMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::EchoRequest(echo) => write!(
+                f,
+                "ICMP Echo Request: id={}, seq={}, payload_len={}",
+                echo.id,
+                echo.seq,
+                echo.data.len()
+            ),
+            Self::EchoReply(echo) => write!(
+                f,
+                "ICMP Echo Reply: id={}, seq={}, payload_len={}",
+                echo.id,
+                echo.seq,
+                echo.data.len()
+            ),
+            Self::DestUnreachable {
+                code, next_hop_mtu, ..
+            } => match code {
+                DestUnreachableCode::FragmentationNeeded => write!(
+                    f,
+                    "ICMP Destination Unreachable: {:?}, next_hop_mtu={}",
+                    code, next_hop_mtu
+                ),
+                _ => write!(f, "ICMP Destination Unreachable: {:?}", code),
+            },
+            Self::Redirect { code, gateway, .. } => {
+                write!(f, "ICMP Redirect: {:?}, gateway={}", code, gateway)
+            }
+            Self::TimeExceeded { code, .. } => write!(f, "ICMP Time Exceeded: {:?}", code),
+            Self::ParameterProblem { code, pointer, .. } => {
+                write!(f, "ICMP Parameter Problem: {:?}, pointer={}", code, pointer)
+            }
+            Self::Timestamp(ts) => write!(
+                f,
+                "ICMP Timestamp Request: id={}, seq={}, originate={}",
+                ts.id, ts.seq, ts.originate
+            ),
+            Self::TimestampReply(ts) => write!(
+                f,
+                "ICMP Timestamp Reply: id={}, seq={}, originate={}, receive={}, transmit={}",
+                ts.id, ts.seq, ts.originate, ts.receive, ts.transmit
+            ),
+            Self::Unknown { type_, code, .. } => {
+                write!(f, "ICMP Unknown Type: type={}, code={}", type_, code)
+            }
+        }
     }
 }
 
-impl TryFrom<Vec<u8>> for ICMP {
-    type Error = IcmpParseError;
+/// 当前时间，单位为 UT 零点起的毫秒数，用于 Timestamp 报文 (RFC 792)
+pub fn ms_since_midnight_ut() -> u32 {
+    let millis = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Time went backwards")
+        .as_millis();
+    (millis % (24 * 60 * 60 * 1000)) as u32
+}
 
-    fn try_from(value: Vec<u8>) -> Result<Self, Self::Error> {
-        Self::parse(value.as_slice())
+/// 构造 ICMP Destination Unreachable 差错报文 (RFC 792)
+/// next_hop_mtu 只在 FragmentationNeeded 时填入，其余 code 下该字段为 0
+/// quoted: 引发差错的原始 IP 首部 + 至少 8 字节载荷
+pub fn build_dest_unreachable(
+    code: DestUnreachableCode,
+    next_hop_mtu: u16,
+    quoted: &[u8],
+) -> Vec<u8> {
+    IcmpMessage::DestUnreachable {
+        code,
+        next_hop_mtu,
+        original: quoted.to_vec(),
     }
+    .to_bytes()
 }
 
-impl fmt::Display for ICMP {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let icmp_type = IcmpType::parse(self.header.type_);
-        match icmp_type {
-            IcmpType::Request => {
-                write!(
-                    f,
-                    "ICMP Echo Request: id={}, seq={}, payload_len={}",
-                    self.header.id,
-                    self.header.seq,
-                    self.data.payload.len()
-                )
-            }
-            IcmpType::Reply => {
-                let current_time = SystemTime::now()
-                    .duration_since(UNIX_EPOCH)
-                    .expect("Time went backwards")
-                    .as_millis() as u32;
-                let rtt = current_time.saturating_sub(self.data.time);
-                write!(
-                    f,
-                    "ICMP Echo Reply: id={}, seq={}, rtt={}ms, payload_len={}",
-                    self.header.id,
-                    self.header.seq,
-                    rtt,
-                    self.data.payload.len()
-                )
-            }
-            IcmpType::Unknown => {
-                write!(
-                    f,
-                    "ICMP Unknown Type: type={}, code={}, id={}, seq={}",
-                    self.header.type_, self.header.code, self.header.id, self.header.seq
-                )
-            }
+/// 构造 ICMP Time Exceeded 差错报文 (RFC 792)
+/// quoted: 引发差错的原始 IP 首部 + 至少 8 字节载荷
+pub fn build_time_exceeded(code: TimeExceededCode, quoted: &[u8]) -> Vec<u8> {
+    IcmpMessage::TimeExceeded {
+        code,
+        original: quoted.to_vec(),
+    }
+    .to_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn quote() -> Vec<u8> {
+        (0..28).collect()
+    }
+
+    #[test]
+    fn messages_round_trip() {
+        let messages = [
+            IcmpMessage::EchoRequest(Echo {
+                id: 0x1234,
+                seq: 7,
+                data: b"odd length".to_vec(),
+            }),
+            IcmpMessage::EchoReply(Echo {
+                id: 0x1234,
+                seq: 7,
+                data: Vec::new(),
+            }),
+            IcmpMessage::DestUnreachable {
+                code: DestUnreachableCode::FragmentationNeeded,
+                next_hop_mtu: 1400,
+                original: quote(),
+            },
+            IcmpMessage::DestUnreachable {
+                code: DestUnreachableCode::PortUnreachable,
+                next_hop_mtu: 0,
+                original: quote(),
+            },
+            IcmpMessage::Redirect {
+                code: RedirectCode::Host,
+                gateway: Ipv4Addr::new(10, 0, 0, 254),
+                original: quote(),
+            },
+            IcmpMessage::TimeExceeded {
+                code: TimeExceededCode::FragmentReassembly,
+                original: quote(),
+            },
+            IcmpMessage::ParameterProblem {
+                code: ParameterProblemCode::Pointer,
+                pointer: 22,
+                original: quote(),
+            },
+            IcmpMessage::Timestamp(Timestamp {
+                id: 1,
+                seq: 2,
+                originate: 3,
+                receive: 0,
+                transmit: 0,
+            }),
+            IcmpMessage::TimestampReply(Timestamp {
+                id: 1,
+                seq: 2,
+                originate: 3,
+                receive: 4,
+                transmit: 5,
+            }),
+            IcmpMessage::Unknown {
+                type_: 42,
+                code: 9,
+                rest: [1, 2, 3, 4],
+                data: vec![5, 6, 7],
+            },
+        ];
+        for message in messages {
+            let bytes = message.to_bytes();
+            assert_eq!(simple_checksum(&bytes), 0, "{}", message);
+            assert_eq!(IcmpMessage::parse(&bytes), Ok(message));
         }
     }
+
+    #[test]
+    fn wire_layout_matches_rfc_792() {
+        let bytes =
+            build_dest_unreachable(DestUnreachableCode::FragmentationNeeded, 1400, &quote());
+        assert_eq!(&bytes[..2], &[3, 4]);
+        assert_eq!(&bytes[4..8], &[0, 0, 0x05, 0x78]);
+        assert_eq!(&bytes[8..], &quote()[..]);
+
+        // 其他 code 下 next hop MTU 字段必须为 0
+        let bytes = build_dest_unreachable(DestUnreachableCode::HostUnreachable, 1400, &quote());
+        assert_eq!(&bytes[4..8], &[0; 4]);
+
+        let bytes = build_time_exceeded(TimeExceededCode::TtlExceeded, &quote());
+        assert_eq!(&bytes[..2], &[11, 0]);
+        assert!(is_error_type(bytes[0]));
+        assert!(!is_error_type(ICMP_ECHO_REQUEST));
+    }
+
+    #[test]
+    fn malformed_messages_are_rejected() {
+        let mut bytes = IcmpMessage::EchoRequest(Echo {
+            id: 1,
+            seq: 1,
+            data: vec![0; 4],
+        })
+        .to_bytes();
+        assert_eq!(
+            IcmpMessage::parse(&bytes[..7]),
+            Err(IcmpParseError::IcmpLengthErr)
+        );
+        bytes[9] ^= 0xff;
+        assert_eq!(
+            IcmpMessage::parse(&bytes),
+            Err(IcmpParseError::InvalidChecksum)
+        );
+
+        // Timestamp 报文体必须正好 12 字节
+        let mut bytes = IcmpMessage::Timestamp(Timestamp {
+            id: 1,
+            seq: 1,
+            originate: 0,
+            receive: 0,
+            transmit: 0,
+        })
+        .to_bytes();
+        bytes.truncate(16);
+        bytes[2..4].copy_from_slice(&[0, 0]);
+        let checksum = simple_checksum(&bytes);
+        bytes[2..4].copy_from_slice(&checksum.to_be_bytes());
+        assert_eq!(
+            IcmpMessage::parse(&bytes),
+            Err(IcmpParseError::IcmpPayloadLengthNotMatch)
+        );
+    }
 }
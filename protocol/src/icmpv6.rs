@@ -0,0 +1,1146 @@
+// Copyright (C) 2025 rrrrrzy
+// SPDX-License-Identifier: GPL-3.0-or-later
+//
+// --------------------------------------------------
+// 致敬所有在深夜调试代码的灵魂。
+// 即便 Bug 如山，我亦往矣。
+// --------------------------------------------------
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+//! ICMPv6 (RFC 4443) 与邻居发现 NDP (RFC 4861) 报文，以及 IPv6 的邻居缓存
+
+use std::collections::HashMap;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use crate::checksum::simple_checksum;
+use crate::error::Icmpv6ParseError;
+use crate::icmp::Echo;
+use crate::ipv6::{self, IPV6_NEXT_ICMPV6, Ipv6Addr};
+use crate::mac::MacAddr;
+
+pub const ICMPV6_DEST_UNREACHABLE: u8 = 1;
+pub const ICMPV6_PACKET_TOO_BIG: u8 = 2;
+pub const ICMPV6_TIME_EXCEEDED: u8 = 3;
+pub const ICMPV6_PARAMETER_PROBLEM: u8 = 4;
+pub const ICMPV6_ECHO_REQUEST: u8 = 128;
+pub const ICMPV6_ECHO_REPLY: u8 = 129;
+pub const ICMPV6_ROUTER_SOLICITATION: u8 = 133;
+pub const ICMPV6_ROUTER_ADVERTISEMENT: u8 = 134;
+pub const ICMPV6_NEIGHBOR_SOLICITATION: u8 = 135;
+pub const ICMPV6_NEIGHBOR_ADVERTISEMENT: u8 = 136;
+
+/// 差错报文的类型都小于 128 (RFC 4443 2.1)
+pub fn is_error_type(type_: u8) -> bool {
+    type_ < 128
+}
+
+/// NDP 报文必须以 255 的 Hop Limit 发送，收到时也要检查，确保来自本链路 (RFC 4861 6.1)
+pub const NDP_HOP_LIMIT: u8 = 255;
+
+// NDP 选项类型
+pub const NDP_OPT_SOURCE_LINK_LAYER_ADDR: u8 = 1;
+pub const NDP_OPT_TARGET_LINK_LAYER_ADDR: u8 = 2;
+pub const NDP_OPT_PREFIX_INFORMATION: u8 = 3;
+pub const NDP_OPT_MTU: u8 = 5;
+pub const NDP_OPT_NONCE: u8 = 14;
+
+/// 路由器通告中的前缀信息 (RFC 4861 4.6.2)，生存期以秒计，u32::MAX 表示无限
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PrefixInformation {
+    pub prefix_len: u8,
+    /// L 位：前缀内的地址在本链路上
+    pub on_link: bool,
+    /// A 位：可用于无状态地址自动配置
+    pub autonomous: bool,
+    pub valid_lifetime: u32,
+    pub preferred_lifetime: u32,
+    pub prefix: Ipv6Addr,
+}
+
+/// Destination Unreachable 的 code 字段 (RFC 4443 3.1)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Icmpv6DestUnreachableCode {
+    NoRoute,
+    AdminProhibited,
+    BeyondScope,
+    AddressUnreachable,
+    PortUnreachable,
+    SourcePolicyFailed,
+    RejectRoute,
+    Unknown(u8),
+}
+
+impl Icmpv6DestUnreachableCode {
+    pub fn code(self) -> u8 {
+        match self {
+            Self::NoRoute => 0,
+            Self::AdminProhibited => 1,
+            Self::BeyondScope => 2,
+            Self::AddressUnreachable => 3,
+            Self::PortUnreachable => 4,
+            Self::SourcePolicyFailed => 5,
+            Self::RejectRoute => 6,
+            Self::Unknown(code) => code,
+        }
+    }
+
+    pub fn parse(code: u8) -> Self {
+        match code {
+            0 => Self::NoRoute,
+            1 => Self::AdminProhibited,
+            2 => Self::BeyondScope,
+            3 => Self::AddressUnreachable,
+            4 => Self::PortUnreachable,
+            5 => Self::SourcePolicyFailed,
+            6 => Self::RejectRoute,
+            other => Self::Unknown(other),
+        }
+    }
+}
+
+/// Time Exceeded 的 code 字段 (RFC 4443 3.3)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Icmpv6TimeExceededCode {
+    HopLimitExceeded,
+    FragmentReassembly,
+    Unknown(u8),
+}
+
+impl Icmpv6TimeExceededCode {
+    pub fn code(self) -> u8 {
+        match self {
+            Self::HopLimitExceeded => 0,
+            Self::FragmentReassembly => 1,
+            Self::Unknown(code) => code,
+        }
+    }
+
+    pub fn parse(code: u8) -> Self {
+        match code {
+            0 => Self::HopLimitExceeded,
+            1 => Self::FragmentReassembly,
+            other => Self::Unknown(other),
+        }
+    }
+}
+
+/// Parameter Problem 的 code 字段 (RFC 4443 3.4)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Icmpv6ParameterProblemCode {
+    ErroneousHeaderField,
+    UnrecognizedNextHeader,
+    UnrecognizedOption,
+    Unknown(u8),
+}
+
+impl Icmpv6ParameterProblemCode {
+    pub fn code(self) -> u8 {
+        match self {
+            Self::ErroneousHeaderField => 0,
+            Self::UnrecognizedNextHeader => 1,
+            Self::UnrecognizedOption => 2,
+            Self::Unknown(code) => code,
+        }
+    }
+
+    pub fn parse(code: u8) -> Self {
+        match code {
+            0 => Self::ErroneousHeaderField,
+            1 => Self::UnrecognizedNextHeader,
+            2 => Self::UnrecognizedOption,
+            other => Self::Unknown(other),
+        }
+    }
+}
+
+/// NDP 报文末尾的 TLV 选项，长度以 8 字节为单位 (RFC 4861 4.6)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NdpOption {
+    SourceLinkLayerAddr(MacAddr),
+    TargetLinkLayerAddr(MacAddr),
+    PrefixInformation(PrefixInformation),
+    Mtu(u32),
+    /// 重复地址检测用的随机数，用来识别环回的邻居请求 (RFC 7527)，长度加 2 须为 8 的倍数
+    Nonce(Vec<u8>),
+    /// data 不含类型和长度字节，加上这两个字节须为 8 的倍数
+    Unknown {
+        kind: u8,
+        data: Vec<u8>,
+    },
+}
+
+impl NdpOption {
+    pub fn kind(&self) -> u8 {
+        match self {
+            Self::SourceLinkLayerAddr(_) => NDP_OPT_SOURCE_LINK_LAYER_ADDR,
+            Self::TargetLinkLayerAddr(_) => NDP_OPT_TARGET_LINK_LAYER_ADDR,
+            Self::PrefixInformation(_) => NDP_OPT_PREFIX_INFORMATION,
+            Self::Mtu(_) => NDP_OPT_MTU,
+            Self::Nonce(_) => NDP_OPT_NONCE,
+            Self::Unknown { kind, .. } => *kind,
+        }
+    }
+
+    pub fn write_to(&self, buf: &mut Vec<u8>) {
+        match self {
+            Self::SourceLinkLayerAddr(mac) | Self::TargetLinkLayerAddr(mac) => {
+                buf.extend_from_slice(&[self.kind(), 1]);
+                buf.extend_from_slice(mac.as_bytes());
+            }
+            Self::PrefixInformation(info) => {
+                let flags = (info.on_link as u8) << 7 | (info.autonomous as u8) << 6;
+                buf.extend_from_slice(&[self.kind(), 4, info.prefix_len, flags]);
+                buf.extend_from_slice(&info.valid_lifetime.to_be_bytes());
+                buf.extend_from_slice(&info.preferred_lifetime.to_be_bytes());
+                buf.extend_from_slice(&[0; 4]);
+                buf.extend_from_slice(&info.prefix.octets());
+            }
+            Self::Mtu(mtu) => {
+                buf.extend_from_slice(&[self.kind(), 1, 0, 0]);
+                buf.extend_from_slice(&mtu.to_be_bytes());
+            }
+            Self::Nonce(data) | Self::Unknown { data, .. } => {
+                buf.extend_from_slice(&[self.kind(), ((2 + data.len()) / 8) as u8]);
+                buf.extend_from_slice(data);
+            }
+        }
+    }
+}
+
+fn parse_ndp_options(mut bytes: &[u8]) -> Result<Vec<NdpOption>, Icmpv6ParseError> {
+    let mut options = Vec::new();
+    while !bytes.is_empty() {
+        // 长度为 0 的选项必须丢弃整个报文，否则会陷入死循环
+        let len = match bytes {
+            [_, len, ..] if *len > 0 => *len as usize * 8,
+            _ => return Err(Icmpv6ParseError::InvalidOption),
+        };
+        let option = bytes.get(..len).ok_or(Icmpv6ParseError::InvalidOption)?;
+        options.push(match option[0] {
+            NDP_OPT_SOURCE_LINK_LAYER_ADDR if len == 8 => {
+                NdpOption::SourceLinkLayerAddr(MacAddr::from_slice(&option[2..8]))
+            }
+            NDP_OPT_TARGET_LINK_LAYER_ADDR if len == 8 => {
+                NdpOption::TargetLinkLayerAddr(MacAddr::from_slice(&option[2..8]))
+            }
+            NDP_OPT_PREFIX_INFORMATION if len == 32 => {
+                let u32_at = |i: usize| {
+                    u32::from_be_bytes([option[i], option[i + 1], option[i + 2], option[i + 3]])
+                };
+                let mut prefix = [0u8; 16];
+                prefix.copy_from_slice(&option[16..32]);
+                NdpOption::PrefixInformation(PrefixInformation {
+                    prefix_len: option[2],
+                    on_link: option[3] & 0x80 != 0,
+                    autonomous: option[3] & 0x40 != 0,
+                    valid_lifetime: u32_at(4),
+                    preferred_lifetime: u32_at(8),
+                    prefix: Ipv6Addr::from_octets(prefix),
+                })
+            }
+            NDP_OPT_MTU if len == 8 => NdpOption::Mtu(u32::from_be_bytes([
+                option[4], option[5], option[6], option[7],
+            ])),
+            NDP_OPT_NONCE => NdpOption::Nonce(option[2..].to_vec()),
+            kind => NdpOption::Unknown {
+                kind,
+                data: option[2..].to_vec(),
+            },
+        });
+        bytes = &bytes[len..];
+    }
+    Ok(options)
+}
+
+/// 一个完整的 ICMPv6 报文
+///
+/// 差错报文的 original 为引发差错的原始 IPv6 报文，在不超过最小 MTU 的前提下尽量完整。
+/// 类型或 code 不认识的报文保存在 Unknown 中，编码时原样输出。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Icmpv6Message {
+    DestUnreachable {
+        code: Icmpv6DestUnreachableCode,
+        original: Vec<u8>,
+    },
+    PacketTooBig {
+        mtu: u32,
+        original: Vec<u8>,
+    },
+    TimeExceeded {
+        code: Icmpv6TimeExceededCode,
+        original: Vec<u8>,
+    },
+    ParameterProblem {
+        code: Icmpv6ParameterProblemCode,
+        /// 出错字段在原始报文中的字节偏移
+        pointer: u32,
+        original: Vec<u8>,
+    },
+    EchoRequest(Echo),
+    EchoReply(Echo),
+    RouterSolicitation {
+        options: Vec<NdpOption>,
+    },
+    RouterAdvertisement {
+        /// 建议的 Hop Limit，0 表示未指定
+        hop_limit: u8,
+        /// M 位：地址由 DHCPv6 分配
+        managed: bool,
+        /// O 位：其他配置由 DHCPv6 提供
+        other: bool,
+        /// 作为默认路由器的生存期 (秒)，0 表示不是默认路由器
+        router_lifetime: u16,
+        /// 以毫秒计，0 表示未指定
+        reachable_time: u32,
+        retrans_timer: u32,
+        options: Vec<NdpOption>,
+    },
+    NeighborSolicitation {
+        target: Ipv6Addr,
+        options: Vec<NdpOption>,
+    },
+    NeighborAdvertisement {
+        /// R 位：发送者是路由器
+        router: bool,
+        /// S 位：作为对邻居请求的回复发送
+        solicited: bool,
+        /// O 位：应当覆盖已缓存的链路层地址
+        override_: bool,
+        target: Ipv6Addr,
+        options: Vec<NdpOption>,
+    },
+    Unknown {
+        type_: u8,
+        code: u8,
+        /// 首部第 4-7 字节
+        rest: [u8; 4],
+        data: Vec<u8>,
+    },
+}
+
+impl Icmpv6Message {
+    pub fn type_(&self) -> u8 {
+        match self {
+            Self::DestUnreachable { .. } => ICMPV6_DEST_UNREACHABLE,
+            Self::PacketTooBig { .. } => ICMPV6_PACKET_TOO_BIG,
+            Self::TimeExceeded { .. } => ICMPV6_TIME_EXCEEDED,
+            Self::ParameterProblem { .. } => ICMPV6_PARAMETER_PROBLEM,
+            Self::EchoRequest(_) => ICMPV6_ECHO_REQUEST,
+            Self::EchoReply(_) => ICMPV6_ECHO_REPLY,
+            Self::RouterSolicitation { .. } => ICMPV6_ROUTER_SOLICITATION,
+            Self::RouterAdvertisement { .. } => ICMPV6_ROUTER_ADVERTISEMENT,
+            Self::NeighborSolicitation { .. } => ICMPV6_NEIGHBOR_SOLICITATION,
+            Self::NeighborAdvertisement { .. } => ICMPV6_NEIGHBOR_ADVERTISEMENT,
+            Self::Unknown { type_, .. } => *type_,
+        }
+    }
+
+    pub fn code(&self) -> u8 {
+        match self {
+            Self::DestUnreachable { code, .. } => code.code(),
+            Self::TimeExceeded { code, .. } => code.code(),
+            Self::ParameterProblem { code, .. } => code.code(),
+            Self::Unknown { code, .. } => *code,
+            _ => 0,
+        }
+    }
+
+    pub fn is_error(&self) -> bool {
+        is_error_type(self.type_())
+    }
+
+    /// 差错报文引用的原始报文
+    pub fn original(&self) -> Option<&[u8]> {
+        match self {
+            Self::DestUnreachable { original, .. }
+            | Self::PacketTooBig { original, .. }
+            | Self::TimeExceeded { original, .. }
+            | Self::ParameterProblem { original, .. } => Some(original),
+            _ => None,
+        }
+    }
+
+    /// NDP 报文的选项
+    pub fn ndp_options(&self) -> &[NdpOption] {
+        match self {
+            Self::RouterSolicitation { options }
+            | Self::RouterAdvertisement { options, .. }
+            | Self::NeighborSolicitation { options, .. }
+            | Self::NeighborAdvertisement { options, .. } => options,
+            _ => &[],
+        }
+    }
+
+    /// NDP 报文携带的源链路层地址选项
+    pub fn source_link_layer_addr(&self) -> Option<MacAddr> {
+        self.ndp_options().iter().find_map(|option| match option {
+            NdpOption::SourceLinkLayerAddr(mac) => Some(*mac),
+            _ => None,
+        })
+    }
+
+    /// NDP 报文携带的目标链路层地址选项
+    pub fn target_link_layer_addr(&self) -> Option<MacAddr> {
+        self.ndp_options().iter().find_map(|option| match option {
+            NdpOption::TargetLinkLayerAddr(mac) => Some(*mac),
+            _ => None,
+        })
+    }
+
+    /// 路由器通告携带的前缀信息
+    pub fn prefix_information(&self) -> impl Iterator<Item = &PrefixInformation> {
+        self.ndp_options().iter().filter_map(|option| match option {
+            NdpOption::PrefixInformation(info) => Some(info),
+            _ => None,
+        })
+    }
+
+    /// 重复地址检测的邻居请求携带的随机数
+    pub fn nonce(&self) -> Option<&[u8]> {
+        self.ndp_options().iter().find_map(|option| match option {
+            NdpOption::Nonce(nonce) => Some(nonce.as_slice()),
+            _ => None,
+        })
+    }
+
+    /// 解析并校验一个 ICMPv6 报文，校验和覆盖 src / dst 组成的伪首部
+    pub fn parse(bytes: &[u8], src: Ipv6Addr, dst: Ipv6Addr) -> Result<Self, Icmpv6ParseError> {
+        if bytes.len() < 8 {
+            return Err(Icmpv6ParseError::InvalidLength);
+        }
+        if checksum(bytes, src, dst) != 0 {
+            return Err(Icmpv6ParseError::InvalidChecksum);
+        }
+
+        let type_ = bytes[0];
+        let code = bytes[1];
+        let rest: [u8; 4] = [bytes[4], bytes[5], bytes[6], bytes[7]];
+        let data = &bytes[8..];
+        // NS / NA 在首部之后是 16 字节的目标地址，然后是选项
+        let target = || -> Result<(Ipv6Addr, Vec<NdpOption>), Icmpv6ParseError> {
+            let target: [u8; 16] = data
+                .get(..16)
+                .ok_or(Icmpv6ParseError::InvalidLength)?
+                .try_into()
+                .map_err(|_| Icmpv6ParseError::InvalidLength)?;
+            Ok((
+                Ipv6Addr::from_octets(target),
+                parse_ndp_options(&data[16..])?,
+            ))
+        };
+
+        let message = match (type_, code) {
+            (ICMPV6_DEST_UNREACHABLE, _) => Self::DestUnreachable {
+                code: Icmpv6DestUnreachableCode::parse(code),
+                original: data.to_vec(),
+            },
+            (ICMPV6_PACKET_TOO_BIG, 0) => Self::PacketTooBig {
+                mtu: u32::from_be_bytes(rest),
+                original: data.to_vec(),
+            },
+            (ICMPV6_TIME_EXCEEDED, _) => Self::TimeExceeded {
+                code: Icmpv6TimeExceededCode::parse(code),
+                original: data.to_vec(),
+            },
+            (ICMPV6_PARAMETER_PROBLEM, _) => Self::ParameterProblem {
+                code: Icmpv6ParameterProblemCode::parse(code),
+                pointer: u32::from_be_bytes(rest),
+                original: data.to_vec(),
+            },
+            (ICMPV6_ECHO_REQUEST | ICMPV6_ECHO_REPLY, 0) => {
+                let echo = Echo {
+                    id: u16::from_be_bytes([rest[0], rest[1]]),
+                    seq: u16::from_be_bytes([rest[2], rest[3]]),
+                    data: data.to_vec(),
+                };
+                if type_ == ICMPV6_ECHO_REQUEST {
+                    Self::EchoRequest(echo)
+                } else {
+                    Self::EchoReply(echo)
+                }
+            }
+            (ICMPV6_ROUTER_SOLICITATION, 0) => Self::RouterSolicitation {
+                options: parse_ndp_options(data)?,
+            },
+            (ICMPV6_ROUTER_ADVERTISEMENT, 0) => {
+                let u32_at = |i: usize| {
+                    data.get(i..i + 4)
+                        .map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+                        .ok_or(Icmpv6ParseError::InvalidLength)
+                };
+                Self::RouterAdvertisement {
+                    hop_limit: rest[0],
+                    managed: rest[1] & 0x80 != 0,
+                    other: rest[1] & 0x40 != 0,
+                    router_lifetime: u16::from_be_bytes([rest[2], rest[3]]),
+                    reachable_time: u32_at(0)?,
+                    retrans_timer: u32_at(4)?,
+                    options: parse_ndp_options(&data[8..])?,
+                }
+            }
+            (ICMPV6_NEIGHBOR_SOLICITATION, 0) => {
+                let (target, options) = target()?;
+                Self::NeighborSolicitation { target, options }
+            }
+            (ICMPV6_NEIGHBOR_ADVERTISEMENT, 0) => {
+                let (target, options) = target()?;
+                Self::NeighborAdvertisement {
+                    router: rest[0] & 0x80 != 0,
+                    solicited: rest[0] & 0x40 != 0,
+                    override_: rest[0] & 0x20 != 0,
+                    target,
+                    options,
+                }
+            }
+            _ => Self::Unknown {
+                type_,
+                code,
+                rest,
+                data: data.to_vec(),
+            },
+        };
+        Ok(message)
+    }
+
+    /// 编码为字节并按 src / dst 填好校验和
+    pub fn to_bytes(&self, src: Ipv6Addr, dst: Ipv6Addr) -> Vec<u8> {
+        let mut bytes = vec![self.type_(), self.code(), 0, 0];
+        match self {
+            Self::DestUnreachable { original, .. } | Self::TimeExceeded { original, .. } => {
+                bytes.extend_from_slice(&[0; 4]);
+                bytes.extend_from_slice(original);
+            }
+            Self::PacketTooBig { mtu, original } => {
+                bytes.extend_from_slice(&mtu.to_be_bytes());
+                bytes.extend_from_slice(original);
+            }
+            Self::ParameterProblem {
+                pointer, original, ..
+            } => {
+                bytes.extend_from_slice(&pointer.to_be_bytes());
+                bytes.extend_from_slice(original);
+            }
+            Self::EchoRequest(echo) | Self::EchoReply(echo) => {
+                bytes.extend_from_slice(&echo.id.to_be_bytes());
+                bytes.extend_from_slice(&echo.seq.to_be_bytes());
+                bytes.extend_from_slice(&echo.data);
+            }
+            Self::RouterSolicitation { options } => {
+                bytes.extend_from_slice(&[0; 4]);
+                for option in options {
+                    option.write_to(&mut bytes);
+                }
+            }
+            Self::RouterAdvertisement {
+                hop_limit,
+                managed,
+                other,
+                router_lifetime,
+                reachable_time,
+                retrans_timer,
+                options,
+            } => {
+                let flags = (*managed as u8) << 7 | (*other as u8) << 6;
+                bytes.extend_from_slice(&[*hop_limit, flags]);
+                bytes.extend_from_slice(&router_lifetime.to_be_bytes());
+                bytes.extend_from_slice(&reachable_time.to_be_bytes());
+                bytes.extend_from_slice(&retrans_timer.to_be_bytes());
+                for option in options {
+                    option.write_to(&mut bytes);
+                }
+            }
+            Self::NeighborSolicitation { target, options } => {
+                bytes.extend_from_slice(&[0; 4]);
+                bytes.extend_from_slice(&target.octets());
+                for option in options {
+                    option.write_to(&mut bytes);
+                }
+            }
+            Self::NeighborAdvertisement {
+                router,
+                solicited,
+                override_,
+                target,
+                options,
+            } => {
+                let flags =
+                    (*router as u8) << 7 | (*solicited as u8) << 6 | (*override_ as u8) << 5;
+                bytes.extend_from_slice(&[flags, 0, 0, 0]);
+                bytes.extend_from_slice(&target.octets());
+                for option in options {
+                    option.write_to(&mut bytes);
+                }
+            }
+            Self::Unknown { rest, data, .. } => {
+                bytes.extend_from_slice(rest);
+                bytes.extend_from_slice(data);
+            }
+        }
+
+        let checksum = checksum(&bytes, src, dst);
+        bytes[2..4].copy_from_slice(&checksum.to_be_bytes());
+        bytes
+    }
+}
+
+/// 带伪首部的校验和，对已填好校验和的报文结果为 0
+fn checksum(bytes: &[u8], src: Ipv6Addr, dst: Ipv6Addr) -> u16 {
+    let mut buffer = Vec::with_capacity(40 + bytes.len());
+    buffer.extend_from_slice(&ipv6::pseudo_header(
+        src,
+        dst,
+        bytes.len() as u32,
+        IPV6_NEXT_ICMPV6,
+    ));
+    buffer.extend_from_slice(bytes);
+    simple_checksum(&buffer)
+}
+
+impl fmt::Display for Icmpv6Message {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::DestUnreachable { code, .. } => {
+                write!(f, "ICMPv6 Destination Unreachable: {:?}", code)
+            }
+            Self::PacketTooBig { mtu, .. } => write!(f, "ICMPv6 Packet Too Big: mtu={}", mtu),
+            Self::TimeExceeded { code, .. } => write!(f, "ICMPv6 Time Exceeded: {:?}", code),
+            Self::ParameterProblem { code, pointer, .. } => write!(
+                f,
+                "ICMPv6 Parameter Problem: {:?}, pointer={}",
+                code, pointer
+            ),
+            Self::EchoRequest(echo) => write!(
+                f,
+                "ICMPv6 Echo Request: id={}, seq={}, payload_len={}",
+                echo.id,
+                echo.seq,
+                echo.data.len()
+            ),
+            Self::EchoReply(echo) => write!(
+                f,
+                "ICMPv6 Echo Reply: id={}, seq={}, payload_len={}",
+                echo.id,
+                echo.seq,
+                echo.data.len()
+            ),
+            Self::RouterSolicitation { .. } => write!(f, "ICMPv6 Router Solicitation"),
+            Self::RouterAdvertisement {
+                router_lifetime, ..
+            } => {
+                write!(
+                    f,
+                    "ICMPv6 Router Advertisement: lifetime={}s, prefixes=[",
+                    router_lifetime
+                )?;
+                for (i, info) in self.prefix_information().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}/{}", info.prefix, info.prefix_len)?;
+                }
+                write!(f, "]")
+            }
+            Self::NeighborSolicitation { target, .. } => {
+                write!(f, "ICMPv6 Neighbor Solicitation: who has {}", target)
+            }
+            Self::NeighborAdvertisement {
+                router,
+                solicited,
+                override_,
+                target,
+                ..
+            } => write!(
+                f,
+                "ICMPv6 Neighbor Advertisement: {} is at {} (R={}, S={}, O={})",
+                target,
+                self.target_link_layer_addr()
+                    .map_or("?".to_string(), |mac| mac.to_string()),
+                *router as u8,
+                *solicited as u8,
+                *override_ as u8
+            ),
+            Self::Unknown { type_, code, .. } => {
+                write!(f, "ICMPv6 Unknown Type: type={}, code={}", type_, code)
+            }
+        }
+    }
+}
+
+/// 邻居缓存项的可达性，简化自 RFC 4861 7.3.2：
+/// 只有收到对邻居请求的回复才算 Reachable，其余途径学到的地址和超过可达时间的项都是 Stale
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NeighborState {
+    Reachable,
+    Stale,
+}
+
+#[derive(Debug, Clone)]
+struct NeighborEntry {
+    mac: MacAddr,
+    // 最近一次确认可达的时刻，None 表示从未确认
+    confirmed: Option<Instant>,
+    updated: Instant,
+    // 最近一次为确认可达性发送单播邻居请求的时刻
+    probed: Option<Instant>,
+    is_router: bool,
+    is_static: bool,
+}
+
+/// IPv6 邻居缓存，作用与 `arp::ArpTable` 相同
+pub struct NeighborCache {
+    entries: HashMap<Ipv6Addr, NeighborEntry>,
+    // 确认可达后多久变为 Stale
+    reachable_time: Duration,
+    // Stale 项多久没有更新后删除
+    stale_time: Duration,
+}
+
+impl NeighborCache {
+    pub fn new(reachable_time: Duration, stale_time: Duration) -> Self {
+        Self {
+            entries: HashMap::new(),
+            reachable_time,
+            stale_time,
+        }
+    }
+
+    fn is_valid(&self, entry: &NeighborEntry, now: Instant) -> bool {
+        entry.is_static || now.duration_since(entry.updated) < self.stale_time
+    }
+
+    /// 查询 IPv6 地址对应的 MAC 地址，Stale 项也可以使用
+    pub fn lookup(&self, ip: Ipv6Addr, now: Instant) -> Option<MacAddr> {
+        self.entries
+            .get(&ip)
+            .filter(|entry| self.is_valid(entry, now))
+            .map(|entry| entry.mac)
+    }
+
+    pub fn state(&self, ip: Ipv6Addr, now: Instant) -> Option<NeighborState> {
+        let entry = self
+            .entries
+            .get(&ip)
+            .filter(|entry| self.is_valid(entry, now))?;
+        let reachable = entry.is_static
+            || entry
+                .confirmed
+                .is_some_and(|confirmed| now.duration_since(confirmed) < self.reachable_time);
+        Some(if reachable {
+            NeighborState::Reachable
+        } else {
+            NeighborState::Stale
+        })
+    }
+
+    /// 收到对邻居请求的回复，邻居确认可达
+    pub fn confirm(&mut self, ip: Ipv6Addr, mac: MacAddr, now: Instant) {
+        let entry = self.entry(ip, mac, now);
+        entry.mac = mac;
+        entry.confirmed = Some(now);
+        entry.updated = now;
+        entry.probed = None;
+    }
+
+    /// 从邻居请求的源地址、非请求的通告等途径学到的地址
+    ///
+    /// 地址变化时标记为 Stale，没有变化时保持原来的状态
+    pub fn insert_stale(&mut self, ip: Ipv6Addr, mac: MacAddr, now: Instant) {
+        let entry = self.entry(ip, mac, now);
+        if entry.is_static {
+            return;
+        }
+        if entry.mac != mac {
+            entry.mac = mac;
+            entry.confirmed = None;
+        }
+        entry.updated = now;
+    }
+
+    /// 插入静态项，不会过期
+    pub fn insert_static(&mut self, ip: Ipv6Addr, mac: MacAddr, now: Instant) {
+        let entry = self.entry(ip, mac, now);
+        entry.mac = mac;
+        entry.is_static = true;
+    }
+
+    fn entry(&mut self, ip: Ipv6Addr, mac: MacAddr, now: Instant) -> &mut NeighborEntry {
+        self.entries.entry(ip).or_insert(NeighborEntry {
+            mac,
+            confirmed: None,
+            updated: now,
+            probed: None,
+            is_router: false,
+            is_static: false,
+        })
+    }
+
+    pub fn contains(&self, ip: Ipv6Addr, now: Instant) -> bool {
+        self.lookup(ip, now).is_some()
+    }
+
+    /// 邻居通告中的 R 位
+    pub fn set_router(&mut self, ip: Ipv6Addr, is_router: bool) {
+        if let Some(entry) = self.entries.get_mut(&ip) {
+            entry.is_router = is_router;
+        }
+    }
+
+    pub fn is_router(&self, ip: Ipv6Addr) -> bool {
+        self.entries.get(&ip).is_some_and(|entry| entry.is_router)
+    }
+
+    /// 向 Stale 邻居发包时调用：距上次更新和上次探测都超过 delay 时记下当前时刻并返回 true，
+    /// 调用者据此发送单播邻居请求确认可达性 (相当于 RFC 4861 的 DELAY 状态)
+    pub fn should_probe(&mut self, ip: Ipv6Addr, delay: Duration, now: Instant) -> bool {
+        if self.state(ip, now) != Some(NeighborState::Stale) {
+            return false;
+        }
+        let Some(entry) = self.entries.get_mut(&ip) else {
+            return false;
+        };
+        if now.duration_since(entry.updated) < delay
+            || entry
+                .probed
+                .is_some_and(|probed| now.duration_since(probed) < delay)
+        {
+            return false;
+        }
+        entry.probed = Some(now);
+        true
+    }
+
+    pub fn remove(&mut self, ip: Ipv6Addr) {
+        self.entries.remove(&ip);
+    }
+
+    /// 清理过期项(后台定期调用)
+    pub fn evict_expired(&mut self, now: Instant) {
+        let stale_time = self.stale_time;
+        self.entries
+            .retain(|_, entry| entry.is_static || now.duration_since(entry.updated) < stale_time);
+    }
+
+    /// 获取所有有效项(用于调试/日志)
+    pub fn entries(&self, now: Instant) -> Vec<(Ipv6Addr, MacAddr, NeighborState)> {
+        self.entries
+            .iter()
+            .filter_map(|(ip, entry)| Some((*ip, entry.mac, self.state(*ip, now)?)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn addr(s: &str) -> Ipv6Addr {
+        Ipv6Addr::from_str(s).unwrap()
+    }
+
+    fn mac(last: u8) -> MacAddr {
+        MacAddr::from_raw([0x02, 0, 0, 0, 0, last])
+    }
+
+    /// 给手工拼出的报文重新填上校验和
+    fn seal(mut bytes: Vec<u8>, src: Ipv6Addr, dst: Ipv6Addr) -> Vec<u8> {
+        bytes[2..4].copy_from_slice(&[0, 0]);
+        let sum = checksum(&bytes, src, dst);
+        bytes[2..4].copy_from_slice(&sum.to_be_bytes());
+        bytes
+    }
+
+    #[test]
+    fn messages_round_trip() {
+        let src = addr("fe80::1");
+        let dst = addr("fe80::2");
+        let prefix = PrefixInformation {
+            prefix_len: 64,
+            on_link: true,
+            autonomous: false,
+            valid_lifetime: u32::MAX,
+            preferred_lifetime: 3600,
+            prefix: addr("2001:db8::"),
+        };
+        let messages = [
+            Icmpv6Message::DestUnreachable {
+                code: Icmpv6DestUnreachableCode::PortUnreachable,
+                original: vec![0x60; 48],
+            },
+            Icmpv6Message::PacketTooBig {
+                mtu: 1280,
+                original: vec![0x60; 48],
+            },
+            Icmpv6Message::TimeExceeded {
+                code: Icmpv6TimeExceededCode::FragmentReassembly,
+                original: vec![0x60; 48],
+            },
+            Icmpv6Message::ParameterProblem {
+                code: Icmpv6ParameterProblemCode::UnrecognizedOption,
+                pointer: 42,
+                original: vec![0x60; 48],
+            },
+            Icmpv6Message::EchoRequest(Echo {
+                id: 0x1234,
+                seq: 7,
+                data: b"ping".to_vec(),
+            }),
+            Icmpv6Message::EchoReply(Echo {
+                id: 0x1234,
+                seq: 7,
+                data: Vec::new(),
+            }),
+            Icmpv6Message::RouterSolicitation {
+                options: vec![NdpOption::SourceLinkLayerAddr(mac(1))],
+            },
+            Icmpv6Message::RouterAdvertisement {
+                hop_limit: 64,
+                managed: false,
+                other: true,
+                router_lifetime: 1800,
+                reachable_time: 30000,
+                retrans_timer: 1000,
+                options: vec![
+                    NdpOption::SourceLinkLayerAddr(mac(1)),
+                    NdpOption::Mtu(1500),
+                    NdpOption::PrefixInformation(prefix),
+                ],
+            },
+            Icmpv6Message::NeighborSolicitation {
+                target: addr("fe80::2"),
+                options: vec![NdpOption::Nonce(vec![1, 2, 3, 4, 5, 6])],
+            },
+            Icmpv6Message::NeighborAdvertisement {
+                router: true,
+                solicited: false,
+                override_: true,
+                target: addr("fe80::1"),
+                options: vec![
+                    NdpOption::TargetLinkLayerAddr(mac(1)),
+                    NdpOption::Unknown {
+                        kind: 200,
+                        data: vec![9; 14],
+                    },
+                ],
+            },
+            Icmpv6Message::Unknown {
+                type_: 200,
+                code: 3,
+                rest: [1, 2, 3, 4],
+                data: vec![5, 6],
+            },
+        ];
+        for message in messages {
+            let bytes = message.to_bytes(src, dst);
+            assert_eq!(Icmpv6Message::parse(&bytes, src, dst), Ok(message));
+        }
+    }
+
+    #[test]
+    fn neighbor_discovery_wire_layout() {
+        let src = addr("fe80::1");
+        let target = addr("fe80::2");
+        let solicitation = Icmpv6Message::NeighborSolicitation {
+            target,
+            options: vec![NdpOption::SourceLinkLayerAddr(mac(1))],
+        };
+        let bytes = solicitation.to_bytes(src, target.solicited_node());
+        assert_eq!(bytes.len(), 32);
+        assert_eq!(bytes[0], ICMPV6_NEIGHBOR_SOLICITATION);
+        assert_eq!(&bytes[4..8], &[0; 4]);
+        assert_eq!(&bytes[8..24], &target.octets());
+        assert_eq!(&bytes[24..26], &[NDP_OPT_SOURCE_LINK_LAYER_ADDR, 1]);
+        assert_eq!(&bytes[26..32], mac(1).as_bytes());
+
+        // R、S、O 依次是第 4 字节的最高三位
+        let advertisement = Icmpv6Message::NeighborAdvertisement {
+            router: true,
+            solicited: true,
+            override_: true,
+            target,
+            options: Vec::new(),
+        };
+        let bytes = advertisement.to_bytes(target, src);
+        assert_eq!(bytes[0], ICMPV6_NEIGHBOR_ADVERTISEMENT);
+        assert_eq!(&bytes[4..8], &[0xe0, 0, 0, 0]);
+
+        let mut option = Vec::new();
+        NdpOption::PrefixInformation(PrefixInformation {
+            prefix_len: 64,
+            on_link: true,
+            autonomous: true,
+            valid_lifetime: 7200,
+            preferred_lifetime: 3600,
+            prefix: addr("2001:db8::"),
+        })
+        .write_to(&mut option);
+        assert_eq!(option.len(), 32);
+        assert_eq!(&option[..4], &[NDP_OPT_PREFIX_INFORMATION, 4, 64, 0xc0]);
+        assert_eq!(&option[4..8], &7200u32.to_be_bytes());
+        assert_eq!(&option[8..12], &3600u32.to_be_bytes());
+        assert_eq!(&option[16..], &addr("2001:db8::").octets());
+    }
+
+    #[test]
+    fn accessors_find_ndp_options() {
+        let message = Icmpv6Message::NeighborAdvertisement {
+            router: false,
+            solicited: true,
+            override_: true,
+            target: addr("fe80::1"),
+            options: vec![
+                NdpOption::TargetLinkLayerAddr(mac(1)),
+                NdpOption::Nonce(vec![0; 6]),
+            ],
+        };
+        assert_eq!(message.target_link_layer_addr(), Some(mac(1)));
+        assert_eq!(message.source_link_layer_addr(), None);
+        assert_eq!(message.nonce(), Some(&[0u8; 6][..]));
+        assert_eq!(message.prefix_information().count(), 0);
+        assert!(!message.is_error());
+        assert_eq!(message.original(), None);
+
+        let error = Icmpv6Message::PacketTooBig {
+            mtu: 1280,
+            original: vec![1, 2, 3],
+        };
+        assert!(error.is_error());
+        assert!(is_error_type(error.type_()));
+        assert!(!is_error_type(ICMPV6_ECHO_REQUEST));
+        assert_eq!(error.original(), Some(&[1u8, 2, 3][..]));
+        assert!(error.ndp_options().is_empty());
+    }
+
+    #[test]
+    fn malformed_messages_are_rejected() {
+        let src = addr("fe80::1");
+        let dst = addr("fe80::2");
+        assert_eq!(
+            Icmpv6Message::parse(&[ICMPV6_ECHO_REQUEST, 0, 0, 0], src, dst),
+            Err(Icmpv6ParseError::InvalidLength)
+        );
+
+        // 校验和覆盖伪首部，换一个目的地址就对不上
+        let echo = Icmpv6Message::EchoRequest(Echo {
+            id: 1,
+            seq: 1,
+            data: vec![0; 8],
+        })
+        .to_bytes(src, dst);
+        assert_eq!(
+            Icmpv6Message::parse(&echo, src, addr("fe80::3")),
+            Err(Icmpv6ParseError::InvalidChecksum)
+        );
+
+        // 目标地址被截断的邻居请求
+        let mut bytes = Icmpv6Message::NeighborSolicitation {
+            target: dst,
+            options: Vec::new(),
+        }
+        .to_bytes(src, dst);
+        bytes.truncate(20);
+        assert_eq!(
+            Icmpv6Message::parse(&seal(bytes, src, dst), src, dst),
+            Err(Icmpv6ParseError::InvalidLength)
+        );
+
+        // 长度为 0 的选项和超出报文的选项
+        for option in [[1u8, 0, 0, 0, 0, 0, 0, 0], [1, 2, 0, 0, 0, 0, 0, 0]] {
+            let mut bytes = vec![ICMPV6_ROUTER_SOLICITATION, 0, 0, 0, 0, 0, 0, 0];
+            bytes.extend_from_slice(&option);
+            assert_eq!(
+                Icmpv6Message::parse(&seal(bytes, src, dst), src, dst),
+                Err(Icmpv6ParseError::InvalidOption)
+            );
+        }
+    }
+
+    #[test]
+    fn neighbor_cache_states() {
+        let reachable = Duration::from_secs(30);
+        let stale = Duration::from_secs(600);
+        let mut cache = NeighborCache::new(reachable, stale);
+        let ip = addr("fe80::2");
+        let t0 = Instant::now();
+
+        // 从邻居请求学到的地址是 Stale，回复确认后变为 Reachable
+        cache.insert_stale(ip, mac(2), t0);
+        assert_eq!(cache.lookup(ip, t0), Some(mac(2)));
+        assert_eq!(cache.state(ip, t0), Some(NeighborState::Stale));
+        cache.confirm(ip, mac(2), t0);
+        assert_eq!(cache.state(ip, t0), Some(NeighborState::Reachable));
+
+        // 地址不变时保持 Reachable，变化后回到 Stale
+        cache.insert_stale(ip, mac(2), t0 + Duration::from_secs(1));
+        assert_eq!(cache.state(ip, t0), Some(NeighborState::Reachable));
+        cache.insert_stale(ip, mac(3), t0 + Duration::from_secs(2));
+        assert_eq!(cache.lookup(ip, t0), Some(mac(3)));
+        assert_eq!(cache.state(ip, t0), Some(NeighborState::Stale));
+
+        // 超过可达时间变为 Stale，超过 stale_time 后失效
+        cache.confirm(ip, mac(3), t0);
+        assert_eq!(cache.state(ip, t0 + reachable), Some(NeighborState::Stale));
+        assert!(cache.contains(ip, t0 + reachable));
+        assert!(!cache.contains(ip, t0 + stale));
+        assert!(cache.entries(t0 + stale).is_empty());
+
+        cache.set_router(ip, true);
+        assert!(cache.is_router(ip));
+        cache.remove(ip);
+        assert!(!cache.is_router(ip));
+        assert_eq!(cache.lookup(ip, t0), None);
+    }
+
+    #[test]
+    fn stale_neighbors_are_probed_after_a_delay() {
+        let mut cache = NeighborCache::new(Duration::from_secs(30), Duration::from_secs(600));
+        let ip = addr("fe80::2");
+        let delay = Duration::from_secs(5);
+        let t0 = Instant::now();
+
+        cache.confirm(ip, mac(2), t0);
+        assert!(!cache.should_probe(ip, delay, t0 + Duration::from_secs(10)));
+
+        // Stale 之后需要距上次更新超过 delay 才探测，探测后 delay 内不再重复
+        cache.insert_stale(ip, mac(3), t0);
+        assert!(!cache.should_probe(ip, delay, t0 + Duration::from_secs(1)));
+        assert!(cache.should_probe(ip, delay, t0 + delay));
+        assert!(!cache.should_probe(ip, delay, t0 + delay + Duration::from_secs(1)));
+        assert!(cache.should_probe(ip, delay, t0 + delay * 2));
+
+        // 确认后不再需要探测
+        cache.confirm(ip, mac(3), t0 + delay * 2);
+        assert!(!cache.should_probe(ip, delay, t0 + delay * 3));
+        assert!(!cache.should_probe(addr("fe80::9"), delay, t0));
+    }
+
+    #[test]
+    fn static_entries_never_expire() {
+        let stale = Duration::from_secs(600);
+        let mut cache = NeighborCache::new(Duration::from_secs(30), stale);
+        let t0 = Instant::now();
+        let fixed = addr("fe80::1");
+        let learned = addr("fe80::2");
+        cache.insert_static(fixed, mac(1), t0);
+        cache.insert_stale(learned, mac(2), t0);
+
+        // 静态项不会被学到的地址覆盖
+        cache.insert_stale(fixed, mac(9), t0);
+        assert_eq!(cache.lookup(fixed, t0), Some(mac(1)));
+
+        let later = t0 + stale * 2;
+        cache.evict_expired(later);
+        assert_eq!(cache.lookup(fixed, later), Some(mac(1)));
+        assert_eq!(cache.state(fixed, later), Some(NeighborState::Reachable));
+        assert_eq!(cache.lookup(learned, t0), None);
+        assert_eq!(
+            cache.entries(later),
+            vec![(fixed, mac(1), NeighborState::Reachable)]
+        );
+    }
+}
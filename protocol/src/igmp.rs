@@ -0,0 +1,513 @@
+// Copyright (C) 2025 rrrrrzy
+// SPDX-License-Identifier: GPL-3.0-or-later
+//
+// --------------------------------------------------
+// 致敬所有在深夜调试代码的灵魂。
+// 即便 Bug 如山，我亦往矣。
+// --------------------------------------------------
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+//! IGMP 报文：v1 (RFC 1112)、v2 (RFC 2236) 和 v3 (RFC 3376) 主机侧用到的查询、报告和离开
+
+use std::fmt;
+use std::time::Duration;
+
+use crate::checksum::simple_checksum;
+use crate::error::IgmpParseError;
+use crate::ipv4::Ipv4Addr;
+
+pub const IGMP_MEMBERSHIP_QUERY: u8 = 0x11;
+pub const IGMP_V1_MEMBERSHIP_REPORT: u8 = 0x12;
+pub const IGMP_V2_MEMBERSHIP_REPORT: u8 = 0x16;
+pub const IGMP_V2_LEAVE_GROUP: u8 = 0x17;
+pub const IGMP_V3_MEMBERSHIP_REPORT: u8 = 0x22;
+
+/// 所有主机组，每台主机都是成员，但从不为它发送报告
+pub const IGMP_ALL_SYSTEMS: Ipv4Addr = Ipv4Addr::new(224, 0, 0, 1);
+/// v2 的离开报文发往所有路由器组
+pub const IGMP_ALL_ROUTERS: Ipv4Addr = Ipv4Addr::new(224, 0, 0, 2);
+/// v3 的成员报告发往该组
+pub const IGMPV3_ROUTERS: Ipv4Addr = Ipv4Addr::new(224, 0, 0, 22);
+
+/// 查询的 max resp code 为 0 时按 v1 处理，默认 10 秒
+pub const IGMP_V1_MAX_RESP_TIME: Duration = Duration::from_secs(10);
+
+/// v3 组记录的类型 (RFC 3376 4.2.12)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GroupRecordType {
+    ModeIsInclude,
+    ModeIsExclude,
+    ChangeToInclude,
+    ChangeToExclude,
+    AllowNewSources,
+    BlockOldSources,
+    Unknown(u8),
+}
+
+impl GroupRecordType {
+    pub fn code(self) -> u8 {
+        match self {
+            Self::ModeIsInclude => 1,
+            Self::ModeIsExclude => 2,
+            Self::ChangeToInclude => 3,
+            Self::ChangeToExclude => 4,
+            Self::AllowNewSources => 5,
+            Self::BlockOldSources => 6,
+            Self::Unknown(code) => code,
+        }
+    }
+
+    pub fn parse(code: u8) -> Self {
+        match code {
+            1 => Self::ModeIsInclude,
+            2 => Self::ModeIsExclude,
+            3 => Self::ChangeToInclude,
+            4 => Self::ChangeToExclude,
+            5 => Self::AllowNewSources,
+            6 => Self::BlockOldSources,
+            other => Self::Unknown(other),
+        }
+    }
+}
+
+/// v3 成员报告中的一条组记录，辅助数据原样保存
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GroupRecord {
+    pub record_type: GroupRecordType,
+    pub group: Ipv4Addr,
+    pub sources: Vec<Ipv4Addr>,
+    pub aux_data: Vec<u8>,
+}
+
+impl GroupRecord {
+    /// 不过滤任何源的记录，EXCLUDE({}) 表示接收该组的所有源，INCLUDE({}) 表示离开
+    pub fn any_source(record_type: GroupRecordType, group: Ipv4Addr) -> Self {
+        Self {
+            record_type,
+            group,
+            sources: Vec::new(),
+            aux_data: Vec::new(),
+        }
+    }
+}
+
+/// v3 查询在 v2 查询之后追加的字段 (RFC 3376 4.1)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Igmpv3Query {
+    /// S 位：路由器不更新定时器
+    pub suppress_router_processing: bool,
+    /// QRV：查询者的健壮性变量
+    pub robustness: u8,
+    /// QQIC：查询间隔，编码方式与 max resp code 相同
+    pub qqic: u8,
+    pub sources: Vec<Ipv4Addr>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IgmpMessage {
+    /// group 为 0.0.0.0 时是通用查询，否则是特定组查询；v3 查询带有额外字段
+    Query {
+        max_resp_code: u8,
+        group: Ipv4Addr,
+        v3: Option<Igmpv3Query>,
+    },
+    V1Report {
+        group: Ipv4Addr,
+    },
+    V2Report {
+        group: Ipv4Addr,
+    },
+    Leave {
+        group: Ipv4Addr,
+    },
+    V3Report {
+        records: Vec<GroupRecord>,
+    },
+    Unknown {
+        type_: u8,
+        data: Vec<u8>,
+    },
+}
+
+/// v3 的 max resp code 和 QQIC 超过 128 时是 1 位标志 + 3 位指数 + 4 位尾数的浮点数
+fn decode_float(code: u8) -> u32 {
+    if code < 128 {
+        code as u32
+    } else {
+        let exp = (code >> 4) & 0x07;
+        let mant = code & 0x0F;
+        ((mant as u32) | 0x10) << (exp + 3)
+    }
+}
+
+fn parse_addrs(bytes: &[u8], count: usize) -> Result<Vec<Ipv4Addr>, IgmpParseError> {
+    bytes
+        .get(..count * 4)
+        .ok_or(IgmpParseError::InvalidLength)
+        .map(|bytes| {
+            bytes
+                .chunks_exact(4)
+                .map(|b| Ipv4Addr::from_octets([b[0], b[1], b[2], b[3]]))
+                .collect()
+        })
+}
+
+fn parse_group_records(mut data: &[u8], count: usize) -> Result<Vec<GroupRecord>, IgmpParseError> {
+    let mut records = Vec::with_capacity(count);
+    for _ in 0..count {
+        if data.len() < 8 {
+            return Err(IgmpParseError::InvalidLength);
+        }
+        let aux_len = data[1] as usize * 4;
+        let num_sources = u16::from_be_bytes([data[2], data[3]]) as usize;
+        let group = Ipv4Addr::from_octets([data[4], data[5], data[6], data[7]]);
+        let sources = parse_addrs(&data[8..], num_sources)?;
+        let aux_start = 8 + num_sources * 4;
+        let aux_data = data
+            .get(aux_start..aux_start + aux_len)
+            .ok_or(IgmpParseError::InvalidLength)?
+            .to_vec();
+        records.push(GroupRecord {
+            record_type: GroupRecordType::parse(data[0]),
+            group,
+            sources,
+            aux_data,
+        });
+        data = &data[aux_start + aux_len..];
+    }
+    Ok(records)
+}
+
+impl IgmpMessage {
+    pub fn type_(&self) -> u8 {
+        match self {
+            Self::Query { .. } => IGMP_MEMBERSHIP_QUERY,
+            Self::V1Report { .. } => IGMP_V1_MEMBERSHIP_REPORT,
+            Self::V2Report { .. } => IGMP_V2_MEMBERSHIP_REPORT,
+            Self::Leave { .. } => IGMP_V2_LEAVE_GROUP,
+            Self::V3Report { .. } => IGMP_V3_MEMBERSHIP_REPORT,
+            Self::Unknown { type_, .. } => *type_,
+        }
+    }
+
+    /// 查询报文的版本 (RFC 3376 7.1)：8 字节且 max resp code 为 0 的是 v1，其余 8 字节的是 v2
+    pub fn query_version(&self) -> Option<u8> {
+        match self {
+            Self::Query { v3: Some(_), .. } => Some(3),
+            Self::Query {
+                max_resp_code: 0, ..
+            } => Some(1),
+            Self::Query { .. } => Some(2),
+            _ => None,
+        }
+    }
+
+    /// 查询允许的最大响应时间
+    pub fn max_resp_time(&self) -> Option<Duration> {
+        match self {
+            Self::Query {
+                max_resp_code: 0,
+                v3: None,
+                ..
+            } => Some(IGMP_V1_MAX_RESP_TIME),
+            Self::Query {
+                max_resp_code, v3, ..
+            } => {
+                let tenths = if v3.is_some() {
+                    decode_float(*max_resp_code)
+                } else {
+                    *max_resp_code as u32
+                };
+                Some(Duration::from_millis(tenths as u64 * 100))
+            }
+            _ => None,
+        }
+    }
+
+    /// 解析并校验一个 IGMP 报文，校验和覆盖整个报文
+    pub fn parse(bytes: &[u8]) -> Result<Self, IgmpParseError> {
+        if bytes.len() < 8 {
+            return Err(IgmpParseError::InvalidLength);
+        }
+        if simple_checksum(bytes) != 0 {
+            return Err(IgmpParseError::InvalidChecksum);
+        }
+
+        let type_ = bytes[0];
+        let group = Ipv4Addr::from_octets([bytes[4], bytes[5], bytes[6], bytes[7]]);
+        let message = match type_ {
+            IGMP_MEMBERSHIP_QUERY => {
+                // 长度在 9 到 11 字节之间的查询无法判断版本，直接丢弃
+                let v3 = match bytes.len() {
+                    8 => None,
+                    9..=11 => return Err(IgmpParseError::InvalidLength),
+                    _ => {
+                        let num_sources = u16::from_be_bytes([bytes[10], bytes[11]]) as usize;
+                        Some(Igmpv3Query {
+                            suppress_router_processing: bytes[8] & 0x08 != 0,
+                            robustness: bytes[8] & 0x07,
+                            qqic: bytes[9],
+                            sources: parse_addrs(&bytes[12..], num_sources)?,
+                        })
+                    }
+                };
+                Self::Query {
+                    max_resp_code: bytes[1],
+                    group,
+                    v3,
+                }
+            }
+            IGMP_V1_MEMBERSHIP_REPORT => Self::V1Report { group },
+            IGMP_V2_MEMBERSHIP_REPORT => Self::V2Report { group },
+            IGMP_V2_LEAVE_GROUP => Self::Leave { group },
+            IGMP_V3_MEMBERSHIP_REPORT => {
+                let count = u16::from_be_bytes([bytes[6], bytes[7]]) as usize;
+                Self::V3Report {
+                    records: parse_group_records(&bytes[8..], count)?,
+                }
+            }
+            _ => Self::Unknown {
+                type_,
+                data: bytes[1..].to_vec(),
+            },
+        };
+        Ok(message)
+    }
+
+    /// 编码为字节并填好校验和
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![self.type_(), 0, 0, 0];
+        match self {
+            Self::Query {
+                max_resp_code,
+                group,
+                v3,
+            } => {
+                bytes[1] = *max_resp_code;
+                bytes.extend_from_slice(&group.octets());
+                if let Some(v3) = v3 {
+                    bytes.push(
+                        ((v3.suppress_router_processing as u8) << 3) | (v3.robustness & 0x07),
+                    );
+                    bytes.push(v3.qqic);
+                    bytes.extend_from_slice(&(v3.sources.len() as u16).to_be_bytes());
+                    for source in &v3.sources {
+                        bytes.extend_from_slice(&source.octets());
+                    }
+                }
+            }
+            Self::V1Report { group } | Self::V2Report { group } | Self::Leave { group } => {
+                bytes.extend_from_slice(&group.octets());
+            }
+            Self::V3Report { records } => {
+                bytes.extend_from_slice(&[0, 0]);
+                bytes.extend_from_slice(&(records.len() as u16).to_be_bytes());
+                for record in records {
+                    bytes.push(record.record_type.code());
+                    bytes.push((record.aux_data.len() / 4) as u8);
+                    bytes.extend_from_slice(&(record.sources.len() as u16).to_be_bytes());
+                    bytes.extend_from_slice(&record.group.octets());
+                    for source in &record.sources {
+                        bytes.extend_from_slice(&source.octets());
+                    }
+                    bytes.extend_from_slice(&record.aux_data);
+                }
+            }
+            Self::Unknown { data, .. } => {
+                bytes.truncate(1);
+                bytes.extend_from_slice(data);
+                bytes[2..4].copy_from_slice(&[0, 0]);
+            }
+        }
+
+        let checksum = simple_checksum(&bytes);
+        bytes[2..4].copy_from_slice(&checksum.to_be_bytes());
+        bytes
+    }
+}
+
+impl fmt::Display for IgmpMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Query { group, .. } => {
+                let version = self.query_version().unwrap_or_default();
+                let max_resp = self.max_resp_time().unwrap_or_default();
+                if *group == Ipv4Addr::unspecified() {
+                    write!(f, "IGMPv{} General Query: max_resp={:?}", version, max_resp)
+                } else {
+                    write!(
+                        f,
+                        "IGMPv{} Group-Specific Query: group={}, max_resp={:?}",
+                        version, group, max_resp
+                    )
+                }
+            }
+            Self::V1Report { group } => write!(f, "IGMPv1 Membership Report: group={}", group),
+            Self::V2Report { group } => write!(f, "IGMPv2 Membership Report: group={}", group),
+            Self::Leave { group } => write!(f, "IGMPv2 Leave Group: group={}", group),
+            Self::V3Report { records } => {
+                write!(f, "IGMPv3 Membership Report: [")?;
+                for (i, record) in records.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{:?} {}", record.record_type, record.group)?;
+                }
+                write!(f, "]")
+            }
+            Self::Unknown { type_, .. } => write!(f, "IGMP Unknown Type: type={:#04x}", type_),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GROUP: Ipv4Addr = Ipv4Addr::new(239, 1, 2, 3);
+
+    /// 重新填写校验和，用于构造 to_bytes 无法产生的报文
+    fn with_checksum(mut bytes: Vec<u8>) -> Vec<u8> {
+        bytes[2..4].copy_from_slice(&[0, 0]);
+        let checksum = simple_checksum(&bytes);
+        bytes[2..4].copy_from_slice(&checksum.to_be_bytes());
+        bytes
+    }
+
+    #[test]
+    fn v1_and_v2_queries_round_trip() {
+        let v1 = IgmpMessage::Query {
+            max_resp_code: 0,
+            group: Ipv4Addr::unspecified(),
+            v3: None,
+        };
+        let bytes = v1.to_bytes();
+        assert_eq!(bytes.len(), 8);
+        assert_eq!(IgmpMessage::parse(&bytes), Ok(v1.clone()));
+        assert_eq!(v1.query_version(), Some(1));
+        assert_eq!(v1.max_resp_time(), Some(IGMP_V1_MAX_RESP_TIME));
+
+        let v2 = IgmpMessage::Query {
+            max_resp_code: 100,
+            group: GROUP,
+            v3: None,
+        };
+        let bytes = v2.to_bytes();
+        assert_eq!(bytes.len(), 8);
+        assert_eq!(IgmpMessage::parse(&bytes), Ok(v2.clone()));
+        assert_eq!(v2.query_version(), Some(2));
+        // v2 的 max resp code 不是浮点编码，200 即 20 秒
+        let v2 = IgmpMessage::Query {
+            max_resp_code: 200,
+            group: GROUP,
+            v3: None,
+        };
+        assert_eq!(v2.max_resp_time(), Some(Duration::from_secs(20)));
+    }
+
+    #[test]
+    fn v3_query_round_trip() {
+        let query = IgmpMessage::Query {
+            max_resp_code: 0x8a,
+            group: GROUP,
+            v3: Some(Igmpv3Query {
+                suppress_router_processing: true,
+                robustness: 2,
+                qqic: 125,
+                sources: vec![Ipv4Addr::new(10, 0, 0, 1), Ipv4Addr::new(10, 0, 0, 2)],
+            }),
+        };
+        let bytes = query.to_bytes();
+        assert_eq!(bytes.len(), 12 + 2 * 4);
+        assert_eq!(bytes[8], 0x0a);
+        assert_eq!(bytes[9], 125);
+        assert_eq!(IgmpMessage::parse(&bytes), Ok(query.clone()));
+        assert_eq!(query.query_version(), Some(3));
+        // 0x8a = 指数 0、尾数 10：(0x10 | 10) << 3 = 208 个 0.1 秒
+        assert_eq!(query.max_resp_time(), Some(Duration::from_millis(20_800)));
+    }
+
+    #[test]
+    fn decode_float_follows_rfc_3376() {
+        assert_eq!(decode_float(0), 0);
+        assert_eq!(decode_float(100), 100);
+        assert_eq!(decode_float(127), 127);
+        assert_eq!(decode_float(0x80), 128);
+        assert_eq!(decode_float(0x8a), 208);
+        assert_eq!(decode_float(0xff), 31 << 10);
+    }
+
+    #[test]
+    fn queries_of_nine_to_eleven_bytes_are_rejected() {
+        let mut bytes = IgmpMessage::Query {
+            max_resp_code: 100,
+            group: GROUP,
+            v3: None,
+        }
+        .to_bytes();
+        for len in 9..=11 {
+            bytes.resize(len, 0);
+            let bytes = with_checksum(bytes.clone());
+            assert_eq!(
+                IgmpMessage::parse(&bytes),
+                Err(IgmpParseError::InvalidLength)
+            );
+        }
+    }
+
+    #[test]
+    fn truncated_source_list_is_rejected() {
+        let mut bytes = IgmpMessage::Query {
+            max_resp_code: 100,
+            group: GROUP,
+            v3: Some(Igmpv3Query {
+                suppress_router_processing: false,
+                robustness: 2,
+                qqic: 125,
+                sources: vec![Ipv4Addr::new(10, 0, 0, 1)],
+            }),
+        }
+        .to_bytes();
+        bytes[11] = 2;
+        let bytes = with_checksum(bytes);
+        assert_eq!(
+            IgmpMessage::parse(&bytes),
+            Err(IgmpParseError::InvalidLength)
+        );
+    }
+
+    #[test]
+    fn v3_report_round_trip() {
+        let report = IgmpMessage::V3Report {
+            records: vec![
+                GroupRecord::any_source(GroupRecordType::ModeIsExclude, GROUP),
+                GroupRecord {
+                    record_type: GroupRecordType::ModeIsInclude,
+                    group: Ipv4Addr::new(239, 9, 9, 9),
+                    sources: vec![Ipv4Addr::new(10, 0, 0, 1), Ipv4Addr::new(10, 0, 0, 2)],
+                    aux_data: vec![1, 2, 3, 4],
+                },
+                GroupRecord::any_source(GroupRecordType::Unknown(9), GROUP),
+            ],
+        };
+        let bytes = report.to_bytes();
+        assert_eq!(bytes.len(), 8 + 8 + (8 + 8 + 4) + 8);
+        // 第二条记录的 Aux Data Len 以 4 字节为单位
+        assert_eq!(bytes[17], 1);
+        assert_eq!(IgmpMessage::parse(&bytes), Ok(report));
+    }
+
+    #[test]
+    fn bad_checksum_is_rejected() {
+        let mut bytes = IgmpMessage::V2Report { group: GROUP }.to_bytes();
+        bytes[7] ^= 1;
+        assert_eq!(
+            IgmpMessage::parse(&bytes),
+            Err(IgmpParseError::InvalidChecksum)
+        );
+    }
+}
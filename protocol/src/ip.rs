@@ -0,0 +1,78 @@
+// Copyright (C) 2025 rrrrrzy
+// SPDX-License-Identifier: GPL-3.0-or-later
+//
+// --------------------------------------------------
+// 致敬所有在深夜调试代码的灵魂。
+// 即便 Bug 如山，我亦往矣。
+// --------------------------------------------------
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+//! 不区分版本的 IP 地址，供同时支持 IPv4 和 IPv6 的上层使用
+
+use crate::ipv4::Ipv4Addr;
+use crate::ipv6::Ipv6Addr;
+use std::fmt;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum IpAddr {
+    V4(Ipv4Addr),
+    V6(Ipv6Addr),
+}
+
+impl IpAddr {
+    pub fn is_ipv4(&self) -> bool {
+        matches!(self, Self::V4(_))
+    }
+
+    pub fn is_ipv6(&self) -> bool {
+        matches!(self, Self::V6(_))
+    }
+
+    /// 0.0.0.0 或 ::
+    pub fn is_unspecified(&self) -> bool {
+        match self {
+            Self::V4(addr) => *addr == Ipv4Addr::unspecified(),
+            Self::V6(addr) => addr.is_unspecified(),
+        }
+    }
+
+    pub fn is_multicast(&self) -> bool {
+        match self {
+            Self::V4(addr) => addr.is_multicast(),
+            Self::V6(addr) => addr.is_multicast(),
+        }
+    }
+
+    /// 与自身同一版本的未指定地址
+    pub fn unspecified_of_same_family(&self) -> Self {
+        match self {
+            Self::V4(_) => Self::V4(Ipv4Addr::unspecified()),
+            Self::V6(_) => Self::V6(Ipv6Addr::unspecified()),
+        }
+    }
+}
+
+impl From<Ipv4Addr> for IpAddr {
+    fn from(addr: Ipv4Addr) -> Self {
+        Self::V4(addr)
+    }
+}
+
+impl From<Ipv6Addr> for IpAddr {
+    fn from(addr: Ipv6Addr) -> Self {
+        Self::V6(addr)
+    }
+}
+
+impl fmt::Display for IpAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::V4(addr) => write!(f, "{}", addr),
+            Self::V6(addr) => write!(f, "{}", addr),
+        }
+    }
+}
@@ -12,6 +12,7 @@
 // (at your option) any later version.
 
 use crate::checksum::simple_checksum;
+use crate::error::Ipv4FragmentError;
 use crate::error::Ipv4HeaderParseError;
 use crate::error::Ipv4ParseError;
 use std::fmt;
@@ -37,6 +38,16 @@ impl Ipv4Addr {
         self.0
     }
 
+    /// 转换为主机字节序的 u32，便于做掩码运算
+    pub const fn to_bits(&self) -> u32 {
+        u32::from_be_bytes(self.0)
+    }
+
+    /// 从主机字节序的 u32 构造
+    pub const fn from_bits(bits: u32) -> Self {
+        Self(bits.to_be_bytes())
+    }
+
     /// 广播地址 255.255.255.255
     pub const fn broadcast() -> Self {
         Self([255, 255, 255, 255])
@@ -101,26 +112,302 @@ impl fmt::Display for Ipv4ParseError {
 
 impl std::error::Error for Ipv4ParseError {}
 
+/// 首部 flags 字段 (3 bit) 中的 DF 位
+pub const IPV4_FLAG_DF: u8 = 0b010;
+/// 首部 flags 字段 (3 bit) 中的 MF 位
+pub const IPV4_FLAG_MF: u8 = 0b001;
+
+pub const IPV4_OPT_END: u8 = 0;
+pub const IPV4_OPT_NOP: u8 = 1;
+pub const IPV4_OPT_RECORD_ROUTE: u8 = 7;
+pub const IPV4_OPT_TIMESTAMP: u8 = 68;
+pub const IPV4_OPT_LSRR: u8 = 131;
+pub const IPV4_OPT_SSRR: u8 = 137;
+pub const IPV4_OPT_ROUTER_ALERT: u8 = 148;
+
+/// 选项部分最长 40 字节 (IHL 最大 15)
+pub const IPV4_MAX_OPTIONS_LEN: usize = 40;
+
+/// Timestamp 选项的 flag 字段 (RFC 791)
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimestampFlag {
+    /// 0: 只记录时间戳
+    TimestampsOnly,
+    /// 1: 每个路由器记录地址和时间戳
+    AddressAndTimestamp,
+    /// 3: 只有预先指定地址的路由器记录时间戳
+    Prespecified,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimestampEntry {
+    /// flag 为 TimestampsOnly 时为 None
+    pub addr: Option<Ipv4Addr>,
+    pub timestamp: u32,
+}
+
+/// IPv4 首部选项
+///
+/// 路由类选项中的地址列表包含尚未填写的空槽，保证解析后再编码与原始字节一致。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Ipv4Option {
+    EndOfList,
+    NoOperation,
+    RecordRoute {
+        pointer: u8,
+        route: Vec<Ipv4Addr>,
+    },
+    Timestamp {
+        pointer: u8,
+        overflow: u8,
+        flag: TimestampFlag,
+        entries: Vec<TimestampEntry>,
+    },
+    /// RFC 2113
+    RouterAlert(u16),
+    LooseSourceRoute {
+        pointer: u8,
+        route: Vec<Ipv4Addr>,
+    },
+    StrictSourceRoute {
+        pointer: u8,
+        route: Vec<Ipv4Addr>,
+    },
+    /// 不认识的选项，data 不含类型和长度两个字节
+    Unknown {
+        kind: u8,
+        data: Vec<u8>,
+    },
+}
+
+impl Ipv4Option {
+    pub fn kind(&self) -> u8 {
+        match self {
+            Self::EndOfList => IPV4_OPT_END,
+            Self::NoOperation => IPV4_OPT_NOP,
+            Self::RecordRoute { .. } => IPV4_OPT_RECORD_ROUTE,
+            Self::Timestamp { .. } => IPV4_OPT_TIMESTAMP,
+            Self::RouterAlert(_) => IPV4_OPT_ROUTER_ALERT,
+            Self::LooseSourceRoute { .. } => IPV4_OPT_LSRR,
+            Self::StrictSourceRoute { .. } => IPV4_OPT_SSRR,
+            Self::Unknown { kind, .. } => *kind,
+        }
+    }
+
+    /// copied 位为 1 的选项在分片时需要复制到每一片
+    pub fn is_copied(&self) -> bool {
+        self.kind() & 0x80 != 0
+    }
+
+    /// 编码后的字节数
+    #[allow(clippy::len_without_is_empty)]
+    pub fn len(&self) -> usize {
+        match self {
+            Self::EndOfList | Self::NoOperation => 1,
+            Self::RecordRoute { route, .. }
+            | Self::LooseSourceRoute { route, .. }
+            | Self::StrictSourceRoute { route, .. } => 3 + route.len() * 4,
+            Self::Timestamp { flag, entries, .. } => {
+                let entry_len = match flag {
+                    TimestampFlag::TimestampsOnly => 4,
+                    _ => 8,
+                };
+                4 + entries.len() * entry_len
+            }
+            Self::RouterAlert(_) => 4,
+            Self::Unknown { data, .. } => 2 + data.len(),
+        }
+    }
+
+    /// 解析一个多字节选项，`data` 不含类型和长度字节
+    fn parse(kind: u8, data: &[u8]) -> Result<Self, Ipv4HeaderParseError> {
+        let parse_route = |data: &[u8]| -> Result<(u8, Vec<Ipv4Addr>), Ipv4HeaderParseError> {
+            let (&pointer, addrs) = data
+                .split_first()
+                .ok_or(Ipv4HeaderParseError::InvalidOption)?;
+            if !addrs.len().is_multiple_of(4) {
+                return Err(Ipv4HeaderParseError::InvalidOption);
+            }
+            let route = addrs
+                .chunks_exact(4)
+                .map(|c| Ipv4Addr::new(c[0], c[1], c[2], c[3]))
+                .collect();
+            Ok((pointer, route))
+        };
+
+        let option = match kind {
+            IPV4_OPT_RECORD_ROUTE => {
+                let (pointer, route) = parse_route(data)?;
+                Self::RecordRoute { pointer, route }
+            }
+            IPV4_OPT_LSRR => {
+                let (pointer, route) = parse_route(data)?;
+                Self::LooseSourceRoute { pointer, route }
+            }
+            IPV4_OPT_SSRR => {
+                let (pointer, route) = parse_route(data)?;
+                Self::StrictSourceRoute { pointer, route }
+            }
+            IPV4_OPT_ROUTER_ALERT => match data {
+                [a, b] => Self::RouterAlert(u16::from_be_bytes([*a, *b])),
+                _ => return Err(Ipv4HeaderParseError::InvalidOption),
+            },
+            IPV4_OPT_TIMESTAMP => {
+                let [pointer, oflw_flg, rest @ ..] = data else {
+                    return Err(Ipv4HeaderParseError::InvalidOption);
+                };
+                let flag = match oflw_flg & 0x0F {
+                    0 => TimestampFlag::TimestampsOnly,
+                    1 => TimestampFlag::AddressAndTimestamp,
+                    3 => TimestampFlag::Prespecified,
+                    // 未定义的 flag 原样保留
+                    _ => {
+                        return Ok(Self::Unknown {
+                            kind,
+                            data: data.to_vec(),
+                        });
+                    }
+                };
+
+                let entry_len = if flag == TimestampFlag::TimestampsOnly {
+                    4
+                } else {
+                    8
+                };
+                if !rest.len().is_multiple_of(entry_len) {
+                    return Err(Ipv4HeaderParseError::InvalidOption);
+                }
+                let entries = rest
+                    .chunks_exact(entry_len)
+                    .map(|c| match c {
+                        [a, b, c, d] => TimestampEntry {
+                            addr: None,
+                            timestamp: u32::from_be_bytes([*a, *b, *c, *d]),
+                        },
+                        _ => TimestampEntry {
+                            addr: Some(Ipv4Addr::new(c[0], c[1], c[2], c[3])),
+                            timestamp: u32::from_be_bytes([c[4], c[5], c[6], c[7]]),
+                        },
+                    })
+                    .collect();
+
+                Self::Timestamp {
+                    pointer: *pointer,
+                    overflow: oflw_flg >> 4,
+                    flag,
+                    entries,
+                }
+            }
+            _ => Self::Unknown {
+                kind,
+                data: data.to_vec(),
+            },
+        };
+        Ok(option)
+    }
+
+    pub fn write_to(&self, buf: &mut Vec<u8>) {
+        buf.push(self.kind());
+        match self {
+            Self::EndOfList | Self::NoOperation => return,
+            _ => buf.push(self.len() as u8),
+        }
+
+        match self {
+            Self::RecordRoute { pointer, route }
+            | Self::LooseSourceRoute { pointer, route }
+            | Self::StrictSourceRoute { pointer, route } => {
+                buf.push(*pointer);
+                for addr in route {
+                    buf.extend_from_slice(&addr.octets());
+                }
+            }
+            Self::Timestamp {
+                pointer,
+                overflow,
+                flag,
+                entries,
+            } => {
+                let flag = match flag {
+                    TimestampFlag::TimestampsOnly => 0,
+                    TimestampFlag::AddressAndTimestamp => 1,
+                    TimestampFlag::Prespecified => 3,
+                };
+                buf.push(*pointer);
+                buf.push((overflow << 4) | flag);
+                for entry in entries {
+                    if let Some(addr) = entry.addr {
+                        buf.extend_from_slice(&addr.octets());
+                    }
+                    buf.extend_from_slice(&entry.timestamp.to_be_bytes());
+                }
+            }
+            Self::RouterAlert(value) => buf.extend_from_slice(&value.to_be_bytes()),
+            Self::Unknown { data, .. } => buf.extend_from_slice(data),
+            Self::EndOfList | Self::NoOperation => {}
+        }
+    }
+}
+
+/// 解析首部中 20 字节之后的选项区，End of Option List 之后的字节视为填充
+pub fn parse_options(bytes: &[u8]) -> Result<Vec<Ipv4Option>, Ipv4HeaderParseError> {
+    let mut options = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            IPV4_OPT_END => {
+                options.push(Ipv4Option::EndOfList);
+                break;
+            }
+            IPV4_OPT_NOP => {
+                options.push(Ipv4Option::NoOperation);
+                i += 1;
+            }
+            kind => {
+                let len = *bytes
+                    .get(i + 1)
+                    .ok_or(Ipv4HeaderParseError::InvalidOption)? as usize;
+                if len < 2 || i + len > bytes.len() {
+                    return Err(Ipv4HeaderParseError::InvalidOption);
+                }
+                options.push(Ipv4Option::parse(kind, &bytes[i + 2..i + len])?);
+                i += len;
+            }
+        }
+    }
+    Ok(options)
+}
+
+/// 编码选项列表，不含末尾填充
+pub fn options_to_bytes(options: &[Ipv4Option]) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(options.iter().map(Ipv4Option::len).sum());
+    for option in options {
+        option.write_to(&mut buf);
+    }
+    buf
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Ipv4Header {
-    pub version: u8,      // 4 bits
-    pub ihl: u8,          // 4 bits (Internet Header Length)
-    pub tos: u8,          // Type of Service
-    pub total_len: u16,   // Total length
-    pub id: u16,          // Identification
-    pub flags: u8,        // 3 bits (R, DF, MF)
-    pub frag_offset: u16, // 13 bits
-    pub ttl: u8,          // Time to live
-    pub protocol: u8,     // Protocol (TCP = 6, UDP = 17, ICMP = 1)
-    pub checksum: u16,    // Header checksum
-    pub src: Ipv4Addr,    // Source address
-    pub dst: Ipv4Addr,    // Destination address
-                          // pub option: [u8; 40], // Option + fill, not support now!
+    pub version: u8,              // 4 bits
+    pub ihl: u8,                  // 4 bits (Internet Header Length)
+    pub tos: u8,                  // Type of Service
+    pub total_len: u16,           // Total length
+    pub id: u16,                  // Identification
+    pub flags: u8,                // 3 bits (R, DF, MF)
+    pub frag_offset: u16,         // 13 bits
+    pub ttl: u8,                  // Time to live
+    pub protocol: u8,             // Protocol (TCP = 6, UDP = 17, ICMP = 1)
+    pub checksum: u16,            // Header checksum
+    pub src: Ipv4Addr,            // Source address
+    pub dst: Ipv4Addr,            // Destination address
+    pub options: Vec<Ipv4Option>, // Options, 编码后按 4 字节补齐，长度由 ihl 决定
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Ipv4Protocol {
     ICMP,
+    IGMP,
     TCP,
     UDP,
     Unknown,
@@ -128,7 +415,7 @@ pub enum Ipv4Protocol {
 
 impl Ipv4Header {
     pub fn new(src: Ipv4Addr, dst: Ipv4Addr, protocol: u8, payload_len: u16, id: u16) -> Self {
-        // notice that we do not support option and fill currently
+        // 不带选项，需要时再调用 set_options
         let total_len = payload_len + 20;
         let mut header = Self {
             version: 4,      // IPv4
@@ -143,14 +430,37 @@ impl Ipv4Header {
             checksum: 0,     // temporarily set to 0
             src,             // set by user
             dst,             // set by user
-                             // option: [0; 40], // empty, we do not support it currently
+            options: Vec::new(),
         };
         header.checksum = header.checksum();
 
         header
     }
 
-    #[allow(clippy::clone_on_copy)] // 这里使用clone来显示声明拷贝了一份，用*self太Rust了，容易引起误解
+    /// 替换选项，同时更新 ihl、total_len (载荷长度不变) 和校验和
+    pub fn set_options(&mut self, options: Vec<Ipv4Option>) -> Result<(), Ipv4HeaderParseError> {
+        let options_len = options
+            .iter()
+            .map(Ipv4Option::len)
+            .sum::<usize>()
+            .next_multiple_of(4);
+        if options_len > IPV4_MAX_OPTIONS_LEN {
+            return Err(Ipv4HeaderParseError::InvalidOption);
+        }
+
+        let payload_len = self.total_len as usize - self.header_len();
+        self.ihl = ((20 + options_len) / 4) as u8;
+        self.total_len = (self.header_len() + payload_len) as u16;
+        self.options = options;
+        self.checksum = self.checksum();
+        Ok(())
+    }
+
+    /// 首部长度 (字节)
+    pub fn header_len(&self) -> usize {
+        self.ihl as usize * 4
+    }
+
     pub fn checksum(&self) -> u16 {
         if self.checksum != 0 {
             let mut ipv4_header = self.clone();
@@ -164,14 +474,16 @@ impl Ipv4Header {
     pub fn get_protocol(&self) -> Ipv4Protocol {
         match self.protocol {
             1 => Ipv4Protocol::ICMP,
+            2 => Ipv4Protocol::IGMP,
             6 => Ipv4Protocol::TCP,
             17 => Ipv4Protocol::UDP,
             _ => Ipv4Protocol::Unknown,
         }
     }
 
-    pub fn to_bytes(&self) -> [u8; 20] {
-        let mut bytes: [u8; 20] = [0; 20];
+    /// 编码首部，选项之后补 0 直到 ihl 指定的长度
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![0; 20];
         bytes[0] = (self.version << 4) + self.ihl;
         bytes[1] = self.tos;
         bytes[2..4].copy_from_slice(&self.total_len.to_be_bytes());
@@ -184,17 +496,37 @@ impl Ipv4Header {
         bytes[12..16].copy_from_slice(&self.src.0);
         bytes[16..20].copy_from_slice(&self.dst.0);
 
+        for option in &self.options {
+            option.write_to(&mut bytes);
+        }
+        if bytes.len() < self.header_len() {
+            bytes.resize(self.header_len(), 0);
+        }
+
         bytes
     }
 
+    /// 检查本地构造的首部，校验和按当前字段的编码计算
+    ///
+    /// 收到的首部在 `parse` 中按原始字节校验，选项的原始编码 (如 End 之后的填充)
+    /// 不一定与重新编码的结果相同
     pub fn validate(&self) -> Result<(), Ipv4HeaderParseError> {
         if self.checksum != self.checksum() {
-            Err(Ipv4HeaderParseError::InvalidChecksum)
-        } else if self.version != 4 {
+            return Err(Ipv4HeaderParseError::InvalidChecksum);
+        }
+        self.validate_fields()
+    }
+
+    fn validate_fields(&self) -> Result<(), Ipv4HeaderParseError> {
+        if self.version != 4 {
             Err(Ipv4HeaderParseError::InvalidVersion)
         } else if self.ihl < 5 || self.ihl > 15 {
             // IHL 单位是 4字节，所以 5 代表 20字节，15 代表 60字节
             Err(Ipv4HeaderParseError::InvalidHeaderLength)
+        } else if (self.total_len as usize) < self.header_len() {
+            Err(Ipv4HeaderParseError::InvalidHeaderLength)
+        } else if 20 + options_to_bytes(&self.options).len() > self.header_len() {
+            Err(Ipv4HeaderParseError::InvalidOption)
         } else if self.ttl == 0 {
             Err(Ipv4HeaderParseError::InvalidTimeToLive)
         } else {
@@ -202,7 +534,51 @@ impl Ipv4Header {
         }
     }
 
+    /// 解析首部 (含选项)，`bytes` 可以带上后面的载荷
     pub fn parse(bytes: &[u8]) -> Result<Self, Ipv4HeaderParseError> {
+        let header_len = Self::wire_header_len(bytes)?;
+        // 在原始字节上校验，包含校验和字段在内的反码和为 0
+        if simple_checksum(&bytes[..header_len]) != 0 {
+            return Err(Ipv4HeaderParseError::InvalidChecksum);
+        }
+        let options = parse_options(&bytes[20..header_len])?;
+        let ipv4_header = Self::decode(bytes, options)?;
+        ipv4_header.validate_fields()?;
+        Ok(ipv4_header)
+    }
+
+    /// 解析 ICMP 差错报文引用的原始首部，只检查版本、IHL 和总长度
+    ///
+    /// 引用的首部可能已被途经路由器改写 (TTL 为 0、校验和未更新、NAT 改写地址等)，
+    /// 这里不校验校验和与 TTL，无法解析的选项按空处理
+    pub fn parse_quoted(bytes: &[u8]) -> Result<Self, Ipv4HeaderParseError> {
+        let header_len = Self::wire_header_len(bytes)?;
+        let options = parse_options(&bytes[20..header_len]).unwrap_or_default();
+        let ipv4_header = Self::decode(bytes, options)?;
+        if ipv4_header.version != 4 {
+            Err(Ipv4HeaderParseError::InvalidVersion)
+        } else if (ipv4_header.total_len as usize) < header_len {
+            Err(Ipv4HeaderParseError::InvalidHeaderLength)
+        } else {
+            Ok(ipv4_header)
+        }
+    }
+
+    /// 由 IHL 得到首部长度，并确认 `bytes` 足够容纳整个首部
+    fn wire_header_len(bytes: &[u8]) -> Result<usize, Ipv4HeaderParseError> {
+        if bytes.len() < 20 {
+            return Err(Ipv4HeaderParseError::InvalidHeaderLength);
+        }
+        let ihl = bytes[0] & 0x0F;
+        let header_len = ihl as usize * 4;
+        if ihl < 5 || bytes.len() < header_len {
+            return Err(Ipv4HeaderParseError::InvalidHeaderLength);
+        }
+        Ok(header_len)
+    }
+
+    /// 读出固定首部的各字段，调用方已确认长度
+    fn decode(bytes: &[u8], options: Vec<Ipv4Option>) -> Result<Self, Ipv4HeaderParseError> {
         let version = bytes[0] >> 4;
         let ihl = bytes[0] & 0x0F;
         let tos = bytes[1];
@@ -236,7 +612,7 @@ impl Ipv4Header {
                 .map_err(|_| Ipv4HeaderParseError::InvalidHeaderLength)?,
         );
 
-        let ipv4_header = Self {
+        Ok(Self {
             version,
             ihl,
             tos,
@@ -249,10 +625,8 @@ impl Ipv4Header {
             checksum,
             src,
             dst,
-        };
-
-        ipv4_header.validate()?;
-        Ok(ipv4_header)
+            options,
+        })
     }
 }
 
@@ -272,7 +646,8 @@ impl fmt::Display for Ipv4Header {
     Protocol: {} ({:?})
     Checksum: {:#06x}
     Source: {}
-    Destination: {}",
+    Destination: {}
+    Options: {:?}",
             self.version,
             self.ihl,
             self.ihl * 4,
@@ -288,7 +663,429 @@ impl fmt::Display for Ipv4Header {
             self.get_protocol(),
             self.checksum,
             self.src,
-            self.dst
+            self.dst,
+            self.options
+        )
+    }
+}
+
+/// 按 MTU 把一个完整的 IPv4 数据报切分为若干分片 (RFC 791)
+///
+/// 数据报不超过 MTU 时原样返回一个元素；输入本身是分片时会保留其偏移和 MF 位。
+/// 首个分片带上全部选项，后续分片只带 copied 位为 1 的选项。
+pub fn fragment_datagram(datagram: &[u8], mtu: usize) -> Result<Vec<Vec<u8>>, Ipv4FragmentError> {
+    if datagram.len() < 20 {
+        return Err(Ipv4FragmentError::InvalidDatagram);
+    }
+    let header_len = (datagram[0] & 0x0F) as usize * 4;
+    let total_len = u16::from_be_bytes([datagram[2], datagram[3]]) as usize;
+    if header_len < 20 || total_len < header_len || total_len > datagram.len() {
+        return Err(Ipv4FragmentError::InvalidDatagram);
+    }
+    let datagram = &datagram[..total_len];
+
+    if total_len <= mtu {
+        return Ok(vec![datagram.to_vec()]);
+    }
+
+    let flags_offset = u16::from_be_bytes([datagram[6], datagram[7]]);
+    if flags_offset & ((IPV4_FLAG_DF as u16) << 13) != 0 {
+        return Err(Ipv4FragmentError::DontFragment);
+    }
+    let base_offset = (flags_offset & 0x1FFF) as usize * 8;
+    let original_more = flags_offset & ((IPV4_FLAG_MF as u16) << 13) != 0;
+
+    let first_header = &datagram[..header_len];
+    let later_header = copied_options_header(first_header);
+    let data = &datagram[header_len..];
+
+    let mut fragments = Vec::new();
+    let mut offset = 0usize;
+    while offset < data.len() {
+        let header = if offset == 0 {
+            first_header
+        } else {
+            &later_header[..]
+        };
+
+        // 除最后一片外，每片数据长度必须是 8 的倍数
+        let room = mtu.saturating_sub(header.len()) & !7;
+        if room == 0 {
+            return Err(Ipv4FragmentError::MtuTooSmall);
+        }
+
+        let chunk_len = room.min(data.len() - offset);
+        let more = offset + chunk_len < data.len() || original_more;
+        fragments.push(build_fragment(
+            header,
+            &data[offset..offset + chunk_len],
+            base_offset + offset,
+            more,
+        ));
+        offset += chunk_len;
+    }
+
+    Ok(fragments)
+}
+
+fn build_fragment(header: &[u8], data: &[u8], offset: usize, more: bool) -> Vec<u8> {
+    let mut packet = Vec::with_capacity(header.len() + data.len());
+    packet.extend_from_slice(header);
+    packet.extend_from_slice(data);
+
+    packet[0] = (4 << 4) | (header.len() / 4) as u8;
+    let total_len = packet.len() as u16;
+    packet[2..4].copy_from_slice(&total_len.to_be_bytes());
+
+    let mut flags_offset = ((offset / 8) as u16) & 0x1FFF;
+    if more {
+        flags_offset |= (IPV4_FLAG_MF as u16) << 13;
+    }
+    packet[6..8].copy_from_slice(&flags_offset.to_be_bytes());
+
+    packet[10..12].fill(0);
+    let checksum = simple_checksum(&packet[..header.len()]);
+    packet[10..12].copy_from_slice(&checksum.to_be_bytes());
+
+    packet
+}
+
+/// 生成后续分片使用的首部：固定 20 字节 + copied 位为 1 的选项，补齐到 4 字节边界
+fn copied_options_header(header: &[u8]) -> Vec<u8> {
+    let mut result = header[..20].to_vec();
+    let mut i = 20;
+    while i < header.len() {
+        match header[i] {
+            // End of Option List
+            0 => break,
+            // No Operation
+            1 => i += 1,
+            kind => {
+                let Some(&len) = header.get(i + 1) else {
+                    break;
+                };
+                let len = len as usize;
+                if len < 2 || i + len > header.len() {
+                    break;
+                }
+                if kind & 0x80 != 0 {
+                    result.extend_from_slice(&header[i..i + len]);
+                }
+                i += len;
+            }
+        }
+    }
+    while !result.len().is_multiple_of(4) {
+        result.push(0);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header_with_options(options: &[u8]) -> Vec<u8> {
+        let mut header = Ipv4Header::new(
+            Ipv4Addr::new(10, 0, 0, 1),
+            Ipv4Addr::new(10, 0, 0, 2),
+            17,
+            0,
+            0x1234,
         )
+        .to_bytes();
+        header[0] = 0x40 | ((20 + options.len()) / 4) as u8;
+        header[2..4].copy_from_slice(&((20 + options.len()) as u16).to_be_bytes());
+        header.extend_from_slice(options);
+        header[10..12].copy_from_slice(&[0, 0]);
+        let checksum = simple_checksum(&header);
+        header[10..12].copy_from_slice(&checksum.to_be_bytes());
+        header
+    }
+
+    #[test]
+    fn header_round_trips() {
+        let mut header = Ipv4Header::new(
+            Ipv4Addr::new(192, 168, 1, 1),
+            Ipv4Addr::new(192, 168, 1, 2),
+            6,
+            100,
+            7,
+        );
+        header
+            .set_options(vec![Ipv4Option::RouterAlert(0)])
+            .unwrap();
+        assert_eq!(header.header_len(), 24);
+        assert_eq!(header.total_len, 124);
+
+        let parsed = Ipv4Header::parse(&header.to_bytes()).unwrap();
+        assert_eq!(parsed, header);
+        assert!(parsed.validate().is_ok());
+    }
+
+    #[test]
+    fn options_round_trip() {
+        let options = vec![
+            Ipv4Option::NoOperation,
+            Ipv4Option::RecordRoute {
+                pointer: 8,
+                route: vec![Ipv4Addr::new(10, 0, 0, 254), Ipv4Addr::unspecified()],
+            },
+            Ipv4Option::Timestamp {
+                pointer: 5,
+                overflow: 3,
+                flag: TimestampFlag::TimestampsOnly,
+                entries: vec![TimestampEntry {
+                    addr: None,
+                    timestamp: 0x0102_0304,
+                }],
+            },
+            Ipv4Option::Timestamp {
+                pointer: 13,
+                overflow: 0,
+                flag: TimestampFlag::AddressAndTimestamp,
+                entries: vec![TimestampEntry {
+                    addr: Some(Ipv4Addr::new(10, 0, 0, 1)),
+                    timestamp: 42,
+                }],
+            },
+            Ipv4Option::Unknown {
+                kind: 0x9e,
+                data: vec![0xaa, 0xbb],
+            },
+        ];
+        let bytes = options_to_bytes(&options);
+        assert_eq!(bytes.len(), 1 + 11 + 8 + 12 + 4);
+        assert_eq!(parse_options(&bytes), Ok(options.clone()));
+        // 编码时按 ihl 补齐到 4 字节边界
+        let mut header = Ipv4Header::new(
+            Ipv4Addr::new(10, 0, 0, 1),
+            Ipv4Addr::new(10, 0, 0, 2),
+            17,
+            0,
+            1,
+        );
+        header.set_options(options.clone()).unwrap();
+        assert_eq!(header.header_len(), 20 + 36);
+        assert_eq!(
+            Ipv4Header::parse(&header.to_bytes()).unwrap().options,
+            options
+        );
+
+        let source_route = vec![
+            Ipv4Option::LooseSourceRoute {
+                pointer: 4,
+                route: vec![Ipv4Addr::new(192, 168, 1, 1)],
+            },
+            Ipv4Option::StrictSourceRoute {
+                pointer: 4,
+                route: vec![Ipv4Addr::new(192, 168, 2, 1)],
+            },
+        ];
+        assert_eq!(
+            parse_options(&options_to_bytes(&source_route)),
+            Ok(source_route)
+        );
+    }
+
+    #[test]
+    fn malformed_options_are_rejected() {
+        // 长度小于 2、截断、Router Alert 长度不对、路由地址不是 4 字节对齐
+        for bytes in [
+            &[IPV4_OPT_RECORD_ROUTE, 1][..],
+            &[IPV4_OPT_RECORD_ROUTE][..],
+            &[IPV4_OPT_ROUTER_ALERT, 3, 0][..],
+            &[IPV4_OPT_RECORD_ROUTE, 5, 4, 0, 0][..],
+            &[IPV4_OPT_TIMESTAMP, 6, 5, 1, 0, 0][..],
+        ] {
+            assert_eq!(
+                parse_options(bytes),
+                Err(Ipv4HeaderParseError::InvalidOption),
+                "{:?}",
+                bytes
+            );
+        }
+        // 选项总长超过 40 字节
+        let mut header = Ipv4Header::new(
+            Ipv4Addr::new(10, 0, 0, 1),
+            Ipv4Addr::new(10, 0, 0, 2),
+            17,
+            0,
+            1,
+        );
+        let too_long = vec![Ipv4Option::RecordRoute {
+            pointer: 4,
+            route: vec![Ipv4Addr::unspecified(); 10],
+        }];
+        assert_eq!(
+            header.set_options(too_long),
+            Err(Ipv4HeaderParseError::InvalidOption)
+        );
+    }
+
+    // End 之后的填充不是 0，重新编码会得到不同的字节，校验和必须按原始字节计算
+    #[test]
+    fn checksum_is_verified_on_wire_bytes() {
+        let bytes = header_with_options(&[IPV4_OPT_END, 0xAA, 0xBB, 0xCC]);
+        let header = Ipv4Header::parse(&bytes).unwrap();
+        assert_eq!(header.options, vec![Ipv4Option::EndOfList]);
+        assert_eq!(header.header_len(), 24);
+
+        let mut corrupted = bytes.clone();
+        corrupted[23] ^= 0x01;
+        assert_eq!(
+            Ipv4Header::parse(&corrupted),
+            Err(Ipv4HeaderParseError::InvalidChecksum)
+        );
+    }
+
+    #[test]
+    fn parse_rejects_malformed_headers() {
+        let bytes = header_with_options(&[]);
+        assert_eq!(
+            Ipv4Header::parse(&bytes[..19]),
+            Err(Ipv4HeaderParseError::InvalidHeaderLength)
+        );
+
+        // 选项长度超出首部
+        let bytes = header_with_options(&[IPV4_OPT_NOP, 0x94, 0x08, 0x00]);
+        assert_eq!(
+            Ipv4Header::parse(&bytes),
+            Err(Ipv4HeaderParseError::InvalidOption)
+        );
+
+        let mut bytes = header_with_options(&[]);
+        bytes[8] = 0;
+        bytes[10..12].copy_from_slice(&[0, 0]);
+        let checksum = simple_checksum(&bytes);
+        bytes[10..12].copy_from_slice(&checksum.to_be_bytes());
+        assert_eq!(
+            Ipv4Header::parse(&bytes),
+            Err(Ipv4HeaderParseError::InvalidTimeToLive)
+        );
+    }
+
+    #[test]
+    fn parse_quoted_ignores_checksum_and_ttl() {
+        // 途经路由器改写后的引用首部：TTL 为 0，校验和未更新，只带回 8 字节载荷
+        let mut bytes = header_with_options(&[]);
+        bytes[2..4].copy_from_slice(&1500u16.to_be_bytes());
+        bytes[8] = 0;
+        bytes.extend_from_slice(&[0x30, 0x39, 0x82, 0x9b, 0x00, 0x10, 0x00, 0x00]);
+        assert!(Ipv4Header::parse(&bytes).is_err());
+
+        let header = Ipv4Header::parse_quoted(&bytes).unwrap();
+        assert_eq!(header.src, Ipv4Addr::new(10, 0, 0, 1));
+        assert_eq!(header.get_protocol(), Ipv4Protocol::UDP);
+        assert_eq!(header.header_len(), 20);
+
+        bytes[0] = 0x65;
+        assert_eq!(
+            Ipv4Header::parse_quoted(&bytes),
+            Err(Ipv4HeaderParseError::InvalidVersion)
+        );
+        bytes[0] = 0x44;
+        assert_eq!(
+            Ipv4Header::parse_quoted(&bytes),
+            Err(Ipv4HeaderParseError::InvalidHeaderLength)
+        );
+        assert_eq!(
+            Ipv4Header::parse_quoted(&bytes[..19]),
+            Err(Ipv4HeaderParseError::InvalidHeaderLength)
+        );
+    }
+
+    /// 带 Record Route (不复制) 和 Router Alert (复制) 选项的数据报
+    fn datagram_with_options(payload_len: usize, flags: u8) -> Vec<u8> {
+        let mut header = Ipv4Header::new(
+            Ipv4Addr::new(10, 0, 0, 1),
+            Ipv4Addr::new(10, 0, 0, 2),
+            17,
+            payload_len as u16,
+            0x4242,
+        );
+        header.flags = flags;
+        header
+            .set_options(vec![
+                Ipv4Option::RecordRoute {
+                    pointer: 4,
+                    route: vec![Ipv4Addr::unspecified()],
+                },
+                Ipv4Option::RouterAlert(0),
+            ])
+            .unwrap();
+        let mut datagram = header.to_bytes();
+        datagram.extend((0..payload_len).map(|i| i as u8));
+        datagram
+    }
+
+    #[test]
+    fn fragments_reassemble_to_the_original_payload() {
+        let datagram = datagram_with_options(3000, 0);
+        let first_len = Ipv4Header::parse(&datagram).unwrap().header_len();
+        assert_eq!(first_len, 32);
+        let fragments = fragment_datagram(&datagram, 1500).unwrap();
+        assert_eq!(fragments.len(), 3);
+
+        let mut payload = Vec::new();
+        for (i, fragment) in fragments.iter().enumerate() {
+            assert!(fragment.len() <= 1500);
+            let header = Ipv4Header::parse(fragment).unwrap();
+            assert_eq!(header.id, 0x4242);
+            assert_eq!(header.total_len as usize, fragment.len());
+            assert_eq!(header.frag_offset as usize * 8, payload.len());
+            assert_eq!(header.flags & IPV4_FLAG_MF != 0, i < fragments.len() - 1);
+            if i == 0 {
+                assert_eq!(header.header_len(), first_len);
+            } else {
+                // 后续分片只带 copied 位为 1 的选项
+                assert_eq!(header.options, vec![Ipv4Option::RouterAlert(0)]);
+            }
+            let data = &fragment[header.header_len()..];
+            if i < fragments.len() - 1 {
+                assert_eq!(data.len() % 8, 0);
+            }
+            payload.extend_from_slice(data);
+        }
+        assert_eq!(payload, datagram[first_len..]);
+    }
+
+    #[test]
+    fn refragmenting_keeps_offset_and_more_fragments() {
+        // 已经是中间分片的数据报：偏移 1480 字节，MF = 1
+        let mut fragment = datagram_with_options(1000, IPV4_FLAG_MF);
+        fragment[6..8].copy_from_slice(&(((IPV4_FLAG_MF as u16) << 13) | 185).to_be_bytes());
+        let pieces = fragment_datagram(&fragment, 576).unwrap();
+        assert_eq!(pieces.len(), 2);
+        let last = Ipv4Header::parse(&pieces[1]).unwrap();
+        assert_ne!(last.flags & IPV4_FLAG_MF, 0);
+        let first = Ipv4Header::parse(&pieces[0]).unwrap();
+        assert_eq!(first.frag_offset, 185);
+        assert_eq!(
+            last.frag_offset as usize * 8,
+            1480 + pieces[0].len() - first.header_len()
+        );
+    }
+
+    #[test]
+    fn fragment_errors() {
+        let datagram = datagram_with_options(100, 0);
+        assert_eq!(
+            fragment_datagram(&datagram, 1500).unwrap(),
+            vec![datagram.clone()]
+        );
+        assert_eq!(
+            fragment_datagram(&datagram, 36),
+            Err(Ipv4FragmentError::MtuTooSmall)
+        );
+        assert_eq!(
+            fragment_datagram(&datagram[..10], 1500),
+            Err(Ipv4FragmentError::InvalidDatagram)
+        );
+        let datagram = datagram_with_options(100, IPV4_FLAG_DF);
+        assert_eq!(
+            fragment_datagram(&datagram, 68),
+            Err(Ipv4FragmentError::DontFragment)
+        );
     }
 }
@@ -0,0 +1,1377 @@
+// Copyright (C) 2025 rrrrrzy
+// SPDX-License-Identifier: GPL-3.0-or-later
+//
+// --------------------------------------------------
+// 致敬所有在深夜调试代码的灵魂。
+// 即便 Bug 如山，我亦往矣。
+// --------------------------------------------------
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+//! IPv6 地址与首部 (RFC 8200)，包括扩展首部链的解析与编码
+
+use crate::checksum::sha256;
+use crate::error::{Ipv6HeaderParseError, Ipv6ParseError};
+use crate::ipv4::Ipv4Addr;
+use crate::mac::MacAddr;
+use std::fmt;
+use std::str::FromStr;
+
+/// 自定义 IPv6 地址类型，和 `Ipv4Addr` 一样不依赖 `std::net`
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct Ipv6Addr([u8; 16]);
+
+/// 地址的作用域，组播取自地址中的 scope 字段 (RFC 4291 2.7, RFC 4007)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Ipv6Scope {
+    InterfaceLocal,
+    LinkLocal,
+    RealmLocal,
+    AdminLocal,
+    SiteLocal,
+    OrganizationLocal,
+    Global,
+}
+
+impl Ipv6Addr {
+    /// 从八个 16 位分段构造
+    #[allow(clippy::too_many_arguments)]
+    pub const fn new(a: u16, b: u16, c: u16, d: u16, e: u16, f: u16, g: u16, h: u16) -> Self {
+        Self::from_segments([a, b, c, d, e, f, g, h])
+    }
+
+    /// 从分段数组构造
+    pub const fn from_segments(segments: [u16; 8]) -> Self {
+        let mut octets = [0u8; 16];
+        let mut i = 0;
+        while i < 8 {
+            let [hi, lo] = segments[i].to_be_bytes();
+            octets[i * 2] = hi;
+            octets[i * 2 + 1] = lo;
+            i += 1;
+        }
+        Self(octets)
+    }
+
+    /// 从字节数组构造
+    pub const fn from_octets(octets: [u8; 16]) -> Self {
+        Self(octets)
+    }
+
+    /// 获取字节数组
+    pub const fn octets(&self) -> [u8; 16] {
+        self.0
+    }
+
+    /// 获取八个 16 位分段
+    pub const fn segments(&self) -> [u16; 8] {
+        let mut segments = [0u16; 8];
+        let mut i = 0;
+        while i < 8 {
+            segments[i] = u16::from_be_bytes([self.0[i * 2], self.0[i * 2 + 1]]);
+            i += 1;
+        }
+        segments
+    }
+
+    /// 转换为主机字节序的 u128，便于做前缀运算
+    pub const fn to_bits(&self) -> u128 {
+        u128::from_be_bytes(self.0)
+    }
+
+    /// 从主机字节序的 u128 构造
+    pub const fn from_bits(bits: u128) -> Self {
+        Self(bits.to_be_bytes())
+    }
+
+    /// 未指定地址 ::
+    pub const fn unspecified() -> Self {
+        Self([0; 16])
+    }
+
+    /// 本地回环地址 ::1
+    pub const fn localhost() -> Self {
+        Self::new(0, 0, 0, 0, 0, 0, 0, 1)
+    }
+
+    /// 链路上所有节点的组播地址 ff02::1
+    pub const fn all_nodes() -> Self {
+        Self::new(0xff02, 0, 0, 0, 0, 0, 0, 1)
+    }
+
+    /// 链路上所有路由器的组播地址 ff02::2
+    pub const fn all_routers() -> Self {
+        Self::new(0xff02, 0, 0, 0, 0, 0, 0, 2)
+    }
+
+    /// 该地址的请求节点组播地址 ff02::1:ffXX:XXXX，NDP 的邻居请求发往这里 (RFC 4291 2.7.1)
+    pub const fn solicited_node(&self) -> Self {
+        let [.., a, b, c] = self.0;
+        Self([0xff, 0x02, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x01, 0xff, a, b, c])
+    }
+
+    /// 取 prefix 的高 64 位，低 64 位换成接口标识
+    pub const fn from_prefix_and_interface_id(prefix: Ipv6Addr, interface_id: [u8; 8]) -> Self {
+        let mut octets = prefix.0;
+        let mut i = 0;
+        while i < 8 {
+            octets[8 + i] = interface_id[i];
+            i += 1;
+        }
+        Self(octets)
+    }
+
+    /// 低 64 位的接口标识
+    pub const fn interface_id(&self) -> [u8; 8] {
+        let [.., a, b, c, d, e, f, g, h] = self.0;
+        [a, b, c, d, e, f, g, h]
+    }
+
+    /// 由 MAC 地址生成的链路本地地址 fe80::/64 + 修改后的 EUI-64
+    pub fn link_local_eui64(mac: MacAddr) -> Self {
+        Self::from_prefix_and_interface_id(
+            Self::new(0xfe80, 0, 0, 0, 0, 0, 0, 0),
+            eui64_interface_id(mac),
+        )
+    }
+
+    /// IPv4 映射地址 ::ffff:a.b.c.d (RFC 4291 2.5.5.2)
+    pub const fn from_ipv4_mapped(addr: Ipv4Addr) -> Self {
+        let [a, b, c, d] = addr.octets();
+        Self([0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0xff, 0xff, a, b, c, d])
+    }
+
+    /// 如果是 IPv4 映射地址，取出其中的 IPv4 地址
+    pub fn to_ipv4_mapped(&self) -> Option<Ipv4Addr> {
+        match self.0 {
+            [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0xff, 0xff, a, b, c, d] => {
+                Some(Ipv4Addr::new(a, b, c, d))
+            }
+            _ => None,
+        }
+    }
+
+    pub fn is_unspecified(&self) -> bool {
+        self.0 == [0; 16]
+    }
+
+    pub fn is_loopback(&self) -> bool {
+        *self == Self::localhost()
+    }
+
+    /// 组播地址 ff00::/8
+    pub fn is_multicast(&self) -> bool {
+        self.0[0] == 0xff
+    }
+
+    /// 链路本地单播地址 fe80::/10
+    pub fn is_unicast_link_local(&self) -> bool {
+        self.0[0] == 0xfe && self.0[1] & 0xc0 == 0x80
+    }
+
+    /// 唯一本地地址 fc00::/7 (RFC 4193)
+    pub fn is_unique_local(&self) -> bool {
+        self.0[0] & 0xfe == 0xfc
+    }
+
+    /// 地址的作用域，未指定地址和保留的组播作用域返回 None
+    ///
+    /// 回环地址按 Linux 的习惯归为 InterfaceLocal；已废弃的站点本地地址 fec0::/10
+    /// 归为 SiteLocal；唯一本地地址按 RFC 4193 属于全局作用域
+    pub fn scope(&self) -> Option<Ipv6Scope> {
+        if self.is_multicast() {
+            return match self.0[1] & 0x0f {
+                0x1 => Some(Ipv6Scope::InterfaceLocal),
+                0x2 => Some(Ipv6Scope::LinkLocal),
+                0x3 => Some(Ipv6Scope::RealmLocal),
+                0x4 => Some(Ipv6Scope::AdminLocal),
+                0x5 => Some(Ipv6Scope::SiteLocal),
+                0x8 => Some(Ipv6Scope::OrganizationLocal),
+                0xe => Some(Ipv6Scope::Global),
+                _ => None,
+            };
+        }
+        if self.is_unspecified() {
+            None
+        } else if self.is_loopback() {
+            Some(Ipv6Scope::InterfaceLocal)
+        } else if self.is_unicast_link_local() {
+            Some(Ipv6Scope::LinkLocal)
+        } else if self.0[0] == 0xfe && self.0[1] & 0xc0 == 0xc0 {
+            Some(Ipv6Scope::SiteLocal)
+        } else {
+            Some(Ipv6Scope::Global)
+        }
+    }
+}
+
+/// 按 RFC 5952 输出：小写十六进制，最长的一段连续 0 (至少两组) 压缩为 ::
+impl fmt::Display for Ipv6Addr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(addr) = self.to_ipv4_mapped() {
+            return write!(f, "::ffff:{}", addr);
+        }
+
+        let segments = self.segments();
+        // (起点, 长度)，长度相同时取靠前的一段
+        let mut longest = (0, 0);
+        let mut current = (0, 0);
+        for (i, &segment) in segments.iter().enumerate() {
+            if segment != 0 {
+                current.1 = 0;
+                continue;
+            }
+            if current.1 == 0 {
+                current.0 = i;
+            }
+            current.1 += 1;
+            if current.1 > longest.1 {
+                longest = current;
+            }
+        }
+
+        let write_groups = |f: &mut fmt::Formatter<'_>, groups: &[u16]| -> fmt::Result {
+            for (i, group) in groups.iter().enumerate() {
+                if i > 0 {
+                    write!(f, ":")?;
+                }
+                write!(f, "{:x}", group)?;
+            }
+            Ok(())
+        };
+        if longest.1 < 2 {
+            return write_groups(f, &segments);
+        }
+        write_groups(f, &segments[..longest.0])?;
+        write!(f, "::")?;
+        write_groups(f, &segments[longest.0 + longest.1..])
+    }
+}
+
+impl FromStr for Ipv6Addr {
+    type Err = Ipv6ParseError;
+
+    /// 支持 :: 缩写，最后 32 位可以写成点分十进制 (如 ::ffff:192.0.2.1)
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (head, tail) = match s.split_once("::") {
+            Some((_, tail)) if tail.contains("::") => return Err(Ipv6ParseError::InvalidFormat),
+            Some((head, tail)) => (head, Some(tail)),
+            None => (s, None),
+        };
+
+        let head = parse_groups(head, tail.is_none())?;
+        let segments = match tail {
+            None if head.len() == 8 => head,
+            None => return Err(Ipv6ParseError::InvalidFormat),
+            Some(tail) => {
+                let tail = parse_groups(tail, true)?;
+                // :: 至少代表一组 0
+                if head.len() + tail.len() > 7 {
+                    return Err(Ipv6ParseError::InvalidFormat);
+                }
+                let mut segments = head;
+                segments.resize(8 - tail.len(), 0);
+                segments.extend(tail);
+                segments
+            }
+        };
+
+        let mut groups = [0u16; 8];
+        groups.copy_from_slice(&segments);
+        Ok(Self::from_segments(groups))
+    }
+}
+
+/// 解析以 : 分隔的若干组，`allow_ipv4` 时最后一组可以是点分十进制的 IPv4 地址
+fn parse_groups(part: &str, allow_ipv4: bool) -> Result<Vec<u16>, Ipv6ParseError> {
+    if part.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let mut groups = Vec::new();
+    let mut iter = part.split(':').peekable();
+    while let Some(group) = iter.next() {
+        if allow_ipv4 && iter.peek().is_none() && group.contains('.') {
+            let [a, b, c, d] = Ipv4Addr::from_str(group)
+                .map_err(|_| Ipv6ParseError::InvalidSegment)?
+                .octets();
+            groups.push(u16::from_be_bytes([a, b]));
+            groups.push(u16::from_be_bytes([c, d]));
+            break;
+        }
+        if group.is_empty() {
+            return Err(Ipv6ParseError::InvalidFormat);
+        }
+        if group.len() > 4 || !group.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(Ipv6ParseError::InvalidSegment);
+        }
+        groups.push(u16::from_str_radix(group, 16).map_err(|_| Ipv6ParseError::InvalidSegment)?);
+    }
+
+    if groups.len() > 8 {
+        return Err(Ipv6ParseError::InvalidFormat);
+    }
+    Ok(groups)
+}
+
+/// 修改后的 EUI-64 接口标识：MAC 中间插入 ff:fe，并翻转 U/L 位 (RFC 4291 附录 A)
+pub fn eui64_interface_id(mac: MacAddr) -> [u8; 8] {
+    let [a, b, c, d, e, f] = *mac.as_bytes();
+    [a ^ 0x02, b, c, 0xff, 0xfe, d, e, f]
+}
+
+/// 稳定隐私接口标识 (RFC 7217)：对前缀、网卡 MAC、DAD 冲突次数和密钥做 SHA-256，取前 64 位
+///
+/// 同一网络内地址保持不变，换到其他前缀后无法与原地址关联。
+/// 与 RFC 5453 保留的接口标识冲突时，调用者应增加 dad_counter 重新生成
+pub fn stable_privacy_interface_id(
+    prefix: Ipv6Addr,
+    mac: MacAddr,
+    dad_counter: u8,
+    secret: &[u8],
+) -> [u8; 8] {
+    let mut input = Vec::with_capacity(8 + 6 + 1 + secret.len());
+    input.extend_from_slice(&prefix.0[..8]);
+    input.extend_from_slice(mac.as_bytes());
+    input.push(dad_counter);
+    input.extend_from_slice(secret);
+    let digest = sha256(&input);
+    let mut interface_id = [0u8; 8];
+    interface_id.copy_from_slice(&digest[..8]);
+    interface_id
+}
+
+/// RFC 5453 保留的接口标识：全 0 (子网路由器任播) 和 fdff:ffff:ffff:ff80-ffff (保留任播)
+pub fn is_reserved_interface_id(interface_id: [u8; 8]) -> bool {
+    interface_id == [0; 8]
+        || (interface_id[..7] == [0xfd, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]
+            && interface_id[7] >= 0x80)
+}
+
+/// 带前缀长度的 IPv6 地址，如 2001:db8::1/64
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub struct Ipv6Cidr {
+    pub addr: Ipv6Addr,
+    pub prefix_len: u8,
+}
+
+impl Ipv6Cidr {
+    /// 前缀长度超过 128 时按 128 处理
+    pub const fn new(addr: Ipv6Addr, prefix_len: u8) -> Self {
+        let prefix_len = if prefix_len > 128 { 128 } else { prefix_len };
+        Self { addr, prefix_len }
+    }
+
+    fn mask(&self) -> u128 {
+        u128::MAX
+            .checked_shl(128 - self.prefix_len as u32)
+            .unwrap_or(0)
+    }
+
+    /// 主机位清零后的网络地址
+    pub fn network(&self) -> Ipv6Addr {
+        Ipv6Addr::from_bits(self.addr.to_bits() & self.mask())
+    }
+
+    /// addr 是否在这个前缀内
+    pub fn contains(&self, addr: Ipv6Addr) -> bool {
+        (addr.to_bits() ^ self.addr.to_bits()) & self.mask() == 0
+    }
+}
+
+impl fmt::Display for Ipv6Cidr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix_len)
+    }
+}
+
+impl FromStr for Ipv6Cidr {
+    type Err = Ipv6ParseError;
+
+    /// 没有写前缀长度时视为 /128
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (addr, prefix_len) = match s.split_once('/') {
+            Some((addr, len)) => (addr, len.parse::<u8>().ok().filter(|len| *len <= 128)),
+            None => (s, Some(128)),
+        };
+        let prefix_len = prefix_len.ok_or(Ipv6ParseError::InvalidPrefixLength)?;
+        Ok(Self::new(Ipv6Addr::from_str(addr)?, prefix_len))
+    }
+}
+
+/// 上层协议计算校验和用的伪首部 (RFC 8200 8.1)
+pub fn pseudo_header(src: Ipv6Addr, dst: Ipv6Addr, upper_len: u32, next_header: u8) -> [u8; 40] {
+    let mut bytes = [0u8; 40];
+    bytes[0..16].copy_from_slice(&src.0);
+    bytes[16..32].copy_from_slice(&dst.0);
+    bytes[32..36].copy_from_slice(&upper_len.to_be_bytes());
+    bytes[39] = next_header;
+    bytes
+}
+
+/// 固定首部长度
+pub const IPV6_HEADER_LEN: usize = 40;
+/// 每条链路都必须支持的最小 MTU (RFC 8200 5)
+pub const IPV6_MIN_MTU: usize = 1280;
+
+// Next Header 字段取值
+pub const IPV6_NEXT_HOP_BY_HOP: u8 = 0;
+pub const IPV6_NEXT_TCP: u8 = 6;
+pub const IPV6_NEXT_UDP: u8 = 17;
+pub const IPV6_NEXT_ROUTING: u8 = 43;
+pub const IPV6_NEXT_FRAGMENT: u8 = 44;
+pub const IPV6_NEXT_ICMPV6: u8 = 58;
+pub const IPV6_NEXT_NONE: u8 = 59;
+pub const IPV6_NEXT_DEST_OPTIONS: u8 = 60;
+
+// 逐跳选项和目的选项的类型
+pub const IPV6_OPT_PAD1: u8 = 0;
+pub const IPV6_OPT_PADN: u8 = 1;
+pub const IPV6_OPT_ROUTER_ALERT: u8 = 5;
+
+/// 不认识的选项的处理方式，由选项类型的最高两位决定 (RFC 8200 4.2)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Ipv6OptionAction {
+    Skip,
+    Discard,
+    /// 丢弃并回复 ICMPv6 Parameter Problem
+    DiscardAndReport,
+    /// 同上，但目的地址是组播时不回复
+    DiscardAndReportUnicast,
+}
+
+/// 逐跳选项和目的选项中的 TLV 选项
+///
+/// Pad1 / PadN 在解析时丢弃，编码时按 8 字节对齐自动补上
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Ipv6Option {
+    /// Router Alert (RFC 2711)，MLD 报文为 0
+    RouterAlert(u16),
+    Unknown {
+        kind: u8,
+        data: Vec<u8>,
+    },
+}
+
+impl Ipv6Option {
+    pub fn kind(&self) -> u8 {
+        match self {
+            Self::RouterAlert(_) => IPV6_OPT_ROUTER_ALERT,
+            Self::Unknown { kind, .. } => *kind,
+        }
+    }
+
+    /// 节点不认识该选项时应当怎么做
+    pub fn action(&self) -> Ipv6OptionAction {
+        match self.kind() >> 6 {
+            0 => Ipv6OptionAction::Skip,
+            1 => Ipv6OptionAction::Discard,
+            2 => Ipv6OptionAction::DiscardAndReport,
+            _ => Ipv6OptionAction::DiscardAndReportUnicast,
+        }
+    }
+
+    /// 编码后的长度 (含类型和长度字节)
+    #[allow(clippy::len_without_is_empty)]
+    pub fn len(&self) -> usize {
+        match self {
+            Self::RouterAlert(_) => 4,
+            Self::Unknown { data, .. } => 2 + data.len(),
+        }
+    }
+
+    pub fn write_to(&self, buf: &mut Vec<u8>) {
+        match self {
+            Self::RouterAlert(value) => {
+                buf.extend_from_slice(&[IPV6_OPT_ROUTER_ALERT, 2]);
+                buf.extend_from_slice(&value.to_be_bytes());
+            }
+            Self::Unknown { kind, data } => {
+                buf.push(*kind);
+                buf.push(data.len() as u8);
+                buf.extend_from_slice(data);
+            }
+        }
+    }
+}
+
+/// 解析选项区 (不含扩展首部开头的两个字节)
+fn parse_options(bytes: &[u8]) -> Result<Vec<Ipv6Option>, Ipv6HeaderParseError> {
+    let mut options = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == IPV6_OPT_PAD1 {
+            i += 1;
+            continue;
+        }
+        if i + 2 > bytes.len() {
+            return Err(Ipv6HeaderParseError::InvalidOption);
+        }
+        let (kind, len) = (bytes[i], bytes[i + 1] as usize);
+        let data = bytes
+            .get(i + 2..i + 2 + len)
+            .ok_or(Ipv6HeaderParseError::InvalidOption)?;
+        match kind {
+            IPV6_OPT_PADN => {}
+            IPV6_OPT_ROUTER_ALERT if len == 2 => {
+                options.push(Ipv6Option::RouterAlert(u16::from_be_bytes([
+                    data[0], data[1],
+                ])));
+            }
+            IPV6_OPT_ROUTER_ALERT => return Err(Ipv6HeaderParseError::InvalidOption),
+            kind => options.push(Ipv6Option::Unknown {
+                kind,
+                data: data.to_vec(),
+            }),
+        }
+        i += 2 + len;
+    }
+    Ok(options)
+}
+
+/// 逐跳选项首部或目的选项首部的内容
+///
+/// 收到的首部记住线路上的长度 ((Hdr Ext Len + 1) * 8)，发送方可能补了比最小
+/// 对齐更多的填充，上层数据的偏移和重新编码都以它为准
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Ipv6Options {
+    options: Vec<Ipv6Option>,
+    len: usize,
+}
+
+impl Ipv6Options {
+    /// 本地构造，按最小填充对齐到 8 字节
+    pub fn new(options: Vec<Ipv6Option>) -> Self {
+        let len = (2 + options.iter().map(Ipv6Option::len).sum::<usize>()).next_multiple_of(8);
+        Self { options, len }
+    }
+
+    /// 解析选项区 (不含扩展首部开头的两个字节)
+    fn parse(bytes: &[u8]) -> Result<Self, Ipv6HeaderParseError> {
+        Ok(Self {
+            options: parse_options(bytes)?,
+            len: 2 + bytes.len(),
+        })
+    }
+
+    pub fn options(&self) -> &[Ipv6Option] {
+        &self.options
+    }
+
+    /// 整个扩展首部的长度 (含开头两个字节)，总是 8 的倍数
+    #[allow(clippy::len_without_is_empty)]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// 编码选项区，用 Pad1 / PadN 补到 `len` 减去开头两个字节
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(self.len - 2);
+        for option in &self.options {
+            option.write_to(&mut buf);
+        }
+        // PadN 的数据最多 255 字节，填充更长时分成几段
+        while buf.len() < self.len - 2 {
+            match (self.len - 2 - buf.len()).min(257) {
+                1 => buf.push(IPV6_OPT_PAD1),
+                pad => {
+                    buf.extend_from_slice(&[IPV6_OPT_PADN, (pad - 2) as u8]);
+                    buf.resize(buf.len() + pad - 2, 0);
+                }
+            }
+        }
+        buf
+    }
+}
+
+impl From<Vec<Ipv6Option>> for Ipv6Options {
+    fn from(options: Vec<Ipv6Option>) -> Self {
+        Self::new(options)
+    }
+}
+
+/// 分片首部的内容
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Ipv6Fragment {
+    /// 以 8 字节为单位的偏移 (13 bit)
+    pub offset: u16,
+    pub more_fragments: bool,
+    pub id: u32,
+}
+
+/// 扩展首部，Next Header 和长度字段在编码时按链中的位置填写
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Ipv6Extension {
+    /// 逐跳选项，只能紧跟在固定首部之后
+    HopByHop(Ipv6Options),
+    /// 路由首部，`data` 是 Segments Left 之后的类型相关数据，长度加 4 须为 8 的倍数
+    Routing {
+        routing_type: u8,
+        segments_left: u8,
+        data: Vec<u8>,
+    },
+    Fragment(Ipv6Fragment),
+    DestinationOptions(Ipv6Options),
+}
+
+impl Ipv6Extension {
+    /// 该扩展首部在前一个首部的 Next Header 字段中的取值
+    pub fn header_type(&self) -> u8 {
+        match self {
+            Self::HopByHop(_) => IPV6_NEXT_HOP_BY_HOP,
+            Self::Routing { .. } => IPV6_NEXT_ROUTING,
+            Self::Fragment(_) => IPV6_NEXT_FRAGMENT,
+            Self::DestinationOptions(_) => IPV6_NEXT_DEST_OPTIONS,
+        }
+    }
+
+    /// 线路上的长度，总是 8 的倍数
+    #[allow(clippy::len_without_is_empty)]
+    pub fn len(&self) -> usize {
+        match self {
+            Self::HopByHop(options) | Self::DestinationOptions(options) => options.len(),
+            Self::Routing { data, .. } => 4 + data.len(),
+            Self::Fragment(_) => 8,
+        }
+    }
+
+    pub fn write_to(&self, buf: &mut Vec<u8>, next_header: u8) {
+        // Hdr Ext Len 以 8 字节为单位，不含开头的 8 字节
+        let ext_len = (self.len() / 8 - 1) as u8;
+        match self {
+            Self::HopByHop(options) | Self::DestinationOptions(options) => {
+                buf.extend_from_slice(&[next_header, ext_len]);
+                buf.extend_from_slice(&options.to_bytes());
+            }
+            Self::Routing {
+                routing_type,
+                segments_left,
+                data,
+            } => {
+                buf.extend_from_slice(&[next_header, ext_len, *routing_type, *segments_left]);
+                buf.extend_from_slice(data);
+            }
+            Self::Fragment(fragment) => {
+                // 分片首部的第二个字节保留为 0
+                buf.extend_from_slice(&[next_header, 0]);
+                let offset = (fragment.offset << 3) | fragment.more_fragments as u16;
+                buf.extend_from_slice(&offset.to_be_bytes());
+                buf.extend_from_slice(&fragment.id.to_be_bytes());
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Ipv6Header {
+    pub version: u8,       // 4 bits
+    pub traffic_class: u8, // DSCP + ECN
+    pub flow_label: u32,   // 20 bits
+    pub payload_len: u16,  // 扩展首部和上层数据的总长度
+    pub hop_limit: u8,
+    pub src: Ipv6Addr,
+    pub dst: Ipv6Addr,
+    /// 按出现顺序排列的扩展首部
+    pub extensions: Vec<Ipv6Extension>,
+    /// 扩展首部链末尾的上层协议 (TCP = 6, UDP = 17, ICMPv6 = 58)，
+    /// 固定首部和各扩展首部的 Next Header 字段在编码时据此填写
+    pub protocol: u8,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Ipv6Protocol {
+    ICMPv6,
+    TCP,
+    UDP,
+    NoNextHeader,
+    Unknown,
+}
+
+impl Ipv6Header {
+    pub fn new(src: Ipv6Addr, dst: Ipv6Addr, protocol: u8, payload_len: u16) -> Self {
+        // 不带扩展首部，需要时再调用 set_extensions
+        Self {
+            version: 6,
+            traffic_class: 0,
+            flow_label: 0,
+            payload_len,
+            hop_limit: 64,
+            src,
+            dst,
+            extensions: Vec::new(),
+            protocol,
+        }
+    }
+
+    /// 替换扩展首部，同时更新 payload_len (上层数据长度不变)
+    pub fn set_extensions(
+        &mut self,
+        extensions: Vec<Ipv6Extension>,
+    ) -> Result<(), Ipv6HeaderParseError> {
+        let upper_len = (self.payload_len as usize).saturating_sub(self.extensions_len());
+        let old = std::mem::replace(&mut self.extensions, extensions);
+        let payload_len = self.extensions_len() + upper_len;
+        if let Err(e) = self.validate_extensions() {
+            self.extensions = old;
+            return Err(e);
+        }
+        if payload_len > u16::MAX as usize {
+            self.extensions = old;
+            return Err(Ipv6HeaderParseError::InvalidPayloadLength);
+        }
+        self.payload_len = payload_len as u16;
+        Ok(())
+    }
+
+    /// 扩展首部的总长度
+    pub fn extensions_len(&self) -> usize {
+        self.extensions.iter().map(Ipv6Extension::len).sum()
+    }
+
+    /// 固定首部加扩展首部的长度，即上层数据在报文中的偏移
+    pub fn header_len(&self) -> usize {
+        IPV6_HEADER_LEN + self.extensions_len()
+    }
+
+    pub fn get_protocol(&self) -> Ipv6Protocol {
+        match self.protocol {
+            IPV6_NEXT_ICMPV6 => Ipv6Protocol::ICMPv6,
+            IPV6_NEXT_TCP => Ipv6Protocol::TCP,
+            IPV6_NEXT_UDP => Ipv6Protocol::UDP,
+            IPV6_NEXT_NONE => Ipv6Protocol::NoNextHeader,
+            _ => Ipv6Protocol::Unknown,
+        }
+    }
+
+    /// 报文带有分片首部时返回其内容
+    pub fn fragment(&self) -> Option<&Ipv6Fragment> {
+        self.extensions
+            .iter()
+            .find_map(|extension| match extension {
+                Ipv6Extension::Fragment(fragment) => Some(fragment),
+                _ => None,
+            })
+    }
+
+    /// 编码固定首部和扩展首部链
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.header_len());
+        let first = (self.version as u32) << 28
+            | (self.traffic_class as u32) << 20
+            | (self.flow_label & 0x000f_ffff);
+        bytes.extend_from_slice(&first.to_be_bytes());
+        bytes.extend_from_slice(&self.payload_len.to_be_bytes());
+        let next_header = self
+            .extensions
+            .first()
+            .map_or(self.protocol, Ipv6Extension::header_type);
+        bytes.push(next_header);
+        bytes.push(self.hop_limit);
+        bytes.extend_from_slice(&self.src.0);
+        bytes.extend_from_slice(&self.dst.0);
+
+        for (i, extension) in self.extensions.iter().enumerate() {
+            let next_header = self
+                .extensions
+                .get(i + 1)
+                .map_or(self.protocol, Ipv6Extension::header_type);
+            extension.write_to(&mut bytes, next_header);
+        }
+
+        bytes
+    }
+
+    pub fn validate(&self) -> Result<(), Ipv6HeaderParseError> {
+        if self.version != 6 {
+            Err(Ipv6HeaderParseError::InvalidVersion)
+        } else if (self.payload_len as usize) < self.extensions_len() {
+            Err(Ipv6HeaderParseError::InvalidPayloadLength)
+        } else {
+            self.validate_extensions()
+        }
+    }
+
+    fn validate_extensions(&self) -> Result<(), Ipv6HeaderParseError> {
+        for (i, extension) in self.extensions.iter().enumerate() {
+            match extension {
+                Ipv6Extension::HopByHop(_) if i != 0 => {
+                    return Err(Ipv6HeaderParseError::InvalidExtensionHeader);
+                }
+                Ipv6Extension::Routing { data, .. } if !(4 + data.len()).is_multiple_of(8) => {
+                    return Err(Ipv6HeaderParseError::InvalidExtensionHeader);
+                }
+                Ipv6Extension::HopByHop(options) | Ipv6Extension::DestinationOptions(options)
+                    if options.len() > 2048 =>
+                {
+                    // Hdr Ext Len 只有 8 位
+                    return Err(Ipv6HeaderParseError::InvalidOption);
+                }
+                Ipv6Extension::HopByHop(options) | Ipv6Extension::DestinationOptions(options)
+                    if options.options().iter().any(|o| o.len() > 257) =>
+                {
+                    return Err(Ipv6HeaderParseError::InvalidOption);
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// 解析固定首部并沿 Next Header 遍历扩展首部链，`bytes` 可以带上后面的载荷
+    ///
+    /// 遇到非扩展首部的 Next Header 时停止，其值记为 `protocol`；
+    /// 非首个分片的分片首部之后是上层数据的中间部分，也在此停止
+    pub fn parse(bytes: &[u8]) -> Result<Self, Ipv6HeaderParseError> {
+        if bytes.len() < IPV6_HEADER_LEN {
+            return Err(Ipv6HeaderParseError::InvalidHeaderLength);
+        }
+        let first = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        let version = (first >> 28) as u8;
+        if version != 6 {
+            return Err(Ipv6HeaderParseError::InvalidVersion);
+        }
+        let payload_len = u16::from_be_bytes([bytes[4], bytes[5]]);
+        // 以太网帧可能带有填充，只看 payload_len 覆盖的部分
+        let end = IPV6_HEADER_LEN + payload_len as usize;
+        let packet = bytes
+            .get(..end)
+            .ok_or(Ipv6HeaderParseError::InvalidPayloadLength)?;
+
+        let mut src = [0u8; 16];
+        src.copy_from_slice(&bytes[8..24]);
+        let mut dst = [0u8; 16];
+        dst.copy_from_slice(&bytes[24..40]);
+
+        let mut extensions = Vec::new();
+        let mut next_header = bytes[6];
+        let mut offset = IPV6_HEADER_LEN;
+        while matches!(
+            next_header,
+            IPV6_NEXT_HOP_BY_HOP | IPV6_NEXT_ROUTING | IPV6_NEXT_FRAGMENT | IPV6_NEXT_DEST_OPTIONS
+        ) {
+            let extension = &packet[offset..];
+            if extension.len() < 8 {
+                return Err(Ipv6HeaderParseError::InvalidHeaderLength);
+            }
+            if next_header == IPV6_NEXT_HOP_BY_HOP && offset != IPV6_HEADER_LEN {
+                return Err(Ipv6HeaderParseError::InvalidExtensionHeader);
+            }
+
+            let len = match next_header {
+                IPV6_NEXT_FRAGMENT => 8,
+                _ => (extension[1] as usize + 1) * 8,
+            };
+            let extension = extension
+                .get(..len)
+                .ok_or(Ipv6HeaderParseError::InvalidHeaderLength)?;
+            let parsed = match next_header {
+                IPV6_NEXT_HOP_BY_HOP => {
+                    Ipv6Extension::HopByHop(Ipv6Options::parse(&extension[2..])?)
+                }
+                IPV6_NEXT_DEST_OPTIONS => {
+                    Ipv6Extension::DestinationOptions(Ipv6Options::parse(&extension[2..])?)
+                }
+                IPV6_NEXT_ROUTING => Ipv6Extension::Routing {
+                    routing_type: extension[2],
+                    segments_left: extension[3],
+                    data: extension[4..].to_vec(),
+                },
+                _ => {
+                    let offset = u16::from_be_bytes([extension[2], extension[3]]);
+                    Ipv6Extension::Fragment(Ipv6Fragment {
+                        offset: offset >> 3,
+                        more_fragments: offset & 1 == 1,
+                        id: u32::from_be_bytes([
+                            extension[4],
+                            extension[5],
+                            extension[6],
+                            extension[7],
+                        ]),
+                    })
+                }
+            };
+
+            next_header = extension[0];
+            offset += len;
+            let later_fragment =
+                matches!(&parsed, Ipv6Extension::Fragment(fragment) if fragment.offset != 0);
+            extensions.push(parsed);
+            if later_fragment {
+                break;
+            }
+        }
+
+        let header = Self {
+            version,
+            traffic_class: (first >> 20) as u8,
+            flow_label: first & 0x000f_ffff,
+            payload_len,
+            hop_limit: bytes[7],
+            src: Ipv6Addr(src),
+            dst: Ipv6Addr(dst),
+            extensions,
+            protocol: next_header,
+        };
+
+        header.validate()?;
+        Ok(header)
+    }
+}
+
+impl fmt::Display for Ipv6Header {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "IPv6 Header:
+    Version: {}
+    Traffic Class: {:#04x}
+    Flow Label: {:#07x}
+    Payload Length: {}
+    Hop Limit: {}
+    Source: {}
+    Destination: {}
+    Extensions: {:?}
+    Protocol: {} ({:?})",
+            self.version,
+            self.traffic_class,
+            self.flow_label,
+            self.payload_len,
+            self.hop_limit,
+            self.src,
+            self.dst,
+            self.extensions,
+            self.protocol,
+            self.get_protocol()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 带一个目的选项首部的 UDP 报文，选项区全是 PadN，Hdr Ext Len 由调用方指定
+    fn packet_with_dest_options(hdr_ext_len: u8) -> Vec<u8> {
+        let ext_len = (hdr_ext_len as usize + 1) * 8;
+        let mut header = Ipv6Header::new(
+            Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 1),
+            Ipv6Addr::all_nodes(),
+            IPV6_NEXT_UDP,
+            (ext_len + 8) as u16,
+        );
+        header.protocol = IPV6_NEXT_DEST_OPTIONS;
+        let mut packet = header.to_bytes();
+        packet.extend_from_slice(&[IPV6_NEXT_UDP, hdr_ext_len]);
+        // 选项区用尽量少的 PadN 填满
+        let mut pad = ext_len - 2;
+        while pad > 0 {
+            let n = pad.min(257);
+            packet.extend_from_slice(&[IPV6_OPT_PADN, (n - 2) as u8]);
+            packet.resize(packet.len() + n - 2, 0);
+            pad -= n;
+        }
+        packet.extend_from_slice(&[0x12, 0x34, 0x00, 0x35, 0x00, 0x08, 0x00, 0x00]);
+        packet
+    }
+
+    #[test]
+    fn options_keep_wire_length() {
+        // 选项区按最小对齐只需 8 字节，但发送方声明了 16 字节
+        let packet = packet_with_dest_options(1);
+        let header = Ipv6Header::parse(&packet).unwrap();
+        assert_eq!(header.extensions.len(), 1);
+        assert_eq!(header.extensions[0].len(), 16);
+        assert_eq!(header.header_len(), 56);
+        assert_eq!(header.get_protocol(), Ipv6Protocol::UDP);
+        assert_eq!(header.to_bytes(), packet[..56]);
+
+        // 填充超过一个 PadN 能表示的长度
+        let packet = packet_with_dest_options(40);
+        let header = Ipv6Header::parse(&packet).unwrap();
+        assert_eq!(header.header_len(), 40 + 41 * 8);
+        assert_eq!(header.to_bytes(), packet[..header.header_len()]);
+    }
+
+    #[test]
+    fn local_options_use_minimal_padding() {
+        let mut header = Ipv6Header::new(
+            Ipv6Addr::localhost(),
+            Ipv6Addr::all_nodes(),
+            IPV6_NEXT_ICMPV6,
+            24,
+        );
+        header
+            .set_extensions(vec![Ipv6Extension::HopByHop(Ipv6Options::new(vec![
+                Ipv6Option::RouterAlert(0),
+            ]))])
+            .unwrap();
+        assert_eq!(header.header_len(), 48);
+        assert_eq!(header.payload_len, 32);
+
+        let bytes = header.to_bytes();
+        assert_eq!(&bytes[40..48], &[IPV6_NEXT_ICMPV6, 0, 5, 2, 0, 0, 1, 0]);
+        let mut packet = bytes;
+        packet.resize(40 + 32, 0);
+        assert_eq!(Ipv6Header::parse(&packet).unwrap(), header);
+    }
+
+    fn addr(s: &str) -> Ipv6Addr {
+        Ipv6Addr::from_str(s).unwrap()
+    }
+
+    #[test]
+    fn addresses_are_formatted_per_rfc_5952() {
+        for (segments, text) in [
+            ([0x2001, 0xdb8, 0, 0, 0, 0, 0, 1], "2001:db8::1"),
+            ([0; 8], "::"),
+            ([0, 0, 0, 0, 0, 0, 0, 1], "::1"),
+            ([0xfe80, 0, 0, 0, 0, 0, 0, 0], "fe80::"),
+            // 单独一组 0 不压缩
+            ([0x2001, 0xdb8, 0, 1, 1, 1, 1, 1], "2001:db8:0:1:1:1:1:1"),
+            // 取最长的一段，长度相同时取靠前的
+            ([0x2001, 0, 0, 1, 0, 0, 0, 1], "2001:0:0:1::1"),
+            ([0x2001, 0xdb8, 0, 0, 1, 0, 0, 1], "2001:db8::1:0:0:1"),
+            ([0xABCD, 0xEF, 0, 0, 0, 0, 0, 0], "abcd:ef::"),
+            ([0, 0, 0, 0, 0, 0xffff, 0xc000, 0x0201], "::ffff:192.0.2.1"),
+        ] {
+            let addr = Ipv6Addr::from_segments(segments);
+            assert_eq!(addr.to_string(), text);
+            assert_eq!(Ipv6Addr::from_str(text), Ok(addr));
+        }
+    }
+
+    #[test]
+    fn address_parsing() {
+        assert_eq!(addr("2001:DB8:0:0:0:0:0:1"), addr("2001:db8::1"));
+        assert_eq!(addr("::192.0.2.1").segments()[6..], [0xc000, 0x0201]);
+        assert_eq!(addr("1:2:3:4:5:6::"), Ipv6Addr::new(1, 2, 3, 4, 5, 6, 0, 0));
+        assert_eq!(
+            addr("::2:3:4:5:6:7:8"),
+            Ipv6Addr::new(0, 2, 3, 4, 5, 6, 7, 8)
+        );
+
+        for (text, error) in [
+            ("", Ipv6ParseError::InvalidFormat),
+            ("1:2:3:4:5:6:7", Ipv6ParseError::InvalidFormat),
+            ("1:2:3:4:5:6:7:8:9", Ipv6ParseError::InvalidFormat),
+            ("1::2::3", Ipv6ParseError::InvalidFormat),
+            // :: 至少代表一组 0
+            ("1:2:3:4::5:6:7:8", Ipv6ParseError::InvalidFormat),
+            ("1:2:3:4:5:6:7:", Ipv6ParseError::InvalidFormat),
+            (":1:2:3:4:5:6:7", Ipv6ParseError::InvalidFormat),
+            ("12345::", Ipv6ParseError::InvalidSegment),
+            ("g::", Ipv6ParseError::InvalidSegment),
+            ("::1.2.3", Ipv6ParseError::InvalidSegment),
+            ("1.2.3.4::", Ipv6ParseError::InvalidSegment),
+        ] {
+            assert_eq!(Ipv6Addr::from_str(text), Err(error), "{}", text);
+        }
+    }
+
+    #[test]
+    fn address_classes_and_scopes() {
+        assert_eq!(Ipv6Addr::unspecified().scope(), None);
+        assert_eq!(
+            Ipv6Addr::localhost().scope(),
+            Some(Ipv6Scope::InterfaceLocal)
+        );
+        assert_eq!(addr("fe80::1").scope(), Some(Ipv6Scope::LinkLocal));
+        assert_eq!(addr("febf::1").scope(), Some(Ipv6Scope::LinkLocal));
+        assert_eq!(addr("fec0::1").scope(), Some(Ipv6Scope::SiteLocal));
+        assert_eq!(addr("fd00::1").scope(), Some(Ipv6Scope::Global));
+        assert_eq!(addr("2001:db8::1").scope(), Some(Ipv6Scope::Global));
+        assert_eq!(Ipv6Addr::all_nodes().scope(), Some(Ipv6Scope::LinkLocal));
+        assert_eq!(addr("ff05::2").scope(), Some(Ipv6Scope::SiteLocal));
+        assert_eq!(addr("ff0e::1").scope(), Some(Ipv6Scope::Global));
+        assert_eq!(addr("ff00::1").scope(), None);
+        assert!(Ipv6Scope::LinkLocal < Ipv6Scope::Global);
+
+        assert!(addr("fc00::1").is_unique_local());
+        assert!(!addr("fe00::1").is_unique_local());
+        assert!(!addr("fec0::1").is_unicast_link_local());
+        assert!(Ipv6Addr::all_routers().is_multicast());
+
+        let target = addr("2001:db8::1:2:abcd:ef01");
+        assert_eq!(target.solicited_node(), addr("ff02::1:ffcd:ef01"));
+        assert_eq!(target.interface_id(), [0, 1, 0, 2, 0xab, 0xcd, 0xef, 0x01]);
+        assert_eq!(
+            Ipv6Addr::from_prefix_and_interface_id(addr("2001:db8:1:2::"), target.interface_id()),
+            addr("2001:db8:1:2:1:2:abcd:ef01")
+        );
+
+        let v4 = Ipv4Addr::new(192, 0, 2, 1);
+        assert_eq!(Ipv6Addr::from_ipv4_mapped(v4).to_ipv4_mapped(), Some(v4));
+        assert_eq!(addr("::192.0.2.1").to_ipv4_mapped(), None);
+        assert_eq!(Ipv6Addr::from_bits(target.to_bits()), target);
+    }
+
+    #[test]
+    fn prefixes_contain_addresses() {
+        let cidr = Ipv6Cidr::from_str("2001:db8:1::5/48").unwrap();
+        assert_eq!(cidr.network(), addr("2001:db8:1::"));
+        assert!(cidr.contains(addr("2001:db8:1:ffff::1")));
+        assert!(!cidr.contains(addr("2001:db8:2::1")));
+        assert_eq!(cidr.to_string(), "2001:db8:1::5/48");
+
+        let host = Ipv6Cidr::from_str("fe80::1").unwrap();
+        assert_eq!(host.prefix_len, 128);
+        assert!(host.contains(addr("fe80::1")));
+        assert!(!host.contains(addr("fe80::2")));
+        assert!(
+            Ipv6Cidr::from_str("::/0")
+                .unwrap()
+                .contains(addr("2001:db8::1"))
+        );
+        assert_eq!(Ipv6Cidr::new(addr("::1"), 200).prefix_len, 128);
+
+        for invalid in ["::/129", "::/-1", "::/x"] {
+            assert_eq!(
+                Ipv6Cidr::from_str(invalid),
+                Err(Ipv6ParseError::InvalidPrefixLength)
+            );
+        }
+        assert_eq!(
+            Ipv6Cidr::from_str("::g/64"),
+            Err(Ipv6ParseError::InvalidSegment)
+        );
+    }
+
+    fn chained_header() -> Ipv6Header {
+        let mut header =
+            Ipv6Header::new(addr("2001:db8::1"), addr("2001:db8::2"), IPV6_NEXT_TCP, 20);
+        header.traffic_class = 0xb8;
+        header.flow_label = 0x12345;
+        header.hop_limit = 3;
+        header
+            .set_extensions(vec![
+                Ipv6Extension::HopByHop(Ipv6Options::new(vec![Ipv6Option::Unknown {
+                    kind: 0x1e,
+                    data: vec![1, 2, 3],
+                }])),
+                Ipv6Extension::Routing {
+                    routing_type: 4,
+                    segments_left: 0,
+                    data: vec![0; 20],
+                },
+                Ipv6Extension::Fragment(Ipv6Fragment {
+                    offset: 0,
+                    more_fragments: true,
+                    id: 0xdeadbeef,
+                }),
+                Ipv6Extension::DestinationOptions(Ipv6Options::new(Vec::new())),
+            ])
+            .unwrap();
+        header
+    }
+
+    #[test]
+    fn extension_chain_round_trips() {
+        let header = chained_header();
+        assert_eq!(header.extensions_len(), 8 + 24 + 8 + 8);
+        assert_eq!(header.payload_len, 48 + 20);
+
+        let mut packet = header.to_bytes();
+        assert_eq!(&packet[..4], &[0x6b, 0x81, 0x23, 0x45]);
+        // 每个首部的 Next Header 指向下一个，最后一个指向上层协议
+        assert_eq!(packet[6], IPV6_NEXT_HOP_BY_HOP);
+        assert_eq!(packet[40], IPV6_NEXT_ROUTING);
+        assert_eq!(&packet[48..52], &[IPV6_NEXT_FRAGMENT, 2, 4, 0]);
+        assert_eq!(&packet[72..76], &[IPV6_NEXT_DEST_OPTIONS, 0, 0, 1]);
+        assert_eq!(&packet[80..84], &[IPV6_NEXT_TCP, 0, IPV6_OPT_PADN, 4]);
+        packet.resize(40 + 68, 0);
+        // 以太网填充被忽略
+        packet.extend_from_slice(&[0; 6]);
+
+        let parsed = Ipv6Header::parse(&packet).unwrap();
+        assert_eq!(parsed, header);
+        assert_eq!(parsed.header_len(), 88);
+        assert_eq!(parsed.get_protocol(), Ipv6Protocol::TCP);
+        assert_eq!(parsed.fragment().map(|f| f.id), Some(0xdeadbeef));
+
+        // 非首个分片的分片首部之后是上层数据的中间部分，不再解析
+        let mut later = Ipv6Header::new(header.src, header.dst, IPV6_NEXT_DEST_OPTIONS, 8);
+        later
+            .set_extensions(vec![Ipv6Extension::Fragment(Ipv6Fragment {
+                offset: 10,
+                more_fragments: false,
+                id: 1,
+            })])
+            .unwrap();
+        let mut packet = later.to_bytes();
+        packet.extend_from_slice(&[IPV6_NEXT_UDP, 200, 0, 0, 0, 0, 0, 0]);
+        let parsed = Ipv6Header::parse(&packet).unwrap();
+        assert_eq!(parsed.extensions.len(), 1);
+        assert_eq!(parsed.protocol, IPV6_NEXT_DEST_OPTIONS);
+        assert_eq!(parsed.fragment().map(|f| f.offset), Some(10));
+    }
+
+    #[test]
+    fn malformed_headers_are_rejected() {
+        let header = chained_header();
+        let mut packet = header.to_bytes();
+        packet.resize(40 + header.payload_len as usize, 0);
+        assert_eq!(
+            Ipv6Header::parse(&packet[..39]),
+            Err(Ipv6HeaderParseError::InvalidHeaderLength)
+        );
+        assert_eq!(
+            Ipv6Header::parse(&packet[..packet.len() - 1]),
+            Err(Ipv6HeaderParseError::InvalidPayloadLength)
+        );
+
+        let mut v4 = packet.clone();
+        v4[0] = 0x45;
+        assert_eq!(
+            Ipv6Header::parse(&v4),
+            Err(Ipv6HeaderParseError::InvalidVersion)
+        );
+
+        // payload_len 只覆盖了前两个扩展首部
+        let mut short = packet.clone();
+        short[4..6].copy_from_slice(&36u16.to_be_bytes());
+        assert_eq!(
+            Ipv6Header::parse(&short),
+            Err(Ipv6HeaderParseError::InvalidHeaderLength)
+        );
+
+        // 逐跳选项只能紧跟在固定首部之后
+        let mut late_hop_by_hop = packet.clone();
+        late_hop_by_hop[80] = IPV6_NEXT_HOP_BY_HOP;
+        assert_eq!(
+            Ipv6Header::parse(&late_hop_by_hop),
+            Err(Ipv6HeaderParseError::InvalidExtensionHeader)
+        );
+        let mut invalid = header.clone();
+        let mut extensions = header.extensions.clone();
+        extensions.reverse();
+        assert_eq!(
+            invalid.set_extensions(extensions),
+            Err(Ipv6HeaderParseError::InvalidExtensionHeader)
+        );
+        let routing = Ipv6Extension::Routing {
+            routing_type: 0,
+            segments_left: 0,
+            data: vec![0; 5],
+        };
+        assert_eq!(
+            invalid.set_extensions(vec![routing]),
+            Err(Ipv6HeaderParseError::InvalidExtensionHeader)
+        );
+        // 失败时保留原来的扩展首部
+        assert_eq!(invalid, header);
+
+        // 选项长度超出选项区，Router Alert 长度不对
+        let mut overflow = packet.clone();
+        overflow[43] = 10;
+        assert_eq!(
+            Ipv6Header::parse(&overflow),
+            Err(Ipv6HeaderParseError::InvalidOption)
+        );
+        let mut router_alert = packet.clone();
+        router_alert[42..46].copy_from_slice(&[IPV6_OPT_ROUTER_ALERT, 3, 0, 0]);
+        assert_eq!(
+            Ipv6Header::parse(&router_alert),
+            Err(Ipv6HeaderParseError::InvalidOption)
+        );
+    }
+
+    #[test]
+    fn unknown_option_actions_follow_the_type() {
+        let option = |kind| Ipv6Option::Unknown {
+            kind,
+            data: Vec::new(),
+        };
+        assert_eq!(option(0x1e).action(), Ipv6OptionAction::Skip);
+        assert_eq!(option(0x5e).action(), Ipv6OptionAction::Discard);
+        assert_eq!(option(0x9e).action(), Ipv6OptionAction::DiscardAndReport);
+        assert_eq!(
+            option(0xde).action(),
+            Ipv6OptionAction::DiscardAndReportUnicast
+        );
+        assert_eq!(Ipv6Option::RouterAlert(0).action(), Ipv6OptionAction::Skip);
+    }
+
+    #[test]
+    fn pseudo_header_layout() {
+        let bytes = pseudo_header(addr("2001:db8::1"), addr("ff02::1"), 0x10203, IPV6_NEXT_UDP);
+        assert_eq!(&bytes[..16], &addr("2001:db8::1").octets());
+        assert_eq!(&bytes[16..32], &addr("ff02::1").octets());
+        assert_eq!(&bytes[32..], &[0, 1, 2, 3, 0, 0, 0, IPV6_NEXT_UDP]);
+    }
+
+    #[test]
+    fn eui64_interface_ids_flip_the_universal_bit() {
+        let mac = MacAddr::from_raw([0x34, 0x56, 0x78, 0x9a, 0xbc, 0xde]);
+        assert_eq!(
+            eui64_interface_id(mac),
+            [0x36, 0x56, 0x78, 0xff, 0xfe, 0x9a, 0xbc, 0xde]
+        );
+        let link_local = Ipv6Addr::link_local_eui64(mac);
+        assert_eq!(link_local, addr("fe80::3656:78ff:fe9a:bcde"));
+        assert!(link_local.is_unicast_link_local());
+        assert_eq!(link_local.interface_id(), eui64_interface_id(mac));
+
+        // 本地管理的 MAC 翻转后 U/L 位为 0
+        let local = MacAddr::from_raw([0x02, 0, 0, 0, 0, 1]);
+        assert_eq!(Ipv6Addr::link_local_eui64(local), addr("fe80::ff:fe00:1"));
+        assert_eq!(
+            Ipv6Addr::from_prefix_and_interface_id(
+                addr("2001:db8:1:2::ffff"),
+                [0, 0, 0, 0, 0, 0, 0, 1]
+            ),
+            addr("2001:db8:1:2::1")
+        );
+    }
+
+    #[test]
+    fn stable_privacy_interface_ids_follow_rfc_7217() {
+        let mac = MacAddr::from_raw([0x02, 0, 0, 0, 0, 1]);
+        let secret = [0x5a; 16];
+        let prefix = addr("2001:db8:1::");
+        let id = stable_privacy_interface_id(prefix, mac, 0, &secret);
+
+        // 相同输入得到相同的标识，前缀只有高 64 位参与计算
+        assert_eq!(stable_privacy_interface_id(prefix, mac, 0, &secret), id);
+        assert_eq!(
+            stable_privacy_interface_id(addr("2001:db8:1::1234"), mac, 0, &secret),
+            id
+        );
+        assert_ne!(id, eui64_interface_id(mac));
+
+        // 前缀、MAC、冲突计数和密钥任一变化都得到不同的标识
+        for other in [
+            stable_privacy_interface_id(addr("2001:db8:2::"), mac, 0, &secret),
+            stable_privacy_interface_id(
+                prefix,
+                MacAddr::from_raw([0x02, 0, 0, 0, 0, 2]),
+                0,
+                &secret,
+            ),
+            stable_privacy_interface_id(prefix, mac, 1, &secret),
+            stable_privacy_interface_id(prefix, mac, 0, &[0xa5; 16]),
+        ] {
+            assert_ne!(other, id);
+        }
+    }
+
+    #[test]
+    fn reserved_interface_ids_match_rfc_5453() {
+        assert!(is_reserved_interface_id([0; 8]));
+        assert!(is_reserved_interface_id([
+            0xfd, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x80
+        ]));
+        assert!(is_reserved_interface_id([
+            0xfd, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff
+        ]));
+        assert!(!is_reserved_interface_id([
+            0xfd, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x7f
+        ]));
+        assert!(!is_reserved_interface_id([0, 0, 0, 0, 0, 0, 0, 1]));
+        assert!(!is_reserved_interface_id(eui64_interface_id(
+            MacAddr::zero()
+        )));
+    }
+}
@@ -13,9 +13,16 @@
 
 pub mod arp;
 pub mod checksum;
+pub mod dhcp;
+pub mod dns;
 pub mod error;
 pub mod ethernet;
 pub mod icmp;
+pub mod icmpv6;
+pub mod igmp;
+pub mod ip;
 pub mod ipv4;
+pub mod ipv6;
 pub mod mac;
+pub mod tcp;
 pub mod udp;
@@ -12,9 +12,11 @@
 // (at your option) any later version.
 
 use crate::error::MacParseError;
+use crate::ipv4::Ipv4Addr;
+use crate::ipv6::Ipv6Addr;
 use std::{borrow::Cow, fmt, str::FromStr};
 
-#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd, Hash)]
 pub struct MacAddr([u8; 6]);
 
 impl MacAddr {
@@ -39,6 +41,23 @@ impl MacAddr {
     pub fn as_bytes(&self) -> &[u8; 6] {
         &self.0
     }
+
+    /// IPv4 多播组对应的以太网地址：01:00:5e 加上组地址的低 23 位 (RFC 1112 6.4)
+    pub const fn from_ipv4_multicast(group: Ipv4Addr) -> Self {
+        let [_, b, c, d] = group.octets();
+        Self([0x01, 0x00, 0x5e, b & 0x7f, c, d])
+    }
+
+    /// IPv6 组播地址对应的以太网地址：33:33 加上组地址的低 32 位 (RFC 2464 7)
+    pub const fn from_ipv6_multicast(group: Ipv6Addr) -> Self {
+        let octets = group.octets();
+        Self([0x33, 0x33, octets[12], octets[13], octets[14], octets[15]])
+    }
+
+    /// I/G 位为 1 的组地址，包括广播地址
+    pub const fn is_multicast(&self) -> bool {
+        self.0[0] & 0x01 != 0
+    }
 }
 
 impl fmt::Display for MacAddr {
@@ -0,0 +1,600 @@
+// Copyright (C) 2025 rrrrrzy
+// SPDX-License-Identifier: GPL-3.0-or-later
+//
+// --------------------------------------------------
+// 致敬所有在深夜调试代码的灵魂。
+// 即便 Bug 如山，我亦往矣。
+// --------------------------------------------------
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+use std::fmt;
+
+use crate::{checksum::simple_checksum, error::TcpParseError, ipv4::Ipv4Addr};
+
+// 控制位 (RFC 9293 3.1)，NS 位已被 RFC 8311 废弃，不予支持
+pub const TCP_FLAG_FIN: u8 = 0x01;
+pub const TCP_FLAG_SYN: u8 = 0x02;
+pub const TCP_FLAG_RST: u8 = 0x04;
+pub const TCP_FLAG_PSH: u8 = 0x08;
+pub const TCP_FLAG_ACK: u8 = 0x10;
+pub const TCP_FLAG_URG: u8 = 0x20;
+pub const TCP_FLAG_ECE: u8 = 0x40;
+pub const TCP_FLAG_CWR: u8 = 0x80;
+
+// 选项类型
+pub const TCP_OPT_END: u8 = 0;
+pub const TCP_OPT_NOP: u8 = 1;
+pub const TCP_OPT_MSS: u8 = 2;
+pub const TCP_OPT_WINDOW_SCALE: u8 = 3;
+pub const TCP_OPT_SACK_PERMITTED: u8 = 4;
+pub const TCP_OPT_SACK: u8 = 5;
+pub const TCP_OPT_TIMESTAMPS: u8 = 8;
+
+/// 选项区最大长度：data offset 最大 15，即 60 字节首部减去 20 字节固定部分
+pub const TCP_MAX_OPTIONS_LEN: usize = 40;
+
+/// 控制位的名字，按报文中的位序输出，如 "SYN,ACK"
+pub fn flag_names(flags: u8) -> String {
+    const NAMES: [(u8, &str); 8] = [
+        (TCP_FLAG_CWR, "CWR"),
+        (TCP_FLAG_ECE, "ECE"),
+        (TCP_FLAG_URG, "URG"),
+        (TCP_FLAG_ACK, "ACK"),
+        (TCP_FLAG_PSH, "PSH"),
+        (TCP_FLAG_RST, "RST"),
+        (TCP_FLAG_SYN, "SYN"),
+        (TCP_FLAG_FIN, "FIN"),
+    ];
+    NAMES
+        .iter()
+        .filter(|(flag, _)| flags & flag != 0)
+        .map(|(_, name)| *name)
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// TCP 校验和使用的伪首部
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct FakeTcpHeader {
+    src_ip: Ipv4Addr,
+    dst_ip: Ipv4Addr,
+    zero: u8,
+    protocol: u8,
+    length: u16, // TCP 首部 + 数据的长度
+}
+
+impl FakeTcpHeader {
+    fn new(src_ip: Ipv4Addr, dst_ip: Ipv4Addr, length: u16) -> Self {
+        Self {
+            src_ip,
+            dst_ip,
+            zero: 0,
+            protocol: 6, // tcp
+            length,
+        }
+    }
+
+    fn to_bytes(self) -> [u8; 12] {
+        let mut bytes: [u8; 12] = [0; 12];
+        bytes[0..4].copy_from_slice(&self.src_ip.octets());
+        bytes[4..8].copy_from_slice(&self.dst_ip.octets());
+        bytes[8] = self.zero;
+        bytes[9] = self.protocol;
+        bytes[10..12].copy_from_slice(&self.length.to_be_bytes());
+
+        bytes
+    }
+}
+
+/// TCP 选项 (RFC 9293 / RFC 7323 / RFC 2018)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TcpOption {
+    EndOfList,
+    NoOperation,
+    /// 最大报文段长度，只出现在 SYN 中
+    MaxSegmentSize(u16),
+    /// 窗口扩大因子 (移位数)，只出现在 SYN 中
+    WindowScale(u8),
+    SackPermitted,
+    /// 已收到的不连续数据块，每块为 [left, right)
+    Sack(Vec<(u32, u32)>),
+    Timestamps {
+        value: u32,
+        echo_reply: u32,
+    },
+    /// 不认识的选项，原样保留
+    Unknown {
+        kind: u8,
+        data: Vec<u8>,
+    },
+}
+
+impl TcpOption {
+    pub fn kind(&self) -> u8 {
+        match self {
+            Self::EndOfList => TCP_OPT_END,
+            Self::NoOperation => TCP_OPT_NOP,
+            Self::MaxSegmentSize(_) => TCP_OPT_MSS,
+            Self::WindowScale(_) => TCP_OPT_WINDOW_SCALE,
+            Self::SackPermitted => TCP_OPT_SACK_PERMITTED,
+            Self::Sack(_) => TCP_OPT_SACK,
+            Self::Timestamps { .. } => TCP_OPT_TIMESTAMPS,
+            Self::Unknown { kind, .. } => *kind,
+        }
+    }
+
+    /// 编码后的字节数
+    #[allow(clippy::len_without_is_empty)]
+    pub fn len(&self) -> usize {
+        match self {
+            Self::EndOfList | Self::NoOperation => 1,
+            Self::MaxSegmentSize(_) => 4,
+            Self::WindowScale(_) => 3,
+            Self::SackPermitted => 2,
+            Self::Sack(blocks) => 2 + blocks.len() * 8,
+            Self::Timestamps { .. } => 10,
+            Self::Unknown { data, .. } => 2 + data.len(),
+        }
+    }
+
+    /// 解析一个多字节选项，`data` 不含类型和长度字节
+    fn parse(kind: u8, data: &[u8]) -> Result<Self, TcpParseError> {
+        let option = match (kind, data) {
+            (TCP_OPT_MSS, [a, b]) => Self::MaxSegmentSize(u16::from_be_bytes([*a, *b])),
+            (TCP_OPT_WINDOW_SCALE, [shift]) => Self::WindowScale(*shift),
+            (TCP_OPT_SACK_PERMITTED, []) => Self::SackPermitted,
+            (TCP_OPT_SACK, blocks) if !blocks.is_empty() && blocks.len().is_multiple_of(8) => {
+                Self::Sack(
+                    blocks
+                        .chunks_exact(8)
+                        .map(|c| {
+                            (
+                                u32::from_be_bytes([c[0], c[1], c[2], c[3]]),
+                                u32::from_be_bytes([c[4], c[5], c[6], c[7]]),
+                            )
+                        })
+                        .collect(),
+                )
+            }
+            (TCP_OPT_TIMESTAMPS, [a, b, c, d, e, f, g, h]) => Self::Timestamps {
+                value: u32::from_be_bytes([*a, *b, *c, *d]),
+                echo_reply: u32::from_be_bytes([*e, *f, *g, *h]),
+            },
+            // 已知类型但长度不对
+            (
+                TCP_OPT_MSS
+                | TCP_OPT_WINDOW_SCALE
+                | TCP_OPT_SACK_PERMITTED
+                | TCP_OPT_SACK
+                | TCP_OPT_TIMESTAMPS,
+                _,
+            ) => return Err(TcpParseError::InvalidOption),
+            _ => Self::Unknown {
+                kind,
+                data: data.to_vec(),
+            },
+        };
+        Ok(option)
+    }
+
+    pub fn write_to(&self, buf: &mut Vec<u8>) {
+        buf.push(self.kind());
+        match self {
+            Self::EndOfList | Self::NoOperation => return,
+            _ => buf.push(self.len() as u8),
+        }
+
+        match self {
+            Self::MaxSegmentSize(mss) => buf.extend_from_slice(&mss.to_be_bytes()),
+            Self::WindowScale(shift) => buf.push(*shift),
+            Self::Sack(blocks) => {
+                for (left, right) in blocks {
+                    buf.extend_from_slice(&left.to_be_bytes());
+                    buf.extend_from_slice(&right.to_be_bytes());
+                }
+            }
+            Self::Timestamps { value, echo_reply } => {
+                buf.extend_from_slice(&value.to_be_bytes());
+                buf.extend_from_slice(&echo_reply.to_be_bytes());
+            }
+            Self::Unknown { data, .. } => buf.extend_from_slice(data),
+            Self::EndOfList | Self::NoOperation | Self::SackPermitted => {}
+        }
+    }
+}
+
+/// 解析首部中 20 字节之后的选项区，End of Option List 之后的字节视为填充
+pub fn parse_options(bytes: &[u8]) -> Result<Vec<TcpOption>, TcpParseError> {
+    let mut options = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            TCP_OPT_END => {
+                options.push(TcpOption::EndOfList);
+                break;
+            }
+            TCP_OPT_NOP => {
+                options.push(TcpOption::NoOperation);
+                i += 1;
+            }
+            kind => {
+                let len = *bytes.get(i + 1).ok_or(TcpParseError::InvalidOption)? as usize;
+                if len < 2 || i + len > bytes.len() {
+                    return Err(TcpParseError::InvalidOption);
+                }
+                options.push(TcpOption::parse(kind, &bytes[i + 2..i + len])?);
+                i += len;
+            }
+        }
+    }
+    Ok(options)
+}
+
+/// 编码选项列表，不含末尾填充
+pub fn options_to_bytes(options: &[TcpOption]) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(options.iter().map(TcpOption::len).sum());
+    for option in options {
+        option.write_to(&mut buf);
+    }
+    buf
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TcpHeader {
+    pub src_port: u16,
+    pub dst_port: u16,
+    pub seq: u32,
+    pub ack: u32,
+    pub data_offset: u8, // 4 bits, 首部长度 (4 字节为单位)
+    pub flags: u8,       // CWR ECE URG ACK PSH RST SYN FIN
+    pub window: u16,
+    pub checksum: u16,
+    pub urgent_ptr: u16,
+    pub options: Vec<TcpOption>, // 编码后按 4 字节补齐，长度由 data_offset 决定
+}
+
+impl TcpHeader {
+    pub fn new(src_port: u16, dst_port: u16, seq: u32, ack: u32, flags: u8, window: u16) -> Self {
+        // 不带选项，需要时再调用 set_options
+        Self {
+            src_port,
+            dst_port,
+            seq,
+            ack,
+            data_offset: 5,
+            flags,
+            window,
+            checksum: 0,
+            urgent_ptr: 0,
+            options: Vec::new(),
+        }
+    }
+
+    /// 设置选项并更新 data_offset，选项超过 40 字节时返回错误
+    pub fn set_options(&mut self, options: Vec<TcpOption>) -> Result<(), TcpParseError> {
+        let options_len = options
+            .iter()
+            .map(TcpOption::len)
+            .sum::<usize>()
+            .next_multiple_of(4);
+        if options_len > TCP_MAX_OPTIONS_LEN {
+            return Err(TcpParseError::InvalidOption);
+        }
+
+        self.data_offset = ((20 + options_len) / 4) as u8;
+        self.options = options;
+        Ok(())
+    }
+
+    /// 首部长度 (字节)
+    pub fn header_len(&self) -> usize {
+        self.data_offset as usize * 4
+    }
+
+    pub fn has_flag(&self, flag: u8) -> bool {
+        self.flags & flag != 0
+    }
+
+    /// 第一个 MSS 选项
+    pub fn mss(&self) -> Option<u16> {
+        self.options.iter().find_map(|o| match o {
+            TcpOption::MaxSegmentSize(mss) => Some(*mss),
+            _ => None,
+        })
+    }
+
+    /// 第一个窗口扩大选项
+    pub fn window_scale(&self) -> Option<u8> {
+        self.options.iter().find_map(|o| match o {
+            TcpOption::WindowScale(shift) => Some(*shift),
+            _ => None,
+        })
+    }
+
+    /// 第一个时间戳选项，(TSval, TSecr)
+    pub fn timestamps(&self) -> Option<(u32, u32)> {
+        self.options.iter().find_map(|o| match o {
+            TcpOption::Timestamps { value, echo_reply } => Some((*value, *echo_reply)),
+            _ => None,
+        })
+    }
+
+    /// 编码为 data_offset * 4 字节，选项不足处补 0 (即 End of Option List)
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.header_len());
+        bytes.extend_from_slice(&self.src_port.to_be_bytes());
+        bytes.extend_from_slice(&self.dst_port.to_be_bytes());
+        bytes.extend_from_slice(&self.seq.to_be_bytes());
+        bytes.extend_from_slice(&self.ack.to_be_bytes());
+        bytes.push(self.data_offset << 4);
+        bytes.push(self.flags);
+        bytes.extend_from_slice(&self.window.to_be_bytes());
+        bytes.extend_from_slice(&self.checksum.to_be_bytes());
+        bytes.extend_from_slice(&self.urgent_ptr.to_be_bytes());
+
+        for option in &self.options {
+            option.write_to(&mut bytes);
+        }
+        bytes.resize(self.header_len().max(20), 0);
+        bytes
+    }
+
+    pub fn parse(bytes: &[u8]) -> Result<Self, TcpParseError> {
+        if bytes.len() < 20 {
+            return Err(TcpParseError::InvalidTcpLen);
+        }
+
+        let data_offset = bytes[12] >> 4;
+        let header_len = data_offset as usize * 4;
+        if data_offset < 5 {
+            return Err(TcpParseError::InvalidDataOffset);
+        }
+        if bytes.len() < header_len {
+            return Err(TcpParseError::InvalidTcpLen);
+        }
+
+        Ok(Self {
+            src_port: u16::from_be_bytes([bytes[0], bytes[1]]),
+            dst_port: u16::from_be_bytes([bytes[2], bytes[3]]),
+            seq: u32::from_be_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]),
+            ack: u32::from_be_bytes([bytes[8], bytes[9], bytes[10], bytes[11]]),
+            data_offset,
+            flags: bytes[13],
+            window: u16::from_be_bytes([bytes[14], bytes[15]]),
+            checksum: u16::from_be_bytes([bytes[16], bytes[17]]),
+            urgent_ptr: u16::from_be_bytes([bytes[18], bytes[19]]),
+            options: parse_options(&bytes[20..header_len])?,
+        })
+    }
+}
+
+impl fmt::Display for TcpHeader {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "TCP Header:
+    Source Port: {}
+    Destination Port: {}
+    Sequence: {}
+    Acknowledgment: {}
+    Data Offset: {} ({} bytes)
+    Flags: {:#04x} ({})
+    Window: {}
+    Checksum: {:#06x}
+    Urgent Pointer: {}
+    Options: {:?}",
+            self.src_port,
+            self.dst_port,
+            self.seq,
+            self.ack,
+            self.data_offset,
+            self.header_len(),
+            self.flags,
+            flag_names(self.flags),
+            self.window,
+            self.checksum,
+            self.urgent_ptr,
+            self.options
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TcpSegment {
+    pub header: TcpHeader,
+    pub payload: Vec<u8>,
+}
+
+impl TcpSegment {
+    /// 组装报文段并按伪首部计算校验和
+    pub fn new(header: TcpHeader, payload: Vec<u8>, src_ip: Ipv4Addr, dst_ip: Ipv4Addr) -> Self {
+        let mut segment = Self { header, payload };
+        segment.header.checksum = 0;
+        segment.header.checksum = segment.compute_checksum(src_ip, dst_ip);
+        segment
+    }
+
+    /// notice validate is not contained in parse
+    pub fn parse(bytes: &[u8]) -> Result<Self, TcpParseError> {
+        let header = TcpHeader::parse(bytes)?;
+        let payload = bytes[header.header_len()..].to_vec();
+        Ok(Self { header, payload })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.header.to_bytes();
+        bytes.extend_from_slice(&self.payload);
+        bytes
+    }
+
+    /// 占用的序号空间：数据长度，SYN 和 FIN 各占一个序号
+    pub fn seq_len(&self) -> u32 {
+        self.payload.len() as u32
+            + self.header.has_flag(TCP_FLAG_SYN) as u32
+            + self.header.has_flag(TCP_FLAG_FIN) as u32
+    }
+
+    pub fn validate(&self, src_ip: Ipv4Addr, dst_ip: Ipv4Addr) -> Result<(), TcpParseError> {
+        // 与 UDP 不同，TCP 校验和是必须的，0 不表示未计算
+        let mut segment = self.clone();
+        segment.header.checksum = 0;
+        if segment.compute_checksum(src_ip, dst_ip) == self.header.checksum {
+            Ok(())
+        } else {
+            Err(TcpParseError::InvalidChecksum)
+        }
+    }
+
+    fn compute_checksum(&self, src_ip: Ipv4Addr, dst_ip: Ipv4Addr) -> u16 {
+        let bytes = self.to_bytes();
+        let fake_header = FakeTcpHeader::new(src_ip, dst_ip, bytes.len() as u16);
+
+        let mut checksum_buffer = Vec::with_capacity(12 + bytes.len());
+        checksum_buffer.extend_from_slice(&fake_header.to_bytes()); // 伪首部在最前面
+        checksum_buffer.extend_from_slice(&bytes);
+        simple_checksum(&checksum_buffer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SRC: Ipv4Addr = Ipv4Addr::new(10, 0, 0, 1);
+    const DST: Ipv4Addr = Ipv4Addr::new(10, 0, 0, 2);
+
+    fn syn() -> TcpHeader {
+        let mut header = TcpHeader::new(40000, 80, 0x0102_0304, 0, TCP_FLAG_SYN, 65535);
+        header
+            .set_options(vec![
+                TcpOption::MaxSegmentSize(1460),
+                TcpOption::SackPermitted,
+                TcpOption::Timestamps {
+                    value: 7,
+                    echo_reply: 0,
+                },
+                TcpOption::NoOperation,
+                TcpOption::WindowScale(7),
+            ])
+            .unwrap();
+        header
+    }
+
+    #[test]
+    fn syn_options_round_trip() {
+        let header = syn();
+        assert_eq!(header.header_len(), 40);
+        let bytes = header.to_bytes();
+        assert_eq!(bytes.len(), 40);
+        assert_eq!(&bytes[20..24], &[TCP_OPT_MSS, 4, 0x05, 0xb4]);
+
+        let parsed = TcpHeader::parse(&bytes).unwrap();
+        assert_eq!(parsed, header);
+        assert_eq!(parsed.mss(), Some(1460));
+        assert_eq!(parsed.window_scale(), Some(7));
+        assert_eq!(parsed.timestamps(), Some((7, 0)));
+    }
+
+    #[test]
+    fn sack_and_unknown_options_round_trip() {
+        let options = vec![
+            TcpOption::NoOperation,
+            TcpOption::NoOperation,
+            TcpOption::Sack(vec![(100, 200), (300, 400)]),
+            TcpOption::Unknown {
+                kind: 30,
+                data: vec![1, 2],
+            },
+        ];
+        let bytes = options_to_bytes(&options);
+        assert_eq!(bytes.len(), 2 + 18 + 4);
+        assert_eq!(parse_options(&bytes), Ok(options.clone()));
+
+        // End of Option List 之后的字节是填充
+        let mut padded = bytes.clone();
+        padded.extend_from_slice(&[TCP_OPT_END, 0xaa, 0xbb, 0xcc]);
+        let mut expected = options;
+        expected.push(TcpOption::EndOfList);
+        assert_eq!(parse_options(&padded), Ok(expected));
+    }
+
+    #[test]
+    fn malformed_options_are_rejected() {
+        for bytes in [
+            &[TCP_OPT_MSS, 3, 0][..],
+            &[TCP_OPT_WINDOW_SCALE, 4, 0, 0][..],
+            &[TCP_OPT_SACK, 2][..],
+            &[TCP_OPT_SACK, 6, 0, 0, 0, 0][..],
+            &[TCP_OPT_TIMESTAMPS, 6, 0, 0, 0, 0][..],
+            &[30, 1][..],
+            &[30][..],
+            &[30, 8, 0][..],
+        ] {
+            assert_eq!(
+                parse_options(bytes),
+                Err(TcpParseError::InvalidOption),
+                "{:?}",
+                bytes
+            );
+        }
+
+        let mut header = TcpHeader::new(1, 2, 0, 0, TCP_FLAG_SYN, 0);
+        assert_eq!(
+            header.set_options(vec![TcpOption::Sack(vec![(0, 0); 5])]),
+            Err(TcpParseError::InvalidOption)
+        );
+    }
+
+    #[test]
+    fn header_length_is_checked() {
+        let bytes = syn().to_bytes();
+        assert_eq!(
+            TcpHeader::parse(&bytes[..19]),
+            Err(TcpParseError::InvalidTcpLen)
+        );
+        assert_eq!(
+            TcpHeader::parse(&bytes[..39]),
+            Err(TcpParseError::InvalidTcpLen)
+        );
+        let mut short = bytes.clone();
+        short[12] = 4 << 4;
+        assert_eq!(
+            TcpHeader::parse(&short),
+            Err(TcpParseError::InvalidDataOffset)
+        );
+    }
+
+    #[test]
+    fn segment_checksum_covers_pseudo_header() {
+        let header = TcpHeader::new(
+            40000,
+            80,
+            1000,
+            2000,
+            TCP_FLAG_ACK | TCP_FLAG_PSH | TCP_FLAG_FIN,
+            512,
+        );
+        let segment = TcpSegment::new(header, b"hello".to_vec(), SRC, DST);
+        assert_eq!(segment.seq_len(), 6);
+        assert_eq!(flag_names(segment.header.flags), "ACK,PSH,FIN");
+
+        let parsed = TcpSegment::parse(&segment.to_bytes()).unwrap();
+        assert_eq!(parsed, segment);
+        assert_eq!(parsed.validate(SRC, DST), Ok(()));
+        // 伪首部中的地址不同，校验和不匹配
+        assert_eq!(
+            parsed.validate(SRC, Ipv4Addr::new(10, 0, 0, 3)),
+            Err(TcpParseError::InvalidChecksum)
+        );
+        let mut corrupted = segment.to_bytes();
+        corrupted[20] ^= 0x20;
+        assert_eq!(
+            TcpSegment::parse(&corrupted).unwrap().validate(SRC, DST),
+            Err(TcpParseError::InvalidChecksum)
+        );
+    }
+}
@@ -11,7 +11,12 @@
 // the Free Software Foundation, either version 3 of the License, or
 // (at your option) any later version.
 
-use crate::{checksum::simple_checksum, error::UdpParseError, ipv4::Ipv4Addr};
+use crate::{
+    checksum::simple_checksum,
+    error::UdpParseError,
+    ipv4::Ipv4Addr,
+    ipv6::{self, IPV6_NEXT_UDP, Ipv6Addr},
+};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 struct FakeUdpHeader {
@@ -33,7 +38,7 @@ impl FakeUdpHeader {
         }
     }
 
-    fn to_bytes(&self) -> [u8; 12] {
+    fn to_bytes(self) -> [u8; 12] {
         let mut bytes: [u8; 12] = [0; 12];
         bytes[0..4].copy_from_slice(&self.src_ip.octets());
         bytes[4..8].copy_from_slice(&self.dst_ip.octets());
@@ -73,6 +78,7 @@ impl UdpHeader {
         bytes
     }
 
+    #[allow(clippy::len_without_is_empty)] // UDP 首部长度固定为 8，不存在"空"的概念
     pub const fn len(&self) -> usize {
         8
     }
@@ -180,4 +186,50 @@ impl UdpPacket {
             Err(UdpParseError::InvalidChecksum)
         }
     }
+
+    /// 与 new 相同，校验和使用 IPv6 伪首部 (RFC 8200 8.1)
+    pub fn new_ipv6(
+        mut header: UdpHeader,
+        payload: Vec<u8>,
+        src_ip: Ipv6Addr,
+        dst_ip: Ipv6Addr,
+    ) -> Self {
+        let total_len = (header.len() + payload.len()) as u16;
+        header.length = total_len;
+        header.checksum = 0;
+
+        let mut udp_packet = Self { header, payload };
+        udp_packet.header.checksum = udp_packet.ipv6_checksum(src_ip, dst_ip);
+        udp_packet
+    }
+
+    /// IPv6 下校验和是必需的，为 0 的报文直接视为无效
+    pub fn validate_ipv6(&self, src_ip: Ipv6Addr, dst_ip: Ipv6Addr) -> Result<(), UdpParseError> {
+        if self.header.checksum != 0 && self.header.checksum == self.ipv6_checksum(src_ip, dst_ip) {
+            Ok(())
+        } else {
+            Err(UdpParseError::InvalidChecksum)
+        }
+    }
+
+    fn ipv6_checksum(&self, src_ip: Ipv6Addr, dst_ip: Ipv6Addr) -> u16 {
+        let total_len = self.header.len() + self.payload.len();
+        let mut header = self.header;
+        header.checksum = 0;
+
+        let mut checksum_buffer = Vec::with_capacity(40 + total_len);
+        checksum_buffer.extend_from_slice(&ipv6::pseudo_header(
+            src_ip,
+            dst_ip,
+            total_len as u32,
+            IPV6_NEXT_UDP,
+        ));
+        checksum_buffer.extend_from_slice(&header.to_bytes());
+        checksum_buffer.extend_from_slice(&self.payload);
+
+        match simple_checksum(&checksum_buffer) {
+            0 => 0xFFFF,
+            checksum => checksum,
+        }
+    }
 }
@@ -66,7 +66,7 @@ impl ArpProcessor {
         if self
             .cache
             .insert(packet.sender_ip, packet.sender_mac)
-            .map_or(true, |old| old != packet.sender_mac)
+            .is_none_or(|old| old != packet.sender_mac)
         {
             println!(
                 "ARP 缓存更新: {} -> {}",
@@ -54,6 +54,14 @@ pub struct ReceiveArgs {
     /// 允许的目的 IPv4 地址（可用逗号分隔多个）
     #[arg(long, value_name = "IPv4", value_delimiter = ',')]
     pub accept_ip: Vec<Ipv4Addr>,
+    /// 只接收带这些 VLAN 标签的帧（可用逗号分隔多个，由外到内；默认不按 VLAN 过滤）
+    #[arg(
+        long,
+        value_name = "VID",
+        value_delimiter = ',',
+        value_parser = clap::value_parser!(u16).range(1..=4094)
+    )]
+    pub vlan: Vec<u16>,
     /// 接收指定数量的匹配帧后停止（默认持续抓取）
     #[arg(long)]
     pub limit: Option<u64>,
@@ -18,13 +18,13 @@ use anyhow::{Context, Result};
 use pcap::{Active, Capture, Device, Error as PcapError, Packet};
 use protocol::checksum::Crc32;
 use protocol::error::ArpParseError;
+use protocol::ethernet::EthernetHeader;
 use protocol::mac::MacAddr;
 
 use crate::arp::ArpProcessor;
 use crate::cli::ReceiveArgs;
 use crate::ipv4::Ipv4Processor;
 
-const MIN_PAYLOAD: usize = 46;
 const MAX_PAYLOAD: usize = 1500;
 const HEADER_LEN: usize = 14;
 const CRC_LEN: usize = 4;
@@ -73,6 +73,17 @@ pub fn receive_packets(args: ReceiveArgs) -> Result<()> {
     );
     println!("目的 IPv4 白名单：{}", ipv4.allowed_destinations());
 
+    if !args.vlan.is_empty() {
+        println!(
+            "VLAN 过滤：{}",
+            args.vlan
+                .iter()
+                .map(|vid| vid.to_string())
+                .collect::<Vec<_>>()
+                .join(" / ")
+        );
+    }
+
     let crc = Crc32::new();
     let mut displayed = 0u64;
     loop {
@@ -81,7 +92,10 @@ pub fn receive_packets(args: ReceiveArgs) -> Result<()> {
                 if handle_packet(
                     &packet,
                     displayed + 1,
-                    &accepted,
+                    &FrameFilter {
+                        macs: &accepted,
+                        vlans: &args.vlan,
+                    },
                     &crc,
                     &mut writer,
                     &mut ipv4,
@@ -114,10 +128,16 @@ fn open_interface(name: &str, timeout_ms: i32) -> Result<Capture<Active>> {
         .with_context(|| format!("打开接口 {name} 失败"))
 }
 
+/// 目的 MAC 白名单和由外到内的 VLAN ID，为空时不过滤
+struct FrameFilter<'a> {
+    macs: &'a [MacAddr],
+    vlans: &'a [u16],
+}
+
 fn handle_packet(
     packet: &Packet,
     ordinal: u64,
-    accepted: &[MacAddr],
+    filter: &FrameFilter,
     crc: &Crc32,
     writer: &mut BufWriter<File>,
     ipv4: &mut Ipv4Processor,
@@ -128,19 +148,36 @@ fn handle_packet(
         return Ok(false);
     }
 
-    let dest = MacAddr::from_slice(&packet.data[0..6]);
-    if !accepted.is_empty() && !accepted.contains(&dest) {
+    let header = match EthernetHeader::parse(packet.data) {
+        Ok(header) if header.header_len() + CRC_LEN <= packet.data.len() => header,
+        _ => {
+            println!("检测到 VLAN 标签不完整的畸形帧，已忽略。");
+            return Ok(false);
+        }
+    };
+    let dest = header.dst;
+    if !filter.macs.is_empty() && !filter.macs.contains(&dest) {
         println!("目的地址 {dest} 不在白名单，已丢弃。");
         return Ok(false);
     }
+    if !filter.vlans.is_empty()
+        && !header
+            .vlans
+            .iter()
+            .map(|tag| tag.vid)
+            .eq(filter.vlans.iter().copied())
+    {
+        return Ok(false);
+    }
 
-    let src = MacAddr::from_slice(&packet.data[6..12]);
-    let ethertype = u16::from_be_bytes([packet.data[12], packet.data[13]]);
+    let src = header.src;
+    let ethertype = u16::from(header.ethertype);
     let crc_range_start = packet.data.len() - CRC_LEN;
-    let payload = &packet.data[HEADER_LEN..crc_range_start];
+    let payload = &packet.data[header.header_len()..crc_range_start];
     let frame_crc = u32::from_le_bytes(packet.data[crc_range_start..].try_into().unwrap());
 
-    if payload.len() < MIN_PAYLOAD || payload.len() > MAX_PAYLOAD {
+    // VLAN 标签计入最小帧长，带标签的帧载荷可以相应缩短
+    if payload.len() < header.min_payload_len() || payload.len() > MAX_PAYLOAD {
         println!("数据长度 {} 不满足以太网要求，已跳过该帧。", payload.len());
         return Ok(false);
     }
@@ -152,9 +189,12 @@ fn handle_packet(
     println!("捕获第 {ordinal} 个数据帧");
     println!("捕获时间戳: {}", packet.header.ts.tv_sec);
     println!("帧总长度: {}", packet.header.len);
-    println!("以太网头长度: {}", HEADER_LEN);
+    println!("以太网头长度: {}", header.header_len());
     println!("数据区长度: {}", payload.len());
     println!("-----Ethernet protocol-------");
+    for tag in &header.vlans {
+        println!("{tag}");
+    }
     println!("EtherType: {ethertype:#06x}");
     println!("源 MAC: {src}");
     println!("目的 MAC: {dest}");
@@ -65,7 +65,26 @@ pub struct SendArgs {
     /// EtherType 字段（十六进制）
     #[arg(long, default_value_t = DEFAULT_ETHERTYPE)]
     pub ethertype: u16,
-    /// 小于 46 字节时自动补零
+    /// 给帧打上 VLAN 标签（可用逗号分隔多个，由外到内，外层使用 802.1ad QinQ）
+    #[arg(
+        long,
+        value_name = "VID",
+        value_delimiter = ',',
+        value_parser = clap::value_parser!(u16).range(1..=4094)
+    )]
+    pub vlan: Vec<u16>,
+    /// 最内层 VLAN 标签的优先级 (PCP, 0-7)
+    #[arg(
+        long,
+        default_value_t = 0,
+        value_parser = clap::value_parser!(u8).range(0..=7),
+        requires = "vlan"
+    )]
+    pub vlan_pcp: u8,
+    /// 设置最内层 VLAN 标签的 DEI 位
+    #[arg(long, requires = "vlan")]
+    pub vlan_dei: bool,
+    /// 小于最小载荷时自动补零（46 字节，每层 VLAN 标签少补 4 字节）
     #[arg(long)]
     pub pad: bool,
     /// 将帧 payload 构造成 ARP 报文
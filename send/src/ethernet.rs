@@ -16,15 +16,14 @@ use std::{fs, thread, time::Duration};
 use anyhow::{Context, Result, bail};
 use pcap::{Active, Capture, Device};
 use protocol::checksum::Crc32;
+use protocol::ethernet::{EthernetHeader, VlanTag};
 
 use crate::arp::build_arp_payload;
 use crate::cli::{ArpMode, SendArgs};
 use crate::ipv4::{Ipv4Config, build_ipv4_payloads};
 use protocol::arp::ArpOperation;
 
-const MIN_PAYLOAD: usize = 46;
 const MAX_PAYLOAD: usize = 1500;
-const HEADER_LEN: usize = 14;
 const CRC_LEN: usize = 4;
 
 pub fn list_adapters() -> Result<()> {
@@ -43,6 +42,24 @@ pub fn list_adapters() -> Result<()> {
 }
 
 pub fn send_packets(args: SendArgs) -> Result<()> {
+    let ethertype = if args.arp_mode.is_some() {
+        0x0806
+    } else if args.ipv4 {
+        0x0800
+    } else {
+        args.ethertype
+    };
+    let mut header = EthernetHeader::new(args.src_mac, args.dest_mac, ethertype.into());
+    header.vlans = VlanTag::stacked(&args.vlan)
+        .with_context(|| format!("VLAN ID 超出范围 1-{}", VlanTag::MAX_VID))?;
+    if let Some(inner) = header.vlans.last_mut() {
+        inner.pcp = args.vlan_pcp;
+        inner.dei = args.vlan_dei;
+    }
+    // 补零按不带标签的帧计算：VLAN 标签本身计入最小帧长
+    let min_payload = header.min_payload_len();
+    let header = header.to_bytes();
+
     let payloads = if let Some(arp_mode) = args.arp_mode {
         let mut payload = build_arp_payload(
             map_arp_mode(arp_mode),
@@ -51,7 +68,7 @@ pub fn send_packets(args: SendArgs) -> Result<()> {
             args.arp_target_mac,
             args.arp_target_ip,
         );
-        enforce_payload_rules(&mut payload, true)?;
+        enforce_payload_rules(&mut payload, min_payload, true)?;
         vec![payload]
     } else {
         let mut file_payload =
@@ -70,7 +87,7 @@ pub fn send_packets(args: SendArgs) -> Result<()> {
             };
             build_ipv4_payloads(&file_payload, &cfg)?
         } else {
-            enforce_payload_rules(&mut file_payload, args.pad)?;
+            enforce_payload_rules(&mut file_payload, min_payload, args.pad)?;
             vec![file_payload]
         }
     };
@@ -79,17 +96,10 @@ pub fn send_packets(args: SendArgs) -> Result<()> {
         bail!("未生成任何以太网载荷，请检查输入文件。");
     }
 
-    let ethertype = if args.arp_mode.is_some() {
-        0x0806
-    } else if args.ipv4 {
-        0x0800
-    } else {
-        args.ethertype
-    };
     let crc = Crc32::new();
     let frames: Vec<Vec<u8>> = payloads
         .into_iter()
-        .map(|payload| assemble_frame(&args, payload, ethertype, &crc))
+        .map(|payload| assemble_frame(&header, payload, &crc))
         .collect();
 
     let mut capture = open_interface(&args.interface, args.timeout_ms)?;
@@ -119,19 +129,18 @@ pub fn send_packets(args: SendArgs) -> Result<()> {
         frames
             .first()
             .map(|f| f.len())
-            .unwrap_or(HEADER_LEN + CRC_LEN),
-        HEADER_LEN,
+            .unwrap_or(header.len() + CRC_LEN),
+        header.len(),
         CRC_LEN
     );
     Ok(())
 }
 
-fn assemble_frame(args: &SendArgs, payload: Vec<u8>, ethertype: u16, crc: &Crc32) -> Vec<u8> {
+/// header 已含 VLAN 标签
+fn assemble_frame(header: &[u8], payload: Vec<u8>, crc: &Crc32) -> Vec<u8> {
     let checksum = crc.checksum(&payload);
-    let mut frame = Vec::with_capacity(HEADER_LEN + payload.len() + CRC_LEN);
-    frame.extend_from_slice(args.dest_mac.as_bytes());
-    frame.extend_from_slice(args.src_mac.as_bytes());
-    frame.extend_from_slice(&ethertype.to_be_bytes());
+    let mut frame = Vec::with_capacity(header.len() + payload.len() + CRC_LEN);
+    frame.extend_from_slice(header);
     frame.extend_from_slice(&payload);
     frame.extend_from_slice(&checksum.to_le_bytes());
     frame
@@ -147,7 +156,7 @@ fn open_interface(name: &str, timeout_ms: i32) -> Result<Capture<Active>> {
         .with_context(|| format!("打开接口 {name} 失败"))
 }
 
-fn enforce_payload_rules(payload: &mut Vec<u8>, pad: bool) -> Result<()> {
+fn enforce_payload_rules(payload: &mut Vec<u8>, min_payload: usize, pad: bool) -> Result<()> {
     if payload.len() > MAX_PAYLOAD {
         bail!(
             "载荷大小 {} 超出以太网最大 {} 字节限制",
@@ -156,14 +165,14 @@ fn enforce_payload_rules(payload: &mut Vec<u8>, pad: bool) -> Result<()> {
         );
     }
 
-    if payload.len() < MIN_PAYLOAD {
+    if payload.len() < min_payload {
         if pad {
-            payload.resize(MIN_PAYLOAD, 0);
+            payload.resize(min_payload, 0);
         } else {
             bail!(
                 "载荷大小 {} 小于以太网最小 {} 字节（使用 --pad 自动补零）",
                 payload.len(),
-                MIN_PAYLOAD
+                min_payload
             );
         }
     }
@@ -46,7 +46,7 @@ pub fn build_ipv4_payloads(data: &[u8], cfg: &Ipv4Config) -> Result<Vec<Vec<u8>>
         cfg.fragment_size
     );
     ensure!(
-        cfg.fragment_size % 8 == 0,
+        cfg.fragment_size.is_multiple_of(8),
         "IPv4 分片大小必须是 8 的倍数，以满足分片偏移要求。"
     );
     ensure!(